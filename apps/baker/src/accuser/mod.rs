@@ -0,0 +1,175 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+mod tracker;
+pub use self::tracker::{
+    ConsensusKey, ConsensusOperation, ConsensusTracker, Evidence, EvidenceError,
+};
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    time::Duration,
+};
+
+use reqwest::Url;
+
+use crypto::hash::{BlockHash, ChainId};
+
+use crate::{
+    machine::{BakerAction, OperationsEventAction, ProposalEventAction, RpcErrorAction},
+    services::logger,
+    RpcClient,
+};
+
+pub struct AccuserConfig {
+    pub endpoint: Url,
+    /// Number of levels behind the head for which evidences are still searched
+    pub preserved_levels: i32,
+}
+
+/// Watches the heads and the mempool of the node, injects an evidence
+/// each time some delegate double bakes or double (pre)endorses.
+///
+/// Runs until `terminating` is set.
+pub fn run(config: AccuserConfig, terminating: Arc<AtomicBool>) {
+    let AccuserConfig {
+        endpoint,
+        preserved_levels,
+    } = config;
+
+    let log = logger::main_logger();
+    let (tx, rx) = mpsc::channel();
+    let client = RpcClient::new(endpoint, 0, tx);
+
+    let chain_id = loop {
+        match client.get_chain_id() {
+            Ok(v) => break v,
+            Err(_) => std::thread::sleep(Duration::from_millis(200)),
+        }
+    };
+    slog::info!(log, "chain_id: {chain_id}");
+    loop {
+        match client.wait_bootstrapped() {
+            Ok(_) => break,
+            Err(_) => std::thread::sleep(Duration::from_millis(200)),
+        }
+    }
+    slog::info!(log, "bootstrapped");
+    client.monitor_heads(&chain_id).unwrap();
+
+    let mut accuser = Accuser {
+        client,
+        log,
+        chain_id,
+        tracker: ConsensusTracker::new(preserved_levels),
+        head: None,
+    };
+    while !terminating.load(Ordering::SeqCst) {
+        let action = match rx.recv_timeout(Duration::from_secs(1)) {
+            Ok((_, action)) => action,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        accuser.handle(action);
+    }
+    slog::info!(accuser.log, "terminated gracefully");
+}
+
+struct Accuser {
+    client: RpcClient,
+    log: slog::Logger,
+    chain_id: ChainId,
+    tracker: ConsensusTracker,
+    /// Branch of the injected evidences
+    head: Option<BlockHash>,
+}
+
+impl Accuser {
+    fn handle(&mut self, action: BakerAction) {
+        match action {
+            BakerAction::ProposalEvent(ProposalEventAction { block, .. }) => {
+                // the mempool monitor is closed by the node on each new head
+                if let Err(err) = self
+                    .client
+                    .monitor_all_operations(Duration::from_secs(3600))
+                {
+                    slog::error!(self.log, " .  {err}");
+                }
+                self.head = Some(block.hash.clone());
+                self.tracker.prune(block.level);
+                if block.transition {
+                    return;
+                }
+
+                let header = self
+                    .client
+                    .get_block_baker(&block.hash)
+                    .and_then(|delegate| {
+                        let bytes = self.client.get_block_header_bytes(&block.hash)?;
+                        Ok((delegate, bytes))
+                    });
+                match header {
+                    Ok((delegate, bytes)) => {
+                        let key = ConsensusKey {
+                            level: block.level,
+                            round: block.round,
+                            delegate,
+                        };
+                        if let Some(evidence) = self.tracker.add_block(key, block.hash, bytes) {
+                            self.inject(evidence);
+                        }
+                    }
+                    Err(err) => slog::error!(self.log, " .  {err}"),
+                }
+            }
+            BakerAction::OperationsEvent(OperationsEventAction { operations }) => {
+                for op in operations
+                    .iter()
+                    .filter_map(ConsensusOperation::from_simple)
+                {
+                    let level = op.level();
+                    if !self.tracker.has_slots(level) {
+                        match self.client.validators(level) {
+                            Ok(delegates) => self.tracker.set_slots(level, delegates),
+                            Err(err) => {
+                                slog::error!(self.log, " .  {err}");
+                                continue;
+                            }
+                        }
+                    }
+                    match self.tracker.add_operation(op) {
+                        Ok(Some(evidence)) => self.inject(evidence),
+                        Ok(None) => (),
+                        Err(err) => slog::warn!(self.log, " .  {err}"),
+                    }
+                }
+            }
+            BakerAction::RpcError(RpcErrorAction { error }) => {
+                slog::error!(self.log, " .  {error}");
+            }
+            _ => (),
+        }
+    }
+
+    fn inject(&self, evidence: Evidence) {
+        slog::warn!(self.log, " .  found {evidence}");
+        let branch = match &self.head {
+            Some(head) => head,
+            None => return,
+        };
+        let op = match evidence.forge(branch) {
+            Ok(v) => hex::encode(v),
+            Err(err) => {
+                slog::error!(self.log, " .  {err}");
+                return;
+            }
+        };
+        match self.client.inject_operation(&self.chain_id, op, false) {
+            Ok(hash) => slog::info!(self.log, " .  inject {evidence}: {hash}"),
+            Err(err) => slog::error!(self.log, " .  {err}"),
+        }
+    }
+}
@@ -0,0 +1,607 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use thiserror::Error;
+
+use crypto::{
+    blake2b::{self, Blake2bError},
+    hash::{BlockHash, ContractTz1Hash, OperationHash},
+};
+use tezos_encoding::enc::{BinError, BinWriter};
+use tezos_messages::protocol::proto_012::operation::{
+    Contents, DoubleEndorsementEvidenceOperation, DoublePreendorsementEvidenceOperation,
+    InlinedEndorsement, InlinedEndorsementMempoolContents,
+    InlinedEndorsementMempoolContentsEndorsementVariant, InlinedPreendorsement,
+    InlinedPreendorsementContents, InlinedPreendorsementVariant,
+};
+
+use crate::services::event::{OperationKind, OperationSimple, Slots};
+
+/// Tag of `Double_baking_evidence` in the operation contents
+const DOUBLE_BAKING_EVIDENCE_TAG: u8 = 3;
+
+#[derive(Debug, Error)]
+pub enum EvidenceError {
+    #[error("bin: {_0}")]
+    Bin(#[from] BinError),
+    #[error("blake2b: {_0}")]
+    Blake2b(#[from] Blake2bError),
+    #[error("slots of level {level} are unknown")]
+    UnknownSlots { level: i32 },
+    #[error("slot {slot} is not assigned at level {level}")]
+    UnknownSlot { level: i32, slot: u16 },
+}
+
+/// Signer of a block or a consensus operation, at some level and round
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ConsensusKey {
+    pub level: i32,
+    pub round: i32,
+    pub delegate: ContractTz1Hash,
+}
+
+impl fmt::Display for ConsensusKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}:{}", self.delegate, self.level, self.round)
+    }
+}
+
+/// Signed (pre)endorsement seen in the mempool
+#[derive(Clone, Debug)]
+pub enum ConsensusOperation {
+    Preendorsement(InlinedPreendorsement),
+    Endorsement(InlinedEndorsement),
+}
+
+impl ConsensusOperation {
+    /// `None` if the operation is not a (pre)endorsement, or is not signed
+    pub fn from_simple(op: &OperationSimple) -> Option<Self> {
+        let signature = op.signature.clone()?;
+        match op.kind()? {
+            OperationKind::Preendorsement(c) => {
+                Some(ConsensusOperation::Preendorsement(InlinedPreendorsement {
+                    branch: op.branch.clone(),
+                    operations: InlinedPreendorsementContents::Preendorsement(
+                        InlinedPreendorsementVariant {
+                            slot: c.slot,
+                            level: c.level,
+                            round: c.round,
+                            block_payload_hash: c.block_payload_hash,
+                        },
+                    ),
+                    signature,
+                }))
+            }
+            OperationKind::Endorsement(c) => {
+                Some(ConsensusOperation::Endorsement(InlinedEndorsement {
+                    branch: op.branch.clone(),
+                    operations: InlinedEndorsementMempoolContents::Endorsement(
+                        InlinedEndorsementMempoolContentsEndorsementVariant {
+                            slot: c.slot,
+                            level: c.level,
+                            round: c.round,
+                            block_payload_hash: c.block_payload_hash,
+                        },
+                    ),
+                    signature,
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn level(&self) -> i32 {
+        match self {
+            ConsensusOperation::Preendorsement(op) => op.operations.level,
+            ConsensusOperation::Endorsement(op) => op.operations.level,
+        }
+    }
+
+    pub fn round(&self) -> i32 {
+        match self {
+            ConsensusOperation::Preendorsement(op) => op.operations.round,
+            ConsensusOperation::Endorsement(op) => op.operations.round,
+        }
+    }
+
+    pub fn slot(&self) -> u16 {
+        match self {
+            ConsensusOperation::Preendorsement(op) => op.operations.slot,
+            ConsensusOperation::Endorsement(op) => op.operations.slot,
+        }
+    }
+}
+
+/// Two conflicting blocks or consensus operations signed by the same delegate
+#[derive(Clone, Debug)]
+pub enum Evidence {
+    DoubleBaking {
+        key: ConsensusKey,
+        bh1: Vec<u8>,
+        bh2: Vec<u8>,
+    },
+    DoublePreendorsement {
+        key: ConsensusKey,
+        op1: InlinedPreendorsement,
+        op2: InlinedPreendorsement,
+    },
+    DoubleEndorsement {
+        key: ConsensusKey,
+        op1: InlinedEndorsement,
+        op2: InlinedEndorsement,
+    },
+}
+
+impl fmt::Display for Evidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Evidence::DoubleBaking { key, .. } => write!(f, "double baking by {key}"),
+            Evidence::DoublePreendorsement { key, .. } => {
+                write!(f, "double preendorsement by {key}")
+            }
+            Evidence::DoubleEndorsement { key, .. } => write!(f, "double endorsement by {key}"),
+        }
+    }
+}
+
+impl Evidence {
+    pub fn key(&self) -> &ConsensusKey {
+        match self {
+            Evidence::DoubleBaking { key, .. }
+            | Evidence::DoublePreendorsement { key, .. }
+            | Evidence::DoubleEndorsement { key, .. } => key,
+        }
+    }
+
+    /// Binary operation denouncing the delegate, ready to be injected.
+    ///
+    /// Evidences are anonymous operations, the signature is zeroed.
+    pub fn forge(&self, branch: &BlockHash) -> Result<Vec<u8>, EvidenceError> {
+        let mut bytes = branch.0.clone();
+        match self {
+            // `FullHeader` of the protocol only encodes the Ithaca liquidity baking vote,
+            // so the headers are kept as bytes and the evidence is encoded here
+            Evidence::DoubleBaking { bh1, bh2, .. } => {
+                bytes.push(DOUBLE_BAKING_EVIDENCE_TAG);
+                for header in [bh1, bh2] {
+                    bytes.extend_from_slice(&(header.len() as u32).to_be_bytes());
+                    bytes.extend_from_slice(header);
+                }
+            }
+            Evidence::DoublePreendorsement { op1, op2, .. } => {
+                Contents::DoublePreendorsementEvidence(DoublePreendorsementEvidenceOperation {
+                    op1: op1.clone(),
+                    op2: op2.clone(),
+                })
+                .bin_write(&mut bytes)?;
+            }
+            Evidence::DoubleEndorsement { op1, op2, .. } => {
+                Contents::DoubleEndorsementEvidence(DoubleEndorsementEvidenceOperation {
+                    op1: op1.clone(),
+                    op2: op2.clone(),
+                })
+                .bin_write(&mut bytes)?;
+            }
+        }
+        bytes.extend_from_slice(&[0; 64]);
+        Ok(bytes)
+    }
+}
+
+/// Keeps the blocks and the consensus operations signed by each delegate
+/// at each level and round, and finds the conflicting ones.
+///
+/// The first conflict of a delegate at some level and round is reported, later
+/// ones are ignored, the delegate is denounced only once.
+pub struct ConsensusTracker {
+    /// Levels kept behind the last seen block
+    preserved_levels: i32,
+    /// Delegate of each slot, per level
+    slots: BTreeMap<i32, BTreeMap<u16, ContractTz1Hash>>,
+    blocks: BTreeMap<ConsensusKey, (BlockHash, Vec<u8>)>,
+    preendorsements: BTreeMap<ConsensusKey, (OperationHash, InlinedPreendorsement)>,
+    endorsements: BTreeMap<ConsensusKey, (OperationHash, InlinedEndorsement)>,
+    denounced: BTreeSet<(&'static str, ConsensusKey)>,
+}
+
+impl ConsensusTracker {
+    pub fn new(preserved_levels: i32) -> Self {
+        ConsensusTracker {
+            preserved_levels,
+            slots: BTreeMap::new(),
+            blocks: BTreeMap::new(),
+            preendorsements: BTreeMap::new(),
+            endorsements: BTreeMap::new(),
+            denounced: BTreeSet::new(),
+        }
+    }
+
+    pub fn has_slots(&self, level: i32) -> bool {
+        self.slots.contains_key(&level)
+    }
+
+    pub fn set_slots(&mut self, level: i32, delegates: BTreeMap<ContractTz1Hash, Slots>) {
+        let slots = delegates
+            .into_iter()
+            .flat_map(|(delegate, Slots(slots))| {
+                slots.into_iter().map(move |slot| (slot, delegate.clone()))
+            })
+            .collect();
+        self.slots.insert(level, slots);
+    }
+
+    /// Record the signed header `header_bytes` of the block `hash`, baked as `key`
+    pub fn add_block(
+        &mut self,
+        key: ConsensusKey,
+        hash: BlockHash,
+        header_bytes: Vec<u8>,
+    ) -> Option<Evidence> {
+        let (existing_hash, existing_header) = match self.blocks.get(&key) {
+            None => {
+                self.blocks.insert(key, (hash, header_bytes));
+                return None;
+            }
+            Some(existing) => existing,
+        };
+        if *existing_hash == hash || !self.denounced.insert(("block", key.clone())) {
+            return None;
+        }
+
+        let (bh1, bh2) = if *existing_hash < hash {
+            (existing_header.clone(), header_bytes)
+        } else {
+            (header_bytes, existing_header.clone())
+        };
+        Some(Evidence::DoubleBaking { key, bh1, bh2 })
+    }
+
+    /// Record the consensus operation, the slots of its level must be set
+    pub fn add_operation(
+        &mut self,
+        op: ConsensusOperation,
+    ) -> Result<Option<Evidence>, EvidenceError> {
+        let (level, slot) = (op.level(), op.slot());
+        let delegate = self
+            .slots
+            .get(&level)
+            .ok_or(EvidenceError::UnknownSlots { level })?
+            .get(&slot)
+            .ok_or(EvidenceError::UnknownSlot { level, slot })?
+            .clone();
+        let key = ConsensusKey {
+            level,
+            round: op.round(),
+            delegate,
+        };
+
+        let evidence = match op {
+            ConsensusOperation::Preendorsement(op) => {
+                let hash = operation_hash(&op)?;
+                conflict(
+                    &mut self.preendorsements,
+                    &mut self.denounced,
+                    key,
+                    hash,
+                    op,
+                )
+                .map(|(key, op1, op2)| Evidence::DoublePreendorsement {
+                    key,
+                    op1,
+                    op2,
+                })
+            }
+            ConsensusOperation::Endorsement(op) => {
+                let hash = operation_hash(&op)?;
+                conflict(&mut self.endorsements, &mut self.denounced, key, hash, op)
+                    .map(|(key, op1, op2)| Evidence::DoubleEndorsement { key, op1, op2 })
+            }
+        };
+        Ok(evidence)
+    }
+
+    /// Forget everything more than `preserved_levels` below `level`
+    pub fn prune(&mut self, level: i32) {
+        let min_level = level.saturating_sub(self.preserved_levels);
+        self.slots = self.slots.split_off(&min_level);
+        self.blocks.retain(|key, _| key.level >= min_level);
+        self.preendorsements.retain(|key, _| key.level >= min_level);
+        self.endorsements.retain(|key, _| key.level >= min_level);
+        self.denounced.retain(|(_, key)| key.level >= min_level);
+    }
+}
+
+/// Record `op`, returns both operations ordered by hash if it conflicts with a recorded one
+fn conflict<T>(
+    ops: &mut BTreeMap<ConsensusKey, (OperationHash, T)>,
+    denounced: &mut BTreeSet<(&'static str, ConsensusKey)>,
+    key: ConsensusKey,
+    hash: OperationHash,
+    op: T,
+) -> Option<(ConsensusKey, T, T)>
+where
+    T: Clone + ConsensusContents,
+{
+    let (existing_hash, existing) = match ops.get(&key) {
+        None => {
+            ops.insert(key, (hash, op));
+            return None;
+        }
+        Some(existing) => existing,
+    };
+    // same signed operation, or same content signed for another branch
+    if *existing_hash == hash || existing.payload() == op.payload() {
+        return None;
+    }
+    if !denounced.insert((T::KIND, key.clone())) {
+        return None;
+    }
+
+    if *existing_hash < hash {
+        Some((key, existing.clone(), op))
+    } else {
+        Some((key, op, existing.clone()))
+    }
+}
+
+trait ConsensusContents {
+    const KIND: &'static str;
+
+    fn payload(&self) -> &[u8];
+}
+
+impl ConsensusContents for InlinedPreendorsement {
+    const KIND: &'static str = "preendorsement";
+
+    fn payload(&self) -> &[u8] {
+        self.operations.block_payload_hash.0.as_slice()
+    }
+}
+
+impl ConsensusContents for InlinedEndorsement {
+    const KIND: &'static str = "endorsement";
+
+    fn payload(&self) -> &[u8] {
+        self.operations.block_payload_hash.0.as_slice()
+    }
+}
+
+/// The inlined operation is encoded as the operation itself: branch, contents and signature
+fn operation_hash<T>(op: &T) -> Result<OperationHash, EvidenceError>
+where
+    T: BinWriter,
+{
+    let mut bytes = vec![];
+    op.bin_write(&mut bytes)?;
+    Ok(OperationHash(blake2b::digest_256(&bytes)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crypto::hash::{BlockPayloadHash, Signature};
+    use tezos_messages::p2p::binary_message::BinaryRead;
+
+    use super::*;
+
+    const LEVEL: i32 = 100;
+
+    fn delegate(n: u8) -> ContractTz1Hash {
+        ContractTz1Hash(vec![n; 20])
+    }
+
+    fn tracker() -> ConsensusTracker {
+        let mut tracker = ConsensusTracker::new(10);
+        let delegates = [
+            (delegate(1), Slots(vec![0, 2])),
+            (delegate(2), Slots(vec![1])),
+        ];
+        tracker.set_slots(LEVEL, delegates.into_iter().collect());
+        tracker
+    }
+
+    fn endorsement(slot: u16, round: i32, payload: u8, branch: u8) -> ConsensusOperation {
+        let mut op = OperationSimple::endorsement(
+            &BlockHash(vec![branch; 32]),
+            &BlockPayloadHash(vec![payload; 32]),
+            LEVEL,
+            round,
+            slot,
+        );
+        op.signature = Some(Signature(vec![payload ^ branch; 64]));
+        ConsensusOperation::from_simple(&op).unwrap()
+    }
+
+    fn preendorsement(slot: u16, round: i32, payload: u8) -> ConsensusOperation {
+        let mut op = OperationSimple::preendorsement(
+            &BlockHash(vec![1; 32]),
+            &BlockPayloadHash(vec![payload; 32]),
+            LEVEL,
+            round,
+            slot,
+        );
+        op.signature = Some(Signature(vec![payload; 64]));
+        ConsensusOperation::from_simple(&op).unwrap()
+    }
+
+    #[test]
+    fn unsigned_operation_is_ignored() {
+        let op = OperationSimple::endorsement(
+            &BlockHash(vec![1; 32]),
+            &BlockPayloadHash(vec![1; 32]),
+            LEVEL,
+            0,
+            0,
+        );
+        assert!(ConsensusOperation::from_simple(&op).is_none());
+    }
+
+    #[test]
+    fn double_endorsement() {
+        let mut tracker = tracker();
+
+        assert!(tracker
+            .add_operation(endorsement(0, 0, 1, 1))
+            .unwrap()
+            .is_none());
+        // same operation again
+        assert!(tracker
+            .add_operation(endorsement(0, 0, 1, 1))
+            .unwrap()
+            .is_none());
+        // another delegate, another round
+        assert!(tracker
+            .add_operation(endorsement(1, 0, 2, 1))
+            .unwrap()
+            .is_none());
+        assert!(tracker
+            .add_operation(endorsement(0, 1, 2, 1))
+            .unwrap()
+            .is_none());
+        // same payload on another branch is not a conflict
+        assert!(tracker
+            .add_operation(endorsement(0, 0, 1, 2))
+            .unwrap()
+            .is_none());
+
+        let evidence = tracker.add_operation(endorsement(0, 0, 2, 1)).unwrap();
+        match evidence {
+            Some(Evidence::DoubleEndorsement { key, op1, op2 }) => {
+                assert_eq!(key.delegate, delegate(1));
+                assert_eq!((key.level, key.round), (LEVEL, 0));
+                assert!(operation_hash(&op1).unwrap() < operation_hash(&op2).unwrap());
+            }
+            _ => panic!("double endorsement not found"),
+        }
+
+        // denounced only once, even when endorsing with another slot
+        assert!(tracker
+            .add_operation(endorsement(2, 0, 3, 1))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn double_preendorsement() {
+        let mut tracker = tracker();
+
+        assert!(tracker
+            .add_operation(preendorsement(1, 0, 1))
+            .unwrap()
+            .is_none());
+        // an endorsement does not conflict with a preendorsement
+        assert!(tracker
+            .add_operation(endorsement(1, 0, 2, 1))
+            .unwrap()
+            .is_none());
+
+        let evidence = tracker.add_operation(preendorsement(1, 0, 2)).unwrap();
+        assert!(matches!(
+            evidence,
+            Some(Evidence::DoublePreendorsement { ref key, .. }) if key.delegate == delegate(2)
+        ));
+    }
+
+    #[test]
+    fn unknown_slots() {
+        let mut tracker = tracker();
+
+        let mut op = OperationSimple::endorsement(
+            &BlockHash(vec![1; 32]),
+            &BlockPayloadHash(vec![1; 32]),
+            LEVEL + 1,
+            0,
+            0,
+        );
+        op.signature = Some(Signature(vec![1; 64]));
+        let op = ConsensusOperation::from_simple(&op).unwrap();
+        assert!(matches!(
+            tracker.add_operation(op),
+            Err(EvidenceError::UnknownSlots { level }) if level == LEVEL + 1
+        ));
+        assert!(matches!(
+            tracker.add_operation(endorsement(7, 0, 1, 1)),
+            Err(EvidenceError::UnknownSlot { slot: 7, .. })
+        ));
+    }
+
+    #[test]
+    fn double_baking() {
+        let mut tracker = tracker();
+        let key = ConsensusKey {
+            level: LEVEL,
+            round: 0,
+            delegate: delegate(1),
+        };
+
+        assert!(tracker
+            .add_block(key.clone(), BlockHash(vec![2; 32]), vec![2; 100])
+            .is_none());
+        assert!(tracker
+            .add_block(key.clone(), BlockHash(vec![2; 32]), vec![2; 100])
+            .is_none());
+        // another round
+        let next_round = ConsensusKey {
+            round: 1,
+            ..key.clone()
+        };
+        assert!(tracker
+            .add_block(next_round, BlockHash(vec![3; 32]), vec![3; 100])
+            .is_none());
+
+        let evidence = tracker.add_block(key.clone(), BlockHash(vec![1; 32]), vec![1; 100]);
+        let evidence = match evidence {
+            Some(evidence @ Evidence::DoubleBaking { .. }) => evidence,
+            _ => panic!("double baking not found"),
+        };
+        if let Evidence::DoubleBaking { bh1, bh2, .. } = &evidence {
+            // ordered by block hash
+            assert_eq!((bh1[0], bh2[0]), (1, 2));
+        }
+
+        let bytes = evidence.forge(&BlockHash(vec![9; 32])).unwrap();
+        assert_eq!(bytes.len(), 32 + 1 + 2 * (4 + 100) + 64);
+        assert_eq!(bytes[32], DOUBLE_BAKING_EVIDENCE_TAG);
+
+        assert!(tracker
+            .add_block(key, BlockHash(vec![4; 32]), vec![4; 100])
+            .is_none());
+    }
+
+    #[test]
+    fn forged_evidence_is_decoded() {
+        let mut tracker = tracker();
+        tracker.add_operation(endorsement(1, 0, 1, 1)).unwrap();
+        let evidence = tracker
+            .add_operation(endorsement(1, 0, 2, 1))
+            .unwrap()
+            .unwrap();
+
+        let bytes = evidence.forge(&BlockHash(vec![9; 32])).unwrap();
+        let contents = Contents::from_bytes(&bytes[32..bytes.len() - 64]).unwrap();
+        match (contents, evidence) {
+            (
+                Contents::DoubleEndorsementEvidence(decoded),
+                Evidence::DoubleEndorsement { op1, op2, .. },
+            ) => {
+                assert_eq!(decoded.op1.signature, op1.signature);
+                assert_eq!(decoded.op2.signature, op2.signature);
+            }
+            _ => panic!("unexpected contents"),
+        }
+    }
+
+    #[test]
+    fn prune() {
+        let mut tracker = tracker();
+        tracker.add_operation(endorsement(0, 0, 1, 1)).unwrap();
+
+        tracker.prune(LEVEL + 11);
+        assert!(!tracker.has_slots(LEVEL));
+        assert!(tracker.endorsements.is_empty());
+    }
+}
@@ -0,0 +1,43 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::sync::{atomic::AtomicBool, Arc};
+
+use reqwest::Url;
+use structopt::StructOpt;
+
+use baker::AccuserConfig;
+
+#[derive(StructOpt, Debug)]
+pub struct Arguments {
+    #[structopt(long)]
+    endpoint: Url,
+    /// Number of levels behind the head for which evidences are still searched
+    #[structopt(long, default_value = "200")]
+    preserved_levels: i32,
+}
+
+fn main() {
+    let Arguments {
+        endpoint,
+        preserved_levels,
+    } = Arguments::from_args();
+
+    let env = env_logger::Env::default().default_filter_or("info");
+    env_logger::Builder::from_env(env)
+        .format_timestamp_millis()
+        .try_init()
+        .unwrap();
+
+    let terminating = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&terminating))
+        .expect("cannot handle signals");
+
+    baker::run_accuser(
+        AccuserConfig {
+            endpoint,
+            preserved_levels,
+        },
+        terminating,
+    );
+}
@@ -0,0 +1,305 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::{
+    fs::{self, File},
+    mem,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use redux_rs::Store;
+use reqwest::Url;
+use sandbox_clock::SandboxClock;
+use serde::{Deserialize, Serialize};
+
+use crypto::hash::ChainId;
+
+use crate::{
+    machine::*,
+    report::Reporter,
+    services::{
+        http_server::{self, Response},
+        injection::{Injection, InjectionKind, InjectionOutcome},
+        metrics::Metrics,
+        SHARED_EVENT_ID,
+    },
+    EventWithTime, LiquidityBakingToggleVote, Services,
+};
+
+pub struct BakerConfig {
+    /// Directory with `secret_keys` file of the `tezos-client`, the baker state is stored here
+    pub base_dir: PathBuf,
+    /// Aliases of the keys in `secret_keys`, one per delegate baked for
+    pub bakers: Vec<String>,
+    pub endpoint: Url,
+    pub archive: bool,
+    pub liquidity_baking_toggle_vote: LiquidityBakingToggleVote,
+    /// Time source of the sandbox, the system time is used if not set
+    pub clock: Option<SandboxClock>,
+    /// Address where the metrics and the missed rights report of the delegates are served,
+    /// not served if not set
+    pub metrics_addr: Option<SocketAddr>,
+    /// Stop once this many blocks are injected and the state is stored, bake until terminated if not set
+    pub max_blocks: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ActionWithInternal {
+    pub event: EventWithTime,
+    pub actions: Vec<BakerAction>,
+    /// Outcomes of the injections performed while handling the event
+    #[serde(default)]
+    pub injections: Vec<Injection>,
+}
+
+/// Directory where the actions of the delegate are archived
+pub(crate) fn archive_path(base_dir: &Path, baker: &str, chain_id: &ChainId) -> PathBuf {
+    base_dir.join(format!("state_{baker}_{chain_id}_archive"))
+}
+
+/// The state machine of one delegate and the files where its state is stored
+struct Delegate {
+    store: Store<BakerStateEjectable, Services, Action>,
+    log: slog::Logger,
+    // store the state here, and then atomically swap to avoid corruption
+    // due to unexpected power outage
+    file_path_swap: PathBuf,
+    file_path: PathBuf,
+    archive_path: Option<PathBuf>,
+    previous_checkpoint: (i32, i32),
+    to_store: Vec<ActionWithInternal>,
+    /// Number of blocks injected by the delegate
+    blocks: usize,
+}
+
+impl Delegate {
+    /// Returns `true` if the state is stored on disk after the event
+    fn handle(&mut self, event: EventWithTime) -> bool {
+        if let BakerAction::ProposalEvent(ProposalEventAction { block, .. }) = &event.action {
+            let state = self.store.state.get().as_ref().as_ref().unwrap();
+            let st = state.as_ref();
+            self.store
+                .service
+                .metrics
+                .observe_head(&st.tb_config.map, block);
+
+            if let Some(archive_path) = &self.archive_path {
+                if !self.to_store.is_empty() {
+                    let state = self.store.state.get().as_ref().as_ref().unwrap();
+                    let st = state.as_ref();
+
+                    let name = format!(
+                        "_{}_{}_actions.json",
+                        st.tb_state.level().unwrap_or(0),
+                        st.tb_state.round().unwrap_or(0),
+                    );
+                    let file_actions = File::create(archive_path.join(name)).expect("msg");
+                    serde_json::to_writer(file_actions, &self.to_store).unwrap();
+                    self.to_store.clear();
+                }
+            }
+        }
+
+        self.store.dispatch(event.clone().action);
+        let state = self.store.state.get().as_ref().as_ref().unwrap();
+        let st = state.as_ref();
+        let injections = mem::take(&mut self.store.service.injections);
+        self.blocks += injections
+            .iter()
+            .filter(|injection| {
+                injection.kind == InjectionKind::Block
+                    && matches!(injection.outcome, InjectionOutcome::Injected(_))
+            })
+            .count();
+
+        if self.archive_path.is_some() {
+            let action_with_internal = ActionWithInternal {
+                event,
+                actions: st.actions.clone(),
+                injections,
+            };
+            self.to_store.push(action_with_internal);
+        }
+
+        let this_checkpoint = (
+            st.tb_state.level().unwrap_or(0),
+            st.tb_state.round().unwrap_or(0),
+        );
+        let previous_checkpoint = std::mem::replace(&mut self.previous_checkpoint, this_checkpoint);
+        if this_checkpoint == previous_checkpoint {
+            return false;
+        }
+
+        let file_swap = File::create(&self.file_path_swap).expect("msg");
+        serde_json::to_writer(file_swap, state).unwrap();
+        fs::rename(&self.file_path_swap, &self.file_path).unwrap();
+        let _ = fs::remove_file(&self.file_path_swap);
+        slog::info!(self.log, "stored on disk");
+
+        if let Some(archive_path) = &self.archive_path {
+            let name = format!("_{}_{}.json", this_checkpoint.0, this_checkpoint.1);
+            let dst = archive_path.join(name);
+            slog::info!(self.log, "archive {}", dst.display());
+            fs::copy(&self.file_path, dst).unwrap();
+        }
+
+        true
+    }
+}
+
+/// Runs the baker for each delegate until `terminating` is set, then stops once their states are stored on disk.
+///
+/// The delegates share the heads and the mempool of the node, each of them
+/// has its own key, state machine and state file.
+pub fn run(config: BakerConfig, terminating: Arc<AtomicBool>) {
+    let BakerConfig {
+        base_dir,
+        bakers,
+        endpoint,
+        archive,
+        liquidity_baking_toggle_vote,
+        clock,
+        metrics_addr,
+        max_blocks,
+    } = config;
+
+    let (services, events) = Services::new(endpoint, &base_dir, &bakers, clock);
+    let srv = services.first().expect("at least one delegate");
+    let log = srv.log.clone();
+    let chain_id = loop {
+        match srv.client.get_chain_id() {
+            Ok(v) => break v,
+            // the node may be gone, nothing is signed yet
+            Err(_) if terminating.load(Ordering::SeqCst) => return,
+            Err(_) => std::thread::sleep(Duration::from_millis(200)),
+        }
+    };
+    slog::info!(log, "chain_id: {chain_id}");
+    loop {
+        match srv.client.wait_bootstrapped() {
+            Ok(_) => break,
+            Err(_) if terminating.load(Ordering::SeqCst) => return,
+            Err(_) => std::thread::sleep(Duration::from_millis(200)),
+        }
+    }
+    slog::info!(log, "bootstrapped");
+    let constants = loop {
+        match srv.client.get_constants() {
+            Ok(v) => break v,
+            Err(_) if terminating.load(Ordering::SeqCst) => return,
+            Err(_) => std::thread::sleep(Duration::from_millis(200)),
+        }
+    };
+    // a single stream for all delegates
+    srv.client.monitor_heads(&chain_id).unwrap();
+
+    if let Some(addr) = metrics_addr {
+        let delegates = bakers
+            .iter()
+            .zip(&services)
+            .map(|(baker, srv)| (baker.clone(), srv.crypto.public_key_hash().clone()))
+            .collect::<Vec<_>>();
+        let metrics = Arc::new(Metrics::new(
+            delegates
+                .iter()
+                .zip(&services)
+                .map(|((baker, pkh), srv)| (baker.clone(), pkh.clone(), srv.metrics.clone()))
+                .collect(),
+        ));
+        let reporter = Reporter::new(srv.client.clone(), base_dir.clone(), delegates);
+        let served = http_server::spawn(addr, log.clone(), move |path| {
+            let (path, query) = path.split_once('?').unwrap_or((path, ""));
+            match path {
+                "/metrics" => Some(Response::prometheus(metrics.render())),
+                "/report" => {
+                    let cycle = query
+                        .split('&')
+                        .find_map(|pair| pair.strip_prefix("cycle=")?.parse().ok());
+                    let response = match reporter.report(cycle) {
+                        Ok(report) => Response::json(serde_json::to_string(&report).unwrap()),
+                        Err(err) => Response::error(err.to_string()),
+                    };
+                    Some(response)
+                }
+                _ => None,
+            }
+        });
+        if let Err(err) = served {
+            slog::error!(log, "cannot serve rpc on {addr}: {err}");
+        }
+    }
+
+    let mut delegates = vec![];
+    for (baker, srv) in bakers.iter().zip(services) {
+        let archive_path = archive_path(&base_dir, baker, &chain_id);
+        let archive_path = if archive {
+            let _ = fs::create_dir_all(&archive_path);
+            Some(archive_path)
+        } else {
+            None
+        };
+
+        let file_path = base_dir.join(format!("state_{baker}_{chain_id}.json"));
+        let persistent_state = File::open(&file_path)
+            .and_then(|rdr| serde_json::from_reader::<_, BakerState>(rdr).map_err(From::from));
+
+        let initial_state = if let Ok(persistent_state) = persistent_state {
+            persistent_state
+        } else {
+            BakerState::new(
+                chain_id.clone(),
+                constants.clone(),
+                srv.crypto.public_key_hash().clone(),
+                liquidity_baking_toggle_vote,
+            )
+        };
+
+        let initial_state = BakerStateEjectable(Some(initial_state));
+        let reducer = baker_reducer::<BakerStateEjectable, Action>;
+        let effects = baker_effects::<BakerStateEjectable, Services, Action>;
+        let initial_time = srv
+            .clock
+            .as_ref()
+            .map_or_else(SystemTime::now, SandboxClock::now);
+        let log = srv.log.clone();
+        delegates.push(Delegate {
+            store: Store::new(reducer, effects, srv, initial_time, initial_state),
+            log,
+            file_path_swap: base_dir.join(format!(".state_{baker}_{chain_id}.json")),
+            file_path,
+            archive_path,
+            previous_checkpoint: (0, 0),
+            to_store: vec![],
+            blocks: 0,
+        });
+    }
+
+    for (id, event) in events {
+        let mut stored = false;
+        for (n, delegate) in delegates.iter_mut().enumerate() {
+            if id == SHARED_EVENT_ID || usize::from(id) == n {
+                stored |= delegate.handle(event.clone());
+            }
+        }
+
+        let blocks = delegates
+            .iter()
+            .map(|delegate| delegate.blocks)
+            .sum::<usize>();
+        if max_blocks.map_or(false, |max_blocks| blocks >= max_blocks) {
+            terminating.store(true, Ordering::SeqCst);
+        }
+
+        if stored && terminating.load(Ordering::SeqCst) {
+            // ctrl+c pressed or enough blocks baked, state is on disk, terminate the baker
+            slog::info!(log, "terminated gracefully");
+            break;
+        }
+    }
+}
@@ -10,6 +10,15 @@ mod proof_of_work;
 
 pub mod machine;
 
+mod daemon;
+pub use self::daemon::{run, BakerConfig};
+
+pub mod accuser;
+pub use self::accuser::{run as run_accuser, AccuserConfig};
+
+pub mod report;
+pub use self::report::{report, ReportConfig};
+
 mod services;
 pub use self::services::{
     client::{
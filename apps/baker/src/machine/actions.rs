@@ -10,7 +10,10 @@ use crypto::hash::{BlockHash, ContractTz1Hash, NonceHash};
 use redux_rs::EnablingCondition;
 use tezos_messages::protocol::proto_012::operation::{InlinedEndorsement, InlinedPreendorsement};
 
-use crate::services::event::{Block, OperationSimple, Slots};
+use crate::services::{
+    client::Constants,
+    event::{Block, OperationSimple, Slots},
+};
 
 use super::{state::Gathering, BakerState};
 
@@ -46,6 +49,9 @@ where
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ProposalEventAction {
     pub block: Block,
+    /// Constants of the new protocol, set only on the migration block
+    #[serde(default)]
+    pub constants: Option<Constants>,
 }
 
 impl<S> EnablingCondition<S> for ProposalEventAction
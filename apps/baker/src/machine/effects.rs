@@ -31,6 +31,7 @@ use crate::{
             RpcErrorInner,
         },
         event::OperationSimple,
+        injection::{Injection, InjectionKind, InjectionOutcome},
         BakerService,
     },
 };
@@ -137,11 +138,13 @@ where
             let mut tries = 3;
             while tries > 0 {
                 tries -= 1;
-                if let Err(err) = store
-                    .service
-                    .client()
-                    .monitor_operations(Duration::from_secs(3600))
-                {
+                let timeout = Duration::from_secs(3600);
+                let client = store.service.client();
+                let r = match st.tb_state.hash() {
+                    Some(head) => client.monitor_operations_after(&head, timeout),
+                    None => client.monitor_operations(timeout),
+                };
+                if let Err(err) = r {
                     slog::error!(store.service.log(), " .  {err}");
                 } else {
                     break;
@@ -188,17 +191,36 @@ where
                     Ok(v) => v,
                     Err(err) => {
                         slog::error!(store.service.log(), " .  {err}");
+                        let outcome = InjectionOutcome::Failed(err.to_string());
+                        store.service.injected(Injection::new(
+                            InjectionKind::Preendorsement,
+                            c.level,
+                            c.round,
+                            outcome,
+                        ));
                         return;
                     }
                 };
-            match store
-                .service
-                .client()
-                .inject_operation(&st.chain_id, hex::encode(data), false)
-            {
-                Ok(hash) => slog::info!(store.service.log(), " .  inject preendorsement: {hash}"),
-                Err(err) => slog::error!(store.service.log(), " .  {err}"),
-            }
+            let outcome = match store.service.client().inject_operation(
+                &st.chain_id,
+                hex::encode(data),
+                false,
+            ) {
+                Ok(hash) => {
+                    slog::info!(store.service.log(), " .  inject preendorsement: {hash}");
+                    InjectionOutcome::Injected(hash.to_string())
+                }
+                Err(err) => {
+                    slog::error!(store.service.log(), " .  {err}");
+                    InjectionOutcome::Failed(err.to_string())
+                }
+            };
+            store.service.injected(Injection::new(
+                InjectionKind::Preendorsement,
+                c.level,
+                c.round,
+                outcome,
+            ));
         }
         Some(BakerAction::Vote(VoteAction { op })) => {
             let InlinedEndorsementMempoolContents::Endorsement(c) = &op.operations;
@@ -211,17 +233,36 @@ where
                     Ok(v) => v,
                     Err(err) => {
                         slog::error!(store.service.log(), " .  {err}");
+                        let outcome = InjectionOutcome::Failed(err.to_string());
+                        store.service.injected(Injection::new(
+                            InjectionKind::Endorsement,
+                            c.level,
+                            c.round,
+                            outcome,
+                        ));
                         return;
                     }
                 };
-            match store
-                .service
-                .client()
-                .inject_operation(&st.chain_id, hex::encode(data), false)
-            {
-                Ok(hash) => slog::info!(store.service.log(), " .  inject endorsement: {hash}"),
-                Err(err) => slog::error!(store.service.log(), " .  {err}"),
-            }
+            let outcome = match store.service.client().inject_operation(
+                &st.chain_id,
+                hex::encode(data),
+                false,
+            ) {
+                Ok(hash) => {
+                    slog::info!(store.service.log(), " .  inject endorsement: {hash}");
+                    InjectionOutcome::Injected(hash.to_string())
+                }
+                Err(err) => {
+                    slog::error!(store.service.log(), " .  {err}");
+                    InjectionOutcome::Failed(err.to_string())
+                }
+            };
+            store.service.injected(Injection::new(
+                InjectionKind::Endorsement,
+                c.level,
+                c.round,
+                outcome,
+            ));
         }
         Some(BakerAction::Propose(ProposeAction {
             payload_round,
@@ -282,7 +323,7 @@ fn inject_block<Srv>(
 
     slog::info!(srv.log(), "begin preapply");
     let r = match st.protocol {
-        Protocol::Ithaca => srv.client().preapply_block::<ProtocolBlockHeaderI>(
+        Some(Protocol::Ithaca) => srv.client().preapply_block::<ProtocolBlockHeaderI>(
             payload_hash,
             payload_round,
             seed_nonce_hash,
@@ -291,7 +332,7 @@ fn inject_block<Srv>(
             operations.clone(),
             liquidity_baking_toggle_vote,
         ),
-        Protocol::Jakarta => srv.client().preapply_block::<ProtocolBlockHeaderJ>(
+        Some(Protocol::Jakarta) => srv.client().preapply_block::<ProtocolBlockHeaderJ>(
             payload_hash,
             payload_round,
             seed_nonce_hash,
@@ -300,12 +341,18 @@ fn inject_block<Srv>(
             operations.clone(),
             liquidity_baking_toggle_vote,
         ),
+        None => {
+            slog::error!(srv.log(), " .  unknown protocol, refuse to sign the block");
+            return;
+        }
     };
     slog::info!(srv.log(), "end preapply");
     let (mut header, ops) = match r {
         Ok(v) => v,
         Err(err) => {
             slog::error!(srv.log(), " .  {err}");
+            let outcome = InjectionOutcome::PreapplyFailed(err.to_string());
+            srv.injected(Injection::new(InjectionKind::Block, level, round, outcome));
             return;
         }
     };
@@ -364,6 +411,8 @@ fn inject_block<Srv>(
         Ok(v) => v,
         Err(err) => {
             slog::error!(srv.log(), " .  {err}");
+            let outcome = InjectionOutcome::Failed(err.to_string());
+            srv.injected(Injection::new(InjectionKind::Block, level, round, outcome));
             return;
         }
     };
@@ -372,14 +421,20 @@ fn inject_block<Srv>(
         .client()
         .inject_block(hex::encode(data), valid_operations)
     {
-        Ok(hash) => slog::info!(
-            srv.log(),
-            " .  inject block: {}:{}, {hash}",
-            header.level(),
-            round
-        ),
+        Ok(hash) => {
+            slog::info!(
+                srv.log(),
+                " .  inject block: {}:{}, {hash}",
+                header.level(),
+                round
+            );
+            let outcome = InjectionOutcome::Injected(hash.to_string());
+            srv.injected(Injection::new(InjectionKind::Block, level, round, outcome));
+        }
         Err(err) => {
             slog::error!(srv.log(), " .  {err}");
+            let outcome = InjectionOutcome::Failed(err.to_string());
+            srv.injected(Injection::new(InjectionKind::Block, level, round, outcome));
             if let RpcErrorInner::NodeError(err, _) = err.as_ref() {
                 let invalid_ops = extract_invalid_ops(err);
                 if !invalid_ops.is_empty() {
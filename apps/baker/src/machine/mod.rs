@@ -13,7 +13,7 @@ pub use self::{
     actions::*,
     effects::baker_effects,
     reducer::baker_reducer,
-    state::{BakerState, BakerStateEjectable},
+    state::{BakerState, BakerStateEjectable, SlotsInfo},
 };
 
 #[cfg(test)]
@@ -340,4 +340,132 @@ mod tests {
             }
         }
     }
+
+    mod protocol {
+        use std::time::Duration;
+
+        use serde::Deserialize;
+        use tenderbake::Timestamp;
+
+        use super::*;
+        use crate::{
+            machine::LogWarningAction,
+            services::client::{head_event, BlockProtocols, Constants, MonitorHead},
+            Protocol,
+        };
+
+        /// Head sent by `monitor/heads`, with the responses the baker fetches for it
+        #[derive(Deserialize)]
+        struct RawHead {
+            header: MonitorHead,
+            protocols: BlockProtocols,
+            #[serde(default)]
+            constants: Option<Constants>,
+        }
+
+        #[test]
+        fn migration() {
+            test_initialized(|state, _, _| {
+                let heads = serde_json::from_str::<Vec<RawHead>>(include_str!(
+                    "test_data/migration_heads.json"
+                ))
+                .unwrap();
+
+                // the state was stored by the Ithaca baker
+                assert_eq!(state.as_ref().protocol, Some(Protocol::Ithaca));
+                let quorum = state.as_ref().tb_config.quorum;
+
+                let mut state = state;
+                for head in heads {
+                    let migration = head.protocols.is_migration();
+                    let head = head_event(head.header, head.protocols, head.constants).unwrap();
+                    let level = head.block.level;
+                    assert_eq!(migration, level == 53);
+
+                    // the protocol data of Ithaca and Jakarta blocks are decoded
+                    assert!(!head.block.transition);
+                    let payload_hash = match level {
+                        52 => "vh3BgF4Az26qm53QaNKLmrVCgXSS2eSpBD89DACq7XsUSmz3K15R",
+                        53 => "vh1pqgscdfGWixXcYJHPkbUDTHLFkZJWgmjdL75ijLGzTMMEi48S",
+                        54 => "vh2ReVmkxfLqxFL8TCgpxUUgzj6ZZvnWi3uTmci3dQebTcU2VWg2",
+                        _ => "vh3JyzSVxRFoFkhgaA9jAL7gBrS1JqTFVv6qGd8hZtZu7YAf5gK2",
+                    };
+                    assert_eq!(head.block.payload_hash.to_base58_check(), payload_hash);
+
+                    let now = Timestamp {
+                        unix_epoch: Duration::from_secs(head.block.timestamp),
+                    };
+                    state = state.handle_event(EventWithTime {
+                        action: BakerAction::ProposalEvent(head),
+                        now,
+                    });
+
+                    let st = state.as_ref();
+                    match level {
+                        52 => {
+                            assert_eq!(st.protocol, Some(Protocol::Ithaca));
+                            assert_eq!(st.tb_config.quorum, quorum);
+                        }
+                        // the migration block, its successor is a Jakarta block
+                        53 | 54 => {
+                            assert_eq!(st.protocol, Some(Protocol::Jakarta));
+                            assert_eq!(st.tb_config.quorum, 2 * (512 / 3) + 1);
+                            assert_eq!(st.tb_config.map.committee_size, 512);
+                            assert_eq!(
+                                st.tb_config.timing.minimal_block_delay,
+                                Duration::from_secs(2)
+                            );
+                            assert_eq!(st.nonces.blocks_per_cycle, 16);
+                        }
+                        // some future protocol
+                        _ => assert_eq!(st.protocol, None),
+                    }
+                }
+            })
+        }
+
+        #[test]
+        fn unknown_protocol_refuse_to_sign() {
+            test_initialized(|mut state, level, validators| {
+                state.as_mut().protocol = None;
+
+                let st = state.as_ref();
+                let quorum_size = st.tb_config.quorum as usize;
+                let predecessor_hash = st.tb_state.predecessor_hash().unwrap();
+                let payload_hash = st.tb_state.payload_hash().unwrap();
+                let round = st.tb_state.round().unwrap();
+                let now = st.tb_state.timestamp().unwrap();
+
+                let preendorsements = BakerAction::OperationsEvent(OperationsEventAction {
+                    operations: validators
+                        .iter()
+                        .map(|&(slot, _)| {
+                            OperationSimple::preendorsement(
+                                &predecessor_hash,
+                                &payload_hash,
+                                level,
+                                round,
+                                slot,
+                            )
+                        })
+                        .collect(),
+                });
+                let total_power = validators.iter().map(|&(_, power)| power).sum::<usize>();
+                assert!(total_power >= quorum_size);
+
+                let state = state.handle_event(EventWithTime {
+                    action: preendorsements,
+                    now,
+                });
+                let actions = &state.as_ref().actions;
+                assert!(!actions.iter().any(|a| matches!(
+                    a,
+                    BakerAction::PreVote(_) | BakerAction::Vote(_) | BakerAction::Propose(_)
+                )));
+                assert!(actions
+                    .iter()
+                    .any(|a| matches!(a, BakerAction::LogWarning(LogWarningAction { .. }))));
+            })
+        }
+    }
 }
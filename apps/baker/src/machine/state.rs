@@ -11,14 +11,17 @@ use serde::{Deserialize, Serialize};
 
 use crypto::hash::{BlockHash, BlockPayloadHash, ChainId, ContractTz1Hash, Signature};
 use tenderbake as tb;
-use tezos_messages::protocol::proto_012::operation::{
-    EndorsementOperation, InlinedEndorsement, InlinedEndorsementMempoolContents,
-    InlinedEndorsementMempoolContentsEndorsementVariant, InlinedPreendorsement,
-    InlinedPreendorsementContents, InlinedPreendorsementVariant,
+use tezos_messages::protocol::proto_012::{
+    operation::{
+        EndorsementOperation, InlinedEndorsement, InlinedEndorsementMempoolContents,
+        InlinedEndorsementMempoolContentsEndorsementVariant, InlinedPreendorsement,
+        InlinedPreendorsementContents, InlinedPreendorsementVariant,
+    },
+    operation_selection::{self, QUOTAS},
 };
 
 use crate::services::{
-    client::{Constants, LiquidityBakingToggleVote, Protocol},
+    client::{default_hard_gas_limit_per_block, Constants, LiquidityBakingToggleVote, Protocol},
     event::{Block, OperationKind, OperationSimple, Slots},
     EventWithTime,
 };
@@ -98,12 +101,15 @@ impl fmt::Display for BakerState {
 
 #[derive(Serialize, Deserialize)]
 pub struct Initialized {
-    #[serde(default)]
-    pub protocol: Protocol,
+    /// Protocol of the next block, `None` if the baker does not support it
+    #[serde(default = "default_protocol")]
+    pub protocol: Option<Protocol>,
     #[serde(default)]
     pub liquidity_baking_toggle_vote: LiquidityBakingToggleVote,
     pub chain_id: ChainId,
     pub proof_of_work_threshold: u64,
+    #[serde(default = "default_hard_gas_limit_per_block")]
+    pub hard_gas_limit_per_block: u64,
     pub this: ContractTz1Hash,
     // cycle state
     pub nonces: CycleNonce,
@@ -122,6 +128,11 @@ pub struct Initialized {
     pub actions: Vec<BakerAction>,
 }
 
+// the state stored before the protocol was tracked is an Ithaca state
+fn default_protocol() -> Option<Protocol> {
+    Some(Protocol::Ithaca)
+}
+
 pub struct BakerStateEjectable(pub Option<BakerState>);
 
 impl AsRef<Option<BakerState>> for BakerStateEjectable {
@@ -163,7 +174,6 @@ impl BakerState {
         chain_id: ChainId,
         constants: Constants,
         this: ContractTz1Hash,
-        protocol: Protocol,
         liquidity_baking_toggle_vote: LiquidityBakingToggleVote,
     ) -> Self {
         let timing = tb::TimingLinearGrow {
@@ -184,10 +194,12 @@ impl BakerState {
         };
 
         BakerState::Idle(Initialized {
-            protocol,
+            // known from the first head
+            protocol: None,
             liquidity_baking_toggle_vote,
             chain_id,
             proof_of_work_threshold: constants.proof_of_work_threshold,
+            hard_gas_limit_per_block: constants.hard_gas_limit_per_block,
             this,
             nonces: CycleNonce {
                 blocks_per_commitment: constants.blocks_per_commitment,
@@ -362,9 +374,22 @@ impl BakerState {
                     s => s,
                 }
             }
-            BakerAction::ProposalEvent(ProposalEventAction { block }) => {
+            BakerAction::ProposalEvent(ProposalEventAction { block, constants }) => {
                 // dbg!(format!("handle in state machine: {}:{}", block.level, block.round));
                 let state = self.as_mut();
+                if let Some(constants) = constants {
+                    let description = format!("migration block {}, new constants", block.level);
+                    state.actions.push(BakerAction::LogInfo(LogInfoAction { with_prefix: true, description }));
+                    state.set_constants(constants);
+                }
+                if state.protocol != block.next_protocol {
+                    let description = match &block.next_protocol {
+                        Some(protocol) => format!("next protocol {protocol:?}"),
+                        None => "next protocol is unknown, will not sign".to_string(),
+                    };
+                    state.actions.push(BakerAction::LogWarning(LogWarningAction { description }));
+                    state.protocol = block.next_protocol;
+                }
                 if block.level < state.tb_config.map.level {
                     let description = "old_block".to_string();
                     state.actions.push(BakerAction::LogWarning(LogWarningAction { description }));
@@ -562,6 +587,16 @@ impl Initialized {
                             deadline,
                         }));
                 }
+                // the encoding is unknown, do not sign anything
+                tb::Action::Propose(..)
+                | tb::Action::Preendorse { .. }
+                | tb::Action::Endorse { .. }
+                    if self.protocol.is_none() =>
+                {
+                    let description = "unknown protocol, refuse to sign".to_string();
+                    self.actions
+                        .push(BakerAction::LogWarning(LogWarningAction { description }));
+                }
                 tb::Action::Propose(block, _, _) => {
                     self.propose(*block);
                 }
@@ -581,6 +616,21 @@ impl Initialized {
         }
     }
 
+    /// Switch to the constants of a new protocol
+    fn set_constants(&mut self, constants: Constants) {
+        self.tb_config.timing = tb::TimingLinearGrow {
+            minimal_block_delay: constants.minimal_block_delay,
+            delay_increment_per_round: constants.delay_increment_per_round,
+        };
+        self.tb_config.map.committee_size = constants.consensus_committee_size;
+        self.tb_config.quorum = 2 * (constants.consensus_committee_size / 3) + 1;
+        self.proof_of_work_threshold = constants.proof_of_work_threshold;
+        self.hard_gas_limit_per_block = constants.hard_gas_limit_per_block;
+        self.nonces.blocks_per_commitment = constants.blocks_per_commitment;
+        self.nonces.blocks_per_cycle = constants.blocks_per_cycle;
+        self.nonces.nonce_length = constants.nonce_length;
+    }
+
     fn pre_vote(&mut self, pred_hash: BlockHash, block_id: tb::BlockId) {
         let slot = self
             .tb_config
@@ -654,12 +704,7 @@ impl Initialized {
             .map(|q| q.votes.ids.into_values())
             .into_iter()
             .flatten();
-        let mut operations = [
-            endorsements.chain(preendorsements).collect::<Vec<_>>(),
-            vec![],
-            vec![],
-            vec![],
-        ];
+        let mut candidates = [vec![], vec![], vec![], vec![]];
         let mut hashes = BTreeSet::new();
         for op in payload.operations {
             op.hash.as_ref().unwrap();
@@ -679,17 +724,25 @@ impl Initialized {
                     self.actions
                         .push(BakerAction::LogWarning(LogWarningAction { description }));
                 }
-                Some(OperationKind::Votes) => operations[1].push(op),
+                Some(OperationKind::Votes) => candidates[1].push(op.into_candidate()),
                 Some(OperationKind::Anonymous) => {
                     let mut op = op;
                     if op.signature.is_none() {
                         op.signature = Some(Signature(vec![0x55; 64]));
                     }
-                    operations[2].push(op)
+                    candidates[2].push(op.into_candidate())
                 }
-                Some(OperationKind::Managers) => operations[3].push(op),
+                Some(OperationKind::Managers) => candidates[3].push(op.into_candidate()),
             }
         }
+        let [_, votes, anonymous, managers] = candidates;
+        // the consensus operations are the quorum, they all go into the block
+        let operations = [
+            endorsements.chain(preendorsements).collect::<Vec<_>>(),
+            operation_selection::select(votes, &QUOTAS[1], 0),
+            operation_selection::select(anonymous, &QUOTAS[2], 0),
+            operation_selection::select(managers, &QUOTAS[3], self.hard_gas_limit_per_block),
+        ];
         let payload_round = payload.payload_round;
         let seed_nonce_hash = self.nonces.gen_nonce(block.level);
         let timestamp = block.time_header.timestamp.unix_epoch.as_secs() as i64;
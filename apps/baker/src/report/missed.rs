@@ -0,0 +1,353 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crypto::hash::ContractTz1Hash;
+use tezos_messages::protocol::proto_012::operation::InlinedEndorsementMempoolContents;
+
+use crate::{
+    daemon::ActionWithInternal,
+    machine::{BakerAction, ProposalEventAction, ProposeAction, VoteAction},
+    services::{
+        event::Slots,
+        injection::{Injection, InjectionKind, InjectionOutcome},
+    },
+};
+
+/// Why the delegate did not use its right
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "cause", rename_all = "snake_case")]
+pub enum Cause {
+    /// The baker archived nothing around the level, it was not running, or ran without `--archive`
+    NotArchived,
+    /// The baker did not get the head, or the (pre)quorum on it, before its round ended
+    LateHead,
+    PreapplyFailure {
+        error: String,
+    },
+    InjectionError {
+        error: String,
+    },
+    /// The node accepted the block or the endorsement, but it is not on chain
+    NotIncluded,
+    /// The baker acted, but the outcome of the injection is not archived
+    Unknown,
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cause::NotArchived => write!(f, "not archived"),
+            Cause::LateHead => write!(f, "late head"),
+            Cause::PreapplyFailure { error } => write!(f, "preapply failure: {error}"),
+            Cause::InjectionError { error } => write!(f, "injection error: {error}"),
+            Cause::NotIncluded => write!(f, "not included"),
+            Cause::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MissedRight {
+    pub level: i32,
+    pub round: i32,
+    #[serde(flatten)]
+    pub cause: Cause,
+}
+
+/// What the baker saw and did, as stored in its archive
+#[derive(Default)]
+pub struct ArchiveLog {
+    heads: BTreeSet<i32>,
+    proposals: BTreeSet<(i32, i32)>,
+    votes: BTreeSet<i32>,
+    injections: BTreeMap<(InjectionKind, i32, i32), InjectionOutcome>,
+}
+
+impl ArchiveLog {
+    pub(crate) fn add(&mut self, record: &ActionWithInternal) {
+        if let BakerAction::ProposalEvent(ProposalEventAction { block, .. }) = &record.event.action
+        {
+            self.head(block.level);
+        }
+        for action in &record.actions {
+            match action {
+                BakerAction::Propose(ProposeAction { level, round, .. }) => {
+                    self.proposed(*level, *round)
+                }
+                BakerAction::Vote(VoteAction { op }) => {
+                    let InlinedEndorsementMempoolContents::Endorsement(c) = &op.operations;
+                    self.voted(c.level);
+                }
+                _ => (),
+            }
+        }
+        for injection in &record.injections {
+            self.injected(injection);
+        }
+    }
+
+    fn head(&mut self, level: i32) {
+        self.heads.insert(level);
+    }
+
+    fn proposed(&mut self, level: i32, round: i32) {
+        self.proposals.insert((level, round));
+    }
+
+    fn voted(&mut self, level: i32) {
+        self.votes.insert(level);
+    }
+
+    /// The last outcome wins, the baker retries the block without the outdated operations
+    fn injected(&mut self, injection: &Injection) {
+        let key = (injection.kind, injection.level, injection.round);
+        self.injections.insert(key, injection.outcome.clone());
+    }
+
+    /// The baker saw the predecessor or the block of the level
+    fn covers(&self, level: i32) -> bool {
+        self.heads.range((level - 1)..=level).next().is_some()
+    }
+
+    fn cause(&self, outcome: Option<&InjectionOutcome>, acted: bool) -> Cause {
+        match outcome {
+            Some(InjectionOutcome::PreapplyFailed(error)) => Cause::PreapplyFailure {
+                error: error.clone(),
+            },
+            Some(InjectionOutcome::Failed(error)) => Cause::InjectionError {
+                error: error.clone(),
+            },
+            Some(InjectionOutcome::Injected(_)) => Cause::NotIncluded,
+            None if acted => Cause::Unknown,
+            None => Cause::LateHead,
+        }
+    }
+
+    fn bake_cause(&self, level: i32, round: i32) -> Cause {
+        if !self.covers(level) {
+            return Cause::NotArchived;
+        }
+        let outcome = self.injections.get(&(InjectionKind::Block, level, round));
+        self.cause(outcome, self.proposals.contains(&(level, round)))
+    }
+
+    fn endorsement_cause(&self, level: i32) -> Cause {
+        if !self.covers(level) {
+            return Cause::NotArchived;
+        }
+        let kind = InjectionKind::Endorsement;
+        let outcome = self
+            .injections
+            .range((kind, level, i32::MIN)..=(kind, level, i32::MAX))
+            .next_back()
+            .map(|(_, outcome)| outcome);
+        self.cause(outcome, self.votes.contains(&level))
+    }
+}
+
+/// The block of the level in the main chain and the rights at the level
+pub struct LevelOnChain {
+    pub level: i32,
+    pub round: i32,
+    pub rights: BTreeMap<ContractTz1Hash, Slots>,
+    /// Slots of the endorsements included in the next block, `None` if there is no next block yet
+    pub endorsed_slots: Option<BTreeSet<u16>>,
+}
+
+impl LevelOnChain {
+    fn first_slot(&self, delegate: &ContractTz1Hash) -> Option<u16> {
+        self.rights.get(delegate)?.0.iter().min().cloned()
+    }
+
+    /// The rounds of the delegate before the round the block was baked at, each slot
+    /// of the delegate is a round where it bakes
+    pub fn missed_bakes(
+        &self,
+        delegate: &ContractTz1Hash,
+        archive: &ArchiveLog,
+    ) -> Vec<MissedRight> {
+        let slots = match self.rights.get(delegate) {
+            Some(slots) => slots.0.iter().cloned().collect::<BTreeSet<u16>>(),
+            None => return vec![],
+        };
+        slots
+            .into_iter()
+            .map(i32::from)
+            .take_while(|round| *round < self.round)
+            .map(|round| MissedRight {
+                level: self.level,
+                round,
+                cause: archive.bake_cause(self.level, round),
+            })
+            .collect()
+    }
+
+    /// The delegate has slots, but the next block has no endorsement of the delegate
+    pub fn missed_endorsement(
+        &self,
+        delegate: &ContractTz1Hash,
+        archive: &ArchiveLog,
+    ) -> Option<MissedRight> {
+        let slot = self.first_slot(delegate)?;
+        if self.endorsed_slots.as_ref()?.contains(&slot) {
+            return None;
+        }
+        Some(MissedRight {
+            level: self.level,
+            round: self.round,
+            cause: archive.endorsement_cause(self.level),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delegate(n: u8) -> ContractTz1Hash {
+        ContractTz1Hash(vec![n; 20])
+    }
+
+    fn level(level: i32, round: i32, endorsed_slots: &[u16]) -> LevelOnChain {
+        LevelOnChain {
+            level,
+            round,
+            rights: [
+                (delegate(1), Slots(vec![1, 3])),
+                (delegate(2), Slots(vec![0, 2])),
+            ]
+            .into_iter()
+            .collect(),
+            endorsed_slots: Some(endorsed_slots.iter().cloned().collect()),
+        }
+    }
+
+    fn injection(
+        kind: InjectionKind,
+        level: i32,
+        round: i32,
+        outcome: InjectionOutcome,
+    ) -> Injection {
+        Injection::new(kind, level, round, outcome)
+    }
+
+    #[test]
+    fn not_missed() {
+        let archive = ArchiveLog::default();
+        // baked at our round, and endorsed
+        let chain = level(10, 1, &[0, 1]);
+        assert!(chain.missed_bakes(&delegate(1), &archive).is_empty());
+        assert_eq!(chain.missed_endorsement(&delegate(1), &archive), None);
+        // no rights
+        assert!(chain.missed_bakes(&delegate(3), &archive).is_empty());
+        assert_eq!(chain.missed_endorsement(&delegate(3), &archive), None);
+        // the endorsements are not known yet
+        let chain = LevelOnChain {
+            endorsed_slots: None,
+            ..level(10, 0, &[])
+        };
+        assert_eq!(chain.missed_endorsement(&delegate(1), &archive), None);
+    }
+
+    #[test]
+    fn not_archived() {
+        let mut archive = ArchiveLog::default();
+        archive.head(20);
+        let chain = level(10, 2, &[0]);
+        let missed = chain.missed_bakes(&delegate(1), &archive).remove(0);
+        assert_eq!((missed.level, missed.round), (10, 1));
+        assert_eq!(missed.cause, Cause::NotArchived);
+        let missed = chain.missed_endorsement(&delegate(1), &archive).unwrap();
+        assert_eq!((missed.level, missed.round), (10, 2));
+        assert_eq!(missed.cause, Cause::NotArchived);
+    }
+
+    #[test]
+    fn every_missed_round() {
+        let mut archive = ArchiveLog::default();
+        archive.head(9);
+        let chain = level(10, 4, &[0]);
+        let rounds = |delegate| {
+            chain
+                .missed_bakes(&delegate, &archive)
+                .into_iter()
+                .map(|missed| missed.round)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(rounds(delegate(1)), [1, 3]);
+        assert_eq!(rounds(delegate(2)), [0, 2]);
+
+        // the block is baked at the second round of the delegate
+        let chain = level(10, 3, &[0]);
+        let missed = chain.missed_bakes(&delegate(1), &archive);
+        assert_eq!(missed.len(), 1);
+        assert_eq!(missed[0].round, 1);
+    }
+
+    #[test]
+    fn late_head() {
+        let mut archive = ArchiveLog::default();
+        archive.head(9);
+        let chain = level(10, 2, &[0]);
+        let missed = chain.missed_bakes(&delegate(1), &archive).remove(0);
+        assert_eq!(missed.cause, Cause::LateHead);
+        let missed = chain.missed_endorsement(&delegate(1), &archive).unwrap();
+        assert_eq!(missed.cause, Cause::LateHead);
+    }
+
+    #[test]
+    fn preapply_failure_then_retry() {
+        let mut archive = ArchiveLog::default();
+        archive.head(9);
+        archive.proposed(10, 1);
+        let failed = InjectionOutcome::PreapplyFailed("failed".to_owned());
+        archive.injected(&injection(InjectionKind::Block, 10, 1, failed));
+        let chain = level(10, 2, &[1]);
+        let missed = chain.missed_bakes(&delegate(1), &archive).remove(0);
+        assert_eq!(
+            missed.cause,
+            Cause::PreapplyFailure {
+                error: "failed".to_owned()
+            }
+        );
+
+        // the retry is injected, but another block is on chain
+        let injected = InjectionOutcome::Injected("BLock".to_owned());
+        archive.injected(&injection(InjectionKind::Block, 10, 1, injected));
+        let missed = chain.missed_bakes(&delegate(1), &archive).remove(0);
+        assert_eq!(missed.cause, Cause::NotIncluded);
+    }
+
+    #[test]
+    fn endorsement_outcome() {
+        let mut archive = ArchiveLog::default();
+        archive.head(10);
+        archive.voted(10);
+        let chain = level(10, 0, &[0]);
+        let missed = chain.missed_endorsement(&delegate(1), &archive).unwrap();
+        assert_eq!(missed.cause, Cause::Unknown);
+
+        let failed = InjectionOutcome::Failed("refused".to_owned());
+        archive.injected(&injection(InjectionKind::Endorsement, 10, 0, failed));
+        let missed = chain.missed_endorsement(&delegate(1), &archive).unwrap();
+        assert_eq!(
+            missed.cause,
+            Cause::InjectionError {
+                error: "refused".to_owned()
+            }
+        );
+
+        // the endorsement of the block at the later round is the latest
+        let injected = InjectionOutcome::Injected("oo".to_owned());
+        archive.injected(&injection(InjectionKind::Endorsement, 10, 1, injected));
+        let missed = chain.missed_endorsement(&delegate(1), &archive).unwrap();
+        assert_eq!(missed.cause, Cause::NotIncluded);
+    }
+}
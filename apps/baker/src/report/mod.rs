@@ -0,0 +1,308 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+mod missed;
+pub use self::missed::{ArchiveLog, Cause, LevelOnChain, MissedRight};
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+};
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crypto::hash::ContractTz1Hash;
+
+use crate::{
+    daemon::{archive_path, ActionWithInternal},
+    services::{
+        client::RpcError,
+        event::OperationKind,
+        key::{CryptoService, ReadKeyError},
+        logger,
+    },
+    RpcClient,
+};
+
+#[derive(Debug, Error)]
+pub enum ReportError {
+    #[error("rpc: {_0}")]
+    Rpc(#[from] RpcError),
+    #[error("key: {_0}")]
+    Key(#[from] ReadKeyError),
+    #[error("archive: {_0}")]
+    Io(#[from] io::Error),
+    #[error("archive {}: {error}", path.display())]
+    De {
+        path: PathBuf,
+        error: serde_json::Error,
+    },
+}
+
+pub struct ReportConfig {
+    /// Directory with `secret_keys` file of the `tezos-client`, the archive of each delegate is here
+    pub base_dir: PathBuf,
+    pub bakers: Vec<String>,
+    pub endpoint: Url,
+    /// The cycle of the head if not set
+    pub cycle: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Report {
+    pub cycle: i32,
+    pub first_level: i32,
+    /// The last level of the cycle, or the level of the head if the cycle is not over
+    pub last_level: i32,
+    pub delegates: Vec<DelegateReport>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DelegateReport {
+    pub baker: String,
+    pub delegate: ContractTz1Hash,
+    pub missed_bakes: Vec<MissedRight>,
+    pub missed_endorsements: Vec<MissedRight>,
+}
+
+/// Lists the missed bakes and endorsements of each delegate in the cycle, with the cause.
+pub fn report(config: ReportConfig) -> Result<Report, ReportError> {
+    let ReportConfig {
+        base_dir,
+        bakers,
+        endpoint,
+        cycle,
+    } = config;
+
+    let log = logger::main_logger();
+    let delegates = bakers
+        .into_iter()
+        .map(|baker| {
+            let crypto = CryptoService::read_key(&log, &base_dir, &baker)?;
+            Ok((baker, crypto.public_key_hash().clone()))
+        })
+        .collect::<Result<_, ReadKeyError>>()?;
+    // the client does not monitor anything, so the events are dropped
+    let (tx, _) = mpsc::channel();
+    let reporter = Reporter::new(RpcClient::new(endpoint, 0, tx), base_dir, delegates);
+    reporter.report(cycle)
+}
+
+/// Number of levels after which a block is final
+const FINALITY: i32 = 2;
+/// Number of cycles whose blocks are kept by the reporter
+const CACHED_CYCLES: usize = 8;
+
+/// Joins the archive of the delegates with the blocks on chain
+#[derive(Clone)]
+pub(crate) struct Reporter {
+    pub client: RpcClient,
+    pub base_dir: PathBuf,
+    pub delegates: Vec<(String, ContractTz1Hash)>,
+    /// Blocks of the cycles whose blocks are all final, shared by the clones
+    chains: Arc<Mutex<BTreeMap<i32, Arc<Vec<LevelOnChain>>>>>,
+}
+
+impl Reporter {
+    pub fn new(
+        client: RpcClient,
+        base_dir: PathBuf,
+        delegates: Vec<(String, ContractTz1Hash)>,
+    ) -> Self {
+        Reporter {
+            client,
+            base_dir,
+            delegates,
+            chains: Arc::default(),
+        }
+    }
+
+    pub fn report(&self, cycle: Option<i32>) -> Result<Report, ReportError> {
+        let (head_level, head_cycle) = self.client.get_head_level_info()?;
+        let cycle = cycle.unwrap_or(head_cycle);
+        let (first_level, last_level) = self.client.get_levels_in_cycle(cycle - head_cycle)?;
+        let last_level = last_level.min(head_level);
+
+        let chain = self.cycle_chain(cycle, first_level, last_level, head_level)?;
+        let chain_id = self.client.get_chain_id()?;
+        let delegates = self
+            .delegates
+            .iter()
+            .map(|(baker, delegate)| {
+                let dir = archive_path(&self.base_dir, baker, &chain_id);
+                let archive = read_archive(&dir, first_level, last_level)?;
+                Ok(DelegateReport {
+                    baker: baker.clone(),
+                    delegate: delegate.clone(),
+                    missed_bakes: chain
+                        .iter()
+                        .flat_map(|level| level.missed_bakes(delegate, &archive))
+                        .collect(),
+                    missed_endorsements: chain
+                        .iter()
+                        .filter_map(|level| level.missed_endorsement(delegate, &archive))
+                        .collect(),
+                })
+            })
+            .collect::<Result<_, ReportError>>()?;
+
+        Ok(Report {
+            cycle,
+            first_level,
+            last_level,
+            delegates,
+        })
+    }
+
+    /// Blocks of the `cycle` on chain, they are fetched once the cycle is final
+    fn cycle_chain(
+        &self,
+        cycle: i32,
+        first_level: i32,
+        last_level: i32,
+        head_level: i32,
+    ) -> Result<Arc<Vec<LevelOnChain>>, ReportError> {
+        let cached = match self.chains.lock() {
+            Ok(chains) => chains.get(&cycle).cloned(),
+            Err(_) => None,
+        };
+        if let Some(chain) = cached {
+            return Ok(chain);
+        }
+
+        let chain = Arc::new(self.chain(first_level, last_level, head_level)?);
+        // the endorsements of the last level are in the next block, it must be final too
+        if last_level + 1 + FINALITY <= head_level {
+            if let Ok(mut chains) = self.chains.lock() {
+                chains.insert(cycle, chain.clone());
+                while chains.len() > CACHED_CYCLES {
+                    let oldest = *chains.keys().next().unwrap(); // never fail, not empty
+                    chains.remove(&oldest);
+                }
+            }
+        }
+        Ok(chain)
+    }
+
+    fn chain(
+        &self,
+        first_level: i32,
+        last_level: i32,
+        head_level: i32,
+    ) -> Result<Vec<LevelOnChain>, ReportError> {
+        let mut chain = vec![];
+        let mut block = self.client.get_block_at_level(first_level)?;
+        for level in first_level..=last_level {
+            let (_, round) = block;
+            // the endorsements of the level are included in the next block
+            let next = if level < head_level {
+                Some(self.client.get_block_at_level(level + 1)?)
+            } else {
+                None
+            };
+            let endorsed_slots = match &next {
+                Some((hash, _)) => {
+                    let operations = self.client.get_operations_for_block(hash)?;
+                    let slots = operations
+                        .first()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|op| match op.kind()? {
+                            OperationKind::Endorsement(c) if c.level == level => Some(c.slot),
+                            _ => None,
+                        })
+                        .collect::<BTreeSet<_>>();
+                    Some(slots)
+                }
+                None => None,
+            };
+            chain.push(LevelOnChain {
+                level,
+                round,
+                rights: self.client.validators(level)?,
+                endorsed_slots,
+            });
+            match next {
+                Some(next) => block = next,
+                None => break,
+            }
+        }
+        Ok(chain)
+    }
+}
+
+/// Reads the actions archived around the levels, the archive may be missing
+fn read_archive(dir: &Path, first_level: i32, last_level: i32) -> Result<ArchiveLog, ReportError> {
+    let mut archive = ArchiveLog::default();
+    let entries = match fs::read_dir(dir) {
+        Ok(v) => v,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(archive),
+        Err(err) => return Err(err.into()),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        // the file `_{level}_{round}_actions.json` has the actions since the previous head,
+        // the block of the next level is proposed while the head is at the level
+        let level = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix('_')?.strip_suffix("_actions.json"))
+            .and_then(|name| name.split('_').next()?.parse::<i32>().ok());
+        match level {
+            Some(level) if (first_level - 1..=last_level + 1).contains(&level) => (),
+            _ => continue,
+        }
+        let records = serde_json::from_reader::<_, Vec<ActionWithInternal>>(File::open(&path)?)
+            .map_err(|error| ReportError::De {
+                path: path.clone(),
+                error,
+            })?;
+        for record in &records {
+            archive.add(record);
+        }
+    }
+    Ok(archive)
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "cycle {}, levels {}..={}",
+            self.cycle, self.first_level, self.last_level
+        )?;
+        for delegate in &self.delegates {
+            writeln!(
+                f,
+                "{} {}: {} missed bakes, {} missed endorsements",
+                delegate.baker,
+                delegate.delegate,
+                delegate.missed_bakes.len(),
+                delegate.missed_endorsements.len(),
+            )?;
+            for MissedRight {
+                level,
+                round,
+                cause,
+            } in &delegate.missed_bakes
+            {
+                writeln!(f, "    bake at {level}:{round}, {cause}")?;
+            }
+            for MissedRight {
+                level,
+                round,
+                cause,
+            } in &delegate.missed_endorsements
+            {
+                writeln!(f, "    endorsement at {level}:{round}, {cause}")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -7,7 +7,7 @@ use std::{
     io, mem,
     num::ParseIntError,
     str::{self, FromStr},
-    sync::mpsc,
+    sync::{mpsc, Arc, Mutex},
     thread,
     time::Duration,
 };
@@ -26,12 +26,17 @@ use crypto::hash::{
     OperationListListHash, ProtocolHash, Signature,
 };
 use tezos_encoding::{binary_reader::BinaryReaderError, types::SizedBytes};
-use tezos_encoding::{enc::BinWriter, encoding::HasEncoding, nom::NomReader};
+use tezos_encoding::{
+    enc::{BinError, BinWriter},
+    encoding::HasEncoding,
+    nom::NomReader,
+};
 use tezos_messages::{
     p2p::{
         binary_message::BinaryRead,
         encoding::{fitness::Fitness, operation::DecodedOperation},
     },
+    protocol::proto_012::operation_selection,
     Timestamp,
 };
 
@@ -47,6 +52,9 @@ pub struct RpcClient {
     tx: mpsc::Sender<(u8, BakerAction)>,
     endpoint: Url,
     inner: Client,
+    /// Head for which the mempool is monitored, shared by the clones of the client,
+    /// so the delegates of one baker monitor the mempool once per head
+    mempool_head: Arc<Mutex<Option<BlockHash>>>,
 }
 
 #[derive(Debug, Error)]
@@ -95,9 +103,12 @@ pub enum RpcErrorInner {
     NodeError(String, StatusCode),
     #[error("invalid fitness")]
     InvalidFitness,
+    #[error("bin: {_0}")]
+    Bin(BinError),
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fuzzing", derive(fuzzcheck::DefaultMutator))]
 pub enum Protocol {
     Ithaca,
     Jakarta,
@@ -121,6 +132,35 @@ impl FromStr for Protocol {
     }
 }
 
+impl Protocol {
+    /// Returns the protocol with the given base58 hash, if the baker supports it.
+    pub fn from_hash(hash: &str) -> Option<Self> {
+        if hash == ProtocolBlockHeaderI::NAME {
+            Some(Protocol::Ithaca)
+        } else if hash == ProtocolBlockHeaderJ::NAME {
+            Some(Protocol::Jakarta)
+        } else {
+            None
+        }
+    }
+
+    /// Payload hash and payload round from the binary protocol data of a block of this protocol.
+    fn decode_header(self, bytes: &[u8]) -> Result<(BlockPayloadHash, i32), BinaryReaderError> {
+        fn decode<H>(bytes: &[u8]) -> Result<(BlockPayloadHash, i32), BinaryReaderError>
+        where
+            H: ProtocolHeader + BinaryRead,
+        {
+            let header = H::from_bytes(bytes)?;
+            Ok((header.payload_hash().clone(), header.payload_round()))
+        }
+
+        match self {
+            Protocol::Ithaca => decode::<ProtocolBlockHeaderI>(bytes),
+            Protocol::Jakarta => decode::<ProtocolBlockHeaderJ>(bytes),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct ShellBlockShortHeader {
     level: i32,
@@ -133,6 +173,45 @@ pub struct ShellBlockShortHeader {
     context: ContextHash,
 }
 
+impl ShellBlockShortHeader {
+    /// Binary encoding of the shell header, the signed header is followed by the protocol data
+    fn to_bytes(self) -> Result<Vec<u8>, RpcErrorInner> {
+        #[derive(BinWriter, HasEncoding)]
+        struct ShellHeader {
+            #[encoding(builtin = "Int32")]
+            level: i32,
+            proto: u8,
+            predecessor: BlockHash,
+            timestamp: Timestamp,
+            validation_pass: u8,
+            operations_hash: OperationListListHash,
+            fitness: Fitness,
+            context: ContextHash,
+        }
+
+        let mut fitness = vec![];
+        for fitness_str in self.fitness {
+            fitness.push(hex::decode(fitness_str)?);
+        }
+        let header = ShellHeader {
+            level: self.level,
+            proto: self.proto,
+            predecessor: self.predecessor,
+            timestamp: OffsetDateTime::parse(&self.timestamp, &Rfc3339)?
+                .unix_timestamp()
+                .into(),
+            validation_pass: self.validation_pass,
+            operations_hash: self.operations_hash,
+            fitness: fitness.into(),
+            context: self.context,
+        };
+
+        let mut bytes = vec![];
+        header.bin_write(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
 pub trait ProtocolHeaderFull
 where
     Self: BinWriter + std::fmt::Debug,
@@ -482,7 +561,8 @@ impl ProtocolHeader for ProtocolBlockHeaderJ {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "fuzzing", derive(fuzzcheck::DefaultMutator))]
 pub struct Constants {
     pub nonce_length: usize,
     pub blocks_per_cycle: u32,
@@ -491,6 +571,12 @@ pub struct Constants {
     pub proof_of_work_threshold: u64,
     pub minimal_block_delay: Duration,
     pub delay_increment_per_round: Duration,
+    #[serde(default = "default_hard_gas_limit_per_block")]
+    pub hard_gas_limit_per_block: u64,
+}
+
+pub(crate) fn default_hard_gas_limit_per_block() -> u64 {
+    operation_selection::HARD_GAS_LIMIT_PER_BLOCK
 }
 
 impl RpcClient {
@@ -503,6 +589,7 @@ impl RpcClient {
                 .timeout(None)
                 .build()
                 .expect("client should created"),
+            mempool_head: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -533,6 +620,16 @@ impl RpcClient {
     }
 
     pub fn get_constants(&self) -> Result<Constants, RpcError> {
+        self.constants("head")
+    }
+
+    /// Constants in the context of the block, after a migration block these are
+    /// the constants of the new protocol
+    pub fn get_block_constants(&self, block_hash: &BlockHash) -> Result<Constants, RpcError> {
+        self.constants(&block_hash.to_base58_check())
+    }
+
+    fn constants(&self, block_id: &str) -> Result<Constants, RpcError> {
         #[derive(Deserialize, Debug)]
         struct ConstantsInner {
             nonce_length: usize,
@@ -542,12 +639,11 @@ impl RpcClient {
             minimal_block_delay: String,
             delay_increment_per_round: String,
             proof_of_work_threshold: String,
+            hard_gas_limit_per_block: String,
         }
 
-        let url = self
-            .endpoint
-            .join("chains/main/blocks/head/context/constants")
-            .expect("valid constant url");
+        let s = format!("chains/main/blocks/{block_id}/context/constants");
+        let url = self.endpoint.join(&s).expect("valid url");
         let ConstantsInner {
             nonce_length,
             blocks_per_cycle,
@@ -556,6 +652,7 @@ impl RpcClient {
             minimal_block_delay,
             delay_increment_per_round,
             proof_of_work_threshold,
+            hard_gas_limit_per_block,
         } = self.single_response_blocking::<ConstantsInner>(&url, None, None)?;
 
         Ok(Constants {
@@ -591,6 +688,13 @@ impl RpcClient {
                         inner,
                     })?,
             ),
+            hard_gas_limit_per_block: hard_gas_limit_per_block
+                .parse()
+                .map_err(|err| RpcErrorInner::IntParse(err, "hard gas limit".to_string()))
+                .map_err(|inner| RpcError::WithContext {
+                    url: url.clone(),
+                    inner,
+                })?,
         })
     }
 
@@ -616,96 +720,71 @@ impl RpcClient {
         Ok(validators)
     }
 
-    pub fn monitor_heads<H>(&self, chain_id: &ChainId) -> Result<(), RpcError>
-    where
-        H: ProtocolHeader + BinaryRead,
-    {
+    /// Monitor the heads of any protocol, the protocol of each head is taken
+    /// from the node, so the baker follows protocol migrations.
+    pub fn monitor_heads(&self, chain_id: &ChainId) -> Result<(), RpcError> {
         let s = format!("monitor/heads/{chain_id}");
-        let mut url = self.endpoint.join(&s).expect("valid constant url");
-        url.query_pairs_mut().append_pair("next_protocol", H::NAME);
-
-        #[allow(dead_code)]
-        #[derive(Deserialize)]
-        struct BlockHeaderJsonGeneric {
-            hash: BlockHash,
-            level: i32,
-            proto: u8,
-            predecessor: BlockHash,
-            timestamp: String,
-            validation_pass: u8,
-            operations_hash: OperationListListHash,
-            fitness: Vec<String>,
-            context: ContextHash,
-            protocol_data: String,
-        }
+        let url = self.endpoint.join(&s).expect("valid constant url");
 
         let this = self.clone();
         let moved_url = url.clone();
-        self.multiple_responses::<BlockHeaderJsonGeneric, _>(&url, None, move |header| {
+        self.multiple_responses::<MonitorHead, _>(&url, None, move |head| {
             let url = moved_url.clone();
-            let timestamp =
-                convert_timestamp(&header.timestamp).map_err(|inner| RpcError::WithContext {
-                    url: url.clone(),
-                    inner,
-                })?;
-
-            #[derive(Deserialize)]
-            struct Protocols {
-                protocol: ProtocolHash,
-            }
-
-            let s = format!("chains/main/blocks/{}/protocols", header.hash);
-            let url = this.endpoint.join(&s).expect("valid url");
-            let Protocols { protocol } =
-                this.single_response_blocking(&url, None, Some(Duration::from_secs(30)))?;
 
-            let transition = (protocol.to_base58_check() != ProtocolBlockHeaderI::NAME)
-                && (protocol.to_base58_check() != ProtocolBlockHeaderJ::NAME);
+            let s = format!("chains/main/blocks/{}/protocols", head.hash);
+            let protocols_url = this.endpoint.join(&s).expect("valid url");
+            let protocols: BlockProtocols =
+                this.single_response_blocking(&protocols_url, None, Some(Duration::from_secs(30)))?;
 
-            let (payload_hash, payload_round, round) = if !transition {
-                let protocol_data_bytes = hex::decode(header.protocol_data)
-                    .map_err(RpcErrorInner::Hex)
-                    .map_err(|inner| RpcError::WithContext {
-                        url: url.clone(),
-                        inner,
-                    })?;
-                let protocol_header = H::from_bytes(&protocol_data_bytes)
-                    .map_err(RpcErrorInner::Nom)
-                    .map_err(|inner| RpcError::WithContext {
-                        url: url.clone(),
-                        inner,
-                    })?;
-                let round =
-                    convert_fitness(&header.fitness).map_err(|inner| RpcError::WithContext {
-                        url: url.clone(),
-                        inner,
-                    })?;
-
-                (
-                    protocol_header.payload_hash().clone(),
-                    protocol_header.payload_round(),
-                    round,
-                )
+            // the migration block, the successor will be baked with the new constants
+            let constants = if protocols.is_migration() {
+                Some(this.get_block_constants(&head.hash)?)
             } else {
-                (BlockPayloadHash(vec![0x55; 32]), 0, 0)
+                None
             };
 
-            Ok(BakerAction::ProposalEvent(ProposalEventAction {
-                block: Block {
-                    hash: header.hash,
-                    level: header.level,
-                    predecessor: header.predecessor,
-                    timestamp,
-                    payload_hash,
-                    payload_round,
-                    round,
-                    transition,
-                },
-            }))
+            let event = head_event(head, protocols, constants)
+                .map_err(|inner| RpcError::WithContext { url, inner })?;
+            Ok(BakerAction::ProposalEvent(event))
         })
         .map_err(|inner| RpcError::WithContext { url, inner })
     }
 
+    /// Signed header of the block in binary form, as included in a double baking evidence
+    pub fn get_block_header_bytes(&self, block_hash: &BlockHash) -> Result<Vec<u8>, RpcError> {
+        let s = format!("chains/main/blocks/{block_hash}/header/shell");
+        let url = self.endpoint.join(&s).expect("valid url");
+        let shell_header: ShellBlockShortHeader =
+            self.single_response_blocking(&url, None, Some(Duration::from_secs(30)))?;
+        let mut bytes = shell_header
+            .to_bytes()
+            .map_err(|inner| RpcError::WithContext { url, inner })?;
+
+        let s = format!("chains/main/blocks/{block_hash}/header/protocol_data/raw");
+        let url = self.endpoint.join(&s).expect("valid url");
+        let protocol_data: String =
+            self.single_response_blocking(&url, None, Some(Duration::from_secs(30)))?;
+        let protocol_data = hex::decode(protocol_data)
+            .map_err(RpcErrorInner::Hex)
+            .map_err(|inner| RpcError::WithContext { url, inner })?;
+        bytes.extend_from_slice(&protocol_data);
+
+        Ok(bytes)
+    }
+
+    pub fn get_block_baker(&self, block_hash: &BlockHash) -> Result<ContractTz1Hash, RpcError> {
+        #[derive(Deserialize)]
+        struct BlockMetadata {
+            baker: ContractTz1Hash,
+        }
+
+        let s = format!("chains/main/blocks/{block_hash}/metadata");
+        let url = self.endpoint.join(&s).expect("valid url");
+        let BlockMetadata { baker } =
+            self.single_response_blocking(&url, None, Some(Duration::from_secs(30)))?;
+        Ok(baker)
+    }
+
     pub fn get_operations_for_block(
         &self,
         block_hash: &BlockHash,
@@ -715,6 +794,59 @@ impl RpcClient {
         self.single_response_blocking(&url, None, Some(Duration::from_secs(30)))
     }
 
+    /// Level and cycle of the head
+    pub fn get_head_level_info(&self) -> Result<(i32, i32), RpcError> {
+        #[derive(Deserialize)]
+        struct LevelInfo {
+            level: i32,
+            cycle: i32,
+        }
+
+        let url = self
+            .endpoint
+            .join("chains/main/blocks/head/helpers/current_level")
+            .expect("valid constant url");
+        let LevelInfo { level, cycle } =
+            self.single_response_blocking(&url, None, Some(Duration::from_secs(30)))?;
+        Ok((level, cycle))
+    }
+
+    /// First and last levels of the cycle which is `offset` cycles after the cycle of the head
+    pub fn get_levels_in_cycle(&self, offset: i32) -> Result<(i32, i32), RpcError> {
+        #[derive(Deserialize)]
+        struct Levels {
+            first: i32,
+            last: i32,
+        }
+
+        let mut url = self
+            .endpoint
+            .join("chains/main/blocks/head/helpers/levels_in_current_cycle")
+            .expect("valid constant url");
+        url.query_pairs_mut()
+            .append_pair("offset", &offset.to_string());
+        let Levels { first, last } =
+            self.single_response_blocking(&url, None, Some(Duration::from_secs(30)))?;
+        Ok((first, last))
+    }
+
+    /// Hash and round of the block at the level in the main chain
+    pub fn get_block_at_level(&self, level: i32) -> Result<(BlockHash, i32), RpcError> {
+        #[derive(Deserialize)]
+        struct BlockHeader {
+            hash: BlockHash,
+            fitness: Vec<String>,
+        }
+
+        let s = format!("chains/main/blocks/{level}/header");
+        let url = self.endpoint.join(&s).expect("valid url");
+        let BlockHeader { hash, fitness } =
+            self.single_response_blocking(&url, None, Some(Duration::from_secs(30)))?;
+        let round =
+            convert_fitness(&fitness).map_err(|inner| RpcError::WithContext { url, inner })?;
+        Ok((hash, round))
+    }
+
     pub fn get_live_blocks(&self, block_hash: &BlockHash) -> Result<Vec<BlockHash>, RpcError> {
         let s = format!("chains/main/blocks/{block_hash}/live_blocks");
         let url = self.endpoint.join(&s).expect("valid url");
@@ -722,15 +854,42 @@ impl RpcClient {
     }
 
     pub fn monitor_operations(&self, timeout: Duration) -> Result<(), RpcError> {
+        self.monitor_mempool(timeout, false)
+    }
+
+    /// Monitor the mempool after the `head` was applied, does nothing if some clone
+    /// of this client already monitors it for this head
+    pub fn monitor_operations_after(
+        &self,
+        head: &BlockHash,
+        timeout: Duration,
+    ) -> Result<(), RpcError> {
+        let mut mempool_head = self.mempool_head.lock().expect("poisoned");
+        if mempool_head.as_ref() == Some(head) {
+            return Ok(());
+        }
+        self.monitor_mempool(timeout, false)?;
+        *mempool_head = Some(head.clone());
+        Ok(())
+    }
+
+    /// Monitor the mempool operations whatever their classification, conflicting
+    /// consensus operations are refused by the mempool
+    pub fn monitor_all_operations(&self, timeout: Duration) -> Result<(), RpcError> {
+        self.monitor_mempool(timeout, true)
+    }
+
+    fn monitor_mempool(&self, timeout: Duration, all: bool) -> Result<(), RpcError> {
+        let all = if all { "yes" } else { "no" };
         let mut url = self
             .endpoint
             .join("chains/main/mempool/monitor_operations")
             .expect("valid constant url");
         url.query_pairs_mut()
             .append_pair("applied", "yes")
-            .append_pair("refused", "no")
-            .append_pair("outdated", "no")
-            .append_pair("branch_refused", "no")
+            .append_pair("refused", all)
+            .append_pair("outdated", all)
+            .append_pair("branch_refused", all)
             .append_pair("branch_delayed", "yes");
         self.multiple_responses(&url, Some(timeout), move |operations| {
             Ok(BakerAction::OperationsEvent(OperationsEventAction {
@@ -1002,6 +1161,78 @@ fn read_error(response: &mut impl io::Read, status: StatusCode) -> Result<(), Rp
     Err(RpcErrorInner::NodeError(err.to_string(), status))
 }
 
+/// Head as sent by `monitor/heads`
+#[allow(dead_code)]
+#[derive(Deserialize)]
+pub(crate) struct MonitorHead {
+    hash: BlockHash,
+    level: i32,
+    proto: u8,
+    predecessor: BlockHash,
+    timestamp: String,
+    validation_pass: u8,
+    operations_hash: OperationListListHash,
+    fitness: Vec<String>,
+    context: ContextHash,
+    protocol_data: String,
+}
+
+/// Protocol of a block and protocol of its successor
+#[derive(Deserialize)]
+pub(crate) struct BlockProtocols {
+    protocol: ProtocolHash,
+    next_protocol: ProtocolHash,
+}
+
+impl BlockProtocols {
+    /// The block is the last one of its protocol, and the baker supports the next one
+    pub(crate) fn is_migration(&self) -> bool {
+        self.protocol != self.next_protocol
+            && Protocol::from_hash(&self.next_protocol.to_base58_check()).is_some()
+    }
+}
+
+/// Proposal event of the head, the protocol data is decoded with the protocol of the block.
+///
+/// `constants` are the constants of the new protocol, given for the migration block.
+pub(crate) fn head_event(
+    head: MonitorHead,
+    protocols: BlockProtocols,
+    constants: Option<Constants>,
+) -> Result<ProposalEventAction, RpcErrorInner> {
+    let timestamp = convert_timestamp(&head.timestamp)?;
+
+    let this_protocol = Protocol::from_hash(&protocols.protocol.to_base58_check());
+    let transition = this_protocol.is_none();
+
+    let (payload_hash, payload_round, round) = if let Some(this_protocol) = this_protocol {
+        let protocol_data_bytes = hex::decode(head.protocol_data).map_err(RpcErrorInner::Hex)?;
+        let (payload_hash, payload_round) = this_protocol
+            .decode_header(&protocol_data_bytes)
+            .map_err(RpcErrorInner::Nom)?;
+        let round = convert_fitness(&head.fitness)?;
+
+        (payload_hash, payload_round, round)
+    } else {
+        (BlockPayloadHash(vec![0x55; 32]), 0, 0)
+    };
+
+    Ok(ProposalEventAction {
+        block: Block {
+            hash: head.hash,
+            level: head.level,
+            predecessor: head.predecessor,
+            timestamp,
+            payload_hash,
+            payload_round,
+            round,
+            transition,
+            next_protocol: Protocol::from_hash(&protocols.next_protocol.to_base58_check()),
+        },
+        constants,
+    })
+}
+
 fn convert_timestamp(v: &str) -> Result<u64, RpcErrorInner> {
     OffsetDateTime::parse(v, &Rfc3339)
         .map_err(Into::into)
@@ -6,7 +6,16 @@ use std::{fmt, str};
 use serde::{Deserialize, Serialize};
 
 use crypto::hash::{BlockHash, BlockPayloadHash, OperationHash, ProtocolHash, Signature};
-use tezos_messages::protocol::proto_012::operation::EndorsementOperation;
+use tezos_encoding::enc::BinWriter;
+use tezos_messages::{
+    base::signature_public_key::SignaturePublicKeyHash,
+    protocol::proto_012::{
+        operation::{Contents, EndorsementOperation},
+        operation_selection::{Candidate, ManagerFields, ManagerInfo},
+    },
+};
+
+use super::client::Protocol;
 
 #[cfg(feature = "fuzzing")]
 use super::operation_mutator::OperationContentMutator;
@@ -118,6 +127,55 @@ impl OperationSimple {
             _ => Some(OperationKind::Managers),
         }
     }
+
+    /// The operation along with its size and fee, to be selected into the block.
+    ///
+    /// The manager fields are read from the json of the contents, whatever the
+    /// parameters or the script of the operation.
+    pub fn into_candidate(self) -> Candidate<Self> {
+        // plus the branch and the signature
+        let size = 32 + contents_size(&self.contents) + 64;
+        let manager = self
+            .contents
+            .iter()
+            .map(manager_fields)
+            .collect::<Option<Vec<_>>>()
+            .and_then(ManagerInfo::from_batch);
+        Candidate::new(self, size, manager)
+    }
+}
+
+/// Size of the forged `contents`.
+///
+/// If one of them cannot be forged, the size of their json is used instead,
+/// the json is larger than the binary, so it is safe to overestimate.
+fn contents_size(contents: &[serde_json::Value]) -> usize {
+    let forged_size = contents.iter().try_fold(0, |size, content| {
+        let mut content = content.clone();
+        content.as_object_mut()?.remove("metadata");
+        let content = serde_json::from_value::<Contents>(content).ok()?;
+        let mut bytes = Vec::new();
+        content.bin_write(&mut bytes).ok()?;
+        Some(size + bytes.len())
+    });
+
+    forged_size.unwrap_or_else(|| serde_json::to_vec(contents).map_or(0, |json| json.len()))
+}
+
+/// Manager fields of the content, `None` if it is not a manager operation
+fn manager_fields(content: &serde_json::Value) -> Option<ManagerFields> {
+    let content = content.as_object()?;
+    let number = |name: &str| -> Option<u64> { content.get(name)?.as_str()?.parse().ok() };
+    // every manager operation has a storage limit
+    number("storage_limit")?;
+
+    let source = content.get("source")?.as_str()?;
+    Some(ManagerFields {
+        source: SignaturePublicKeyHash::from_b58_hash(source).ok()?,
+        fee: number("fee")?,
+        counter: number("counter")?,
+        gas_limit: number("gas_limit")?,
+    })
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -131,6 +189,9 @@ pub struct Block {
     pub payload_round: i32,
     pub round: i32,
     pub transition: bool,
+    /// Protocol of the successor, `None` if the baker does not support it
+    #[serde(default)]
+    pub next_protocol: Option<Protocol>,
 }
 
 /// Adapter for serialize and deserialize
@@ -164,3 +225,104 @@ impl<'de> Deserialize<'de> for Slots {
         Ok(slots)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crypto::hash::{BlockHash, BlockPayloadHash};
+    use tezos_messages::{
+        base::signature_public_key::SignaturePublicKeyHash,
+        protocol::proto_012::operation_selection::ManagerInfo,
+    };
+
+    use super::OperationSimple;
+
+    const ALICE: &str = "tz1VSUr8wwNhLAzempoch5d6hLRiTh8Cjcjb";
+    const BRANCH: &str = "BLZuzB7xVKBGEYtNZdeRBpsSHSbgv22pPiSvKxC6QM2bVUBnrQd";
+
+    fn manager_info(contents: &str) -> Option<ManagerInfo> {
+        let branch = BlockHash::from_base58_check(BRANCH).unwrap();
+        OperationSimple::new(&branch, contents)
+            .into_candidate()
+            .manager
+    }
+
+    #[test]
+    fn transaction_with_parameters() {
+        let info = manager_info(&format!(
+            r#"{{ "kind": "transaction", "source": "{ALICE}", "fee": "1420", "counter": "12", "gas_limit": "10600", "storage_limit": "300", "amount": "0", "destination": "KT1PWx2mnDueood7fEmfbBDKx1D9BAnnXitn", "parameters": {{ "entrypoint": "transfer", "value": {{ "prim": "Pair", "args": [ {{ "string": "{ALICE}" }}, {{ "int": "10" }} ] }} }} }}"#
+        ));
+        assert_eq!(
+            info,
+            Some(ManagerInfo {
+                source: SignaturePublicKeyHash::from_b58_hash(ALICE).unwrap(),
+                first_counter: 12,
+                last_counter: 12,
+                fee: 1420,
+                gas_limit: 10600,
+            })
+        );
+    }
+
+    #[test]
+    fn origination() {
+        let info = manager_info(&format!(
+            r#"{{ "kind": "origination", "source": "{ALICE}", "fee": "2000", "counter": "3", "gas_limit": "5000", "storage_limit": "1000", "balance": "0", "script": {{ "code": [ {{ "prim": "parameter", "args": [ {{ "prim": "unit" }} ] }}, {{ "prim": "storage", "args": [ {{ "prim": "unit" }} ] }}, {{ "prim": "code", "args": [ [ {{ "prim": "CDR" }}, {{ "prim": "NIL", "args": [ {{ "prim": "operation" }} ] }}, {{ "prim": "PAIR" }} ] ] }} ], "storage": {{ "prim": "Unit" }} }} }}"#
+        ));
+        assert_eq!(
+            info,
+            Some(ManagerInfo {
+                source: SignaturePublicKeyHash::from_b58_hash(ALICE).unwrap(),
+                first_counter: 3,
+                last_counter: 3,
+                fee: 2000,
+                gas_limit: 5000,
+            })
+        );
+    }
+
+    fn size(contents: &str) -> usize {
+        let branch = BlockHash::from_base58_check(BRANCH).unwrap();
+        OperationSimple::new(&branch, contents)
+            .into_candidate()
+            .size
+    }
+
+    #[test]
+    fn forged_size() {
+        // tag, slot, level, round, payload hash, plus the branch and the signature
+        let branch = BlockHash::from_base58_check(BRANCH).unwrap();
+        let payload_hash = BlockPayloadHash::from_base58_check(
+            "vh1pqgscdfGWixXcYJHPkbUDTHLFkZJWgmjdL75ijLGzTMMEi48S",
+        )
+        .unwrap();
+        let endorsement = OperationSimple::endorsement(&branch, &payload_hash, 53, 0, 1);
+        assert_eq!(endorsement.into_candidate().size, 1 + 2 + 4 + 4 + 32 + 96);
+
+        // tag, source, fee, counter, gas and storage limits, amount, destination,
+        // no parameters, plus the branch and the signature
+        let transaction = size(&format!(
+            r#"{{ "kind": "transaction", "source": "{ALICE}", "fee": "1420", "counter": "12", "gas_limit": "10600", "storage_limit": "300", "amount": "0", "destination": "{ALICE}", "metadata": {{}} }}"#
+        ));
+        assert_eq!(transaction, 1 + 21 + 2 + 1 + 2 + 2 + 1 + 22 + 1 + 96);
+    }
+
+    #[test]
+    fn json_size_when_not_forged() {
+        let contents =
+            r#"{ "kind": "unknown_operation", "source": "tz1VSUr8wwNhLAzempoch5d6hLRiTh8Cjcjb" }"#;
+        let json: serde_json::Value = serde_json::from_str(contents).unwrap();
+        let json_length = serde_json::to_vec(&vec![json]).unwrap().len();
+        assert_eq!(size(contents), 32 + json_length + 64);
+    }
+
+    #[test]
+    fn not_a_manager_operation() {
+        let branch = BlockHash::from_base58_check(BRANCH).unwrap();
+        let payload_hash = BlockPayloadHash::from_base58_check(
+            "vh1pqgscdfGWixXcYJHPkbUDTHLFkZJWgmjdL75ijLGzTMMEi48S",
+        )
+        .unwrap();
+        let preendorsement = OperationSimple::preendorsement(&branch, &payload_hash, 53, 0, 1);
+        assert_eq!(preendorsement.into_candidate().manager, None);
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+pub struct Response {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json(body: String) -> Self {
+        Response {
+            status: "200 OK",
+            content_type: "application/json",
+            body,
+        }
+    }
+
+    pub fn prometheus(body: String) -> Self {
+        Response {
+            status: "200 OK",
+            content_type: "text/plain; version=0.0.4",
+            body,
+        }
+    }
+
+    pub fn error(message: String) -> Self {
+        Response {
+            status: "500 Internal Server Error",
+            content_type: "text/plain",
+            body: message,
+        }
+    }
+
+    fn empty(status: &'static str) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            body: "null".to_owned(),
+        }
+    }
+}
+
+/// Number of connections served at once
+const WORKERS: usize = 4;
+/// Number of accepted connections waiting for a worker, further connections are closed
+const PENDING_CONNECTIONS: usize = 16;
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Maximal length of the request line and the headers
+const MAX_REQUEST_HEAD_LENGTH: u64 = 8 * 1024;
+
+/// Serves `GET` requests on `addr` in background threads, the connections are served
+/// by `WORKERS` threads, each with a clone of the `handler`, so a slow request does not
+/// hold the others.
+///
+/// The `handler` takes the path with the query, and returns `None` if nothing is there.
+pub fn spawn<F>(addr: SocketAddr, log: slog::Logger, handler: F) -> io::Result<()>
+where
+    F: Fn(&str) -> Option<Response> + Clone + Send + 'static,
+{
+    let listener = TcpListener::bind(addr)?;
+    slog::info!(log, "serving http on {addr}");
+
+    let (tx, rx) = mpsc::sync_channel::<TcpStream>(PENDING_CONNECTIONS);
+    let rx = Arc::new(Mutex::new(rx));
+    for _ in 0..WORKERS {
+        let rx = rx.clone();
+        let handler = handler.clone();
+        let log = log.clone();
+        thread::spawn(move || loop {
+            let stream = match rx.lock().map(|rx| rx.recv()) {
+                Ok(Ok(stream)) => stream,
+                _ => break,
+            };
+            if let Err(err) = serve(stream, &handler) {
+                slog::warn!(log, " .  http: {err}");
+            }
+        });
+    }
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    slog::warn!(log, " .  http: {err}");
+                    continue;
+                }
+            };
+            if let Err(TrySendError::Full(_)) = tx.try_send(stream) {
+                slog::warn!(log, " .  http: too many connections, closing one");
+            }
+        }
+    });
+    Ok(())
+}
+
+fn serve<F>(mut stream: TcpStream, handler: &F) -> io::Result<()>
+where
+    F: Fn(&str) -> Option<Response>,
+{
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut request_line = String::new();
+    let mut reader = BufReader::new((&stream).take(MAX_REQUEST_HEAD_LENGTH));
+    reader.read_line(&mut request_line)?;
+    let complete = request_line.ends_with('\n') && skip_headers(&mut reader)?;
+
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        _ if !complete => Response::empty("431 Request Header Fields Too Large"),
+        (Some("GET"), Some(path)) => {
+            handler(path).unwrap_or_else(|| Response::empty("404 Not Found"))
+        }
+        _ => Response::empty("405 Method Not Allowed"),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body,
+    )?;
+    stream.flush()
+}
+
+/// Skips the headers, the body is not needed.
+///
+/// Returns `false` if the end of the headers is not found before the end of `reader`.
+fn skip_headers<R: BufRead>(reader: &mut R) -> io::Result<bool> {
+    let mut header = String::new();
+    loop {
+        header.clear();
+        reader.read_line(&mut header)?;
+        if !header.ends_with('\n') {
+            return Ok(false);
+        }
+        if header.len() <= 2 {
+            return Ok(true);
+        }
+    }
+}
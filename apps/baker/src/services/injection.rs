@@ -0,0 +1,44 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InjectionKind {
+    Block,
+    Preendorsement,
+    Endorsement,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InjectionOutcome {
+    /// The node accepted it, the hash of the block or of the operation
+    Injected(String),
+    /// The node refused to preapply the block
+    PreapplyFailed(String),
+    /// Refused to sign, or the node refused to inject
+    Failed(String),
+}
+
+/// Outcome of an attempt to inject a block or a consensus operation, it is
+/// stored in the archive along with the actions
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Injection {
+    pub kind: InjectionKind,
+    pub level: i32,
+    pub round: i32,
+    pub outcome: InjectionOutcome,
+}
+
+impl Injection {
+    pub fn new(kind: InjectionKind, level: i32, round: i32, outcome: InjectionOutcome) -> Self {
+        Injection {
+            kind,
+            level,
+            round,
+            outcome,
+        }
+    }
+}
@@ -0,0 +1,251 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crypto::hash::ContractTz1Hash;
+use tenderbake::ProposerMap;
+
+use crate::machine::SlotsInfo;
+
+use super::{
+    event::{Block, Slots},
+    injection::{Injection, InjectionKind, InjectionOutcome},
+};
+
+/// Counters of one delegate, shared with the metrics server
+#[derive(Default)]
+pub struct DelegateMetrics {
+    blocks_injected: AtomicU64,
+    preendorsements_injected: AtomicU64,
+    endorsements_injected: AtomicU64,
+    injection_errors: AtomicU64,
+    blocks_produced: AtomicU64,
+    blocks_missed: AtomicU64,
+    endorsements_missed: AtomicU64,
+    last_endorsed_level: AtomicI32,
+    head: Mutex<HeadTracker>,
+}
+
+#[derive(Default)]
+struct HeadTracker {
+    level: i32,
+    round: i32,
+    /// The levels up to this one are accounted
+    accounted: i32,
+}
+
+impl DelegateMetrics {
+    pub fn observe_injection(&self, injection: &Injection) {
+        let counter = match (&injection.outcome, injection.kind) {
+            (InjectionOutcome::Injected(_), InjectionKind::Block) => &self.blocks_injected,
+            (InjectionOutcome::Injected(_), InjectionKind::Preendorsement) => {
+                &self.preendorsements_injected
+            }
+            (InjectionOutcome::Injected(_), InjectionKind::Endorsement) => {
+                self.last_endorsed_level
+                    .fetch_max(injection.level, Ordering::Relaxed);
+                &self.endorsements_injected
+            }
+            (InjectionOutcome::PreapplyFailed(_) | InjectionOutcome::Failed(_), _) => {
+                &self.injection_errors
+            }
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Accounts the rights of the delegate at the level of the previous head,
+    /// once the head moves to the next level. The last head of the level is
+    /// the block which was baked on it.
+    pub fn observe_head(&self, map: &SlotsInfo, block: &Block) {
+        let mut head = self.head.lock().expect("poisoned");
+        if block.level > head.level && head.level > head.accounted {
+            let level = head.level;
+            match map.proposer(level, 0) {
+                Some((round, _)) if round == head.round => {
+                    self.blocks_produced.fetch_add(1, Ordering::Relaxed);
+                }
+                Some((round, _)) if round < head.round => {
+                    self.blocks_missed.fetch_add(1, Ordering::Relaxed);
+                }
+                _ => (),
+            }
+            let endorsing = map.delegates.get(&level).map_or(false, |d| {
+                map.ours
+                    .iter()
+                    .any(|our| matches!(d.get(our), Some(Slots(slots)) if !slots.is_empty()))
+            });
+            if endorsing && self.last_endorsed_level.load(Ordering::Relaxed) < level {
+                self.endorsements_missed.fetch_add(1, Ordering::Relaxed);
+            }
+            head.accounted = level;
+        }
+        head.level = block.level;
+        head.round = block.round;
+    }
+}
+
+/// Metrics of every delegate of the process, in the Prometheus text exposition format
+pub struct Metrics {
+    delegates: Vec<(String, ContractTz1Hash, Arc<DelegateMetrics>)>,
+}
+
+impl Metrics {
+    pub fn new(delegates: Vec<(String, ContractTz1Hash, Arc<DelegateMetrics>)>) -> Self {
+        Metrics { delegates }
+    }
+
+    pub fn render(&self) -> String {
+        type Counter = fn(&DelegateMetrics) -> &AtomicU64;
+        let counters: [(&str, &str, Counter); 7] = [
+            (
+                "tezedge_baker_blocks_injected_total",
+                "Blocks injected by the delegate",
+                |m| &m.blocks_injected,
+            ),
+            (
+                "tezedge_baker_preendorsements_injected_total",
+                "Preendorsements injected by the delegate",
+                |m| &m.preendorsements_injected,
+            ),
+            (
+                "tezedge_baker_endorsements_injected_total",
+                "Endorsements injected by the delegate",
+                |m| &m.endorsements_injected,
+            ),
+            (
+                "tezedge_baker_injection_errors_total",
+                "Blocks and operations the node refused to inject",
+                |m| &m.injection_errors,
+            ),
+            (
+                "tezedge_baker_blocks_produced_total",
+                "Blocks of the delegate which became the head of their level",
+                |m| &m.blocks_produced,
+            ),
+            (
+                "tezedge_baker_blocks_missed_total",
+                "Levels where the head was baked at a later round than the delegate's one",
+                |m| &m.blocks_missed,
+            ),
+            (
+                "tezedge_baker_endorsements_missed_total",
+                "Levels where the delegate had endorsing slots and injected no endorsement",
+                |m| &m.endorsements_missed,
+            ),
+        ];
+
+        let mut out = String::new();
+        for (name, help, counter) in counters {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            for (alias, pkh, metrics) in &self.delegates {
+                let value = counter(metrics).load(Ordering::Relaxed);
+                let _ = writeln!(
+                    out,
+                    "{name}{{baker=\"{alias}\",delegate=\"{pkh}\"}} {value}"
+                );
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, sync::Arc};
+
+    use crypto::hash::{BlockHash, BlockPayloadHash, ContractTz1Hash};
+
+    use super::{DelegateMetrics, Metrics};
+    use crate::{
+        machine::SlotsInfo,
+        services::{
+            event::{Block, Slots},
+            injection::{Injection, InjectionKind, InjectionOutcome},
+        },
+    };
+
+    fn block(level: i32, round: i32) -> Block {
+        Block {
+            hash: BlockHash::from_base58_check(
+                "BLockGenesisGenesisGenesisGenesisGenesisb83baZgbyZe",
+            )
+            .unwrap(),
+            level,
+            predecessor: BlockHash::from_base58_check(
+                "BLockGenesisGenesisGenesisGenesisGenesisb83baZgbyZe",
+            )
+            .unwrap(),
+            timestamp: 0,
+            payload_hash: BlockPayloadHash::from_base58_check(
+                "vh3Ed4mvDcNYVtskGLCYKKk1aBxJTpQNc46Hyi4EedpGCmgZ4LiG",
+            )
+            .unwrap(),
+            payload_round: 0,
+            round,
+            transition: false,
+            next_protocol: None,
+        }
+    }
+
+    #[test]
+    fn account_rights_of_previous_level() {
+        let this =
+            ContractTz1Hash::from_base58_check("tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx").unwrap();
+        let other =
+            ContractTz1Hash::from_base58_check("tz1Ke2h7sDdakHJQh8WX4Z372du1KChsksyU").unwrap();
+        let slots = |ours: &[u16]| {
+            BTreeMap::from([
+                (this.clone(), Slots(ours.to_vec())),
+                (other.clone(), Slots(vec![0])),
+            ])
+        };
+        let map = SlotsInfo {
+            committee_size: 7000,
+            ours: vec![this.clone()],
+            level: 0,
+            delegates: BTreeMap::from([(10, slots(&[1])), (11, slots(&[1])), (12, slots(&[]))]),
+        };
+
+        let metrics = Arc::new(DelegateMetrics::default());
+        // level 10 is baked at our round, and endorsed
+        metrics.observe_head(&map, &block(10, 0));
+        metrics.observe_head(&map, &block(10, 1));
+        metrics.observe_injection(&Injection::new(
+            InjectionKind::Endorsement,
+            10,
+            1,
+            InjectionOutcome::Injected(
+                "oo5qFAHchTG9rpNDbRBmSaJbSsiCqHBtfmKwk9Atiavhih9hYbC".to_owned(),
+            ),
+        ));
+        // level 11 is baked at a later round than ours, and not endorsed
+        metrics.observe_head(&map, &block(11, 2));
+        metrics.observe_head(&map, &block(12, 0));
+        // a reorganization does not account the levels twice
+        metrics.observe_head(&map, &block(11, 3));
+        metrics.observe_head(&map, &block(12, 1));
+        metrics.observe_head(&map, &block(13, 0));
+
+        let text = Metrics::new(vec![("alice".to_owned(), this.clone(), metrics)]).render();
+        let value = |name: &str| {
+            let prefix = format!("{name}{{baker=\"alice\",delegate=\"{this}\"}} ");
+            text.lines()
+                .find_map(|line| line.strip_prefix(&prefix))
+                .unwrap()
+                .to_owned()
+        };
+        assert_eq!(value("tezedge_baker_blocks_produced_total"), "1");
+        assert_eq!(value("tezedge_baker_blocks_missed_total"), "1");
+        assert_eq!(value("tezedge_baker_endorsements_injected_total"), "1");
+        assert_eq!(value("tezedge_baker_endorsements_missed_total"), "1");
+        assert!(text.contains("# TYPE tezedge_baker_blocks_injected_total counter\n"));
+    }
+}
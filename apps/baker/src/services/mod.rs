@@ -3,19 +3,28 @@
 
 pub mod client;
 pub mod event;
+pub mod http_server;
+pub mod injection;
 pub mod key;
 pub mod logger;
+pub mod metrics;
 pub mod timer;
 
 #[cfg(feature = "fuzzing")]
 mod operation_mutator;
 
-use std::{fs::File, path::Path, sync::mpsc, time::SystemTime};
+use std::{
+    fs::File,
+    path::Path,
+    sync::{mpsc, Arc},
+    time::{Instant, SystemTime},
+};
 
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 
 use redux_rs::TimeService;
+use sandbox_clock::SandboxClock;
 use tenderbake as tb;
 
 use crate::machine::BakerAction;
@@ -25,6 +34,11 @@ pub struct Services {
     pub crypto: key::CryptoService,
     pub log: slog::Logger,
     pub timer: timer::Timer,
+    /// Time source of the sandbox, the system time is used if not set
+    pub clock: Option<SandboxClock>,
+    pub metrics: Arc<metrics::DelegateMetrics>,
+    /// Outcomes of the injections since they were last taken for the archive
+    pub injections: Vec<injection::Injection>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -33,22 +47,55 @@ pub struct EventWithTime {
     pub now: tenderbake::Timestamp,
 }
 
+/// Id of the events coming from the client, those events are for every delegate
+pub const SHARED_EVENT_ID: u8 = u8::MAX;
+
 impl Services {
+    /// Services of each delegate in `bakers`, they share one client, so the heads
+    /// and the mempool are monitored once for all of them. Events are tagged with
+    /// the index of the delegate, or with `SHARED_EVENT_ID`.
     pub fn new(
         endpoint: Url,
         base_dir: &Path,
-        baker: &str,
-    ) -> (Self, impl Iterator<Item = EventWithTime>) {
+        bakers: &[String],
+        clock: Option<SandboxClock>,
+    ) -> (Vec<Self>, impl Iterator<Item = (u8, EventWithTime)>) {
+        assert!(
+            bakers.len() < usize::from(SHARED_EVENT_ID),
+            "too many delegates"
+        );
         let (tx, rx) = mpsc::channel();
 
+        let client = client::RpcClient::new(endpoint, SHARED_EVENT_ID, tx.clone());
+        let log = logger::main_logger();
+        let services = bakers
+            .iter()
+            .enumerate()
+            .map(|(id, baker)| {
+                Self::with_client(
+                    client.clone(),
+                    base_dir,
+                    baker,
+                    log.new(slog::o!("baker" => baker.clone())),
+                    id as u8,
+                    tx.clone(),
+                    clock.clone(),
+                )
+            })
+            .collect();
+
+        let events_clock = clock;
         (
-            Self::new_with_id_and_log(endpoint, base_dir, baker, None, 0, tx),
-            rx.into_iter().map(|(_, event)| {
-                let unix_epoch = SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap();
+            services,
+            rx.into_iter().map(move |(id, event)| {
+                let unix_epoch = match &events_clock {
+                    Some(clock) => clock.since_epoch(),
+                    None => SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap(),
+                };
                 let now = tb::Timestamp { unix_epoch };
-                EventWithTime { now, action: event }
+                (id, EventWithTime { now, action: event })
             }),
         )
     }
@@ -60,16 +107,33 @@ impl Services {
         log: Option<File>,
         id: u8,
         tx: mpsc::Sender<(u8, BakerAction)>,
+        clock: Option<SandboxClock>,
     ) -> Self {
         let log = match log {
             Some(f) => logger::file_logger(f),
             None => logger::main_logger(),
         };
+        let client = client::RpcClient::new(endpoint, id, tx.clone());
+        Self::with_client(client, base_dir, baker, log, id, tx, clock)
+    }
+
+    fn with_client(
+        client: client::RpcClient,
+        base_dir: &Path,
+        baker: &str,
+        log: slog::Logger,
+        id: u8,
+        tx: mpsc::Sender<(u8, BakerAction)>,
+        clock: Option<SandboxClock>,
+    ) -> Self {
         Services {
-            client: client::RpcClient::new(endpoint, id, tx.clone()),
+            client,
             crypto: key::CryptoService::read_key(&log, base_dir, baker).unwrap(),
             log,
-            timer: timer::Timer::spawn(id, tx),
+            timer: timer::Timer::spawn(id, tx, clock.clone()),
+            clock,
+            metrics: Arc::default(),
+            injections: vec![],
         }
     }
 }
@@ -82,6 +146,10 @@ pub trait BakerService {
     fn log(&self) -> &slog::Logger;
 
     fn timer(&self) -> &timer::Timer;
+
+    fn metrics(&self) -> &metrics::DelegateMetrics;
+
+    fn injected(&mut self, injection: injection::Injection);
 }
 
 impl BakerService for Services {
@@ -100,6 +168,22 @@ impl BakerService for Services {
     fn timer(&self) -> &timer::Timer {
         &self.timer
     }
+
+    fn metrics(&self) -> &metrics::DelegateMetrics {
+        &self.metrics
+    }
+
+    fn injected(&mut self, injection: injection::Injection) {
+        self.metrics().observe_injection(&injection);
+        self.injections.push(injection);
+    }
 }
 
-impl TimeService for Services {}
+impl TimeService for Services {
+    fn monotonic_time(&mut self) -> Instant {
+        match &self.clock {
+            Some(clock) => clock.monotonic_time(),
+            None => Instant::now(),
+        }
+    }
+}
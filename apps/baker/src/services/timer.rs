@@ -1,7 +1,13 @@
 // Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
 // SPDX-License-Identifier: MIT
 
-use std::{sync::mpsc, thread, time::SystemTime};
+use std::{
+    sync::mpsc,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use sandbox_clock::SandboxClock;
 
 use tenderbake as tb;
 
@@ -13,17 +19,48 @@ pub struct Timer {
 }
 
 impl Timer {
-    pub fn spawn(id: u8, event_sender: mpsc::Sender<(u8, BakerAction)>) -> Self {
+    /// The sandbox clock can be adjusted at any moment, so the timer checks it at least this often
+    const SANDBOX_CLOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    pub fn spawn(
+        id: u8,
+        event_sender: mpsc::Sender<(u8, BakerAction)>,
+        clock: Option<SandboxClock>,
+    ) -> Self {
         let (task_tx, task_rx) = mpsc::channel::<(tb::Timestamp, i32, i32)>();
         let handle = thread::spawn(move || {
-            let mut timeout_duration = None;
+            let clock_now = || match &clock {
+                Some(clock) => clock.since_epoch(),
+                None => SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .expect("the unix epoch has begun"),
+            };
+            // real time to wait, while `remaining` of the clock time elapses
+            let real_wait = |remaining: Duration| match &clock {
+                Some(clock) => clock
+                    .state()
+                    .real_duration(remaining)
+                    .map_or(Self::SANDBOX_CLOCK_POLL_INTERVAL, |d| {
+                        d.min(Self::SANDBOX_CLOCK_POLL_INTERVAL)
+                    }),
+                None => remaining,
+            };
+
+            let mut timeout_duration: Option<(Duration, tb::Timestamp)> = None;
             let mut scheduled_at_level = 0;
             let mut scheduled_at_round = 0;
             loop {
                 let (next, l, r) = match timeout_duration.take() {
-                    Some((duration, _)) => match task_rx.recv_timeout(duration) {
+                    Some((duration, target)) => match task_rx.recv_timeout(duration) {
                         Ok(next) => next,
                         Err(mpsc::RecvTimeoutError::Timeout) => {
+                            let now = clock_now();
+                            if target.unix_epoch > now {
+                                // the clock is slower than the real time, keep waiting
+                                timeout_duration =
+                                    Some((real_wait(target.unix_epoch - now), target));
+                                continue;
+                            }
                             let act = BakerAction::TickEvent(TickEventAction {
                                 scheduled_at_level,
                                 scheduled_at_round,
@@ -38,11 +75,9 @@ impl Timer {
                         Err(mpsc::RecvError) => break,
                     },
                 };
-                let now = SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .expect("the unix epoch has begun");
+                let now = clock_now();
                 if next.unix_epoch > now && l >= scheduled_at_level {
-                    timeout_duration = Some((next.unix_epoch - now, next));
+                    timeout_duration = Some((real_wait(next.unix_epoch - now), next));
                     scheduled_at_level = l;
                     scheduled_at_round = r;
                 } else if l >= scheduled_at_level {
@@ -54,7 +89,7 @@ impl Timer {
                 } else if next.unix_epoch > now {
                     // next timeout remains the same still the same
                     let next = timeout_duration.map(|(_, t)| t).unwrap_or(next);
-                    timeout_duration = Some((next.unix_epoch - now, next));
+                    timeout_duration = Some((real_wait(next.unix_epoch - now), next));
                 }
             }
         });
@@ -100,7 +135,7 @@ mod tests {
 
     fn new_timer_and_collector() -> (Timer, tb::Timestamp, thread::JoinHandle<Vec<BakerAction>>) {
         let (tx, rx) = mpsc::channel();
-        let timer = Timer::spawn(0, tx);
+        let timer = Timer::spawn(0, tx, None);
         let now = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .expect("the unix epoch has begun");
@@ -26,7 +26,7 @@ use tezos_messages::protocol::proto_012::operation::{
 use crate::{
     machine::{baker_effects, baker_reducer, Action, BakerAction, BakerState, BakerStateEjectable},
     services::event::Block,
-    LiquidityBakingToggleVote, Protocol, ProtocolBlockHeaderJ, RpcClient, Services,
+    LiquidityBakingToggleVote, RpcClient, Services,
 };
 
 #[derive(Debug, Error)]
@@ -231,7 +231,6 @@ where
     let client = RpcClient::new("http://localhost:18732".parse().unwrap(), 4, tx.clone());
     let chain_id = client.get_chain_id().unwrap();
     let constants = client.get_constants().unwrap();
-    let protocol = Protocol::Jakarta;
     let liquidity_baking_toggle_vote = LiquidityBakingToggleVote::Off;
     let mut bakers = vec![];
     for id in 0..4 {
@@ -244,17 +243,14 @@ where
             Some(log),
             id,
             tx.clone(),
+            None,
         );
-        service
-            .client
-            .monitor_heads::<ProtocolBlockHeaderJ>(&chain_id)
-            .unwrap();
+        service.client.monitor_heads(&chain_id).unwrap();
         let this = service.crypto.public_key_hash().clone();
         let state = BakerState::new(
             chain_id.clone(),
             constants.clone(),
             this,
-            protocol,
             liquidity_baking_toggle_vote,
         );
         let initial_state = BakerStateEjectable(Some(state));
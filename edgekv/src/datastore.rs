@@ -6,16 +6,19 @@
 use crate::datastore::DataIndex::Persisted;
 use crate::errors::EdgeKVError;
 use crate::file_ops::{
-    create_new_file_pair, fetch_file_pairs, get_lock_file, ActiveFilePair, FilePair, Index,
+    create_merged_file_pair, create_new_file_pair, fetch_file_pairs, get_lock_file, ActiveFilePair,
+    FilePair, Index,
 };
-use crate::schema::{DataEntry, Decoder, Encoder};
+use crate::schema::{DataEntry, Decoder, Encoder, DATA_ENTRY_HEADER_SIZE};
 use fs2::FileExt;
 
 use std::collections::{BTreeMap, HashMap};
 use std::fs::{File, OpenOptions};
 use std::ops::{Add, RangeBounds};
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, RwLock, RwLockReadGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
 
 use crate::Result;
 use std::io::{BufReader, Write};
@@ -58,66 +61,220 @@ impl KeyDirEntry {
             + std::mem::size_of_val(&self.data_entry_position)
             + self.file_id.len()
     }
+
+    /// Size of the encoded data entry in the data file
+    pub fn data_entry_size(&self) -> u64 {
+        DATA_ENTRY_HEADER_SIZE + self.key_size + self.value_size
+    }
+
+    fn is_same_entry(&self, other: &KeyDirEntry) -> bool {
+        self.file_id == other.file_id && self.data_entry_position == other.data_entry_position
+    }
+}
+
+/// Live and dead bytes of a data file
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileFragmentation {
+    /// Size of all the entries written to the data file
+    pub total_bytes: u64,
+    /// Size of the entries still referenced by the keys dir
+    pub live_bytes: u64,
+}
+
+impl FileFragmentation {
+    /// Size of the entries overwritten or deleted since they were written
+    pub fn dead_bytes(&self) -> u64 {
+        self.total_bytes.saturating_sub(self.live_bytes)
+    }
+
+    pub fn dead_ratio(&self) -> f64 {
+        if self.total_bytes == 0 {
+            return 0.0;
+        }
+        self.dead_bytes() as f64 / self.total_bytes as f64
+    }
+}
+
+/// Statistics of the merges of the data files
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MergeStats {
+    pub merges: u64,
+    pub merged_files: u64,
+    /// Disk space freed by the merges
+    pub reclaimed_bytes: u64,
+    pub total_merge_duration: Duration,
 }
 
 pub type IVec = Arc<Vec<u8>>;
 
 pub struct KeysDir {
-    keys: RwLock<BTreeMap<IVec, DataIndex>>,
+    /// Shared with the live snapshots. It is only copied by a write while a snapshot
+    /// still shares it, writes without live snapshots modify it in place.
+    keys: RwLock<Arc<BTreeMap<IVec, DataIndex>>>,
+    /// Live and dead bytes of each data file, always locked after `keys`
+    fragmentation: RwLock<BTreeMap<String, FileFragmentation>>,
+}
+
+fn add_live_bytes(fragmentation: &mut BTreeMap<String, FileFragmentation>, entry: &KeyDirEntry) {
+    fragmentation
+        .entry(entry.file_id.clone())
+        .or_default()
+        .live_bytes += entry.data_entry_size();
+}
+
+fn remove_live_bytes(
+    fragmentation: &mut BTreeMap<String, FileFragmentation>,
+    old_index: Option<DataIndex>,
+) {
+    if let Some(Persisted(entry)) = old_index {
+        if let Some(file) = fragmentation.get_mut(&entry.file_id) {
+            file.live_bytes = file.live_bytes.saturating_sub(entry.data_entry_size());
+        }
+    }
 }
 
 impl KeysDir {
+    fn fragmentation_writer(
+        &self,
+    ) -> Result<RwLockWriteGuard<BTreeMap<String, FileFragmentation>>> {
+        self.fragmentation
+            .write()
+            .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))
+    }
+
     pub fn insert(&self, key: IVec, value: KeyDirEntry) -> Result<()> {
-        let mut keys_dir_writer = self
+        let mut keys_dir_guard = self
             .keys
             .write()
             .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))?;
+        let keys_dir_writer = Arc::make_mut(&mut keys_dir_guard);
+        let mut fragmentation = self.fragmentation_writer()?;
 
+        add_live_bytes(&mut fragmentation, &value);
         let index = DataIndex::Persisted(value);
-        keys_dir_writer.insert(key, index);
+        remove_live_bytes(&mut fragmentation, keys_dir_writer.insert(key, index));
         Ok(())
     }
 
     pub fn insert_bulk(&self, bulk: BTreeMap<Vec<u8>, KeyDirEntry>) -> Result<()> {
-        let mut keys_dir_writer = self
+        let mut keys_dir_guard = self
+            .keys
+            .write()
+            .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))?;
+        let keys_dir_writer = Arc::make_mut(&mut keys_dir_guard);
+        let mut fragmentation = self.fragmentation_writer()?;
+
+        for (k, v) in bulk {
+            add_live_bytes(&mut fragmentation, &v);
+            let old_index = keys_dir_writer.insert(Arc::new(k), DataIndex::Persisted(v));
+            remove_live_bytes(&mut fragmentation, old_index);
+        }
+        Ok(())
+    }
+
+    /// Points the keys to the entries copied by a merge, unless they were overwritten
+    /// or deleted since the merge read them.
+    ///
+    /// Each element is the key, the entry read by the merge and the copied entry.
+    pub fn replace_merged(&self, merged: Vec<(IVec, KeyDirEntry, KeyDirEntry)>) -> Result<()> {
+        let mut keys_dir_guard = self
             .keys
             .write()
             .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))?;
-        keys_dir_writer.extend(
-            bulk.iter()
-                .map(|(k, v)| (Arc::new(k.clone()), DataIndex::Persisted(v.clone()))),
-        );
+        let keys_dir_writer = Arc::make_mut(&mut keys_dir_guard);
+        let mut fragmentation = self.fragmentation_writer()?;
+
+        for (key, old_entry, new_entry) in merged {
+            let index = match keys_dir_writer.get_mut(&key) {
+                Some(index) => index,
+                None => continue,
+            };
+            match index {
+                Persisted(entry) if entry.is_same_entry(&old_entry) => {
+                    add_live_bytes(&mut fragmentation, &new_entry);
+                    let old_index = std::mem::replace(index, Persisted(new_entry));
+                    remove_live_bytes(&mut fragmentation, Some(old_index));
+                }
+                _ => continue,
+            }
+        }
         Ok(())
     }
 
     pub fn partial_insert(&self, key: IVec) -> Result<()> {
-        let mut keys_dir_writer = self
+        let mut keys_dir_guard = self
             .keys
             .write()
             .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))?;
+        let keys_dir_writer = Arc::make_mut(&mut keys_dir_guard);
+        let mut fragmentation = self.fragmentation_writer()?;
+
         let index = DataIndex::InBuffer;
-        keys_dir_writer.insert(key, index);
+        remove_live_bytes(&mut fragmentation, keys_dir_writer.insert(key, index));
         Ok(())
     }
 
     pub fn remove(&self, key: &Vec<u8>) -> Result<()> {
-        let mut keys_dir_writer = self
+        let mut keys_dir_guard = self
             .keys
             .write()
             .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))?;
-        keys_dir_writer.remove(key);
+        let keys_dir_writer = Arc::make_mut(&mut keys_dir_guard);
+        let mut fragmentation = self.fragmentation_writer()?;
+
+        remove_live_bytes(&mut fragmentation, keys_dir_writer.remove(key));
         Ok(())
     }
 
     pub fn clear(&self) -> Result<()> {
-        let mut keys_dir_writer = self
+        let mut keys_dir_guard = self
             .keys
             .write()
             .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))?;
-        keys_dir_writer.clear();
+        let mut fragmentation = self.fragmentation_writer()?;
+
+        *keys_dir_guard = Default::default();
+        for file in fragmentation.values_mut() {
+            file.live_bytes = 0;
+        }
         Ok(())
     }
 
+    /// Counts an entry of `size` bytes written to the data file `file_id`
+    pub fn record_write(&self, file_id: &str, size: u64) -> Result<()> {
+        let mut fragmentation = self.fragmentation_writer()?;
+        match fragmentation.get_mut(file_id) {
+            Some(file) => file.total_bytes += size,
+            None => {
+                fragmentation.insert(
+                    file_id.to_string(),
+                    FileFragmentation {
+                        total_bytes: size,
+                        live_bytes: 0,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Forgets the fragmentation of data files removed by a merge
+    pub fn remove_files(&self, file_ids: &[String]) -> Result<()> {
+        let mut fragmentation = self.fragmentation_writer()?;
+        for file_id in file_ids {
+            fragmentation.remove(file_id);
+        }
+        Ok(())
+    }
+
+    pub fn fragmentation(&self) -> Result<BTreeMap<String, FileFragmentation>> {
+        let fragmentation = self
+            .fragmentation
+            .read()
+            .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))?;
+        Ok(fragmentation.clone())
+    }
+
     pub fn keys(&self) -> Vec<Arc<Vec<u8>>> {
         let keys_dir_reader = match self.keys.read() {
             Ok(rdr) => rdr,
@@ -195,12 +352,21 @@ impl KeysDir {
             .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))?;
         Ok(keys_dir_reader.contains_key(key))
     }
+
+    fn shared_keys(&self) -> Result<Arc<BTreeMap<IVec, DataIndex>>> {
+        let keys_dir_reader = self
+            .keys
+            .read()
+            .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))?;
+        Ok(keys_dir_reader.clone())
+    }
 }
 
 impl KeysDir {
     pub fn new(file_pairs: &BTreeMap<String, FilePair>) -> Result<Self> {
         let keys_dir = Self {
             keys: Default::default(),
+            fragmentation: Default::default(),
         };
         for fp in file_pairs.values() {
             fp.fetch_hint_entries(&keys_dir)?;
@@ -244,6 +410,17 @@ impl IndexDir {
         indexes.insert(file_pair.file_id(), file_pair.to_index()?);
         Ok(())
     }
+
+    pub fn remove(&self, file_ids: &[String]) -> Result<()> {
+        let mut indexes = self
+            .indexes
+            .write()
+            .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))?;
+        for file_id in file_ids {
+            indexes.remove(file_id);
+        }
+        Ok(())
+    }
 }
 
 pub struct DataStore {
@@ -256,6 +433,97 @@ pub struct DataStore {
     double_buffer: HashMap<Vec<u8>, DataEntry>,
     buffer_size: RwLock<usize>,
     cache: RwLock<LruCache<Arc<Vec<u8>>, Arc<Vec<u8>>>>,
+    /// Only one merge runs at a time
+    merge_lock: Mutex<()>,
+    merge_stats: RwLock<MergeStats>,
+    /// Number of live snapshots
+    snapshots: AtomicUsize,
+    /// Data files replaced by a merge, removed when no snapshot can read them anymore
+    retired_files: Mutex<Vec<Index>>,
+}
+
+/// Read access to the values, shared by the data store and its snapshots
+pub trait DataReader: Send + Sync {
+    fn get(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>>;
+}
+
+impl DataReader for DataStore {
+    fn get(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>> {
+        DataStore::get(self, key)
+    }
+}
+
+/// Read-only view of the data store at the time it was taken.
+///
+/// The snapshot shares the keys dir with the data store until its next write.
+/// Data files merged while a snapshot is alive are kept until all snapshots are dropped.
+pub struct DataStoreSnapshot {
+    store: Arc<DataStore>,
+    keys: Arc<BTreeMap<IVec, DataIndex>>,
+    buffer: HashMap<Arc<Vec<u8>>, Arc<Vec<u8>>>,
+}
+
+impl DataStoreSnapshot {
+    pub fn get(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>> {
+        if let Some(value) = self.buffer.get(key) {
+            return Ok(Some(value.to_vec()));
+        }
+
+        match self.keys.get(key) {
+            Some(Persisted(entry)) => {
+                let indexes_read_lock = self.store.index_dir.indexes()?;
+                let index = indexes_read_lock
+                    .get(&entry.file_id)
+                    .ok_or(EdgeKVError::CorruptData)?;
+                let data_entry = index.read(entry.data_entry_position, entry.size())?;
+                Ok(Some(data_entry.value()))
+            }
+            Some(DataIndex::InBuffer) => Ok(None),
+            None => Ok(self
+                .store
+                .double_buffer
+                .get(key)
+                .map(|data_entry| data_entry.value())),
+        }
+    }
+
+    pub fn contains(&self, key: &Vec<u8>) -> bool {
+        self.buffer.contains_key(key) || self.keys.contains_key(key)
+    }
+
+    pub fn keys(&self) -> Vec<Arc<Vec<u8>>> {
+        self.keys.keys().cloned().collect()
+    }
+
+    pub fn range<R>(&self, range: R) -> Vec<Arc<Vec<u8>>>
+    where
+        R: RangeBounds<Vec<u8>>,
+    {
+        self.keys.range(range).map(|(k, _)| k.clone()).collect()
+    }
+
+    pub fn prefix(&self, prefix: &Vec<u8>) -> Vec<Arc<Vec<u8>>> {
+        self.keys
+            .range(prefix.clone()..)
+            .take_while(|(k, _)| k.starts_with(prefix))
+            .map(|(k, _)| k.clone())
+            .collect()
+    }
+}
+
+impl DataReader for DataStoreSnapshot {
+    fn get(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>> {
+        DataStoreSnapshot::get(self, key)
+    }
+}
+
+impl Drop for DataStoreSnapshot {
+    fn drop(&mut self) {
+        if self.store.snapshots.fetch_sub(1, Ordering::SeqCst) == 1 {
+            // TODO - TE-721: handle this error
+            let _ = self.store.remove_retired_files();
+        }
+    }
 }
 
 pub fn fetch_double_buffer_file(
@@ -293,6 +561,10 @@ impl DataStore {
             double_buffer,
             buffer_size: RwLock::new(0),
             cache: RwLock::new(LruCache::new(24_000)),
+            merge_lock: Mutex::new(()),
+            merge_stats: RwLock::new(MergeStats::default()),
+            snapshots: AtomicUsize::new(0),
+            retired_files: Mutex::new(Vec::new()),
         };
         instance.lock()?;
         Ok(instance)
@@ -363,7 +635,7 @@ impl DataStore {
             return Ok(Some(value.to_vec()));
         }
 
-        let key_dir_entry = if let Some(entry) = self.keys_dir.get(key) {
+        let mut key_dir_entry = if let Some(entry) = self.keys_dir.get(key) {
             entry
         } else {
             if let Some(data_entry) = self.double_buffer.get(key) {
@@ -372,16 +644,32 @@ impl DataStore {
             return Ok(None);
         };
 
-        let indexes_read_lock = self.index_dir.indexes()?;
-        let index = indexes_read_lock
-            .get(&key_dir_entry.file_id)
-            .ok_or(EdgeKVError::CorruptData)?;
-        if let Ok(data_entry) = index.read(key_dir_entry.data_entry_position, key_dir_entry.size())
-        {
-            cache.insert(Arc::new(key.clone()), Arc::new(data_entry.value()));
-            return Ok(Some(data_entry.value()));
+        loop {
+            let indexes_read_lock = self.index_dir.indexes()?;
+            let index = match indexes_read_lock.get(&key_dir_entry.file_id) {
+                Some(index) => index,
+                None => {
+                    // The data file was removed by a merge after the keys dir was read,
+                    // the key now points to the merged file
+                    drop(indexes_read_lock);
+                    match self.keys_dir.get(key) {
+                        Some(entry) if !entry.is_same_entry(&key_dir_entry) => {
+                            key_dir_entry = entry;
+                            continue;
+                        }
+                        Some(_) => return Err(EdgeKVError::CorruptData),
+                        None => return Ok(None),
+                    }
+                }
+            };
+            if let Ok(data_entry) =
+                index.read(key_dir_entry.data_entry_position, key_dir_entry.size())
+            {
+                cache.insert(Arc::new(key.clone()), Arc::new(data_entry.value()));
+                return Ok(Some(data_entry.value()));
+            }
+            return Ok(None);
         }
-        Ok(None)
     }
 
     pub fn delete(&self, key: &Vec<u8>) -> Result<()> {
@@ -485,33 +773,235 @@ impl DataStore {
         self.keys_dir.prefix(prefix)
     }
 
-    pub fn merge(&self) -> Result<()> {
+    /// Takes a read-only view of the data store
+    pub fn snapshot(self: &Arc<Self>) -> Result<DataStoreSnapshot> {
+        // the keys dir is only changed with the buffer locked
+        let buffer = self
+            .buffer
+            .read()
+            .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))?;
+        let keys = self.keys_dir.shared_keys()?;
+        self.snapshots.fetch_add(1, Ordering::SeqCst);
+
+        Ok(DataStoreSnapshot {
+            store: self.clone(),
+            keys,
+            buffer: buffer.clone(),
+        })
+    }
+
+    /// Removes the data files replaced by merges, unless a snapshot may still read them
+    fn remove_retired_files(&self) -> Result<()> {
+        let mut retired_files = self
+            .retired_files
+            .lock()
+            .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))?;
+        if retired_files.is_empty() || self.snapshots.load(Ordering::SeqCst) > 0 {
+            return Ok(());
+        }
+
+        let file_ids: Vec<String> = retired_files.iter().map(|index| index.file_id()).collect();
+        self.index_dir.remove(&file_ids)?;
+
+        let mut mark_for_removal = Vec::with_capacity(retired_files.len() * 2);
+        for index in retired_files.drain(..) {
+            mark_for_removal.push(index.data_file_path());
+            mark_for_removal.push(index.hint_file_path());
+        }
+        fs_extra::remove_items(&mark_for_removal)?;
+        Ok(())
+    }
+
+    fn active_file_id(&self) -> Result<String> {
         let active_file = self
             .active_file
             .read()
             .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))?;
-        let merged_file_pair = ActiveFilePair::from(create_new_file_pair(self.dir.as_path())?)?;
-        let mut mark_for_removal = Vec::new();
+        Ok(active_file.file_id())
+    }
 
-        for (_, index) in self.index_dir.indexes()?.iter() {
-            if index.file_id() == active_file.file_id() {
-                continue;
+    /// Live and dead bytes of each data file
+    pub fn fragmentation(&self) -> Result<BTreeMap<String, FileFragmentation>> {
+        self.keys_dir.fragmentation()
+    }
+
+    pub fn merge_stats(&self) -> MergeStats {
+        match self.merge_stats.read() {
+            Ok(stats) => *stats,
+            Err(_) => MergeStats::default(),
+        }
+    }
+
+    /// Data files, except the active one, with at least `min_dead_bytes` dead bytes
+    /// making at least `min_dead_ratio` of the file
+    pub fn fragmented_files(
+        &self,
+        min_dead_ratio: f64,
+        min_dead_bytes: u64,
+    ) -> Result<Vec<String>> {
+        let active_file_id = self.active_file_id()?;
+        Ok(self
+            .keys_dir
+            .fragmentation()?
+            .into_iter()
+            .filter(|(file_id, file)| {
+                *file_id != active_file_id
+                    && file.dead_bytes() >= min_dead_bytes
+                    && file.dead_ratio() >= min_dead_ratio
+            })
+            .map(|(file_id, _)| file_id)
+            .collect())
+    }
+
+    /// Merges all the data files except the active one
+    pub fn merge(&self) -> Result<()> {
+        let active_file_id = self.active_file_id()?;
+        let file_ids: Vec<String> = self
+            .index_dir
+            .indexes()?
+            .keys()
+            .filter(|file_id| **file_id != active_file_id)
+            .cloned()
+            .collect();
+        self.merge_files(&file_ids)
+    }
+
+    /// Copies the live entries of the data files `file_ids` into new data files, and
+    /// removes them.
+    ///
+    /// Only adjacent data files are merged together: each run of adjacent files of
+    /// `file_ids` is merged into its own file, loaded where the run was. Merging files
+    /// around a file left out would move the entries and tombstones of the older files
+    /// after it.
+    ///
+    /// Readers and writers are not blocked during the copy: the keys dir is only locked
+    /// to point the keys to the merged file, and keys written or deleted meanwhile are
+    /// left unchanged.
+    pub fn merge_files(&self, file_ids: &[String]) -> Result<()> {
+        let _merge_guard = self
+            .merge_lock
+            .lock()
+            .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))?;
+        let active_file_id = self.active_file_id()?;
+
+        let retired_file_ids: Vec<String> = self
+            .retired_files
+            .lock()
+            .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))?
+            .iter()
+            .map(|index| index.file_id())
+            .collect();
+
+        let (runs, mut drop_tombstones) = {
+            let indexes = self.index_dir.indexes()?;
+            let mut runs: Vec<Vec<Index>> = Vec::new();
+            let mut in_run = false;
+            for (file_id, index) in indexes.iter() {
+                if *file_id == active_file_id || retired_file_ids.contains(file_id) {
+                    continue;
+                }
+                if !file_ids.contains(file_id) {
+                    in_run = false;
+                    continue;
+                }
+                match runs.last_mut() {
+                    Some(run) if in_run => run.push(index.clone()),
+                    _ => runs.push(vec![index.clone()]),
+                }
+                in_run = true;
             }
-            let hints = index.get_hints()?;
-            for hint in hints {
-                if let Some(keys_dir_entry) = self.keys_dir.get(&hint.key()) {
-                    if keys_dir_entry.file_id == index.file_id() {
-                        let data_entry = index.read(hint.data_entry_position(), hint.size())?;
-                        let key_entry = merged_file_pair.write(&data_entry, &self.keys_dir)?;
-                        self.keys_dir.insert(Arc::new(hint.key()), key_entry)?;
+
+            // Tombstones only hide entries of older files, they are kept unless
+            // all the older files are merged too
+            let drop_tombstones = matches!(
+                indexes.keys().find(|file_id| {
+                    **file_id != active_file_id && !retired_file_ids.contains(*file_id)
+                }),
+                Some(oldest_file_id) if file_ids.contains(oldest_file_id)
+            );
+            (runs, drop_tombstones)
+        };
+        for run in runs {
+            self.merge_run(run, drop_tombstones)?;
+            drop_tombstones = false;
+        }
+        Ok(())
+    }
+
+    /// Merges the adjacent data files `indexes` into a new data file loaded right after them
+    fn merge_run(&self, indexes: Vec<Index>, drop_tombstones: bool) -> Result<()> {
+        let started = Instant::now();
+        let merged_file_ids: Vec<String> = indexes.iter().map(|index| index.file_id()).collect();
+
+        let merged_file_pair = ActiveFilePair::from(create_merged_file_pair(
+            self.dir.as_path(),
+            &merged_file_ids[merged_file_ids.len() - 1],
+        )?)?;
+        let mut merged_entries = Vec::new();
+        let mut is_empty = true;
+        let mut removed_bytes = 0;
+
+        for index in indexes.iter() {
+            let file_id = index.file_id();
+            for hint in index.get_hints()? {
+                if hint.is_deleted() {
+                    if !drop_tombstones {
+                        merged_file_pair.remove(hint.key())?;
+                        is_empty = false;
                     }
+                    continue;
                 }
+                let keys_dir_entry = match self.keys_dir.get(&hint.key()) {
+                    Some(entry)
+                        if entry.file_id == file_id
+                            && entry.data_entry_position == hint.data_entry_position() =>
+                    {
+                        entry
+                    }
+                    _ => continue,
+                };
+                let data_entry = index.read(hint.data_entry_position(), hint.size())?;
+                let key_entry = merged_file_pair.write(&data_entry, &self.keys_dir)?;
+                merged_entries.push((Arc::new(hint.key()), keys_dir_entry, key_entry));
+                is_empty = false;
             }
-            mark_for_removal.push(index.data_file_path());
-            mark_for_removal.push(index.hint_file_path());
+            removed_bytes += std::fs::metadata(index.data_file_path())?.len()
+                + std::fs::metadata(index.hint_file_path())?.len();
         }
 
-        fs_extra::remove_items(&mark_for_removal)?;
+        merged_file_pair.sync()?;
+        let merged_bytes =
+            merged_file_pair.data_file_size()? + merged_file_pair.hint_file_size()?;
+        let merged_file_pair_paths = merged_file_pair.get_file_pair();
+        drop(merged_file_pair);
+
+        // The merged file is readable before any key points to it
+        if is_empty {
+            fs_extra::remove_items(&[
+                merged_file_pair_paths.data_file_path(),
+                merged_file_pair_paths.hint_file_path(),
+            ])?;
+        } else {
+            self.index_dir.insert(merged_file_pair_paths)?;
+        }
+        self.keys_dir.replace_merged(merged_entries)?;
+        self.keys_dir.remove_files(&merged_file_ids)?;
+
+        let merged_files_count = indexes.len() as u64;
+        self.retired_files
+            .lock()
+            .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))?
+            .extend(indexes);
+        self.remove_retired_files()?;
+
+        let mut merge_stats = self
+            .merge_stats
+            .write()
+            .map_err(|e| EdgeKVError::RWLockPoisonError(format!("{}", e)))?;
+        merge_stats.merges += 1;
+        merge_stats.merged_files += merged_files_count;
+        merge_stats.reclaimed_bytes += removed_bytes.saturating_sub(merged_bytes);
+        merge_stats.total_merge_duration += started.elapsed();
 
         Ok(())
     }
@@ -779,6 +1269,141 @@ mod tests {
         clean_up();
     }
 
+    #[test]
+    #[serial]
+    fn test_merge_fragmented_files() {
+        fs_extra::dir::remove("./testdir/_test_merge_fragmented_files").ok();
+        {
+            let ds = DataStore::open("./testdir/_test_merge_fragmented_files").unwrap();
+            ds.put(vec![1], vec![1; 100]).unwrap();
+            ds.put(vec![2], vec![2; 100]).unwrap();
+            ds.put(vec![3], vec![3; 100]).unwrap();
+            ds.sync_all(true).expect("Failed sync_all");
+            ds.put(vec![1], vec![4; 100]).unwrap();
+            ds.delete(&vec![2]).unwrap();
+            ds.sync_all(true).expect("Failed sync_all");
+
+            let fragmented = ds.fragmented_files(0.5, 0).unwrap();
+            assert_eq!(fragmented.len(), 1);
+            let fragmentation = ds.fragmentation().unwrap();
+            assert_eq!(fragmentation[&fragmented[0]].dead_bytes(), 2 * 129);
+            assert_eq!(fragmentation[&fragmented[0]].live_bytes, 129);
+
+            ds.merge_files(&fragmented).expect("Failed merge");
+            assert!(ds.fragmented_files(0.5, 0).unwrap().is_empty());
+            assert_eq!(ds.merge_stats().merges, 1);
+            assert_eq!(ds.merge_stats().merged_files, 1);
+            assert!(ds.merge_stats().reclaimed_bytes > 0);
+
+            assert_eq!(ds.get(&vec![1]).unwrap(), Some(vec![4; 100]));
+            assert_eq!(ds.get(&vec![2]).unwrap(), None);
+            assert_eq!(ds.get(&vec![3]).unwrap(), Some(vec![3; 100]));
+        }
+
+        {
+            let ds = DataStore::open("./testdir/_test_merge_fragmented_files").unwrap();
+            assert_eq!(ds.get(&vec![1]).unwrap(), Some(vec![4; 100]));
+            assert_eq!(ds.get(&vec![2]).unwrap(), None);
+            assert_eq!(ds.get(&vec![3]).unwrap(), Some(vec![3; 100]));
+        }
+        fs_extra::dir::remove("./testdir/_test_merge_fragmented_files").ok();
+    }
+
+    #[test]
+    #[serial]
+    fn test_merge_non_adjacent_files() {
+        fs_extra::dir::remove("./testdir/_test_merge_non_adjacent_files").ok();
+        {
+            let ds = DataStore::open("./testdir/_test_merge_non_adjacent_files").unwrap();
+            ds.put(vec![1], vec![1; 100]).unwrap();
+            ds.sync_all(true).expect("Failed sync_all");
+            // A: the tombstone hides the entry of the older file
+            ds.delete(&vec![1]).unwrap();
+            ds.sync_all(true).expect("Failed sync_all");
+            // B: written again after the tombstone
+            ds.put(vec![1], vec![2; 100]).unwrap();
+            ds.sync_all(true).expect("Failed sync_all");
+            // C
+            ds.put(vec![3], vec![3; 100]).unwrap();
+            ds.sync_all(true).expect("Failed sync_all");
+
+            let file_ids: Vec<String> = ds.index_dir.indexes().unwrap().keys().cloned().collect();
+            let (a, c) = (file_ids[1].clone(), file_ids[3].clone());
+            ds.merge_files(&[a, c]).expect("Failed merge");
+            assert_eq!(ds.merge_stats().merges, 2);
+            assert_eq!(ds.merge_stats().merged_files, 2);
+
+            assert_eq!(ds.get(&vec![1]).unwrap(), Some(vec![2; 100]));
+            assert_eq!(ds.get(&vec![3]).unwrap(), Some(vec![3; 100]));
+        }
+
+        {
+            let ds = DataStore::open("./testdir/_test_merge_non_adjacent_files").unwrap();
+            assert_eq!(ds.get(&vec![1]).unwrap(), Some(vec![2; 100]));
+            assert_eq!(ds.get(&vec![3]).unwrap(), Some(vec![3; 100]));
+        }
+        fs_extra::dir::remove("./testdir/_test_merge_non_adjacent_files").ok();
+    }
+
+    #[test]
+    #[serial]
+    fn test_snapshot() {
+        fs_extra::dir::remove("./testdir/_test_snapshot").ok();
+        {
+            let ds = Arc::new(DataStore::open("./testdir/_test_snapshot").unwrap());
+            ds.put(vec![1], vec![1]).unwrap();
+            ds.put(vec![2], vec![2]).unwrap();
+            ds.sync_all(true).expect("Failed sync_all");
+            ds.put(vec![3], vec![3]).unwrap();
+
+            let snapshot = ds.snapshot().unwrap();
+            ds.put(vec![1], vec![4]).unwrap();
+            ds.delete(&vec![2]).unwrap();
+            ds.delete(&vec![3]).unwrap();
+            ds.put(vec![5], vec![5]).unwrap();
+            ds.sync_all(true).expect("Failed sync_all");
+            ds.merge().expect("Failed merge");
+
+            assert_eq!(snapshot.get(&vec![1]).unwrap(), Some(vec![1]));
+            assert_eq!(snapshot.get(&vec![2]).unwrap(), Some(vec![2]));
+            assert_eq!(snapshot.get(&vec![3]).unwrap(), Some(vec![3]));
+            assert_eq!(snapshot.get(&vec![5]).unwrap(), None);
+            assert_eq!(snapshot.keys().len(), 3);
+            drop(snapshot);
+
+            assert_eq!(ds.get(&vec![1]).unwrap(), Some(vec![4]));
+            assert_eq!(ds.get(&vec![2]).unwrap(), None);
+            assert_eq!(ds.get(&vec![5]).unwrap(), Some(vec![5]));
+        }
+        fs_extra::dir::remove("./testdir/_test_snapshot").ok();
+    }
+
+    #[test]
+    #[serial]
+    fn test_keys_dir_copied_only_with_live_snapshot() {
+        fs_extra::dir::remove("./testdir/_test_keys_dir_copy").ok();
+        {
+            let ds = Arc::new(DataStore::open("./testdir/_test_keys_dir_copy").unwrap());
+            let keys_ptr = |ds: &DataStore| Arc::as_ptr(&ds.keys_dir.shared_keys().unwrap());
+
+            let before = keys_ptr(&ds);
+            ds.put(vec![1], vec![1]).unwrap();
+            ds.sync_all(false).expect("Failed sync_all");
+            assert_eq!(keys_ptr(&ds), before);
+
+            let snapshot = ds.snapshot().unwrap();
+            ds.put(vec![2], vec![2]).unwrap();
+            let copied = keys_ptr(&ds);
+            assert_ne!(copied, before);
+            drop(snapshot);
+
+            ds.put(vec![3], vec![3]).unwrap();
+            ds.delete(&vec![1]).unwrap();
+            assert_eq!(keys_ptr(&ds), copied);
+        }
+        fs_extra::dir::remove("./testdir/_test_keys_dir_copy").ok();
+    }
+
     fn clean_up() {
         fs_extra::dir::remove("./testdir/_test_data_store").ok();
     }
@@ -3,17 +3,20 @@
 
 #![allow(clippy::ptr_arg)]
 
-use crate::datastore::{DataStore, MergeOperator};
+use crate::datastore::{
+    DataReader, DataStore, DataStoreSnapshot, FileFragmentation, MergeOperator, MergeStats,
+};
 
 use crate::Result;
 
+use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
 use std::ops::RangeBounds;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 // TODO - TE-721: dir and config are not used
 pub struct EdgeKV {
@@ -34,6 +37,8 @@ impl Display for EdgeKV {
 #[derive(Copy, Clone)]
 pub struct EdgeKVConfiguration {
     pub write_threshold: usize,
+    /// Automatic merge of the fragmented data files, disabled with `None`
+    pub merge_policy: Option<MergePolicy>,
 }
 
 const DEFAULT_WRITE_THRESHOLD: usize = 1000;
@@ -42,6 +47,32 @@ impl Default for EdgeKVConfiguration {
     fn default() -> Self {
         Self {
             write_threshold: DEFAULT_WRITE_THRESHOLD,
+            merge_policy: Some(MergePolicy::default()),
+        }
+    }
+}
+
+/// When the background merge worker merges a data file
+#[derive(Copy, Clone, Debug)]
+pub struct MergePolicy {
+    /// Minimal part of the data file made of overwritten or deleted entries
+    pub min_dead_ratio: f64,
+    /// Minimal size of the overwritten or deleted entries of the data file
+    pub min_dead_bytes: u64,
+    /// Interval between two checks of the fragmentation
+    pub check_interval: Duration,
+}
+
+const DEFAULT_MERGE_MIN_DEAD_RATIO: f64 = 0.5;
+const DEFAULT_MERGE_MIN_DEAD_BYTES: u64 = 16 * 1024 * 1024;
+const DEFAULT_MERGE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+impl Default for MergePolicy {
+    fn default() -> Self {
+        Self {
+            min_dead_ratio: DEFAULT_MERGE_MIN_DEAD_RATIO,
+            min_dead_bytes: DEFAULT_MERGE_MIN_DEAD_BYTES,
+            check_interval: DEFAULT_MERGE_CHECK_INTERVAL,
         }
     }
 }
@@ -102,13 +133,43 @@ impl EdgeKV {
                     match store.sync_all(store.buffer_size() >= config.write_threshold) {
                         Ok(_) => {}
                         Err(e) => {
-                            //Todo log Error
-                            println!("Error {:?}", e)
+                            log::error!("EdgeKV sync error: {:?}", e)
                         }
                     }
                 }
                 drop(store)
             })?;
+
+        if let Some(merge_policy) = config.merge_policy {
+            let is_dropped = self.dropped.clone();
+            let store = self.store.clone();
+            thread::Builder::new()
+                .name(format!("edgekv-merge-{}", worker_name))
+                .spawn(move || {
+                    let mut last_check = Instant::now();
+                    loop {
+                        thread::sleep(Duration::from_millis(100));
+                        if is_dropped.load(Ordering::Acquire) {
+                            break;
+                        }
+                        if last_check.elapsed() < merge_policy.check_interval {
+                            continue;
+                        }
+                        last_check = Instant::now();
+
+                        let result = store
+                            .fragmented_files(
+                                merge_policy.min_dead_ratio,
+                                merge_policy.min_dead_bytes,
+                            )
+                            .and_then(|file_ids| store.merge_files(&file_ids));
+                        if let Err(e) = result {
+                            log::error!("EdgeKV merge error: {:?}", e)
+                        }
+                    }
+                    drop(store)
+                })?;
+        }
         Ok(())
     }
     pub fn put(&self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
@@ -140,6 +201,15 @@ impl EdgeKV {
         self.store.merge()
     }
 
+    /// Live and dead bytes of each data file
+    pub fn fragmentation(&self) -> Result<BTreeMap<String, FileFragmentation>> {
+        self.store.fragmentation()
+    }
+
+    pub fn merge_stats(&self) -> MergeStats {
+        self.store.merge_stats()
+    }
+
     pub fn clear(&self) -> Result<()> {
         self.store.clear()
     }
@@ -184,6 +254,48 @@ impl EdgeKV {
     pub fn sync_all(&self) -> Result<()> {
         self.store.sync_all(true)
     }
+
+    /// Takes a read-only view of the database, unaffected by later writes
+    pub fn snapshot(&self) -> Result<EdgeKVSnapshot> {
+        Ok(EdgeKVSnapshot {
+            snapshot: Arc::new(self.store.snapshot()?),
+        })
+    }
+}
+
+pub struct EdgeKVSnapshot {
+    snapshot: Arc<DataStoreSnapshot>,
+}
+
+impl EdgeKVSnapshot {
+    pub fn get(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>> {
+        if key.is_empty() {
+            return Ok(None);
+        }
+        self.snapshot.get(key)
+    }
+
+    pub fn contains(&self, key: &Vec<u8>) -> Result<bool> {
+        if key.is_empty() {
+            return Ok(false);
+        }
+        Ok(self.snapshot.contains(key))
+    }
+
+    pub fn iter(&self) -> DBIterator {
+        DBIterator::with_keys(self.snapshot.clone(), self.snapshot.keys())
+    }
+
+    pub fn range<R>(&self, range: R) -> DBIterator
+    where
+        R: RangeBounds<Vec<u8>>,
+    {
+        DBIterator::with_keys(self.snapshot.clone(), self.snapshot.range(range))
+    }
+
+    pub fn prefix(&self, prefix: &Vec<u8>) -> DBIterator {
+        DBIterator::with_keys(self.snapshot.clone(), self.snapshot.prefix(prefix))
+    }
 }
 
 impl Drop for EdgeKV {
@@ -195,7 +307,7 @@ impl Drop for EdgeKV {
 }
 
 pub struct DBIterator {
-    store: Arc<DataStore>,
+    store: Arc<dyn DataReader>,
     inner: Vec<Arc<Vec<u8>>>,
     cursor: usize,
 }
@@ -230,6 +342,14 @@ impl DBIterator {
             cursor: 0,
         }
     }
+
+    fn with_keys(store: Arc<dyn DataReader>, keys: Vec<Arc<Vec<u8>>>) -> Self {
+        Self {
+            store,
+            inner: keys,
+            cursor: 0,
+        }
+    }
 }
 
 impl Iterator for DBIterator {
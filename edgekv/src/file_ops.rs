@@ -17,6 +17,7 @@ use time::OffsetDateTime;
 const DATA_FILE_EXTENSION: &str = "data";
 const HINT_FILE_EXTENSION: &str = "hint";
 const BUFFER_FILE_EXTENSION: &str = "buff";
+const MERGED_FILE_ID_SEPARATOR: char = '_';
 
 #[derive(Debug, Clone)]
 pub struct FilePair {
@@ -42,7 +43,7 @@ impl FilePair {
         String::from(self.hint_file_path.to_string_lossy())
     }
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Index {
     file_id: String,
     data_file_path: PathBuf,
@@ -129,6 +130,7 @@ impl FilePair {
                     hint_entry.value_size(),
                     hint_entry.data_entry_position(),
                 );
+                keys_dir.record_write(&self.file_id, key_dir_entry.data_entry_size())?;
                 keys_dir.insert(Arc::new(hint_entry.key()), key_dir_entry)?;
             }
         }
@@ -211,7 +213,7 @@ impl Drop for ActiveFilePair {
 }
 
 impl ActiveFilePair {
-    pub fn write(&self, entry: &DataEntry, _keys_dir: &KeysDir) -> Result<KeyDirEntry> {
+    pub fn write(&self, entry: &DataEntry, keys_dir: &KeysDir) -> Result<KeyDirEntry> {
         self.data_file.try_lock_exclusive()?;
         self.hint_file.try_lock_exclusive()?;
 
@@ -230,12 +232,14 @@ impl ActiveFilePair {
 
         self.data_file.unlock()?;
         self.hint_file.unlock()?;
-        Ok(KeyDirEntry::new(
+        let key_dir_entry = KeyDirEntry::new(
             self.file_pair.file_id.to_string(),
             hint_entry.key_size(),
             hint_entry.value_size(),
             data_entry_position,
-        ))
+        );
+        keys_dir.record_write(&self.file_pair.file_id, key_dir_entry.data_entry_size())?;
+        Ok(key_dir_entry)
     }
 
     pub fn remove(&self, key: Vec<u8>) -> Result<()> {
@@ -253,9 +257,34 @@ impl ActiveFilePair {
 }
 
 pub fn create_new_file_pair<P: AsRef<Path>>(dir: P) -> Result<FilePair> {
+    let file_name = OffsetDateTime::now_utc().unix_timestamp_nanos().to_string();
+    create_file_pair(dir, file_name)
+}
+
+/// Creates the file pair receiving the merge of data files up to `last_merged_file_id`.
+///
+/// File pairs are loaded in the order of their ids, so the merged pair is named to be
+/// loaded right after the last merged file, and before any newer file.
+pub fn create_merged_file_pair<P: AsRef<Path>>(
+    dir: P,
+    last_merged_file_id: &str,
+) -> Result<FilePair> {
+    let base_file_id = last_merged_file_id
+        .split(MERGED_FILE_ID_SEPARATOR)
+        .next()
+        .unwrap_or(last_merged_file_id);
+    let file_name = format!(
+        "{}{}{}",
+        base_file_id,
+        MERGED_FILE_ID_SEPARATOR,
+        OffsetDateTime::now_utc().unix_timestamp_nanos()
+    );
+    create_file_pair(dir, file_name)
+}
+
+fn create_file_pair<P: AsRef<Path>>(dir: P, file_name: String) -> Result<FilePair> {
     fs_extra::dir::create_all(dir.as_ref(), false)?;
 
-    let file_name = OffsetDateTime::now_utc().unix_timestamp_nanos().to_string();
     let mut data_file_path = PathBuf::new();
     data_file_path.push(dir.as_ref());
     data_file_path.push(format!("{}.{}", file_name, DATA_FILE_EXTENSION));
@@ -7,6 +7,9 @@ use time::OffsetDateTime;
 pub const CRC_CKSUM: Crc<u32> = Crc::<u32>::new(&CRC_32_CKSUM);
 use crate::Result;
 
+/// Size of the crc, timestamp, key size and value size of an encoded [`DataEntry`]
+pub const DATA_ENTRY_HEADER_SIZE: u64 = 4 + 8 + 8 + 8;
+
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
 pub struct DataEntry {
     crc: u32,
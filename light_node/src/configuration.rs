@@ -19,6 +19,7 @@ use crypto::hash::BlockHash;
 use logging::config::{FileLoggerConfig, LogFormat, LoggerType, NoDrainError, SlogConfig};
 use shell::shell_automaton_manager::P2p;
 use shell::PeerConnectionThreshold;
+use shell_automaton::service::recording::RecordingConfig;
 use storage::database::tezedge_database::TezedgeDatabaseBackendConfiguration;
 use storage::initializer::{DbsRocksDbTableInitializer, RocksDbConfig};
 use storage::{BlockReference, Replay, StorageSnapshot};
@@ -91,13 +92,14 @@ pub struct Storage {
     pub patch_context: Option<PatchContext>,
     pub main_db: TezedgeDatabaseBackendConfiguration,
     pub initialize_context_timeout: Duration,
+    pub context_scrub_interval: Option<Duration>,
 }
 
 impl Storage {
     const STORAGES_COUNT: usize = 3;
     const MINIMAL_THREAD_COUNT: usize = 1;
 
-    const DB_STORAGE_VERSION: i64 = 21;
+    const DB_STORAGE_VERSION: i64 = 22;
 
     const LRU_CACHE_SIZE_96MB: usize = 96 * 1024 * 1024;
 
@@ -265,6 +267,12 @@ pub fn tezos_app() -> App<'static, 'static> {
             .takes_value(true)
             .value_name("BOOL")
             .help("Enable or not the integrity check on persistent tezedge context"))
+        .arg(Arg::with_name("context-key-history")
+            .long("context-key-history")
+            .global(true)
+            .takes_value(true)
+            .value_name("BOOL")
+            .help("Record the values changed by each applied block in the persistent tezedge context, to query the history of a key (/dev/chains/:chain_id/context/history)"))
         .arg(Arg::with_name("identity-file")
             .long("identity-file")
             .global(true)
@@ -301,6 +309,14 @@ pub fn tezos_app() -> App<'static, 'static> {
             .help("Panic if the context initialization of application took longer than this number of seconds")
             .validator(parse_validator_fn!(u64, "Value must be a valid number"))
         )
+        .arg(Arg::with_name("context-scrub-interval-in-secs")
+            .long("context-scrub-interval-in-secs")
+            .takes_value(true)
+            .value_name("NUM")
+            .required(false)
+            .help("Every NUM seconds, recompute the hashes of the most recent commits of the TezEdge on-disk context in a low priority thread, and report the first corrupted object. Disabled by default")
+            .validator(parse_validator_fn!(u64, "Value must be a valid number"))
+        )
         .arg(Arg::with_name("initialize-chain-manager-timeout-in-secs")
             .long("initialize-chain-manager-timeout-in-secs")
             .takes_value(true)
@@ -591,6 +607,22 @@ pub fn tezos_app() -> App<'static, 'static> {
             .takes_value(false)
             .help("Enable recording/persisting shell automaton actions.")
         )
+        .arg(Arg::with_name("record-shell-automaton-dir")
+            .long("record-shell-automaton-dir")
+            .global(true)
+            .takes_value(true)
+            .value_name("PATH")
+            .help("Record the shell automaton actions and state snapshots to compressed files in this directory, instead of the main database. Import them with the import-shell-automaton-recording subcommand.")
+        )
+        .arg(Arg::with_name("record-shell-automaton-file-size")
+            .long("record-shell-automaton-file-size")
+            .global(true)
+            .takes_value(true)
+            .value_name("MB")
+            .default_value("256")
+            .help("A new recording file is started once the current one reaches this size")
+            .validator(parse_validator_fn!(u64, "Value must be a valid number"))
+        )
         .arg(Arg::with_name("sandbox-patch-context-json-file")
             .long("sandbox-patch-context-json-file")
             .global(true)
@@ -599,6 +631,14 @@ pub fn tezos_app() -> App<'static, 'static> {
             .required(false)
             .help("Path to the json file with key-values, which will be added to empty context on startup and commit genesis.")
             .validator(|v| if Path::new(&v).exists() { Ok(()) } else { Err(format!("Sandbox patch-context json file not found at '{}'", v)) }))
+        .arg(Arg::with_name("sandbox-clock-file")
+            .long("sandbox-clock-file")
+            .global(true)
+            .takes_value(true)
+            .value_name("PATH")
+            .required(false)
+            .help("Path to the clock file of the sandbox launcher, the node follows the sandbox time instead of the system time.")
+            .validator(|v| if Path::new(&v).exists() { Ok(()) } else { Err(format!("Sandbox clock file not found at '{}'", v)) }))
         .arg(Arg::with_name("baker-data-dir")
             .long("baker-data-dir")
             .global(true)
@@ -926,6 +966,254 @@ fn import_snapshot_app() -> App<'static, 'static> {
     app
 }
 
+fn migrate_maindb_app() -> App<'static, 'static> {
+    App::new("TezEdge Light Node")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author("TezEdge and the project contributors")
+        .about("Rust implementation of the Tezos node")
+        .setting(clap::AppSettings::AllArgsOverrideSelf)
+        .subcommand(
+            clap::SubCommand::with_name("migrate-maindb")
+                .about("Copies the main database to another backend. The node must not be running.")
+                .arg(Arg::with_name("source-backend")
+                     .long("source-backend")
+                     .takes_value(true)
+                     .value_name("STRING")
+                     .required(true)
+                     .possible_values(&TezedgeDatabaseBackendConfiguration::possible_values())
+                     .help("Backend of the source main database"))
+                .arg(Arg::with_name("source-path")
+                     .long("source-path")
+                     .takes_value(true)
+                     .value_name("PATH")
+                     .required(true)
+                     .help("Directory of the source main database (<tezos-data-dir>/<bootstrap-db-path>/db)")
+                     .validator(|v| {
+                         if Path::new(&v).is_dir() {
+                             Ok(())
+                         } else {
+                             Err(format!("Source main database directory '{}' does not exist!", v))
+                         }
+                     }))
+                .arg(Arg::with_name("target-backend")
+                     .long("target-backend")
+                     .takes_value(true)
+                     .value_name("STRING")
+                     .required(true)
+                     .possible_values(&TezedgeDatabaseBackendConfiguration::possible_values())
+                     .help("Backend of the target main database"))
+                .arg(Arg::with_name("target-path")
+                     .long("target-path")
+                     .takes_value(true)
+                     .value_name("PATH")
+                     .required(true)
+                     .help("Directory of the target main database. An interrupted migration is resumed when run again with the same target."))
+                .arg(Arg::with_name("verify-sample-rate")
+                     .long("verify-sample-rate")
+                     .takes_value(true)
+                     .value_name("NUM")
+                     .default_value("1000")
+                     .help("After the copy, the values of one key out of every NUM keys are compared")
+                     .validator(parse_validator_fn!(u64, "Value must be a valid number")))
+        )
+}
+
+pub struct MigrateMainDb {
+    pub source_backend: TezedgeDatabaseBackendConfiguration,
+    pub source_path: PathBuf,
+    pub target_backend: TezedgeDatabaseBackendConfiguration,
+    pub target_path: PathBuf,
+    pub verify_sample_rate: u64,
+}
+
+impl MigrateMainDb {
+    pub fn from_args() -> Option<Self> {
+        let app = migrate_maindb_app();
+        let args = app.get_matches();
+
+        args.subcommand_matches("migrate-maindb").map(|args| {
+            let backend = |name| {
+                args.value_of(name)
+                    .unwrap()
+                    .parse::<TezedgeDatabaseBackendConfiguration>()
+                    .expect("Provided value cannot be converted to a main database backend")
+            };
+            let path = |name| {
+                args.value_of(name)
+                    .unwrap()
+                    .parse::<PathBuf>()
+                    .expect("Provided value cannot be converted to path")
+            };
+
+            MigrateMainDb {
+                source_backend: backend("source-backend"),
+                source_path: path("source-path"),
+                target_backend: backend("target-backend"),
+                target_path: path("target-path"),
+                verify_sample_rate: args
+                    .value_of("verify-sample-rate")
+                    .unwrap()
+                    .parse::<u64>()
+                    .expect("Provided value cannot be converted to number"),
+            }
+        })
+    }
+}
+
+fn check_shell_automaton_replay_app() -> App<'static, 'static> {
+    App::new("TezEdge Light Node")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author("TezEdge and the project contributors")
+        .about("Rust implementation of the Tezos node")
+        .setting(clap::AppSettings::AllArgsOverrideSelf)
+        .subcommand(
+            clap::SubCommand::with_name("check-shell-automaton-replay")
+                .about("Replays the recorded shell automaton actions with the current reducer, and reports the first state snapshot which differs. The node must not be running.")
+                .arg(Arg::with_name("maindb-backend")
+                     .long("maindb-backend")
+                     .takes_value(true)
+                     .value_name("STRING")
+                     .possible_values(&TezedgeDatabaseBackendConfiguration::possible_values())
+                     .default_value(Storage::DEFAULT_MAINDB)
+                     .help("Backend of the main database"))
+                .arg(Arg::with_name("db-path")
+                     .long("db-path")
+                     .takes_value(true)
+                     .value_name("PATH")
+                     .required(true)
+                     .help("Directory of the main database (<tezos-data-dir>/<bootstrap-db-path>/db), recorded with --record-shell-automaton-actions and --record-shell-automaton-state-snapshots")
+                     .validator(|v| {
+                         if Path::new(&v).is_dir() {
+                             Ok(())
+                         } else {
+                             Err(format!("Main database directory '{}' does not exist!", v))
+                         }
+                     }))
+                .arg(Arg::with_name("from-action-id")
+                     .long("from-action-id")
+                     .takes_value(true)
+                     .value_name("NUM")
+                     .help("The replay starts from the closest state snapshot before this action, or from the first snapshot if not set")
+                     .validator(parse_validator_fn!(u64, "Value must be a valid number")))
+                .arg(Arg::with_name("to-action-id")
+                     .long("to-action-id")
+                     .takes_value(true)
+                     .value_name("NUM")
+                     .help("The replay stops after this action, or after the last recorded action if not set")
+                     .validator(parse_validator_fn!(u64, "Value must be a valid number")))
+        )
+}
+
+pub struct CheckShellAutomatonReplay {
+    pub maindb_backend: TezedgeDatabaseBackendConfiguration,
+    pub db_path: PathBuf,
+    pub from_action_id: Option<u64>,
+    pub to_action_id: Option<u64>,
+}
+
+impl CheckShellAutomatonReplay {
+    pub fn from_args() -> Option<Self> {
+        let app = check_shell_automaton_replay_app();
+        let args = app.get_matches();
+
+        args.subcommand_matches("check-shell-automaton-replay")
+            .map(|args| {
+                let action_id = |name| {
+                    args.value_of(name).map(|v| {
+                        v.parse::<u64>()
+                            .expect("Provided value cannot be converted to number")
+                    })
+                };
+
+                CheckShellAutomatonReplay {
+                    maindb_backend: args
+                        .value_of("maindb-backend")
+                        .unwrap()
+                        .parse::<TezedgeDatabaseBackendConfiguration>()
+                        .expect("Provided value cannot be converted to a main database backend"),
+                    db_path: args
+                        .value_of("db-path")
+                        .unwrap()
+                        .parse::<PathBuf>()
+                        .expect("Provided value cannot be converted to path"),
+                    from_action_id: action_id("from-action-id"),
+                    to_action_id: action_id("to-action-id"),
+                }
+            })
+    }
+}
+
+fn import_shell_automaton_recording_app() -> App<'static, 'static> {
+    App::new("TezEdge Light Node")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author("TezEdge and the project contributors")
+        .about("Rust implementation of the Tezos node")
+        .setting(clap::AppSettings::AllArgsOverrideSelf)
+        .subcommand(
+            clap::SubCommand::with_name("import-shell-automaton-recording")
+                .about("Imports the shell automaton actions and state snapshots recorded to files (--record-shell-automaton-dir) into a main database, for check-shell-automaton-replay or the dev RPCs.")
+                .arg(Arg::with_name("from")
+                     .long("from")
+                     .takes_value(true)
+                     .value_name("PATH")
+                     .required(true)
+                     .help("Directory of the recording files")
+                     .validator(|v| {
+                         if Path::new(&v).is_dir() {
+                             Ok(())
+                         } else {
+                             Err(format!("Recording directory '{}' does not exist!", v))
+                         }
+                     }))
+                .arg(Arg::with_name("maindb-backend")
+                     .long("maindb-backend")
+                     .takes_value(true)
+                     .value_name("STRING")
+                     .possible_values(&TezedgeDatabaseBackendConfiguration::possible_values())
+                     .default_value(Storage::DEFAULT_MAINDB)
+                     .help("Backend of the main database"))
+                .arg(Arg::with_name("db-path")
+                     .long("db-path")
+                     .takes_value(true)
+                     .value_name("PATH")
+                     .required(true)
+                     .help("Directory of the main database, created if it does not exist. It must not be used by a running node."))
+        )
+}
+
+pub struct ImportShellAutomatonRecording {
+    pub from: PathBuf,
+    pub maindb_backend: TezedgeDatabaseBackendConfiguration,
+    pub db_path: PathBuf,
+}
+
+impl ImportShellAutomatonRecording {
+    pub fn from_args() -> Option<Self> {
+        let app = import_shell_automaton_recording_app();
+        let args = app.get_matches();
+
+        args.subcommand_matches("import-shell-automaton-recording")
+            .map(|args| {
+                let path = |name| {
+                    args.value_of(name)
+                        .unwrap()
+                        .parse::<PathBuf>()
+                        .expect("Provided value cannot be converted to path")
+                };
+
+                ImportShellAutomatonRecording {
+                    from: path("from"),
+                    maindb_backend: args
+                        .value_of("maindb-backend")
+                        .unwrap()
+                        .parse::<TezedgeDatabaseBackendConfiguration>()
+                        .expect("Provided value cannot be converted to a main database backend"),
+                    db_path: path("db-path"),
+                }
+            })
+    }
+}
+
 pub struct ImportSnapshot {
     pub from: Url,
     pub to: PathBuf,
@@ -956,6 +1244,9 @@ impl ImportSnapshot {
 #[allow(clippy::large_enum_variant)]
 pub enum TezedgeEnv {
     ImportSnapshot(ImportSnapshot),
+    MigrateMainDb(MigrateMainDb),
+    CheckShellAutomatonReplay(CheckShellAutomatonReplay),
+    ImportShellAutomatonRecording(ImportShellAutomatonRecording),
     Normal(Environment),
 }
 
@@ -1243,6 +1534,21 @@ impl Environment {
                 record_shell_automaton_state_snapshots: args
                     .is_present("record-shell-automaton-state-snapshots"),
                 record_shell_automaton_actions: args.is_present("record-shell-automaton-actions"),
+                record_shell_automaton_to_files: args.value_of("record-shell-automaton-dir").map(
+                    |dir| RecordingConfig {
+                        dir: dir
+                            .parse::<PathBuf>()
+                            .map(|p| get_final_path(&tezos_data_dir, p))
+                            .expect("Provided value cannot be converted to path"),
+                        max_file_size: args
+                            .value_of("record-shell-automaton-file-size")
+                            .unwrap()
+                            .parse::<u64>()
+                            .expect("Provided value cannot be converted to number")
+                            * 1024
+                            * 1024,
+                    },
+                ),
 
                 baker_data_dir: args
                     .value_of("baker-data-dir")
@@ -1259,6 +1565,7 @@ impl Environment {
                     .unwrap_or("off")
                     .parse()
                     .expect("incorrect value"),
+                sandbox_clock_file: args.value_of("sandbox-clock-file").map(PathBuf::from),
             },
             rpc: crate::configuration::Rpc {
                 listener_port: args
@@ -1350,6 +1657,12 @@ impl Environment {
                     .parse::<bool>()
                     .expect("Provided value cannot be converted to bool");
 
+                let key_history = args
+                    .value_of("context-key-history")
+                    .unwrap_or("false")
+                    .parse::<bool>()
+                    .expect("Provided value cannot be converted to bool");
+
                 let context_kv_store = args
                     .value_of("context-kv-store")
                     .unwrap_or(Storage::DEFAULT_CONTEXT_KV_STORE_BACKEND)
@@ -1362,6 +1675,7 @@ impl Environment {
                                     .into_string()
                                     .unwrap(),
                                 startup_check,
+                                key_history,
                             },
                         ),
                         SupportedContextKeyValueStore::OnDisk => {
@@ -1372,6 +1686,7 @@ impl Environment {
                                         .into_string()
                                         .unwrap(),
                                     startup_check,
+                                    key_history,
                                 },
                             )
                         }
@@ -1481,6 +1796,15 @@ impl Environment {
                             .parse::<u64>()
                             .expect("Provided value cannot be converted to number"),
                     ),
+                    context_scrub_interval: args
+                        .value_of("context-scrub-interval-in-secs")
+                        .map(|value| {
+                            value
+                                .parse::<u64>()
+                                .expect("Provided value cannot be converted to number")
+                        })
+                        .filter(|secs| *secs > 0)
+                        .map(Duration::from_secs),
                 }
             },
             identity: crate::configuration::Identity {
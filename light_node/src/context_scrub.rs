@@ -0,0 +1,93 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Background verification of the TezEdge on-disk context.
+//!
+//! The context is opened once in read mode, next to the protocol runner writing
+//! it, and reloaded on each run to verify its most recent commits with `tezos_context::scrub`.
+
+use std::time::{Duration, Instant};
+
+use slog::{crit, info, warn, Logger};
+use tezos_context::{scrub, snapshot, Persistent};
+
+/// Number of commits verified on each run, starting from the last one
+const SCRUB_COMMITS_COUNT: usize = 10;
+
+/// Lowest priority for the scrub thread
+#[cfg(target_os = "linux")]
+const SCRUB_THREAD_NICE: nix::libc::c_int = 19;
+
+/// Spawn a low priority thread scrubbing the context at `context_path` every `interval`
+pub fn spawn_context_scrub(context_path: String, interval: Duration, log: Logger) {
+    let result = std::thread::Builder::new()
+        .name("ctx-scrub".to_string())
+        .spawn(move || {
+            lower_thread_priority(&log);
+
+            let mut repository = None;
+            loop {
+                std::thread::sleep(interval);
+                scrub_once(&context_path, &mut repository, &log);
+            }
+        });
+
+    if let Err(e) = result {
+        warn!(log, "Failed to spawn context scrub thread"; "reason" => format!("{:?}", e));
+    }
+}
+
+/// Scrub the context `repository`, it is opened on the first run
+fn scrub_once(context_path: &str, repository: &mut Option<Persistent>, log: &Logger) {
+    let now = Instant::now();
+
+    let repository = match repository {
+        Some(repository) => {
+            if let Err(e) = repository.reload_database() {
+                warn!(log, "Context scrub: failed to reload the context"; "context_path" => context_path, "reason" => format!("{:?}", e));
+                return;
+            }
+            repository
+        }
+        None => match snapshot::reload_context_readonly(context_path.to_string()) {
+            Ok(opened) => repository.insert(opened),
+            Err(e) => {
+                warn!(log, "Context scrub: failed to open the context"; "context_path" => context_path, "reason" => format!("{:?}", e));
+                return;
+            }
+        },
+    };
+
+    match scrub::scrub_context(repository, SCRUB_COMMITS_COUNT, |_| {}) {
+        Ok(report) => match report.corruption {
+            Some(corruption) => {
+                crit!(log, "Context scrub: corrupted context found";
+                           "context_path" => context_path,
+                           "corruption" => corruption.to_string());
+            }
+            None => {
+                info!(log, "Context scrub: context is valid";
+                           "commits" => report.commits_checked,
+                           "objects" => report.objects_checked,
+                           "elapsed" => format!("{:?}", now.elapsed()));
+            }
+        },
+        Err(e) => {
+            warn!(log, "Context scrub: failed to scrub the context"; "context_path" => context_path, "reason" => format!("{:?}", e));
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn lower_thread_priority(log: &Logger) {
+    // On Linux, the nice value is a per-thread attribute: this only affects the scrub thread
+    let result = unsafe { nix::libc::setpriority(nix::libc::PRIO_PROCESS, 0, SCRUB_THREAD_NICE) };
+
+    if result != 0 {
+        warn!(log, "Context scrub: failed to lower the thread priority";
+                   "reason" => format!("{:?}", std::io::Error::last_os_error()));
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn lower_thread_priority(_log: &Logger) {}
@@ -45,8 +45,12 @@ use storage::database::tezedge_database::TezedgeDatabaseBackendConfiguration;
 use storage::initializer::initialize_maindb;
 
 mod configuration;
+mod context_scrub;
 mod identity;
+mod maindb_migration_command;
 mod notification_integration;
+mod shell_automaton_recording_command;
+mod shell_automaton_replay_command;
 mod snapshot_command;
 mod system;
 
@@ -118,6 +122,23 @@ fn block_on_actors(
     let (context_init_status_sender, context_init_status_receiver) =
         tokio::sync::watch::channel(false);
     let protocol_runner_configuration = create_protocol_runner_configuration(&env);
+
+    if let Some(interval) = env.storage.context_scrub_interval {
+        match env
+            .storage
+            .context_storage_configuration
+            .get_tezedge_on_disk_path()
+        {
+            Some(context_path) => {
+                context_scrub::spawn_context_scrub(context_path, interval, log.clone())
+            }
+            None => warn!(
+                log,
+                "Context scrub is only supported with the TezEdge on-disk context"
+            ),
+        }
+    }
+
     let tezos_protocol_api = ProtocolRunnerApi::new(
         protocol_runner_configuration.clone(),
         context_init_status_receiver,
@@ -530,6 +551,13 @@ fn main() {
                 println!("Snapshot imported successfully!");
             }
         }
+        TezedgeEnv::MigrateMainDb(env) => maindb_migration_command::migrate_maindb(&env),
+        TezedgeEnv::CheckShellAutomatonReplay(env) => {
+            shell_automaton_replay_command::check_shell_automaton_replay(&env)
+        }
+        TezedgeEnv::ImportShellAutomatonRecording(env) => {
+            shell_automaton_recording_command::import_shell_automaton_recording(&env)
+        }
         TezedgeEnv::Normal(env) => {
             // Creates loggers
             let log = match env.create_logger() {
@@ -659,6 +687,23 @@ fn parse_environment() -> configuration::TezedgeEnv {
                 return configuration::TezedgeEnv::ImportSnapshot(snapshot_import_env);
             }
         }
+        if subcommand.eq(&OsString::from("migrate-maindb")) {
+            if let Some(migrate_env) = crate::configuration::MigrateMainDb::from_args() {
+                return configuration::TezedgeEnv::MigrateMainDb(migrate_env);
+            }
+        }
+        if subcommand.eq(&OsString::from("check-shell-automaton-replay")) {
+            if let Some(replay_env) = crate::configuration::CheckShellAutomatonReplay::from_args() {
+                return configuration::TezedgeEnv::CheckShellAutomatonReplay(replay_env);
+            }
+        }
+        if subcommand.eq(&OsString::from("import-shell-automaton-recording")) {
+            if let Some(import_env) =
+                crate::configuration::ImportShellAutomatonRecording::from_args()
+            {
+                return configuration::TezedgeEnv::ImportShellAutomatonRecording(import_env);
+            }
+        }
     }
     configuration::TezedgeEnv::Normal(crate::configuration::Environment::from_args())
 }
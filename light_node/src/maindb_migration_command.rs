@@ -0,0 +1,152 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Main database migration command. Copies every column of the main database
+//! to a new database using another backend, then verifies the copy.
+//!
+//! Example:
+//!
+//! ```
+//! ./target/release/light-node migrate-maindb \
+//!     --source-backend rocksdb \
+//!     --source-path /path/to/tezos-data-dir/bootstrap_db/db \
+//!     --target-backend edgekv \
+//!     --target-path /path/to/new/db
+//! ```
+//!
+//! Then start the node with `--maindb-backend edgekv`, with the new database in place of the old one.
+
+use std::time::Instant;
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use storage::database::migration::{
+    migrate_main_db, verify_main_db, MigrationDatabase, MIGRATION_PROGRESS_FILE_NAME,
+};
+
+use crate::configuration::MigrateMainDb;
+
+pub fn migrate_maindb(env: &MigrateMainDb) {
+    if env.source_path == env.target_path {
+        panic!("Source and target main database directories must be different");
+    }
+
+    fs_extra::dir::create_all(&env.target_path, false).unwrap_or_else(|_| {
+        panic!(
+            "Failed to create directory at path {}",
+            env.target_path.display()
+        )
+    });
+
+    let source = MigrationDatabase::open(env.source_backend, &env.source_path)
+        .unwrap_or_else(|e| panic!("Failed to open the source main database, reason: {}", e));
+    let target = MigrationDatabase::open(env.target_backend, &env.target_path)
+        .unwrap_or_else(|e| panic!("Failed to open the target main database, reason: {}", e));
+
+    let progress_path = env.target_path.join(MIGRATION_PROGRESS_FILE_NAME);
+    if progress_path.exists() {
+        println!(
+            "Resuming the interrupted migration from {}",
+            progress_path.display()
+        );
+    }
+
+    let instant = Instant::now();
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(
+        ProgressStyle::default_spinner().template("{spinner:.green} [{elapsed_precise}] {msg}"),
+    );
+
+    let reports = migrate_main_db(&source, &target, &progress_path, |column, copied| {
+        pb.set_message(&format!("Copying {}: {} entries", column, copied));
+        pb.tick();
+    })
+    .unwrap_or_else(|e| panic!("Failed to migrate the main database, reason: {}", e));
+
+    pb.finish_with_message(&format!(
+        "Copied the main database in {:?}",
+        instant.elapsed()
+    ));
+    for report in &reports {
+        println!(
+            "  {}: {} entries ({} copied by this run)",
+            report.column, report.copied, report.copied_now
+        );
+    }
+
+    println!(
+        "Verifying the copy, comparing one value out of {}...",
+        env.verify_sample_rate
+    );
+    let reports = verify_main_db(&source, &target, env.verify_sample_rate)
+        .unwrap_or_else(|e| panic!("Verification of the main database failed, reason: {}", e));
+    for report in &reports {
+        println!(
+            "  {}: {} entries, {} values compared",
+            report.column, report.entries, report.sampled
+        );
+    }
+
+    println!(
+        "Main database migrated successfully to {}",
+        env.target_path.display()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use storage::database::tezedge_database::TezedgeDatabaseBackendConfiguration;
+    use storage::persistent::main_db_columns;
+
+    use super::*;
+
+    fn env(dir: &str) -> MigrateMainDb {
+        MigrateMainDb {
+            source_backend: TezedgeDatabaseBackendConfiguration::Sled,
+            source_path: Path::new(dir).join("source"),
+            target_backend: TezedgeDatabaseBackendConfiguration::EdgeKV,
+            target_path: Path::new(dir).join("target"),
+            verify_sample_rate: 1,
+        }
+    }
+
+    #[test]
+    fn test_migrate_maindb() {
+        let dir = "./testdir/maindb_migration";
+        if Path::new(dir).exists() {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+        let env = env(dir);
+        let column = main_db_columns()[0];
+
+        std::fs::create_dir_all(&env.source_path).unwrap();
+        let source = MigrationDatabase::open(env.source_backend, &env.source_path).unwrap();
+        source
+            .backend()
+            .write_batch(column, vec![(vec![1], vec![10]), (vec![2], vec![20])])
+            .unwrap();
+        source.backend().flush().unwrap();
+        drop(source);
+
+        migrate_maindb(&env);
+        // running it again resumes a finished migration
+        migrate_maindb(&env);
+
+        let target = MigrationDatabase::open(env.target_backend, &env.target_path).unwrap();
+        assert_eq!(target.backend().get(column, &[1]).unwrap(), Some(vec![10]));
+        assert_eq!(target.backend().get(column, &[2]).unwrap(), Some(vec![20]));
+        assert!(env.target_path.join(MIGRATION_PROGRESS_FILE_NAME).exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "must be different")]
+    fn test_same_source_and_target_is_rejected() {
+        let mut env = env("./testdir/maindb_migration_same");
+        env.target_path = env.source_path.clone();
+        migrate_maindb(&env);
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Import of a shell automaton recording. Writes the actions and state snapshots
+//! recorded to files by a node running with `--record-shell-automaton-dir` into a
+//! main database, where `check-shell-automaton-replay` and the dev RPCs read them.
+//!
+//! Example:
+//!
+//! ```
+//! ./target/release/light-node import-shell-automaton-recording \
+//!     --from /path/to/recording \
+//!     --db-path /tmp/recording-db
+//! ```
+
+use std::time::Instant;
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use shell_automaton::service::recording::{
+    recording_files, Record, RecordingError, RecordingReader,
+};
+use storage::rocksdb::Cache;
+use storage::{ShellAutomatonActionStorage, ShellAutomatonStateStorage};
+
+use crate::configuration::ImportShellAutomatonRecording;
+use crate::shell_automaton_replay_command::{open_main_db, REPLAY_ROCKSDB_CACHE_SIZE};
+
+pub fn import_shell_automaton_recording(env: &ImportShellAutomatonRecording) {
+    let files = recording_files(&env.from)
+        .unwrap_or_else(|e| panic!("Failed to list the recording files, reason: {}", e));
+    if files.is_empty() {
+        panic!("No recording files in {}", env.from.display());
+    }
+
+    let log = slog::Logger::root(slog::Discard, slog::o!());
+    // IMPORTANT: Cache object must live at least as long as DB
+    let cache = Cache::new_lru_cache(REPLAY_ROCKSDB_CACHE_SIZE)
+        .expect("Failed to initialize RocksDB cache");
+    let db = open_main_db(env.maindb_backend, &env.db_path, &cache, log)
+        .unwrap_or_else(|e| panic!("Failed to open the main database, reason: {}", e));
+
+    let snapshot_storage = ShellAutomatonStateStorage::from_kv(db.clone());
+    let action_storage = ShellAutomatonActionStorage::from_kv(db);
+
+    let instant = Instant::now();
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(
+        ProgressStyle::default_spinner().template("{spinner:.green} [{elapsed_precise}] {msg}"),
+    );
+
+    let mut actions = 0_u64;
+    let mut snapshots = 0_u64;
+    for path in &files {
+        let reader = RecordingReader::open(path)
+            .unwrap_or_else(|e| panic!("Failed to open {}, reason: {}", path.display(), e));
+        for record in reader {
+            let result = match record {
+                Ok(Record::Action { action_id, action }) => {
+                    actions += 1;
+                    action_storage.put(&action_id, &action)
+                }
+                Ok(Record::StateSnapshot { action_id, state }) => {
+                    snapshots += 1;
+                    snapshot_storage.put(&action_id, &state)
+                }
+                Err(RecordingError::Truncated { path }) => {
+                    pb.println(format!(
+                        "{} is truncated, the records after the last state snapshot in it are lost",
+                        path.display()
+                    ));
+                    continue;
+                }
+                Err(e) => panic!("Failed to read {}, reason: {}", path.display(), e),
+            };
+            result.unwrap_or_else(|e| panic!("Failed to write the record, reason: {}", e));
+        }
+        pb.set_message(&format!(
+            "Imported {} actions and {} state snapshots",
+            actions, snapshots
+        ));
+        pb.tick();
+    }
+
+    pb.finish_with_message(&format!(
+        "Imported {} actions and {} state snapshots from {} files in {:?}",
+        actions,
+        snapshots,
+        files.len(),
+        instant.elapsed()
+    ));
+}
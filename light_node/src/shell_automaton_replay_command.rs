@@ -0,0 +1,353 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Shell automaton replay check. Replays the actions recorded by a node with the
+//! current reducer, starting from a recorded state snapshot, and compares the
+//! recomputed state with every later snapshot, to verify reducer changes against
+//! real recordings.
+//!
+//! Example:
+//!
+//! ```
+//! ./target/release/light-node check-shell-automaton-replay \
+//!     --db-path /path/to/tezos-data-dir/bootstrap_db/db \
+//!     --from-action-id 1646301785223170000
+//! ```
+//!
+//! The node must have been running with `--record-shell-automaton-actions` and
+//! `--record-shell-automaton-state-snapshots`.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::Value;
+
+use shell_automaton::{Action, ActionId, ActionWithMeta, State};
+use storage::database::edgekv_backend::EdgeKVBackend;
+use storage::database::rockdb_backend::RocksDBBackend;
+use storage::database::sled_backend::SledDBBackend;
+use storage::database::tezedge_database::{
+    TezedgeDatabase, TezedgeDatabaseBackendConfiguration, TezedgeDatabaseBackendOptions,
+};
+use storage::initializer::{DbsRocksDbTableInitializer, RocksDbConfig};
+use storage::persistent::{main_db_columns, Decoder};
+use storage::rocksdb::Cache;
+use storage::{Direction, IteratorMode, ShellAutomatonActionStorage, ShellAutomatonStateStorage};
+
+use crate::configuration::CheckShellAutomatonReplay;
+
+/// Block cache used when the main database is RocksDB
+pub(crate) const REPLAY_ROCKSDB_CACHE_SIZE: usize = 128 * 1024 * 1024;
+
+/// Longest json printed for each side of a difference
+const DIFFERENCE_MAX_LEN: usize = 1000;
+
+pub fn check_shell_automaton_replay(env: &CheckShellAutomatonReplay) {
+    let log = slog::Logger::root(slog::Discard, slog::o!());
+    // IMPORTANT: Cache object must live at least as long as DB
+    let cache = Cache::new_lru_cache(REPLAY_ROCKSDB_CACHE_SIZE)
+        .expect("Failed to initialize RocksDB cache");
+    let db = open_main_db(env.maindb_backend, &env.db_path, &cache, log)
+        .unwrap_or_else(|e| panic!("Failed to open the main database, reason: {}", e));
+
+    let snapshot_storage = ShellAutomatonStateStorage::from_kv(db.clone());
+    let action_storage = ShellAutomatonActionStorage::from_kv(db);
+
+    let snapshot = match env.from_action_id {
+        Some(action_id) => snapshot_storage.get_closest_before::<State>(&action_id),
+        None => snapshot_storage.get_closest_after::<State>(&0),
+    };
+    let mut state = match snapshot {
+        Ok(Some(state)) => state,
+        Ok(None) => panic!("No shell automaton state snapshot recorded to start from"),
+        Err(e) => panic!("Failed to read the state snapshot, reason: {}", e),
+    };
+    let mut checked_action_id = u64::from(state.last_action.id());
+    println!(
+        "Replaying the actions after the state snapshot at action {}",
+        checked_action_id
+    );
+
+    let instant = Instant::now();
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(
+        ProgressStyle::default_spinner().template("{spinner:.green} [{elapsed_precise}] {msg}"),
+    );
+
+    let actions = action_storage
+        .find(IteratorMode::From(
+            Cow::Owned(checked_action_id),
+            Direction::Forward,
+        ))
+        .unwrap_or_else(|e| panic!("Failed to read the recorded actions, reason: {}", e));
+
+    let mut replayed = 0_u64;
+    let mut checked = 0_u64;
+    for result in actions {
+        let (key, value) =
+            result.unwrap_or_else(|e| panic!("Failed to read the action, reason: {}", e));
+        let action_id = u64::decode(&key)
+            .unwrap_or_else(|e| panic!("Failed to decode the action id, reason: {}", e));
+        if action_id <= u64::from(state.last_action.id()) {
+            continue;
+        }
+        if matches!(env.to_action_id, Some(to) if action_id > to) {
+            break;
+        }
+        let action = ActionWithMeta {
+            id: ActionId::new_unchecked(action_id),
+            // not included in the storage
+            depth: 0,
+            action: Action::decode(&value).unwrap_or_else(|e| {
+                panic!("Failed to decode the action {}, reason: {}", action_id, e)
+            }),
+        };
+        shell_automaton::reducer(&mut state, &action);
+        replayed += 1;
+
+        let recorded = snapshot_storage
+            .get::<State>(&action_id)
+            .unwrap_or_else(|e| panic!("Failed to read the state snapshot, reason: {}", e));
+        if let Some(recorded) = recorded {
+            checked += 1;
+            if let Some(difference) = state_difference(&recorded, &state) {
+                pb.finish_and_clear();
+                // the previous snapshot matched, so the divergence comes from the actions since
+                println!(
+                    "State diverges at the snapshot after action {} ({:?}), the divergent action is one of the actions {} to {}",
+                    action_id,
+                    action.action.kind(),
+                    checked_action_id + 1,
+                    action_id
+                );
+                println!("{}", difference);
+                std::process::exit(1);
+            }
+            checked_action_id = action_id;
+        }
+
+        if replayed % 10_000 == 0 {
+            pb.set_message(&format!(
+                "Replayed {} actions, checked {} snapshots",
+                replayed, checked
+            ));
+            pb.tick();
+        }
+    }
+
+    pb.finish_with_message(&format!(
+        "Replayed {} actions in {:?}",
+        replayed,
+        instant.elapsed()
+    ));
+    println!(
+        "No divergence, {} state snapshots checked, the last one at action {}",
+        checked, checked_action_id
+    );
+}
+
+/// Opens the main database directly on its backend, without the checks of the node
+pub(crate) fn open_main_db(
+    maindb_backend: TezedgeDatabaseBackendConfiguration,
+    db_path: &Path,
+    cache: &Cache,
+    log: slog::Logger,
+) -> Result<Arc<TezedgeDatabase>, storage::database::error::Error> {
+    let backend = match maindb_backend {
+        TezedgeDatabaseBackendConfiguration::Sled => {
+            TezedgeDatabaseBackendOptions::SledDB(SledDBBackend::new(db_path)?)
+        }
+        TezedgeDatabaseBackendConfiguration::EdgeKV => {
+            TezedgeDatabaseBackendOptions::EdgeKV(EdgeKVBackend::new(db_path, main_db_columns())?)
+        }
+        TezedgeDatabaseBackendConfiguration::RocksDB => {
+            TezedgeDatabaseBackendOptions::RocksDB(RocksDBBackend::new(
+                cache,
+                &RocksDbConfig {
+                    cache_size: REPLAY_ROCKSDB_CACHE_SIZE,
+                    expected_db_version: 0,
+                    db_path: db_path.to_path_buf(),
+                    columns: DbsRocksDbTableInitializer,
+                    threads: None,
+                },
+            )?)
+        }
+    };
+    Ok(Arc::new(TezedgeDatabase::new(backend, log)))
+}
+
+/// First value which differs between the recorded and the recomputed state
+struct StateDifference {
+    path: Vec<PathSegment>,
+    recorded: Option<Value>,
+    recomputed: Option<Value>,
+}
+
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl fmt::Display for StateDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = |value: &Option<Value>| match value {
+            Some(value) => {
+                let mut json = value.to_string();
+                if json.len() > DIFFERENCE_MAX_LEN {
+                    let end = (0..=DIFFERENCE_MAX_LEN)
+                        .rev()
+                        .find(|i| json.is_char_boundary(*i))
+                        .unwrap_or(0);
+                    json.truncate(end);
+                    json.push_str("...");
+                }
+                json
+            }
+            None => "<missing>".to_owned(),
+        };
+        write!(f, "  at state")?;
+        for segment in &self.path {
+            match segment {
+                PathSegment::Key(key) => write!(f, ".{}", key)?,
+                PathSegment::Index(i) => write!(f, "[{}]", i)?,
+            }
+        }
+        writeln!(f)?;
+        writeln!(f, "  recorded:   {}", json(&self.recorded))?;
+        write!(f, "  recomputed: {}", json(&self.recomputed))
+    }
+}
+
+fn state_difference(recorded: &State, recomputed: &State) -> Option<StateDifference> {
+    let to_json = |state| {
+        serde_json::to_value(state)
+            .unwrap_or_else(|e| panic!("Failed to serialize the state, reason: {}", e))
+    };
+    json_difference(&to_json(recorded), &to_json(recomputed))
+}
+
+fn json_difference(recorded: &Value, recomputed: &Value) -> Option<StateDifference> {
+    let mut path = vec![];
+    if !value_differs(&mut path, recorded, recomputed) {
+        return None;
+    }
+    Some(StateDifference {
+        recorded: value_at(recorded, &path).cloned(),
+        recomputed: value_at(recomputed, &path).cloned(),
+        path,
+    })
+}
+
+/// Leaves in `path` the first path where the values differ
+fn value_differs(path: &mut Vec<PathSegment>, recorded: &Value, recomputed: &Value) -> bool {
+    match (recorded, recomputed) {
+        (Value::Object(recorded), Value::Object(recomputed)) => {
+            for (key, value) in recorded {
+                path.push(PathSegment::Key(key.clone()));
+                match recomputed.get(key) {
+                    Some(other) if !value_differs(path, value, other) => (),
+                    _ => return true,
+                }
+                path.pop();
+            }
+            match recomputed.keys().find(|key| !recorded.contains_key(*key)) {
+                Some(key) => {
+                    path.push(PathSegment::Key(key.clone()));
+                    true
+                }
+                None => false,
+            }
+        }
+        (Value::Array(recorded), Value::Array(recomputed)) => {
+            for (i, (value, other)) in recorded.iter().zip(recomputed).enumerate() {
+                path.push(PathSegment::Index(i));
+                if value_differs(path, value, other) {
+                    return true;
+                }
+                path.pop();
+            }
+            if recorded.len() != recomputed.len() {
+                path.push(PathSegment::Index(recorded.len().min(recomputed.len())));
+                return true;
+            }
+            false
+        }
+        (recorded, recomputed) => recorded != recomputed,
+    }
+}
+
+fn value_at<'a>(value: &'a Value, path: &[PathSegment]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, segment| match segment {
+        PathSegment::Key(key) => value.get(key),
+        PathSegment::Index(i) => value.get(*i),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn difference(recorded: Value, recomputed: Value) -> Option<String> {
+        json_difference(&recorded, &recomputed).map(|difference| difference.to_string())
+    }
+
+    #[test]
+    fn same_values() {
+        let state = json!({ "peers": [{ "address": "1.2.3.4:9732" }], "level": 10 });
+        assert!(difference(state.clone(), state).is_none());
+    }
+
+    #[test]
+    fn object_key_mismatch() {
+        assert_eq!(
+            difference(json!({ "a": 1, "b": 2 }), json!({ "a": 1 })).unwrap(),
+            "  at state.b\n  recorded:   2\n  recomputed: <missing>"
+        );
+        assert_eq!(
+            difference(json!({ "a": 1 }), json!({ "a": 1, "c": 3 })).unwrap(),
+            "  at state.c\n  recorded:   <missing>\n  recomputed: 3"
+        );
+    }
+
+    #[test]
+    fn array_length_mismatch() {
+        assert_eq!(
+            difference(json!([1, 2]), json!([1, 2, 3])).unwrap(),
+            "  at state[2]\n  recorded:   <missing>\n  recomputed: 3"
+        );
+        assert_eq!(
+            difference(json!([1, 2]), json!([1])).unwrap(),
+            "  at state[1]\n  recorded:   2\n  recomputed: <missing>"
+        );
+    }
+
+    #[test]
+    fn nested_path() {
+        let recorded = json!({ "peers": { "list": [{ "level": 1 }, { "level": 2 }] } });
+        let recomputed = json!({ "peers": { "list": [{ "level": 1 }, { "level": 3 }] } });
+        assert_eq!(
+            difference(recorded, recomputed).unwrap(),
+            "  at state.peers.list[1].level\n  recorded:   2\n  recomputed: 3"
+        );
+    }
+
+    #[test]
+    fn first_difference_only() {
+        let mut path = vec![];
+        assert!(value_differs(
+            &mut path,
+            &json!({ "a": [0, { "b": true }], "z": 1 }),
+            &json!({ "a": [0, { "b": false }], "z": 2 }),
+        ));
+        assert!(matches!(
+            path.as_slice(),
+            [PathSegment::Key(a), PathSegment::Index(1), PathSegment::Key(b)] if a == "a" && b == "b"
+        ));
+    }
+}
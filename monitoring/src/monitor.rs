@@ -81,17 +81,21 @@ impl Monitor {
             }
         }
 
-        if let Some(monitor) = self.peer_monitors.get_mut(&msg.peer_address) {
+        let size = if let Some(size_hint) = msg.message.size_hint() {
+            *size_hint
+        } else {
+            debug!(log, "size_hint not available for received peer message"; "peer" => msg.peer_address.to_string());
+            size_of_val(&msg.message)
+        };
+        self.process_peer_incoming_bytes(msg.peer_address, size, log);
+    }
+
+    fn process_peer_incoming_bytes(&mut self, peer_address: SocketAddr, size: usize, log: &Logger) {
+        if let Some(monitor) = self.peer_monitors.get_mut(&peer_address) {
             // TODO: TE-190 - reimplement correctly, now not all messages are counted in (Ack, Metadata, ConnectionMessage is not involved)
-            let size = if let Some(size_hint) = msg.message.size_hint() {
-                *size_hint
-            } else {
-                debug!(log, "size_hint not available for received peer message"; "peer" => msg.peer_address.to_string());
-                size_of_val(&msg.message)
-            };
             monitor.incoming_bytes(size);
         } else {
-            debug!(log, "Missing monitor for peer"; "peer" => msg.peer_address.to_string());
+            debug!(log, "Missing monitor for peer"; "peer" => peer_address.to_string());
         }
     }
 
@@ -228,6 +232,9 @@ impl Receive<NetworkChannelMsg> for Monitor {
             NetworkChannelMsg::PeerMessageReceived(msg) => {
                 self.process_peer_message(msg, &ctx.system.log())
             }
+            NetworkChannelMsg::PeerMessageSkipped(msg) => {
+                self.process_peer_incoming_bytes(msg.peer_address, msg.size, &ctx.system.log())
+            }
             NetworkChannelMsg::PeerDisconnected(peer) => {
                 if self.peer_monitors.remove(&peer).is_some() {
                     ctx.myself.tell(
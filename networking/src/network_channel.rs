@@ -23,6 +23,14 @@ pub struct PeerMessageReceived {
     pub message: Arc<PeerMessageResponse>,
 }
 
+/// We have received message from another peer, but it wasn't needed so it was dropped
+#[derive(Clone, Debug)]
+pub struct PeerMessageSkipped {
+    pub peer_address: SocketAddr,
+    /// Size of the encoded message.
+    pub size: usize,
+}
+
 pub type NewCurrentHeadNotificationRef = Arc<NewCurrentHeadNotification>;
 
 #[derive(Debug)]
@@ -69,6 +77,7 @@ pub enum NetworkChannelMsg {
     PeerBootstrapped(Arc<PeerId>, MetadataMessage, Arc<NetworkVersion>),
     PeerDisconnected(SocketAddr),
     PeerMessageReceived(PeerMessageReceived),
+    PeerMessageSkipped(PeerMessageSkipped),
 
     NewCurrentHead(NewCurrentHeadNotificationRef),
     BlockReceived(BlockReceived),
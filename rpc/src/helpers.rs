@@ -19,7 +19,8 @@ use shell_integration::{Prevalidator, WorkerStatus, WorkerStatusPhase};
 use storage::chain_meta_storage::ChainMetaStorageReader;
 use storage::{
     BlockAdditionalData, BlockHeaderWithHash, BlockJsonData, BlockMetaStorage,
-    BlockMetaStorageReader, BlockStorage, BlockStorageReader, ChainMetaStorage, StorageError,
+    BlockMetaStorageReader, BlockStorage, BlockStorageReader, ChainMetaStorage, PersistentStorage,
+    StorageError,
 };
 use tezos_api::ffi::{RpcMethod, RpcRequest};
 use tezos_messages::p2p::binary_message::MessageHashError;
@@ -719,6 +720,16 @@ pub fn parse_block_hash(
     Ok(block_hash)
 }
 
+/// Point-in-time view of the storage, so the parts of a block response read from
+/// different columns are consistent even if the block is being stored meanwhile
+pub(crate) fn read_snapshot(
+    env: &RpcServiceEnvironment,
+) -> Result<PersistentStorage, RpcServiceError> {
+    env.persistent_storage()
+        .snapshot()
+        .map_err(|error| RpcServiceError::StorageError { error })
+}
+
 pub(crate) async fn create_rpc_request(req: Request<Body>) -> Result<RpcRequest, anyhow::Error> {
     let context_path = req.uri().path_and_query().unwrap().as_str().to_string();
     let meth = RpcMethod::try_from(req.method().to_string().as_str()).unwrap(); // TODO: handle correctly
@@ -73,6 +73,24 @@ pub fn make_raw_response(raw: &'static [u8]) -> ServiceResult {
         .body(Body::from(raw))?)
 }
 
+/// Produces a response in the Prometheus text exposition format
+pub fn make_prometheus_response(text: String) -> ServiceResult {
+    Ok(Response::builder()
+        .header(hyper::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .header(hyper::header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(hyper::header::ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type")
+        .header(hyper::header::ACCESS_CONTROL_ALLOW_HEADERS, "content-type")
+        .header(
+            hyper::header::ACCESS_CONTROL_ALLOW_HEADERS,
+            "x-requested-with",
+        )
+        .header(
+            hyper::header::ACCESS_CONTROL_ALLOW_METHODS,
+            "GET, POST, OPTIONS, PUT",
+        )
+        .body(Body::from(text))?)
+}
+
 /// Produces a JSON response from an FFI RPC response
 pub fn make_response_with_status_and_json_string(status_code: u16, body: &str) -> ServiceResult {
     Ok(Response::builder()
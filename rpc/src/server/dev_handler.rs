@@ -265,6 +265,56 @@ pub async fn cycle_eras(
     )
 }
 
+pub async fn context_diff(
+    _: Request<Body>,
+    params: Params,
+    query: Query,
+    env: Arc<RpcServiceEnvironment>,
+) -> ServiceResult {
+    let chain_id = parse_chain_id(required_param!(params, "chain_id")?, &env)?;
+    let block_hash = parse_block_hash(&chain_id, required_param!(params, "block_id")?, &env)
+        .map_err(|e| format_err!("Failed to parse_block_hash, reason: {}", e))?;
+    let with_block_id = query
+        .get_str("with")
+        .ok_or_else(|| format_err!("Missing mandatory query parameter `with`"))?;
+    let with_block_hash = parse_block_hash(&chain_id, with_block_id, &env)
+        .map_err(|e| format_err!("Failed to parse_block_hash, reason: {}", e))?;
+
+    result_to_json_response(
+        dev_services::get_context_diff(
+            &chain_id,
+            &block_hash,
+            &with_block_hash,
+            query.get_str("after"),
+            query.get_usize("limit"),
+            query.get_str("values") == Some("true"),
+            &env,
+        )
+        .await,
+        env.log(),
+    )
+}
+
+pub async fn context_key_history(
+    _: Request<Body>,
+    params: Params,
+    query: Query,
+    env: Arc<RpcServiceEnvironment>,
+) -> ServiceResult {
+    // only validated, the key history is recorded for the main chain
+    parse_chain_id(required_param!(params, "chain_id")?, &env)?;
+    let key = query
+        .get_str("key")
+        .ok_or_else(|| format_err!("Missing mandatory query parameter `key`"))?;
+    let from_level = query.get_u64("from_level").unwrap_or(0);
+    let to_level = query.get_u64("to_level").unwrap_or_else(|| u32::MAX.into());
+
+    result_to_json_response(
+        dev_services::get_context_key_history(key, from_level, to_level, &env).await,
+        env.log(),
+    )
+}
+
 /// Get the version string
 pub async fn dev_version(
     _: Request<Body>,
@@ -827,3 +877,17 @@ pub async fn patch_bakers(
 
     make_json_response(&dev_services::patch_bakers(patch, &env).await?)
 }
+
+pub async fn patch_graylist(
+    req: Request<Body>,
+    _: Params,
+    _: Query,
+    env: Arc<RpcServiceEnvironment>,
+) -> ServiceResult {
+    use shell_automaton::service::rpc_service::GraylistPatch;
+    let body = req.into_body();
+    let body_bytes = body::to_bytes(body).await?;
+    let patch = serde_json::from_str::<GraylistPatch>(std::str::from_utf8(&body_bytes)?)?;
+
+    make_json_response(&dev_services::patch_graylist(patch, &env).await?)
+}
@@ -0,0 +1,22 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::sync::Arc;
+
+use hyper::{Body, Request};
+
+use crate::server::{Params, Query, RpcServiceEnvironment};
+use crate::services::metrics_services;
+use crate::{error, make_prometheus_response, ServiceResult};
+
+pub async fn metrics(
+    _: Request<Body>,
+    _: Params,
+    _: Query,
+    env: Arc<RpcServiceEnvironment>,
+) -> ServiceResult {
+    match metrics_services::get_metrics(&env).await {
+        Ok(text) => make_prometheus_response(text),
+        Err(e) => error(e),
+    }
+}
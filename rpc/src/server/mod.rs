@@ -34,6 +34,7 @@ use url::Url;
 use crate::{error_with_message, not_found, options};
 
 mod dev_handler;
+mod metrics_handler;
 mod openapi_handler;
 mod protocol_handler;
 pub mod router;
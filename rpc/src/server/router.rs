@@ -8,7 +8,9 @@ use std::sync::Arc;
 use hyper::{Body, Method, Request};
 use path_tree::PathTree;
 
-use crate::server::{dev_handler, openapi_handler, protocol_handler, shell_handler};
+use crate::server::{
+    dev_handler, metrics_handler, openapi_handler, protocol_handler, shell_handler,
+};
 use crate::server::{HResult, MethodHandler, Params, Query, RpcServiceEnvironment};
 
 macro_rules! hash_set {
@@ -290,6 +292,16 @@ pub fn create_routes(tezedge_is_enabled: bool, allow_unsafe: bool) -> PathTree<M
         "/dev/chains/:chain_id/blocks/:block_id/cycle_eras",
         dev_handler::cycle_eras,
     );
+    routes.handle(
+        hash_set![Method::GET],
+        "/dev/chains/:chain_id/blocks/:block_id/context/diff",
+        dev_handler::context_diff,
+    );
+    routes.handle(
+        hash_set![Method::GET],
+        "/dev/chains/:chain_id/context/history",
+        dev_handler::context_key_history,
+    );
     routes.handle(
         hash_set![Method::GET],
         "/dev/shell/automaton/state",
@@ -408,8 +420,14 @@ pub fn create_routes(tezedge_is_enabled: bool, allow_unsafe: bool) -> PathTree<M
             "/dev/shell/automaton/bakers",
             dev_handler::patch_bakers,
         );
+        routes.handle(
+            hash_set![Method::PATCH],
+            "/dev/shell/automaton/peers/graylist",
+            dev_handler::patch_graylist,
+        );
     }
 
+    routes.handle(hash_set![Method::GET], "/metrics", metrics_handler::metrics);
     routes.handle(
         hash_set![Method::GET],
         "/stats/memory",
@@ -14,7 +14,7 @@ use tezos_context_api::{context_key_owned, StringTreeObject};
 use tezos_messages::p2p::encoding::version::NetworkVersion;
 
 use crate::helpers::{
-    BlockHeaderInfo, BlockHeaderShellInfo, BlockInfo, BlockMetadata, BlockOperation,
+    read_snapshot, BlockHeaderInfo, BlockHeaderShellInfo, BlockInfo, BlockMetadata, BlockOperation,
     BlockOperations, BlockValidationPass, InnerBlockHeader, NodeVersion, Protocols,
     RpcServiceError,
 };
@@ -100,17 +100,16 @@ pub(crate) async fn get_block_metadata(
 ) -> Result<Arc<BlockMetadata>, RpcServiceError> {
     // TODO - TE-709: these two  sync calls, need to be wrapped in `tokio::task::spawn_blocking`
 
+    // all parts of the response are read from the same snapshot
+    let snapshot = read_snapshot(env)?;
+
     // header + jsons
     let block_header_with_json_data =
-        async { get_block_with_json_data(chain_id, block_hash, env.persistent_storage()) };
+        async { get_block_with_json_data(chain_id, block_hash, &snapshot) };
 
     // additional data
     let block_additional_data = async {
-        crate::services::base_services::get_additional_data_or_fail(
-            chain_id,
-            block_hash,
-            env.persistent_storage(),
-        )
+        crate::services::base_services::get_additional_data_or_fail(chain_id, block_hash, &snapshot)
     };
 
     // 1. wait for data to collect
@@ -399,9 +398,12 @@ pub(crate) async fn get_block_operations_metadata(
 ) -> Result<Arc<BlockOperations>, RpcServiceError> {
     // TODO - TE-709: these two  sync calls, need to be wrapped in `tokio::task::spawn_blocking`
 
+    // all parts of the response are read from the same snapshot
+    let snapshot = read_snapshot(env)?;
+
     // header + jsons
     let block_json_data = async {
-        match BlockStorage::new(env.persistent_storage()).get_json_data(block_hash) {
+        match BlockStorage::new(&snapshot).get_json_data(block_hash) {
             Ok(Some(data)) => Ok(data),
             Ok(None) => Err(RpcServiceError::NoDataFoundError {
                 reason: format!(
@@ -416,15 +418,13 @@ pub(crate) async fn get_block_operations_metadata(
     // additional data
     let block_additional_data = async {
         crate::services::base_services::get_additional_data_or_fail(
-            &chain_id,
-            block_hash,
-            env.persistent_storage(),
+            &chain_id, block_hash, &snapshot,
         )
     };
 
     // operations
     let operations = async {
-        OperationsStorage::new(env.persistent_storage())
+        OperationsStorage::new(&snapshot)
             .get_operations(block_hash)
             .map_err(|error| RpcServiceError::StorageError { error })
     };
@@ -565,22 +565,21 @@ pub async fn get_block(
 ) -> Result<Arc<BlockInfo>, RpcServiceError> {
     // TODO - TE-709: these two  sync calls, need to be wrapped in `tokio::task::spawn_blocking`
 
+    // all parts of the response are read from the same snapshot
+    let snapshot = read_snapshot(env)?;
+
     // header + jsons
     let block_header_with_json_data =
-        async { get_block_with_json_data(chain_id, block_hash, env.persistent_storage()) };
+        async { get_block_with_json_data(chain_id, block_hash, &snapshot) };
 
     // additional data
     let block_additional_data = async {
-        crate::services::base_services::get_additional_data_or_fail(
-            chain_id,
-            block_hash,
-            env.persistent_storage(),
-        )
+        crate::services::base_services::get_additional_data_or_fail(chain_id, block_hash, &snapshot)
     };
 
     // operations
     let operations = async {
-        OperationsStorage::new(env.persistent_storage())
+        OperationsStorage::new(&snapshot)
             .get_operations(block_hash)
             .map_err(|error| RpcServiceError::StorageError { error })
     };
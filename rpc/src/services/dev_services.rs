@@ -38,6 +38,10 @@ use storage::{
     ShellAutomatonStateStorage, StorageError,
 };
 //use tezos_context::channel::ContextAction;
+use tezos_context_api::{
+    ContextDiffEntry, ContextDiffRequest, ContextKeyHistoryEntry, ContextKeyOwned,
+    CONTEXT_DIFF_DEFAULT_LIMIT, CONTEXT_DIFF_MAX_LIMIT,
+};
 use tezos_messages::base::ConversionError;
 use tezos_messages::p2p::encoding::block_header::Level;
 
@@ -46,7 +50,9 @@ use crate::server::RpcServiceEnvironment;
 
 use crate::services::protocol::get_blocks_per_cycle;
 
-use super::base_services::{get_additional_data_or_fail, get_raw_block_header_with_hash};
+use super::base_services::{
+    get_additional_data_or_fail, get_context_hash, get_raw_block_header_with_hash,
+};
 
 pub type ContractAddress = Vec<u8>;
 
@@ -239,6 +245,164 @@ pub(crate) fn get_cycle_eras(
     }
 }
 
+/// One changed key of the context diff, values are hex encoded and only present when requested
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum ContextDiffEntryJson {
+    Added {
+        key: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        value: Option<String>,
+    },
+    Removed {
+        key: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        value: Option<String>,
+    },
+    Modified {
+        key: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        old_value: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        new_value: Option<String>,
+    },
+}
+
+impl From<ContextDiffEntry> for ContextDiffEntryJson {
+    fn from(entry: ContextDiffEntry) -> Self {
+        match entry {
+            ContextDiffEntry::Added { key, value } => Self::Added {
+                key: key.join("/"),
+                value: value.map(hex::encode),
+            },
+            ContextDiffEntry::Removed { key, value } => Self::Removed {
+                key: key.join("/"),
+                value: value.map(hex::encode),
+            },
+            ContextDiffEntry::Modified {
+                key,
+                old_value,
+                new_value,
+            } => Self::Modified {
+                key: key.join("/"),
+                old_value: old_value.map(hex::encode),
+                new_value: new_value.map(hex::encode),
+            },
+        }
+    }
+}
+
+/// Page of the context diff, `next` is the `after` parameter of the next page
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct ContextDiffJson {
+    entries: Vec<ContextDiffEntryJson>,
+    next: Option<String>,
+}
+
+/// Keys added, removed or modified in the context of `with_block_hash` compared to `block_hash`,
+/// at most `limit` of them (capped to `CONTEXT_DIFF_MAX_LIMIT`) sorted after the key `after`
+pub(crate) async fn get_context_diff(
+    chain_id: &ChainId,
+    block_hash: &BlockHash,
+    with_block_hash: &BlockHash,
+    after: Option<&str>,
+    limit: Option<usize>,
+    with_values: bool,
+    env: &RpcServiceEnvironment,
+) -> Result<ContextDiffJson, RpcServiceError> {
+    let from_context_hash = get_context_hash(chain_id, block_hash, env)?;
+    let to_context_hash = get_context_hash(chain_id, with_block_hash, env)?;
+
+    let request = ContextDiffRequest {
+        after: after.map(|key| key.split('/').map(str::to_owned).collect()),
+        limit: limit
+            .unwrap_or(CONTEXT_DIFF_DEFAULT_LIMIT)
+            .min(CONTEXT_DIFF_MAX_LIMIT),
+        with_values,
+    };
+    let diff = env
+        .tezedge_context()
+        .get_context_diff(&from_context_hash, &to_context_hash, request)
+        .await
+        .map_err(|e| RpcServiceError::UnexpectedError {
+            reason: format!("{}", e),
+        })?;
+
+    Ok(ContextDiffJson {
+        entries: diff
+            .entries
+            .into_iter()
+            .map(ContextDiffEntryJson::from)
+            .collect(),
+        next: diff.next.map(|key| key.join("/")),
+    })
+}
+
+/// Value of the context key after the block at `level` changed it, hex encoded
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct ContextKeyHistoryEntryJson {
+    level: u32,
+    /// `None` when the key was removed
+    value: Option<String>,
+}
+
+impl From<ContextKeyHistoryEntry> for ContextKeyHistoryEntryJson {
+    fn from(entry: ContextKeyHistoryEntry) -> Self {
+        Self {
+            level: entry.level,
+            value: entry.value.map(hex::encode),
+        }
+    }
+}
+
+/// Values of a context key over a range of levels
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct ContextKeyHistoryJson {
+    entries: Vec<ContextKeyHistoryEntryJson>,
+    /// Levels whose changes were not recorded, the value of the key is unknown from them
+    missing_levels: Vec<u32>,
+}
+
+/// Values of the context `key` changed by the blocks between `from_level` and `to_level`
+///
+/// The last change before `from_level` is included, it is the value of the key at `from_level`.
+pub(crate) async fn get_context_key_history(
+    key: &str,
+    from_level: u64,
+    to_level: u64,
+    env: &RpcServiceEnvironment,
+) -> Result<ContextKeyHistoryJson, RpcServiceError> {
+    let key: ContextKeyOwned = key
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .map(str::to_string)
+        .collect();
+    let from_level = from_level.try_into().unwrap_or(u32::MAX);
+    let to_level = to_level.try_into().unwrap_or(u32::MAX);
+
+    let history = env
+        .tezedge_context()
+        .get_key_history(key, from_level, to_level)
+        .await
+        .map_err(|e| RpcServiceError::UnexpectedError {
+            reason: format!("{}", e),
+        })?
+        .ok_or_else(|| RpcServiceError::UnexpectedError {
+            reason: "The context key history is not recorded, it is enabled with the option \
+                     `--context-key-history`"
+                .to_string(),
+        })?;
+
+    Ok(ContextKeyHistoryJson {
+        entries: history
+            .entries
+            .into_iter()
+            .map(ContextKeyHistoryEntryJson::from)
+            .collect(),
+        missing_levels: history.missing_levels,
+    })
+}
+
 pub(crate) fn get_dev_version() -> String {
     let version_env: &'static str = env!("CARGO_PKG_VERSION");
 
@@ -1173,3 +1337,16 @@ pub(crate) async fn patch_bakers(
     let response = rx.await?;
     Ok(response)
 }
+
+pub(crate) async fn patch_graylist(
+    patch: shell_automaton::service::rpc_service::GraylistPatch,
+    env: &RpcServiceEnvironment,
+) -> anyhow::Result<serde_json::Value> {
+    let rx = env
+        .shell_automaton_sender()
+        .send(RpcShellAutomatonMsg::PatchGraylist { patch })
+        .await?;
+
+    let response = rx.await?;
+    Ok(response)
+}
@@ -0,0 +1,264 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Metrics of the node in the Prometheus text exposition format.
+
+use std::fmt::{self, Display, Write};
+
+use shell::stats::memory::MemoryData;
+use shell_automaton::service::rpc_service::{NodeMetrics, RpcRequest as RpcShellAutomatonMsg};
+use shell_automaton::service::BlockApplyStats;
+use slog::warn;
+
+use crate::server::RpcServiceEnvironment;
+use crate::services::dev_services;
+
+pub(crate) async fn get_metrics(env: &RpcServiceEnvironment) -> anyhow::Result<String> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let _ = env
+        .shell_automaton_sender()
+        .send(RpcShellAutomatonMsg::GetMetrics { channel: tx })
+        .await;
+    let node = rx.await?;
+
+    let best_remote_level = env
+        .state()
+        .read()
+        .ok()
+        .and_then(|state| *state.best_remote_level());
+    // reads the memory of the processes from /proc
+    let protocol_runners =
+        tokio::task::spawn_blocking(dev_services::get_stats_memory_protocol_runners).await?;
+    let protocol_runners = match protocol_runners {
+        Ok(v) => v,
+        Err(err) => {
+            warn!(env.log(), "GetStatsMemory: {}", err);
+            vec![]
+        }
+    };
+
+    Ok(render(&node, best_remote_level, &protocol_runners)?)
+}
+
+fn render(
+    node: &NodeMetrics,
+    best_remote_level: Option<i32>,
+    protocol_runners: &[MemoryData],
+) -> Result<String, fmt::Error> {
+    let mut out = Exposition(String::new());
+
+    out.gauge("tezedge_head_level", "Level of the current head")?;
+    if let Some(level) = node.current_head_level {
+        out.sample("tezedge_head_level", &[], level)?;
+    }
+    out.gauge(
+        "tezedge_best_remote_level",
+        "Highest level advertised by the peers",
+    )?;
+    if let Some(level) = best_remote_level {
+        out.sample("tezedge_best_remote_level", &[], level)?;
+    }
+    out.gauge("tezedge_bootstrapped", "Whether the node is bootstrapped")?;
+    out.sample("tezedge_bootstrapped", &[], u8::from(node.is_bootstrapped))?;
+    out.gauge(
+        "tezedge_bootstrap_finished",
+        "Whether the bootstrap pipeline has finished",
+    )?;
+    out.sample(
+        "tezedge_bootstrap_finished",
+        &[],
+        u8::from(node.bootstrap_finished),
+    )?;
+
+    out.gauge("tezedge_peers", "Number of peers by the connection status")?;
+    for (status, count) in &node.peers {
+        out.sample("tezedge_peers", &[("status", status)], count)?;
+    }
+
+    out.gauge(
+        "tezedge_mempool_operations",
+        "Number of mempool operations by classification",
+    )?;
+    for (classification, count) in &node.mempool {
+        out.sample(
+            "tezedge_mempool_operations",
+            &[("classification", classification)],
+            count,
+        )?;
+    }
+
+    out.gauge(
+        "tezedge_head_application_seconds",
+        "Duration of the phases of the current head application",
+    )?;
+    if let Some(stats) = &node.current_head_stats {
+        for (phase, nanos) in application_phases(stats) {
+            let seconds = nanos as f64 / 1_000_000_000.0;
+            out.sample(
+                "tezedge_head_application_seconds",
+                &[("phase", phase)],
+                seconds,
+            )?;
+        }
+    }
+
+    out.gauge(
+        "tezedge_protocol_runner_resident_memory_bytes",
+        "Resident memory of the protocol runners",
+    )?;
+    for (runner, memory) in protocol_runners.iter().enumerate() {
+        if let Some(bytes) = memory.resident_bytes() {
+            let runner = runner.to_string();
+            out.sample(
+                "tezedge_protocol_runner_resident_memory_bytes",
+                &[("runner", &runner)],
+                bytes,
+            )?;
+        }
+    }
+
+    Ok(out.0)
+}
+
+/// Durations in nanoseconds of the phases which are known.
+fn application_phases(stats: &BlockApplyStats) -> Vec<(&'static str, u64)> {
+    let span = |start: Option<u64>, end: Option<u64>| Some(end?.saturating_sub(start?));
+    let mut phases = vec![
+        ("precheck", span(stats.precheck_start, stats.precheck_end)),
+        (
+            "download_block_header",
+            span(
+                stats.download_block_header_start,
+                stats.download_block_header_end,
+            ),
+        ),
+        (
+            "download_block_operations",
+            span(
+                stats.download_block_operations_start,
+                stats.download_block_operations_end,
+            ),
+        ),
+        (
+            "load_data",
+            span(stats.load_data_start, stats.load_data_end),
+        ),
+        (
+            "apply_block",
+            span(stats.apply_block_start, stats.apply_block_end),
+        ),
+        (
+            "store_result",
+            span(stats.store_result_start, stats.store_result_end),
+        ),
+    ];
+    if let Some(protocol) = &stats.apply_block_stats {
+        let span = |start: u64, end: u64| Some(end.saturating_sub(start));
+        phases.extend([
+            (
+                "protocol_operations_decoding",
+                span(
+                    protocol.operations_decoding_start,
+                    protocol.operations_decoding_end,
+                ),
+            ),
+            (
+                "protocol_begin_application",
+                span(
+                    protocol.begin_application_start,
+                    protocol.begin_application_end,
+                ),
+            ),
+            (
+                "protocol_operations_application",
+                Some(
+                    protocol
+                        .operations_application
+                        .iter()
+                        .flatten()
+                        .map(|(start, end)| end.saturating_sub(*start))
+                        .sum(),
+                ),
+            ),
+            (
+                "protocol_operations_metadata_encoding",
+                span(
+                    protocol.operations_metadata_encoding_start,
+                    protocol.operations_metadata_encoding_end,
+                ),
+            ),
+            (
+                "protocol_finalize_block",
+                span(protocol.finalize_block_start, protocol.finalize_block_end),
+            ),
+            (
+                "protocol_collect_new_rolls_owner_snapshots",
+                span(
+                    protocol.collect_new_rolls_owner_snapshots_start,
+                    protocol.collect_new_rolls_owner_snapshots_end,
+                ),
+            ),
+            (
+                "protocol_commit",
+                span(protocol.commit_start, protocol.commit_end),
+            ),
+        ]);
+    }
+    phases
+        .into_iter()
+        .filter_map(|(phase, nanos)| Some((phase, nanos?)))
+        .collect()
+}
+
+struct Exposition(String);
+
+impl Exposition {
+    fn gauge(&mut self, name: &str, help: &str) -> fmt::Result {
+        writeln!(self.0, "# HELP {name} {help}")?;
+        writeln!(self.0, "# TYPE {name} gauge")
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) -> fmt::Result {
+        write!(self.0, "{name}")?;
+        if !labels.is_empty() {
+            let labels = labels
+                .iter()
+                .map(|(label, value)| format!("{label}=\"{value}\""))
+                .collect::<Vec<_>>()
+                .join(",");
+            write!(self.0, "{{{labels}}}")?;
+        }
+        writeln!(self.0, " {value}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use shell_automaton::service::rpc_service::NodeMetrics;
+
+    use super::render;
+
+    #[test]
+    fn render_node_metrics() {
+        let node = NodeMetrics {
+            current_head_level: Some(42),
+            is_bootstrapped: true,
+            bootstrap_finished: false,
+            peers: BTreeMap::from([("handshaked".to_owned(), 3), ("potential".to_owned(), 7)]),
+            mempool: BTreeMap::from([("applied".to_owned(), 2)]),
+            current_head_stats: None,
+        };
+        let text = render(&node, Some(50), &[]).unwrap();
+
+        assert!(text.contains("# TYPE tezedge_head_level gauge\ntezedge_head_level 42\n"));
+        assert!(text.contains("tezedge_best_remote_level 50\n"));
+        assert!(text.contains("tezedge_bootstrapped 1\n"));
+        assert!(text.contains("tezedge_bootstrap_finished 0\n"));
+        assert!(text.contains("tezedge_peers{status=\"handshaked\"} 3\n"));
+        assert!(text.contains("tezedge_peers{status=\"potential\"} 7\n"));
+        assert!(text.contains("tezedge_mempool_operations{classification=\"applied\"} 2\n"));
+        assert!(!text.contains("tezedge_head_application_seconds{"));
+    }
+}
@@ -200,14 +200,11 @@ run_sandbox() {
   # protocol_runner needs 'libtezos.so' to run
   export LD_LIBRARY_PATH="${BASH_SOURCE%/*}/tezos/sys/lib_tezos/artifacts:${BASH_SOURCE%/*}/target/$PROFILE"
 
-  export TEZOS_CLIENT_UNSAFE_DISABLE_DISCLAIMER="Y"
-
   cargo run $CARGO_PROFILE_ARG --bin sandbox -- \
             --log-level "info" \
             --sandbox-rpc-port "3030" \
             --light-node-path "./target/$PROFILE/light-node" \
-            --protocol-runner-path "./target/$PROFILE/protocol-runner" \
-            --tezos-client-path "./sandbox/artifacts/tezos-client" "${args[@]}"
+            --protocol-runner-path "./target/$PROFILE/protocol-runner" "${args[@]}"
 }
 
 case $1 in
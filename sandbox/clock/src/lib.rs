@@ -0,0 +1,269 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+#![forbid(unsafe_code)]
+
+//! Controllable time source for the sandbox.
+//!
+//! The sandbox launcher owns the clock file and adjusts the clock (advance, freeze, scale),
+//! sandbox nodes and bakers open the same file and read the time from it, so all of them
+//! see the same (warped) time. The clock never goes backwards.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+/// Parameters of the clock, the sandbox time is `virtual_anchor + (now - real_anchor) * rate`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ClockState {
+    /// Real time (since unix epoch) of the last adjustment
+    pub real_anchor: Duration,
+    /// Sandbox time (since unix epoch) at the last adjustment
+    pub virtual_anchor: Duration,
+    /// How fast the sandbox time flows compared to the real time, `0.0` means frozen
+    pub rate: f64,
+}
+
+impl ClockState {
+    /// The clock following the real time
+    pub fn real() -> Self {
+        let now = real_now();
+        Self {
+            real_anchor: now,
+            virtual_anchor: now,
+            rate: 1.0,
+        }
+    }
+
+    /// Sandbox time (since unix epoch) at the real time `real`
+    pub fn at(&self, real: Duration) -> Duration {
+        self.virtual_anchor + real.saturating_sub(self.real_anchor).mul_f64(self.rate)
+    }
+
+    /// Sandbox time (since unix epoch)
+    pub fn now(&self) -> Duration {
+        self.at(real_now())
+    }
+
+    /// Moves the clock forward by `by`
+    pub fn advance(self, by: Duration) -> Self {
+        let real = real_now();
+        Self {
+            real_anchor: real,
+            virtual_anchor: self.at(real) + by,
+            rate: self.rate,
+        }
+    }
+
+    /// Changes the speed of the clock, `0.0` freezes it, `1.0` is the real time speed
+    pub fn scale(self, rate: f64) -> Self {
+        let real = real_now();
+        Self {
+            real_anchor: real,
+            virtual_anchor: self.at(real),
+            rate: if rate.is_finite() { rate.max(0.0) } else { 1.0 },
+        }
+    }
+
+    /// Stops the clock
+    pub fn freeze(self) -> Self {
+        self.scale(0.0)
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.rate == 0.0
+    }
+
+    /// Real duration to wait until the sandbox time `duration` elapses, `None` if frozen
+    pub fn real_duration(&self, duration: Duration) -> Option<Duration> {
+        if self.is_frozen() {
+            None
+        } else {
+            Some(duration.div_f64(self.rate))
+        }
+    }
+}
+
+struct Cached {
+    state: ClockState,
+    read_at: Instant,
+    /// The latest time returned, to keep the clock monotonic when the state changes
+    last: Duration,
+}
+
+/// Sandbox clock backed by a file
+#[derive(Clone)]
+pub struct SandboxClock {
+    path: PathBuf,
+    cached: Arc<Mutex<Cached>>,
+    /// Pair of the `Instant` and the sandbox time when the clock was opened, used to derive monotonic time
+    origin: (Instant, Duration),
+}
+
+impl SandboxClock {
+    /// How often the clock file is re-read
+    const REFRESH_INTERVAL: Duration = Duration::from_millis(50);
+
+    /// Creates the clock file, following the real time
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        write_state(path.as_ref(), &ClockState::real())?;
+        Self::open(path)
+    }
+
+    /// Opens the clock file created by the sandbox launcher
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let state = read_state(&path)?;
+        let now = state.now();
+        Ok(Self {
+            path,
+            cached: Arc::new(Mutex::new(Cached {
+                state,
+                read_at: Instant::now(),
+                last: now,
+            })),
+            origin: (Instant::now(), now),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Current state of the clock
+    pub fn state(&self) -> ClockState {
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        Self::refresh(&self.path, &mut cached);
+        cached.state
+    }
+
+    /// Sandbox time since unix epoch
+    pub fn since_epoch(&self) -> Duration {
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        Self::refresh(&self.path, &mut cached);
+        let now = cached.state.now().max(cached.last);
+        cached.last = now;
+        now
+    }
+
+    /// Sandbox time
+    pub fn now(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + self.since_epoch()
+    }
+
+    /// Sandbox time as `Instant`, for the `redux_rs::TimeService`
+    pub fn monotonic_time(&self) -> Instant {
+        let (instant, since_epoch) = self.origin;
+        instant + self.since_epoch().saturating_sub(since_epoch)
+    }
+
+    /// Adjusts the clock and stores it in the file
+    pub fn update<F>(&self, f: F) -> io::Result<ClockState>
+    where
+        F: FnOnce(ClockState) -> ClockState,
+    {
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        let state = f(read_state(&self.path)?);
+        write_state(&self.path, &state)?;
+        cached.state = state;
+        cached.read_at = Instant::now();
+        Ok(state)
+    }
+
+    fn refresh(path: &Path, cached: &mut Cached) {
+        if cached.read_at.elapsed() < Self::REFRESH_INTERVAL {
+            return;
+        }
+        // keep the previous state if the file cannot be read for a moment
+        if let Ok(state) = read_state(path) {
+            cached.state = state;
+        }
+        cached.read_at = Instant::now();
+    }
+}
+
+fn real_now() -> Duration {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+fn read_state(path: &Path) -> io::Result<ClockState> {
+    serde_json::from_slice(&fs::read(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Writes into a temporary file first and renames it, so the readers never see a partial state
+fn write_state(path: &Path, state: &ClockState) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec(state)?)?;
+    fs::rename(tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sandbox-clock-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    #[test]
+    fn test_advance_and_freeze() {
+        let state = ClockState::real().freeze();
+        let before = state.now();
+        let state = state.advance(Duration::from_secs(3600));
+        assert_eq!(state.now(), before + Duration::from_secs(3600));
+        assert_eq!(state.real_duration(Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn test_scale() {
+        let state = ClockState::real().scale(10.0);
+        assert_eq!(
+            state.real_duration(Duration::from_secs(30)),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            state.at(state.real_anchor + Duration::from_secs(2)),
+            state.virtual_anchor + Duration::from_secs(20)
+        );
+        assert!(ClockState::real().scale(-1.0).is_frozen());
+    }
+
+    #[test]
+    fn test_shared_through_file() {
+        let path = clock_path("shared.json");
+        let launcher = SandboxClock::create(&path).unwrap();
+        let node = SandboxClock::open(&path).unwrap();
+
+        launcher
+            .update(|state| state.freeze().advance(Duration::from_secs(600)))
+            .unwrap();
+        std::thread::sleep(SandboxClock::REFRESH_INTERVAL);
+
+        assert_eq!(node.state(), launcher.state());
+        assert_eq!(node.since_epoch(), launcher.since_epoch());
+        assert!(node.monotonic_time() >= node.origin.0 + Duration::from_secs(600));
+    }
+
+    #[test]
+    fn test_monotonic() {
+        let path = clock_path("monotonic.json");
+        let clock = SandboxClock::create(&path).unwrap();
+        let now = clock.since_epoch();
+
+        // write a state in the past directly, the clock must not go back
+        let mut past = ClockState::real().freeze();
+        past.virtual_anchor = now - Duration::from_secs(60);
+        write_state(&path, &past).unwrap();
+        std::thread::sleep(SandboxClock::REFRESH_INTERVAL);
+
+        assert!(clock.since_epoch() >= now);
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Control of the sandbox clock.
+//!
+//! The launcher creates the clock file, all sandbox nodes (`--sandbox-clock-file`) and bakers
+//! follow it, so the time can be advanced, frozen or scaled for the whole sandbox at once.
+
+use std::time::Duration;
+
+use sandbox_clock::{ClockState, SandboxClock};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use warp::reject;
+
+#[derive(Debug, Error)]
+pub enum ClockError {
+    #[error("Failed to update the sandbox clock, reason: {reason}")]
+    IOError { reason: std::io::Error },
+
+    #[error("Invalid clock request - {reason}")]
+    InvalidRequest { reason: String },
+}
+
+impl reject::Reject for ClockError {}
+
+/// The json body incoming with the request to advance the clock
+#[derive(Clone, Debug, Deserialize)]
+pub struct ClockAdvanceRequest {
+    /// Seconds to move the clock forward
+    pub seconds: f64,
+}
+
+/// The json body incoming with the request to change the speed of the clock
+#[derive(Clone, Debug, Deserialize)]
+pub struct ClockScaleRequest {
+    /// Speed of the clock compared to the real time, `0.0` freezes the clock
+    pub rate: f64,
+}
+
+/// Current sandbox time and the clock parameters
+#[derive(Clone, Debug, Serialize)]
+pub struct ClockReply {
+    /// Sandbox time in seconds since unix epoch
+    pub now: u64,
+    pub rate: f64,
+    pub frozen: bool,
+}
+
+impl ClockReply {
+    pub fn new(clock: &SandboxClock) -> Self {
+        Self::with_state(clock, clock.state())
+    }
+
+    fn with_state(clock: &SandboxClock, state: ClockState) -> Self {
+        Self {
+            now: clock.since_epoch().as_secs(),
+            rate: state.rate,
+            frozen: state.is_frozen(),
+        }
+    }
+}
+
+/// The clock can be advanced by at most 100 years at once
+const ADVANCE_MAX_SECONDS: f64 = 100.0 * 365.0 * 24.0 * 3600.0;
+
+pub fn advance(clock: &SandboxClock, seconds: f64) -> Result<ClockReply, ClockError> {
+    let by = advance_duration(seconds)?;
+    update(clock, |state| state.advance(by))
+}
+
+fn advance_duration(seconds: f64) -> Result<Duration, ClockError> {
+    if !(0.0..=ADVANCE_MAX_SECONDS).contains(&seconds) {
+        return Err(ClockError::InvalidRequest {
+            reason: format!(
+                "cannot advance the clock by {} seconds, max {}",
+                seconds, ADVANCE_MAX_SECONDS
+            ),
+        });
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+pub fn scale(clock: &SandboxClock, rate: f64) -> Result<ClockReply, ClockError> {
+    if !rate.is_finite() || rate < 0.0 {
+        return Err(ClockError::InvalidRequest {
+            reason: format!("invalid clock rate {}", rate),
+        });
+    }
+    update(clock, |state| state.scale(rate))
+}
+
+pub fn freeze(clock: &SandboxClock) -> Result<ClockReply, ClockError> {
+    update(clock, ClockState::freeze)
+}
+
+fn update<F>(clock: &SandboxClock, f: F) -> Result<ClockReply, ClockError>
+where
+    F: FnOnce(ClockState) -> ClockState,
+{
+    let state = clock
+        .update(f)
+        .map_err(|reason| ClockError::IOError { reason })?;
+    Ok(ClockReply::with_state(clock, state))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advance_duration() {
+        assert_eq!(advance_duration(1.5).unwrap(), Duration::from_millis(1500));
+        assert_eq!(advance_duration(0.0).unwrap(), Duration::ZERO);
+
+        for seconds in [
+            -1.0,
+            f64::NAN,
+            f64::INFINITY,
+            1e300,
+            ADVANCE_MAX_SECONDS * 2.0,
+        ] {
+            assert!(matches!(
+                advance_duration(seconds),
+                Err(ClockError::InvalidRequest { .. })
+            ));
+        }
+    }
+}
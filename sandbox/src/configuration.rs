@@ -18,7 +18,6 @@ pub struct LauncherEnvironment {
     pub protocol_runner_path: PathBuf,
     pub log_level: slog::Level,
     pub sandbox_rpc_port: u16,
-    pub tezos_client_path: PathBuf,
     pub zcash_param: ZcashParams,
 }
 
@@ -69,21 +68,6 @@ fn sandbox_app() -> App<'static, 'static> {
                     }
                 }),
         )
-        .arg(
-            Arg::with_name("tezos-client-path")
-                .long("tezos-client-path")
-                .takes_value(true)
-                .value_name("PATH")
-                .help("Path to the tezos-client binary")
-                .required(true)
-                .validator(|v| {
-                    if Path::new(&v).exists() {
-                        Ok(())
-                    } else {
-                        Err(format!("Tezos-client binary not found at '{}'", v))
-                    }
-                }),
-        )
         .arg(
             Arg::with_name("log-level")
                 .long("log-level")
@@ -148,11 +132,6 @@ impl LauncherEnvironment {
                 .unwrap_or("")
                 .parse::<u16>()
                 .expect("Was expecting value of sandbox-rpc-port"),
-            tezos_client_path: args
-                .value_of("tezos-client-path")
-                .unwrap_or("")
-                .parse::<PathBuf>()
-                .expect("Provided value cannot be converted to path"),
             zcash_param: ZcashParams {
                 init_sapling_spend_params_file: args
                     .value_of("init-sapling-spend-params-file")
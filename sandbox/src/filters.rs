@@ -4,25 +4,33 @@
 use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
 
+use sandbox_clock::SandboxClock;
+use serde::de::DeserializeOwned;
 use slog::Logger;
 use warp::filters::BoxedFilter;
 use warp::Filter;
 
+use crate::clock::{ClockAdvanceRequest, ClockScaleRequest};
 use crate::handlers::{
-    activate_protocol, bake_block_with_client, bake_block_with_client_arbitrary, get_wallets,
-    handle_rejection, init_client_data, list_nodes, resolve_node_from_request,
-    start_node_with_config, stop_node,
+    activate_protocol, advance_clock, bake_block_on_topology, bake_block_with_client,
+    bake_block_with_client_arbitrary, freeze_clock, get_clock, get_topology, get_wallets,
+    handle_rejection, heal_topology, init_client_data, list_nodes, partition_topology,
+    resolve_node_from_request, scale_clock, start_node_with_config, start_topology, stop_node,
+    stop_topology,
 };
 use crate::node_runner::{LightNodeRunnerRef, NodeRpcIpPort};
 use crate::tezos_client_runner::{
     BakeRequest, SandboxWallets, TezosClientRunnerRef, TezosProtcolActivationParameters,
 };
+use crate::topology::TopologyRef;
 
 pub fn sandbox(
     log: Logger,
     runner: LightNodeRunnerRef,
     client_runner: TezosClientRunnerRef,
     peers: Arc<Mutex<HashSet<NodeRpcIpPort>>>,
+    topology: TopologyRef,
+    clock: SandboxClock,
 ) -> BoxedFilter<(impl warp::Reply,)> {
     // Allow cors from any origin
     let cors = warp::cors()
@@ -38,7 +46,7 @@ pub fn sandbox(
     )
     .or(stop(
         log.clone(),
-        runner,
+        runner.clone(),
         client_runner.clone(),
         peers.clone(),
     ))
@@ -51,7 +59,27 @@ pub fn sandbox(
     .or(wallets(log.clone(), client_runner.clone(), peers.clone()))
     .or(activate(log.clone(), client_runner.clone(), peers.clone()))
     .or(bake(log.clone(), client_runner.clone(), peers.clone()))
-    .or(bake_random(log.clone(), client_runner, peers))
+    .or(bake_random(log.clone(), client_runner.clone(), peers))
+    .or(topology_start(
+        log.clone(),
+        runner.clone(),
+        client_runner.clone(),
+        topology.clone(),
+    ))
+    .or(topology_get(log.clone(), topology.clone()))
+    .or(topology_stop(
+        log.clone(),
+        runner,
+        client_runner.clone(),
+        topology.clone(),
+    ))
+    .or(topology_partition(log.clone(), topology.clone()))
+    .or(topology_heal(log.clone(), topology.clone()))
+    .or(topology_bake(log.clone(), client_runner, topology))
+    .or(clock_get(log.clone(), clock.clone()))
+    .or(clock_advance(log.clone(), clock.clone()))
+    .or(clock_freeze(log.clone(), clock.clone()))
+    .or(clock_scale(log.clone(), clock))
     .recover(move |rejection| handle_rejection(rejection, log.clone()))
     .with(cors)
     .boxed()
@@ -176,6 +204,121 @@ pub fn bake_random(
         .boxed()
 }
 
+pub fn topology_start(
+    log: Logger,
+    runner: LightNodeRunnerRef,
+    client_runner: TezosClientRunnerRef,
+    topology: TopologyRef,
+) -> BoxedFilter<(impl warp::Reply,)> {
+    warp::path!("topology")
+        .and(warp::post())
+        .and(topology_json_body())
+        .and(with_log(log))
+        .and(with_runner(runner))
+        .and(with_client_runner(client_runner))
+        .and(with_topology(topology))
+        .and_then(start_topology)
+        .boxed()
+}
+
+pub fn topology_get(log: Logger, topology: TopologyRef) -> BoxedFilter<(impl warp::Reply,)> {
+    warp::path!("topology")
+        .and(warp::get())
+        .and(with_log(log))
+        .and(with_topology(topology))
+        .and_then(get_topology)
+        .boxed()
+}
+
+pub fn topology_stop(
+    log: Logger,
+    runner: LightNodeRunnerRef,
+    client_runner: TezosClientRunnerRef,
+    topology: TopologyRef,
+) -> BoxedFilter<(impl warp::Reply,)> {
+    warp::path!("topology" / "stop")
+        .and(warp::get())
+        .and(with_log(log))
+        .and(with_runner(runner))
+        .and(with_client_runner(client_runner))
+        .and(with_topology(topology))
+        .and_then(stop_topology)
+        .boxed()
+}
+
+pub fn topology_partition(log: Logger, topology: TopologyRef) -> BoxedFilter<(impl warp::Reply,)> {
+    warp::path!("topology" / "partition")
+        .and(warp::post())
+        .and(topology_json_body())
+        .and(with_log(log))
+        .and(with_topology(topology))
+        .and_then(partition_topology)
+        .boxed()
+}
+
+pub fn topology_heal(log: Logger, topology: TopologyRef) -> BoxedFilter<(impl warp::Reply,)> {
+    warp::path!("topology" / "heal")
+        .and(warp::post())
+        .and(topology_json_body())
+        .and(with_log(log))
+        .and(with_topology(topology))
+        .and_then(heal_topology)
+        .boxed()
+}
+
+pub fn topology_bake(
+    log: Logger,
+    client_runner: TezosClientRunnerRef,
+    topology: TopologyRef,
+) -> BoxedFilter<(impl warp::Reply,)> {
+    warp::path!("topology" / "bake")
+        .and(warp::post())
+        .and(topology_json_body())
+        .and(with_log(log))
+        .and(with_client_runner(client_runner))
+        .and(with_topology(topology))
+        .and_then(bake_block_on_topology)
+        .boxed()
+}
+
+pub fn clock_get(log: Logger, clock: SandboxClock) -> BoxedFilter<(impl warp::Reply,)> {
+    warp::path!("clock")
+        .and(warp::get())
+        .and(with_log(log))
+        .and(with_clock(clock))
+        .and_then(get_clock)
+        .boxed()
+}
+
+pub fn clock_advance(log: Logger, clock: SandboxClock) -> BoxedFilter<(impl warp::Reply,)> {
+    warp::path!("clock" / "advance")
+        .and(warp::post())
+        .and(clock_json_body::<ClockAdvanceRequest>())
+        .and(with_log(log))
+        .and(with_clock(clock))
+        .and_then(advance_clock)
+        .boxed()
+}
+
+pub fn clock_freeze(log: Logger, clock: SandboxClock) -> BoxedFilter<(impl warp::Reply,)> {
+    warp::path!("clock" / "freeze")
+        .and(warp::post())
+        .and(with_log(log))
+        .and(with_clock(clock))
+        .and_then(freeze_clock)
+        .boxed()
+}
+
+pub fn clock_scale(log: Logger, clock: SandboxClock) -> BoxedFilter<(impl warp::Reply,)> {
+    warp::path!("clock" / "scale")
+        .and(warp::post())
+        .and(clock_json_body::<ClockScaleRequest>())
+        .and(with_log(log))
+        .and(with_clock(clock))
+        .and_then(scale_clock)
+        .boxed()
+}
+
 fn json_body() -> BoxedFilter<(serde_json::Value,)> {
     // When accepting a body, we want a JSON body
     // (and to reject huge payloads)...
@@ -208,6 +351,22 @@ fn bake_json_body() -> BoxedFilter<(BakeRequest,)> {
         .boxed()
 }
 
+fn topology_json_body<T: DeserializeOwned + Send + 'static>() -> BoxedFilter<(T,)> {
+    // When accepting a body, we want a JSON body with the deserialized topology request
+    // (and to reject huge payloads)...
+    warp::body::content_length_limit(1024 * 64)
+        .and(warp::body::json())
+        .boxed()
+}
+
+fn clock_json_body<T: DeserializeOwned + Send + 'static>() -> BoxedFilter<(T,)> {
+    // When accepting a body, we want a JSON body with the deserialized clock request
+    // (and to reject huge payloads)...
+    warp::body::content_length_limit(1024)
+        .and(warp::body::json())
+        .boxed()
+}
+
 fn with_log(log: Logger) -> BoxedFilter<(Logger,)> {
     warp::any().map(move || log.clone()).boxed()
 }
@@ -220,6 +379,14 @@ fn with_client_runner(client_runner: TezosClientRunnerRef) -> BoxedFilter<(Tezos
     warp::any().map(move || client_runner.clone()).boxed()
 }
 
+fn with_clock(clock: SandboxClock) -> BoxedFilter<(SandboxClock,)> {
+    warp::any().map(move || clock.clone()).boxed()
+}
+
+fn with_topology(topology: TopologyRef) -> BoxedFilter<(TopologyRef,)> {
+    warp::any().map(move || topology.clone()).boxed()
+}
+
 fn with_peers(
     peers: Arc<Mutex<HashSet<NodeRpcIpPort>>>,
 ) -> BoxedFilter<(Arc<Mutex<HashSet<NodeRpcIpPort>>>,)> {
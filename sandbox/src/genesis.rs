@@ -0,0 +1,270 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Forging and signing of the genesis `activate` command (the block activating an economic protocol)
+
+use crypto::hash::{
+    BlockHash, ChainId, ContextHash, Ed25519Signature, OperationListListHash, ProtocolHash,
+    SeedEd25519,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tezos_encoding::enc::BinWriter;
+use tezos_messages::p2p::encoding::fitness::Fitness;
+use tezos_messages::p2p::encoding::prelude::BlockHeaderBuilder;
+use tezos_messages::Timestamp;
+
+use crate::tezos_client_runner::TezosClientRunnerError;
+
+/// Protocol of the genesis block, which understands the `activate` command
+pub const GENESIS_PROTOCOL: &str = "ProtoGenesisGenesisGenesisGenesisGenesisGenesk612im";
+
+/// Secret key of the sandbox activator, which is the genesis key of the sandboxed chain
+pub const ACTIVATOR_SECRET_KEY: &str = "edsk31vznjHSSpGExDMHYASz45VZqXN4DPxvsa4hAyY8dHM28cZzp6";
+
+/// Watermark of the block header signature
+const BLOCK_HEADER_WATERMARK: u8 = 0x01;
+
+/// Tag of the `activate` command in the genesis protocol data
+const ACTIVATE_COMMAND_TAG: u8 = 0x00;
+
+/// The shell part of the block header, as returned by the preapply rpc
+#[derive(Clone, Debug, Deserialize)]
+pub struct ShellHeader {
+    level: i32,
+    proto: u8,
+    predecessor: BlockHash,
+    timestamp: Timestamp,
+    validation_pass: u8,
+    operations_hash: OperationListListHash,
+    fitness: Fitness,
+    context: ContextHash,
+}
+
+/// Genesis command activating the protocol `protocol` with the `protocol_parameters`
+pub struct ActivationCommand {
+    protocol: ProtocolHash,
+    fitness: Fitness,
+    protocol_parameters: Vec<u8>,
+}
+
+impl ActivationCommand {
+    /// Equivalent of `activate protocol <protocol_hash> with fitness 1` of the tezos-client
+    pub fn new(
+        protocol_hash: &str,
+        protocol_parameters: &Value,
+    ) -> Result<Self, TezosClientRunnerError> {
+        let protocol = ProtocolHash::from_base58_check(protocol_hash).map_err(|e| {
+            TezosClientRunnerError::ForgeError {
+                reason: format!("invalid protocol hash {}: {}", protocol_hash, e),
+            }
+        })?;
+        let protocol_parameters = match protocol_parameters.as_object() {
+            Some(params) => encode_json(params),
+            None => {
+                return Err(TezosClientRunnerError::ProtocolParameterError {
+                    json: protocol_parameters.clone(),
+                })
+            }
+        };
+
+        Ok(Self {
+            protocol,
+            fitness: Fitness::from_bytes([vec![0x00], 1i64.to_be_bytes().to_vec()]),
+            protocol_parameters,
+        })
+    }
+
+    /// Protocol data in the form expected by the preapply rpc, signed with zeroes
+    pub fn to_json(&self) -> Value {
+        serde_json::json!({
+            "protocol": GENESIS_PROTOCOL,
+            "content": {
+                "command": "activate",
+                "hash": self.protocol.to_base58_check(),
+                "fitness": self.fitness.as_hex_vec(),
+                "protocol_parameters": hex::encode(&self.protocol_parameters),
+            },
+            "signature": Ed25519Signature(vec![0; 64]).to_base58_check(),
+        })
+    }
+
+    /// Binary protocol data of the block, without the signature
+    fn to_bytes(&self) -> Result<Vec<u8>, TezosClientRunnerError> {
+        let mut out = vec![ACTIVATE_COMMAND_TAG];
+        self.protocol
+            .bin_write(&mut out)
+            .and_then(|_| self.fitness.bin_write(&mut out))
+            .map_err(|e| TezosClientRunnerError::ForgeError {
+                reason: e.to_string(),
+            })?;
+        out.extend_from_slice(&self.protocol_parameters);
+        Ok(out)
+    }
+
+    /// Forges the block header from the preapplied `shell_header` and signs it with the activator key,
+    /// the result is ready to be injected
+    pub fn forge_signed(
+        &self,
+        shell_header: ShellHeader,
+        chain_id: &ChainId,
+    ) -> Result<Vec<u8>, TezosClientRunnerError> {
+        let forge_error = |reason: String| TezosClientRunnerError::ForgeError { reason };
+
+        let header = BlockHeaderBuilder::default()
+            .level(shell_header.level)
+            .proto(shell_header.proto)
+            .predecessor(shell_header.predecessor)
+            .timestamp(shell_header.timestamp)
+            .validation_pass(shell_header.validation_pass)
+            .operations_hash(shell_header.operations_hash)
+            .fitness(shell_header.fitness)
+            .context(shell_header.context)
+            .protocol_data(self.to_bytes()?.into())
+            .build()
+            .map_err(|e| forge_error(e.to_string()))?;
+
+        let mut forged = Vec::new();
+        header
+            .bin_write(&mut forged)
+            .map_err(|e| forge_error(e.to_string()))?;
+
+        let mut watermark = vec![BLOCK_HEADER_WATERMARK];
+        watermark.extend_from_slice(&chain_id.0);
+
+        let (_, secret_key) = SeedEd25519::from_base58_check(ACTIVATOR_SECRET_KEY)
+            .map_err(|e| forge_error(e.to_string()))?
+            .keypair()
+            .map_err(|e| forge_error(e.to_string()))?;
+        let signature = secret_key
+            .sign([watermark.as_slice(), forged.as_slice()])
+            .map_err(|e| forge_error(e.to_string()))?;
+
+        forged.extend_from_slice(&signature.0);
+        Ok(forged)
+    }
+}
+
+/// Binary encoding of a json object, a size prefixed bson document (as `Data_encoding.json` in octez)
+///
+/// Keys are written sorted, as kept by the `Map`, where tezos-client keeps the order of the file.
+fn encode_json(object: &Map<String, Value>) -> Vec<u8> {
+    let mut document = Vec::new();
+    write_bson_document(object.iter().map(|(k, v)| (k.clone(), v)), &mut document);
+
+    let mut out = Vec::with_capacity(document.len() + 4);
+    out.extend_from_slice(&(document.len() as u32).to_be_bytes());
+    out.extend(document);
+    out
+}
+
+fn write_bson_document<'a>(elements: impl Iterator<Item = (String, &'a Value)>, out: &mut Vec<u8>) {
+    let start = out.len();
+    // placeholder for the document size
+    out.extend_from_slice(&[0; 4]);
+    for (key, value) in elements {
+        let tag_position = out.len();
+        out.push(0x00);
+        out.extend_from_slice(key.as_bytes());
+        out.push(0x00);
+        let tag = match value {
+            Value::Number(n) => {
+                out.extend_from_slice(&n.as_f64().unwrap_or_default().to_le_bytes());
+                0x01
+            }
+            Value::String(s) => {
+                out.extend_from_slice(&(s.len() as i32 + 1).to_le_bytes());
+                out.extend_from_slice(s.as_bytes());
+                out.push(0x00);
+                0x02
+            }
+            Value::Object(o) => {
+                write_bson_document(o.iter().map(|(k, v)| (k.clone(), v)), out);
+                0x03
+            }
+            Value::Array(a) => {
+                write_bson_document(a.iter().enumerate().map(|(i, v)| (i.to_string(), v)), out);
+                0x04
+            }
+            Value::Bool(b) => {
+                out.push(*b as u8);
+                0x08
+            }
+            Value::Null => 0x0A,
+        };
+        out[tag_position] = tag;
+    }
+    out.push(0x00);
+    let size = (out.len() - start) as i32;
+    out[start..start + 4].copy_from_slice(&size.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROTOCOL: &str = "PtJakart2xVj7pYXJBXrqHgd82rdkLey5ZeeGwDgPp9rhQUbSqY";
+
+    fn protocol_parameters() -> Value {
+        serde_json::from_str(include_str!(
+            "../tests/resources/genesis/protocol_parameters.json"
+        ))
+        .unwrap()
+    }
+
+    fn expected(hex_bytes: &str) -> Vec<u8> {
+        hex::decode(hex_bytes.trim()).unwrap()
+    }
+
+    #[test]
+    fn test_encode_json_document() {
+        let object = serde_json::json!({ "hello": "world" });
+        // the document of the bson specification, prefixed with its size
+        assert_eq!(
+            encode_json(object.as_object().unwrap()),
+            expected("00000016160000000268656c6c6f0006000000776f726c640000")
+        );
+    }
+
+    #[test]
+    fn test_encode_json_protocol_parameters() {
+        // numbers are doubles, arrays are documents indexed by the position, as in octez
+        assert_eq!(
+            encode_json(protocol_parameters().as_object().unwrap()),
+            expected(include_str!(
+                "../tests/resources/genesis/protocol_parameters.hex"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_forge_signed() {
+        let command = ActivationCommand::new(PROTOCOL, &protocol_parameters()).unwrap();
+        let shell_header = ShellHeader {
+            level: 1,
+            proto: 0,
+            predecessor: BlockHash::try_from(vec![1; 32]).unwrap(),
+            timestamp: Timestamp::from(1640995200),
+            validation_pass: 0,
+            operations_hash: OperationListListHash::try_from(vec![2; 32]).unwrap(),
+            fitness: Fitness::from(vec![vec![0x01], 1i64.to_be_bytes().to_vec()]),
+            context: ContextHash::try_from(vec![3; 32]).unwrap(),
+        };
+        let chain_id = ChainId::from_base58_check("NetXdQprcVkpaWU").unwrap();
+
+        assert_eq!(
+            command.forge_signed(shell_header, &chain_id).unwrap(),
+            expected(include_str!(
+                "../tests/resources/genesis/activation_block.hex"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_invalid_protocol_parameters() {
+        assert!(matches!(
+            ActivationCommand::new(PROTOCOL, &Value::Array(vec![])),
+            Err(TezosClientRunnerError::ProtocolParameterError { .. })
+        ));
+    }
+}
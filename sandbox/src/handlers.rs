@@ -7,15 +7,23 @@ use std::sync::{Arc, Mutex};
 use std::{collections::HashSet, sync::PoisonError};
 
 use itertools::Itertools;
+use sandbox_clock::SandboxClock;
 use serde::Serialize;
 use slog::{error, info, Logger};
 use warp::http::StatusCode;
 use warp::{reject, Rejection, Reply};
 
-use crate::node_runner::{LightNodeRunnerError, LightNodeRunnerRef, NodeRpcIpPort};
+use crate::clock::{self, ClockAdvanceRequest, ClockError, ClockReply, ClockScaleRequest};
+use crate::node_runner::{
+    LightNodeRunner, LightNodeRunnerError, LightNodeRunnerRef, NodeRpcIpPort,
+};
 use crate::tezos_client_runner::{
-    reply_with_client_output, BakeRequest, SandboxWallets, TezosClientRunnerError,
-    TezosClientRunnerRef, TezosProtcolActivationParameters,
+    reply_with_client_output, BakeRequest, SandboxWallets, TezosClientRunner,
+    TezosClientRunnerError, TezosClientRunnerRef, TezosProtcolActivationParameters,
+};
+use crate::topology::{
+    patch_graylist, Link, Topology, TopologyBakeRequest, TopologyError, TopologyLinksRequest,
+    TopologyNode, TopologyRef, TopologyRequest,
 };
 
 #[derive(Debug, Serialize, Clone)]
@@ -249,10 +257,11 @@ pub async fn bake_block_with_client(
     info!(log, "Received request to bake a block");
 
     let node_ref = ensure_node(node_ref)?;
-    let client_runner = client_runner
-        .read()
-        .map_err(|e| LockErrorCause::new(e, "Cannot get write lock on client_runner"))?;
-    let client_output = client_runner.bake_block(Some(request), &node_ref, &log)?;
+    let baking = client_runner
+        .write()
+        .map_err(|e| LockErrorCause::new(e, "Cannot get write lock on client_runner"))?
+        .start_baker(Some(request), &node_ref, &log)?;
+    let client_output = baking.wait().await?;
 
     reply_with_client_output(client_output, &log).map_err(|e| e.into())
 }
@@ -265,14 +274,371 @@ pub async fn bake_block_with_client_arbitrary(
     info!(log, "Received request to bake arbitrary a block");
 
     let node_ref = ensure_node(node_ref)?;
-    let client_runner = client_runner
-        .read()
+    let baking = client_runner
+        .write()
+        .map_err(|e| LockErrorCause::new(e, "Cannot get write lock on client_runner"))?
+        .start_baker(None, &node_ref, &log)?;
+    let client_output = baking.wait().await?;
+
+    reply_with_client_output(client_output, &log).map_err(|e| e.into())
+}
+
+/// Handler for start topology endpoint
+pub async fn start_topology(
+    request: TopologyRequest,
+    log: Logger,
+    runner: LightNodeRunnerRef,
+    client_runner: TezosClientRunnerRef,
+    topology: TopologyRef,
+) -> Result<impl warp::Reply, reject::Rejection> {
+    info!(log, "Received request to start the sandbox topology";
+               "nodes" => request.nodes,
+               "connections" => format!("{:?}", request.connections),
+               "bakers" => format!("{:?}", request.bakers));
+
+    let new_topology = {
+        let mut topology = topology
+            .lock()
+            .map_err(|e| LockErrorCause::new(e, "Cannot get lock on topology"))?;
+        if topology.is_some() {
+            return Err(TopologyError::TopologyAlreadyRunning.into());
+        }
+        let new_topology = Topology::new(&request)?;
+
+        let mut runner = runner
+            .write()
+            .map_err(|e| LockErrorCause::new(e, "Cannot get write lock on runner"))?;
+        let mut client_runner = client_runner
+            .write()
+            .map_err(|e| LockErrorCause::new(e, "Cannot get write lock on client_runner"))?;
+
+        if let Err(e) = spawn_topology(
+            &new_topology,
+            &request,
+            &mut runner,
+            &mut client_runner,
+            &log,
+        ) {
+            // stop nodes started so far
+            shutdown_topology_nodes(&new_topology.nodes, &mut runner, &mut client_runner);
+            return Err(e);
+        }
+
+        // the nodes are running, so the topology can be stopped while its activation is pending
+        *topology = Some(new_topology.clone());
+        new_topology
+    };
+
+    if let Some(activation) = &request.activation {
+        // the locks are not held while waiting for the nodes
+        let activated = match new_topology.wait_for_rpc().await {
+            Ok(()) => activate_topology(&new_topology, activation, &client_runner, &log),
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = activated {
+            error!(log, "Failed to activate the sandbox topology, stopping it");
+            // the topology may have been stopped meanwhile
+            let _ = stop_topology_nodes(&topology, &runner, &client_runner);
+            return Err(e);
+        }
+    }
+
+    info!(log, "Sandbox topology started successfully!"; "links" => format!("{:?}", new_topology.links));
+    Ok(warp::reply::with_status(
+        warp::reply::json(&new_topology),
+        StatusCode::OK,
+    ))
+}
+
+fn spawn_topology(
+    topology: &Topology,
+    request: &TopologyRequest,
+    runner: &mut LightNodeRunner,
+    client_runner: &mut TezosClientRunner,
+    log: &Logger,
+) -> Result<(), reject::Rejection> {
+    for (index, node) in topology.nodes.iter().enumerate() {
+        info!(log, "Starting topology light-node..."; "index" => index, "node_ref" => format!("{}", &node.node_ref));
+        let (node_ref, data_dir) =
+            runner.spawn(topology.node_config(index, &request.node_config), log)?;
+        client_runner.init_sandbox_data(node_ref.clone(), data_dir);
+
+        if !request.wallets.is_empty() {
+            let client_output =
+                client_runner.init_client_data(request.wallets.clone(), &node_ref, log)?;
+            reply_with_client_output(client_output, log)?;
+        }
+    }
+    Ok(())
+}
+
+fn activate_topology(
+    topology: &Topology,
+    activation: &TezosProtcolActivationParameters,
+    client_runner: &TezosClientRunnerRef,
+    log: &Logger,
+) -> Result<(), reject::Rejection> {
+    let mut client_runner = client_runner
+        .write()
+        .map_err(|e| LockErrorCause::new(e, "Cannot get write lock on client_runner"))?;
+    info!(log, "Activating protocol on topology"; "node_ref" => format!("{}", &topology.nodes[0].node_ref));
+    let client_output =
+        client_runner.activate_protocol(activation.clone(), &topology.nodes[0].node_ref, log)?;
+    reply_with_client_output(client_output, log)?;
+    Ok(())
+}
+
+/// Stops the running topology, returns errors of the nodes that failed to stop
+fn stop_topology_nodes(
+    topology: &TopologyRef,
+    runner: &LightNodeRunnerRef,
+    client_runner: &TezosClientRunnerRef,
+) -> Result<Vec<String>, reject::Rejection> {
+    let mut topology = topology
+        .lock()
+        .map_err(|e| LockErrorCause::new(e, "Cannot get lock on topology"))?;
+    let nodes = topology
+        .take()
+        .ok_or(TopologyError::TopologyNotRunning)?
+        .nodes;
+    let mut runner = runner
+        .write()
+        .map_err(|e| LockErrorCause::new(e, "Cannot get write lock on runner"))?;
+    let mut client_runner = client_runner
+        .write()
         .map_err(|e| LockErrorCause::new(e, "Cannot get write lock on client_runner"))?;
-    let client_output = client_runner.bake_block(None, &node_ref, &log)?;
+    Ok(shutdown_topology_nodes(
+        &nodes,
+        &mut runner,
+        &mut client_runner,
+    ))
+}
+
+fn shutdown_topology_nodes(
+    nodes: &[TopologyNode],
+    runner: &mut LightNodeRunner,
+    client_runner: &mut TezosClientRunner,
+) -> Vec<String> {
+    let mut errors = vec![];
+    for node in nodes {
+        if let Err(e) = runner.shutdown(&node.node_ref) {
+            errors.push(format!("{:?}", e));
+        }
+        if let Err(e) = client_runner.cleanup(&node.node_ref) {
+            errors.push(format!("{:?}", e));
+        }
+    }
+    errors
+}
+
+pub async fn get_topology(
+    log: Logger,
+    topology: TopologyRef,
+) -> Result<impl warp::Reply, reject::Rejection> {
+    info!(log, "Received request to get the sandbox topology");
+
+    let topology = topology
+        .lock()
+        .map_err(|e| LockErrorCause::new(e, "Cannot get lock on topology"))?;
+    let topology = topology.as_ref().ok_or(TopologyError::TopologyNotRunning)?;
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(topology),
+        StatusCode::OK,
+    ))
+}
+
+pub async fn stop_topology(
+    log: Logger,
+    runner: LightNodeRunnerRef,
+    client_runner: TezosClientRunnerRef,
+    topology: TopologyRef,
+) -> Result<impl warp::Reply, reject::Rejection> {
+    info!(log, "Received request to stop the sandbox topology...");
+
+    let errors = stop_topology_nodes(&topology, &runner, &client_runner)?;
+
+    if errors.is_empty() {
+        info!(log, "Sandbox topology stopped!");
+    } else {
+        error!(log, "Sandbox topology stopped!"; "errors" => errors.join(", "));
+    }
+    Ok(warp::reply::with_status(
+        warp::reply::json(&errors),
+        StatusCode::OK,
+    ))
+}
+
+pub async fn partition_topology(
+    request: TopologyLinksRequest,
+    log: Logger,
+    topology: TopologyRef,
+) -> Result<impl warp::Reply, reject::Rejection> {
+    info!(log, "Received request to partition the sandbox topology"; "links" => format!("{:?}", request.links));
+    set_topology_links_partitioned(&request.links, true, topology, &log).await
+}
+
+pub async fn heal_topology(
+    request: TopologyLinksRequest,
+    log: Logger,
+    topology: TopologyRef,
+) -> Result<impl warp::Reply, reject::Rejection> {
+    info!(log, "Received request to heal the sandbox topology"; "links" => format!("{:?}", request.links));
+    set_topology_links_partitioned(&request.links, false, topology, &log).await
+}
+
+/// Graylists (or removes from graylist) the nodes of the changed links on each other,
+/// the nodes keep running and their other connections are not affected
+async fn set_topology_links_partitioned(
+    links: &[Link],
+    partitioned: bool,
+    topology: TopologyRef,
+    log: &Logger,
+) -> Result<impl warp::Reply, reject::Rejection> {
+    let patches = {
+        let topology = topology
+            .lock()
+            .map_err(|e| LockErrorCause::new(e, "Cannot get lock on topology"))?;
+        let topology = topology.as_ref().ok_or(TopologyError::TopologyNotRunning)?;
+        topology
+            .resolve_links(links)?
+            .into_iter()
+            .filter(|link| topology.partitioned.contains(link) != partitioned)
+            .map(|link| (link, topology.graylist_patches(link)))
+            .collect_vec()
+    };
+
+    let client = reqwest::Client::new();
+    let mut changed = vec![];
+    let mut result = Ok(());
+    'links: for (link, link_patches) in patches {
+        for (node_ref, point) in link_patches {
+            info!(log, "Patching topology node graylist"; "node_ref" => format!("{}", node_ref), "point" => point.to_string(), "add" => partitioned);
+            if let Err(e) = patch_graylist(&client, &node_ref, point, partitioned).await {
+                result = Err(e);
+                break 'links;
+            }
+        }
+        changed.push(link);
+    }
+
+    // record the links changed so far, even if some patch failed
+    let mut topology = topology
+        .lock()
+        .map_err(|e| LockErrorCause::new(e, "Cannot get lock on topology"))?;
+    let topology = topology.as_mut().ok_or(TopologyError::TopologyNotRunning)?;
+    for link in changed {
+        if partitioned {
+            topology.partitioned.insert(link);
+        } else {
+            topology.partitioned.remove(&link);
+        }
+    }
+    result?;
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(topology),
+        StatusCode::OK,
+    ))
+}
+
+pub async fn bake_block_on_topology(
+    request: TopologyBakeRequest,
+    log: Logger,
+    client_runner: TezosClientRunnerRef,
+    topology: TopologyRef,
+) -> Result<impl warp::Reply, reject::Rejection> {
+    info!(log, "Received request to bake a block on the sandbox topology"; "node" => request.node, "alias" => format!("{:?}", request.alias));
+
+    let baking = {
+        let topology = topology
+            .lock()
+            .map_err(|e| LockErrorCause::new(e, "Cannot get lock on topology"))?;
+        let topology = topology.as_ref().ok_or(TopologyError::TopologyNotRunning)?;
+        let node =
+            topology
+                .nodes
+                .get(request.node)
+                .ok_or_else(|| TopologyError::InvalidRequest {
+                    reason: format!("there is no node {} in the topology", request.node),
+                })?;
+        let alias = match request.alias.or_else(|| node.bakers.first().cloned()) {
+            Some(alias) if node.bakers.contains(&alias) => alias,
+            alias => {
+                return Err(TopologyError::InvalidRequest {
+                    reason: format!(
+                        "node {} has no baker {}",
+                        request.node,
+                        alias.unwrap_or_else(|| "-none-".to_string())
+                    ),
+                }
+                .into())
+            }
+        };
+
+        client_runner
+            .write()
+            .map_err(|e| LockErrorCause::new(e, "Cannot get write lock on client_runner"))?
+            .start_baker(Some(BakeRequest::new(alias)), &node.node_ref, &log)?
+    };
+    let client_output = baking.wait().await?;
 
     reply_with_client_output(client_output, &log).map_err(|e| e.into())
 }
 
+pub async fn get_clock(
+    log: Logger,
+    clock: SandboxClock,
+) -> Result<impl warp::Reply, reject::Rejection> {
+    info!(log, "Received request to get the sandbox clock");
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&ClockReply::new(&clock)),
+        StatusCode::OK,
+    ))
+}
+
+pub async fn advance_clock(
+    request: ClockAdvanceRequest,
+    log: Logger,
+    clock: SandboxClock,
+) -> Result<impl warp::Reply, reject::Rejection> {
+    info!(log, "Received request to advance the sandbox clock"; "seconds" => request.seconds);
+
+    let reply = clock::advance(&clock, request.seconds)?;
+    Ok(warp::reply::with_status(
+        warp::reply::json(&reply),
+        StatusCode::OK,
+    ))
+}
+
+pub async fn freeze_clock(
+    log: Logger,
+    clock: SandboxClock,
+) -> Result<impl warp::Reply, reject::Rejection> {
+    info!(log, "Received request to freeze the sandbox clock");
+
+    let reply = clock::freeze(&clock)?;
+    Ok(warp::reply::with_status(
+        warp::reply::json(&reply),
+        StatusCode::OK,
+    ))
+}
+
+pub async fn scale_clock(
+    request: ClockScaleRequest,
+    log: Logger,
+    clock: SandboxClock,
+) -> Result<impl warp::Reply, reject::Rejection> {
+    info!(log, "Received request to scale the sandbox clock"; "rate" => request.rate);
+
+    let reply = clock::scale(&clock, request.rate)?;
+    Ok(warp::reply::with_status(
+        warp::reply::json(&reply),
+        StatusCode::OK,
+    ))
+}
+
 pub async fn handle_rejection(err: Rejection, log: Logger) -> Result<impl Reply, Infallible> {
     let (code, error_message) = if err.is_not_found() {
         error!(log, "Rpc handle error"; "message" => "rpc not found");
@@ -300,7 +666,10 @@ pub async fn handle_rejection(err: Rejection, log: Logger) -> Result<impl Reply,
             | TezosClientRunnerError::UnavailableSandboxNodeError
             | TezosClientRunnerError::IOError { .. }
             | TezosClientRunnerError::SandboxDataDirNotInitialized { .. }
-            | TezosClientRunnerError::SerdeError { .. } => {
+            | TezosClientRunnerError::SerdeError { .. }
+            | TezosClientRunnerError::ForgeError { .. }
+            | TezosClientRunnerError::UnsupportedProtocol { .. }
+            | TezosClientRunnerError::BakerAlreadyRunning { .. } => {
                 let message = format!("{}", tcre);
                 error!(log, "Rpc handle error (tezos-client)"; "message" => message.clone());
                 (
@@ -308,6 +677,20 @@ pub async fn handle_rejection(err: Rejection, log: Logger) -> Result<impl Reply,
                     ErrorMessage::generic(StatusCode::BAD_REQUEST, &message, "".to_string()),
                 )
             }
+            TezosClientRunnerError::NodeRpcError { .. }
+            | TezosClientRunnerError::BakeTimeout { .. }
+            | TezosClientRunnerError::BakerStopped { .. } => {
+                let message = format!("{}", tcre);
+                error!(log, "Rpc handle error (tezos-client)"; "message" => message.clone());
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    ErrorMessage::generic(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        &message,
+                        "".to_string(),
+                    ),
+                )
+            }
             TezosClientRunnerError::CallError { message } => {
                 error!(log, "Rpc handle error (tezos-client)"; "message" => format!("{:?}", message));
                 (
@@ -316,13 +699,34 @@ pub async fn handle_rejection(err: Rejection, log: Logger) -> Result<impl Reply,
                 )
             }
         }
+    } else if let Some(te) = err.find::<TopologyError>() {
+        // Topology errors
+        let message = format!("{}", te);
+        error!(log, "Rpc handle error (topology)"; "message" => message.clone());
+        let code = match te {
+            TopologyError::NodeRpcUnreachable { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            TopologyError::TopologyAlreadyRunning
+            | TopologyError::TopologyNotRunning
+            | TopologyError::InvalidRequest { .. }
+            | TopologyError::UnknownLink { .. } => StatusCode::BAD_REQUEST,
+        };
+        (code, ErrorMessage::generic(code, &message, "".to_string()))
+    } else if let Some(ce) = err.find::<ClockError>() {
+        // Sandbox clock errors
+        let message = format!("{}", ce);
+        error!(log, "Rpc handle error (clock)"; "message" => message.clone());
+        let code = match ce {
+            ClockError::IOError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            ClockError::InvalidRequest { .. } => StatusCode::BAD_REQUEST,
+        };
+        (code, ErrorMessage::generic(code, &message, "".to_string()))
     } else if let Some(lnre) = err.find::<LightNodeRunnerError>() {
         // Light-node errors
         match lnre {
             LightNodeRunnerError::JsonParsingError { .. }
             | LightNodeRunnerError::IOError { .. }
             | LightNodeRunnerError::ConfigurationMissingValidRpcPort { .. }
-            | LightNodeRunnerError::NodeAlreadyRunning
+            | LightNodeRunnerError::NodeAlreadyRunning { .. }
             | LightNodeRunnerError::NodeNotRunning { .. } => {
                 let message = format!("{}", lnre);
                 error!(log, "Rpc handle error (light-node)"; "message" => message.clone());
@@ -9,13 +9,17 @@ use std::{env, fs, io, iter};
 
 use rand::distributions::Alphanumeric;
 use rand::Rng;
+use sandbox_clock::SandboxClock;
 use slog::{error, info, Drain, Level, Logger};
 
+mod clock;
 mod configuration;
 mod filters;
+mod genesis;
 mod handlers;
 mod node_runner;
 mod tezos_client_runner;
+mod topology;
 
 #[tokio::main]
 async fn main() {
@@ -38,24 +42,37 @@ async fn main() {
     // sandbox peers map
     let peers = Arc::new(Mutex::new(HashSet::new()));
 
+    // sandbox topology, if started
+    let topology = Arc::new(Mutex::new(None));
+
+    // sandbox clock, shared with the nodes and bakers through the clock file
+    let clock = create_temp_dir("sandbox-clock")
+        .and_then(|dir| SandboxClock::create(dir.join("clock.json")))
+        .unwrap_or_else(|e| {
+            error!(log, "Failed to create sandbox clock"; "reason" => format!("{}", e));
+            panic!("Failed to create sandbox clock, reason: {}", e)
+        });
+    info!(log, "Sandbox clock created"; "file" => clock.path().display().to_string());
+
     // create a thread safe reference to the runner struct
     let runner = Arc::new(RwLock::new(node_runner::LightNodeRunner::new(
         "light-node-0",
         env.light_node_path,
         env.protocol_runner_path,
+        clock.path().to_path_buf(),
     )));
 
     // create a thread safe reference to the client runner struct
     let client_runner = Arc::new(RwLock::new(tezos_client_runner::TezosClientRunner::new(
         "tezos-client",
-        env.tezos_client_path,
+        clock.clone(),
     )));
 
     // the port to open the rpc server on
     let rpc_port = env.sandbox_rpc_port;
 
     // combined warp filter
-    let api = filters::sandbox(log.clone(), runner, client_runner, peers);
+    let api = filters::sandbox(log.clone(), runner, client_runner, peers, topology, clock);
 
     info!(log, "Start to serving Sandbox RPCs");
 
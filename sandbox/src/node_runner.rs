@@ -1,6 +1,7 @@
 // Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
 // SPDX-License-Identifier: MIT
 
+use std::collections::HashMap;
 use std::fmt;
 use std::fs;
 use std::io::{BufReader, Read};
@@ -25,8 +26,8 @@ use crate::{create_temp_dir, rand_chars};
 #[derive(Debug, Error)]
 pub enum LightNodeRunnerError {
     /// Already running error.
-    #[error("Sandbox light-node is already running")]
-    NodeAlreadyRunning,
+    #[error("Sandbox light-node is already running, node_ref: {node_ref}")]
+    NodeAlreadyRunning { node_ref: NodeRpcIpPort },
 
     /// Already running error.
     #[error("Sandbox light-node is not running, node_ref: {node_ref}")]
@@ -57,13 +58,14 @@ impl reject::Reject for LightNodeRunnerError {}
 /// Thread safe reference to a shared Runner
 pub type LightNodeRunnerRef = Arc<RwLock<LightNodeRunner>>;
 
-const SANDBOX_NODE_IP: &str = "localhost";
+pub(crate) const SANDBOX_NODE_IP: &str = "localhost";
 const NODE_CONFIG_RPC_PORT: &str = "rpc_port";
 const NODE_CONFIG_TEZOS_DATA_DIR: &str = "tezos_data_dir";
 const NODE_CONFIG_TEZEDGE_DATA_DIR: &str = "bootstrap_db_path";
 const NODE_CONFIG_IDENTITY_FILE: &str = "identity_file";
 const NODE_CONFIG_PATCH_CONTEXT_JSON_FILE_PATH: &str = "sandbox_patch_context_json_file";
 const NODE_CONFIG_PROTOCOL_RUNNER: &str = "protocol_runner";
+const NODE_CONFIG_SANDBOX_CLOCK_FILE: &str = "sandbox_clock_file";
 
 /// RPC ip/port, where is light node listening for rpc requests
 #[derive(PartialEq, Eq, Hash, Clone, Debug, Serialize, Deserialize)]
@@ -108,15 +110,15 @@ impl fmt::Display for NodeRpcIpPort {
     }
 }
 
-/// Struct that holds info about the running child process
+/// Struct that holds info about the running child processes
 pub struct LightNodeRunner {
     executable_path: PathBuf,
     protocol_runner_executable_path: PathBuf,
+    /// Clock file shared by all sandbox nodes
+    clock_file: PathBuf,
     _name: String,
 
-    // TODO: TE-213 Launch multiple nodes + wrap process with rpc ip/port
-    // TODO: in main we have peers
-    process: Option<Child>,
+    processes: HashMap<NodeRpcIpPort, Child>,
 }
 
 impl LightNodeRunner {
@@ -126,12 +128,14 @@ impl LightNodeRunner {
         name: &str,
         executable_path: PathBuf,
         protocol_runner_executable_path: PathBuf,
+        clock_file: PathBuf,
     ) -> Self {
         Self {
             executable_path,
             protocol_runner_executable_path,
+            clock_file,
             _name: name.to_string(),
-            process: None,
+            processes: HashMap::new(),
         }
     }
 
@@ -146,12 +150,12 @@ impl LightNodeRunner {
         cfg: serde_json::Value,
         log: &Logger,
     ) -> Result<(NodeRpcIpPort, PathBuf), LightNodeRunnerError> {
-        if self.is_running() {
-            Err(LightNodeRunnerError::NodeAlreadyRunning)
-        } else {
-            // parse rpc settings
-            let node = NodeRpcIpPort::new(&cfg)?;
+        // parse rpc settings
+        let node = NodeRpcIpPort::new(&cfg)?;
 
+        if self.is_running(&node) {
+            Err(LightNodeRunnerError::NodeAlreadyRunning { node_ref: node })
+        } else {
             // one node will have its own temp folder for data (identity, dbs, ...)
             let data_dir =
                 create_temp_dir("sandbox-node").map_err(|err| LightNodeRunnerError::IOError {
@@ -208,9 +212,7 @@ impl LightNodeRunner {
                 _ => {
                     // somehow process was not finished, so we need to kill him
                     match Self::send_sigint(process.id()) {
-                        Ok(()) => {
-                            self.process = None;
-                        }
+                        Ok(()) => (),
                         // if for some reason, the SIGINT fails to end the process, kill it with SIGKILL
                         Err(e) => {
                             let error_msg = handle_stderr(&mut process);
@@ -259,42 +261,48 @@ impl LightNodeRunner {
 
             // the process started up OK, but we restart it to enable normal logging (stderr won't be piped)
             // start the process again without piped stdout/stderr, e.g. to have ability log to syslog in docker to tezos-debugger
-            let process = Command::new(&self.executable_path)
-                .args(Self::construct_args(cfg, false)?)
-                .spawn()
-                .map_err(|err| LightNodeRunnerError::IOError {
-                    message: "Failed to start light-node".to_string(),
-                    reason: err,
-                })?;
+            let process = Self::start_process(&self.executable_path, &cfg)?;
 
-            self.process = Some(process);
+            self.processes.insert(node.clone(), process);
             Ok((node, data_dir))
         }
     }
 
     /// Shut down the light-node
     pub fn shutdown(&mut self, node_ref: &NodeRpcIpPort) -> Result<(), LightNodeRunnerError> {
-        if self.is_running() {
-            let process = self.process.as_mut().unwrap();
+        if self.is_running(node_ref) {
+            let mut process = self.processes.remove(node_ref).unwrap();
             // kill with SIGINT (ctr-c)
             match Self::send_sigint(process.id()) {
-                Ok(()) => {
-                    self.process = None;
-                    Ok(())
-                }
+                Ok(()) => Ok(()),
                 // if for some reason, the SIGINT fails to end the process, kill it with SIGKILL
                 Err(_) => {
-                    Self::terminate_ref(process);
+                    Self::terminate_ref(&mut process);
                     Ok(())
                 }
             }
         } else {
+            self.processes.remove(node_ref);
             Err(LightNodeRunnerError::NodeNotRunning {
                 node_ref: node_ref.clone(),
             })
         }
     }
 
+    /// Start the light-node process without piped stdout/stderr
+    fn start_process(
+        executable_path: &Path,
+        cfg: &serde_json::Value,
+    ) -> Result<Child, LightNodeRunnerError> {
+        Command::new(executable_path)
+            .args(Self::construct_args(cfg.clone(), false)?)
+            .spawn()
+            .map_err(|err| LightNodeRunnerError::IOError {
+                message: "Failed to start light-node".to_string(),
+                reason: err,
+            })
+    }
+
     fn terminate_ref(process: &mut Child) {
         match process.wait_timeout(Self::PROCESS_WAIT_TIMEOUT).unwrap() {
             Some(_) => (),
@@ -305,8 +313,8 @@ impl LightNodeRunner {
         };
     }
 
-    fn is_running(&mut self) -> bool {
-        if let Some(process) = &mut self.process {
+    fn is_running(&mut self, node_ref: &NodeRpcIpPort) -> bool {
+        if let Some(process) = self.processes.get_mut(node_ref) {
             matches!(process.try_wait(), Ok(None))
         } else {
             false
@@ -344,6 +352,7 @@ impl LightNodeRunner {
     /// 1. replaces [NODE_CONFIG_TEZOS_DATA_DIR][NODE_CONFIG_TEZEDGE_DATA_DIR][NODE_CONFIG_IDENTITY_FILE] with custom names prefixed [sandbox_data_dir]
     /// 2. replaces [NODE_CONFIG_PROTOCOL_RUNNER] with own settings
     /// 3. stores [sandbox_patch_context_json] to tempfile and sets it as [NODE_CONFIG_PATCH_CONTEXT_JSON_FILE_PATH] (if present)
+    /// 4. sets [NODE_CONFIG_SANDBOX_CLOCK_FILE] to the sandbox clock file
     fn ensure_sandbox_cfg(
         &self,
         mut cfg: serde_json::Value,
@@ -433,6 +442,15 @@ impl LightNodeRunner {
                                "new_value" => sandbox_patch_context_json_file.as_path().display().to_string());
                 }
             }
+
+            // 4.
+            let clock_file = self.clock_file.as_path().display().to_string();
+            if let Some(old_value) = map.insert(
+                NODE_CONFIG_SANDBOX_CLOCK_FILE.to_string(),
+                serde_json::Value::String(clock_file.clone()),
+            ) {
+                info!(log, "Changing sandbox node configuration"; "property" => NODE_CONFIG_SANDBOX_CLOCK_FILE.to_string(), "old_value" => old_value.to_string(), "new_value" => clock_file);
+            }
         }
         Ok(cfg)
     }
@@ -3,21 +3,28 @@
 
 use std::collections::HashMap;
 use std::fs;
-use std::io::{BufReader, Read};
 use std::path::PathBuf;
-use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
+use baker::{BakerConfig, Protocol};
+use crypto::hash::ChainId;
 use itertools::Itertools;
+use reqwest::blocking::{Client, RequestBuilder};
+use sandbox_clock::SandboxClock;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use slog::{error, info, warn, Logger};
 use thiserror::Error;
+use tokio::sync::oneshot;
 use warp::http::StatusCode;
 use warp::reject;
 
+use crate::genesis::{ActivationCommand, ShellHeader, ACTIVATOR_SECRET_KEY};
 use crate::handlers::ErrorMessage;
 use crate::node_runner::NodeRpcIpPort;
-use crate::rand_chars;
 
 #[derive(Debug, Error)]
 pub enum TezosClientRunnerError {
@@ -47,6 +54,30 @@ pub enum TezosClientRunnerError {
 
     #[error("System error - sandbox data dir was not initialized for node_ref: {node_ref}")]
     SandboxDataDirNotInitialized { node_ref: NodeRpcIpPort },
+
+    /// Failed to forge the activation block.
+    #[error("Error while forging the activation block, reason: {reason}")]
+    ForgeError { reason: String },
+
+    /// Node rpc call error.
+    #[error("Node rpc call error, reason: {reason}")]
+    NodeRpcError { reason: String },
+
+    /// The protocol of the node is not supported by the baker.
+    #[error("Protocol ({protocol}) is not supported by the baker")]
+    UnsupportedProtocol { protocol: String },
+
+    /// The baker did not bake a block in time.
+    #[error("Baker ({alias}) did not bake a block within {timeout:?}")]
+    BakeTimeout { alias: String, timeout: Duration },
+
+    /// The baker stopped without baking a block.
+    #[error("Baker ({alias}) stopped without baking a block")]
+    BakerStopped { alias: String },
+
+    /// The baker of the wallet is still baking the previous block.
+    #[error("Baker ({alias}) is still running")]
+    BakerAlreadyRunning { alias: String },
 }
 
 impl From<std::io::Error> for TezosClientRunnerError {
@@ -76,12 +107,24 @@ pub struct Wallet {
     initial_balance: String,
 }
 
+impl Wallet {
+    pub fn alias(&self) -> &str {
+        &self.alias
+    }
+}
+
 /// The json body incoming with the bake request containing the alias for the wallet to bake with
 #[derive(Clone, Debug, Deserialize)]
 pub struct BakeRequest {
     alias: String,
 }
 
+impl BakeRequest {
+    pub fn new(alias: String) -> Self {
+        Self { alias }
+    }
+}
+
 #[derive(Serialize)]
 pub struct TezosClientErrorReply {
     pub message: String,
@@ -89,20 +132,95 @@ pub struct TezosClientErrorReply {
 }
 
 /// Like wallets we need to store per node, because, if we run multiple nodes, we can have different wallet setting per node
-#[derive(Clone)]
 pub struct SandboxData {
     pub data_dir_path: PathBuf,
     pub wallets: HashMap<String, Wallet>,
+
+    /// Bakers started against the node, by wallet alias, they are joined once finished
+    bakers: HashMap<String, BakerThread>,
+}
+
+impl SandboxData {
+    /// Join the bakers which already stopped
+    fn join_finished_bakers(&mut self) {
+        let finished = self
+            .bakers
+            .iter()
+            .filter(|(_, baker)| baker.handle.is_finished())
+            .map(|(alias, _)| alias.clone())
+            .collect_vec();
+        for alias in finished {
+            if let Some(baker) = self.bakers.remove(&alias) {
+                let _ = baker.handle.join();
+            }
+        }
+    }
+}
+
+/// Thread running the baker from `apps/baker`
+struct BakerThread {
+    terminating: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+/// Baker started by [TezosClientRunner::start_baker], it stops once it bakes a block
+pub struct Baking {
+    alias: String,
+    endpoint: String,
+    /// Level of the head when the baker was started
+    level: i32,
+    terminating: Arc<AtomicBool>,
+    stopped: oneshot::Receiver<()>,
+}
+
+impl Baking {
+    /// Wait for the baker to bake the block, the baker is stopped if it does not bake it in time
+    pub async fn wait(self) -> Result<TezosClientReply, TezosClientRunnerError> {
+        let timeout = TezosClientRunner::BAKE_TIMEOUT;
+        if tokio::time::timeout(timeout, self.stopped).await.is_err() {
+            self.terminating.store(true, Ordering::SeqCst);
+            return Err(TezosClientRunnerError::BakeTimeout {
+                alias: self.alias,
+                timeout,
+            });
+        }
+
+        let rpc_error = |e: reqwest::Error| TezosClientRunnerError::NodeRpcError {
+            reason: e.to_string(),
+        };
+        let head = reqwest::get(format!("{}chains/main/blocks/head/header", self.endpoint))
+            .await
+            .map_err(rpc_error)?
+            .json::<HeadHeader>()
+            .await
+            .map_err(rpc_error)?;
+        // the baker is stopped without baking when the node is stopped
+        if head.level <= self.level {
+            return Err(TezosClientRunnerError::BakerStopped { alias: self.alias });
+        }
+        Ok(TezosClientReply::new(
+            format!("Block {} at level {} baked", head.hash, head.level),
+            String::new(),
+        ))
+    }
+}
+
+#[derive(Deserialize)]
+struct HeadHeader {
+    hash: String,
+    level: i32,
 }
 
 /// Thread-safe reference to the client runner
 pub type TezosClientRunnerRef = Arc<RwLock<TezosClientRunner>>;
 
-/// Structure holding data to use a tezos-client binary
-#[derive(Clone)]
+/// Structure doing the work of the tezos-client for the sandbox nodes: keeps the wallets,
+/// activates protocols and bakes with the baker from `apps/baker`
 pub struct TezosClientRunner {
     pub name: String,
-    pub executable_path: PathBuf,
+
+    /// Sandbox clock shared with the nodes, used by the bakers
+    clock: SandboxClock,
 
     /// Temporary data per node
     sandbox_data: HashMap<NodeRpcIpPort, SandboxData>,
@@ -135,10 +253,13 @@ impl Default for TezosClientReply {
 }
 
 impl TezosClientRunner {
-    pub fn new(name: &str, executable_path: PathBuf) -> Self {
+    /// How long to wait for a baker to bake a block
+    const BAKE_TIMEOUT: Duration = Duration::from_secs(60);
+
+    pub fn new(name: &str, clock: SandboxClock) -> Self {
         Self {
             name: name.to_string(),
-            executable_path,
+            clock,
             sandbox_data: HashMap::default(),
         }
     }
@@ -149,6 +270,7 @@ impl TezosClientRunner {
             SandboxData {
                 data_dir_path,
                 wallets: HashMap::default(),
+                bakers: HashMap::default(),
             },
         );
     }
@@ -157,27 +279,29 @@ impl TezosClientRunner {
         &self,
         node_ref: &NodeRpcIpPort,
     ) -> Result<&HashMap<String, Wallet>, TezosClientRunnerError> {
-        match self.sandbox_data.get(node_ref) {
-            Some(data) => Ok(&data.wallets),
-            None => Err(TezosClientRunnerError::SandboxDataDirNotInitialized {
+        Ok(&self.sandbox_data(node_ref)?.wallets)
+    }
+
+    fn sandbox_data(
+        &self,
+        node_ref: &NodeRpcIpPort,
+    ) -> Result<&SandboxData, TezosClientRunnerError> {
+        self.sandbox_data.get(node_ref).ok_or_else(|| {
+            TezosClientRunnerError::SandboxDataDirNotInitialized {
                 node_ref: node_ref.clone(),
-            }),
-        }
+            }
+        })
     }
 
-    fn insert_wallet(
+    fn sandbox_data_mut(
         &mut self,
         node_ref: &NodeRpcIpPort,
-        wallet: Wallet,
-    ) -> Result<(), TezosClientRunnerError> {
-        if let Some(data) = self.sandbox_data.get_mut(node_ref) {
-            data.wallets.insert(wallet.alias.clone(), wallet);
-            Ok(())
-        } else {
-            Err(TezosClientRunnerError::SandboxDataDirNotInitialized {
+    ) -> Result<&mut SandboxData, TezosClientRunnerError> {
+        self.sandbox_data.get_mut(node_ref).ok_or_else(|| {
+            TezosClientRunnerError::SandboxDataDirNotInitialized {
                 node_ref: node_ref.clone(),
-            })
-        }
+            }
+        })
     }
 
     /// Activate a protocol with the provided parameters
@@ -187,15 +311,6 @@ impl TezosClientRunner {
         node_ref: &NodeRpcIpPort,
         log: &Logger,
     ) -> Result<TezosClientReply, TezosClientRunnerError> {
-        let data_dir = match self.sandbox_data.get(node_ref) {
-            Some(data) => &data.data_dir_path,
-            None => {
-                return Err(TezosClientRunnerError::SandboxDataDirNotInitialized {
-                    node_ref: node_ref.clone(),
-                })
-            }
-        };
-
         // get as mutable object, so we can insert the hardcoded bootstrap accounts
         let params = if let Some(params) = activation_parameters.protocol_parameters.as_object_mut()
         {
@@ -217,59 +332,71 @@ impl TezosClientRunner {
         let sandbox_accounts = serde_json::json!(wallet_activation);
         params.insert("bootstrap_accounts".to_string(), sandbox_accounts);
 
-        // create a temporary file, the tezos-client requires the parameters to be passed in a .json file
-        let protocol_parameters_json_file =
-            data_dir.join(format!("protocol_parameters-{}.json", rand_chars(5)));
-        fs::write(
-            &protocol_parameters_json_file,
-            activation_parameters.protocol_parameters.to_string(),
-        )
-        .map_err(|err| TezosClientRunnerError::IOError { reason: err })?;
-
-        let mut client_output: TezosClientReply = Default::default();
-        self.run_client(
-            node_ref,
-            [
-                "--block",
-                "genesis",
-                "activate",
-                "protocol",
-                &activation_parameters.protocol_hash,
-                "with",
-                "fitness",
-                "1",
-                "and",
-                "key",
-                "activator",
-                "and",
-                "parameters",
-                &protocol_parameters_json_file
-                    .as_path()
-                    .display()
-                    .to_string(),
-                "--timestamp",
-                &activation_parameters.timestamp,
-            ]
-            .to_vec(),
-            &mut client_output,
-            log,
+        let command = ActivationCommand::new(
+            &activation_parameters.protocol_hash,
+            &activation_parameters.protocol_parameters,
         )?;
 
-        Ok(client_output)
+        info!(log, "Activating protocol"; "protocol" => &activation_parameters.protocol_hash, "timestamp" => &activation_parameters.timestamp, "node_ref" => format!("{}", node_ref));
+
+        let endpoint = endpoint(node_ref);
+        let timestamp = activation_parameters.timestamp;
+        let injected = call_node(move |client| {
+            let chain_id: ChainId =
+                node_call(client.get(format!("{}chains/main/chain_id", endpoint)))?;
+
+            #[derive(Deserialize)]
+            struct PreapplyResult {
+                shell_header: ShellHeader,
+            }
+            let preapplied: PreapplyResult = node_call(
+                client
+                    .post(format!(
+                        "{}chains/main/blocks/genesis/helpers/preapply/block",
+                        endpoint
+                    ))
+                    .query(&[("timestamp", timestamp)])
+                    .json(&serde_json::json!({
+                        "protocol_data": command.to_json(),
+                        "operations": [],
+                    })),
+            )?;
+
+            let signed = command.forge_signed(preapplied.shell_header, &chain_id)?;
+            node_call::<String>(client.post(format!("{}injection/block", endpoint)).json(
+                &serde_json::json!({
+                    "data": hex::encode(signed),
+                    "operations": [],
+                }),
+            ))
+        });
+
+        match injected {
+            Ok(block_hash) => Ok(TezosClientReply::new(
+                format!("Injected {}", block_hash),
+                String::new(),
+            )),
+            // the node refused the activation, report it the same way as the tezos-client did
+            Err(TezosClientRunnerError::NodeRpcError { reason }) => {
+                Ok(TezosClientReply::new(String::new(), reason))
+            }
+            Err(e) => Err(e),
+        }
     }
 
-    /// Bake a block with the bootstrap1 account
-    pub fn bake_block(
-        &self,
+    /// Start baking a block with the requested account, or with an arbitrary one if not specified
+    ///
+    /// The baker stops once it bakes the block, [Baking::wait] waits for it without
+    /// holding the runner.
+    pub fn start_baker(
+        &mut self,
         request: Option<BakeRequest>,
         node_ref: &NodeRpcIpPort,
         log: &Logger,
-    ) -> Result<TezosClientReply, TezosClientRunnerError> {
-        let mut client_output: TezosClientReply = Default::default();
-
+    ) -> Result<Baking, TezosClientRunnerError> {
         let alias = if let Some(request) = request {
             if let Some(wallet) = self.wallets(node_ref)?.get(&request.alias) {
-                &wallet.alias
+                wallet.alias.clone()
             } else {
                 return Err(TezosClientRunnerError::NonexistantWallet {
                     alias: request.alias,
@@ -278,7 +405,7 @@ impl TezosClientRunner {
         } else {
             // if there is no wallet provided in the request (GET) set the alias to be an arbitrary wallet
             if let Some(wallet) = self.wallets(node_ref)?.values().next() {
-                &wallet.alias
+                wallet.alias.clone()
             } else {
                 return Err(TezosClientRunnerError::NonexistantWallet {
                     alias: "-none-".to_string(),
@@ -286,17 +413,81 @@ impl TezosClientRunner {
             }
         };
 
-        self.run_client(
-            node_ref,
-            ["bake", "for", alias, "--minimal-timestamp"].to_vec(),
-            &mut client_output,
-            log,
-        )?;
+        let data = self.sandbox_data_mut(node_ref)?;
+        data.join_finished_bakers();
+        if data.bakers.contains_key(&alias) {
+            return Err(TezosClientRunnerError::BakerAlreadyRunning { alias });
+        }
 
-        Ok(client_output)
+        #[derive(Deserialize)]
+        struct HeadProtocols {
+            next_protocol: String,
+        }
+
+        let endpoint = endpoint(node_ref);
+        let (head, protocols) = {
+            let endpoint = endpoint.clone();
+            call_node(move |client| {
+                let head: HeadHeader =
+                    node_call(client.get(format!("{}chains/main/blocks/head/header", endpoint)))?;
+                let protocols: HeadProtocols = node_call(
+                    client.get(format!("{}chains/main/blocks/head/protocols", endpoint)),
+                )?;
+                Ok((head, protocols))
+            })?
+        };
+
+        // the baker follows the protocol of the node, but refuses to sign for an unknown one
+        Protocol::from_hash(&protocols.next_protocol).ok_or_else(|| {
+            TezosClientRunnerError::UnsupportedProtocol {
+                protocol: protocols.next_protocol.clone(),
+            }
+        })?;
+        let config = BakerConfig {
+            base_dir: data.data_dir_path.clone(),
+            bakers: vec![alias.clone()],
+            endpoint: reqwest::Url::parse(&endpoint).map_err(|e| {
+                TezosClientRunnerError::NodeRpcError {
+                    reason: e.to_string(),
+                }
+            })?,
+            archive: false,
+            liquidity_baking_toggle_vote: Default::default(),
+            clock: Some(self.clock.clone()),
+            metrics_addr: None,
+            max_blocks: Some(1),
+        };
+
+        info!(log, "Starting baker"; "alias" => &alias, "protocol" => &protocols.next_protocol, "node_ref" => format!("{}", node_ref));
+        let terminating = Arc::new(AtomicBool::new(false));
+        let (stopped_tx, stopped) = oneshot::channel();
+        let handle = {
+            let terminating = terminating.clone();
+            thread::Builder::new()
+                .name(format!("baker-{}", alias))
+                .spawn(move || {
+                    baker::run(config, terminating);
+                    let _ = stopped_tx.send(());
+                })?
+        };
+        data.bakers.insert(
+            alias.clone(),
+            BakerThread {
+                terminating: terminating.clone(),
+                handle,
+            },
+        );
+
+        Ok(Baking {
+            alias,
+            endpoint,
+            level: head.level,
+            terminating,
+            stopped,
+        })
     }
 
-    /// Initialize the accounts in the tezos-client
+    /// Initialize the accounts, they are stored as the `secret_keys` file of the tezos-client, which the baker reads
     pub fn init_client_data(
         &mut self,
         requested_wallets: SandboxWallets,
@@ -305,100 +496,91 @@ impl TezosClientRunner {
     ) -> Result<TezosClientReply, TezosClientRunnerError> {
         let mut client_output: TezosClientReply = Default::default();
 
-        self.run_client(
-            node_ref,
-            [
-                "import",
-                "secret",
-                "key",
-                "activator",
-                "unencrypted:edsk31vznjHSSpGExDMHYASz45VZqXN4DPxvsa4hAyY8dHM28cZzp6",
-            ]
-            .to_vec(),
-            &mut client_output,
-            log,
-        )?;
-
+        let data = self.sandbox_data_mut(node_ref)?;
         for wallet in requested_wallets {
-            self.run_client(
-                node_ref,
-                [
-                    "import",
-                    "secret",
-                    "key",
-                    &wallet.alias,
-                    &format!("unencrypted:{}", &wallet.secret_key),
-                ]
-                .to_vec(),
-                &mut client_output,
-                log,
-            )?;
-            self.insert_wallet(node_ref, wallet)?;
+            client_output.output += &format!(
+                "Tezos address added: {} ({})\n",
+                wallet.public_key_hash, wallet.alias
+            );
+            data.wallets.insert(wallet.alias.clone(), wallet);
+        }
+
+        #[derive(Serialize)]
+        struct SecretKeyRecord<'a> {
+            name: &'a str,
+            value: String,
         }
 
+        let secret_keys = std::iter::once(SecretKeyRecord {
+            name: "activator",
+            value: format!("unencrypted:{}", ACTIVATOR_SECRET_KEY),
+        })
+        .chain(data.wallets.values().map(|wallet| SecretKeyRecord {
+            name: &wallet.alias,
+            value: format!("unencrypted:{}", wallet.secret_key),
+        }))
+        .collect::<Vec<_>>();
+
+        let secret_keys_file = data.data_dir_path.join("secret_keys");
+        info!(log, "Writing wallets"; "file" => secret_keys_file.as_path().display().to_string());
+        fs::write(secret_keys_file, serde_json::to_string(&secret_keys)?)?;
+
         Ok(client_output)
     }
 
-    /// Cleanup the tezos-client directory
+    /// Stop the bakers and cleanup the data directory
     pub fn cleanup(&mut self, node_ref: &NodeRpcIpPort) -> Result<(), anyhow::Error> {
         // clear node sandbox data
         if let Some(data) = self.sandbox_data.remove(node_ref) {
+            // the bakers terminate once their state is stored, they must not write to the removed dir
+            for baker in data.bakers.values() {
+                baker.terminating.store(true, Ordering::SeqCst);
+            }
+            for (_, baker) in data.bakers {
+                let _ = baker.handle.join();
+            }
             // remove work dir
             fs::remove_dir_all(&data.data_dir_path)?;
         }
 
         Ok(())
     }
+}
 
-    /// Private method to run the tezos-client as a subprocess and wait for its completion
-    ///
-    /// args - should contains just command args
-    ///
-    /// --base-dir | -A | -P are added automatically
-    fn run_client(
-        &self,
-        node_ref: &NodeRpcIpPort,
-        command_args: Vec<&str>,
-        client_output: &mut TezosClientReply,
-        log: &Logger,
-    ) -> Result<(), TezosClientRunnerError> {
-        let data_dir = match self.sandbox_data.get(node_ref) {
-            Some(data) => data.data_dir_path.as_path().display().to_string(),
-            None => {
-                return Err(TezosClientRunnerError::SandboxDataDirNotInitialized {
-                    node_ref: node_ref.clone(),
-                })
-            }
-        };
-        let endpoint = format!("http://{}:{}", &node_ref.ip, &node_ref.port);
-
-        let mut args = [
-            // add base-dir
-            "--base-dir",
-            &data_dir,
-            // add client ip/port
-            "--endpoint",
-            &endpoint,
-        ]
-        .to_vec();
-        args.extend(command_args);
-
-        info!(log, "Calling tezos-client ({})", self.executable_path.as_path().display().to_string(); "command" => args.join(" "));
-
-        // call tezos-client
-        let output = Command::new(&self.executable_path)
-            .args(args)
-            .output()
-            .map_err(|err| TezosClientRunnerError::IOError { reason: err })?;
-
-        let _ = BufReader::new(output.stdout.as_slice()).read_to_string(&mut client_output.output);
-        let _ = BufReader::new(output.stderr.as_slice()).read_to_string(&mut client_output.error);
+fn endpoint(node_ref: &NodeRpcIpPort) -> String {
+    format!("http://{}:{}/", &node_ref.ip, &node_ref.port)
+}
 
-        Ok(())
+/// The node rpc is called with the blocking client, which must not run on the threads of the async runtime
+fn call_node<T, F>(f: F) -> Result<T, TezosClientRunnerError>
+where
+    T: Send + 'static,
+    F: FnOnce(Client) -> Result<T, TezosClientRunnerError> + Send + 'static,
+{
+    thread::spawn(move || f(Client::new()))
+        .join()
+        .unwrap_or_else(|_| {
+            Err(TezosClientRunnerError::NodeRpcError {
+                reason: "node rpc call panicked".to_string(),
+            })
+        })
+}
+
+fn node_call<T: DeserializeOwned>(request: RequestBuilder) -> Result<T, TezosClientRunnerError> {
+    let rpc_error = |e: reqwest::Error| TezosClientRunnerError::NodeRpcError {
+        reason: e.to_string(),
+    };
+    let response = request.send().map_err(rpc_error)?;
+    if response.status().is_success() {
+        response.json().map_err(rpc_error)
+    } else {
+        Err(TezosClientRunnerError::NodeRpcError {
+            reason: response.text().map_err(rpc_error)?,
+        })
     }
 }
 
-/// Construct a reply using the output from the tezos-client
+/// Construct a reply using the output of the client call
 pub fn reply_with_client_output(
     reply: TezosClientReply,
     log: &Logger,
@@ -0,0 +1,370 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Multiple sandbox nodes connected to each other according to the requested graph.
+//!
+//! Nodes are started as private nodes with `--peers` set to their neighbours, so they
+//! do not discover the rest of the network. A link is partitioned (healed) by graylisting
+//! (removing from graylist) the p2p address of each of its nodes on the other one,
+//! using the unsafe graylist RPC of the nodes, so the nodes keep running.
+
+use std::collections::BTreeSet;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::net::TcpStream;
+use warp::reject;
+
+use crate::node_runner::{NodeRpcIpPort, SANDBOX_NODE_IP};
+use crate::tezos_client_runner::{SandboxWallets, TezosProtcolActivationParameters};
+
+#[derive(Debug, Error)]
+pub enum TopologyError {
+    #[error("Sandbox topology is already running")]
+    TopologyAlreadyRunning,
+
+    #[error("Sandbox topology is not running")]
+    TopologyNotRunning,
+
+    #[error("Invalid topology request - {reason}")]
+    InvalidRequest { reason: String },
+
+    #[error("There is no link between nodes {from} and {to} in the topology")]
+    UnknownLink { from: usize, to: usize },
+
+    #[error("Sandbox node {index} is not reachable, node_ref: {node_ref}")]
+    NodeRpcUnreachable {
+        index: usize,
+        node_ref: NodeRpcIpPort,
+    },
+
+    #[error(
+        "Failed to patch graylist of the node {node_ref} with point {point}, reason: {reason}"
+    )]
+    NodeGraylistPatchFailed {
+        node_ref: NodeRpcIpPort,
+        point: SocketAddr,
+        reason: String,
+    },
+}
+
+impl reject::Reject for TopologyError {}
+
+/// Thread safe reference to a running topology
+pub type TopologyRef = Arc<Mutex<Option<Topology>>>;
+
+/// Connection between two nodes, identified by their indexes in the topology
+pub type Link = (usize, usize);
+
+const SANDBOX_PEER_IP: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+const NODE_CONFIG_RPC_PORT: &str = "rpc_port";
+const NODE_CONFIG_P2P_PORT: &str = "p2p_port";
+const NODE_CONFIG_WEBSOCKET_ADDRESS: &str = "websocket_address";
+const NODE_CONFIG_PEERS: &str = "peers";
+const NODE_CONFIG_PRIVATE_NODE: &str = "private_node";
+const NODE_CONFIG_ALLOW_UNSAFE_RPC: &str = "allow_unsafe_rpc";
+
+/// The json body incoming with the request to start a topology
+#[derive(Clone, Debug, Deserialize)]
+pub struct TopologyRequest {
+    /// Light-node configuration shared by all nodes, the same as for the `start` endpoint.
+    /// `rpc_port`, `p2p_port` and `websocket_address` port are incremented by the node index.
+    pub node_config: serde_json::Value,
+    /// Number of nodes to start
+    pub nodes: usize,
+    /// Connections between nodes, all nodes are connected to each other if not set
+    #[serde(default)]
+    pub connections: Option<Vec<Link>>,
+    /// Aliases of the wallets baking on a node, indexed by the node index
+    #[serde(default)]
+    pub bakers: Vec<Vec<String>>,
+    /// Wallets imported to tezos-client of every node
+    #[serde(default)]
+    pub wallets: SandboxWallets,
+    /// Protocol activated (once) on the first node when all nodes are started
+    #[serde(default)]
+    pub activation: Option<TezosProtcolActivationParameters>,
+}
+
+/// The json body incoming with the partition/heal request
+#[derive(Clone, Debug, Deserialize)]
+pub struct TopologyLinksRequest {
+    /// Links to partition/heal, empty means all links
+    #[serde(default)]
+    pub links: Vec<Link>,
+}
+
+/// The json body incoming with the request to bake a block on a topology node
+#[derive(Clone, Debug, Deserialize)]
+pub struct TopologyBakeRequest {
+    pub node: usize,
+    /// Baker of the node, the first one is used if not set
+    #[serde(default)]
+    pub alias: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TopologyNode {
+    pub node_ref: NodeRpcIpPort,
+    pub p2p_port: u16,
+    pub bakers: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Topology {
+    pub nodes: Vec<TopologyNode>,
+    pub links: BTreeSet<Link>,
+    pub partitioned: BTreeSet<Link>,
+}
+
+impl Topology {
+    /// Time to wait for node rpc to become reachable
+    pub const NODE_RPC_TIMEOUT: Duration = Duration::from_secs(60);
+
+    pub fn new(request: &TopologyRequest) -> Result<Self, TopologyError> {
+        if request.nodes == 0 {
+            return Err(invalid_request("at least one node is required"));
+        }
+        if request.bakers.len() > request.nodes {
+            return Err(invalid_request(
+                "bakers are specified for more nodes than requested",
+            ));
+        }
+        for alias in request.bakers.iter().flatten() {
+            if !request.wallets.iter().any(|wallet| wallet.alias() == alias) {
+                return Err(invalid_request(&format!(
+                    "baker `{}` is not among the wallets",
+                    alias
+                )));
+            }
+        }
+
+        let rpc_port = config_port(&request.node_config, NODE_CONFIG_RPC_PORT, request.nodes)?;
+        let p2p_port = config_port(&request.node_config, NODE_CONFIG_P2P_PORT, request.nodes)?;
+
+        let links = match &request.connections {
+            Some(connections) => connections
+                .iter()
+                .map(|link| {
+                    let link = normalize(*link)?;
+                    if link.1 < request.nodes {
+                        Ok(link)
+                    } else {
+                        Err(invalid_request(&format!(
+                            "connection {:?} refers to a nonexistent node",
+                            link
+                        )))
+                    }
+                })
+                .collect::<Result<_, _>>()?,
+            None => (0..request.nodes)
+                .flat_map(|from| (from + 1..request.nodes).map(move |to| (from, to)))
+                .collect(),
+        };
+
+        let nodes = (0..request.nodes)
+            .map(|index| TopologyNode {
+                node_ref: NodeRpcIpPort {
+                    ip: SANDBOX_NODE_IP.to_string(),
+                    port: rpc_port + index as u16,
+                },
+                p2p_port: p2p_port + index as u16,
+                bakers: request.bakers.get(index).cloned().unwrap_or_default(),
+            })
+            .collect();
+
+        Ok(Self {
+            nodes,
+            links,
+            partitioned: BTreeSet::new(),
+        })
+    }
+
+    /// Configuration for the node with `index`, derived from the shared `node_config`
+    pub fn node_config(&self, index: usize, node_config: &serde_json::Value) -> serde_json::Value {
+        let mut cfg = node_config.clone();
+        if let Some(map) = cfg.as_object_mut() {
+            let node = &self.nodes[index];
+            map.insert(NODE_CONFIG_RPC_PORT.to_string(), node.node_ref.port.into());
+            map.insert(NODE_CONFIG_P2P_PORT.to_string(), node.p2p_port.into());
+
+            if let Some(mut address) = map
+                .get(NODE_CONFIG_WEBSOCKET_ADDRESS)
+                .and_then(|address| address.as_str())
+                .and_then(|address| address.parse::<SocketAddr>().ok())
+            {
+                address.set_port(address.port().saturating_add(index as u16));
+                map.insert(
+                    NODE_CONFIG_WEBSOCKET_ADDRESS.to_string(),
+                    address.to_string().into(),
+                );
+            }
+
+            for (key, value) in self.peers_config(index) {
+                if value.is_null() {
+                    map.remove(&key);
+                } else {
+                    map.insert(key, value);
+                }
+            }
+
+            // graylist RPC used to partition the links is unsafe
+            map.insert(NODE_CONFIG_ALLOW_UNSAFE_RPC.to_string(), "".into());
+        }
+        cfg
+    }
+
+    /// Peer settings for the node with `index`, based on the links of the topology
+    ///
+    /// `null` means that the property should be removed from the node configuration.
+    fn peers_config(&self, index: usize) -> serde_json::Map<String, serde_json::Value> {
+        let peers = self
+            .links
+            .iter()
+            .filter_map(|(from, to)| {
+                if *from == index {
+                    Some(*to)
+                } else if *to == index {
+                    Some(*from)
+                } else {
+                    None
+                }
+            })
+            .map(|peer| format!("{}:{}", SANDBOX_PEER_IP, self.nodes[peer].p2p_port))
+            .collect::<Vec<_>>();
+
+        let mut cfg = serde_json::Map::new();
+        if peers.is_empty() {
+            // isolated node, `private_node` requires `peers`
+            cfg.insert(NODE_CONFIG_PEERS.to_string(), serde_json::Value::Null);
+            cfg.insert(
+                NODE_CONFIG_PRIVATE_NODE.to_string(),
+                serde_json::Value::Null,
+            );
+        } else {
+            cfg.insert(NODE_CONFIG_PEERS.to_string(), peers.join(",").into());
+            cfg.insert(NODE_CONFIG_PRIVATE_NODE.to_string(), true.into());
+        }
+        cfg
+    }
+
+    /// Validates requested links, returns all links of the topology if none is requested
+    pub fn resolve_links(&self, links: &[Link]) -> Result<BTreeSet<Link>, TopologyError> {
+        if links.is_empty() {
+            return Ok(self.links.clone());
+        }
+        links
+            .iter()
+            .map(|link| {
+                let link = normalize(*link)?;
+                if self.links.contains(&link) {
+                    Ok(link)
+                } else {
+                    Err(TopologyError::UnknownLink {
+                        from: link.0,
+                        to: link.1,
+                    })
+                }
+            })
+            .collect()
+    }
+
+    /// Nodes of the link with the p2p address of the other node of the link,
+    /// which they graylist when the link is partitioned
+    pub fn graylist_patches(&self, (from, to): Link) -> [(NodeRpcIpPort, SocketAddr); 2] {
+        [(from, to), (to, from)].map(|(node, peer)| {
+            (
+                self.nodes[node].node_ref.clone(),
+                SocketAddr::new(SANDBOX_PEER_IP, self.nodes[peer].p2p_port),
+            )
+        })
+    }
+
+    /// Waits until rpc of all nodes is reachable
+    pub async fn wait_for_rpc(&self) -> Result<(), TopologyError> {
+        let deadline = Instant::now() + Self::NODE_RPC_TIMEOUT;
+        for (index, node) in self.nodes.iter().enumerate() {
+            let address = (node.node_ref.ip.as_str(), node.node_ref.port);
+            while TcpStream::connect(address).await.is_err() {
+                if Instant::now() >= deadline {
+                    return Err(TopologyError::NodeRpcUnreachable {
+                        index,
+                        node_ref: node.node_ref.clone(),
+                    });
+                }
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Graylists the `point` on the node (`add`), or removes it from the graylist
+pub async fn patch_graylist(
+    client: &reqwest::Client,
+    node_ref: &NodeRpcIpPort,
+    point: SocketAddr,
+    add: bool,
+) -> Result<(), TopologyError> {
+    let error = |reason: String| TopologyError::NodeGraylistPatchFailed {
+        node_ref: node_ref.clone(),
+        point,
+        reason,
+    };
+    let response = client
+        .patch(format!(
+            "http://{}:{}/dev/shell/automaton/peers/graylist",
+            node_ref.ip, node_ref.port
+        ))
+        .json(&serde_json::json!({
+            "command": if add { "add" } else { "remove" },
+            "point": point,
+        }))
+        .send()
+        .await
+        .map_err(|e| error(e.to_string()))?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(error(response.text().await.unwrap_or_default()))
+    }
+}
+
+fn invalid_request(reason: &str) -> TopologyError {
+    TopologyError::InvalidRequest {
+        reason: reason.to_string(),
+    }
+}
+
+fn normalize((from, to): Link) -> Result<Link, TopologyError> {
+    if from == to {
+        Err(invalid_request(&format!(
+            "node {} cannot be connected to itself",
+            from
+        )))
+    } else {
+        Ok((from.min(to), from.max(to)))
+    }
+}
+
+/// Port of the first node, ensuring there is enough ports for all nodes
+fn config_port(
+    node_config: &serde_json::Value,
+    key: &str,
+    nodes: usize,
+) -> Result<u16, TopologyError> {
+    node_config
+        .get(key)
+        .and_then(|value| value.as_u64())
+        .filter(|port| port + nodes as u64 - 1 <= u16::MAX as u64)
+        .map(|port| port as u16)
+        .ok_or_else(|| {
+            invalid_request(&format!(
+                "missing or invalid `{}` in node configuration for {} nodes",
+                key, nodes
+            ))
+        })
+}
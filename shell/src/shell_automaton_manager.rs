@@ -31,10 +31,12 @@ pub use shell_automaton::service::actors_service::{
 pub use shell_automaton::service::actors_service::{ApplyBlockCallback, ApplyBlockResult};
 use shell_automaton::service::baker_service::BakerSigner;
 use shell_automaton::service::mio_service::MioInternalEventsContainer;
+use shell_automaton::service::recording::{RecordingConfig, RecordingWriter};
 use shell_automaton::service::rpc_service::RpcShellAutomatonSender;
 use shell_automaton::service::{
     ActorsServiceDefault, BakerServiceDefault, DnsServiceDefault, MioServiceDefault,
-    ProtocolRunnerServiceDefault, RpcServiceDefault, ServiceDefault, StorageServiceDefault,
+    ProtocolRunnerServiceDefault, RpcServiceDefault, SandboxClock, ServiceDefault,
+    StorageServiceDefault,
 };
 use shell_automaton::shell_compatibility_version::ShellCompatibilityVersion;
 use shell_automaton::ShellAutomaton;
@@ -71,10 +73,15 @@ pub struct P2p {
 
     pub record_shell_automaton_state_snapshots: bool,
     pub record_shell_automaton_actions: bool,
+    /// Actions and state snapshots are recorded to files in this directory, instead of the main database.
+    pub record_shell_automaton_to_files: Option<RecordingConfig>,
 
     pub baker_data_dir: PathBuf,
     pub baker_names: Vec<String>,
     pub liquidity_baking_escape_vote: LiquidityBakingToggleVote,
+
+    /// Clock file of the sandbox launcher, the automaton follows the sandbox time instead of the system time.
+    pub sandbox_clock_file: Option<PathBuf>,
 }
 
 impl P2p {
@@ -140,8 +147,21 @@ impl ShellAutomatonManager {
         );
         let (rpc_service, rpc_channel) = RpcServiceDefault::new(mio_service.waker(), 128);
 
-        let storage_service =
-            StorageServiceDefault::init(log.clone(), mio_service.waker(), persistent_storage, 4096);
+        let recording = p2p_config
+            .record_shell_automaton_to_files
+            .clone()
+            .map(|config| {
+                info!(log, "Recording shell automaton to files"; "dir" => config.dir.display().to_string());
+                RecordingWriter::new(config)
+                    .expect("Failed to create shell automaton recording directory")
+            });
+        let storage_service = StorageServiceDefault::init(
+            log.clone(),
+            mio_service.waker(),
+            persistent_storage,
+            recording,
+            4096,
+        );
 
         let (automaton_sender, automaton_receiver) =
             shell_automaton::service::actors_service::sync_channel(
@@ -168,6 +188,14 @@ impl ShellAutomatonManager {
             baker_service.add_signer(pkh, signer);
         }
 
+        let clock = p2p_config.sandbox_clock_file.as_ref().map(|path| {
+            info!(log, "Using sandbox clock"; "file" => path.display().to_string());
+            SandboxClock::open(path).expect("Failed to open sandbox clock file")
+        });
+        let initial_time = clock
+            .as_ref()
+            .map_or_else(SystemTime::now, SandboxClock::now);
+
         let service = ServiceDefault {
             randomness: StdRng::seed_from_u64(seed),
             dns: DnsServiceDefault::default(),
@@ -178,6 +206,7 @@ impl ShellAutomatonManager {
             actors: ActorsServiceDefault::new(automaton_receiver, network_channel),
             baker: baker_service,
             statistics: Some(Default::default()),
+            clock,
         };
 
         let events = MioInternalEventsContainer::with_capacity(1024);
@@ -192,7 +221,7 @@ impl ShellAutomatonManager {
             })
             .collect();
         let mut initial_state = shell_automaton::State::new(shell_automaton::Config {
-            initial_time: SystemTime::now(),
+            initial_time,
 
             protocol_runner: protocol_runner_config,
             init_storage_data,
@@ -41,6 +41,22 @@ pub enum MemoryData {
     DarwinOs(DarwinOsData),
 }
 
+impl MemoryData {
+    /// Resident set size in bytes
+    pub fn resident_bytes(&self) -> Option<u64> {
+        match self {
+            // `statm` counts pages
+            MemoryData::Linux(data) => data
+                .resident
+                .parse::<u64>()
+                .ok()
+                .map(|pages| pages * data.page_size as u64),
+            // `ps` reports kilobytes
+            MemoryData::DarwinOs(data) => data.resident.parse::<u64>().ok().map(|kb| kb * 1024),
+        }
+    }
+}
+
 impl From<LinuxData> for MemoryData {
     fn from(data: LinuxData) -> Self {
         MemoryData::Linux(data)
@@ -263,4 +279,20 @@ pub mod tests {
         });
         assert_eq!(Ok(parse_result), memory.parse_mac_data(to_parse))
     }
+
+    #[test]
+    fn resident_bytes() {
+        let memory = Memory::new();
+        let linux = memory
+            .parse_linux_statm("218428 10272 6459 7780 0 22424 0".to_string())
+            .unwrap();
+        assert_eq!(
+            linux.resident_bytes(),
+            Some(10272 * memory.page_size as u64)
+        );
+        let mac = memory
+            .parse_mac_data("PID %MEM   RSS\n0.3  5336\n".to_string())
+            .unwrap();
+        assert_eq!(mac.resident_bytes(), Some(5336 * 1024));
+    }
 }
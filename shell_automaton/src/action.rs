@@ -67,7 +67,8 @@ use crate::peers::dns_lookup::{
 };
 use crate::peers::graylist::{
     PeersGraylistAddressAction, PeersGraylistIpAddAction, PeersGraylistIpAddedAction,
-    PeersGraylistIpRemoveAction, PeersGraylistIpRemovedAction,
+    PeersGraylistIpRemoveAction, PeersGraylistIpRemovedAction, PeersGraylistPointAddAction,
+    PeersGraylistPointRemoveAction,
 };
 use crate::peers::init::PeersInitAction;
 use crate::peers::remove::PeersRemoveAction;
@@ -317,6 +318,8 @@ pub enum Action {
     PeersGraylistIpAdded(PeersGraylistIpAddedAction),
     PeersGraylistIpRemove(PeersGraylistIpRemoveAction),
     PeersGraylistIpRemoved(PeersGraylistIpRemovedAction),
+    PeersGraylistPointAdd(PeersGraylistPointAddAction),
+    PeersGraylistPointRemove(PeersGraylistPointRemoveAction),
 
     PeersAddIncomingPeer(PeersAddIncomingPeerAction),
     PeersAddMulti(PeersAddMultiAction),
@@ -387,6 +390,7 @@ pub enum Action {
     PeerMessageReadInit(PeerMessageReadInitAction),
     PeerMessageReadError(PeerMessageReadErrorAction),
     PeerMessageReadSuccess(PeerMessageReadSuccessAction),
+    PeerMessageReadSkip(PeerMessageReadSkipAction),
 
     PeerMessageWriteNext(PeerMessageWriteNextAction),
     PeerMessageWriteInit(PeerMessageWriteInitAction),
@@ -5,6 +5,9 @@ use crypto::hash::{BlockPayloadHash, HashTrait, HashType, OperationListHash};
 use storage::BlockHeaderWithHash;
 use tezos_encoding::types::SizedBytes;
 use tezos_messages::p2p::encoding::block_header::BlockHeaderBuilder;
+use tezos_messages::protocol::proto_012::operation_selection::{
+    self, Candidate, HARD_GAS_LIMIT_PER_BLOCK, QUOTAS,
+};
 
 use crate::baker::persisted::LastBakedBlock;
 use crate::baker::{BakerState, ElectedBlock};
@@ -21,13 +24,13 @@ fn set_elected_block_operations(baker: &mut BakerState, mempool: &MempoolState)
             return block;
         }
 
-        let empty_operations = vec![vec![], vec![], vec![], vec![]];
+        let empty_operations = [vec![], vec![], vec![], vec![]];
         let ops_iter = mempool.operations_for_block_iter(
             block.header().level(),
             block.round(),
             block.payload_hash(),
         );
-        let operations = ops_iter.fold(empty_operations, |mut r, (hash, op, kind)| {
+        let candidates = ops_iter.fold(empty_operations, |mut r, (hash, op, kind)| {
             let container = match kind {
                 OperationKind::Unknown
                 | OperationKind::Preendorsement
@@ -47,9 +50,22 @@ fn set_elected_block_operations(baker: &mut BakerState, mempool: &MempoolState)
                 | OperationKind::RegisterGlobalConstant
                 | OperationKind::SetDepositsLimit => &mut r[3],
             };
-            container.push((op.clone(), hash.clone()));
+            let data: &[u8] = op.data().as_ref();
+            let size = HashType::BlockHash.size() + data.len();
+            let manager = mempool
+                .operations_state
+                .get(hash)
+                .and_then(|op| op.operation_decoded_contents.as_ref()?.manager_info());
+            container.push(Candidate::new((op.clone(), hash.clone()), size, manager));
             r
         });
+        let operations = candidates
+            .into_iter()
+            .zip(QUOTAS)
+            .map(|(candidates, quota)| {
+                operation_selection::select(candidates, &quota, HARD_GAS_LIMIT_PER_BLOCK)
+            })
+            .collect::<Vec<_>>();
 
         block.operations = operations
             .iter()
@@ -380,17 +380,11 @@ pub struct BootstrapPeerBlockOperationsReceivedAction {
 
 impl EnablingCondition<State> for BootstrapPeerBlockOperationsReceivedAction {
     fn is_enabled(&self, state: &State) -> bool {
-        match &state.bootstrap {
-            BootstrapState::PeersBlockOperationsGetPending { pending, .. } => pending
-                .get(self.message.operations_for_block().block_hash())
-                .and_then(|v| v.peers.get(&self.peer))
-                .map_or(false, |peer_state| {
-                    peer_state.is_validation_pass_pending(
-                        self.message.operations_for_block().validation_pass() as u8,
-                    )
-                }),
-            _ => false,
-        }
+        state.bootstrap.is_peer_block_operations_pending(
+            self.peer,
+            self.message.operations_for_block().block_hash(),
+            self.message.operations_for_block().validation_pass() as u8,
+        )
     }
 }
 
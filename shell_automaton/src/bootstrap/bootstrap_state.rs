@@ -615,6 +615,25 @@ impl BootstrapState {
         }
     }
 
+    /// Whether operations for the given block and validation pass are
+    /// being awaited from the `peer`.
+    pub fn is_peer_block_operations_pending(
+        &self,
+        peer: SocketAddr,
+        block_hash: &BlockHash,
+        validation_pass: u8,
+    ) -> bool {
+        match self {
+            Self::PeersBlockOperationsGetPending { pending, .. } => pending
+                .get(block_hash)
+                .and_then(|v| v.peers.get(&peer))
+                .map_or(false, |peer_state| {
+                    peer_state.is_validation_pass_pending(validation_pass)
+                }),
+            _ => false,
+        }
+    }
+
     pub fn peer_intervals(&self) -> Option<&Vec<PeerIntervalState>> {
         match self {
             Self::PeersBlockHeadersGetPending { peer_intervals, .. } => Some(peer_intervals),
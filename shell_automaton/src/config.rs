@@ -170,6 +170,7 @@ pub fn default_test_config() -> Config {
                         TezosContextTezedgeOnDiskBackendOptions {
                             base_path: "/tmp/tezedge".to_string(),
                             startup_check: false,
+                            key_history: false,
                         },
                     ),
                     ipc_socket_path: None,
@@ -229,4 +229,17 @@ pub fn effects<S: Service>(store: &mut Store<S>, action: &ActionWithMeta) {
     baker_seed_nonce_effects(store, action);
 
     shutdown_effects(store, action);
+
+    // a dispatch at depth 0 returns once the effects of the actions it caused are done
+    #[cfg(feature = "invariants")]
+    if action.depth == 0 {
+        if let Err(violation) = crate::invariants::check_after_effects(store.state()) {
+            panic!(
+                "{} after the effects of action {:?} (id: {})",
+                violation,
+                action.action.kind(),
+                u64::from(action.id)
+            );
+        }
+    }
 }
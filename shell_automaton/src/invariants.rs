@@ -0,0 +1,532 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Invariants of the [`State`], which must hold after every action.
+//!
+//! Invariants are kept in a global registry, initialized with the ones defined
+//! here. Other crates (e.g. tests) can add their own with [`register`].
+//!
+//! Limits on the number of peers and the blacklist are enforced by effects,
+//! which disconnect or drop the peers after the reducer has already added or
+//! graylisted them. Those invariants are only in [`Invariants::with_peers`],
+//! as they can be violated in between, they are checked once a dispatch has
+//! finished its effects with [`check_after_effects`].
+//!
+//! With the `invariants` feature, they are checked after every reducer call, and
+//! after the effects of every action dispatched at depth 0. A violation panics with
+//! the violating action. The actions fuzzer, which runs the reducers only, checks
+//! [`check`] after every action as the safety condition of the state.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::RwLock;
+
+use once_cell::sync::Lazy;
+use thiserror::Error;
+
+use crate::bootstrap::BootstrapState;
+use crate::peer::PeerStatus;
+use crate::State;
+
+/// Returns the reason if the invariant doesn't hold for the state.
+pub type InvariantCheck = fn(&State) -> Result<(), String>;
+
+#[derive(Debug, Clone)]
+pub struct Invariant {
+    pub name: &'static str,
+    pub check: InvariantCheck,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("invariant `{invariant}` violated: {reason}")]
+pub struct InvariantViolation {
+    pub invariant: &'static str,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Invariants {
+    list: Vec<Invariant>,
+}
+
+impl Invariants {
+    /// Registry without any invariants.
+    pub fn empty() -> Self {
+        Self { list: vec![] }
+    }
+
+    /// Registry with the invariants that hold after every reducer call.
+    pub fn new() -> Self {
+        let mut invariants = Self::empty();
+        invariants.register(
+            "bootstrap_pending_requests_peer_live",
+            bootstrap_pending_requests_peer_live,
+        );
+        invariants.register(
+            "bootstrap_peer_intervals_contiguous",
+            bootstrap_peer_intervals_contiguous,
+        );
+        invariants.register(
+            "mempool_operation_classified_once",
+            mempool_operation_classified_once,
+        );
+        invariants
+    }
+
+    /// Registry with the invariants of [`Invariants::new`] and the peers ones,
+    /// which hold only once the effects have handled the last action.
+    pub fn with_peers() -> Self {
+        let mut invariants = Self::new();
+        invariants.register("peers_connected_max", peers_connected_max);
+        invariants.register("peers_potential_max", peers_potential_max);
+        invariants.register(
+            "peers_connected_not_blacklisted",
+            peers_connected_not_blacklisted,
+        );
+        invariants
+    }
+
+    /// Adds the invariant, replacing the one with the same name.
+    pub fn register(&mut self, name: &'static str, check: InvariantCheck) {
+        self.list.retain(|invariant| invariant.name != name);
+        self.list.push(Invariant { name, check });
+    }
+
+    pub fn unregister(&mut self, name: &str) {
+        self.list.retain(|invariant| invariant.name != name);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Invariant> {
+        self.list.iter()
+    }
+
+    /// Checks the invariants in the order of registration, returning the first violation.
+    pub fn check(&self, state: &State) -> Result<(), InvariantViolation> {
+        self.list.iter().try_for_each(|invariant| {
+            (invariant.check)(state).map_err(|reason| InvariantViolation {
+                invariant: invariant.name,
+                reason,
+            })
+        })
+    }
+}
+
+impl Default for Invariants {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static INVARIANTS: Lazy<RwLock<Invariants>> = Lazy::new(|| RwLock::new(Invariants::new()));
+
+static INVARIANTS_AFTER_EFFECTS: Lazy<RwLock<Invariants>> =
+    Lazy::new(|| RwLock::new(Invariants::with_peers()));
+
+/// Adds the invariant to the global registries.
+pub fn register(name: &'static str, check: InvariantCheck) {
+    INVARIANTS.write().unwrap().register(name, check);
+    INVARIANTS_AFTER_EFFECTS
+        .write()
+        .unwrap()
+        .register(name, check);
+}
+
+/// Removes the invariant from the global registries.
+pub fn unregister(name: &str) {
+    INVARIANTS.write().unwrap().unregister(name);
+    INVARIANTS_AFTER_EFFECTS.write().unwrap().unregister(name);
+}
+
+/// Checks the invariants of the global registry, which hold after every reducer call.
+pub fn check(state: &State) -> Result<(), InvariantViolation> {
+    INVARIANTS.read().unwrap().check(state)
+}
+
+/// Checks the invariants of the global registry with the peers ones, which hold once
+/// the effects of a dispatched action, and of the actions they dispatched, are done.
+pub fn check_after_effects(state: &State) -> Result<(), InvariantViolation> {
+    INVARIANTS_AFTER_EFFECTS.read().unwrap().check(state)
+}
+
+fn peers_connected_max(state: &State) -> Result<(), String> {
+    let connected = state.peers.connected_len();
+    if connected > state.config.peers_connected_max {
+        return Err(format!(
+            "{} connected peers, max {}",
+            connected, state.config.peers_connected_max
+        ));
+    }
+    Ok(())
+}
+
+fn peers_potential_max(state: &State) -> Result<(), String> {
+    let potential = state.peers.potential_len();
+    if potential > state.config.peers_potential_max {
+        return Err(format!(
+            "{} potential peers, max {}",
+            potential, state.config.peers_potential_max
+        ));
+    }
+    Ok(())
+}
+
+fn peers_connected_not_blacklisted(state: &State) -> Result<(), String> {
+    match state
+        .peers
+        .connected_iter()
+        .find(|(address, _)| state.peers.is_blacklisted(&address.ip()))
+    {
+        Some((address, _)) => Err(format!("connected peer {} is blacklisted", address)),
+        None => Ok(()),
+    }
+}
+
+/// Peer is known and neither potential nor disconnected.
+fn is_peer_live(state: &State, address: &SocketAddr) -> bool {
+    state.peers.get(address).map_or(false, |peer| {
+        !matches!(
+            peer.status,
+            PeerStatus::Potential | PeerStatus::Disconnected
+        )
+    })
+}
+
+/// Bootstrap requests pending on a peer are cancelled once it disconnects.
+fn bootstrap_pending_requests_peer_live(state: &State) -> Result<(), String> {
+    match &state.bootstrap {
+        BootstrapState::PeersBlockHeadersGetPending { peer_intervals, .. } => peer_intervals
+            .iter()
+            .filter(|interval| interval.current.is_pending())
+            .filter_map(|interval| {
+                let peer = interval.current.peer()?;
+                Some((interval.current.block_level()?, peer))
+            })
+            .try_for_each(|(level, peer)| {
+                if !is_peer_live(state, &peer) {
+                    return Err(format!(
+                        "block header request at level {} pending on not connected peer {}",
+                        level, peer
+                    ));
+                }
+                Ok(())
+            }),
+        BootstrapState::PeersBlockOperationsGetPending { pending, .. } => pending
+            .iter()
+            .flat_map(|(block_hash, block)| {
+                block
+                    .peers
+                    .iter()
+                    .filter(|(_, peer_state)| peer_state.is_pending())
+                    .map(move |(peer, _)| (block_hash, peer))
+            })
+            .try_for_each(|(block_hash, peer)| {
+                if !is_peer_live(state, peer) {
+                    return Err(format!(
+                        "block operations request for {} pending on not connected peer {}",
+                        block_hash, peer
+                    ));
+                }
+                Ok(())
+            }),
+        _ => Ok(()),
+    }
+}
+
+/// Headers in each interval are downloaded from the highest level down, without
+/// gaps, and the intervals are ordered by level without overlapping.
+fn bootstrap_peer_intervals_contiguous(state: &State) -> Result<(), String> {
+    let peer_intervals = match &state.bootstrap {
+        BootstrapState::PeersBlockHeadersGetPending { peer_intervals, .. } => peer_intervals,
+        _ => return Ok(()),
+    };
+
+    for (index, interval) in peer_intervals.iter().enumerate() {
+        let levels = interval.downloaded.iter().map(|(level, ..)| *level);
+        let next_levels = levels
+            .clone()
+            .skip(1)
+            .map(Some)
+            .chain(std::iter::once(interval.current.block_level()));
+        for (level, next_level) in levels.zip(next_levels) {
+            match next_level {
+                Some(next_level) if next_level != level - 1 => {
+                    return Err(format!(
+                        "interval {} has level {} after level {}",
+                        index, next_level, level
+                    ));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    for (index, pair) in peer_intervals.windows(2).enumerate() {
+        if let (Some(highest), Some(next_lowest)) =
+            (pair[0].highest_level(), pair[1].lowest_level())
+        {
+            if highest >= next_lowest {
+                return Err(format!(
+                    "interval {} ends at level {}, interval {} starts at level {}",
+                    index,
+                    highest,
+                    index + 1,
+                    next_lowest
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A validated operation is in exactly one classification.
+fn mempool_operation_classified_once(state: &State) -> Result<(), String> {
+    let ops = &state.mempool.validated_operations;
+    let classifications = [
+        (
+            "applied",
+            ops.applied.iter().map(|v| &v.hash).collect::<Vec<_>>(),
+        ),
+        (
+            "branch_delayed",
+            ops.branch_delayed.iter().map(|v| &v.hash).collect(),
+        ),
+        (
+            "branch_refused",
+            ops.branch_refused.iter().map(|v| &v.hash).collect(),
+        ),
+        ("refused", ops.refused.iter().map(|v| &v.hash).collect()),
+        ("outdated", ops.outdated.iter().map(|v| &v.hash).collect()),
+    ];
+
+    let mut seen = BTreeMap::new();
+    for (classification, hashes) in classifications {
+        for hash in hashes {
+            if let Some(other) = seen.insert(hash, classification) {
+                return Err(format!(
+                    "operation {} is both {} and {}",
+                    hash, other, classification
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use crypto::hash::{BlockHash, HashTrait, OperationHash, OperationListListHash};
+    use tezos_api::ffi::{Errored, Validated};
+
+    use crate::bootstrap::{PeerIntervalCurrentState, PeerIntervalState};
+    use crate::config::default_test_config;
+    use crate::peer::connection::outgoing::PeerConnectionOutgoingState;
+    use crate::peer::connection::PeerConnectionState;
+    use crate::peer::{Peer, PeerIOLoopState, PeerToken};
+    use crate::peers::graylist::{peers_graylist_reducer, PeersGraylistIpAddAction};
+    use crate::{ActionId, ActionWithMeta};
+
+    use super::*;
+
+    fn state() -> State {
+        State::new(default_test_config())
+    }
+
+    fn potential_peer() -> Peer {
+        Peer {
+            status: PeerStatus::Potential,
+            try_read_loop: PeerIOLoopState::Idle,
+            try_write_loop: PeerIOLoopState::Idle,
+        }
+    }
+
+    fn connected_peer() -> Peer {
+        Peer {
+            status: PeerStatus::Connecting(PeerConnectionState::Outgoing(
+                PeerConnectionOutgoingState::Pending {
+                    time: 0,
+                    token: PeerToken::new_unchecked(0),
+                },
+            )),
+            try_read_loop: PeerIOLoopState::Idle,
+            try_write_loop: PeerIOLoopState::Idle,
+        }
+    }
+
+    fn interval(levels: &[i32], current: Option<i32>) -> PeerIntervalState {
+        let block_hash = BlockHash::try_from_bytes(&[0; 32]).unwrap();
+        let operations_hash = OperationListListHash::try_from_bytes(&[0; 32]).unwrap();
+        PeerIntervalState {
+            peers: Default::default(),
+            downloaded: levels
+                .iter()
+                .map(|level| (*level, block_hash.clone(), 0, operations_hash.clone()))
+                .collect(),
+            current: match current {
+                Some(block_level) => PeerIntervalCurrentState::idle(0, block_level, block_hash),
+                None => PeerIntervalCurrentState::Finished {
+                    time: 0,
+                    peer: "127.0.0.1:9732".parse().unwrap(),
+                },
+            },
+        }
+    }
+
+    fn with_peer_intervals(peer_intervals: Vec<PeerIntervalState>) -> State {
+        let mut state = state();
+        state.bootstrap = BootstrapState::PeersBlockHeadersGetPending {
+            time: 0,
+            timeouts_last_check: None,
+            last_logged: 0,
+            last_logged_downloaded_count: 0,
+            main_chain_last_level: 100,
+            main_chain_last_hash: BlockHash::try_from_bytes(&[0; 32]).unwrap(),
+            main_chain: Default::default(),
+            peer_intervals,
+        };
+        state
+    }
+
+    fn pending_interval(peer: SocketAddr) -> PeerIntervalState {
+        let block_hash = BlockHash::try_from_bytes(&[0; 32]).unwrap();
+        PeerIntervalState {
+            peers: Default::default(),
+            downloaded: vec![],
+            current: PeerIntervalCurrentState::Pending {
+                time: 0,
+                peer,
+                block_level: 10,
+                block_hash,
+            },
+        }
+    }
+
+    fn operation_hash() -> OperationHash {
+        OperationHash::try_from_bytes(&[1; 32]).unwrap()
+    }
+
+    fn errored(hash: OperationHash) -> Errored {
+        Errored {
+            hash,
+            is_endorsement: false,
+            protocol_data_json: String::new(),
+            error_json: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_initial_state() {
+        assert_eq!(Invariants::new().check(&state()), Ok(()));
+        assert_eq!(Invariants::with_peers().check(&state()), Ok(()));
+    }
+
+    #[test]
+    fn test_peers_potential_max() {
+        let mut state = state();
+        state.config.peers_potential_max = 1;
+        for port in 0..2 {
+            let address = SocketAddr::from(([127, 0, 0, 1], port));
+            state.peers.list.insert(address, potential_peer());
+        }
+
+        let violation = Invariants::with_peers().check(&state).unwrap_err();
+        assert_eq!(violation.invariant, "peers_potential_max");
+        assert_eq!(Invariants::new().check(&state), Ok(()));
+
+        let mut invariants = Invariants::with_peers();
+        invariants.unregister("peers_potential_max");
+        assert_eq!(invariants.check(&state), Ok(()));
+
+        // the global registries
+        assert_eq!(
+            check_after_effects(&state).unwrap_err().invariant,
+            "peers_potential_max"
+        );
+        assert_eq!(check(&state), Ok(()));
+    }
+
+    #[test]
+    fn test_peers_connected_not_blacklisted() {
+        let address = SocketAddr::from(([127, 0, 0, 1], 9732));
+        let mut state = state();
+        state.peers.list.insert(address, connected_peer());
+        assert_eq!(peers_connected_not_blacklisted(&state), Ok(()));
+
+        let action = ActionWithMeta {
+            action: PeersGraylistIpAddAction { ip: address.ip() }.into(),
+            id: ActionId::new_unchecked(0),
+            depth: 0,
+        };
+        peers_graylist_reducer(&mut state, &action);
+        assert!(peers_connected_not_blacklisted(&state).is_err());
+
+        state.peers.list.insert(address, potential_peer());
+        assert_eq!(peers_connected_not_blacklisted(&state), Ok(()));
+    }
+
+    #[test]
+    fn test_bootstrap_pending_requests_peer_live() {
+        let address = SocketAddr::from(([127, 0, 0, 1], 9732));
+        let mut state = with_peer_intervals(vec![pending_interval(address)]);
+        assert!(bootstrap_pending_requests_peer_live(&state).is_err());
+
+        state.peers.list.insert(address, connected_peer());
+        assert_eq!(bootstrap_pending_requests_peer_live(&state), Ok(()));
+
+        state.peers.list.insert(
+            address,
+            Peer {
+                status: PeerStatus::Disconnected,
+                ..connected_peer()
+            },
+        );
+        assert!(bootstrap_pending_requests_peer_live(&state).is_err());
+    }
+
+    #[test]
+    fn test_mempool_operation_classified_once() {
+        let mut state = state();
+        state.mempool.validated_operations.applied.push(Validated {
+            hash: operation_hash(),
+            protocol_data_json: String::new(),
+        });
+        assert_eq!(mempool_operation_classified_once(&state), Ok(()));
+
+        state
+            .mempool
+            .validated_operations
+            .refused
+            .push_back(errored(operation_hash()));
+        assert!(mempool_operation_classified_once(&state).is_err());
+    }
+
+    #[test]
+    fn test_bootstrap_peer_intervals_contiguous() {
+        let contiguous = with_peer_intervals(vec![
+            interval(&[12, 11], Some(10)),
+            interval(&[20, 19, 18], None),
+        ]);
+        assert_eq!(bootstrap_peer_intervals_contiguous(&contiguous), Ok(()));
+
+        let gap = with_peer_intervals(vec![interval(&[12, 10], None)]);
+        assert!(bootstrap_peer_intervals_contiguous(&gap).is_err());
+
+        let overlapping =
+            with_peer_intervals(vec![interval(&[12, 11], None), interval(&[13, 12], None)]);
+        assert!(bootstrap_peer_intervals_contiguous(&overlapping).is_err());
+    }
+
+    #[test]
+    fn test_register() {
+        let mut invariants = Invariants::empty();
+        invariants.register("always", |_| Err("violated".to_owned()));
+        assert_eq!(
+            invariants.check(&state()),
+            Err(InvariantViolation {
+                invariant: "always",
+                reason: "violated".to_owned(),
+            })
+        );
+    }
+}
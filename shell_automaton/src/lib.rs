@@ -25,6 +25,8 @@ pub use state::State;
 mod reducer;
 pub use reducer::reducer;
 
+pub mod invariants;
+
 mod effects;
 pub use effects::{check_timeouts, effects};
 
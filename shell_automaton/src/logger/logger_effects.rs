@@ -131,6 +131,12 @@ pub fn logger_effects<S: Service>(store: &mut Store<S>, action: &ActionWithMeta)
         Action::PeersGraylistIpRemove(content) => {
             slog::info!(log, "Whitelisting peer ip"; "ip" => content.ip.to_string());
         }
+        Action::PeersGraylistPointAdd(content) => {
+            slog::warn!(log, "Graylisting peer point"; "point" => content.point.to_string());
+        }
+        Action::PeersGraylistPointRemove(content) => {
+            slog::info!(log, "Whitelisting peer point"; "point" => content.point.to_string());
+        }
         Action::PeersCheckTimeoutsSuccess(content) => {
             if !content.peer_timeouts.is_empty() {
                 slog::warn!(log, "Peers timed out";
@@ -91,6 +91,11 @@ impl MempoolState {
             .or_else(|| self.pending_operations.next_for_prevalidation())
     }
 
+    /// Number of operations waiting for the prevalidation.
+    pub fn pending_operations_len(&self) -> usize {
+        self.pending_operations.len()
+    }
+
     pub fn operations_for_block_iter<'a>(
         &'a self,
         block_level: Level,
@@ -137,6 +142,10 @@ impl MempoolPendingOperations {
         self.ops.is_empty()
     }
 
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
     pub fn contains_key(&self, key: &OperationHash) -> bool {
         self.ops.contains_key(key)
     }
@@ -57,3 +57,18 @@ impl EnablingCondition<State> for PeerMessageReadSuccessAction {
         true
     }
 }
+
+/// PeerMessage has been received, but it isn't needed so it was
+/// dropped without fully decoding it.
+#[cfg_attr(feature = "fuzzing", derive(fuzzcheck::DefaultMutator))]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PeerMessageReadSkipAction {
+    #[cfg_attr(feature = "fuzzing", field_mutator(SocketAddrMutator))]
+    pub address: SocketAddr,
+}
+
+impl EnablingCondition<State> for PeerMessageReadSkipAction {
+    fn is_enabled(&self, _: &State) -> bool {
+        true
+    }
+}
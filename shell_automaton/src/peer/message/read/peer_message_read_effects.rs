@@ -4,10 +4,10 @@
 use std::net::SocketAddr;
 
 use crypto::hash::BlockHash;
-use networking::network_channel::PeerMessageReceived;
+use networking::network_channel::{PeerMessageReceived, PeerMessageSkipped};
 use storage::{BlockHeaderWithHash, OperationKey};
-use tezos_messages::p2p::binary_message::{BinaryRead, MessageHash};
-use tezos_messages::p2p::encoding::peer::{PeerMessage, PeerMessageResponse};
+use tezos_messages::p2p::binary_message::MessageHash;
+use tezos_messages::p2p::encoding::peer::{PeerMessage, PeerMessageRef, PeerMessageResponse};
 use tezos_messages::p2p::encoding::prelude::AdvertiseMessage;
 
 use crate::bootstrap::{
@@ -28,7 +28,7 @@ use crate::service::actors_service::{ActorsMessageTo, ActorsService};
 use crate::service::{RandomnessService, Service, StatisticsService};
 use crate::{Action, ActionId, ActionWithMeta, State, Store};
 
-use super::{PeerMessageReadInitAction, PeerMessageReadSuccessAction};
+use super::{PeerMessageReadInitAction, PeerMessageReadSkipAction, PeerMessageReadSuccessAction};
 
 fn stats_message_received(
     state: &State,
@@ -143,8 +143,34 @@ where
                 None => return,
             };
 
-            match PeerMessageResponse::from_bytes(&content.message) {
-                Ok(mut message) => {
+            // Bulk messages are decoded without copying first, so that
+            // the ones we aren't waiting for are dropped cheaply.
+            let mut skipped = None;
+            let state = store.state.get();
+            let message = PeerMessageResponse::from_bytes_filtered(&content.message, |message| {
+                let msg = match message {
+                    PeerMessageRef::OperationsForBlocks(msg) => msg,
+                    _ => return true,
+                };
+                let block_hash = msg.operations_for_block().hash().to_hash();
+                let validation_pass = msg.operations_for_block().validation_pass();
+                if state.bootstrap.is_peer_block_operations_pending(
+                    content.address,
+                    &block_hash,
+                    validation_pass as u8,
+                ) {
+                    return true;
+                }
+                slog::debug!(&state.log, "Skipping unexpected OperationsForBlocks from peer";
+                    "peer" => format!("{}", content.address),
+                    "block_hash" => block_hash.to_base58_check(),
+                    "validation_pass" => validation_pass);
+                skipped = Some(block_hash);
+                false
+            });
+
+            match message {
+                Ok(Some(mut message)) => {
                     // Set size hint to unencrypted encoded message size.
                     // Maybe we should set encrypted size instead? Since
                     // that's the actual size of data transmitted.
@@ -155,6 +181,21 @@ where
                         message: message.into(),
                     });
                 }
+                Ok(None) => {
+                    if let (Some(stats), Some(block_hash)) = (store.service.statistics(), skipped) {
+                        stats.block_operations_download_end(&block_hash, action.id.into());
+                    }
+                    store
+                        .service()
+                        .actors()
+                        .send(ActorsMessageTo::PeerMessageSkipped(PeerMessageSkipped {
+                            peer_address: content.address,
+                            size: content.message.len(),
+                        }));
+                    store.dispatch(PeerMessageReadSkipAction {
+                        address: content.address,
+                    });
+                }
                 Err(err) => {
                     store.dispatch(PeerMessageReadErrorAction {
                         address: content.address,
@@ -384,6 +425,11 @@ where
                 address: content.address,
             });
         }
+        Action::PeerMessageReadSkip(content) => {
+            store.dispatch(PeerMessageReadInitAction {
+                address: content.address,
+            });
+        }
         Action::PeerMessageReadError(content) => {
             store.dispatch(PeersGraylistAddressAction {
                 address: content.address,
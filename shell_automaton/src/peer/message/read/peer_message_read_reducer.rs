@@ -89,6 +89,25 @@ pub fn peer_message_read_reducer(state: &mut State, action: &ActionWithMeta) {
                 }
             }
         }
+        Action::PeerMessageReadSkip(content) => {
+            let peer = match state.peers.get_handshaked_mut(&content.address) {
+                Some(v) => v,
+                None => return,
+            };
+            let read_crypto = match &peer.message_read {
+                PeerMessageReadState::Pending {
+                    binary_message_read: PeerBinaryMessageReadState::Ready { crypto, .. },
+                } => crypto.clone(),
+                _ => return,
+            };
+            peer.crypto =
+                PeerCrypto::unsplit_after_reading(read_crypto.clone(), peer.crypto.local_nonce());
+            peer.message_read = PeerMessageReadState::Pending {
+                binary_message_read: PeerBinaryMessageReadState::Init {
+                    crypto: read_crypto,
+                },
+            };
+        }
         Action::PeerCurrentHeadUpdate(content) => {
             if let Some(peer) = state.peers.get_handshaked_mut(&content.address) {
                 peer.current_head = Some(content.current_head.clone());
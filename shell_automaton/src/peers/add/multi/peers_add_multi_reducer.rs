@@ -14,6 +14,9 @@ pub fn peers_add_multi_reducer(state: &mut State, action: &ActionWithMeta) {
             .saturating_sub(state.peers.potential_len());
 
         for address in addresses.iter().take(max_len) {
+            if state.peers.is_point_graylisted(address) {
+                continue;
+            }
             if let Ok(entry) = state.peers.entry(*address) {
                 entry.or_insert_with(|| Peer {
                     status: PeerStatus::Potential,
@@ -108,3 +108,33 @@ impl EnablingCondition<State> for PeersGraylistIpRemovedAction {
         true
     }
 }
+
+/// Graylist the point (listening address of a peer) until it's removed with
+/// [PeersGraylistPointRemoveAction]. Unlike ip graylisting, other peers
+/// with the same ip stay connected.
+#[cfg_attr(feature = "fuzzing", derive(fuzzcheck::DefaultMutator))]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PeersGraylistPointAddAction {
+    #[cfg_attr(feature = "fuzzing", field_mutator(SocketAddrMutator))]
+    pub point: SocketAddr,
+}
+
+impl EnablingCondition<State> for PeersGraylistPointAddAction {
+    fn is_enabled(&self, state: &State) -> bool {
+        !state.peers.is_point_graylisted(&self.point)
+    }
+}
+
+/// Remove the point from graylist and try to connect to it.
+#[cfg_attr(feature = "fuzzing", derive(fuzzcheck::DefaultMutator))]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PeersGraylistPointRemoveAction {
+    #[cfg_attr(feature = "fuzzing", field_mutator(SocketAddrMutator))]
+    pub point: SocketAddr,
+}
+
+impl EnablingCondition<State> for PeersGraylistPointRemoveAction {
+    fn is_enabled(&self, state: &State) -> bool {
+        state.peers.is_point_graylisted(&self.point)
+    }
+}
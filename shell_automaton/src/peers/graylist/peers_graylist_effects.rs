@@ -1,8 +1,10 @@
 // Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
 // SPDX-License-Identifier: MIT
 
+use crate::peer::connection::outgoing::PeerConnectionOutgoingInitAction;
 use crate::peer::disconnection::PeerDisconnectAction;
 use crate::peer::PeerStatus;
+use crate::peers::add::multi::PeersAddMultiAction;
 use crate::peers::remove::PeersRemoveAction;
 use crate::{Action, ActionWithMeta, Service, Store};
 
@@ -56,6 +58,52 @@ pub fn peers_graylist_effects<S: Service>(store: &mut Store<S>, action: &ActionW
         Action::PeersGraylistIpRemove(action) => {
             store.dispatch(PeersGraylistIpRemovedAction { ip: action.ip });
         }
+        Action::PeersGraylistPointAdd(action) => {
+            let peers = &store.state.get().peers;
+            let mut remove_peers = Vec::with_capacity(1);
+            let mut disconnect_peers = Vec::with_capacity(1);
+
+            peers
+                .iter()
+                .filter(|(addr, _)| peers.peer_point(addr) == action.point)
+                .for_each(|(addr, peer)| match &peer.status {
+                    PeerStatus::Potential => remove_peers.push(*addr),
+
+                    PeerStatus::Connecting(_)
+                    | PeerStatus::Handshaking(_)
+                    | PeerStatus::Handshaked(_) => disconnect_peers.push(*addr),
+
+                    PeerStatus::Disconnecting(_) => {}
+                    PeerStatus::Disconnected => {}
+                });
+
+            for address in remove_peers {
+                store.dispatch(PeersRemoveAction { address });
+            }
+
+            for address in disconnect_peers {
+                store.dispatch(PeerDisconnectAction { address });
+            }
+        }
+        Action::PeersGraylistPointRemove(action) => {
+            store.dispatch(PeersAddMultiAction {
+                addresses: vec![action.point],
+            });
+            store.dispatch(PeerConnectionOutgoingInitAction {
+                address: action.point,
+            });
+        }
+        Action::PeerHandshakingFinish(action) => {
+            // Point of the incoming peer is only known after the handshake.
+            let peers = &store.state.get().peers;
+            if peers.get_handshaked(&action.address).is_some()
+                && peers.is_point_graylisted(&peers.peer_point(&action.address))
+            {
+                store.dispatch(PeerDisconnectAction {
+                    address: action.address,
+                });
+            }
+        }
         _ => {}
     }
 }
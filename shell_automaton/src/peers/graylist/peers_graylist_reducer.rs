@@ -16,6 +16,12 @@ pub fn peers_graylist_reducer(state: &mut State, action: &ActionWithMeta) {
         Action::PeersGraylistIpRemove(action_content) => {
             state.peers.remove_blacklisted_ip(&action_content.ip);
         }
+        Action::PeersGraylistPointAdd(action_content) => {
+            state.peers.graylist_point(action_content.point);
+        }
+        Action::PeersGraylistPointRemove(action_content) => {
+            state.peers.remove_graylisted_point(&action_content.point);
+        }
         _ => {}
     }
 }
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: MIT
 
 use std::collections::btree_map::{BTreeMap, Entry as BTreeMapEntry};
+use std::collections::BTreeSet;
 use std::net::{IpAddr, SocketAddr};
 use std::time::Duration;
 
@@ -33,6 +34,9 @@ impl PeerBlacklistState {
 pub struct PeersState {
     pub list: BTreeMap<SocketAddr, Peer>,
     ip_blacklist: BTreeMap<IpAddr, PeerBlacklistState>,
+    /// Points (listening addresses of peers) graylisted on request, until removed.
+    #[serde(default)]
+    point_graylist: BTreeSet<SocketAddr>,
 
     pub dns_lookup: Option<PeersDnsLookupState>,
 
@@ -48,6 +52,7 @@ impl PeersState {
         Self {
             list: BTreeMap::new(),
             ip_blacklist: BTreeMap::new(),
+            point_graylist: BTreeSet::new(),
 
             dns_lookup: None,
 
@@ -120,6 +125,16 @@ impl PeersState {
         self.ip_blacklist.remove(ip)
     }
 
+    #[inline(always)]
+    pub(super) fn graylist_point(&mut self, point: SocketAddr) -> bool {
+        self.point_graylist.insert(point)
+    }
+
+    #[inline(always)]
+    pub(super) fn remove_graylisted_point(&mut self, point: &SocketAddr) -> bool {
+        self.point_graylist.remove(point)
+    }
+
     #[inline(always)]
     pub fn iter(&self) -> impl Iterator<Item = (&SocketAddr, &Peer)> {
         self.list.iter()
@@ -181,6 +196,20 @@ impl PeersState {
         self.ip_blacklist.get(ip)
     }
 
+    #[inline(always)]
+    pub fn is_point_graylisted(&self, point: &SocketAddr) -> bool {
+        self.point_graylist.contains(point)
+    }
+
+    /// Listening address of the peer. For incoming connections it's only
+    /// known after the handshake, until then the connection address is returned.
+    pub fn peer_point(&self, address: &SocketAddr) -> SocketAddr {
+        match self.get_handshaked(address) {
+            Some(peer) => SocketAddr::new(address.ip(), peer.port),
+            None => *address,
+        }
+    }
+
     #[inline(always)]
     pub fn blacklist_ip_iter(&self) -> impl Iterator<Item = (&IpAddr, &PeerBlacklistState)> {
         self.ip_blacklist.iter()
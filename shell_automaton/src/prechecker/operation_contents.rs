@@ -5,7 +5,10 @@ use crypto::hash::{BlockHash, BlockPayloadHash, ChainId};
 use tezos_messages::{
     base::signature_public_key::SignaturePublicKey,
     p2p::encoding::operation::Operation,
-    protocol::{proto_012::operation::OperationVerifyError, SupportedProtocol},
+    protocol::{
+        proto_012::{operation::OperationVerifyError, operation_selection::ManagerInfo},
+        SupportedProtocol,
+    },
 };
 
 use crate::rights::Slot;
@@ -126,6 +129,19 @@ impl OperationDecodedContents {
         }
     }
 
+    /// Fee, gas and counters of the manager operation.
+    pub(crate) fn manager_info(&self) -> Option<ManagerInfo> {
+        match self {
+            OperationDecodedContents::Proto012(operation) => {
+                ManagerInfo::from_contents(&operation.contents)
+            }
+            OperationDecodedContents::Proto013(operation) => {
+                ManagerInfo::from_contents(&operation.contents)
+            }
+            _ => None,
+        }
+    }
+
     pub(crate) fn as_json(&self) -> serde_json::Value {
         match self {
             OperationDecodedContents::Proto010(operation) => operation.as_json(),
@@ -167,4 +167,14 @@ pub fn reducer(state: &mut State, action: &ActionWithMeta) {
         applied_actions_count_reducer,
         last_action_reducer
     );
+
+    #[cfg(feature = "invariants")]
+    if let Err(violation) = crate::invariants::check(state) {
+        panic!(
+            "{} after action {:?} (id: {})",
+            violation,
+            action.action.kind(),
+            u64::from(action.id)
+        );
+    }
 }
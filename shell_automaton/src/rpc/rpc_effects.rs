@@ -18,8 +18,11 @@ use crate::mempool::mempool_actions::{
     MempoolRpcEndorsementsStatusGetAction,
 };
 use crate::mempool::OperationKind;
+use crate::peers::graylist::{PeersGraylistPointAddAction, PeersGraylistPointRemoveAction};
 use crate::rights::{rights_actions::RightsRpcGetAction, RightsKey};
-use crate::service::rpc_service::{BakerPatch, BakingState, RpcRequest, RpcRequestStream};
+use crate::service::rpc_service::{
+    BakerPatch, BakingState, GraylistPatch, NodeMetrics, RpcRequest, RpcRequestStream,
+};
 use crate::service::{BakerService, RpcService, Service};
 use crate::storage::request::StorageRequestStatus;
 use crate::{Action, ActionWithMeta, Store};
@@ -204,6 +207,14 @@ pub fn rpc_effects<S: Service>(store: &mut Store<S>, action: &ActionWithMeta) {
 
                         let _ = channel.send(data);
                     }
+                    RpcRequest::GetMetrics { channel } => {
+                        let head_hash = store.state().current_head.get().map(|h| h.hash.clone());
+                        let head_stats = head_hash.and_then(|hash| {
+                            let stats = store.service.statistics()?;
+                            stats.block_stats_get_all().get(&hash).cloned()
+                        });
+                        let _ = channel.send(NodeMetrics::new(store.state(), head_stats));
+                    }
                     RpcRequest::InjectBlock { block } => {
                         if !store.dispatch(RpcInjectBlockAction {
                             rpc_id,
@@ -378,6 +389,20 @@ pub fn rpc_effects<S: Service>(store: &mut Store<S>, action: &ActionWithMeta) {
                             .rpc()
                             .respond(rpc_id, serde_json::Value::Bool(res));
                     }
+                    RpcRequest::PatchGraylist { patch } => {
+                        let res = match patch {
+                            GraylistPatch::Add { point } => {
+                                store.dispatch(PeersGraylistPointAddAction { point })
+                            }
+                            GraylistPatch::Remove { point } => {
+                                store.dispatch(PeersGraylistPointRemoveAction { point })
+                            }
+                        };
+                        store
+                            .service()
+                            .rpc()
+                            .respond(rpc_id, serde_json::Value::Bool(res));
+                    }
                 }
             }
         }
@@ -9,7 +9,7 @@ use std::sync::{mpsc, Arc};
 use crypto::hash::{BlockHash, ChainId};
 use networking::network_channel::{
     AllBlockOperationsReceived, BlockReceived, NetworkChannelMsg, NetworkChannelRef,
-    NetworkChannelTopic, NewCurrentHeadNotificationRef, PeerMessageReceived,
+    NetworkChannelTopic, NewCurrentHeadNotificationRef, PeerMessageReceived, PeerMessageSkipped,
 };
 use storage::BlockHeaderWithHash;
 use tezedge_actor_system::actors::*;
@@ -41,6 +41,7 @@ pub enum ActorsMessageTo {
     PeerHandshaked(Arc<PeerId>, MetadataMessage, Arc<NetworkVersion>),
     PeerDisconnected(SocketAddr),
     PeerMessageReceived(PeerMessageReceived),
+    PeerMessageSkipped(PeerMessageSkipped),
 
     NewCurrentHead(NewCurrentHeadNotificationRef),
     BlockReceived(BlockReceived),
@@ -56,6 +57,7 @@ impl From<ActorsMessageTo> for NetworkChannelMsg {
             }
             ActorsMessageTo::PeerDisconnected(address) => Self::PeerDisconnected(address),
             ActorsMessageTo::PeerMessageReceived(address) => Self::PeerMessageReceived(address),
+            ActorsMessageTo::PeerMessageSkipped(v) => Self::PeerMessageSkipped(v),
             ActorsMessageTo::NewCurrentHead(v) => Self::NewCurrentHead(v),
             ActorsMessageTo::BlockReceived(v) => Self::BlockReceived(v),
             ActorsMessageTo::BlockApplied(v) => Self::BlockApplied(v),
@@ -1,7 +1,10 @@
 // Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
 // SPDX-License-Identifier: MIT
 
+use std::time::Instant;
+
 pub use redux_rs::TimeService;
+pub use sandbox_clock::SandboxClock;
 
 pub mod service_async_channel;
 pub mod service_channel;
@@ -20,6 +23,8 @@ mod protocol_runner_service_worker;
 pub mod protocol_runner_service;
 pub use protocol_runner_service::{ProtocolRunnerService, ProtocolRunnerServiceDefault};
 
+pub mod recording;
+
 pub mod storage_service;
 pub use storage_service::{StorageService, StorageServiceDefault};
 
@@ -78,9 +83,18 @@ pub struct ServiceDefault {
     pub actors: ActorsServiceDefault,
     pub baker: BakerServiceDefault,
     pub statistics: Option<StatisticsService>,
+    /// Time source of the sandbox, the system time is used if not set.
+    pub clock: Option<SandboxClock>,
 }
 
-impl TimeService for ServiceDefault {}
+impl TimeService for ServiceDefault {
+    fn monotonic_time(&mut self) -> Instant {
+        match &self.clock {
+            Some(clock) => clock.monotonic_time(),
+            None => Instant::now(),
+        }
+    }
+}
 
 impl Service for ServiceDefault {
     type Randomness = RandomnessServiceDefault;
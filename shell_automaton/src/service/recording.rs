@@ -0,0 +1,318 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Recording of the actions and state snapshots to files, instead of the main database.
+//!
+//! A recording is a directory of zstd compressed files, named by the id of their
+//! first action, so they sort in the order of the actions. A new file is started
+//! once the current one reaches the maximum size. Each file is a sequence of bincode
+//! [`Record`]s, each one prefixed by its length as `u32` little endian. The action
+//! or the state in a record is encoded like in the main database.
+//!
+//! The file is flushed after every state snapshot, so a recording of a node which
+//! crashed can be read up to the last snapshot.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use storage::persistent::{Encoder, SchemaError};
+
+use crate::{Action, State};
+
+/// Extension of the recording files
+pub const RECORDING_FILE_EXTENSION: &str = "records.zst";
+
+const RECORDING_COMPRESSION_LEVEL: i32 = 3;
+
+#[derive(Debug, Error)]
+pub enum RecordingError {
+    #[error("I/O error: {0}")]
+    IO(#[from] io::Error),
+    #[error("Encoding error: {0}")]
+    Encoding(#[from] bincode::Error),
+    #[error("Schema error: {0}")]
+    Schema(#[from] SchemaError),
+    #[error("Recording file {path:?} is truncated")]
+    Truncated { path: PathBuf },
+}
+
+/// Bincode cannot decode [`Action`], as it is adjacently tagged, so the action
+/// and the state are stored in their main database encoding.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Record {
+    /// State snapshot, by the id of the last action applied to it
+    StateSnapshot {
+        action_id: u64,
+        state: Vec<u8>,
+    },
+    Action {
+        action_id: u64,
+        action: Vec<u8>,
+    },
+}
+
+impl Record {
+    pub fn state_snapshot(state: &State) -> Result<Self, RecordingError> {
+        Ok(Record::StateSnapshot {
+            action_id: state.last_action.id().into(),
+            state: state.encode()?,
+        })
+    }
+
+    pub fn action(action_id: u64, action: &Action) -> Result<Self, RecordingError> {
+        Ok(Record::Action {
+            action_id,
+            action: action.encode()?,
+        })
+    }
+
+    pub fn action_id(&self) -> u64 {
+        match self {
+            Record::StateSnapshot { action_id, .. } | Record::Action { action_id, .. } => {
+                *action_id
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordingConfig {
+    pub dir: PathBuf,
+    /// A new file is started once the compressed size of the current one reaches it
+    pub max_file_size: u64,
+}
+
+/// Counts the bytes written to the file, after the compression
+struct CountingWriter<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.written += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+type RecordingFileEncoder = zstd::Encoder<'static, CountingWriter<BufWriter<File>>>;
+
+pub struct RecordingWriter {
+    config: RecordingConfig,
+    file: Option<RecordingFileEncoder>,
+}
+
+impl RecordingWriter {
+    pub fn new(config: RecordingConfig) -> Result<Self, RecordingError> {
+        fs::create_dir_all(&config.dir)?;
+        Ok(Self { config, file: None })
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), RecordingError> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(self.create_file(record.action_id())?),
+        };
+
+        let bytes = bincode::serialize(record)?;
+        file.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        file.write_all(&bytes)?;
+        if let Record::StateSnapshot { .. } = record {
+            // the encoder does not flush the file
+            file.flush()?;
+            file.get_mut().flush()?;
+        }
+
+        if file.get_ref().written >= self.config.max_file_size {
+            self.finish_file()?;
+        }
+        Ok(())
+    }
+
+    fn create_file(&self, first_action_id: u64) -> Result<RecordingFileEncoder, RecordingError> {
+        let path = self.config.dir.join(format!(
+            "{:020}.{}",
+            first_action_id, RECORDING_FILE_EXTENSION
+        ));
+        let writer = CountingWriter {
+            inner: BufWriter::new(File::create(path)?),
+            written: 0,
+        };
+        Ok(zstd::Encoder::new(writer, RECORDING_COMPRESSION_LEVEL)?)
+    }
+
+    fn finish_file(&mut self) -> Result<(), RecordingError> {
+        if let Some(file) = self.file.take() {
+            file.finish()?.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RecordingWriter {
+    fn drop(&mut self) {
+        let _ = self.finish_file();
+    }
+}
+
+/// Recording files in `dir`, in the order of the actions.
+pub fn recording_files(dir: &Path) -> Result<Vec<PathBuf>, RecordingError> {
+    let mut files = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .filter(|path| match path {
+            Ok(path) => path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| name.ends_with(RECORDING_FILE_EXTENSION)),
+            Err(_) => true,
+        })
+        .collect::<Result<Vec<_>, io::Error>>()?;
+    files.sort();
+    Ok(files)
+}
+
+/// Reads the records of one recording file.
+///
+/// A file which was not finished, because the node crashed, ends with
+/// [`RecordingError::Truncated`] after its last complete record.
+pub struct RecordingReader {
+    path: PathBuf,
+    file: zstd::Decoder<'static, BufReader<File>>,
+    done: bool,
+}
+
+impl RecordingReader {
+    pub fn open(path: &Path) -> Result<Self, RecordingError> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: zstd::Decoder::new(File::open(path)?)?,
+            done: false,
+        })
+    }
+
+    fn read_record(&mut self) -> Result<Option<Record>, RecordingError> {
+        let mut len = [0; 4];
+        let mut read = 0;
+        while read < len.len() {
+            match self.file.read(&mut len[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(self.truncated()),
+                Ok(n) => read += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(self.truncated())
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let mut bytes = vec![0; u32::from_le_bytes(len) as usize];
+        match self.file.read_exact(&mut bytes) {
+            Ok(()) => Ok(Some(bincode::deserialize(&bytes)?)),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Err(self.truncated()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn truncated(&self) -> RecordingError {
+        RecordingError::Truncated {
+            path: self.path.clone(),
+        }
+    }
+}
+
+impl Iterator for RecordingReader {
+    type Item = Result<Record, RecordingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_record().transpose();
+        self.done = !matches!(result, Some(Ok(_)));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(action_id: u64) -> Record {
+        Record::Action {
+            action_id,
+            action: vec![action_id as u8; 100],
+        }
+    }
+
+    fn read_all(dir: &Path) -> Vec<Result<Record, String>> {
+        recording_files(dir)
+            .unwrap()
+            .iter()
+            .flat_map(|path| RecordingReader::open(path).unwrap())
+            .map(|result| result.map_err(|err| err.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_write_read_rotated() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = |action_id| Record::StateSnapshot {
+            action_id,
+            state: vec![1, 2, 3],
+        };
+        let mut records = vec![snapshot(1)];
+        records.extend((2..50).map(action));
+        records.push(snapshot(49));
+        records.extend((50..100).map(action));
+
+        let mut writer = RecordingWriter::new(RecordingConfig {
+            dir: dir.path().to_path_buf(),
+            max_file_size: 1,
+        })
+        .unwrap();
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        drop(writer);
+
+        // the compressed size is known once the snapshot is flushed
+        assert_eq!(recording_files(dir.path()).unwrap().len(), 3);
+        let read = read_all(dir.path());
+        assert_eq!(read, records.into_iter().map(Ok).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_read_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = RecordingWriter::new(RecordingConfig {
+            dir: dir.path().to_path_buf(),
+            max_file_size: u64::MAX,
+        })
+        .unwrap();
+        writer.write(&action(1)).unwrap();
+        writer
+            .write(&Record::StateSnapshot {
+                action_id: 1,
+                state: vec![],
+            })
+            .unwrap();
+        writer.write(&action(2)).unwrap();
+        // the node crashed, the last records are not flushed
+        std::mem::forget(writer.file.take());
+
+        let read = read_all(dir.path());
+        assert_eq!(read.len(), 3);
+        assert_eq!(read[0], Ok(action(1)));
+        assert!(read[2].as_ref().unwrap_err().contains("truncated"));
+    }
+}
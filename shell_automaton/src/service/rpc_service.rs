@@ -3,6 +3,7 @@
 
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap},
+    net::SocketAddr,
     sync::Arc,
     thread,
     time::Instant,
@@ -27,6 +28,7 @@ use tezos_messages::{
 use crate::{
     baker::BakerState,
     mempool::{mempool_actions::ConsensusOperationMatcher, OperationStats, QuorumState},
+    peer::PeerStatus,
     request::RequestId,
     rpc::ValidBlocksQuery,
     storage::request::StorageRequestor,
@@ -85,6 +87,61 @@ pub struct BakingState {
     pub bakers: BTreeMap<SignaturePublicKeyHash, BakerState>,
 }
 
+/// Summary of the node state, exported in the Prometheus format.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NodeMetrics {
+    pub current_head_level: Option<Level>,
+    pub is_bootstrapped: bool,
+    pub bootstrap_finished: bool,
+    /// Number of peers by the status of the connection
+    pub peers: BTreeMap<String, usize>,
+    /// Number of mempool operations by their classification
+    pub mempool: BTreeMap<String, usize>,
+    /// Application of the current head, if the statistics are enabled
+    pub current_head_stats: Option<BlockApplyStats>,
+}
+
+impl NodeMetrics {
+    pub fn new(state: &State, current_head_stats: Option<BlockApplyStats>) -> Self {
+        let mut peers = BTreeMap::new();
+        for (_, peer) in state.peers.iter() {
+            let status = match &peer.status {
+                PeerStatus::Potential => "potential",
+                PeerStatus::Connecting(_) => "connecting",
+                PeerStatus::Handshaking(_) => "handshaking",
+                PeerStatus::Handshaked(_) => "handshaked",
+                PeerStatus::Disconnecting(_) => "disconnecting",
+                PeerStatus::Disconnected => "disconnected",
+            };
+            *peers.entry(status.to_owned()).or_default() += 1;
+        }
+
+        let mempool = &state.mempool;
+        let validated = &mempool.validated_operations;
+        let mempool = [
+            ("pending", mempool.pending_operations_len()),
+            ("applied", validated.applied.len()),
+            ("branch_delayed", validated.branch_delayed.len()),
+            ("branch_refused", validated.branch_refused.len()),
+            ("refused", validated.refused.len()),
+            ("outdated", validated.outdated.len()),
+            ("unparseable", mempool.unparseable_operations.len()),
+        ]
+        .into_iter()
+        .map(|(classification, len)| (classification.to_owned(), len))
+        .collect();
+
+        Self {
+            current_head_level: state.current_head_level(),
+            is_bootstrapped: state.is_bootstrapped(),
+            bootstrap_finished: state.bootstrap.is_finished(),
+            peers,
+            mempool,
+            current_head_stats,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum RpcRequest {
     GetCurrentGlobalState {
@@ -118,6 +175,9 @@ pub enum RpcRequest {
     GetBakingState {
         channel: oneshot::Sender<Option<BakingState>>,
     },
+    GetMetrics {
+        channel: oneshot::Sender<NodeMetrics>,
+    },
 
     InjectBlockStart {
         chain_id: ChainId,
@@ -155,6 +215,9 @@ pub enum RpcRequest {
     PatchBakers {
         patch: BakerPatch,
     },
+    PatchGraylist {
+        patch: GraylistPatch,
+    },
 }
 
 #[derive(Debug, Deserialize)]
@@ -164,6 +227,14 @@ pub enum BakerPatch {
     Remove { baker: String },
 }
 
+/// Graylists the peer point (listening address), or removes it from graylist.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "command")]
+pub enum GraylistPatch {
+    Add { point: SocketAddr },
+    Remove { point: SocketAddr },
+}
+
 #[derive(Debug)]
 pub enum MempoolOperationStatsFilter {
     None,
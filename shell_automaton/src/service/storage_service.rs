@@ -30,6 +30,7 @@ use crate::request::RequestId;
 use crate::storage::kv_cycle_meta::CycleKey;
 use crate::{Action, ActionId, ActionWithMeta, State};
 
+use super::recording::{Record, RecordingError, RecordingWriter};
 use super::service_channel::{
     worker_channel, RequestSendError, ResponseTryRecvError, ServiceWorkerRequester,
     ServiceWorkerResponder,
@@ -76,6 +77,12 @@ impl From<std::io::Error> for StorageError {
     }
 }
 
+impl From<RecordingError> for StorageError {
+    fn from(err: RecordingError) -> Self {
+        Self(format!("recording error: {}", err))
+    }
+}
+
 impl From<serde_json::Error> for StorageError {
     fn from(err: serde_json::Error) -> Self {
         Self(format!("json error: {}", err))
@@ -323,6 +330,7 @@ impl StorageServiceDefault {
     fn run_worker(
         log: slog::Logger,
         storage: PersistentStorage,
+        mut recording: Option<RecordingWriter>,
         mut channel: StorageWorkerResponder,
     ) {
         use StorageRequestPayload::*;
@@ -347,15 +355,31 @@ impl StorageServiceDefault {
             let result = match req.payload {
                 StateSnapshotPut(state) => {
                     let last_action_id = state.last_action.id();
-                    snapshot_storage
-                        .put(&last_action_id.into(), &*state)
+                    let result = match &mut recording {
+                        Some(recording) => Record::state_snapshot(&state)
+                            .and_then(|record| recording.write(&record))
+                            .map_err(StorageError::from),
+                        None => snapshot_storage
+                            .put(&last_action_id.into(), &*state)
+                            .map_err(StorageError::from),
+                    };
+                    result
                         .map(|_| StateSnapshotPutSuccess(last_action_id))
-                        .map_err(|err| StateSnapshotPutError(last_action_id, err.into()))
+                        .map_err(|err| StateSnapshotPutError(last_action_id, err))
+                }
+                ActionPut(action) => {
+                    let result = match &mut recording {
+                        Some(recording) => Record::action(action.id.into(), &action.action)
+                            .and_then(|record| recording.write(&record))
+                            .map_err(StorageError::from),
+                        None => action_storage
+                            .put::<Action>(&action.id.into(), &action.action)
+                            .map_err(StorageError::from),
+                    };
+                    result
+                        .map(|_| ActionPutSuccess(action.id))
+                        .map_err(ActionPutError)
                 }
-                ActionPut(action) => action_storage
-                    .put::<Action>(&action.id.into(), &action.action)
-                    .map(|_| ActionPutSuccess(action.id))
-                    .map_err(|err| ActionPutError(err.into())),
 
                 BlockMetaGet(block_hash) => block_meta_storage
                     .get(&block_hash)
@@ -576,6 +600,7 @@ impl StorageServiceDefault {
         log: slog::Logger,
         waker: Arc<mio::Waker>,
         persistent_storage: PersistentStorage,
+        recording: Option<RecordingWriter>,
         channel_bound: usize,
     ) -> Self {
         let (requester, responder) = worker_channel(waker, channel_bound);
@@ -584,7 +609,7 @@ impl StorageServiceDefault {
 
         thread::Builder::new()
             .name("storage-thread".to_owned())
-            .spawn(move || Self::run_worker(log, storage, responder))
+            .spawn(move || Self::run_worker(log, storage, recording, responder))
             .unwrap();
 
         Self {
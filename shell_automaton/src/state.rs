@@ -19,6 +19,7 @@ use crate::bootstrap::BootstrapState;
 use crate::config::Config;
 use crate::current_head::CurrentHeadState;
 use crate::current_head_precheck::CurrentHeads;
+use crate::invariants::InvariantViolation;
 use crate::mempool::MempoolState;
 use crate::paused_loops::PausedLoopsState;
 use crate::peer::connection::incoming::accept::PeerConnectionIncomingAcceptState;
@@ -315,37 +316,10 @@ impl storage::persistent::Decoder for State {
     }
 }
 
-#[derive(Debug)]
-pub enum SafetyConditionError {
-    ConnectedPeersMaxedOut,
-    PotentialPeersMaxedOut,
-    ConnectedPeerIsBlacklisted,
-}
-
 impl SafetyCondition for State {
-    type Error = SafetyConditionError;
+    type Error = InvariantViolation;
 
     fn check_safety_condition(&self) -> Result<(), Self::Error> {
-        /*
-            TODO: safety conditions checks are temporarily disabled.
-            If enabled, these conditions are quickly violated by the Actions-Fuzzer.
-            Once these are fixed the following code can be un-commented.
-        */
-        /*
-        if self.peers.connected_len() > self.config.peers_connected_max {
-            return Err(SafetyConditionError::ConnectedPeersMaxedOut)
-        }
-
-        if self.peers.potential_len() > self.config.peers_potential_max {
-            return Err(SafetyConditionError::PotentialPeersMaxedOut);
-        }
-
-        for (peer_addr, _) in self.peers.connected_iter() {
-            if self.peers.is_blacklisted(&peer_addr.ip()) {
-                return Err(SafetyConditionError::ConnectedPeerIsBlacklisted);
-            }
-        }
-        */
-        Ok(())
+        crate::invariants::check(self)
     }
 }
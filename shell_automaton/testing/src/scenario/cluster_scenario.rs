@@ -0,0 +1,468 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crypto::hash::{BlockHash, ChainId, CryptoboxPublicKeyHash, ProtocolHash};
+use shell_automaton::block_applier::{
+    BlockApplierApplyProtocolRunnerApplySuccessAction, BlockApplierApplyState,
+};
+use shell_automaton::bootstrap::BootstrapState;
+use shell_automaton::event::WakeupEvent;
+use shell_automaton::service::storage_service::{
+    StorageError, StorageRequestPayload, StorageResponse, StorageResponseError,
+    StorageResponseSuccess,
+};
+use shell_automaton::State;
+use storage::block_meta_storage::Meta;
+use storage::{BlockAdditionalData, BlockHeaderWithHash};
+use tezos_api::ffi::{ApplyBlockRequest, ApplyBlockResponse};
+use tezos_messages::p2p::encoding::current_branch::{BlockLocator, CurrentBranchMessage};
+use tezos_messages::p2p::encoding::current_head::CurrentHeadMessage;
+use tezos_messages::p2p::encoding::mempool::Mempool;
+use tezos_messages::p2p::encoding::operations_for_blocks::{OperationsForBlocksMessage, Path};
+use tezos_messages::p2p::encoding::peer::PeerMessage;
+use tezos_messages::protocol::proto_010;
+
+use crate::build_expected_history;
+use crate::one_real_node_cluster::Cluster;
+use crate::service::{IOCondition, MioPeerMockedId};
+
+use super::{Misbehavior, MockedChain, MockedPeer, RequestKind};
+
+/// Index of the peer in the [`Scenario`], in the order of connection.
+pub type ScenarioPeerId = usize;
+
+/// Rounds of [`Scenario::run`] after which the node is considered to be
+/// stuck in a request loop.
+const MAX_ROUNDS: usize = 10_000;
+
+/// Max operations ttl in the mocked results of block application.
+const MAX_OPERATIONS_TTL: i32 = 120;
+
+struct ScenarioPeer {
+    id: MioPeerMockedId,
+    public_key_hash: CryptoboxPublicKeyHash,
+    chain: MockedChain,
+    misbehaviors: BTreeMap<RequestKind, (Misbehavior, usize)>,
+    /// Number of responded requests by kind.
+    responded: BTreeMap<RequestKind, usize>,
+    /// Messages received from the node.
+    received: Vec<PeerMessage>,
+}
+
+/// One real node, connected to mocked peers which serve their chains to it.
+///
+/// Peers respond to the requests of the node in [`Scenario::run`], until it
+/// has nothing more to request, so that the outcome of bootstrap or of
+/// a reorg can be asserted on the state of the node.
+///
+/// Storage and the protocol runner are mocked for block application, every
+/// block scheduled by the node is applied successfully in [`Scenario::run`].
+pub struct Scenario {
+    cluster: Cluster,
+    local_chain: MockedChain,
+    peers: Vec<ScenarioPeer>,
+}
+
+impl Scenario {
+    pub(super) fn new(cluster: Cluster, local_chain: MockedChain) -> Self {
+        Self {
+            cluster,
+            local_chain,
+            peers: vec![],
+        }
+    }
+
+    /// Spawns the mocked peer, connects it to the node and does the handshake.
+    pub fn connect(&mut self, peer: MockedPeer) -> ScenarioPeerId {
+        let pow_target = self.state().config.pow_target;
+        let id = self.cluster.peer_init(pow_target);
+        self.cluster.connect_to_peer(id);
+        self.cluster.set_peer_connected(id);
+        self.cluster.do_handshake(id).unwrap();
+
+        let public_key_hash = self.cluster.peer(id).identity().peer_id();
+        self.peers.push(ScenarioPeer {
+            id,
+            public_key_hash,
+            chain: peer.chain,
+            misbehaviors: peer.misbehaviors,
+            responded: BTreeMap::new(),
+            received: vec![],
+        });
+        self.peers.len() - 1
+    }
+
+    /// Closes the connection from the peer side.
+    pub fn disconnect(&mut self, peer: ScenarioPeerId) {
+        let id = self.peers[peer].id;
+        self.cluster.peer(id).disconnect();
+        self.cluster
+            .dispatch_peer_ready_event(id, false, false, true);
+    }
+
+    pub fn is_connected(&mut self, peer: ScenarioPeerId) -> bool {
+        let id = self.peers[peer].id;
+        self.cluster.peer(id).is_connected()
+    }
+
+    /// Switches the peer to another chain, e.g. a longer fork to cause
+    /// a reorg. The peer doesn't advertise it until asked to.
+    pub fn set_chain(&mut self, peer: ScenarioPeerId, chain: MockedChain) {
+        self.peers[peer].chain = chain;
+    }
+
+    /// Peer sends the head of its chain to the node, without a request.
+    pub fn advertise_current_head(&mut self, peer: ScenarioPeerId) {
+        let message = self.current_head_message(peer);
+        self.send(peer, vec![message]);
+    }
+
+    /// Connected peers send the heads of their chains to the node.
+    pub fn advertise_current_heads(&mut self) {
+        for peer in 0..self.peers.len() {
+            if self.is_connected(peer) {
+                self.advertise_current_head(peer);
+            }
+        }
+    }
+
+    /// Peers respond to the node and scheduled blocks are applied, until
+    /// it has no more requests for the peers nor blocks to apply.
+    ///
+    /// Returns the number of messages the node sent in the meantime.
+    pub fn run(&mut self) -> usize {
+        let mut total = 0;
+        for _ in 0..MAX_ROUNDS {
+            let received = (0..self.peers.len())
+                .map(|peer| self.respond(peer))
+                .sum::<usize>();
+            let applied = self.apply_blocks();
+            if received == 0 && applied == 0 {
+                return total;
+            }
+            total += received;
+        }
+        panic!("node still sends requests after {} rounds", MAX_ROUNDS);
+    }
+
+    /// Advances time and lets the node check timeouts, then runs the scenario.
+    pub fn advance_time(&mut self, by: Duration) -> usize {
+        self.cluster.advance_time(by);
+        self.cluster.loop_next();
+        self.run()
+    }
+
+    pub fn cluster(&mut self) -> &mut Cluster {
+        &mut self.cluster
+    }
+
+    pub fn state(&self) -> &State {
+        self.cluster.state()
+    }
+
+    pub fn local_chain(&self) -> &MockedChain {
+        &self.local_chain
+    }
+
+    pub fn peer_address(&self, peer: ScenarioPeerId) -> SocketAddr {
+        self.peers[peer].id.to_ipv4()
+    }
+
+    pub fn peer_chain(&self, peer: ScenarioPeerId) -> &MockedChain {
+        &self.peers[peer].chain
+    }
+
+    /// Messages the peer received from the node.
+    pub fn received(&self, peer: ScenarioPeerId) -> &[PeerMessage] {
+        &self.peers[peer].received
+    }
+
+    /// Number of requests of the `kind` the peer received from the node.
+    pub fn received_requests(&self, peer: ScenarioPeerId, kind: RequestKind) -> usize {
+        self.received(peer)
+            .iter()
+            .filter(|message| RequestKind::of(message) == Some(kind))
+            .count()
+    }
+
+    pub fn current_head(&self) -> &BlockHeaderWithHash {
+        self.state().current_head.get().unwrap()
+    }
+
+    pub fn bootstrap(&self) -> &BootstrapState {
+        &self.state().bootstrap
+    }
+
+    /// Block being applied, followed by the ones queued for application.
+    pub fn blocks_scheduled_for_apply(&self) -> Vec<BlockHash> {
+        let block_applier = &self.state().block_applier;
+        block_applier
+            .current
+            .block_hash()
+            .filter(|_| block_applier.current.is_pending())
+            .into_iter()
+            .chain(
+                block_applier
+                    .queue
+                    .iter()
+                    .map(|(block_hash, _)| &**block_hash),
+            )
+            .cloned()
+            .collect()
+    }
+
+    pub fn is_graylisted(&self, peer: ScenarioPeerId) -> bool {
+        self.state()
+            .peers
+            .is_blacklisted(&self.peer_address(peer).ip())
+    }
+
+    /// Responds to the storage requests of the block applier and mocks
+    /// the protocol runner's result of block application, until there is
+    /// no block left to apply. Returns the number of applied blocks.
+    fn apply_blocks(&mut self) -> usize {
+        let mut applied = 0;
+        loop {
+            let requests = std::mem::take(&mut self.cluster.service().storage.requests);
+            let mut responses = vec![];
+            for request in requests {
+                let result = match &request.payload {
+                    StorageRequestPayload::PrepareApplyBlockData {
+                        chain_id,
+                        block_hash,
+                    } => self.prepare_apply_block_data(chain_id, block_hash),
+                    StorageRequestPayload::StoreApplyBlockResult { block_result, .. } => {
+                        Ok(StorageResponseSuccess::StoreApplyBlockResultSuccess(
+                            Arc::new(block_additional_data(block_result)),
+                        ))
+                    }
+                    _ => {
+                        self.cluster.service().storage.requests.push_back(request);
+                        continue;
+                    }
+                };
+                responses.push(StorageResponse::new(request.id, result));
+            }
+            if !responses.is_empty() {
+                self.cluster.service().storage.responses.extend(responses);
+                self.cluster.dispatch(WakeupEvent);
+                continue;
+            }
+
+            let block = match &self.state().block_applier.current {
+                BlockApplierApplyState::ProtocolRunnerApplyPending { block, .. } => block.clone(),
+                _ => return applied,
+            };
+            self.cluster
+                .dispatch(BlockApplierApplyProtocolRunnerApplySuccessAction {
+                    apply_result: Arc::new(apply_result(&block)),
+                });
+            applied += 1;
+        }
+    }
+
+    fn prepare_apply_block_data(
+        &self,
+        chain_id: &ChainId,
+        block_hash: &BlockHash,
+    ) -> Result<StorageResponseSuccess, StorageResponseError> {
+        let not_found = || StorageResponseError::PrepareApplyBlockDataError(StorageError::mocked());
+        let chain = self
+            .peers
+            .iter()
+            .map(|peer| &peer.chain)
+            .chain(std::iter::once(&self.local_chain))
+            .find(|chain| chain.block(block_hash).is_some())
+            .ok_or_else(not_found)?;
+        let block = chain.block(block_hash).ok_or_else(not_found)?;
+        let pred = chain
+            .block(block.header.predecessor())
+            .ok_or_else(not_found)?;
+        let operations = (0..block.header.validation_pass())
+            .map(|validation_pass| chain.operations(block_hash, validation_pass))
+            .collect();
+
+        Ok(StorageResponseSuccess::PrepareApplyBlockDataSuccess {
+            block: Arc::new(block.clone()),
+            block_meta: Arc::new(Meta::new(
+                false,
+                Some(pred.hash.clone()),
+                block.header.level(),
+                chain_id.clone(),
+            )),
+            apply_block_req: Arc::new(ApplyBlockRequest {
+                chain_id: chain_id.clone(),
+                block_header: (*block.header).clone(),
+                pred_header: (*pred.header).clone(),
+                max_operations_ttl: MAX_OPERATIONS_TTL,
+                operations,
+                predecessor_block_metadata_hash: None,
+                predecessor_ops_metadata_hash: None,
+            }),
+        })
+    }
+
+    fn send(&mut self, peer: ScenarioPeerId, messages: Vec<PeerMessage>) {
+        let id = self.peers[peer].id;
+        let stream = self.cluster.peer(id);
+        for message in messages {
+            stream.send_peer_message(message);
+        }
+        stream
+            .set_read_cond(IOCondition::NoLimit)
+            .set_write_cond(IOCondition::NoLimit);
+        self.cluster
+            .dispatch_peer_ready_event(id, true, true, false);
+    }
+
+    /// Reads the messages the node sent to the peer and responds to the
+    /// requests. Returns the number of read messages.
+    fn respond(&mut self, peer: ScenarioPeerId) -> usize {
+        let id = self.peers[peer].id;
+        if !self.cluster.peer(id).is_connected() {
+            return 0;
+        }
+        // Let the node write the messages it has queued for the peer.
+        self.cluster.peer(id).set_write_cond(IOCondition::NoLimit);
+        self.cluster
+            .dispatch_peer_ready_event(id, false, true, false);
+
+        let mut messages = vec![];
+        while let Some(message) = self.cluster.peer(id).read_peer_message() {
+            messages.push(message);
+        }
+        let received = messages.len();
+
+        let mut responses = vec![];
+        for message in messages {
+            let scenario_peer = &mut self.peers[peer];
+            scenario_peer.received.push(message.clone());
+            let kind = match RequestKind::of(&message) {
+                Some(v) => v,
+                None => continue,
+            };
+            let responded = scenario_peer.responded.entry(kind).or_insert(0);
+            let misbehavior = scenario_peer
+                .misbehaviors
+                .get(&kind)
+                .filter(|(_, after)| *responded >= *after)
+                .map(|(misbehavior, _)| *misbehavior);
+            match misbehavior {
+                Some(Misbehavior::Ignore) => {}
+                Some(Misbehavior::Disconnect) => {
+                    self.disconnect(peer);
+                    return received;
+                }
+                None => {
+                    *responded += 1;
+                    responses.extend(self.responses(peer, &message));
+                }
+            }
+        }
+        if !responses.is_empty() {
+            self.send(peer, responses);
+        }
+        received
+    }
+
+    fn responses(&self, peer: ScenarioPeerId, request: &PeerMessage) -> Vec<PeerMessage> {
+        let chain = &self.peers[peer].chain;
+        match request {
+            PeerMessage::GetCurrentBranch(_) => vec![self.current_branch_message(peer)],
+            PeerMessage::GetCurrentHead(_) => vec![self.current_head_message(peer)],
+            PeerMessage::GetBlockHeaders(request) => request
+                .get_block_headers()
+                .iter()
+                .filter_map(|block_hash| chain.block(block_hash))
+                .map(|block| PeerMessage::BlockHeader((*block.header).clone().into()))
+                .collect(),
+            PeerMessage::GetOperationsForBlocks(request) => request
+                .get_operations_for_blocks()
+                .iter()
+                .filter(|key| chain.block(key.block_hash()).is_some())
+                .map(|key| {
+                    let operations =
+                        chain.operations(key.block_hash(), key.validation_pass() as u8);
+                    PeerMessage::OperationsForBlocks(OperationsForBlocksMessage::new(
+                        key.clone(),
+                        Path(vec![]),
+                        operations,
+                    ))
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    fn current_head_message(&self, peer: ScenarioPeerId) -> PeerMessage {
+        let head = self.peers[peer].chain.head();
+        PeerMessage::CurrentHead(CurrentHeadMessage::new(
+            self.state().config.chain_id.clone(),
+            (*head.header).clone(),
+            Mempool::new(vec![], vec![]),
+        ))
+    }
+
+    fn current_branch_message(&self, peer: ScenarioPeerId) -> PeerMessage {
+        let scenario_peer = &self.peers[peer];
+        let head = scenario_peer.chain.head();
+        let history = build_expected_history(
+            scenario_peer.chain.blocks(),
+            scenario_peer.public_key_hash.clone(),
+            self.state().config.identity.peer_id(),
+            head.header.level(),
+        )
+        .into_iter()
+        .map(|block| block.hash)
+        .collect();
+        PeerMessage::CurrentBranch(CurrentBranchMessage::new(
+            self.state().config.chain_id.clone(),
+            BlockLocator::new((*head.header).clone(), history),
+        ))
+    }
+}
+
+/// Successful result of the block application by the protocol runner.
+fn apply_result(block: &BlockHeaderWithHash) -> ApplyBlockResponse {
+    let protocol_hash = ProtocolHash::from_base58_check(proto_010::PROTOCOL_HASH).unwrap();
+    ApplyBlockResponse {
+        validation_result_message: "applied".to_owned(),
+        context_hash: block.header.context().clone(),
+        protocol_hash: protocol_hash.clone(),
+        next_protocol_hash: protocol_hash,
+        block_header_proto_json: "{}".to_owned(),
+        block_header_proto_metadata_bytes: vec![],
+        operations_proto_metadata_bytes: vec![],
+        max_operations_ttl: MAX_OPERATIONS_TTL,
+        last_allowed_fork_level: 0,
+        forking_testchain: false,
+        forking_testchain_data: None,
+        block_metadata_hash: None,
+        ops_metadata_hashes: None,
+        ops_metadata_hash: None,
+        cycle: None,
+        cycle_position: None,
+        cycle_rolls_owner_snapshots: vec![],
+        new_protocol_constants_json: None,
+        new_cycle_eras_json: None,
+        commit_time: 0.0,
+        execution_timestamps: Default::default(),
+    }
+}
+
+fn block_additional_data(block_result: &ApplyBlockResponse) -> BlockAdditionalData {
+    BlockAdditionalData::new(
+        block_result.max_operations_ttl as u16,
+        block_result.last_allowed_fork_level,
+        block_result.protocol_hash.clone(),
+        block_result.next_protocol_hash.clone(),
+        block_result.block_metadata_hash.clone(),
+        block_result.ops_metadata_hash.clone(),
+        block_result.ops_metadata_hashes.clone(),
+    )
+}
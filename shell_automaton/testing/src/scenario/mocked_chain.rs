@@ -0,0 +1,116 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::collections::BTreeMap;
+
+use crypto::hash::BlockHash;
+use storage::BlockHeaderWithHash;
+use tezos_messages::p2p::encoding::block_header::Level;
+use tezos_messages::p2p::encoding::operation::Operation;
+
+use crate::{generate_chain, generate_next_block};
+
+/// Chain of blocks from genesis to the head, with the operations of
+/// each block, which a mocked peer serves to the node.
+#[derive(Debug, Clone)]
+pub struct MockedChain {
+    /// Block at index `i` has level `i`.
+    blocks: Vec<BlockHeaderWithHash>,
+    /// Operations of the block by validation pass. Blocks which are
+    /// missing here have no operations.
+    operations: BTreeMap<BlockHash, Vec<Vec<Operation>>>,
+}
+
+impl MockedChain {
+    pub fn generate(genesis_block: BlockHeaderWithHash, head_level: Level) -> Self {
+        Self {
+            blocks: generate_chain(genesis_block, head_level),
+            operations: BTreeMap::new(),
+        }
+    }
+
+    /// Same chain, with `length` more blocks on top of the head.
+    pub fn extend(&self, length: usize) -> Self {
+        self.fork(self.head().header.level(), length, 0)
+    }
+
+    /// Chain which leaves this one after the block at `level` and has
+    /// `length` blocks of its own. Forks from the same block are only
+    /// different if their `timestamp_offset` is.
+    pub fn fork(&self, level: Level, length: usize, timestamp_offset: i64) -> Self {
+        let mut blocks = self.blocks[..=level as usize].to_vec();
+        for _ in 0..length {
+            let block = generate_next_block(blocks.last().unwrap(), timestamp_offset);
+            blocks.push(block);
+        }
+        let operations = blocks
+            .iter()
+            .filter_map(|block| {
+                let operations = self.operations.get(&block.hash)?;
+                Some((block.hash.clone(), operations.clone()))
+            })
+            .collect();
+        Self { blocks, operations }
+    }
+
+    /// Generates `count` operations in every validation pass of the blocks
+    /// which don't have operations yet.
+    pub fn with_operations(mut self, count: usize) -> Self {
+        for block in self.blocks.iter().skip(1) {
+            let level = block.header.level();
+            self.operations
+                .entry(block.hash.clone())
+                .or_insert_with(|| {
+                    (0..block.header.validation_pass())
+                        .map(|validation_pass| {
+                            (0..count)
+                                .map(|index| {
+                                    let mut data = level.to_be_bytes().to_vec();
+                                    data.extend_from_slice(&[validation_pass, index as u8]);
+                                    Operation::new(block.header.predecessor().clone(), data.into())
+                                })
+                                .collect()
+                        })
+                        .collect()
+                });
+        }
+        self
+    }
+
+    pub fn blocks(&self) -> &[BlockHeaderWithHash] {
+        &self.blocks
+    }
+
+    pub fn head(&self) -> &BlockHeaderWithHash {
+        self.blocks.last().unwrap()
+    }
+
+    pub fn head_pred(&self) -> Option<&BlockHeaderWithHash> {
+        self.blocks.iter().rev().nth(1)
+    }
+
+    pub fn block_at(&self, level: Level) -> Option<&BlockHeaderWithHash> {
+        self.blocks.get(usize::try_from(level).ok()?)
+    }
+
+    pub fn block(&self, block_hash: &BlockHash) -> Option<&BlockHeaderWithHash> {
+        self.blocks
+            .iter()
+            .rev()
+            .find(|block| &block.hash == block_hash)
+    }
+
+    /// Blocks above `level`, from the lowest one.
+    pub fn blocks_above(&self, level: Level) -> &[BlockHeaderWithHash] {
+        let start = usize::try_from(level + 1).unwrap_or(0);
+        &self.blocks[start.min(self.blocks.len())..]
+    }
+
+    pub fn operations(&self, block_hash: &BlockHash, validation_pass: u8) -> Vec<Operation> {
+        self.operations
+            .get(block_hash)
+            .and_then(|operations| operations.get(validation_pass as usize))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
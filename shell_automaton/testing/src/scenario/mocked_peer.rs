@@ -0,0 +1,72 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::collections::BTreeMap;
+
+use tezos_messages::p2p::encoding::peer::PeerMessage;
+
+use super::MockedChain;
+
+/// Request from the node, which a mocked peer responds to.
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Clone, Copy)]
+pub enum RequestKind {
+    CurrentBranch,
+    CurrentHead,
+    BlockHeaders,
+    BlockOperations,
+}
+
+impl RequestKind {
+    pub fn of(message: &PeerMessage) -> Option<Self> {
+        match message {
+            PeerMessage::GetCurrentBranch(_) => Some(Self::CurrentBranch),
+            PeerMessage::GetCurrentHead(_) => Some(Self::CurrentHead),
+            PeerMessage::GetBlockHeaders(_) => Some(Self::BlockHeaders),
+            PeerMessage::GetOperationsForBlocks(_) => Some(Self::BlockOperations),
+            _ => None,
+        }
+    }
+}
+
+/// What a mocked peer does instead of responding to a request.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Misbehavior {
+    /// Never respond.
+    Ignore,
+    /// Close the connection.
+    Disconnect,
+}
+
+/// Mocked peer of a scenario, before it is connected to the node.
+#[derive(Debug, Clone)]
+pub struct MockedPeer {
+    pub chain: MockedChain,
+    /// Misbehavior for the kind of request, after that many requests
+    /// of that kind were responded to.
+    pub misbehaviors: BTreeMap<RequestKind, (Misbehavior, usize)>,
+}
+
+impl MockedPeer {
+    pub fn new(chain: MockedChain) -> Self {
+        Self {
+            chain,
+            misbehaviors: BTreeMap::new(),
+        }
+    }
+
+    /// Misbehave on every request of the `kind`.
+    pub fn misbehave_on(self, kind: RequestKind, misbehavior: Misbehavior) -> Self {
+        self.misbehave_on_after(kind, misbehavior, 0)
+    }
+
+    /// Respond to the first `responded` requests of the `kind`, then misbehave.
+    pub fn misbehave_on_after(
+        mut self,
+        kind: RequestKind,
+        misbehavior: Misbehavior,
+        responded: usize,
+    ) -> Self {
+        self.misbehaviors.insert(kind, (misbehavior, responded));
+        self
+    }
+}
@@ -0,0 +1,17 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Scenarios of a real node bootstrapping from mocked peers, which serve
+//! generated chains and can misbehave on requests.
+
+mod mocked_chain;
+pub use mocked_chain::*;
+
+mod mocked_peer;
+pub use mocked_peer::*;
+
+mod scenario_builder;
+pub use scenario_builder::*;
+
+mod cluster_scenario;
+pub use cluster_scenario::*;
@@ -0,0 +1,132 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::convert::TryInto;
+use std::time::Duration;
+
+use crypto::hash::ChainId;
+use shell_automaton::bootstrap::BootstrapState;
+use shell_automaton::config::default_test_config;
+use shell_automaton::current_head::CurrentHeadState;
+use shell_automaton::mempool::HeadState;
+use shell_automaton::shell_compatibility_version::ShellCompatibilityVersion;
+use shell_automaton::{Config, State};
+use storage::BlockHeaderWithHash;
+use tezos_identity::Identity;
+use tezos_messages::p2p::encoding::block_header::Level;
+
+use crate::one_real_node_cluster::Cluster;
+
+use super::{MockedChain, MockedPeer, Scenario};
+
+/// Builds a [`Scenario`]: the node with its applied chain and the mocked
+/// peers which connect to it.
+///
+/// ```ignore
+/// let builder = ScenarioBuilder::new(5);
+/// let chain = builder.local_chain().extend(10).with_operations(2);
+/// let mut scenario = builder
+///     .peer(MockedPeer::new(chain.clone()))
+///     .peer(MockedPeer::new(chain).misbehave_on(RequestKind::BlockHeaders, Misbehavior::Ignore))
+///     .build();
+/// scenario.run();
+/// ```
+pub struct ScenarioBuilder {
+    config: Config,
+    local_chain: MockedChain,
+    peers: Vec<MockedPeer>,
+}
+
+impl ScenarioBuilder {
+    /// Node which has applied the chain from genesis to `local_head_level`
+    /// and is bootstrapped.
+    pub fn new(local_head_level: Level) -> Self {
+        let config = Config {
+            pow_target: 0.0,
+            identity: Identity::generate(0.0).unwrap(),
+            shell_compatibility_version: ShellCompatibilityVersion::new(
+                "TEZOS_LOCALNET".to_owned(),
+                vec![1],
+                vec![1],
+            ),
+            chain_id: ChainId::try_from("NetXz969SFaFn8k").unwrap(), // granada
+            check_timeouts_interval: Duration::from_millis(500),
+            peer_connecting_timeout: Duration::from_millis(2000),
+            peers_bootstrapped_min: 1,
+            ..default_test_config()
+        };
+        let local_chain = MockedChain::generate(genesis_block(&config), local_head_level);
+        Self {
+            config,
+            local_chain,
+            peers: vec![],
+        }
+    }
+
+    pub fn config<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut Config),
+    {
+        f(&mut self.config);
+        self
+    }
+
+    /// Chain applied by the node, to generate the chains of the peers from.
+    pub fn local_chain(&self) -> &MockedChain {
+        &self.local_chain
+    }
+
+    pub fn peer(mut self, peer: MockedPeer) -> Self {
+        self.peers.push(peer);
+        self
+    }
+
+    pub fn peers(mut self, count: usize, peer: MockedPeer) -> Self {
+        self.peers.extend(std::iter::repeat(peer).take(count));
+        self
+    }
+
+    /// Creates the node, then connects and handshakes the peers in the
+    /// order they were added.
+    pub fn build(self) -> Scenario {
+        let initial_time = self.config.initial_time;
+        let head = self.local_chain.head().clone();
+        let head_pred = self.local_chain.head_pred().cloned();
+
+        let mut state = State::new(self.config);
+        state.current_head = CurrentHeadState::rehydrated(head.clone(), head_pred);
+        state.bootstrap = BootstrapState::Finished {
+            time: 0,
+            error: None,
+        };
+        state.mempool.local_head_state = Some(HeadState {
+            header: (*head.header).clone(),
+            hash: head.hash,
+        });
+
+        let mut scenario = Scenario::new(Cluster::new(state, initial_time), self.local_chain);
+        for peer in self.peers {
+            scenario.connect(peer);
+        }
+        scenario
+    }
+}
+
+fn genesis_block(config: &Config) -> BlockHeaderWithHash {
+    let genesis_header = config
+        .protocol_runner
+        .environment
+        .genesis_header(
+            "CoV8SQumiVU9saiu3FVNeDNewJaJH8yWdsGF3WLdsRr2P9S7MzCj"
+                .try_into()
+                .unwrap(),
+            "LLoZS2LW3rEi7KYU4ouBQtorua37aWWCtpDmv1n2x3xoKi6sVXLWp"
+                .try_into()
+                .unwrap(),
+        )
+        .unwrap();
+    BlockHeaderWithHash {
+        hash: config.init_storage_data.genesis_block_header_hash.clone(),
+        header: genesis_header.into(),
+    }
+}
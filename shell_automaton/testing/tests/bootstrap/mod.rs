@@ -1 +1,2 @@
 pub mod test_fork;
+pub mod test_scenario;
@@ -0,0 +1,100 @@
+use shell_automaton::bootstrap::{BootstrapError, BootstrapState};
+use shell_automaton_testing::scenario::{Misbehavior, MockedPeer, RequestKind, ScenarioBuilder};
+
+#[test]
+fn test_bootstrap_from_peers() {
+    let builder = ScenarioBuilder::new(5);
+    let chain = builder.local_chain().extend(10).with_operations(2);
+    let mut scenario = builder.peers(2, MockedPeer::new(chain.clone())).build();
+    scenario.run();
+
+    let header_requests = (0..2)
+        .map(|peer| scenario.received_requests(peer, RequestKind::BlockHeaders))
+        .sum::<usize>();
+    assert!(header_requests > 0);
+    assert!(scenario.blocks_scheduled_for_apply().is_empty());
+    assert_eq!(scenario.current_head().hash, chain.head().hash);
+}
+
+#[test]
+fn test_bootstrap_operations_from_another_peer_after_disconnect() {
+    let builder = ScenarioBuilder::new(5);
+    let chain = builder.local_chain().extend(10).with_operations(2);
+    let mut scenario = builder
+        .peer(
+            MockedPeer::new(chain.clone())
+                .misbehave_on(RequestKind::BlockOperations, Misbehavior::Disconnect),
+        )
+        .build();
+    scenario.run();
+
+    assert!(!scenario.is_connected(0));
+    assert!(matches!(
+        scenario.bootstrap(),
+        BootstrapState::PeersBlockOperationsGetPending { .. }
+    ));
+    assert!(scenario.blocks_scheduled_for_apply().is_empty());
+    assert_eq!(
+        scenario.current_head().hash,
+        scenario.local_chain().head().hash
+    );
+
+    let peer = scenario.connect(MockedPeer::new(chain.clone()));
+    scenario.run();
+
+    assert!(scenario.received_requests(peer, RequestKind::BlockOperations) > 0);
+    assert_eq!(scenario.current_head().hash, chain.head().hash);
+}
+
+#[test]
+fn test_reorg_allowed_fork() {
+    let builder = ScenarioBuilder::new(10);
+    let fork = builder.local_chain().fork(9, 2, 30000);
+    let mut scenario = builder.peer(MockedPeer::new(fork.clone())).build();
+    scenario.run();
+
+    assert_ne!(
+        scenario.local_chain().block_at(10).unwrap().hash,
+        fork.block_at(10).unwrap().hash
+    );
+    assert!(scenario.blocks_scheduled_for_apply().is_empty());
+    assert_eq!(scenario.current_head().hash, fork.head().hash);
+}
+
+#[test]
+fn test_reorg_after_bootstrap() {
+    let builder = ScenarioBuilder::new(10);
+    let chain = builder.local_chain().extend(5).with_operations(1);
+    let mut scenario = builder.peer(MockedPeer::new(chain.clone())).build();
+    scenario.run();
+
+    assert_eq!(scenario.current_head().hash, chain.head().hash);
+
+    let fork = chain.fork(14, 2, 30000).with_operations(1);
+    scenario.set_chain(0, fork.clone());
+    scenario.advertise_current_head(0);
+    scenario.run();
+
+    assert!(scenario.blocks_scheduled_for_apply().is_empty());
+    assert_eq!(scenario.current_head().hash, fork.head().hash);
+}
+
+#[test]
+fn test_reorg_cemented_block_fork_rejected() {
+    let builder = ScenarioBuilder::new(20);
+    let local_head = builder.local_chain().head().clone();
+    let fork = builder.local_chain().fork(15, 6, 30000);
+    let mut scenario = builder.peer(MockedPeer::new(fork)).build();
+    scenario.run();
+
+    assert!(matches!(
+        scenario.bootstrap(),
+        BootstrapState::Finished {
+            error: Some(BootstrapError::CementedBlockReorg { .. }),
+            ..
+        }
+    ));
+    assert!(scenario.is_graylisted(0));
+    assert_eq!(scenario.current_head().hash, local_head.hash);
+    assert!(scenario.blocks_scheduled_for_apply().is_empty());
+}
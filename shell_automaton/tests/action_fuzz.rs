@@ -1401,9 +1401,11 @@ fn reduce_with_state(action: &ActionWithMeta<Action>) -> bool {
         } else {
             //println!("{:?}", action);
             shell_automaton::reducer(&mut state.current_target_state, action);
+            // only the reducers run here, the peers invariants that effects enforce
+            // are checked after the effects by `invariants::check_after_effects`
             match state.current_target_state.check_safety_condition() {
-                Err(error) => {
-                    println!("{:?}", error);
+                Err(violation) => {
+                    println!("{} after action {:?}", violation, action);
                     false
                 }
                 _ => true,
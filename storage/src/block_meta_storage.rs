@@ -16,7 +16,9 @@ use crypto::hash::{
 };
 use tezos_messages::p2p::encoding::block_header::Level;
 
-use crate::database::tezedge_database::{KVStoreKeyValueSchema, TezedgeDatabaseWithIterator};
+use crate::database::tezedge_database::{
+    KVStoreKeyValueSchema, TezedgeDatabaseWithIterator, WriteBatch,
+};
 use crate::persistent::database::{default_table_options, RocksDbKeyValueSchema};
 use crate::persistent::{BincodeEncoded, Decoder, Encoder, KeyValueSchema, SchemaError};
 use crate::predecessor_storage::{PredecessorKey, PredecessorStorage};
@@ -213,6 +215,20 @@ impl BlockMetaStorage {
         Ok(())
     }
 
+    pub fn store_predecessors_to_batch(
+        &self,
+        batch: &mut WriteBatch,
+        block_hash: &BlockHash,
+        block_meta: &Meta,
+    ) -> Result<(), StorageError> {
+        self.predecessors_index.store_predecessors_to_batch(
+            batch,
+            block_hash,
+            block_meta,
+            Self::STORED_PREDECESSORS_SIZE,
+        )
+    }
+
     pub fn put_block_additional_data(
         &self,
         block_hash: &BlockHash,
@@ -223,11 +239,39 @@ impl BlockMetaStorage {
             .map_err(StorageError::from)
     }
 
+    pub fn put_block_additional_data_to_batch(
+        &self,
+        batch: &mut WriteBatch,
+        block_hash: &BlockHash,
+        additional_data: &BlockAdditionalData,
+    ) -> Result<(), StorageError> {
+        batch
+            .put::<BlockAdditionalData>(block_hash, additional_data)
+            .map_err(StorageError::from)
+    }
+
     #[inline]
     pub fn put(&self, block_hash: &BlockHash, meta: &Meta) -> Result<(), StorageError> {
         self.kv.merge(block_hash, meta).map_err(StorageError::from)
     }
 
+    #[inline]
+    pub fn put_to_batch(
+        &self,
+        batch: &mut WriteBatch,
+        block_hash: &BlockHash,
+        meta: &Meta,
+    ) -> Result<(), StorageError> {
+        batch
+            .merge::<Self>(block_hash, meta)
+            .map_err(StorageError::from)
+    }
+
+    /// Writes `batch` atomically, it can contain writes of any storage of the main database
+    pub fn write_batch(&self, batch: WriteBatch) -> Result<(), StorageError> {
+        self.kv.write(batch).map_err(StorageError::from)
+    }
+
     #[inline]
     pub fn get(&self, block_hash: &BlockHash) -> Result<Option<Meta>, StorageError> {
         self.kv.get(block_hash).map_err(StorageError::from)
@@ -11,7 +11,9 @@ use crypto::hash::{BlockHash, ContextHash};
 use tezos_messages::p2p::encoding::block_header::Level;
 
 use crate::commit_log::{CommitLogWithSchema, Location};
-use crate::database::tezedge_database::{KVStoreKeyValueSchema, TezedgeDatabaseWithIterator};
+use crate::database::tezedge_database::{
+    KVStoreKeyValueSchema, TezedgeDatabaseWithIterator, WriteBatch,
+};
 use crate::persistent::database::RocksDbKeyValueSchema;
 use crate::persistent::{BincodeEncoded, CommitLogSchema, KeyValueSchema};
 use crate::{BlockHeaderWithHash, Direction, IteratorMode, PersistentStorage, StorageError};
@@ -164,6 +166,21 @@ impl BlockStorage {
         &self,
         block_hash: &BlockHash,
         json_data: BlockJsonData,
+    ) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        self.put_block_json_data_to_batch(&mut batch, block_hash, json_data)?;
+        self.primary_index
+            .kv
+            .write(batch)
+            .map_err(StorageError::from)
+    }
+
+    /// Appends `json_data` to the commit log, and adds the update of the indexes to `batch`
+    pub fn put_block_json_data_to_batch(
+        &self,
+        batch: &mut WriteBatch,
+        block_hash: &BlockHash,
+        json_data: BlockJsonData,
     ) -> Result<(), StorageError> {
         let updated_column_location = {
             let block_json_data_location = self
@@ -180,12 +197,9 @@ impl BlockStorage {
         };
         let block_header = self.get_block_header_by_location(&updated_column_location)?;
         // update indexes
-        self.primary_index
-            .put(&block_header.hash, &updated_column_location)
-            .and(
-                self.by_level_index
-                    .put(block_header.header.level(), &updated_column_location),
-            )
+        batch.put::<BlockPrimaryIndex>(&block_header.hash, &updated_column_location)?;
+        batch.put::<BlockByLevelIndex>(&block_header.header.level(), &updated_column_location)?;
+        Ok(())
     }
 
     #[inline]
@@ -11,7 +11,9 @@ use serde::{Deserialize, Serialize};
 use crypto::hash::{ChainId, HashType};
 use tezos_messages::Head;
 
-use crate::database::tezedge_database::{KVStoreKeyValueSchema, TezedgeDatabaseWithIterator};
+use crate::database::tezedge_database::{
+    KVStoreKeyValueSchema, TezedgeDatabaseWithIterator, WriteBatch,
+};
 use crate::persistent::database::{default_table_options, RocksDbKeyValueSchema};
 use crate::persistent::{BincodeEncoded, Decoder, Encoder, KeyValueSchema, SchemaError};
 use crate::{PersistentStorage, StorageError};
@@ -72,6 +74,21 @@ impl ChainMetaStorage {
             .map_err(StorageError::from)
     }
 
+    #[inline]
+    pub fn set_current_head_to_batch(
+        &self,
+        batch: &mut WriteBatch,
+        chain_id: &ChainId,
+        head: Head,
+    ) -> Result<(), StorageError> {
+        batch
+            .put::<Self>(
+                &MetaKey::key_current_head(chain_id.clone()),
+                &MetadataValue::Head(head),
+            )
+            .map_err(StorageError::from)
+    }
+
     #[inline]
     pub fn set_caboose(&self, chain_id: &ChainId, head: Head) -> Result<(), StorageError> {
         self.kv
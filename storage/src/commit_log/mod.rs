@@ -2,8 +2,13 @@
 // SPDX-License-Identifier: MIT
 
 //! ## Commit Log
-//! append only - adds data in a file then returns the data size and location in  file
+//! append only - adds data in segment files then returns the data size and location in the log
 //! uses zstd as a compression library
+//!
+//! The log is split into segment files, named after the offset of their first record.
+//! Each record is prefixed with a header, made of the length and the CRC32 checksum of its data.
+//! On startup, an incomplete or corrupted record at the end of the last segment (a torn write)
+//! is truncated. A corrupted record followed by other records is an error.
 
 mod compression;
 
@@ -19,20 +24,159 @@ use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 
 use crate::commit_log::compression::{zstd_compress, zstd_decompress};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
 use std::fs::{File, OpenOptions};
-use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::FileExt;
 
 use slog::Logger;
 
 pub type CommitLogRef = Arc<RwLock<CommitLog>>;
 
-const DATA_FILE_NAME: &str = "table.data";
+/// Data file of the commit log before it was split into segments
+const LEGACY_DATA_FILE_NAME: &str = "table.data";
+const SEGMENT_FILE_EXTENSION: &str = "segment";
+
+/// Maximum size of a segment file, a record is never split between two segments
+pub const DEFAULT_MAX_SEGMENT_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Length (u32) and CRC32 checksum (u32) of the record data
+const RECORD_HEADER_LENGTH: usize = 8;
+
+struct Segment {
+    /// Offset of the first record of this segment in the commit log
+    base_offset: u64,
+    file: File,
+    path: PathBuf,
+    size: u64,
+}
+
+impl Segment {
+    fn path(dir: &Path, base_offset: u64) -> PathBuf {
+        dir.join(format!("{:020}.{}", base_offset, SEGMENT_FILE_EXTENSION))
+    }
+
+    fn open(dir: &Path, base_offset: u64) -> Result<Self, CommitLogError> {
+        let path = Self::path(dir, base_offset);
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .read(true)
+            .open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            base_offset,
+            file,
+            path,
+            size,
+        })
+    }
+
+    /// Offset of the end of this segment in the commit log
+    fn end_offset(&self) -> u64 {
+        self.base_offset + self.size
+    }
+
+    /// Reads the header of the record at `position`.
+    ///
+    /// Returns `None` when the header is not complete.
+    fn read_header(&self, position: u64) -> Result<Option<(usize, u32)>, CommitLogError> {
+        if position + RECORD_HEADER_LENGTH as u64 > self.size {
+            return Ok(None);
+        }
+
+        let mut header = [0_u8; RECORD_HEADER_LENGTH];
+        self.file.read_exact_at(&mut header, position)?;
+
+        let length = u32::from_le_bytes(header[..4].try_into().unwrap()); // never fail
+        let checksum = u32::from_le_bytes(header[4..].try_into().unwrap()); // never fail
+
+        Ok(Some((length as usize, checksum)))
+    }
+
+    /// Verifies all records.
+    ///
+    /// Returns the location of the last valid record and the size of the valid records.
+    /// Only the last record can be invalid, it is a torn write: an incomplete record,
+    /// or a record with a wrong checksum at the end of the segment. A wrong checksum
+    /// before other records is an error.
+    fn scan(&self) -> Result<(Option<Location>, u64), CommitLogError> {
+        let mut position = 0;
+        let mut last_valid = None;
+        let mut buffer = Vec::new();
+
+        while let Some((length, checksum)) = self.read_header(position)? {
+            let data_position = position + RECORD_HEADER_LENGTH as u64;
+            let next_position = data_position + length as u64;
+            if next_position > self.size {
+                break;
+            }
+
+            buffer.resize(length, 0);
+            self.file.read_exact_at(&mut buffer, data_position)?;
+            if crc32fast::hash(&buffer) != checksum {
+                if next_position < self.size {
+                    return Err(CommitLogError::CorruptSegment {
+                        path: self.path.clone(),
+                        position,
+                    });
+                }
+                break;
+            }
+
+            last_valid = Some(Location(self.base_offset + position, length));
+            position = next_position;
+        }
+
+        Ok((last_valid, position))
+    }
+
+    /// Verifies all records, and truncates the torn write after the last valid one.
+    ///
+    /// Returns the location of the last valid record.
+    fn recover(&mut self, log: &Logger) -> Result<Option<Location>, CommitLogError> {
+        let (last_valid, position) = self.scan()?;
+
+        if position < self.size {
+            slog::warn!(log, "Truncating torn commit log tail";
+                             "segment" => format!("{:?}", self.path),
+                             "valid_size" => position,
+                             "size" => self.size);
+            self.file.set_len(position)?;
+            self.file.sync_all()?;
+            self.size = position;
+        }
+
+        Ok(last_valid)
+    }
+
+    /// Returns the location of the last record of a segment that is not written anymore.
+    ///
+    /// It was synchronized before the next segment was started, so it cannot end with
+    /// a torn write.
+    fn last_record(&self) -> Result<Option<Location>, CommitLogError> {
+        let (last_valid, position) = self.scan()?;
+
+        if position < self.size {
+            return Err(CommitLogError::CorruptSegment {
+                path: self.path.clone(),
+                position,
+            });
+        }
+
+        Ok(last_valid)
+    }
+}
 
 pub struct CommitLog {
-    data_file: File,
-    data_file_path: PathBuf,
+    dir: PathBuf,
+    /// Segments by their base offset, the last one is written to
+    segments: BTreeMap<u64, Segment>,
+    max_segment_size: u64,
     use_compression: bool,
+    /// Location of the last record found on startup
+    last_valid_location: Option<Location>,
 }
 
 impl CommitLog {
@@ -40,62 +184,206 @@ impl CommitLog {
     /// *use_compression* - when enabled compresses data when `append_msg()` is called
     /// > Using compression decrease read and write speed
     pub fn new<P: AsRef<Path>>(dir: P, use_compression: bool) -> Result<Self, CommitLogError> {
-        if !dir.as_ref().exists() {
-            std::fs::create_dir_all(dir.as_ref())?;
+        Self::with_max_segment_size(
+            dir,
+            use_compression,
+            DEFAULT_MAX_SEGMENT_SIZE,
+            &Logger::root(slog::Discard, slog::o!()),
+        )
+    }
+
+    /// Opens the commit log in *dir*, and recovers its last record, see `Self::last_valid_location`
+    ///
+    /// *max_segment_size* - a new segment is started when a record doesn't fit in the last one
+    pub fn with_max_segment_size<P: AsRef<Path>>(
+        dir: P,
+        use_compression: bool,
+        max_segment_size: u64,
+        log: &Logger,
+    ) -> Result<Self, CommitLogError> {
+        let dir = dir.as_ref().to_path_buf();
+        if !dir.exists() {
+            std::fs::create_dir_all(&dir)?;
         }
-        let mut data_file_path = PathBuf::new();
-        data_file_path.push(dir.as_ref());
-        data_file_path.push(DATA_FILE_NAME);
-        let data_file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .read(true)
-            .open(data_file_path.clone())?;
+
+        let legacy_data_file = dir.join(LEGACY_DATA_FILE_NAME);
+        if legacy_data_file.exists() {
+            return Err(CommitLogError::LegacyFormat {
+                path: legacy_data_file,
+            });
+        }
+
+        let mut segments = BTreeMap::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SEGMENT_FILE_EXTENSION) {
+                continue;
+            }
+            let base_offset = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok())
+                .ok_or_else(|| CommitLogError::InvalidSegmentName { path: path.clone() })?;
+
+            segments.insert(base_offset, Segment::open(&dir, base_offset)?);
+        }
+
+        if segments.is_empty() {
+            segments.insert(0, Segment::open(&dir, 0)?);
+        }
+
+        // Only the last segment is written to, a torn write can only be there.
+        // When it is empty (the log stopped right after rolling over), the last record
+        // is in a previous segment.
+        let mut last_valid_location = None;
+        for (index, segment) in segments.values_mut().rev().enumerate() {
+            last_valid_location = if index == 0 {
+                segment.recover(log)?
+            } else {
+                segment.last_record()?
+            };
+            if last_valid_location.is_some() {
+                break;
+            }
+        }
+
         Ok(Self {
-            data_file,
-            data_file_path,
+            dir,
+            segments,
+            max_segment_size,
             use_compression,
+            last_valid_location,
         })
     }
-    /// appends bytes of data to file
+
+    /// Location of the last valid record found when the commit log was opened.
+    ///
+    /// Records appended after a torn write are lost, they are not part of the log.
+    pub fn last_valid_location(&self) -> Option<Location> {
+        self.last_valid_location
+    }
+
+    fn active_segment(&mut self) -> Result<&mut Segment, CommitLogError> {
+        self.segments
+            .values_mut()
+            .next_back()
+            .ok_or(CommitLogError::MissingSegment { offset: 0 })
+    }
+
+    /// appends bytes of data to the last segment
+    ///
+    /// Returns the offset of the record and the length of its data
     pub fn append_msg<B: AsRef<[u8]>>(
         &mut self,
         payload: B,
     ) -> Result<(u64, usize), CommitLogError> {
-        let mut writer = BufWriter::new(&mut self.data_file);
-        let offset = writer.seek(SeekFrom::End(0))?;
-        let buf_size = if self.use_compression {
+        let data = if self.use_compression {
             let mut compressed_payload = Vec::new();
             zstd_compress(payload, &mut compressed_payload)?;
-            writer.write_all(compressed_payload.as_slice())?;
-            compressed_payload.len()
+            compressed_payload
         } else {
-            writer.write_all(payload.as_ref())?;
-            payload.as_ref().len()
+            payload.as_ref().to_vec()
         };
-        writer.flush()?;
-        Ok((offset, buf_size))
+        let length: u32 = data
+            .len()
+            .try_into()
+            .map_err(|_| CommitLogError::RecordTooLarge { length: data.len() })?;
+
+        let mut record = Vec::with_capacity(RECORD_HEADER_LENGTH + data.len());
+        record.extend_from_slice(&length.to_le_bytes());
+        record.extend_from_slice(&crc32fast::hash(&data).to_le_bytes());
+        record.extend_from_slice(&data);
+
+        let max_segment_size = self.max_segment_size;
+        let active = self.active_segment()?;
+        if active.size > 0 && active.size + record.len() as u64 > max_segment_size {
+            // The full segment is synchronized once, it won't be written again
+            active.file.sync_data()?;
+            let base_offset = active.end_offset();
+            let segment = Segment::open(&self.dir, base_offset)?;
+            self.segments.insert(base_offset, segment);
+        }
+
+        let active = self.active_segment()?;
+        let offset = active.end_offset();
+        active.file.write_all_at(&record, active.size)?;
+        active.size += record.len() as u64;
+
+        Ok((offset, data.len()))
     }
-    /// `offset` - location of data in log file
+
+    /// `offset` - location of the record in the log
     /// `buf_size` - exact data size to be read
     pub fn read(&self, offset: u64, buf_size: usize) -> Result<Vec<u8>, CommitLogError> {
+        let location = Location(offset, buf_size);
+        let segment = self
+            .segments
+            .range(..=offset)
+            .next_back()
+            .map(|(_, segment)| segment)
+            .filter(|segment| offset < segment.end_offset())
+            .ok_or(CommitLogError::MissingSegment { offset })?;
+
+        let position = offset - segment.base_offset;
+        let (length, checksum) = segment
+            .read_header(position)?
+            .ok_or(CommitLogError::ReadError { location })?;
+        if length != buf_size {
+            return Err(CommitLogError::ReadError { location });
+        }
+
         let mut buf = vec![0_u8; buf_size];
-        let mut reader = BufReader::new(File::open(self.data_file_path.as_path())?);
-        reader.seek(SeekFrom::Start(offset))?;
-        reader.read_exact(&mut buf)?;
+        segment
+            .file
+            .read_exact_at(&mut buf, position + RECORD_HEADER_LENGTH as u64)?;
+        if crc32fast::hash(&buf) != checksum {
+            return Err(CommitLogError::CorruptData);
+        }
 
-        return if self.use_compression {
+        if self.use_compression {
             let mut uncompressed_payload = Vec::new();
             zstd_decompress(buf.as_slice(), &mut uncompressed_payload)?;
             Ok(uncompressed_payload)
         } else {
             Ok(buf)
+        }
+    }
+
+    /// Deletes the segments whose records are all before `offset`, the last segment is kept.
+    ///
+    /// Returns the number of deleted segments.
+    pub fn delete_segments_before(&mut self, offset: u64) -> Result<usize, CommitLogError> {
+        let last_base_offset = match self.segments.keys().next_back() {
+            Some(last) => *last,
+            None => return Ok(0),
         };
+
+        let to_delete: Vec<u64> = self
+            .segments
+            .values()
+            .take_while(|segment| {
+                segment.base_offset < last_base_offset && segment.end_offset() <= offset
+            })
+            .map(|segment| segment.base_offset)
+            .collect();
+
+        for base_offset in &to_delete {
+            if let Some(segment) = self.segments.remove(base_offset) {
+                std::fs::remove_file(&segment.path)?;
+            }
+        }
+
+        Ok(to_delete.len())
+    }
+
+    /// Path of the commit log directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
     }
 
     /// Flushes data to disc
     pub fn sync(&mut self) -> Result<(), CommitLogError> {
-        self.data_file.sync_data()?;
+        self.active_segment()?.file.sync_data()?;
         Ok(())
     }
 }
@@ -115,6 +403,16 @@ pub enum CommitLogError {
     CorruptData,
     #[error("RwLock Poison Error {error}")]
     RwLockPoisonError { error: String },
+    #[error("No commit log segment contains offset {offset}")]
+    MissingSegment { offset: u64 },
+    #[error("Commit log segment {path:?} is corrupted at position {position}")]
+    CorruptSegment { path: PathBuf, position: u64 },
+    #[error("Invalid commit log segment file name {path:?}")]
+    InvalidSegmentName { path: PathBuf },
+    #[error("Commit log record of {length} bytes is too large")]
+    RecordTooLarge { length: usize },
+    #[error("Commit log {path:?} uses the format without segments, please re-sync your node to empty storage")]
+    LegacyFormat { path: PathBuf },
 }
 
 impl From<SchemaError> for CommitLogError {
@@ -148,9 +446,22 @@ type ItemCount = u32;
 pub struct Location(pub u64, pub ByteLimit);
 
 impl Location {
+    /// Records are written one after another, each of them prefixed by its header
     #[inline]
     pub fn is_consecutive(&self, prev: &Location) -> bool {
-        (prev.0 < self.0) && (self.0 - prev.0 == 1)
+        self.0 == prev.next_offset()
+    }
+
+    /// Offset of the record following this one
+    #[inline]
+    fn next_offset(&self) -> u64 {
+        self.0 + self.record_length() as u64
+    }
+
+    /// Length of the record, including its header
+    #[inline]
+    fn record_length(&self) -> ByteLimit {
+        RECORD_HEADER_LENGTH + self.1
     }
 }
 
@@ -162,7 +473,8 @@ impl fmt::Display for Location {
 
 impl BincodeEncoded for Location {}
 
-/// Range of values to get from a commit log
+/// Range of values to get from a commit log: offset of the first record,
+/// length of the records including their headers, and their count
 #[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Range(pub u64, pub ByteLimit, pub ItemCount);
 
@@ -176,6 +488,11 @@ pub trait CommitLogWithSchema<S: CommitLogSchema> {
 
     /// Flush to disk.
     fn sync(&self) -> Result<(), CommitLogError>;
+
+    /// Delete the segments containing only records before `location`.
+    ///
+    /// Returns the number of deleted segments, reading their records fails afterward.
+    fn delete_segments_before(&self, location: &Location) -> Result<usize, CommitLogError>;
 }
 
 impl<S: CommitLogSchema> CommitLogWithSchema<S> for CommitLogs {
@@ -217,6 +534,18 @@ impl<S: CommitLogSchema> CommitLogWithSchema<S> for CommitLogs {
 
         Ok(())
     }
+
+    fn delete_segments_before(&self, location: &Location) -> Result<usize, CommitLogError> {
+        let cl = self
+            .cl_handle(S::name())?
+            .ok_or(CommitLogError::MissingCommitLog { name: S::name() })?;
+
+        let mut cl = cl.write().map_err(|e| CommitLogError::RwLockPoisonError {
+            error: e.to_string(),
+        })?;
+
+        cl.delete_segments_before(location.0)
+    }
 }
 
 pub fn fold_consecutive_locations(locations: &[Location]) -> Vec<Range> {
@@ -226,14 +555,14 @@ pub fn fold_consecutive_locations(locations: &[Location]) -> Vec<Range> {
         let mut ranges = Vec::with_capacity(locations.len());
 
         let mut prev = locations[0];
-        let mut range = Range(prev.0, prev.1, 1);
+        let mut range = Range(prev.0, prev.record_length(), 1);
         for curr in &locations[1..] {
             if curr.is_consecutive(&prev) {
-                range.1 += curr.1;
+                range.1 += curr.record_length();
                 range.2 += 1;
             } else {
                 ranges.push(range);
-                range = Range(curr.0, curr.1, 1);
+                range = Range(curr.0, curr.record_length(), 1);
             }
             prev = *curr;
         }
@@ -275,7 +604,11 @@ impl CommitLogs {
         if !Path::new(&path).exists() {
             std::fs::create_dir_all(&path)?;
         }
-        let log = CommitLog::new(path, false)?;
+        let log =
+            CommitLog::with_max_segment_size(path, false, DEFAULT_MAX_SEGMENT_SIZE, &self.log)?;
+        slog::info!(&self.log, "Commit log opened";
+                               "commit_log_name" => name,
+                               "last_valid_location" => format!("{:?}", log.last_valid_location()));
 
         let mut commit_log_map =
             self.commit_log_map
@@ -317,10 +650,10 @@ impl CommitLogs {
                     })?;
             match commit_log.sync() {
                 Ok(_) => {
-                    slog::debug!(&self.log, "Successfully flushed commit log"; "commit_log_num" => (commit_log_idx + 1), "commit_log_name" => commit_log_name, "data_file_path" => format!("{:?}", commit_log.data_file_path))
+                    slog::debug!(&self.log, "Successfully flushed commit log"; "commit_log_num" => (commit_log_idx + 1), "commit_log_name" => commit_log_name, "dir" => format!("{:?}", commit_log.dir()))
                 }
                 Err(e) => {
-                    slog::error!(&self.log, "Failed to flush commit log"; "commit_log_name" => commit_log_name, "data_file_path" => format!("{:?}", commit_log.data_file_path), "reason" =>  e)
+                    slog::error!(&self.log, "Failed to flush commit log"; "commit_log_name" => commit_log_name, "dir" => format!("{:?}", commit_log.dir()), "reason" =>  e)
                 }
             }
         }
@@ -374,32 +707,230 @@ mod tests {
     fn test_fold_consecutive_locations_single() {
         let locations = vec![Location(1, 10)];
         let ranges = fold_consecutive_locations(&locations);
-        assert_eq!(vec![Range(1, 10, 1)], ranges);
+        assert_eq!(vec![Range(1, 18, 1)], ranges);
     }
 
     #[test]
     fn test_fold_consecutive_locations_multi() {
         let locations = vec![
-            Location(1, 10),
-            Location(2, 10),
-            Location(5, 10),
-            Location(7, 10),
-            Location(8, 10),
-            Location(9, 10),
-            Location(6, 10),
+            Location(0, 10),
+            Location(18, 10),
+            Location(100, 10),
+            Location(200, 10),
+            Location(218, 2),
+            Location(228, 30),
+            Location(50, 10),
         ];
         let ranges = fold_consecutive_locations(&locations);
         assert_eq!(
             vec![
-                Range(1, 20, 2),
-                Range(5, 10, 1),
-                Range(7, 30, 3),
-                Range(6, 10, 1)
+                Range(0, 36, 2),
+                Range(100, 18, 1),
+                Range(200, 66, 3),
+                Range(50, 18, 1)
             ],
             ranges
         );
     }
 
+    #[test]
+    fn test_fold_consecutive_appended_locations() {
+        let dir = "./testdir/commit_log/fold_consecutive";
+        clean_dir(dir);
+
+        let mut commit_log = CommitLog::new(dir, false).unwrap();
+        let locations: Vec<_> = [10, 0, 100]
+            .iter()
+            .map(|length| {
+                let (offset, length) = commit_log.append_msg(vec![1_u8; *length]).unwrap();
+                Location(offset, length)
+            })
+            .collect();
+
+        let ranges = fold_consecutive_locations(&locations);
+        let end = commit_log.append_msg(Vec::<u8>::new()).unwrap().0;
+        assert_eq!(vec![Range(0, end as usize, 3)], ranges);
+
+        clean_dir(dir);
+    }
+
+    fn clean_dir(dir: &str) {
+        if Path::new(dir).exists() {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_torn_tail_is_truncated() {
+        let dir = "./testdir/commit_log/torn_tail";
+        clean_dir(dir);
+
+        let mut commit_log = CommitLog::new(dir, false).unwrap();
+        let first = commit_log.append_msg(vec![1_u8; 100]).unwrap();
+        let second = commit_log.append_msg(vec![2_u8; 100]).unwrap();
+        commit_log.sync().unwrap();
+        drop(commit_log);
+
+        // simulate a torn write: the last record is incomplete
+        let segment_path = Segment::path(Path::new(dir), 0);
+        let file = OpenOptions::new().write(true).open(&segment_path).unwrap();
+        file.set_len(second.0 + RECORD_HEADER_LENGTH as u64 + 50)
+            .unwrap();
+        drop(file);
+
+        let mut commit_log = CommitLog::new(dir, false).unwrap();
+        let last = commit_log.last_valid_location().unwrap();
+        assert_eq!((last.0, last.1), first);
+        assert_eq!(commit_log.read(first.0, first.1).unwrap(), vec![1_u8; 100]);
+        assert!(commit_log.read(second.0, second.1).is_err());
+
+        // the next record is written right after the last valid one
+        let third = commit_log.append_msg(vec![3_u8; 10]).unwrap();
+        assert_eq!(third.0, second.0);
+        assert_eq!(commit_log.read(third.0, third.1).unwrap(), vec![3_u8; 10]);
+
+        clean_dir(dir);
+    }
+
+    #[test]
+    fn test_corrupted_record_before_others_is_an_error() {
+        let dir = "./testdir/commit_log/corrupted_middle_record";
+        clean_dir(dir);
+
+        let mut commit_log = CommitLog::new(dir, false).unwrap();
+        let first = commit_log.append_msg(vec![1_u8; 100]).unwrap();
+        commit_log.append_msg(vec![2_u8; 100]).unwrap();
+        commit_log.sync().unwrap();
+        drop(commit_log);
+
+        let segment_path = Segment::path(Path::new(dir), 0);
+        let file = OpenOptions::new().write(true).open(&segment_path).unwrap();
+        file.write_all_at(&[0], first.0 + RECORD_HEADER_LENGTH as u64 + 10)
+            .unwrap();
+        drop(file);
+
+        // the records after the corrupted one are not truncated
+        assert!(matches!(
+            CommitLog::new(dir, false),
+            Err(CommitLogError::CorruptSegment { position: 0, .. })
+        ));
+        let segment_size = std::fs::metadata(&segment_path).unwrap().len();
+        assert_eq!(segment_size, 2 * (RECORD_HEADER_LENGTH as u64 + 100));
+
+        clean_dir(dir);
+    }
+
+    #[test]
+    fn test_last_record_is_found_in_previous_segment() {
+        let dir = "./testdir/commit_log/previous_segment";
+        clean_dir(dir);
+
+        let log = Logger::root(slog::Discard, slog::o!());
+        let mut commit_log = CommitLog::with_max_segment_size(dir, false, 1000, &log).unwrap();
+        let records: Vec<_> = (0..6_u8)
+            .map(|index| commit_log.append_msg(vec![index; 192]).unwrap())
+            .collect();
+        commit_log.sync().unwrap();
+        drop(commit_log);
+
+        // the log stopped while writing the first record of the second segment
+        let segment_path = Segment::path(Path::new(dir), records[5].0);
+        let file = OpenOptions::new().write(true).open(&segment_path).unwrap();
+        file.set_len(50).unwrap();
+        drop(file);
+
+        let mut commit_log = CommitLog::with_max_segment_size(dir, false, 1000, &log).unwrap();
+        let last = commit_log.last_valid_location().unwrap();
+        assert_eq!((last.0, last.1), records[4]);
+
+        // the torn write is truncated, the next record replaces it
+        let next = commit_log.append_msg(vec![6_u8; 192]).unwrap();
+        assert_eq!(next, records[5]);
+        assert_eq!(commit_log.read(next.0, next.1).unwrap(), vec![6_u8; 192]);
+
+        // an empty last segment too
+        drop(commit_log);
+        let file = OpenOptions::new().write(true).open(&segment_path).unwrap();
+        file.set_len(0).unwrap();
+        drop(file);
+
+        let commit_log = CommitLog::with_max_segment_size(dir, false, 1000, &log).unwrap();
+        let last = commit_log.last_valid_location().unwrap();
+        assert_eq!((last.0, last.1), records[4]);
+
+        clean_dir(dir);
+    }
+
+    #[test]
+    fn test_corrupted_record_is_detected() {
+        let dir = "./testdir/commit_log/corrupted_record";
+        clean_dir(dir);
+
+        let mut commit_log = CommitLog::new(dir, false).unwrap();
+        let record = commit_log.append_msg(vec![1_u8; 100]).unwrap();
+        commit_log.sync().unwrap();
+
+        let segment_path = Segment::path(Path::new(dir), 0);
+        let file = OpenOptions::new().write(true).open(&segment_path).unwrap();
+        file.write_all_at(&[0], record.0 + RECORD_HEADER_LENGTH as u64 + 10)
+            .unwrap();
+
+        assert!(matches!(
+            commit_log.read(record.0, record.1),
+            Err(CommitLogError::CorruptData)
+        ));
+
+        clean_dir(dir);
+    }
+
+    #[test]
+    fn test_segments_rolling_and_deletion() {
+        let dir = "./testdir/commit_log/segments";
+        clean_dir(dir);
+
+        let log = Logger::root(slog::Discard, slog::o!());
+        let mut commit_log = CommitLog::with_max_segment_size(dir, false, 1000, &log).unwrap();
+        let records: Vec<_> = (0..20_u8)
+            .map(|index| commit_log.append_msg(vec![index; 192]).unwrap())
+            .collect();
+        // 5 records of 200 bytes per segment
+        assert_eq!(commit_log.segments.len(), 4);
+
+        for (index, record) in records.iter().enumerate() {
+            assert_eq!(
+                commit_log.read(record.0, record.1).unwrap(),
+                vec![index as u8; 192]
+            );
+        }
+
+        // segments are reopened with their offsets
+        drop(commit_log);
+        let mut commit_log = CommitLog::with_max_segment_size(dir, false, 1000, &log).unwrap();
+        let last = commit_log.last_valid_location().unwrap();
+        assert_eq!((last.0, last.1), records[19]);
+
+        // the segment of the record 12 and the following ones are kept
+        assert_eq!(commit_log.delete_segments_before(records[12].0).unwrap(), 2);
+        assert!(matches!(
+            commit_log.read(records[0].0, records[0].1),
+            Err(CommitLogError::MissingSegment { .. })
+        ));
+        assert_eq!(
+            commit_log.read(records[12].0, records[12].1).unwrap(),
+            vec![12_u8; 192]
+        );
+
+        // the last segment is never deleted
+        assert_eq!(commit_log.delete_segments_before(u64::MAX).unwrap(), 1);
+        assert_eq!(commit_log.segments.len(), 1);
+        assert_eq!(
+            commit_log.read(records[19].0, records[19].1).unwrap(),
+            vec![19_u8; 192]
+        );
+
+        clean_dir(dir);
+    }
+
     fn generate_random_data(
         data_size: usize,
         min_message_size: usize,
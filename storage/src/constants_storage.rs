@@ -8,7 +8,9 @@ use rocksdb::{Cache, ColumnFamilyDescriptor};
 
 use crypto::hash::ProtocolHash;
 
-use crate::database::tezedge_database::{KVStoreKeyValueSchema, TezedgeDatabaseWithIterator};
+use crate::database::tezedge_database::{
+    KVStoreKeyValueSchema, TezedgeDatabaseWithIterator, WriteBatch,
+};
 use crate::persistent::database::{default_table_options, RocksDbKeyValueSchema};
 use crate::persistent::KeyValueSchema;
 use crate::{IteratorMode, PersistentStorage, StorageError};
@@ -41,6 +43,17 @@ impl ConstantsStorage {
         Ok(())
     }
 
+    pub fn store_constants_data_to_batch(
+        &self,
+        batch: &mut WriteBatch,
+        protocol_hash: ProtocolHash,
+        new_constants: String,
+    ) -> Result<(), StorageError> {
+        batch
+            .put::<Self>(&protocol_hash, &new_constants)
+            .map_err(StorageError::from)
+    }
+
     #[inline]
     fn put(&self, key: &ConstantsKey, data: &str) -> Result<(), StorageError> {
         self.kv
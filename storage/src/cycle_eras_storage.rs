@@ -10,7 +10,9 @@ use serde::{Deserialize, Serialize};
 
 use crypto::hash::ProtocolHash;
 
-use crate::database::tezedge_database::{KVStoreKeyValueSchema, TezedgeDatabaseWithIterator};
+use crate::database::tezedge_database::{
+    KVStoreKeyValueSchema, TezedgeDatabaseWithIterator, WriteBatch,
+};
 use crate::persistent::database::{default_table_options, RocksDbKeyValueSchema};
 use crate::persistent::{BincodeEncoded, KeyValueSchema};
 use crate::{IteratorMode, PersistentStorage, StorageError};
@@ -63,6 +65,20 @@ impl CycleErasStorage {
         Ok(())
     }
 
+    pub fn store_cycle_eras_data_to_batch(
+        &self,
+        batch: &mut WriteBatch,
+        protocol_hash: ProtocolHash,
+        new_cycle_eras_json: String,
+    ) -> Result<(), StorageError> {
+        // deserialize the JSON into the CycleErasData struct
+        let decoded_cycle_eras: CycleErasData = serde_json::from_str(&new_cycle_eras_json)?;
+
+        batch
+            .put::<Self>(&protocol_hash, &decoded_cycle_eras)
+            .map_err(StorageError::from)
+    }
+
     #[inline]
     pub fn put(&self, key: &CycleErasKey, data: CycleErasData) -> Result<(), StorageError> {
         self.kv.put(key, &data).map_err(StorageError::from)
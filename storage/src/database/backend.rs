@@ -28,6 +28,13 @@ pub struct DBStats {
     pub total_updates: u64,
     #[serde(serialize_with = "to_u128")]
     pub total_update_duration: Duration,
+    /// Merges of the data files, only done by EdgeKV
+    pub total_merges: u64,
+    #[serde(serialize_with = "to_u128")]
+    pub total_merge_duration: Duration,
+    pub merge_reclaimed_bytes: u64,
+    /// Size of the overwritten or deleted entries still on disk, only tracked by EdgeKV
+    pub dead_bytes: u64,
 }
 
 fn to_u128<S>(x: &Duration, s: S) -> Result<S::Ok, S::Error>
@@ -37,6 +44,44 @@ where
     s.serialize_some(&x.as_micros())
 }
 
+/// Single write of a [`BackendWriteBatch`]
+#[derive(Clone, Debug)]
+pub enum BackendBatchOperation {
+    Put {
+        column: &'static str,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Merge {
+        column: &'static str,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Delete {
+        column: &'static str,
+        key: Vec<u8>,
+    },
+}
+
+impl BackendBatchOperation {
+    pub fn column(&self) -> &'static str {
+        match self {
+            Self::Put { column, .. } | Self::Merge { column, .. } | Self::Delete { column, .. } => {
+                column
+            }
+        }
+    }
+
+    pub fn key(&self) -> &[u8] {
+        match self {
+            Self::Put { key, .. } | Self::Merge { key, .. } | Self::Delete { key, .. } => key,
+        }
+    }
+}
+
+/// Writes to any columns, applied in order and all at once
+pub type BackendWriteBatch = Vec<BackendBatchOperation>;
+
 #[allow(clippy::ptr_arg)]
 pub trait TezedgeDatabaseBackendStore {
     fn put(&self, column: &'static str, key: &[u8], value: &[u8]) -> Result<(), Error>;
@@ -49,6 +94,9 @@ pub trait TezedgeDatabaseBackendStore {
         column: &'static str,
         batch: Vec<(Vec<u8>, Vec<u8>)>,
     ) -> Result<(), Error>;
+    /// Write a batch spanning several columns atomically: either all of its
+    /// operations are persisted, or none of them.
+    fn write_multi_column_batch(&self, batch: BackendWriteBatch) -> Result<(), Error>;
     fn flush(&self) -> Result<usize, Error>;
     fn size(&self) -> HashMap<&'static str, usize>;
     fn sync(&self) -> Result<(), Error>;
@@ -65,4 +113,27 @@ pub trait TezedgeDatabaseBackendStore {
     ) -> Result<BackendIterator<'a>, Error>;
 
     fn column_stats(&self) -> HashMap<&'static str, DBStats>;
+
+    /// Read-only view of all columns at this point in time, unaffected by later writes
+    fn snapshot(&self) -> Result<Box<BackendSnapshotReader>, Error>;
+}
+
+/// Reads of a [`TezedgeDatabaseBackendStore::snapshot`]
+#[allow(clippy::ptr_arg)]
+pub trait BackendSnapshot {
+    fn get(&self, column: &'static str, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+    fn contains(&self, column: &'static str, key: &[u8]) -> Result<bool, Error>;
+    fn find<'a>(
+        &'a self,
+        column: &'static str,
+        mode: BackendIteratorMode,
+    ) -> Result<BackendIterator<'a>, Error>;
+    fn find_by_prefix<'a>(
+        &'a self,
+        column: &'static str,
+        key: &Vec<u8>,
+        max_key_len: usize,
+    ) -> Result<BackendIterator<'a>, Error>;
 }
+
+pub type BackendSnapshotReader = dyn BackendSnapshot + Send + Sync;
@@ -2,22 +2,41 @@
 // SPDX-License-Identifier: MIT
 
 use crate::database::backend::{
-    BackendIterator, BackendIteratorMode, DBStats, TezedgeDatabaseBackendStore,
+    BackendBatchOperation, BackendIterator, BackendIteratorMode, BackendSnapshot,
+    BackendSnapshotReader, BackendWriteBatch, DBStats, TezedgeDatabaseBackendStore,
 };
 use crate::database::error::Error;
 use crate::database::tezedge_database::{KVStoreKeyValueSchema, TezdegeDatabaseBackendKV};
 use crate::{
     block_meta_storage, operations_meta_storage, BlockMetaStorage, Direction, OperationsMetaStorage,
 };
-use edgekv::edgekv::EdgeKV;
-use std::collections::HashMap;
+use edgekv::edgekv::{DBIterator, EdgeKV, EdgeKVSnapshot};
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::ops::RangeBounds;
+use std::os::unix::fs::FileExt;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Instant;
 
+/// File in the database directory logging the multi column batch being written
+const BATCH_LOG_FILE_NAME: &str = "batch.log";
+
+/// Length and checksum of the logged batch
+const BATCH_LOG_HEADER_LENGTH: usize = 8;
+
+/// Multi column batch with the merges resolved: column, key and value, `None` for a delete
+type ResolvedWriteBatch = Vec<(String, Vec<u8>, Option<Vec<u8>>)>;
+
+type MergeFunction = fn(&[u8], Option<Vec<u8>>, &[u8]) -> Option<Vec<u8>>;
+
 pub struct EdgeKVBackend {
     column_stats: Arc<RwLock<HashMap<&'static str, DBStats>>>,
     db: HashMap<&'static str, EdgeKV>,
+    /// Log of the multi column batch being written, replayed on open
+    batch_log: Mutex<File>,
 }
 
 impl EdgeKVBackend {
@@ -34,13 +53,143 @@ impl EdgeKVBackend {
             db.insert(col, col_db);
         }
 
-        Ok(Self {
+        std::fs::create_dir_all(&p).map_err(|error| Error::IOError { error })?;
+        let batch_log = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(p.join(BATCH_LOG_FILE_NAME))
+            .map_err(|error| Error::IOError { error })?;
+
+        let backend = Self {
             column_stats: Arc::new(Default::default()),
             db,
-        })
+            batch_log: Mutex::new(batch_log),
+        };
+        backend.replay_batch_log()?;
+        Ok(backend)
+    }
+
+    /// Applies the batch logged by an interrupted multi column batch write. A batch
+    /// which is not completely logged was not applied at all, it is dropped.
+    fn replay_batch_log(&self) -> Result<(), Error> {
+        let mut batch_log = self.batch_log.lock().map_err(|e| Error::GuardPoison {
+            error: format!("{}", e),
+        })?;
+
+        let mut bytes = Vec::new();
+        batch_log
+            .read_to_end(&mut bytes)
+            .map_err(|error| Error::IOError { error })?;
+        if let Some(batch) = decode_batch_record(&bytes) {
+            self.apply_resolved_batch(&batch)?;
+        }
+
+        batch_log
+            .set_len(0)
+            .and_then(|()| batch_log.sync_data())
+            .map_err(|error| Error::IOError { error })
+    }
+
+    /// Replaces the merges of the batch with the values they result in, so the
+    /// logged batch can be applied again
+    fn resolve_batch(&self, batch: BackendWriteBatch) -> Result<ResolvedWriteBatch, Error> {
+        // values written by the batch so far, the merges apply on top of them
+        let mut written: HashMap<(&'static str, Vec<u8>), Option<Vec<u8>>> = HashMap::new();
+        let mut resolved = Vec::with_capacity(batch.len());
+
+        for operation in batch {
+            let (column, key, value) = match operation {
+                BackendBatchOperation::Put { column, key, value } => (column, key, Some(value)),
+                BackendBatchOperation::Delete { column, key } => (column, key, None),
+                BackendBatchOperation::Merge { column, key, value } => {
+                    let value = match merge_function(column) {
+                        Some(merge) => {
+                            let existing = match written.get(&(column, key.clone())) {
+                                Some(existing) => existing.clone(),
+                                None => self.get(column, &key)?,
+                            };
+                            merge(&key, existing, &value)
+                        }
+                        None => Some(value),
+                    };
+                    (column, key, value)
+                }
+            };
+            written.insert((column, key.clone()), value.clone());
+            resolved.push((column.to_string(), key, value));
+        }
+
+        Ok(resolved)
+    }
+
+    /// Applies the batch and persists the changed columns
+    fn apply_resolved_batch(&self, batch: &ResolvedWriteBatch) -> Result<(), Error> {
+        let mut changed = BTreeSet::new();
+        for (column, key, value) in batch {
+            let column = self
+                .db
+                .keys()
+                .find(|name| **name == column.as_str())
+                .copied()
+                .ok_or_else(|| Error::EdgeKVError {
+                    error: format!("Column Missing: {}", column),
+                })?;
+            match value {
+                Some(value) => self.put(column, key, value)?,
+                None => self.delete(column, key)?,
+            }
+            changed.insert(column);
+        }
+
+        for column in changed {
+            if let Some(db) = self.db.get(column) {
+                db.sync_all().map_err(|e| Error::EdgeKVError {
+                    error: format!("EdgeKV Error: {:?}", e),
+                })?;
+            }
+        }
+        Ok(())
     }
 }
 
+/// Merge operator of the column, the merged value replaces the existing one if there is none
+fn merge_function(column: &str) -> Option<MergeFunction> {
+    if column == OperationsMetaStorage::column_name() {
+        Some(operations_meta_storage::merge_meta_value_edgekv)
+    } else if column == BlockMetaStorage::column_name() {
+        Some(block_meta_storage::merge_meta_value_edgekv)
+    } else {
+        None
+    }
+}
+
+fn encode_batch_record(batch: &ResolvedWriteBatch) -> Result<Vec<u8>, Error> {
+    let payload = bincode::serialize(batch).map_err(|e| Error::EdgeKVError {
+        error: format!("Failed to encode the batch: {:?}", e),
+    })?;
+    let length: u32 = payload.len().try_into().map_err(|_| Error::EdgeKVError {
+        error: format!("Batch too large: {}", payload.len()),
+    })?;
+
+    let mut record = Vec::with_capacity(BATCH_LOG_HEADER_LENGTH + payload.len());
+    record.extend_from_slice(&length.to_le_bytes());
+    record.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    record.extend_from_slice(&payload);
+    Ok(record)
+}
+
+/// The logged batch, `None` if it is not completely written
+fn decode_batch_record(bytes: &[u8]) -> Option<ResolvedWriteBatch> {
+    let length = u32::from_le_bytes(bytes.get(0..4)?.try_into().ok()?) as usize;
+    let checksum = u32::from_le_bytes(bytes.get(4..8)?.try_into().ok()?);
+    let payload = bytes.get(BATCH_LOG_HEADER_LENGTH..BATCH_LOG_HEADER_LENGTH + length)?;
+    if crc32fast::hash(payload) != checksum {
+        return None;
+    }
+    bincode::deserialize(payload).ok()
+}
+
 #[derive(Clone)]
 pub enum EdgeKVIteratorMode {
     Start,
@@ -49,13 +198,48 @@ pub enum EdgeKVIteratorMode {
     Prefix(Vec<u8>),
 }
 
+/// Store which can be iterated, the database itself or a snapshot of it
+trait EdgeKVIterable {
+    fn iter(&self) -> DBIterator;
+    fn range<R: RangeBounds<Vec<u8>>>(&self, range: R) -> DBIterator;
+    fn prefix(&self, prefix: &Vec<u8>) -> DBIterator;
+}
+
+impl EdgeKVIterable for EdgeKV {
+    fn iter(&self) -> DBIterator {
+        EdgeKV::iter(self)
+    }
+
+    fn range<R: RangeBounds<Vec<u8>>>(&self, range: R) -> DBIterator {
+        EdgeKV::range(self, range)
+    }
+
+    fn prefix(&self, prefix: &Vec<u8>) -> DBIterator {
+        EdgeKV::prefix(self, prefix)
+    }
+}
+
+impl EdgeKVIterable for EdgeKVSnapshot {
+    fn iter(&self) -> DBIterator {
+        EdgeKVSnapshot::iter(self)
+    }
+
+    fn range<R: RangeBounds<Vec<u8>>>(&self, range: R) -> DBIterator {
+        EdgeKVSnapshot::range(self, range)
+    }
+
+    fn prefix(&self, prefix: &Vec<u8>) -> DBIterator {
+        EdgeKVSnapshot::prefix(self, prefix)
+    }
+}
+
 pub struct EdgeKVIterator {
     mode: EdgeKVIteratorMode,
-    iter: edgekv::edgekv::DBIterator,
+    iter: DBIterator,
 }
 
 impl EdgeKVIterator {
-    fn new(mode: EdgeKVIteratorMode, db: &EdgeKV) -> Self {
+    fn new<D: EdgeKVIterable>(mode: EdgeKVIteratorMode, db: &D) -> Self {
         match mode.clone() {
             EdgeKVIteratorMode::Start => Self {
                 mode,
@@ -228,6 +412,13 @@ impl TezedgeDatabaseBackendStore for EdgeKVBackend {
         column: &'static str,
         batch: Vec<(Vec<u8>, Vec<u8>)>,
     ) -> Result<(), Error> {
+        let mut stats = self.column_stats.write().map_err(|e| Error::GuardPoison {
+            error: format!("{}", e),
+        })?;
+
+        let timer = Instant::now();
+        let writes = batch.len() as u64;
+
         let db = self.db.get(column).ok_or(Error::EdgeKVError {
             error: format!("Column Missing: {}", column),
         })?;
@@ -236,9 +427,50 @@ impl TezedgeDatabaseBackendStore for EdgeKVBackend {
                 error: format!("{:?}", error),
             })?;
         }
+
+        let total_write_duration = timer.elapsed();
+        let mut stat = stats.entry(column).or_insert_with(Default::default);
+        stat.total_write_duration += total_write_duration;
+        stat.total_writes += writes;
         Ok(())
     }
 
+    /// Every column is a separate EdgeKV store, there is no transaction across them.
+    ///
+    /// The batch, with its merges resolved, is logged and synced before it is applied,
+    /// and the log is cleared once the changed columns are synced. A batch interrupted
+    /// by a crash is applied again when the database is opened.
+    fn write_multi_column_batch(&self, batch: BackendWriteBatch) -> Result<(), Error> {
+        if let Some(operation) = batch
+            .iter()
+            .find(|operation| !self.db.contains_key(operation.column()))
+        {
+            return Err(Error::EdgeKVError {
+                error: format!("Column Missing: {}", operation.column()),
+            });
+        }
+
+        // one batch at a time, they share the log
+        let batch_log = self.batch_log.lock().map_err(|e| Error::GuardPoison {
+            error: format!("{}", e),
+        })?;
+
+        let batch = self.resolve_batch(batch)?;
+        let record = encode_batch_record(&batch)?;
+        batch_log
+            .set_len(0)
+            .and_then(|()| batch_log.write_all_at(&record, 0))
+            .and_then(|()| batch_log.sync_data())
+            .map_err(|error| Error::IOError { error })?;
+
+        self.apply_resolved_batch(&batch)?;
+
+        batch_log
+            .set_len(0)
+            .and_then(|()| batch_log.sync_data())
+            .map_err(|error| Error::IOError { error })
+    }
+
     fn flush(&self) -> Result<usize, Error> {
         for (_, db) in self.db.iter() {
             db.sync_all().map_err(|e| Error::EdgeKVError {
@@ -311,6 +543,178 @@ impl TezedgeDatabaseBackendStore for EdgeKVBackend {
             Ok(stats) => stats,
             Err(_) => return Default::default(),
         };
-        stats.clone()
+        let mut stats = stats.clone();
+
+        for (column, db) in self.db.iter() {
+            let merge_stats = db.merge_stats();
+            let column_stats = stats.entry(*column).or_default();
+            column_stats.total_merges = merge_stats.merges;
+            column_stats.total_merge_duration = merge_stats.total_merge_duration;
+            column_stats.merge_reclaimed_bytes = merge_stats.reclaimed_bytes;
+            column_stats.dead_bytes = db
+                .fragmentation()
+                .map(|files| files.values().map(|file| file.dead_bytes()).sum())
+                .unwrap_or(0);
+        }
+        stats
+    }
+
+    /// Every column is snapshotted separately: a write to several columns which is
+    /// in progress can be seen partially, like with `write_multi_column_batch`.
+    fn snapshot(&self) -> Result<Box<BackendSnapshotReader>, Error> {
+        let db = self
+            .db
+            .iter()
+            .map(|(column, db)| {
+                db.snapshot()
+                    .map(|snapshot| (*column, snapshot))
+                    .map_err(|error| Error::EdgeKVError {
+                        error: format!("{:?}", error),
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Box::new(EdgeKVBackendSnapshot { db }))
+    }
+}
+
+pub struct EdgeKVBackendSnapshot {
+    db: HashMap<&'static str, EdgeKVSnapshot>,
+}
+
+impl EdgeKVBackendSnapshot {
+    fn column(&self, column: &'static str) -> Result<&EdgeKVSnapshot, Error> {
+        self.db.get(column).ok_or(Error::EdgeKVError {
+            error: format!("Column Missing: {}", column),
+        })
+    }
+}
+
+impl BackendSnapshot for EdgeKVBackendSnapshot {
+    fn get(&self, column: &'static str, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.column(column)?
+            .get(&key.to_vec())
+            .map_err(|error| Error::EdgeKVError {
+                error: format!("{:?}", error),
+            })
+    }
+
+    fn contains(&self, column: &'static str, key: &[u8]) -> Result<bool, Error> {
+        self.column(column)?
+            .contains(&key.to_vec())
+            .map_err(|error| Error::EdgeKVError {
+                error: format!("{:?}", error),
+            })
+    }
+
+    fn find<'a>(
+        &'a self,
+        column: &'static str,
+        mode: BackendIteratorMode,
+    ) -> Result<BackendIterator<'a>, Error> {
+        let db = self.column(column)?;
+
+        let iter = match mode {
+            BackendIteratorMode::Start => EdgeKVIterator::new(EdgeKVIteratorMode::Start, db),
+            BackendIteratorMode::End => EdgeKVIterator::new(EdgeKVIteratorMode::End, db),
+            BackendIteratorMode::From(key, direction) => {
+                EdgeKVIterator::new(EdgeKVIteratorMode::From(key, direction), db)
+            }
+        };
+
+        Ok(Box::new(iter.map(|result| {
+            result.map(|(k, v)| (k.into_boxed_slice(), v.into_boxed_slice()))
+        })))
+    }
+
+    fn find_by_prefix<'a>(
+        &'a self,
+        column: &'static str,
+        key: &Vec<u8>,
+        max_key_len: usize,
+    ) -> Result<BackendIterator<'a>, Error> {
+        let db = self.column(column)?;
+
+        let prefix_key = key[..max_key_len].to_vec();
+        let iter = EdgeKVIterator::new(EdgeKVIteratorMode::Prefix(prefix_key), db);
+
+        Ok(Box::new(iter.map(|result| {
+            result.map(|(k, v)| (k.into_boxed_slice(), v.into_boxed_slice()))
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_with_batch_log(dir: &str, batch_log: &[u8]) -> EdgeKVBackend {
+        if Path::new(dir).exists() {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(Path::new(dir).join(BATCH_LOG_FILE_NAME), batch_log).unwrap();
+        EdgeKVBackend::new(dir, vec!["a", "b"]).unwrap()
+    }
+
+    fn batch_log_length(dir: &str) -> u64 {
+        std::fs::metadata(Path::new(dir).join(BATCH_LOG_FILE_NAME))
+            .unwrap()
+            .len()
+    }
+
+    #[test]
+    fn test_interrupted_batch_is_replayed() {
+        let dir = "./testdir/edgekv_backend/replayed";
+        let batch = vec![
+            ("a".to_string(), vec![1], Some(vec![10])),
+            ("b".to_string(), vec![2], Some(vec![20])),
+            ("a".to_string(), vec![1], None),
+        ];
+        let backend = open_with_batch_log(dir, &encode_batch_record(&batch).unwrap());
+
+        assert_eq!(backend.get("a", &[1]).unwrap(), None);
+        assert_eq!(backend.get("b", &[2]).unwrap(), Some(vec![20]));
+        assert_eq!(batch_log_length(dir), 0);
+    }
+
+    #[test]
+    fn test_partially_logged_batch_is_dropped() {
+        let dir = "./testdir/edgekv_backend/dropped";
+        let batch = vec![("a".to_string(), vec![1], Some(vec![10]))];
+        let record = encode_batch_record(&batch).unwrap();
+        let backend = open_with_batch_log(dir, &record[..record.len() - 1]);
+
+        assert_eq!(backend.get("a", &[1]).unwrap(), None);
+        assert_eq!(batch_log_length(dir), 0);
+    }
+
+    #[test]
+    fn test_write_multi_column_batch() {
+        let dir = "./testdir/edgekv_backend/written";
+        let backend = open_with_batch_log(dir, &[]);
+
+        backend
+            .write_multi_column_batch(vec![
+                BackendBatchOperation::Put {
+                    column: "a",
+                    key: vec![1],
+                    value: vec![10],
+                },
+                BackendBatchOperation::Merge {
+                    column: "b",
+                    key: vec![2],
+                    value: vec![20],
+                },
+                BackendBatchOperation::Delete {
+                    column: "a",
+                    key: vec![3],
+                },
+            ])
+            .unwrap();
+
+        assert_eq!(backend.get("a", &[1]).unwrap(), Some(vec![10]));
+        assert_eq!(backend.get("b", &[2]).unwrap(), Some(vec![20]));
+        assert_eq!(batch_log_length(dir), 0);
     }
 }
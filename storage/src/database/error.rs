@@ -33,6 +33,8 @@ pub enum Error {
     RocksDBError { error: rocksdb::Error },
     #[error("Column family {name} is missing")]
     MissingColumnFamily { name: &'static str },
+    #[error("Database snapshot is read-only")]
+    ReadOnlySnapshot,
 }
 
 impl From<SchemaError> for Error {
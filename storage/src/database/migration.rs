@@ -0,0 +1,584 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Offline copy of the main database from one backend to another.
+//!
+//! Every column is copied in chunks. After each chunk, the target database is flushed
+//! and the last copied key is saved to a progress file, so an interrupted migration
+//! resumes where it stopped instead of starting again.
+//!
+//! Both databases must not be used by a running node during the migration.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rocksdb::Cache;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::database::backend::{BackendIteratorMode, TezedgeDatabaseBackendStore};
+use crate::database::edgekv_backend::EdgeKVBackend;
+use crate::database::error::Error;
+use crate::database::rockdb_backend::RocksDBBackend;
+use crate::database::sled_backend::SledDBBackend;
+use crate::database::tezedge_database::{
+    TezedgeDatabaseBackend, TezedgeDatabaseBackendConfiguration,
+};
+use crate::initializer::{DbsRocksDbTableInitializer, RocksDbConfig};
+use crate::persistent::main_db_columns;
+use crate::Direction;
+
+/// Name of the progress file, written in the directory of the target database
+pub const MIGRATION_PROGRESS_FILE_NAME: &str = "maindb_migration.json";
+
+/// Number of entries written to the target database at once
+const MIGRATION_CHUNK_SIZE: usize = 10_000;
+
+/// Block cache used when one of the databases is RocksDB
+const MIGRATION_ROCKSDB_CACHE_SIZE: usize = 128 * 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error("Database error: {error}")]
+    DatabaseError { error: Error },
+    #[error("I/O error: {error}")]
+    IOError { error: io::Error },
+    #[error("Invalid progress file {path:?}: {error}")]
+    InvalidProgressFile {
+        path: PathBuf,
+        error: serde_json::Error,
+    },
+    #[error("Verification of column {column} failed: {reason}")]
+    VerificationFailed {
+        column: &'static str,
+        reason: String,
+    },
+}
+
+impl From<Error> for MigrationError {
+    fn from(error: Error) -> Self {
+        MigrationError::DatabaseError { error }
+    }
+}
+
+impl From<io::Error> for MigrationError {
+    fn from(error: io::Error) -> Self {
+        MigrationError::IOError { error }
+    }
+}
+
+impl From<rocksdb::Error> for MigrationError {
+    fn from(error: rocksdb::Error) -> Self {
+        MigrationError::DatabaseError {
+            error: Error::from(error),
+        }
+    }
+}
+
+/// Main database opened directly on its backend, without any compatibility check
+pub struct MigrationDatabase {
+    backend: Box<TezedgeDatabaseBackend>,
+    // IMPORTANT: Cache object must live at least as long as DB, so it is declared (dropped) after it
+    _cache: Option<Cache>,
+}
+
+impl MigrationDatabase {
+    pub fn open(
+        backend: TezedgeDatabaseBackendConfiguration,
+        db_path: &Path,
+    ) -> Result<Self, MigrationError> {
+        let (backend, cache): (Box<TezedgeDatabaseBackend>, Option<Cache>) = match backend {
+            TezedgeDatabaseBackendConfiguration::Sled => {
+                (Box::new(SledDBBackend::new(db_path)?), None)
+            }
+            TezedgeDatabaseBackendConfiguration::EdgeKV => (
+                Box::new(EdgeKVBackend::new(db_path, main_db_columns())?),
+                None,
+            ),
+            TezedgeDatabaseBackendConfiguration::RocksDB => {
+                let cache = Cache::new_lru_cache(MIGRATION_ROCKSDB_CACHE_SIZE)?;
+                let backend = RocksDBBackend::new(
+                    &cache,
+                    &RocksDbConfig {
+                        cache_size: MIGRATION_ROCKSDB_CACHE_SIZE,
+                        expected_db_version: 0,
+                        db_path: db_path.to_path_buf(),
+                        columns: DbsRocksDbTableInitializer,
+                        threads: None,
+                    },
+                )?;
+                (Box::new(backend), Some(cache))
+            }
+        };
+
+        Ok(Self {
+            backend,
+            _cache: cache,
+        })
+    }
+
+    pub fn backend(&self) -> &TezedgeDatabaseBackend {
+        &*self.backend
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct MigrationProgress {
+    columns: BTreeMap<String, ColumnProgress>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+struct ColumnProgress {
+    /// Number of entries copied so far
+    copied: u64,
+    /// Last key written to the target database
+    last_key: Option<Vec<u8>>,
+    done: bool,
+}
+
+impl MigrationProgress {
+    fn load(path: &Path) -> Result<Self, MigrationError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let bytes = fs::read(path)?;
+        serde_json::from_slice(&bytes).map_err(|error| MigrationError::InvalidProgressFile {
+            path: path.to_path_buf(),
+            error,
+        })
+    }
+
+    fn save(&self, path: &Path) -> Result<(), MigrationError> {
+        let bytes =
+            serde_json::to_vec(self).map_err(|error| MigrationError::InvalidProgressFile {
+                path: path.to_path_buf(),
+                error,
+            })?;
+
+        // write to a temporary file first, so a crash never leaves a truncated progress file
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, bytes)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+/// Result of the copy of one column
+#[derive(Debug, Clone)]
+pub struct ColumnMigrationReport {
+    pub column: &'static str,
+    /// Number of entries copied, including previous interrupted runs
+    pub copied: u64,
+    /// Number of entries copied by this run
+    pub copied_now: u64,
+}
+
+/// Copy every column of `source` into `target`.
+///
+/// `on_progress` is called after each written chunk with the column and the number of
+/// entries copied so far. The entries written by this run are checked against the
+/// `column_stats` of the target backend.
+pub fn migrate_main_db<F>(
+    source: &MigrationDatabase,
+    target: &MigrationDatabase,
+    progress_path: &Path,
+    on_progress: F,
+) -> Result<Vec<ColumnMigrationReport>, MigrationError>
+where
+    F: FnMut(&'static str, u64),
+{
+    migrate_main_db_in_chunks(
+        source,
+        target,
+        progress_path,
+        MIGRATION_CHUNK_SIZE,
+        on_progress,
+    )
+}
+
+fn migrate_main_db_in_chunks<F>(
+    source: &MigrationDatabase,
+    target: &MigrationDatabase,
+    progress_path: &Path,
+    chunk_size: usize,
+    mut on_progress: F,
+) -> Result<Vec<ColumnMigrationReport>, MigrationError>
+where
+    F: FnMut(&'static str, u64),
+{
+    let mut progress = MigrationProgress::load(progress_path)?;
+    let mut reports = Vec::new();
+
+    for column in main_db_columns() {
+        let mut column_progress = progress.columns.get(column).cloned().unwrap_or_default();
+        let copied_before = column_progress.copied;
+
+        if !column_progress.done {
+            let mode = match &column_progress.last_key {
+                Some(last_key) => BackendIteratorMode::From(last_key.clone(), Direction::Forward),
+                None => BackendIteratorMode::Start,
+            };
+
+            let mut chunk = Vec::with_capacity(chunk_size);
+
+            for entry in source.backend.find(column, mode)? {
+                let (key, value) = entry?;
+
+                // iteration resumes from the last copied key, which is already in the target
+                if column_progress.last_key.as_deref() == Some(key.as_ref()) {
+                    continue;
+                }
+
+                chunk.push((key.into_vec(), value.into_vec()));
+
+                if chunk.len() == chunk_size {
+                    write_chunk(target, column, &mut chunk, &mut column_progress)?;
+                    progress
+                        .columns
+                        .insert(column.to_string(), column_progress.clone());
+                    progress.save(progress_path)?;
+                    on_progress(column, column_progress.copied);
+                }
+            }
+
+            write_chunk(target, column, &mut chunk, &mut column_progress)?;
+            column_progress.done = true;
+            progress
+                .columns
+                .insert(column.to_string(), column_progress.clone());
+            progress.save(progress_path)?;
+            on_progress(column, column_progress.copied);
+        }
+
+        let copied_now = column_progress.copied - copied_before;
+        let written = target
+            .backend
+            .column_stats()
+            .get(column)
+            .map(|stats| stats.total_writes)
+            .unwrap_or(0);
+        if written != copied_now {
+            return Err(MigrationError::VerificationFailed {
+                column,
+                reason: format!(
+                    "copied {} entries, but the target backend counted {} writes",
+                    copied_now, written
+                ),
+            });
+        }
+
+        reports.push(ColumnMigrationReport {
+            column,
+            copied: column_progress.copied,
+            copied_now,
+        });
+    }
+
+    Ok(reports)
+}
+
+fn write_chunk(
+    target: &MigrationDatabase,
+    column: &'static str,
+    chunk: &mut Vec<(Vec<u8>, Vec<u8>)>,
+    column_progress: &mut ColumnProgress,
+) -> Result<(), MigrationError> {
+    if chunk.is_empty() {
+        return Ok(());
+    }
+
+    let last_key = chunk.last().map(|(key, _)| key.clone());
+    let count = chunk.len() as u64;
+
+    target.backend.write_batch(column, std::mem::take(chunk))?;
+    // the progress is saved only when the chunk is on disk
+    target.backend.flush()?;
+
+    column_progress.copied += count;
+    column_progress.last_key = last_key;
+    Ok(())
+}
+
+/// Result of the verification of one column
+#[derive(Debug, Clone)]
+pub struct ColumnVerificationReport {
+    pub column: &'static str,
+    pub entries: u64,
+    pub sampled: u64,
+}
+
+/// Check that every column of `target` has as many entries as in `source`, and compare
+/// the values of one key out of every `sample_every` keys.
+pub fn verify_main_db(
+    source: &MigrationDatabase,
+    target: &MigrationDatabase,
+    sample_every: u64,
+) -> Result<Vec<ColumnVerificationReport>, MigrationError> {
+    let sample_every = sample_every.max(1);
+    let mut reports = Vec::new();
+
+    for column in main_db_columns() {
+        let mut entries = 0;
+        let mut sampled = 0;
+
+        for entry in source.backend.find(column, BackendIteratorMode::Start)? {
+            let (key, value) = entry?;
+
+            if entries % sample_every == 0 {
+                match target.backend.get(column, &key)? {
+                    Some(target_value) if target_value.as_slice() == value.as_ref() => {}
+                    Some(_) => {
+                        return Err(MigrationError::VerificationFailed {
+                            column,
+                            reason: format!("different value for key {}", hex::encode(&key)),
+                        })
+                    }
+                    None => {
+                        return Err(MigrationError::VerificationFailed {
+                            column,
+                            reason: format!("missing key {}", hex::encode(&key)),
+                        })
+                    }
+                }
+                sampled += 1;
+            }
+            entries += 1;
+        }
+
+        let mut target_entries = 0;
+        for entry in target.backend.find(column, BackendIteratorMode::Start)? {
+            entry?;
+            target_entries += 1;
+        }
+
+        if entries != target_entries {
+            return Err(MigrationError::VerificationFailed {
+                column,
+                reason: format!(
+                    "source has {} entries, target has {} entries",
+                    entries, target_entries
+                ),
+            });
+        }
+
+        reports.push(ColumnVerificationReport {
+            column,
+            entries,
+            sampled,
+        });
+    }
+
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use super::*;
+
+    const CHUNK_SIZE: usize = 10;
+
+    fn open(backend: TezedgeDatabaseBackendConfiguration, dir: &Path) -> MigrationDatabase {
+        fs::create_dir_all(dir).unwrap();
+        MigrationDatabase::open(backend, dir).unwrap()
+    }
+
+    /// Opens empty source and target databases in `dir`
+    fn open_empty(
+        dir: &str,
+        source_backend: TezedgeDatabaseBackendConfiguration,
+        target_backend: TezedgeDatabaseBackendConfiguration,
+    ) -> (MigrationDatabase, MigrationDatabase, PathBuf) {
+        if Path::new(dir).exists() {
+            fs::remove_dir_all(dir).unwrap();
+        }
+        let target_dir = Path::new(dir).join("target");
+        (
+            open(source_backend, &Path::new(dir).join("source")),
+            open(target_backend, &target_dir),
+            target_dir.join(MIGRATION_PROGRESS_FILE_NAME),
+        )
+    }
+
+    /// Writes entries in every column of `db`, the first column spans several chunks
+    fn fill(db: &MigrationDatabase) {
+        for (index, column) in main_db_columns().into_iter().enumerate() {
+            let count = if index == 0 { 3 * CHUNK_SIZE - 5 } else { 3 };
+            let batch = (0..count as u32)
+                .map(|key| {
+                    (
+                        key.to_be_bytes().to_vec(),
+                        vec![index as u8; key as usize + 1],
+                    )
+                })
+                .collect();
+            db.backend().write_batch(column, batch).unwrap();
+        }
+        db.backend().flush().unwrap();
+    }
+
+    fn entries(db: &MigrationDatabase, column: &'static str) -> Vec<(Vec<u8>, Vec<u8>)> {
+        db.backend()
+            .find(column, BackendIteratorMode::Start)
+            .unwrap()
+            .map(|entry| {
+                let (key, value) = entry.unwrap();
+                (key.into_vec(), value.into_vec())
+            })
+            .collect()
+    }
+
+    fn assert_same_entries(source: &MigrationDatabase, target: &MigrationDatabase) {
+        for column in main_db_columns() {
+            assert_eq!(
+                entries(source, column),
+                entries(target, column),
+                "{}",
+                column
+            );
+        }
+    }
+
+    #[test]
+    fn test_migrate_every_column() {
+        for (dir, target_backend) in [
+            (
+                "./testdir/migration/to_rocksdb",
+                TezedgeDatabaseBackendConfiguration::RocksDB,
+            ),
+            (
+                "./testdir/migration/to_edgekv",
+                TezedgeDatabaseBackendConfiguration::EdgeKV,
+            ),
+        ] {
+            let (source, target, progress_path) = open_empty(
+                dir,
+                TezedgeDatabaseBackendConfiguration::Sled,
+                target_backend,
+            );
+            fill(&source);
+
+            let reports =
+                migrate_main_db_in_chunks(&source, &target, &progress_path, CHUNK_SIZE, |_, _| {})
+                    .unwrap();
+
+            assert_eq!(reports.len(), main_db_columns().len());
+            for (report, column) in reports.iter().zip(main_db_columns()) {
+                let expected = entries(&source, column).len() as u64;
+                assert_eq!(report.column, column);
+                assert_eq!(report.copied, expected);
+                assert_eq!(report.copied_now, expected);
+            }
+            assert_same_entries(&source, &target);
+            verify_main_db(&source, &target, 1).unwrap();
+
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_interrupted_migration_resumes() {
+        let dir = "./testdir/migration/interrupted";
+        let (source, target, progress_path) = open_empty(
+            dir,
+            TezedgeDatabaseBackendConfiguration::Sled,
+            TezedgeDatabaseBackendConfiguration::RocksDB,
+        );
+        fill(&source);
+        let first_column = main_db_columns()[0];
+
+        // the migration stops after the first chunk of the first column
+        let interrupted = panic::catch_unwind(AssertUnwindSafe(|| {
+            migrate_main_db_in_chunks(
+                &source,
+                &target,
+                &progress_path,
+                CHUNK_SIZE,
+                |column, copied| {
+                    if column == first_column && copied == CHUNK_SIZE as u64 {
+                        panic!("interrupted");
+                    }
+                },
+            )
+        }));
+        assert!(interrupted.is_err());
+        assert_eq!(entries(&target, first_column).len(), CHUNK_SIZE);
+
+        let progress = MigrationProgress::load(&progress_path).unwrap();
+        let column_progress = &progress.columns[first_column];
+        assert_eq!(column_progress.copied, CHUNK_SIZE as u64);
+        assert_eq!(
+            column_progress.last_key,
+            Some((CHUNK_SIZE as u32 - 1).to_be_bytes().to_vec())
+        );
+        assert!(!column_progress.done);
+        drop(target);
+
+        let target = open(
+            TezedgeDatabaseBackendConfiguration::RocksDB,
+            Path::new(dir).join("target").as_path(),
+        );
+        let reports =
+            migrate_main_db_in_chunks(&source, &target, &progress_path, CHUNK_SIZE, |_, _| {})
+                .unwrap();
+
+        // the entries of the first chunk are not written again, the target backend
+        // counted only the writes of this run
+        let total = entries(&source, first_column).len() as u64;
+        assert_eq!(reports[0].copied, total);
+        assert_eq!(reports[0].copied_now, total - CHUNK_SIZE as u64);
+        assert_same_entries(&source, &target);
+        verify_main_db(&source, &target, 1).unwrap();
+
+        // a finished migration copies nothing
+        let reports =
+            migrate_main_db_in_chunks(&source, &target, &progress_path, CHUNK_SIZE, |_, _| {})
+                .unwrap();
+        assert!(reports.iter().all(|report| report.copied_now == 0));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_verify_detects_different_and_missing_values() {
+        let dir = "./testdir/migration/verify";
+        let (source, target, progress_path) = open_empty(
+            dir,
+            TezedgeDatabaseBackendConfiguration::Sled,
+            TezedgeDatabaseBackendConfiguration::EdgeKV,
+        );
+        fill(&source);
+        migrate_main_db_in_chunks(&source, &target, &progress_path, CHUNK_SIZE, |_, _| {}).unwrap();
+        let column = main_db_columns()[0];
+        let key = 7_u32.to_be_bytes();
+
+        target.backend().put(column, &key, &[0xff]).unwrap();
+        match verify_main_db(&source, &target, 1) {
+            Err(MigrationError::VerificationFailed {
+                column: failed,
+                reason,
+            }) => {
+                assert_eq!(failed, column);
+                assert!(reason.starts_with("different value"), "{}", reason);
+            }
+            result => panic!("unexpected verification result {:?}", result),
+        }
+
+        target.backend().delete(column, &key).unwrap();
+        match verify_main_db(&source, &target, 1) {
+            Err(MigrationError::VerificationFailed {
+                column: failed,
+                reason,
+            }) => {
+                assert_eq!(failed, column);
+                assert!(reason.starts_with("missing key"), "{}", reason);
+            }
+            result => panic!("unexpected verification result {:?}", result),
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -4,6 +4,8 @@
 pub mod backend;
 pub mod edgekv_backend;
 pub mod error;
+pub mod migration;
 pub mod rockdb_backend;
 pub mod sled_backend;
+pub mod snapshot;
 pub mod tezedge_database;
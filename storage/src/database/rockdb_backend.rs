@@ -1,13 +1,16 @@
 // Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
 // SPDX-License-Identifier: MIT
 
-use crate::database::backend::{BackendIteratorMode, DBStats, TezedgeDatabaseBackendStore};
+use crate::database::backend::{
+    BackendBatchOperation, BackendIteratorMode, BackendSnapshot, BackendSnapshotReader,
+    BackendWriteBatch, DBStats, TezedgeDatabaseBackendStore,
+};
 use crate::database::error::Error;
 use crate::database::tezedge_database::TezdegeDatabaseBackendKV;
 use crate::initializer::{RocksDbColumnFactory, RocksDbConfig};
 use crate::persistent::database::default_kv_options;
 use crate::persistent::DbConfiguration;
-use rocksdb::{Cache, ColumnFamilyDescriptor, WriteBatch, WriteOptions, DB};
+use rocksdb::{Cache, ColumnFamilyDescriptor, ReadOptions, Snapshot, WriteBatch, WriteOptions, DB};
 use std::collections::HashMap;
 use std::path::Path;
 use std::sync::{Arc, RwLock};
@@ -150,6 +153,13 @@ impl TezedgeDatabaseBackendStore for RocksDBBackend {
         column: &'static str,
         batch: Vec<(Vec<u8>, Vec<u8>)>,
     ) -> Result<(), Error> {
+        let mut stats = self.column_stats.write().map_err(|e| Error::GuardPoison {
+            error: format!("{}", e),
+        })?;
+
+        let timer = Instant::now();
+        let writes = batch.len() as u64;
+
         let mut rocksb_batch = WriteBatch::default();
         for (key, value) in batch.iter() {
             let cf = self
@@ -159,6 +169,34 @@ impl TezedgeDatabaseBackendStore for RocksDBBackend {
             rocksb_batch.put_cf(cf, &key, &value);
         }
         self.db.write_opt(rocksb_batch, &default_write_options())?;
+
+        let total_write_duration = timer.elapsed();
+        let mut stat = stats.entry(column).or_insert_with(Default::default);
+        stat.total_write_duration += total_write_duration;
+        stat.total_writes += writes;
+        Ok(())
+    }
+
+    fn write_multi_column_batch(&self, batch: BackendWriteBatch) -> Result<(), Error> {
+        // A single rocksdb batch can span several column families, and is written atomically
+        let mut rocksb_batch = WriteBatch::default();
+        for operation in batch.iter() {
+            let column = operation.column();
+            let cf = self
+                .db
+                .cf_handle(column)
+                .ok_or(Error::MissingColumnFamily { name: column })?;
+            match operation {
+                BackendBatchOperation::Put { key, value, .. } => {
+                    rocksb_batch.put_cf(cf, key, value)
+                }
+                BackendBatchOperation::Merge { key, value, .. } => {
+                    rocksb_batch.merge_cf(cf, key, value)
+                }
+                BackendBatchOperation::Delete { key, .. } => rocksb_batch.delete_cf(cf, key),
+            }
+        }
+        self.db.write_opt(rocksb_batch, &default_write_options())?;
         Ok(())
     }
 
@@ -221,6 +259,82 @@ impl TezedgeDatabaseBackendStore for RocksDBBackend {
         };
         stats.clone()
     }
+
+    fn snapshot(&self) -> Result<Box<BackendSnapshotReader>, Error> {
+        let db = self.db.clone();
+        // SAFETY: the snapshot only borrows the database, which is kept alive by `db`
+        // until the snapshot is dropped
+        let snapshot =
+            unsafe { std::mem::transmute::<Snapshot<'_>, Snapshot<'static>>(db.snapshot()) };
+        Ok(Box::new(RocksDBSnapshot { snapshot, db }))
+    }
+}
+
+/// Point-in-time view of the database, backed by a RocksDB snapshot
+pub struct RocksDBSnapshot {
+    // IMPORTANT: snapshot must be dropped before the database it refers to, so it is declared before it
+    snapshot: Snapshot<'static>,
+    db: Arc<DB>,
+}
+
+impl BackendSnapshot for RocksDBSnapshot {
+    fn get(&self, column: &'static str, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let cf = self
+            .db
+            .cf_handle(column)
+            .ok_or(Error::MissingColumnFamily { name: column })?;
+        self.snapshot.get_cf(cf, key).map_err(Error::from)
+    }
+
+    fn contains(&self, column: &'static str, key: &[u8]) -> Result<bool, Error> {
+        self.get(column, key).map(|value| value.is_some())
+    }
+
+    fn find<'a>(
+        &'a self,
+        column: &'static str,
+        mode: BackendIteratorMode,
+    ) -> Result<BackendIterator<'a>, Error> {
+        let cf = self
+            .db
+            .cf_handle(column)
+            .ok_or(Error::MissingColumnFamily { name: column })?;
+
+        let iter = match mode {
+            BackendIteratorMode::Start => {
+                self.snapshot.iterator_cf(cf, rocksdb::IteratorMode::Start)
+            }
+            BackendIteratorMode::End => self.snapshot.iterator_cf(cf, rocksdb::IteratorMode::End),
+            BackendIteratorMode::From(key, direction) => self
+                .snapshot
+                .iterator_cf(cf, rocksdb::IteratorMode::From(&key, direction.into())),
+        };
+
+        Ok(Box::new(iter.map(Ok)))
+    }
+
+    fn find_by_prefix<'a>(
+        &'a self,
+        column: &'static str,
+        key: &Vec<u8>,
+        _: usize,
+    ) -> Result<BackendIterator<'a>, Error> {
+        let cf = self
+            .db
+            .cf_handle(column)
+            .ok_or(Error::MissingColumnFamily { name: column })?;
+
+        // same as `DB::prefix_iterator_cf`, with the snapshot
+        let mut read_options = ReadOptions::default();
+        read_options.set_prefix_same_as_start(true);
+        let iter = self.snapshot.iterator_cf_opt(
+            cf,
+            read_options,
+            rocksdb::IteratorMode::From(key, rocksdb::Direction::Forward),
+        );
+
+        Ok(Box::new(iter.map(Ok)))
+    }
 }
 
 fn default_write_options() -> WriteOptions {
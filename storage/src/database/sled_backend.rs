@@ -2,22 +2,60 @@
 // SPDX-License-Identifier: MIT
 
 use crate::block_meta_storage;
-use crate::database::backend::{BackendIteratorMode, DBStats, TezedgeDatabaseBackendStore};
+use crate::database::backend::{
+    BackendBatchOperation, BackendIteratorMode, BackendSnapshot, BackendSnapshotReader,
+    BackendWriteBatch, DBStats, TezedgeDatabaseBackendStore,
+};
 use crate::database::error::Error;
 use crate::database::tezedge_database::{KVStoreKeyValueSchema, TezdegeDatabaseBackendKV};
 use crate::operations_meta_storage;
 use crate::{BlockMetaStorage, Direction, OperationsMetaStorage};
+use sled::transaction::{TransactionError, TransactionResult, Transactional};
 use sled::{Config, IVec, Tree};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ops::Bound;
 use std::path::Path;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use std::time::Instant;
 
 use super::backend::BackendIterator;
 
+type SledMergeFunction = fn(&[u8], Option<&[u8]>, &[u8]) -> Option<Vec<u8>>;
+
 pub struct SledDBBackend {
     column_stats: Arc<RwLock<HashMap<&'static str, DBStats>>>,
     db: sled::Db,
+    snapshots: Arc<RwLock<SledSnapshots>>,
+}
+
+/// Values of a column saved for a snapshot, `None` if the key did not exist
+type SavedValues = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// Sled has no snapshots, they are emulated: each snapshot gets a generation, and
+/// before a key is written, its current value is saved for every live generation
+/// which did not save it yet.
+#[derive(Default)]
+struct SledSnapshots {
+    next_generation: u64,
+    saved: HashMap<u64, HashMap<&'static str, SavedValues>>,
+}
+
+impl SledSnapshots {
+    fn saved_values(&self, generation: u64, column: &'static str) -> Option<&SavedValues> {
+        self.saved
+            .get(&generation)
+            .and_then(|columns| columns.get(column))
+    }
+}
+
+/// Held by a writer until its keys are written
+enum SnapshotsWriteGuard<'a> {
+    NoSnapshot {
+        _guard: RwLockReadGuard<'a, SledSnapshots>,
+    },
+    Saved {
+        _guard: RwLockWriteGuard<'a, SledSnapshots>,
+    },
 }
 
 impl SledDBBackend {
@@ -32,22 +70,71 @@ impl SledDBBackend {
         Ok(Self {
             column_stats: Arc::new(Default::default()),
             db,
+            snapshots: Arc::new(Default::default()),
         })
     }
+
+    /// Saves the current values of `keys` for the live snapshots.
+    ///
+    /// The returned guard must be held until the keys are written. Without a live
+    /// snapshot there is nothing to save, writers only share the lock, so that no
+    /// snapshot is taken in the middle of their writes.
+    fn lock_snapshots_for_write<'a>(
+        &self,
+        keys: impl IntoIterator<Item = (&'static str, &'a [u8])>,
+    ) -> Result<SnapshotsWriteGuard, Error> {
+        let snapshots = self.snapshots.read().map_err(|e| Error::GuardPoison {
+            error: format!("{}", e),
+        })?;
+        if snapshots.saved.is_empty() {
+            return Ok(SnapshotsWriteGuard::NoSnapshot { _guard: snapshots });
+        }
+        drop(snapshots);
+
+        let mut snapshots = self.snapshots.write().map_err(|e| Error::GuardPoison {
+            error: format!("{}", e),
+        })?;
+        for (column, key) in keys {
+            let mut current_value = None;
+            for columns in snapshots.saved.values_mut() {
+                let values = columns.entry(column).or_default();
+                if values.contains_key(key) {
+                    continue;
+                }
+                let value = match &current_value {
+                    Some(value) => Option::clone(value),
+                    None => {
+                        let value = self.get_tree(column)?.get(key)?.map(|v| v.to_vec());
+                        current_value = Some(value.clone());
+                        value
+                    }
+                };
+                values.insert(key.to_vec(), value);
+            }
+        }
+        Ok(SnapshotsWriteGuard::Saved { _guard: snapshots })
+    }
+
     pub fn get_tree(&self, name: &'static str) -> Result<Tree, Error> {
         let tree = self.db.open_tree(name).map_err(Error::from)?;
 
-        // TODO - TE-498: refactor - SledBackend should be universal, this should be pass here by "some cfg"
-        if name == OperationsMetaStorage::column_name() {
-            tree.set_merge_operator(operations_meta_storage::merge_meta_value_sled)
-        }
-        // TODO - TE-498: refactor - SledBackend should be universal, this should be pass here by "some cfg"
-        if name == BlockMetaStorage::column_name() {
-            tree.set_merge_operator(block_meta_storage::merge_meta_value_sled)
+        if let Some(merge_function) = Self::merge_function(name) {
+            tree.set_merge_operator(merge_function)
         }
 
         Ok(tree)
     }
+
+    // TODO - TE-498: refactor - SledBackend should be universal, this should be pass here by "some cfg"
+    fn merge_function(name: &'static str) -> Option<SledMergeFunction> {
+        if name == OperationsMetaStorage::column_name() {
+            Some(operations_meta_storage::merge_meta_value_sled)
+        } else if name == BlockMetaStorage::column_name() {
+            Some(block_meta_storage::merge_meta_value_sled)
+        } else {
+            None
+        }
+    }
 }
 
 impl TezedgeDatabaseBackendStore for SledDBBackend {
@@ -58,6 +145,7 @@ impl TezedgeDatabaseBackendStore for SledDBBackend {
 
         let timer = Instant::now();
 
+        let _snapshots = self.lock_snapshots_for_write([(column, key)])?;
         let tree = self.get_tree(column)?;
         let _ = tree.insert(key, value).map_err(Error::from)?;
 
@@ -70,6 +158,7 @@ impl TezedgeDatabaseBackendStore for SledDBBackend {
     }
 
     fn delete(&self, column: &'static str, key: &[u8]) -> Result<(), Error> {
+        let _snapshots = self.lock_snapshots_for_write([(column, key)])?;
         let tree = self.get_tree(column)?;
         let _ = tree.remove(key).map_err(Error::from)?;
         Ok(())
@@ -82,6 +171,7 @@ impl TezedgeDatabaseBackendStore for SledDBBackend {
 
         let timer = Instant::now();
 
+        let _snapshots = self.lock_snapshots_for_write([(column, key)])?;
         let tree = self.get_tree(column)?;
         let _ = tree.merge(key, value).map_err(Error::from)?;
 
@@ -123,12 +213,90 @@ impl TezedgeDatabaseBackendStore for SledDBBackend {
         column: &'static str,
         batch: Vec<(Vec<u8>, Vec<u8>)>,
     ) -> Result<(), Error> {
+        let mut stats = self.column_stats.write().map_err(|e| Error::GuardPoison {
+            error: format!("{}", e),
+        })?;
+
+        let timer = Instant::now();
+        let writes = batch.len() as u64;
+
+        let _snapshots =
+            self.lock_snapshots_for_write(batch.iter().map(|(k, _)| (column, k.as_slice())))?;
         let mut sled_batch = sled::Batch::default();
         let tree = self.get_tree(column)?;
         for (k, v) in batch {
             sled_batch.insert(k, v)
         }
-        tree.apply_batch(sled_batch).map_err(Error::from)
+        tree.apply_batch(sled_batch).map_err(Error::from)?;
+
+        let total_write_duration = timer.elapsed();
+        let mut stat = stats.entry(column).or_insert_with(Default::default);
+        stat.total_write_duration += total_write_duration;
+        stat.total_writes += writes;
+        Ok(())
+    }
+
+    fn write_multi_column_batch(&self, batch: BackendWriteBatch) -> Result<(), Error> {
+        // every operation is applied to the tree at `tree_indexes[i]`
+        let mut columns: Vec<&'static str> = Vec::new();
+        let mut tree_indexes = Vec::with_capacity(batch.len());
+        for operation in batch.iter() {
+            let index = match columns.iter().position(|c| *c == operation.column()) {
+                Some(index) => index,
+                None => {
+                    columns.push(operation.column());
+                    columns.len() - 1
+                }
+            };
+            tree_indexes.push(index);
+        }
+        let trees = columns
+            .iter()
+            .map(|column| self.get_tree(column))
+            .collect::<Result<Vec<Tree>, Error>>()?;
+
+        let _snapshots = self.lock_snapshots_for_write(
+            batch
+                .iter()
+                .map(|operation| (operation.column(), operation.key())),
+        )?;
+
+        // Merge operators are not applied inside of a transaction,
+        // the merge is done here by reading the current value.
+        let result: TransactionResult<(), Error> = trees.as_slice().transaction(|trees| {
+            for (operation, index) in batch.iter().zip(tree_indexes.iter()) {
+                let tree = &trees[*index];
+
+                match operation {
+                    BackendBatchOperation::Put { key, value, .. } => {
+                        tree.insert(key.as_slice(), value.as_slice())?;
+                    }
+                    BackendBatchOperation::Merge { column, key, value } => {
+                        match Self::merge_function(column) {
+                            Some(merge_function) => {
+                                let existing = tree.get(key.as_slice())?;
+                                match merge_function(key, existing.as_deref(), value) {
+                                    Some(merged) => tree.insert(key.as_slice(), merged)?,
+                                    None => tree.remove(key.as_slice())?,
+                                };
+                            }
+                            None => {
+                                tree.insert(key.as_slice(), value.as_slice())?;
+                            }
+                        }
+                    }
+                    BackendBatchOperation::Delete { key, .. } => {
+                        tree.remove(key.as_slice())?;
+                    }
+                }
+            }
+            Ok(())
+        });
+
+        result.map_err(|error| match error {
+            TransactionError::Abort(error) => error,
+            TransactionError::Storage(error) => Error::from(error),
+        })
     }
 
     fn flush(&self) -> Result<usize, Error> {
@@ -189,10 +357,252 @@ impl TezedgeDatabaseBackendStore for SledDBBackend {
         };
         stats.clone()
     }
+
+    fn snapshot(&self) -> Result<Box<BackendSnapshotReader>, Error> {
+        let mut snapshots = self.snapshots.write().map_err(|e| Error::GuardPoison {
+            error: format!("{}", e),
+        })?;
+        let generation = snapshots.next_generation;
+        snapshots.next_generation += 1;
+        snapshots.saved.insert(generation, HashMap::new());
+
+        Ok(Box::new(SledDBSnapshot {
+            generation,
+            db: self.db.clone(),
+            snapshots: self.snapshots.clone(),
+        }))
+    }
 }
 
 impl TezdegeDatabaseBackendKV for SledDBBackend {}
 
+/// Point-in-time view of the database: the current values, except the keys
+/// written since the snapshot was taken, whose previous values were saved
+pub struct SledDBSnapshot {
+    generation: u64,
+    db: sled::Db,
+    snapshots: Arc<RwLock<SledSnapshots>>,
+}
+
+impl SledDBSnapshot {
+    fn read_snapshots(&self) -> Result<RwLockReadGuard<SledSnapshots>, Error> {
+        self.snapshots.read().map_err(|e| Error::GuardPoison {
+            error: format!("{}", e),
+        })
+    }
+}
+
+impl Drop for SledDBSnapshot {
+    fn drop(&mut self) {
+        if let Ok(mut snapshots) = self.snapshots.write() {
+            snapshots.saved.remove(&self.generation);
+        }
+    }
+}
+
+impl BackendSnapshot for SledDBSnapshot {
+    fn get(&self, column: &'static str, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        // writers save the current value before writing, with the lock held
+        let snapshots = self.read_snapshots()?;
+        if let Some(value) = snapshots
+            .saved_values(self.generation, column)
+            .and_then(|values| values.get(key))
+        {
+            return Ok(value.clone());
+        }
+
+        let tree = self.db.open_tree(column)?;
+        let value = tree.get(key)?;
+        Ok(value.map(|v| v.to_vec()))
+    }
+
+    fn contains(&self, column: &'static str, key: &[u8]) -> Result<bool, Error> {
+        self.get(column, key).map(|value| value.is_some())
+    }
+
+    fn find<'a>(
+        &'a self,
+        column: &'static str,
+        mode: BackendIteratorMode,
+    ) -> Result<BackendIterator<'a>, Error> {
+        let mode = match mode {
+            BackendIteratorMode::Start => SledDBIteratorMode::Start,
+            BackendIteratorMode::End => SledDBIteratorMode::End,
+            BackendIteratorMode::From(key, direction) => {
+                SledDBIteratorMode::From(IVec::from(key), direction)
+            }
+        };
+        let iter = SledDBSnapshotIterator::new(self, column, mode)?;
+
+        Ok(Box::new(iter.map(|result| {
+            result.map(|(k, v)| (k.into_boxed_slice(), v.into_boxed_slice()))
+        })))
+    }
+
+    fn find_by_prefix<'a>(
+        &'a self,
+        column: &'static str,
+        key: &Vec<u8>,
+        max_key_len: usize,
+    ) -> Result<BackendIterator<'a>, Error> {
+        let prefix_key = key[..max_key_len].to_vec();
+        let iter = SledDBSnapshotIterator::new(
+            self,
+            column,
+            SledDBIteratorMode::Prefix(IVec::from(prefix_key)),
+        )?;
+
+        Ok(Box::new(iter.map(|result| {
+            result.map(|(k, v)| (k.into_boxed_slice(), v.into_boxed_slice()))
+        })))
+    }
+}
+
+/// Iterates over the current values of the tree, replaced by the values saved for the
+/// snapshot. Saved values of keys removed since the snapshot are inserted in order.
+struct SledDBSnapshotIterator<'a> {
+    snapshot: &'a SledDBSnapshot,
+    column: &'static str,
+    mode: SledDBIteratorMode,
+    iter: SledDBIterator,
+    iter_done: bool,
+    last_key: Option<Vec<u8>>,
+    pending: VecDeque<(Vec<u8>, Vec<u8>)>,
+}
+
+impl<'a> SledDBSnapshotIterator<'a> {
+    fn new(
+        snapshot: &'a SledDBSnapshot,
+        column: &'static str,
+        mode: SledDBIteratorMode,
+    ) -> Result<Self, Error> {
+        let tree = snapshot.db.open_tree(column)?;
+        Ok(Self {
+            snapshot,
+            column,
+            mode: mode.clone(),
+            iter: SledDBIterator::new(mode, tree),
+            iter_done: false,
+            last_key: None,
+            pending: VecDeque::new(),
+        })
+    }
+
+    fn is_reverse(&self) -> bool {
+        matches!(
+            self.mode,
+            SledDBIteratorMode::End | SledDBIteratorMode::From(_, Direction::Reverse)
+        )
+    }
+
+    /// Saved values of the keys between the last returned key and `until`, in the
+    /// order of the iteration
+    fn saved_values_until(
+        &self,
+        snapshots: &SledSnapshots,
+        until: Option<&[u8]>,
+    ) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let values = match snapshots.saved_values(self.snapshot.generation, self.column) {
+            Some(values) => values,
+            None => return Vec::new(),
+        };
+
+        let (mode_lower, mode_upper) = match &self.mode {
+            SledDBIteratorMode::Start | SledDBIteratorMode::End => {
+                (Bound::Unbounded, Bound::Unbounded)
+            }
+            SledDBIteratorMode::From(key, Direction::Forward) => {
+                (Bound::Included(key.as_ref()), Bound::Unbounded)
+            }
+            SledDBIteratorMode::From(key, Direction::Reverse) => {
+                (Bound::Unbounded, Bound::Included(key.as_ref()))
+            }
+            SledDBIteratorMode::Prefix(prefix) => {
+                (Bound::Included(prefix.as_ref()), Bound::Unbounded)
+            }
+        };
+        let last = self.last_key.as_deref().map(Bound::Excluded);
+        let until = until.map(Bound::Excluded);
+        let (lower, upper) = if self.is_reverse() {
+            (until.unwrap_or(mode_lower), last.unwrap_or(mode_upper))
+        } else {
+            (last.unwrap_or(mode_lower), until.unwrap_or(mode_upper))
+        };
+        if is_empty_range(lower, upper) {
+            return Vec::new();
+        }
+
+        let range = values
+            .range::<[u8], _>((lower, upper))
+            .filter_map(|(k, v)| v.as_ref().map(|v| (k.clone(), v.clone())));
+        let mut saved: Vec<_> = if self.is_reverse() {
+            range.rev().collect()
+        } else {
+            range.collect()
+        };
+        if let SledDBIteratorMode::Prefix(prefix) = &self.mode {
+            saved.retain(|(k, _)| k.starts_with(prefix));
+        }
+        saved
+    }
+}
+
+fn is_empty_range(lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> bool {
+    match (lower, upper) {
+        (Bound::Included(lower), Bound::Included(upper)) => lower > upper,
+        (Bound::Included(lower), Bound::Excluded(upper))
+        | (Bound::Excluded(lower), Bound::Included(upper))
+        | (Bound::Excluded(lower), Bound::Excluded(upper)) => lower >= upper,
+        _ => false,
+    }
+}
+
+impl<'a> Iterator for SledDBSnapshotIterator<'a> {
+    type Item = Result<(Vec<u8>, Vec<u8>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((key, value)) = self.pending.pop_front() {
+                self.last_key = Some(key.clone());
+                return Some(Ok((key, value)));
+            }
+            if self.iter_done {
+                return None;
+            }
+
+            let current = match self.iter.next() {
+                Some(Ok(current)) => Some(current),
+                Some(Err(error)) => return Some(Err(error)),
+                None => {
+                    self.iter_done = true;
+                    None
+                }
+            };
+
+            let snapshots = match self.snapshot.read_snapshots() {
+                Ok(snapshots) => snapshots,
+                Err(error) => return Some(Err(error)),
+            };
+            let saved =
+                self.saved_values_until(&snapshots, current.as_ref().map(|(k, _)| k.as_slice()));
+            self.pending.extend(saved);
+
+            if let Some((key, value)) = current {
+                match snapshots
+                    .saved_values(self.snapshot.generation, self.column)
+                    .and_then(|values| values.get(&key))
+                {
+                    // written since the snapshot
+                    Some(Some(saved_value)) => self.pending.push_back((key, saved_value.clone())),
+                    // created since the snapshot
+                    Some(None) => (),
+                    None => self.pending.push_back((key, value)),
+                }
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum SledDBIteratorMode {
     Start,
@@ -0,0 +1,130 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Read-only backend over a [`BackendSnapshot`], so a snapshot can be used as a
+//! [`TezedgeDatabase`](crate::database::tezedge_database::TezedgeDatabase) by all storages.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::database::backend::{
+    BackendIterator, BackendIteratorMode, BackendSnapshot, BackendSnapshotReader,
+    BackendWriteBatch, DBStats, TezedgeDatabaseBackendStore,
+};
+use crate::database::error::Error;
+use crate::database::tezedge_database::TezdegeDatabaseBackendKV;
+
+/// Every write fails with [`Error::ReadOnlySnapshot`]
+#[derive(Clone)]
+pub struct SnapshotBackend {
+    snapshot: Arc<BackendSnapshotReader>,
+}
+
+impl SnapshotBackend {
+    pub fn new(snapshot: Box<BackendSnapshotReader>) -> Self {
+        Self {
+            snapshot: Arc::from(snapshot),
+        }
+    }
+}
+
+impl TezdegeDatabaseBackendKV for SnapshotBackend {}
+
+impl TezedgeDatabaseBackendStore for SnapshotBackend {
+    fn put(&self, _column: &'static str, _key: &[u8], _value: &[u8]) -> Result<(), Error> {
+        Err(Error::ReadOnlySnapshot)
+    }
+
+    fn delete(&self, _column: &'static str, _key: &[u8]) -> Result<(), Error> {
+        Err(Error::ReadOnlySnapshot)
+    }
+
+    fn merge(&self, _column: &'static str, _key: &[u8], _value: &[u8]) -> Result<(), Error> {
+        Err(Error::ReadOnlySnapshot)
+    }
+
+    fn get(&self, column: &'static str, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.snapshot.get(column, key)
+    }
+
+    fn contains(&self, column: &'static str, key: &[u8]) -> Result<bool, Error> {
+        self.snapshot.contains(column, key)
+    }
+
+    fn write_batch(
+        &self,
+        _column: &'static str,
+        _batch: Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> Result<(), Error> {
+        Err(Error::ReadOnlySnapshot)
+    }
+
+    fn write_multi_column_batch(&self, _batch: BackendWriteBatch) -> Result<(), Error> {
+        Err(Error::ReadOnlySnapshot)
+    }
+
+    fn flush(&self) -> Result<usize, Error> {
+        Ok(0)
+    }
+
+    fn size(&self) -> HashMap<&'static str, usize> {
+        HashMap::new()
+    }
+
+    fn sync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn find<'a>(
+        &'a self,
+        column: &'static str,
+        mode: BackendIteratorMode,
+    ) -> Result<BackendIterator<'a>, Error> {
+        self.snapshot.find(column, mode)
+    }
+
+    fn find_by_prefix<'a>(
+        &'a self,
+        column: &'static str,
+        key: &Vec<u8>,
+        max_key_len: usize,
+    ) -> Result<BackendIterator<'a>, Error> {
+        self.snapshot.find_by_prefix(column, key, max_key_len)
+    }
+
+    fn column_stats(&self) -> HashMap<&'static str, DBStats> {
+        HashMap::new()
+    }
+
+    /// A snapshot of a snapshot is the same point in time
+    fn snapshot(&self) -> Result<Box<BackendSnapshotReader>, Error> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+impl BackendSnapshot for SnapshotBackend {
+    fn get(&self, column: &'static str, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.snapshot.get(column, key)
+    }
+
+    fn contains(&self, column: &'static str, key: &[u8]) -> Result<bool, Error> {
+        self.snapshot.contains(column, key)
+    }
+
+    fn find<'a>(
+        &'a self,
+        column: &'static str,
+        mode: BackendIteratorMode,
+    ) -> Result<BackendIterator<'a>, Error> {
+        self.snapshot.find(column, mode)
+    }
+
+    fn find_by_prefix<'a>(
+        &'a self,
+        column: &'static str,
+        key: &Vec<u8>,
+        max_key_len: usize,
+    ) -> Result<BackendIterator<'a>, Error> {
+        self.snapshot.find_by_prefix(column, key, max_key_len)
+    }
+}
@@ -1,11 +1,15 @@
 // Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
 // SPDX-License-Identifier: MIT
 
-use crate::database::backend::{BackendIteratorMode, DBStats, TezedgeDatabaseBackendStore};
+use crate::database::backend::{
+    BackendBatchOperation, BackendIteratorMode, BackendWriteBatch, DBStats,
+    TezedgeDatabaseBackendStore,
+};
 use crate::database::edgekv_backend::EdgeKVBackend;
 use crate::database::error::Error;
 use crate::database::rockdb_backend::RocksDBBackend;
 use crate::database::sled_backend::SledDBBackend;
+use crate::database::snapshot::SnapshotBackend;
 use crate::persistent::{Decoder, Encoder, KeyValueSchema, SchemaError};
 use crate::IteratorMode;
 use serde::{Deserialize, Serialize};
@@ -66,6 +70,61 @@ pub trait KVStore<S: KeyValueSchema> {
     fn flush(&self) -> Result<(), Error>;
 }
 
+/// Writes to several schemas (columns), committed at once with [`KVStoreWithWriteBatch::write`]
+#[derive(Default)]
+pub struct WriteBatch {
+    operations: BackendWriteBatch,
+}
+
+impl WriteBatch {
+    pub fn put<S: KVStoreKeyValueSchema>(
+        &mut self,
+        key: &S::Key,
+        value: &S::Value,
+    ) -> Result<(), Error> {
+        self.operations.push(BackendBatchOperation::Put {
+            column: S::column_name(),
+            key: key.encode()?,
+            value: value.encode()?,
+        });
+        Ok(())
+    }
+
+    pub fn merge<S: KVStoreKeyValueSchema>(
+        &mut self,
+        key: &S::Key,
+        value: &S::Value,
+    ) -> Result<(), Error> {
+        self.operations.push(BackendBatchOperation::Merge {
+            column: S::column_name(),
+            key: key.encode()?,
+            value: value.encode()?,
+        });
+        Ok(())
+    }
+
+    pub fn delete<S: KVStoreKeyValueSchema>(&mut self, key: &S::Key) -> Result<(), Error> {
+        self.operations.push(BackendBatchOperation::Delete {
+            column: S::column_name(),
+            key: key.encode()?,
+        });
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+}
+
+pub trait KVStoreWithWriteBatch {
+    /// Write all operations of `batch` atomically, whatever their columns
+    fn write(&self, batch: WriteBatch) -> Result<(), Error>;
+}
+
 pub trait KVStoreWithSchemaIterator<S: KeyValueSchema> {
     fn find<'a>(&'a self, mode: IteratorMode<'a, S>) -> Result<BackendIterator<'a>, Error>;
 
@@ -139,7 +198,7 @@ pub trait TezdegeDatabaseBackendKV: TezedgeDatabaseBackendStore {}
 pub type TezedgeDatabaseBackend = dyn TezdegeDatabaseBackendKV + Send + Sync;
 
 pub trait TezedgeDatabaseWithIterator<S: KVStoreKeyValueSchema>:
-    KVStore<S> + KVStoreWithSchemaIterator<S>
+    KVStore<S> + KVStoreWithSchemaIterator<S> + KVStoreWithWriteBatch
 {
 }
 
@@ -148,6 +207,8 @@ impl<S: KVStoreKeyValueSchema> TezedgeDatabaseWithIterator<S> for TezedgeDatabas
 pub struct TezedgeDatabase {
     backend: Arc<TezedgeDatabaseBackend>,
     log: slog::Logger,
+    /// Set for snapshots, which have nothing to flush
+    read_only: bool,
 }
 
 impl TezedgeDatabase {
@@ -156,14 +217,17 @@ impl TezedgeDatabase {
             TezedgeDatabaseBackendOptions::SledDB(backend) => TezedgeDatabase {
                 backend: Arc::new(backend),
                 log: log.clone(),
+                read_only: false,
             },
             TezedgeDatabaseBackendOptions::RocksDB(backend) => TezedgeDatabase {
                 backend: Arc::new(backend),
                 log: log.clone(),
+                read_only: false,
             },
             TezedgeDatabaseBackendOptions::EdgeKV(backend) => TezedgeDatabase {
                 backend: Arc::new(backend),
                 log: log.clone(),
+                read_only: false,
             },
         }
     }
@@ -172,11 +236,25 @@ impl TezedgeDatabase {
         self.backend.flush()
     }
 
+    /// Read-only database seeing all columns as they are now, unaffected by later writes.
+    ///
+    /// Writes to the returned database fail with [`Error::ReadOnlySnapshot`].
+    pub fn snapshot(&self) -> Result<TezedgeDatabase, Error> {
+        Ok(TezedgeDatabase {
+            backend: Arc::new(SnapshotBackend::new(self.backend.snapshot()?)),
+            log: self.log.clone(),
+            read_only: true,
+        })
+    }
+
     pub fn db_stats(&self) -> HashMap<&'static str, DBStats> {
         self.backend.column_stats()
     }
 
     pub fn flush_checked(&self) {
+        if self.read_only {
+            return;
+        }
         match self.flush() {
             Err(e) => {
                 slog::error!(&self.log, "Failed to flush database"; "reason" => format!("{:?}", e))
@@ -246,6 +324,15 @@ impl<S: KVStoreKeyValueSchema> KVStore<S> for TezedgeDatabase {
     }
 }
 
+impl KVStoreWithWriteBatch for TezedgeDatabase {
+    fn write(&self, batch: WriteBatch) -> Result<(), Error> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.backend.write_multi_column_batch(batch.operations)
+    }
+}
+
 impl TezedgeDatabase {
     pub fn size(&self) -> HashMap<&'static str, usize> {
         self.backend.size()
@@ -40,7 +40,7 @@ use crate::commit_log::{CommitLogError, CommitLogs};
 pub use crate::constants_storage::ConstantsStorage;
 pub use crate::cycle_eras_storage::CycleErasStorage;
 pub use crate::cycle_storage::CycleMetaStorage;
-use crate::database::tezedge_database::TezedgeDatabase;
+use crate::database::tezedge_database::{TezedgeDatabase, WriteBatch};
 pub use crate::mempool_storage::{MempoolStorage, MempoolStorageKV};
 pub use crate::operations_meta_storage::{OperationsMetaStorage, OperationsMetaStorageKV};
 pub use crate::operations_storage::{
@@ -416,6 +416,9 @@ fn resolve_block_data(
 }
 
 /// Stores apply result to storage and mark block as applied, if everythnig is ok.
+///
+/// All the writes to the main database are done in a single atomic batch,
+/// so a crash cannot leave the block half stored.
 pub fn store_applied_block_result(
     chain_meta_storage: &ChainMetaStorage,
     block_storage: &BlockStorage,
@@ -434,7 +437,8 @@ pub fn store_applied_block_result(
         block_result.block_header_proto_metadata_bytes,
         block_result.operations_proto_metadata_bytes,
     );
-    block_storage.put_block_json_data(block_hash, block_json_data)?;
+    let mut batch = WriteBatch::default();
+    block_storage.put_block_json_data_to_batch(&mut batch, block_hash, block_json_data)?;
 
     // store additional data
     let block_additional_data = BlockAdditionalData::new(
@@ -460,41 +464,55 @@ pub fn store_applied_block_result(
         },
         block_result.ops_metadata_hashes,
     );
-    block_meta_storage.put_block_additional_data(block_hash, &block_additional_data)?;
+    block_meta_storage.put_block_additional_data_to_batch(
+        &mut batch,
+        block_hash,
+        &block_additional_data,
+    )?;
 
     // TODO: check context checksum or context_hash
 
     // populate predecessor storage
-    block_meta_storage.store_predecessors(block_hash, block_metadata)?;
+    block_meta_storage.store_predecessors_to_batch(&mut batch, block_hash, block_metadata)?;
 
     // populate cycle data if is present in the response
     for cycle_data in block_result.cycle_rolls_owner_snapshots.into_iter() {
-        cycle_meta_storage.store_cycle_data(cycle_data)?;
+        cycle_meta_storage.store_cycle_data_to_batch(&mut batch, cycle_data)?;
     }
 
     // store new constants if they are present
     if let Some(constants) = block_result.new_protocol_constants_json {
-        constants_storage
-            .store_constants_data(block_result.next_protocol_hash.clone(), constants)?;
+        constants_storage.store_constants_data_to_batch(
+            &mut batch,
+            block_result.next_protocol_hash.clone(),
+            constants,
+        )?;
     }
 
     // store new cycle eras if they are present
     if let Some(new_cycle_eras) = block_result.new_cycle_eras_json {
-        cycle_eras_storage
-            .store_cycle_eras_data(block_result.next_protocol_hash.clone(), new_cycle_eras)?;
+        cycle_eras_storage.store_cycle_eras_data_to_batch(
+            &mut batch,
+            block_result.next_protocol_hash.clone(),
+            new_cycle_eras,
+        )?;
     }
 
     // if everything is stored and ok, we can considere this block as applied
     // mark current head as applied
     block_metadata.set_is_applied(true);
-    block_meta_storage.put(block_hash, block_metadata)?;
+    block_meta_storage.put_to_batch(&mut batch, block_hash, block_metadata)?;
 
     // TODO(zura): maybe move to separate storage call.
-    chain_meta_storage.set_current_head(
+    chain_meta_storage.set_current_head_to_batch(
+        &mut batch,
         block_metadata.chain_id(),
         Head::new(block_hash.clone(), block_metadata.level(), block_fitness),
     )?;
 
+    // write everything at once
+    block_meta_storage.write_batch(batch)?;
+
     // Flush to disk
     block_storage.flush()?;
 
@@ -847,6 +865,18 @@ impl PersistentStorage {
         self.seq.clone()
     }
 
+    /// Storage reading the main db as it is now, for consistent reads across several columns.
+    ///
+    /// The commit log is shared: it is append-only, so entries referenced
+    /// from the snapshot never change.
+    pub fn snapshot(&self) -> Result<Self, StorageError> {
+        Ok(Self {
+            main_db: Arc::new(self.main_db.snapshot()?),
+            clog: self.clog.clone(),
+            seq: self.seq.clone(),
+        })
+    }
+
     pub fn flush_dbs(&mut self) {
         if Arc::strong_count(&self.clog) == 1 {
             self.clog.flush_checked();
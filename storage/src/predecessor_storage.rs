@@ -9,7 +9,9 @@ use serde::{Deserialize, Serialize};
 use crypto::hash::BlockHash;
 
 use crate::block_meta_storage::Meta;
-use crate::database::tezedge_database::{KVStoreKeyValueSchema, TezedgeDatabaseWithIterator};
+use crate::database::tezedge_database::{
+    KVStoreKeyValueSchema, TezedgeDatabaseWithIterator, WriteBatch,
+};
 use crate::persistent::database::{default_table_options, RocksDbKeyValueSchema};
 use crate::persistent::{BincodeEncoded, KeyValueSchema};
 use crate::{PersistentStorage, StorageError};
@@ -49,6 +51,27 @@ impl PredecessorStorage {
         block_hash: &BlockHash,
         block_meta: &Meta,
         stored_predecessors_size: u32,
+    ) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        self.store_predecessors_to_batch(
+            &mut batch,
+            block_hash,
+            block_meta,
+            stored_predecessors_size,
+        )?;
+        self.kv.write(batch).map_err(StorageError::from)
+    }
+
+    /// Adds the predecessors of `block_hash` to `batch`.
+    ///
+    /// Predecessors of the direct predecessor are read from storage, so they must
+    /// already be stored.
+    pub fn store_predecessors_to_batch(
+        &self,
+        batch: &mut WriteBatch,
+        block_hash: &BlockHash,
+        block_meta: &Meta,
+        stored_predecessors_size: u32,
     ) -> Result<(), StorageError> {
         if let Some(direct_predecessor) = block_meta.predecessor() {
             // genesis
@@ -56,7 +79,7 @@ impl PredecessorStorage {
                 return Ok(());
             } else {
                 // put the direct predecessor to slot 0
-                self.put(
+                batch.put::<Self>(
                     &PredecessorKey::new(block_hash.clone(), 0),
                     direct_predecessor,
                 )?;
@@ -69,7 +92,7 @@ impl PredecessorStorage {
                     if let Some(p) = self.get(&predecessor_key)? {
                         let key =
                             PredecessorKey::new(block_hash.clone(), predecessor_exponent_slot);
-                        self.put(&key, &p)?;
+                        batch.put::<Self>(&key, &p)?;
                         predecessor = p;
                     } else {
                         return Ok(());
@@ -29,6 +29,11 @@ impl ShellAutomatonActionStorage {
         }
     }
 
+    /// Storage on a main database opened without [`PersistentStorage`], by offline tools.
+    pub fn from_kv(kv: Arc<ShellAutomatonActionIndexStorageKV>) -> Self {
+        Self { kv }
+    }
+
     #[inline]
     pub fn put<T>(&self, action_id: &u64, action: &T) -> Result<(), StorageError>
     where
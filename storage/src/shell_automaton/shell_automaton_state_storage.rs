@@ -29,6 +29,11 @@ impl ShellAutomatonStateStorage {
         }
     }
 
+    /// Storage on a main database opened without [`PersistentStorage`], by offline tools.
+    pub fn from_kv(kv: Arc<ShellAutomatonStateIndexStorageKV>) -> Self {
+        Self { kv }
+    }
+
     #[inline]
     pub fn put<T>(&self, action_id: &u64, state_snapshot: &T) -> Result<(), StorageError>
     where
@@ -68,6 +73,23 @@ impl ShellAutomatonStateStorage {
             .map(|res| Ok(T::decode(&res?.1)?))
             .transpose()
     }
+
+    /// Get closest state snapshot, where `state.last_action.id` >= `action_id`.
+    #[inline]
+    pub fn get_closest_after<T>(&self, action_id: &u64) -> Result<Option<T>, StorageError>
+    where
+        T: Decoder,
+    {
+        self.kv
+            .find(IteratorMode::From(
+                Cow::Borrowed(action_id),
+                Direction::Forward,
+            ))?
+            .take(1)
+            .next()
+            .map(|res| Ok(T::decode(&res?.1)?))
+            .transpose()
+    }
 }
 
 impl KeyValueSchema for ShellAutomatonStateStorage {
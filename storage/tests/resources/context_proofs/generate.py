@@ -0,0 +1,126 @@
+#!/usr/bin/env python3
+# Generates the `Context.Proof` fixtures of `test_octez_tree_proofs`.
+#
+# The proofs are built here from small trees, independently of the `tezos_context` crate:
+# hashes follow the Irmin tree hashing used by Tezos, the binary follows the Octez
+# `Merkle_proof_encoding.V1.Tree32` encoding.
+#
+# For each proof, `<name>.bin` is the encoded proof, `<name>.json` the proven key and
+# its value (hex, `null` when the proof shows the key is absent).
+#
+# usage: generate.py
+
+import hashlib
+import json
+import os
+import struct
+
+
+def blake2b(data):
+    return hashlib.blake2b(data, digest_size=32).digest()
+
+
+def leb128(n):
+    out = b''
+    while True:
+        byte = n & 0x7F
+        n >>= 7
+        if n:
+            out += bytes([byte | 0x80])
+        else:
+            return out + bytes([byte])
+
+
+# a tree is ('value', bytes), ('blinded_value', hash), ('node', [(step, tree)]) or
+# ('blinded_node', hash)
+
+def kinded_hash(tree):
+    kind, content = tree
+    if kind == 'value':
+        return 'value', blake2b(struct.pack('>Q', len(content)) + content)
+    if kind == 'blinded_value':
+        return 'value', content
+    if kind == 'node':
+        data = struct.pack('>Q', len(content))
+        for step, child in sorted(content):
+            child_kind, child_hash = kinded_hash(child)
+            data += b'\xff' + b'\0' * 7 if child_kind == 'value' else b'\0' * 8
+            data += leb128(len(step)) + step.encode()
+            data += struct.pack('>Q', 32) + child_hash
+        return 'node', blake2b(data)
+    if kind == 'blinded_node':
+        return 'node', content
+    raise ValueError(kind)
+
+
+def dynamic(data):
+    return struct.pack('>I', len(data)) + data
+
+
+def encode_tree(tree):
+    kind, content = tree
+    if kind == 'value':
+        return b'\x00' + dynamic(content)
+    if kind == 'blinded_value':
+        return b'\x01' + content
+    if kind == 'node':
+        entries = b''.join(
+            bytes([len(step)]) + step.encode() + encode_tree(child)
+            for step, child in sorted(content)
+        )
+        return b'\x02' + dynamic(entries)
+    if kind == 'blinded_node':
+        return b'\x03' + content
+    raise ValueError(kind)
+
+
+def encode_proof(tree):
+    kind, root = kinded_hash(tree)
+    hash_ = (b'\x00' if kind == 'value' else b'\x01') + root
+    # version 0: 32-ary tree, not a stream
+    return struct.pack('>H', 0) + hash_ + hash_ + encode_tree(tree)
+
+
+def blinded_value(seed):
+    return 'blinded_value', blake2b(b'value ' + seed.encode())
+
+
+def blinded_node(seed):
+    return 'blinded_node', blake2b(b'node ' + seed.encode())
+
+
+votes = ('node', [
+    ('data', ('node', [
+        ('contracts', blinded_node('contracts')),
+        ('votes', ('node', [
+            ('current_period', ('value', bytes.fromhex('0000000300000001'))),
+            ('listings', blinded_node('listings')),
+            ('participation_ema', blinded_value('participation_ema')),
+        ])),
+    ])),
+    ('protocol', blinded_value('protocol')),
+])
+
+protocol = ('node', [
+    ('data', blinded_node('data')),
+    ('protocol', ('value', bytes.fromhex(
+        '3ea1efd8e8fac4a8d9a8b71ba4bf93a3d3fe1ff3e7d4d2c6d8bf9d4e2fb6f0ab'))),
+])
+
+fixtures = {
+    'votes_current_period': (votes, 'data/votes/current_period', '0000000300000001'),
+    'votes_absent': (votes, 'data/votes/current_proposal', None),
+    'protocol': (
+        protocol,
+        'protocol',
+        '3ea1efd8e8fac4a8d9a8b71ba4bf93a3d3fe1ff3e7d4d2c6d8bf9d4e2fb6f0ab',
+    ),
+}
+
+directory = os.path.dirname(os.path.abspath(__file__))
+for name, (tree, key, value) in fixtures.items():
+    with open(os.path.join(directory, name + '.bin'), 'wb') as f:
+        f.write(encode_proof(tree))
+    with open(os.path.join(directory, name + '.json'), 'w') as f:
+        json.dump({'key': key, 'value': value}, f, indent=2)
+        f.write('\n')
@@ -12,6 +12,7 @@ use slog::{Drain, Level, Logger};
 
 use crypto::hash::{chain_id_from_block_hash, BlockHash, ContextHash, ProtocolHash};
 use storage::chain_meta_storage::ChainMetaStorageReader;
+use storage::database::tezedge_database::WriteBatch;
 use storage::tests_common::TmpStorage;
 use storage::*;
 use tezos_api::environment::TezosEnvironmentConfiguration;
@@ -69,6 +70,7 @@ fn test_storage() -> Result<(), Error> {
             backend: ContextKvStoreConfiguration::InMem(TezosContextTezedgeOnDiskBackendOptions {
                 base_path: tmp_storage_dir.to_str().unwrap().to_string(),
                 startup_check: false,
+                key_history: false,
             }),
             ipc_socket_path: None,
         },
@@ -303,6 +305,83 @@ fn test_storage() -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+fn test_write_batch_across_storages() -> Result<(), Error> {
+    let tmp_storage = TmpStorage::create(test_storage_dir_path("__write_batch_across_storages"))?;
+    let block_meta_storage = BlockMetaStorage::new(tmp_storage.storage());
+    let chain_meta_storage = ChainMetaStorage::new(tmp_storage.storage());
+    let constants_storage = ConstantsStorage::new(tmp_storage.storage());
+
+    let block = make_test_block_header()?;
+    let chain_id = chain_id_from_block_hash(&block.hash)?;
+    let protocol_hash =
+        ProtocolHash::try_from("PtBMwNZT94N7gXKw4i273CKcSaBrrBnqnt3RATExNKr9KNX2USV")?;
+    let head = Head::new(
+        block.hash.clone(),
+        block.header.level(),
+        block.header.fitness().clone(),
+    );
+
+    let mut batch = WriteBatch::default();
+    constants_storage.store_constants_data_to_batch(
+        &mut batch,
+        protocol_hash.clone(),
+        "{}".to_string(),
+    )?;
+    chain_meta_storage.set_current_head_to_batch(&mut batch, &chain_id, head)?;
+
+    // nothing is written before the batch
+    assert!(constants_storage.get(&protocol_hash)?.is_none());
+    assert!(chain_meta_storage.get_current_head(&chain_id)?.is_none());
+
+    block_meta_storage.write_batch(batch)?;
+
+    assert_eq!(
+        constants_storage.get(&protocol_hash)?,
+        Some("{}".to_string())
+    );
+    assert_eq!(
+        chain_meta_storage
+            .get_current_head(&chain_id)?
+            .expect("Current head should be set")
+            .block_hash(),
+        &block.hash
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_ignores_later_writes() -> Result<(), Error> {
+    let tmp_storage = TmpStorage::create(test_storage_dir_path("__snapshot_ignores_later_writes"))?;
+    let constants_storage = ConstantsStorage::new(tmp_storage.storage());
+
+    let protocol_hash =
+        ProtocolHash::try_from("PtBMwNZT94N7gXKw4i273CKcSaBrrBnqnt3RATExNKr9KNX2USV")?;
+    constants_storage.store_constants_data(protocol_hash.clone(), "{}".to_string())?;
+
+    let snapshot = tmp_storage.storage().snapshot()?;
+    let snapshot_constants_storage = ConstantsStorage::new(&snapshot);
+
+    constants_storage.store_constants_data(protocol_hash.clone(), "{\"a\":1}".to_string())?;
+
+    assert_eq!(
+        snapshot_constants_storage.get(&protocol_hash)?,
+        Some("{}".to_string())
+    );
+    assert_eq!(
+        constants_storage.get(&protocol_hash)?,
+        Some("{\"a\":1}".to_string())
+    );
+
+    // snapshots are read-only
+    assert!(snapshot_constants_storage
+        .store_constants_data(protocol_hash, "{}".to_string())
+        .is_err());
+
+    Ok(())
+}
+
 fn make_test_block_header() -> Result<BlockHeaderWithHash, Error> {
     let message_bytes = hex::decode("00006d6e0102dd00defaf70c53e180ea148b349a6feb4795610b2abc7b07fe91ce50a90814000000005c1276780432bc1d3a28df9a67b363aa1638f807214bb8987e5f9c0abcbd69531facffd1c80000001100000001000000000800000000000c15ef15a6f54021cb353780e2847fb9c546f1d72c1dc17c3db510f45553ce501ce1de000000000003c762c7df00a856b8bfcaf0676f069f825ca75f37f2bee9fe55ba109cec3d1d041d8c03519626c0c0faa557e778cb09d2e0c729e8556ed6a7a518c84982d1f2682bc6aa753f")?;
     let block_header = BlockHeaderWithHash::new(BlockHeader::from_bytes(message_bytes)?)?;
@@ -68,6 +68,10 @@ pub struct TezosContextIrminStorageConfiguration {
 pub struct TezosContextTezedgeOnDiskBackendOptions {
     pub base_path: String,
     pub startup_check: bool,
+    /// Record the values changed by each applied block, to query the history of a key.
+    ///
+    /// Used by the persistent backend only.
+    pub key_history: bool,
 }
 
 // Must be in sync with ffi_config.ml
@@ -140,6 +144,7 @@ impl TezosContextStorageConfiguration {
                             TezosContextTezedgeOnDiskBackendOptions {
                                 base_path: data_dir,
                                 startup_check: false,
+                                key_history: false,
                             },
                         ),
                         ipc_socket_path: None,
@@ -162,6 +167,22 @@ impl TezosContextStorageConfiguration {
         }
     }
 
+    /// Returns the path of the TezEdge context when it is stored on disk
+    pub fn get_tezedge_on_disk_path(&self) -> Option<String> {
+        let tezedge = match self {
+            TezosContextStorageConfiguration::IrminOnly(_) => return None,
+            TezosContextStorageConfiguration::TezEdgeOnly(tezedge) => tezedge,
+            TezosContextStorageConfiguration::Both(_irmin, tezedge) => tezedge,
+        };
+
+        match &tezedge.backend {
+            ContextKvStoreConfiguration::OnDisk(options) => Some(options.base_path.clone()),
+            ContextKvStoreConfiguration::InMem(_) | ContextKvStoreConfiguration::ReadOnlyIpc => {
+                None
+            }
+        }
+    }
+
     pub fn tezedge_is_enabled(&self) -> bool {
         match self {
             TezosContextStorageConfiguration::IrminOnly(_) => false,
@@ -269,6 +290,84 @@ pub enum StringTreeObject {
     Null,
 }
 
+/// Change of a single value between two context trees, values are `None` unless requested
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContextDiffEntry {
+    Added {
+        key: ContextKeyOwned,
+        value: Option<ContextValue>,
+    },
+    Removed {
+        key: ContextKeyOwned,
+        value: Option<ContextValue>,
+    },
+    Modified {
+        key: ContextKeyOwned,
+        old_value: Option<ContextValue>,
+        new_value: Option<ContextValue>,
+    },
+}
+
+impl ContextDiffEntry {
+    pub fn key(&self) -> &ContextKeyOwned {
+        match self {
+            Self::Added { key, .. } | Self::Removed { key, .. } | Self::Modified { key, .. } => key,
+        }
+    }
+}
+
+/// Number of changes returned by a context diff request without limit
+pub const CONTEXT_DIFF_DEFAULT_LIMIT: usize = 1000;
+
+/// Maximal number of changes returned by a single context diff request
+pub const CONTEXT_DIFF_MAX_LIMIT: usize = 10_000;
+
+/// Page of the changes requested from a context diff
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextDiffRequest {
+    /// Only the changes of keys sorted after this one, the `next` key of the previous page
+    pub after: Option<ContextKeyOwned>,
+    /// Maximal number of changes
+    pub limit: usize,
+    /// Whether the values are returned, or only the changed keys
+    pub with_values: bool,
+}
+
+impl Default for ContextDiffRequest {
+    fn default() -> Self {
+        Self {
+            after: None,
+            limit: CONTEXT_DIFF_DEFAULT_LIMIT,
+            with_values: false,
+        }
+    }
+}
+
+/// Changes between two context trees, sorted by key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextDiff {
+    pub entries: Vec<ContextDiffEntry>,
+    /// Key of the last entry when more changes follow, the next page starts after it
+    pub next: Option<ContextKeyOwned>,
+}
+
+/// Value of a key after the block applied at `level` changed it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextKeyHistoryEntry {
+    pub level: u32,
+    /// `None` when the key was removed
+    pub value: Option<ContextValue>,
+}
+
+/// Values of a key over a range of levels
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextKeyHistory {
+    pub entries: Vec<ContextKeyHistoryEntry>,
+    /// Levels of the range whose changes could not be recorded, the value of the key
+    /// at those levels is unknown
+    pub missing_levels: Vec<u32>,
+}
+
 /// Marco that simplifies and unificates ContextKey creation
 ///
 /// Common usage:
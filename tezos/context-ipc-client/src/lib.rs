@@ -1,17 +1,23 @@
 // Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
 // SPDX-License-Identifier: MIT
 
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use async_ipc::IpcError;
 use crypto::hash::ContextHash;
-use tezos_context_api::{ContextKeyOwned, ContextValue, StringTreeObject};
+use tezos_context::kv_store::readonly_ipc::{ContextServiceError, IpcContextClient};
+use tezos_context_api::{
+    ContextDiff, ContextDiffRequest, ContextKeyHistory, ContextKeyOwned, ContextValue,
+    StringTreeObject,
+};
 use tezos_protocol_ipc_client::{ProtocolRunnerApi, ProtocolServiceError};
 use thiserror::Error;
 
 #[derive(Clone)]
 pub struct TezedgeContextClient {
     tezos_protocol_api: Arc<ProtocolRunnerApi>,
+    /// Connection to the context IPC server of the writable protocol runner, opened on first use
+    context_ipc: Arc<Mutex<Option<IpcContextClient>>>,
 }
 
 #[derive(Debug, Error)]
@@ -26,11 +32,23 @@ pub enum TezedgeContextClientError {
         #[from]
         reason: IpcError,
     },
+    #[error("Context service error: {reason}")]
+    ContextServiceError {
+        #[from]
+        reason: ContextServiceError,
+    },
+    #[error("Context IPC server is not available, it requires the TezEdge context storage")]
+    ContextIpcUnavailable,
+    #[error("Context IPC request failed, reason: {reason}")]
+    ContextIpcRequestFailed { reason: String },
 }
 
 impl TezedgeContextClient {
     pub fn new(tezos_protocol_api: Arc<ProtocolRunnerApi>) -> Self {
-        Self { tezos_protocol_api }
+        Self {
+            tezos_protocol_api,
+            context_ipc: Arc::new(Mutex::new(None)),
+        }
     }
 
     pub async fn get_key_from_history(
@@ -72,4 +90,79 @@ impl TezedgeContextClient {
             .get_context_tree_by_prefix(context_hash, prefix, depth)
             .await?)
     }
+
+    /// Page of the values added, removed or modified between two commits, sorted by key.
+    ///
+    /// Unlike the other queries, this one is served by the context IPC server in the writable
+    /// protocol runner, through the same client readonly protocol runners use.
+    pub async fn get_context_diff(
+        &self,
+        from_context_hash: &ContextHash,
+        to_context_hash: &ContextHash,
+        request: ContextDiffRequest,
+    ) -> Result<ContextDiff, TezedgeContextClientError> {
+        let from_context_hash = from_context_hash.clone();
+        let to_context_hash = to_context_hash.clone();
+
+        self.context_ipc_request(move |client| {
+            client.get_context_diff(&from_context_hash, &to_context_hash, &request)
+        })
+        .await
+    }
+
+    /// Values of `key` changed by the blocks applied between `from_level` and `to_level`.
+    ///
+    /// Returns `None` when the writable protocol runner does not record the key history.
+    /// Like `get_context_diff`, this is served by the context IPC server.
+    pub async fn get_key_history(
+        &self,
+        key: ContextKeyOwned,
+        from_level: u32,
+        to_level: u32,
+    ) -> Result<Option<ContextKeyHistory>, TezedgeContextClientError> {
+        self.context_ipc_request(move |client| client.get_key_history(&key, from_level, to_level))
+            .await
+    }
+
+    /// Run `request` on the connection to the context IPC server of the writable protocol runner
+    async fn context_ipc_request<T, F>(&self, request: F) -> Result<T, TezedgeContextClientError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&IpcContextClient) -> Result<T, ContextServiceError> + Send + 'static,
+    {
+        let socket_path = self
+            .tezos_protocol_api
+            .configuration()
+            .storage
+            .get_ipc_socket_path()
+            .ok_or(TezedgeContextClientError::ContextIpcUnavailable)?;
+        self.tezos_protocol_api.wait_for_context_init().await.ok();
+
+        let context_ipc = Arc::clone(&self.context_ipc);
+
+        self.tezos_protocol_api
+            .tokio_runtime
+            .spawn_blocking(move || -> Result<_, TezedgeContextClientError> {
+                let mut context_ipc = context_ipc.lock().map_err(|e| {
+                    TezedgeContextClientError::ContextIpcRequestFailed {
+                        reason: e.to_string(),
+                    }
+                })?;
+                let client = match context_ipc.take() {
+                    Some(client) => client,
+                    None => IpcContextClient::try_connect(&socket_path)
+                        .map_err(ContextServiceError::from)?,
+                };
+                let result = request(&client);
+                // a broken connection is dropped and opened again on the next request
+                if !matches!(result, Err(ContextServiceError::IpcError { .. })) {
+                    context_ipc.replace(client);
+                }
+                Ok(result?)
+            })
+            .await
+            .map_err(|e| TezedgeContextClientError::ContextIpcRequestFailed {
+                reason: e.to_string(),
+            })?
+    }
 }
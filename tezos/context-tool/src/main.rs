@@ -8,9 +8,10 @@ use clap::{Parser, Subcommand};
 use crypto::hash::{ContextHash, HashTrait};
 use parking_lot::RwLock;
 use tezos_context::{
+    export,
     kv_store::persistent::{FileSizes, PersistentConfiguration},
     persistent::file::{File, TAG_SIZES},
-    snapshot,
+    scrub, snapshot,
     working_tree::string_interner::StringId,
     IndexApi, Persistent, TezedgeIndex,
 };
@@ -72,6 +73,37 @@ enum Commands {
         #[clap(short, long)]
         output: Option<String>,
     },
+    /// Export the context of a commit as a portable stream
+    ExportCommit {
+        /// Path of the persistent context
+        #[clap(short, long)]
+        context_path: String,
+        /// Context hash to export, default to last commit
+        #[clap(short, long)]
+        hash: Option<String>,
+        /// Path of the resulting stream, default to stdout
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+    /// Import a stream made by `export-commit` into a new persistent context
+    ImportCommit {
+        /// Path of the new persistent context
+        #[clap(short, long)]
+        context_path: String,
+        /// Path of the stream, default to stdin
+        #[clap(short, long)]
+        input: Option<String>,
+    },
+    /// Recompute the hashes of all objects reachable from the most recent commits,
+    /// and report the first corrupted object
+    Scrub {
+        /// Path of the persistent context
+        #[clap(short, long)]
+        context_path: String,
+        /// Number of commits to verify, starting from the last one, default to 10
+        #[clap(short = 'n', long)]
+        count: Option<usize>,
+    },
 }
 
 fn reload_context_readonly(context_path: String) -> Persistent {
@@ -264,6 +296,99 @@ fn main() {
                 start.elapsed(),
             );
         }
+        Commands::Scrub {
+            context_path,
+            count,
+        } => {
+            let start = std::time::Instant::now();
+            let mut ctx = reload_context_readonly(context_path.clone());
+
+            let report = scrub::scrub_context(&mut ctx, count.unwrap_or(10), log_snapshot).unwrap();
+
+            log!(
+                "Scrubbed {} commits, {} objects in {:?}",
+                report.commits_checked,
+                report.objects_checked,
+                start.elapsed()
+            );
+
+            match report.corruption {
+                Some(corruption) => {
+                    elog!("Context at {:?} is corrupted: {}", context_path, corruption);
+                    std::process::exit(1);
+                }
+                None => log!("Context at {:?} is valid", context_path),
+            }
+        }
+        Commands::ExportCommit {
+            context_path,
+            hash: context_hash,
+            output,
+        } => {
+            // Logs are written to stderr, stdout might be the stream
+            let start = std::time::Instant::now();
+            let ctx = snapshot::reload_context_readonly(context_path).unwrap();
+
+            let context_hash = if let Some(context_hash) = context_hash.as_ref() {
+                ContextHash::from_b58check(context_hash).unwrap()
+            } else {
+                ctx.get_last_context_hash().unwrap()
+            };
+
+            elog!("Exporting {:?}...", context_hash.to_base58_check());
+
+            let index = TezedgeIndex::new(Arc::new(RwLock::new(ctx)), None);
+
+            match output {
+                Some(path) => {
+                    let file = std::fs::File::create(&path).unwrap();
+                    export::export_commit(&index, &context_hash, file).unwrap();
+                }
+                None => {
+                    let stdout = std::io::stdout();
+                    export::export_commit(&index, &context_hash, stdout.lock()).unwrap();
+                }
+            }
+
+            elog!("Exporting ok {:?}", start.elapsed());
+        }
+        Commands::ImportCommit {
+            context_path,
+            input,
+        } => {
+            let start = std::time::Instant::now();
+
+            if PathBuf::from(&context_path).exists() {
+                panic!("{:?} already exist", context_path);
+            }
+
+            let repo = Persistent::try_new(PersistentConfiguration {
+                db_path: Some(context_path.clone()),
+                startup_check: false,
+                read_mode: false,
+            })
+            .unwrap();
+            let index = TezedgeIndex::new(Arc::new(RwLock::new(repo)), None);
+
+            log!("Importing into {:?}...", context_path);
+
+            let context_hash = match input {
+                Some(path) => {
+                    let file = std::fs::File::open(&path).unwrap();
+                    export::import_commit(&index, file).unwrap()
+                }
+                None => {
+                    let stdin = std::io::stdin();
+                    export::import_commit(&index, stdin.lock()).unwrap()
+                }
+            };
+
+            log!(
+                "Imported {:?} in {:?}",
+                context_hash.to_base58_check(),
+                start.elapsed()
+            );
+        }
     }
 }
 
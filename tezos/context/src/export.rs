@@ -0,0 +1,468 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Export and import the context of a single commit as a portable stream.
+//!
+//! The stream doesn't depend on the backend (in-memory or persistent), nor on
+//! the OCaml runner, it can be piped over stdout to transfer a context
+//! between machines.
+//!
+//! Stream layout (version 1), integers are LEB128 encoded unless stated otherwise:
+//!
+//! ```text
+//! | magic (8 bytes) | version (1 byte) | commit header | record | ... | record |
+//! ```
+//!
+//! The commit header contains the context hash (32 bytes), the parent hash
+//! (`\000` or `\001` followed by 32 bytes), the time, the author and the message.
+//!
+//! The tree objects are written in post-order: the children of a directory
+//! are written before the directory itself.
+//!
+//! | Record      | Tag    | Content                                                   |
+//! |-------------|--------|-----------------------------------------------------------|
+//! | `String`    | `\000` | length, bytes. Its id is the number of previous strings   |
+//! | `Shape`     | `\001` | number of names, string ids. Its id is the number of previous shapes |
+//! | `Blob`      | `\002` | length, bytes                                             |
+//! | `Directory` | `\003` | shape id. Its entries are the last `len(shape)` objects   |
+//! | `End`       | `\004` | the last object is the root directory                     |
+
+use std::{
+    collections::HashMap,
+    io::{self, BufReader, BufWriter, Read, Write},
+    rc::Rc,
+};
+
+use crypto::hash::{ContextHash, HashTrait};
+use thiserror::Error;
+
+use crate::{
+    persistent::DBError,
+    working_tree::{
+        storage::{DirectoryId, Storage, StorageError},
+        string_interner::StringInterner,
+        working_tree::{MerkleError, WorkingTree},
+        DirEntry, DirEntryKind, Object, ObjectReference,
+    },
+    ContextError, ContextKeyValueStore, ObjectHash, ShellContextApi, TezedgeContext, TezedgeIndex,
+};
+
+const STREAM_MAGIC: &[u8; 8] = b"TZCTXSTR";
+const STREAM_VERSION: u8 = 1;
+
+const TAG_STRING: u8 = 0;
+const TAG_SHAPE: u8 = 1;
+const TAG_BLOB: u8 = 2;
+const TAG_DIRECTORY: u8 = 3;
+const TAG_END: u8 = 4;
+
+/// Upper bound of lengths read from the stream, to avoid huge allocations
+/// on invalid streams
+const MAX_LENGTH: u64 = 1 << 30;
+
+#[derive(Debug, Error)]
+pub enum ContextStreamError {
+    #[error("IO error: {error}")]
+    IoError {
+        #[from]
+        error: io::Error,
+    },
+    #[error("Not a context stream")]
+    InvalidMagic,
+    #[error("Unsupported stream version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Invalid record tag {0}")]
+    InvalidTag(u8),
+    #[error("Invalid stream: {0}")]
+    InvalidStream(&'static str),
+    #[error("Commit not found: {context_hash}")]
+    CommitNotFound { context_hash: String },
+    #[error("Imported context hash {imported} differs from the exported one {expected}")]
+    HashMismatch { expected: String, imported: String },
+    #[error("Context error: {error}")]
+    ContextError {
+        #[from]
+        error: ContextError,
+    },
+    #[error("Merkle error: {error}")]
+    MerkleError {
+        #[from]
+        error: MerkleError,
+    },
+    #[error("Storage error: {error}")]
+    StorageError {
+        #[from]
+        error: StorageError,
+    },
+    #[error("Database error: {error}")]
+    DBError {
+        #[from]
+        error: DBError,
+    },
+}
+
+fn write_length(output: &mut impl Write, value: usize) -> Result<(), ContextStreamError> {
+    leb128::write::unsigned(output, value as u64)?;
+    Ok(())
+}
+
+fn write_bytes(output: &mut impl Write, bytes: &[u8]) -> Result<(), ContextStreamError> {
+    write_length(output, bytes.len())?;
+    output.write_all(bytes)?;
+    Ok(())
+}
+
+fn read_u8(input: &mut impl Read) -> Result<u8, ContextStreamError> {
+    let mut byte = [0; 1];
+    input.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_length(input: &mut impl Read) -> Result<usize, ContextStreamError> {
+    match leb128::read::unsigned(input) {
+        Ok(value) if value <= MAX_LENGTH => Ok(value as usize),
+        Ok(_) => Err(ContextStreamError::InvalidStream("length too big")),
+        Err(leb128::read::Error::IoError(error)) => Err(error.into()),
+        Err(leb128::read::Error::Overflow) => {
+            Err(ContextStreamError::InvalidStream("invalid LEB128 value"))
+        }
+    }
+}
+
+fn read_bytes(input: &mut impl Read) -> Result<Vec<u8>, ContextStreamError> {
+    let length = read_length(input)?;
+    let mut bytes = vec![0; length];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_string(input: &mut impl Read) -> Result<String, ContextStreamError> {
+    String::from_utf8(read_bytes(input)?)
+        .map_err(|_| ContextStreamError::InvalidStream("string is not valid UTF-8"))
+}
+
+fn read_hash(input: &mut impl Read) -> Result<ObjectHash, ContextStreamError> {
+    let mut hash: ObjectHash = Default::default();
+    input.read_exact(&mut hash)?;
+    Ok(hash)
+}
+
+/// Child of a directory, waiting to be exported
+enum ExportedChild {
+    InlinedBlob(Vec<u8>),
+    Blob(ObjectReference),
+    Directory(ObjectReference),
+}
+
+struct Exporter<'a, W: Write> {
+    repository: &'a ContextKeyValueStore,
+    strings: &'a mut StringInterner,
+    /// Objects are loaded one by one in this `Storage`, it's cleared after each object
+    storage: Storage,
+    output: W,
+    string_ids: HashMap<String, usize>,
+    shape_ids: HashMap<Vec<usize>, usize>,
+}
+
+impl<'a, W: Write> Exporter<'a, W> {
+    fn load_directory(
+        &mut self,
+        object_ref: ObjectReference,
+    ) -> Result<Vec<(String, ExportedChild)>, ContextStreamError> {
+        self.storage.clear();
+
+        let dir_id =
+            match self
+                .repository
+                .get_object(object_ref, &mut self.storage, self.strings)?
+            {
+                Object::Directory(dir_id) => dir_id,
+                _ => return Err(ContextStreamError::InvalidStream("expected a directory")),
+            };
+
+        let entries = self
+            .storage
+            .dir_to_vec_sorted(dir_id, self.strings, self.repository)?;
+
+        let mut children = Vec::with_capacity(entries.len());
+        for (string_id, dir_entry_id) in entries {
+            let name = self.strings.get_str(string_id)?.into_owned();
+            let dir_entry = self.storage.get_dir_entry(dir_entry_id)?;
+
+            let child = if let Some(blob) = dir_entry.get_inlined_blob(&self.storage) {
+                ExportedChild::InlinedBlob(blob.to_vec())
+            } else {
+                let reference = dir_entry.get_reference();
+                if reference.hash_id_opt().is_none() && reference.offset_opt().is_none() {
+                    return Err(ContextStreamError::InvalidStream("object is not commited"));
+                }
+
+                match dir_entry.dir_entry_kind() {
+                    DirEntryKind::Blob => ExportedChild::Blob(reference),
+                    DirEntryKind::Directory => ExportedChild::Directory(reference),
+                }
+            };
+            children.push((name, child));
+        }
+
+        Ok(children)
+    }
+
+    fn export_blob(&mut self, object_ref: ObjectReference) -> Result<(), ContextStreamError> {
+        self.storage.clear();
+
+        let blob_id =
+            match self
+                .repository
+                .get_object(object_ref, &mut self.storage, self.strings)?
+            {
+                Object::Blob(blob_id) => blob_id,
+                _ => return Err(ContextStreamError::InvalidStream("expected a blob")),
+            };
+        let blob = self.storage.get_blob(blob_id)?;
+
+        self.output.write_all(&[TAG_BLOB])?;
+        write_bytes(&mut self.output, blob.as_ref())
+    }
+
+    fn export_directory(&mut self, object_ref: ObjectReference) -> Result<(), ContextStreamError> {
+        let children = self.load_directory(object_ref)?;
+
+        let mut shape = Vec::with_capacity(children.len());
+        for (name, child) in children {
+            match child {
+                ExportedChild::InlinedBlob(blob) => {
+                    self.output.write_all(&[TAG_BLOB])?;
+                    write_bytes(&mut self.output, &blob)?;
+                }
+                ExportedChild::Blob(object_ref) => self.export_blob(object_ref)?,
+                ExportedChild::Directory(object_ref) => self.export_directory(object_ref)?,
+            }
+            shape.push(self.string_id(name)?);
+        }
+
+        let shape_id = self.shape_id(shape)?;
+
+        self.output.write_all(&[TAG_DIRECTORY])?;
+        write_length(&mut self.output, shape_id)
+    }
+
+    /// Returns the id of `name`, writes a `String` record when it's not known yet
+    fn string_id(&mut self, name: String) -> Result<usize, ContextStreamError> {
+        if let Some(id) = self.string_ids.get(&name) {
+            return Ok(*id);
+        }
+
+        self.output.write_all(&[TAG_STRING])?;
+        write_bytes(&mut self.output, name.as_bytes())?;
+
+        let id = self.string_ids.len();
+        self.string_ids.insert(name, id);
+        Ok(id)
+    }
+
+    /// Returns the id of `shape`, writes a `Shape` record when it's not known yet
+    fn shape_id(&mut self, shape: Vec<usize>) -> Result<usize, ContextStreamError> {
+        if let Some(id) = self.shape_ids.get(&shape) {
+            return Ok(*id);
+        }
+
+        self.output.write_all(&[TAG_SHAPE])?;
+        write_length(&mut self.output, shape.len())?;
+        for string_id in &shape {
+            write_length(&mut self.output, *string_id)?;
+        }
+
+        let id = self.shape_ids.len();
+        self.shape_ids.insert(shape, id);
+        Ok(id)
+    }
+}
+
+/// Write the context of the commit `context_hash` to `output`
+pub fn export_commit(
+    index: &TezedgeIndex,
+    context_hash: &ContextHash,
+    output: impl Write,
+) -> Result<(), ContextStreamError> {
+    let commit = index
+        .fetch_commit_from_context_hash(context_hash)?
+        .ok_or_else(|| ContextStreamError::CommitNotFound {
+            context_hash: context_hash.to_base58_check(),
+        })?;
+
+    let repository = index.repository.read();
+    let mut strings = index.get_string_interner()?;
+
+    let mut output = BufWriter::new(output);
+
+    output.write_all(STREAM_MAGIC)?;
+    output.write_all(&[STREAM_VERSION])?;
+
+    output.write_all(context_hash.as_ref())?;
+    match commit.parent_commit_ref {
+        Some(parent_ref) => {
+            output.write_all(&[1])?;
+            output.write_all(&repository.get_hash(parent_ref)?[..])?;
+        }
+        None => output.write_all(&[0])?,
+    }
+    leb128::write::unsigned(&mut output, commit.time)?;
+    write_bytes(&mut output, commit.author.as_bytes())?;
+    write_bytes(&mut output, commit.message.as_bytes())?;
+
+    let mut exporter = Exporter {
+        repository: &*repository,
+        strings: &mut strings,
+        storage: Storage::new(),
+        output,
+        string_ids: HashMap::default(),
+        shape_ids: HashMap::default(),
+    };
+
+    exporter.export_directory(commit.root_ref)?;
+    exporter.output.write_all(&[TAG_END])?;
+    exporter.output.flush()?;
+
+    Ok(())
+}
+
+/// Read a stream written by `export_commit` and commit it in the repository
+/// of `index`.
+///
+/// The commit is verified: its context hash must be the exported one.
+pub fn import_commit(
+    index: &TezedgeIndex,
+    input: impl Read,
+) -> Result<ContextHash, ContextStreamError> {
+    let mut input = BufReader::new(input);
+
+    let mut magic = [0; STREAM_MAGIC.len()];
+    input.read_exact(&mut magic)?;
+    if &magic != STREAM_MAGIC {
+        return Err(ContextStreamError::InvalidMagic);
+    }
+
+    let version = read_u8(&mut input)?;
+    if version != STREAM_VERSION {
+        return Err(ContextStreamError::UnsupportedVersion(version));
+    }
+
+    let expected_hash = read_hash(&mut input)?;
+    let parent_hash = match read_u8(&mut input)? {
+        0 => None,
+        1 => Some(read_hash(&mut input)?),
+        _ => return Err(ContextStreamError::InvalidStream("invalid parent tag")),
+    };
+    let time = match leb128::read::unsigned(&mut input) {
+        Ok(time) => time,
+        Err(leb128::read::Error::IoError(error)) => return Err(error.into()),
+        Err(leb128::read::Error::Overflow) => {
+            return Err(ContextStreamError::InvalidStream("invalid LEB128 value"))
+        }
+    };
+    let author = read_string(&mut input)?;
+    let message = read_string(&mut input)?;
+
+    let root_dir_id = import_tree(index, &mut input)?;
+
+    let parent_ref = match parent_hash {
+        Some(parent_hash) => {
+            let mut repository = index.repository.write();
+            let hash_id = repository
+                .get_vacant_object_hash()?
+                .write_with(|entry| *entry = parent_hash)?;
+            Some(ObjectReference::new(Some(hash_id), None))
+        }
+        None => None,
+    };
+
+    let tree = WorkingTree::new_with_directory(index.clone(), root_dir_id);
+    let context = TezedgeContext::new(index.clone(), parent_ref, Some(Rc::new(tree)));
+
+    let context_hash = context.commit(author, message, time as i64)?;
+
+    if context_hash.as_ref().as_slice() != expected_hash.as_slice() {
+        return Err(ContextStreamError::HashMismatch {
+            expected: ContextHash::try_from_bytes(&expected_hash)
+                .map(|hash| hash.to_base58_check())
+                .unwrap_or_else(|_| hex::encode(expected_hash)),
+            imported: context_hash.to_base58_check(),
+        });
+    }
+
+    Ok(context_hash)
+}
+
+/// Read the records of the stream and rebuild the tree in the `Storage` of `index`.
+///
+/// Returns the root directory.
+fn import_tree(
+    index: &TezedgeIndex,
+    input: &mut impl Read,
+) -> Result<DirectoryId, ContextStreamError> {
+    let mut storage = index.storage.borrow_mut();
+    let mut strings = index.get_string_interner()?;
+    let repository = index.repository.read();
+
+    let mut stream_strings: Vec<String> = Vec::new();
+    let mut stream_shapes: Vec<Vec<usize>> = Vec::new();
+    // Objects waiting for their parent directory
+    let mut pending: Vec<DirEntry> = Vec::new();
+
+    loop {
+        match read_u8(input)? {
+            TAG_STRING => stream_strings.push(read_string(input)?),
+            TAG_SHAPE => {
+                let length = read_length(input)?;
+                let mut shape = Vec::new();
+                for _ in 0..length {
+                    let string_id = read_length(input)?;
+                    if string_id >= stream_strings.len() {
+                        return Err(ContextStreamError::InvalidStream("unknown string"));
+                    }
+                    shape.push(string_id);
+                }
+                stream_shapes.push(shape);
+            }
+            TAG_BLOB => {
+                let blob = read_bytes(input)?;
+                let blob_id = storage.add_blob_by_ref(&blob)?;
+                pending.push(DirEntry::new_blob(Object::Blob(blob_id)));
+            }
+            TAG_DIRECTORY => {
+                let shape = stream_shapes
+                    .get(read_length(input)?)
+                    .ok_or(ContextStreamError::InvalidStream("unknown shape"))?;
+                let first = pending.len().checked_sub(shape.len()).ok_or(
+                    ContextStreamError::InvalidStream("missing directory entries"),
+                )?;
+
+                let mut dir_id = DirectoryId::empty();
+                for (string_id, dir_entry) in shape.iter().zip(pending.drain(first..)) {
+                    dir_id = storage.dir_insert(
+                        dir_id,
+                        &stream_strings[*string_id],
+                        dir_entry,
+                        &mut strings,
+                        &*repository,
+                    )?;
+                }
+                pending.push(DirEntry::new(
+                    DirEntryKind::Directory,
+                    Object::Directory(dir_id),
+                ));
+            }
+            TAG_END => break,
+            tag => return Err(ContextStreamError::InvalidTag(tag)),
+        }
+    }
+
+    match (
+        pending.pop().and_then(|root| root.get_object()),
+        pending.is_empty(),
+    ) {
+        (Some(Object::Directory(dir_id)), true) => Ok(dir_id),
+        _ => Err(ContextStreamError::InvalidStream("invalid root directory")),
+    }
+}
@@ -111,16 +111,39 @@ fn encode_irmin_dir_entry_kind(kind: &DirEntryKind) -> [u8; 8] {
     }
 }
 
+fn store_object_hash(
+    object_hash: &ObjectHash,
+    store: &mut ContextKeyValueStore,
+) -> Result<HashId, HashingError> {
+    let hash_id = store
+        .get_vacant_object_hash()?
+        .write_with(|object| object.copy_from_slice(object_hash))?;
+
+    Ok(hash_id)
+}
+
 pub(crate) fn index(depth: u32, name: &str) -> u32 {
     ocaml_hash_string(depth, name.as_bytes()) % 32
 }
 
-fn hash_long_inode(
+pub(crate) fn hash_long_inode(
     ptr_id: DirectoryOrInodeId,
     store: &mut ContextKeyValueStore,
     storage: &Storage,
     strings: &StringInterner,
 ) -> Result<HashId, HashingError> {
+    let object_hash = long_inode_hash(ptr_id, store, storage, strings)?;
+    store_object_hash(&object_hash, store)
+}
+
+/// Same as `hash_long_inode` but the hash is returned instead of being
+/// stored in the repository.
+pub(crate) fn long_inode_hash(
+    ptr_id: DirectoryOrInodeId,
+    store: &mut ContextKeyValueStore,
+    storage: &Storage,
+    strings: &StringInterner,
+) -> Result<ObjectHash, HashingError> {
     let mut hasher = VarBlake2b::new(OBJECT_HASH_LEN)?;
 
     match ptr_id {
@@ -218,11 +241,10 @@ fn hash_long_inode(
         }
     }
 
-    let hash_id = store
-        .get_vacant_object_hash()?
-        .write_with(|object| hasher.finalize_variable(|r| object.copy_from_slice(r)))?;
+    let mut object_hash: ObjectHash = Default::default();
+    hasher.finalize_variable(|r| object_hash.copy_from_slice(r));
 
-    Ok(hash_id)
+    Ok(object_hash)
 }
 
 // hash is calculated as:
@@ -236,6 +258,16 @@ fn hash_short_inode(
     storage: &Storage,
     strings: &StringInterner,
 ) -> Result<HashId, HashingError> {
+    let object_hash = short_inode_hash(dir_id, store, storage, strings)?;
+    store_object_hash(&object_hash, store)
+}
+
+fn short_inode_hash(
+    dir_id: DirectoryId,
+    store: &mut ContextKeyValueStore,
+    storage: &Storage,
+    strings: &StringInterner,
+) -> Result<ObjectHash, HashingError> {
     let mut hasher = VarBlake2b::new(OBJECT_HASH_LEN)?;
 
     // DirEntry list:
@@ -270,11 +302,10 @@ fn hash_short_inode(
         }
     }
 
-    let hash_id = store
-        .get_vacant_object_hash()?
-        .write_with(|object| hasher.finalize_variable(|r| object.copy_from_slice(r)))?;
+    let mut object_hash: ObjectHash = Default::default();
+    hasher.finalize_variable(|r| object_hash.copy_from_slice(r));
 
-    Ok(hash_id)
+    Ok(object_hash)
 }
 
 // Calculates hash of directory
@@ -292,6 +323,21 @@ pub(crate) fn hash_directory(
     }
 }
 
+/// Same as `hash_directory` but the hash is returned instead of being
+/// stored in the repository.
+pub(crate) fn directory_hash(
+    dir_id: DirectoryId,
+    store: &mut ContextKeyValueStore,
+    storage: &Storage,
+    strings: &StringInterner,
+) -> Result<ObjectHash, HashingError> {
+    if let Some(inode_id) = dir_id.get_inode_id() {
+        long_inode_hash(DirectoryOrInodeId::Inode(inode_id), store, storage, strings)
+    } else {
+        short_inode_hash(dir_id, store, storage, strings)
+    }
+}
+
 // Calculates hash of BLOB
 // uses BLAKE2 binary 256 length hash function
 // hash is calculated as <length of data (8 bytes)><data>
@@ -344,6 +390,16 @@ pub(crate) fn hash_commit(
     commit: &Commit,
     store: &mut ContextKeyValueStore,
 ) -> Result<HashId, HashingError> {
+    let object_hash = commit_hash(commit, store)?;
+    store_object_hash(&object_hash, store)
+}
+
+/// Same as `hash_commit` but the hash is returned instead of being
+/// stored in the repository.
+pub(crate) fn commit_hash(
+    commit: &Commit,
+    store: &ContextKeyValueStore,
+) -> Result<ObjectHash, HashingError> {
     let mut hasher = VarBlake2b::new(OBJECT_HASH_LEN)?;
     hasher.update(&(OBJECT_HASH_LEN as u64).to_be_bytes());
 
@@ -365,11 +421,10 @@ pub(crate) fn hash_commit(
     hasher.update(&(commit.message.len() as u64).to_be_bytes());
     hasher.update(&commit.message.clone().into_bytes());
 
-    let hash_id = store
-        .get_vacant_object_hash()?
-        .write_with(|object| hasher.finalize_variable(|r| object.copy_from_slice(r)))?;
+    let mut object_hash: ObjectHash = Default::default();
+    hasher.finalize_variable(|r| object_hash.copy_from_slice(r));
 
-    Ok(hash_id)
+    Ok(object_hash)
 }
 
 pub(crate) fn hash_object(
@@ -137,11 +137,17 @@ pub fn initialize_tezedge_index(
             })?))
         }
         ContextKvStoreConfiguration::OnDisk(ref options) => {
-            Arc::new(RwLock::new(Persistent::try_new(PersistentConfiguration {
+            let mut repository = Persistent::try_new(PersistentConfiguration {
                 db_path: Some(options.base_path.clone()),
                 startup_check: options.startup_check,
                 read_mode: false,
-            })?))
+            })?;
+
+            if options.key_history {
+                repository.enable_key_history()?;
+            }
+
+            Arc::new(RwLock::new(repository))
         }
     };
 
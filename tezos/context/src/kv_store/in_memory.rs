@@ -19,6 +19,7 @@ use crossbeam_channel::Sender;
 use crypto::hash::ContextHash;
 
 use parking_lot::RwLock;
+use tezos_context_api::{ContextKey, ContextKeyHistory};
 use tezos_timing::{RepositoryMemoryUsage, SerializeStats};
 
 use crate::{
@@ -32,7 +33,8 @@ use crate::{
     },
     hash::ObjectHash,
     persistent::{
-        DBError, Flushable, KeyValueStoreBackend, Persistable, ReadStatistics, ReloadError,
+        key_history::KeyHistoryChange, DBError, Flushable, KeyValueStoreBackend, Persistable,
+        ReadStatistics, ReloadError,
     },
     working_tree::{
         shape::{DirectoryShapeId, DirectoryShapes, ShapeStrings},
@@ -470,6 +472,31 @@ impl KeyValueStoreBackend for InMemory {
         }
     }
 
+    fn is_key_history_enabled(&self) -> bool {
+        false
+    }
+
+    fn put_key_history(
+        &mut self,
+        _block_level: u32,
+        _changes: &[KeyHistoryChange],
+    ) -> Result<(), DBError> {
+        Ok(())
+    }
+
+    fn put_key_history_missing(&mut self, _block_level: u32) -> Result<(), DBError> {
+        Ok(())
+    }
+
+    fn get_key_history(
+        &self,
+        _key: &ContextKey,
+        _from_level: u32,
+        _to_level: u32,
+    ) -> Result<Option<ContextKeyHistory>, DBError> {
+        Ok(None)
+    }
+
     #[cfg(test)]
     fn synchronize_data(
         &mut self,
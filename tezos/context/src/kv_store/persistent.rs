@@ -19,6 +19,7 @@ use blake2::{
 use crypto::hash::{ContextHash, HashTrait};
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
+use tezos_context_api::{ContextKey, ContextKeyHistory, ContextKeyHistoryEntry, ContextValue};
 use tezos_timing::{RepositoryMemoryUsage, SerializeStats};
 
 use crate::{
@@ -29,9 +30,11 @@ use crate::{
     persistent::{
         file::{
             get_persistent_base_path, File, TAG_BIG_STRINGS, TAG_COMMIT_INDEX, TAG_DATA,
-            TAG_HASHES, TAG_SHAPE, TAG_SHAPE_INDEX, TAG_SIZES, TAG_STRINGS,
+            TAG_HASHES, TAG_KEY_HISTORY, TAG_KEY_HISTORY_INDEX, TAG_SHAPE, TAG_SHAPE_INDEX,
+            TAG_SIZES, TAG_STRINGS,
         },
         get_commit_hash,
+        key_history::{KeyHistory, KeyHistoryChange, KeyHistoryValue},
         lock::Lock,
         DBError, Flushable, KeyValueStoreBackend, Persistable, ReadStatistics, ReloadError,
     },
@@ -134,6 +137,11 @@ pub struct Persistent {
     startup_check: bool,
     lastest_commits_on_startup: VecDeque<ObjectReference>,
     read_statistics: Option<Mutex<ReadStatistics>>,
+    base_path: String,
+    /// Values changed by each applied block, `None` when not enabled.
+    ///
+    /// See `persistent::key_history`
+    key_history: Option<KeyHistory>,
 }
 
 impl Drop for Persistent {
@@ -294,6 +302,8 @@ impl Persistent {
             } else {
                 None
             },
+            base_path,
+            key_history: None,
         })
     }
 
@@ -301,6 +311,38 @@ impl Persistent {
         self.hashes.in_memory.dedup_hashes = Some(Default::default());
     }
 
+    /// Open the key history files and record the values changed by each applied block
+    pub fn enable_key_history(&mut self) -> Result<(), IndexInitializationError> {
+        let read_mode = self.lock_file.is_none();
+        let changes = File::<{ TAG_KEY_HISTORY }>::try_new(&self.base_path, read_mode)?;
+        let index = File::<{ TAG_KEY_HISTORY_INDEX }>::try_new(&self.base_path, read_mode)?;
+
+        self.key_history = Some(KeyHistory::load(changes, index, read_mode)?);
+
+        Ok(())
+    }
+
+    /// Returns the value of the blob object at `offset`
+    fn get_blob_value(
+        &self,
+        offset: AbsoluteOffset,
+        storage: &mut Storage,
+        strings: &mut StringInterner,
+    ) -> Result<ContextValue, DBError> {
+        let object_ref = ObjectReference::new(None, Some(offset));
+
+        match self.get_object(object_ref, storage, strings)? {
+            Object::Blob(blob_id) => match storage.get_blob(blob_id) {
+                Ok(blob) => Ok(blob.to_vec()),
+                Err(_) => Err(DBError::MissingObject { object_ref }),
+            },
+            _ => Err(DBError::FoundUnexpectedStructure {
+                sought: "blob".to_string(),
+                found: "directory or commit".to_string(),
+            }),
+        }
+    }
+
     pub fn hashes_in_memory_len(&self) -> usize {
         self.hashes.in_memory_len()
     }
@@ -646,6 +688,15 @@ hashes_file={:?}, in sizes.db={:?}",
     }
 
     pub fn reload_database(&mut self) -> Result<(), IndexInitializationError> {
+        // In read mode, the database can be reloaded to get the commits added since it was opened
+        self.data_file.refresh_read_only_offset()?;
+        self.shape_file.refresh_read_only_offset()?;
+        self.shape_index_file.refresh_read_only_offset()?;
+        self.commit_index_file.refresh_read_only_offset()?;
+        self.strings_file.refresh_read_only_offset()?;
+        self.big_strings_file.refresh_read_only_offset()?;
+        self.hashes.hashes_file.refresh_read_only_offset()?;
+
         let list_sizes = FileSizes::make_list_from_file(&self.sizes_file);
 
         let commit_counter = Self::truncate_files_with_correct_sizes(
@@ -974,6 +1025,65 @@ impl KeyValueStoreBackend for Persistent {
         // no-op
     }
 
+    fn is_key_history_enabled(&self) -> bool {
+        self.key_history.is_some()
+    }
+
+    fn put_key_history(
+        &mut self,
+        block_level: u32,
+        changes: &[KeyHistoryChange],
+    ) -> Result<(), DBError> {
+        if let Some(key_history) = self.key_history.as_mut() {
+            key_history.append(block_level, changes)?;
+        }
+        Ok(())
+    }
+
+    fn put_key_history_missing(&mut self, block_level: u32) -> Result<(), DBError> {
+        if let Some(key_history) = self.key_history.as_mut() {
+            key_history.append_missing(block_level)?;
+        }
+        Ok(())
+    }
+
+    fn get_key_history(
+        &self,
+        key: &ContextKey,
+        from_level: u32,
+        to_level: u32,
+    ) -> Result<Option<ContextKeyHistory>, DBError> {
+        let key_history = match self.key_history.as_ref() {
+            Some(key_history) => key_history,
+            None => return Ok(None),
+        };
+
+        let history = key_history.get(&key.join("/"), from_level, to_level)?;
+
+        let mut storage = Storage::new();
+        let mut strings = StringInterner::default();
+
+        let entries = history
+            .changes
+            .into_iter()
+            .map(|(level, value)| {
+                let value = match value {
+                    KeyHistoryValue::Removed => None,
+                    KeyHistoryValue::Inlined(value) => Some(value),
+                    KeyHistoryValue::Object(offset) => {
+                        Some(self.get_blob_value(offset, &mut storage, &mut strings)?)
+                    }
+                };
+                Ok(ContextKeyHistoryEntry { level, value })
+            })
+            .collect::<Result<_, DBError>>()?;
+
+        Ok(Some(ContextKeyHistory {
+            entries,
+            missing_levels: history.missing_levels,
+        }))
+    }
+
     fn contains(&self, hash_id: HashId) -> Result<bool, DBError> {
         self.hashes.contains(hash_id)
     }
@@ -18,11 +18,16 @@ use crate::ContextKeyValueStore;
 use crypto::hash::ContextHash;
 use parking_lot::RwLock;
 use slog::{error, info};
+use tezos_context_api::{
+    ContextDiff, ContextDiffRequest, ContextKey, ContextKeyHistory, ContextKeyOwned,
+    CONTEXT_DIFF_MAX_LIMIT,
+};
 use tezos_timing::{RepositoryMemoryUsage, SerializeStats};
 use thiserror::Error;
 
 use crate::persistent::{
-    get_commit_hash, DBError, Flushable, Persistable, ReadStatistics, ReloadError,
+    get_commit_hash, key_history::KeyHistoryChange, DBError, Flushable, Persistable,
+    ReadStatistics, ReloadError,
 };
 
 use crate::serialize::{in_memory, persistent, ObjectHeader};
@@ -32,7 +37,8 @@ use crate::working_tree::string_interner::{StringId, StringInterner};
 use crate::working_tree::working_tree::{PostCommitData, SerializeOutput, WorkingTree};
 use crate::working_tree::{Object, ObjectReference};
 use crate::{
-    ffi::TezedgeIndexError, gc::NotGarbageCollected, persistent::KeyValueStoreBackend, ObjectHash,
+    ffi::TezedgeIndexError, gc::NotGarbageCollected, persistent::KeyValueStoreBackend, IndexApi,
+    ObjectHash,
 };
 
 pub struct ReadonlyIpcBackend {
@@ -289,6 +295,35 @@ impl KeyValueStoreBackend for ReadonlyIpcBackend {
         Ok(None)
     }
 
+    fn is_key_history_enabled(&self) -> bool {
+        false
+    }
+
+    fn put_key_history(
+        &mut self,
+        _block_level: u32,
+        _changes: &[KeyHistoryChange],
+    ) -> Result<(), DBError> {
+        Ok(())
+    }
+
+    fn put_key_history_missing(&mut self, _block_level: u32) -> Result<(), DBError> {
+        Ok(())
+    }
+
+    fn get_key_history(
+        &self,
+        key: &ContextKey,
+        from_level: u32,
+        to_level: u32,
+    ) -> Result<Option<ContextKeyHistory>, DBError> {
+        let key: ContextKeyOwned = key.iter().map(|part| part.to_string()).collect();
+
+        self.client
+            .get_key_history(&key, from_level, to_level)
+            .map_err(|reason| DBError::IpcAccessError { reason })
+    }
+
     #[cfg(test)]
     fn synchronize_data(
         &mut self,
@@ -331,6 +366,8 @@ enum ContextRequest {
     GetObjectBytes(ObjectReference),
     GetShape(DirectoryShapeId),
     ContainsObject(HashId),
+    GetContextDiff(ContextHash, ContextHash, ContextDiffRequest),
+    GetKeyHistory(ContextKeyOwned, u32, u32),
     ShutdownCall, // TODO: is this required?
 }
 
@@ -343,6 +380,8 @@ enum ContextResponse {
     GetObjectBytesResponse(Result<Vec<u8>, String>),
     GetShapeResponse(Result<Vec<String>, String>),
     ContainsObjectResponse(Result<bool, String>),
+    GetContextDiffResponse(Result<ContextDiff, String>),
+    GetKeyHistoryResponse(Result<Option<ContextKeyHistory>, String>),
     ShutdownResult,
 }
 
@@ -364,6 +403,10 @@ pub enum ContextError {
     GetHashError { reason: String },
     #[error("Context get hash id error: {reason}")]
     GetHashIdError { reason: String },
+    #[error("Context get diff error: {reason}")]
+    GetContextDiffError { reason: String },
+    #[error("Context get key history error: {reason}")]
+    GetKeyHistoryError { reason: String },
 }
 
 #[derive(Error, Debug)]
@@ -581,6 +624,63 @@ impl IpcContextClient {
         }
     }
 
+    /// Get a page of the values changed between two commits, sorted by key.
+    ///
+    /// The server returns at most `CONTEXT_DIFF_MAX_LIMIT` changes.
+    pub fn get_context_diff(
+        &self,
+        from_context_hash: &ContextHash,
+        to_context_hash: &ContextHash,
+        request: &ContextDiffRequest,
+    ) -> Result<ContextDiff, ContextServiceError> {
+        let mut io = self.io.borrow_mut();
+        io.tx.send(&ContextRequest::GetContextDiff(
+            from_context_hash.clone(),
+            to_context_hash.clone(),
+            request.clone(),
+        ))?;
+
+        // this might take a while, so we will use unusually long timeout
+        match io
+            .rx
+            .try_receive(Some(Self::TIMEOUT), Some(IpcContextListener::IO_TIMEOUT))?
+        {
+            ContextResponse::GetContextDiffResponse(result) => {
+                result.map_err(|err| ContextError::GetContextDiffError { reason: err }.into())
+            }
+            message => Err(ContextServiceError::UnexpectedMessage {
+                message: message.into(),
+            }),
+        }
+    }
+
+    /// Get the values of `key` changed by the blocks applied between `from_level` and `to_level`
+    pub fn get_key_history(
+        &self,
+        key: &ContextKeyOwned,
+        from_level: u32,
+        to_level: u32,
+    ) -> Result<Option<ContextKeyHistory>, ContextServiceError> {
+        let mut io = self.io.borrow_mut();
+        io.tx.send(&ContextRequest::GetKeyHistory(
+            key.clone(),
+            from_level,
+            to_level,
+        ))?;
+
+        match io
+            .rx
+            .try_receive(Some(Self::TIMEOUT), Some(IpcContextListener::IO_TIMEOUT))?
+        {
+            ContextResponse::GetKeyHistoryResponse(result) => {
+                result.map_err(|err| ContextError::GetKeyHistoryError { reason: err }.into())
+            }
+            message => Err(ContextServiceError::UnexpectedMessage {
+                message: message.into(),
+            }),
+        }
+    }
+
     fn get_object_bytes<'a>(
         &self,
         object_ref: ObjectReference,
@@ -822,6 +922,36 @@ impl IpcContextServer {
                         }
                     }
                 }
+                ContextRequest::GetContextDiff(from_context_hash, to_context_hash, mut request) => {
+                    match crate::ffi::get_context_index()? {
+                        None => io.tx.send(&ContextResponse::GetContextDiffResponse(Err(
+                            "Context index unavailable".to_owned(),
+                        )))?,
+                        Some(index) => {
+                            request.limit = request.limit.min(CONTEXT_DIFF_MAX_LIMIT);
+                            let res = index
+                                .get_context_diff(&from_context_hash, &to_context_hash, &request)
+                                .map_err(|err| format!("Context error: {:?}", err));
+
+                            io.tx.send(&ContextResponse::GetContextDiffResponse(res))?;
+                        }
+                    }
+                }
+                ContextRequest::GetKeyHistory(key, from_level, to_level) => {
+                    match crate::ffi::get_context_index()? {
+                        None => io.tx.send(&ContextResponse::GetKeyHistoryResponse(Err(
+                            "Context index unavailable".to_owned(),
+                        )))?,
+                        Some(index) => {
+                            let key: Vec<&str> = key.iter().map(String::as_str).collect();
+                            let res = index
+                                .get_key_history(&key, from_level, to_level)
+                                .map_err(|err| format!("Context error: {:?}", err));
+
+                            io.tx.send(&ContextResponse::GetKeyHistoryResponse(res))?;
+                        }
+                    }
+                }
                 ContextRequest::GetHashId(object_ref) => match crate::ffi::get_context_index()? {
                     None => io.tx.send(&ContextResponse::GetHashIdResponse(Err(
                         "Context index unavailable".to_owned(),
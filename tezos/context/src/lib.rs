@@ -130,8 +130,10 @@ macro_rules! elog {
 }
 
 pub mod chunks;
+pub mod export;
 pub mod gc;
 pub mod hash;
+pub mod proof;
 pub mod serialize;
 pub mod working_tree;
 
@@ -140,6 +142,7 @@ pub mod from_ocaml;
 pub mod initializer;
 pub mod timings;
 
+pub mod scrub;
 pub mod snapshot;
 
 pub fn force_libtezos_linking() {
@@ -154,7 +157,10 @@ use gc::GarbageCollectionError;
 pub use kv_store::persistent::Persistent;
 
 use persistent::{DBError, KeyValueStoreBackend};
-use tezos_context_api::{ContextKey, ContextKeyOwned, ContextValue, StringTreeObject};
+use tezos_context_api::{
+    ContextDiff, ContextDiffEntry, ContextDiffRequest, ContextKey, ContextKeyHistory,
+    ContextKeyOwned, ContextValue, StringTreeObject,
+};
 use thiserror::Error;
 
 pub use hash::ObjectHash;
@@ -249,6 +255,27 @@ pub trait IndexApi<T: ShellContextApi + ProtocolContextApi> {
         prefix: &ContextKey,
         depth: Option<usize>,
     ) -> Result<StringTreeObject, ContextError>;
+    // get a page of the values added, removed or modified between two commits, sorted by key
+    fn get_context_diff(
+        &self,
+        from_context_hash: &ContextHash,
+        to_context_hash: &ContextHash,
+        request: &ContextDiffRequest,
+    ) -> Result<ContextDiff, ContextError>;
+    // get the values of `key` changed by the blocks applied between `from_level` and `to_level`,
+    // `None` when the changes are not recorded, see `persistent::key_history`
+    fn get_key_history(
+        &self,
+        key: &ContextKey,
+        from_level: u32,
+        to_level: u32,
+    ) -> Result<Option<ContextKeyHistory>, ContextError>;
+    // get a merkle proof of the values of `keys`, see `proof::TreeProof`
+    fn produce_tree_proof(
+        &self,
+        context_hash: &ContextHash,
+        keys: &[ContextKeyOwned],
+    ) -> Result<proof::TreeProof, ContextError>;
 }
 
 /// Context API used by the Shell
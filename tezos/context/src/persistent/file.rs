@@ -28,6 +28,8 @@ pub enum FileType {
     BigStrings,
     Hashes,
     Sizes,
+    KeyHistory,
+    KeyHistoryIndex,
 }
 
 type TaggedFile = u64;
@@ -40,6 +42,8 @@ pub const TAG_STRINGS: u64 = 4;
 pub const TAG_BIG_STRINGS: u64 = 5;
 pub const TAG_HASHES: u64 = 6;
 pub const TAG_SIZES: u64 = 7;
+pub const TAG_KEY_HISTORY: u64 = 8;
+pub const TAG_KEY_HISTORY_INDEX: u64 = 9;
 
 impl From<FileType> for u64 {
     fn from(file_type: FileType) -> Self {
@@ -52,6 +56,8 @@ impl From<FileType> for u64 {
             FileType::BigStrings => TAG_BIG_STRINGS,
             FileType::Hashes => TAG_HASHES,
             FileType::Sizes => TAG_SIZES,
+            FileType::KeyHistory => TAG_KEY_HISTORY,
+            FileType::KeyHistoryIndex => TAG_KEY_HISTORY_INDEX,
         }
     }
 }
@@ -67,6 +73,8 @@ impl From<u64> for FileType {
             TAG_BIG_STRINGS => FileType::BigStrings,
             TAG_HASHES => FileType::Hashes,
             TAG_SIZES => FileType::Sizes,
+            TAG_KEY_HISTORY => FileType::KeyHistory,
+            TAG_KEY_HISTORY_INDEX => FileType::KeyHistoryIndex,
             _ => unreachable!(), // error at compile time
         }
     }
@@ -91,6 +99,11 @@ pub enum HeaderError {
 const PERSISTENT_BASE_PATH: &str = "db_persistent";
 
 impl FileType {
+    /// Files written with `File::write_all_at`, all others are append only
+    fn is_writable_at(&self) -> bool {
+        matches!(self, FileType::Sizes | FileType::KeyHistoryIndex)
+    }
+
     fn get_path(&self) -> &Path {
         match self {
             FileType::ShapeDirectories => Path::new("shape_directories.db"),
@@ -101,6 +114,8 @@ impl FileType {
             FileType::Hashes => Path::new("hashes.db"),
             FileType::BigStrings => Path::new("big_strings.db"),
             FileType::Sizes => Path::new("sizes.db"),
+            FileType::KeyHistory => Path::new("key_history.db"),
+            FileType::KeyHistoryIndex => Path::new("key_history_index.db"),
         }
     }
 }
@@ -182,7 +197,7 @@ impl<const T: TaggedFile> File<T> {
             remove_file_when_empty(&filepath)?;
         }
 
-        let append_mode = !matches!(file_type, FileType::Sizes);
+        let append_mode = !file_type.is_writable_at();
         let mut options = OpenOptions::new();
 
         if read_only {
@@ -227,7 +242,7 @@ impl<const T: TaggedFile> File<T> {
         std::fs::create_dir_all(&base_path)?;
 
         let file_type: FileType = T.into();
-        let append_mode = !matches!(file_type, FileType::Sizes);
+        let append_mode = !file_type.is_writable_at();
 
         OpenOptions::new()
             .read(true)
@@ -341,6 +356,15 @@ impl<const T: TaggedFile> File<T> {
         Ok(())
     }
 
+    /// Move the offset of a read only file to its end, the file might have been
+    /// appended by the writer since it was opened
+    pub fn refresh_read_only_offset(&mut self) -> Result<(), io::Error> {
+        if self.read_only {
+            self.offset = self.file.seek(SeekFrom::End(0))?;
+        }
+        Ok(())
+    }
+
     pub fn truncate(&mut self, new_size: u64) -> Result<(), io::Error> {
         if new_size != self.offset {
             assert!(new_size < self.offset);
@@ -404,8 +428,9 @@ impl<const T: TaggedFile> File<T> {
 
         assert!(!self.read_only);
 
-        // This method must be used with TAG_SIZES only, other files are append only
-        assert_eq!(T, TAG_SIZES);
+        // This method must be used with TAG_SIZES and TAG_KEY_HISTORY_INDEX only, other
+        // files are append only
+        assert!(FileType::from(T).is_writable_at());
 
         let bytes = bytes.as_ref();
         self.file.write_all_at(bytes, offset.as_u64())
@@ -0,0 +1,656 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! History of the values of each key, recorded when blocks are applied.
+//!
+//! This is an optional index, stored next to the files of the persistent context and
+//! enabled with `TezosContextTezedgeOnDiskBackendOptions::key_history`.
+//! It answers queries like "values of this key over the last 10000 blocks" without
+//! checking out the context of each of those blocks.
+//!
+//! Values are not copied: a change refers to the blob object in the repository, only
+//! the values inlined in their directory (which have no object) are written here.
+//!
+//! The changes are written in `key_history.db`, each change points to the previous
+//! change of the same key. `key_history_index.db` is a hash table from a key to its
+//! last change, so nothing grows in memory with the number of keys or blocks.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+};
+
+use blake2::{
+    digest::{Update, VariableOutput},
+    VarBlake2b,
+};
+use tezos_context_api::ContextValue;
+
+use crate::serialize::persistent::AbsoluteOffset;
+
+use super::file::{File, TAG_KEY_HISTORY, TAG_KEY_HISTORY_INDEX};
+
+/// Number of blocks whose changes can be discarded.
+///
+/// When a block is applied at a level lower or equal to one of those blocks (a branch
+/// switch, or a block applied again after a restart), the changes at those levels are
+/// discarded.
+const RECENT_BLOCKS_LENGTH: usize = 120;
+
+/// Key of the change written for a level whose changes could not be recorded.
+///
+/// A key of the context is never empty.
+const MISSING_LEVEL_KEY: &str = "";
+
+const BLOCK_HEADER_LENGTH: usize = 16;
+const CHANGE_HEADER_LENGTH: usize = 25;
+
+const VALUE_REMOVED: u8 = 0;
+const VALUE_OBJECT: u8 = 1;
+const VALUE_INLINED: u8 = 2;
+const INLINED_VALUE_MAX_LENGTH: usize = 7;
+
+const META_LENGTH: usize = 36 + RECENT_BLOCKS_LENGTH * 12;
+const FIRST_TABLE_OFFSET: u64 = 4096;
+const FIRST_TABLE_CAPACITY: u64 = 1 << 16;
+const BUCKET_LENGTH: u64 = 16;
+/// Number of buckets read at once when the table grows
+const GROW_CHUNK_LENGTH: u64 = 4096;
+
+/// Value of a key after a change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyHistoryValue {
+    Removed,
+    /// Offset of the blob object in the repository
+    Object(AbsoluteOffset),
+    /// Value inlined in its directory, it has no object in the repository
+    Inlined(ContextValue),
+}
+
+/// Change of a key made by an applied block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyHistoryChange {
+    /// The key, with its parts separated by `/`
+    pub key: String,
+    pub value: KeyHistoryValue,
+}
+
+/// Changes of a key over a range of levels, see `KeyHistory::get`
+#[derive(Debug, Default)]
+pub struct KeyHistoryChanges {
+    /// Level and value of each change, ordered by level
+    pub changes: Vec<(u32, KeyHistoryValue)>,
+    /// Levels of the range whose changes could not be recorded
+    pub missing_levels: Vec<u32>,
+}
+
+/// Change read from `KeyHistory::changes` to update the index
+struct IndexedChange {
+    key_hash: u64,
+    offset: u64,
+    previous: u64,
+}
+
+pub struct KeyHistory {
+    /// Changes of all applied blocks, append only.
+    ///
+    /// File format:
+    /// For each applied block:
+    /// [level (u32), number of changes (u32), length of the changes (u64), [change], ..]
+    ///
+    /// A change is:
+    /// [level (u32), offset of the previous change (u64), value kind (u8), value (8 bytes),
+    ///  key length (u32), key (nbytes)]
+    ///
+    /// The previous change is the last change of a key with the same hash, or 0.
+    /// The value is the offset of the blob object, or the length (u8) and bytes of an
+    /// inlined value, or zeros when the key was removed.
+    changes: File<{ TAG_KEY_HISTORY }>,
+    /// Hash table from the hash of a key to the offset of its last change in `changes`.
+    ///
+    /// File format:
+    /// [metadata (see `Self::write_meta`), .., table]
+    ///
+    /// A table has `capacity` buckets [key hash (u64), offset (u64)], a key hash of 0
+    /// is an empty bucket. Collisions are resolved by linear probing.
+    /// When it is half full, a table twice larger is written after it.
+    index: File<{ TAG_KEY_HISTORY_INDEX }>,
+    /// Offset in `changes` until which the blocks are in the index
+    indexed_until: u64,
+    table_offset: u64,
+    capacity: u64,
+    /// Number of used buckets in the table
+    length: u64,
+    /// Level and offset in `changes` of the last `RECENT_BLOCKS_LENGTH` blocks
+    recent_blocks: VecDeque<(u32, u64)>,
+}
+
+impl KeyHistory {
+    /// Open the index, and add to it the blocks written after it was last updated.
+    ///
+    /// An incomplete block at the end of `changes`, left by a crash, is truncated.
+    /// When `index` is new, it is rebuilt from `changes`.
+    pub fn load(
+        changes: File<{ TAG_KEY_HISTORY }>,
+        mut index: File<{ TAG_KEY_HISTORY_INDEX }>,
+        read_only: bool,
+    ) -> Result<Self, io::Error> {
+        let is_new_index = index.offset().as_u64() <= index.start();
+        if is_new_index {
+            if read_only {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "Key history index not found",
+                ));
+            }
+            // Extend the file with the empty table
+            write_bucket(
+                &mut index,
+                FIRST_TABLE_OFFSET,
+                FIRST_TABLE_CAPACITY - 1,
+                0,
+                0,
+            )?;
+        }
+
+        let mut history = Self {
+            indexed_until: changes.start(),
+            changes,
+            index,
+            table_offset: FIRST_TABLE_OFFSET,
+            capacity: FIRST_TABLE_CAPACITY,
+            length: 0,
+            recent_blocks: VecDeque::with_capacity(RECENT_BLOCKS_LENGTH),
+        };
+
+        if is_new_index {
+            history.write_meta()?;
+        } else {
+            history.read_meta()?;
+
+            let table_end = history.table_offset + history.capacity * BUCKET_LENGTH;
+            if !read_only && history.index.offset().as_u64() > table_end {
+                // A larger table was being written
+                history.index.truncate(table_end)?;
+            }
+        }
+
+        if !read_only {
+            history.index_new_blocks()?;
+        }
+
+        Ok(history)
+    }
+
+    /// Write the changes of the block applied at `level`.
+    ///
+    /// The changes of the recent blocks applied at `level` or higher are discarded.
+    pub fn append(&mut self, level: u32, changes: &[KeyHistoryChange]) -> Result<(), io::Error> {
+        self.discard_from(level)?;
+
+        let start = self.changes.offset().as_u64();
+
+        let mut bytes = Vec::with_capacity(BLOCK_HEADER_LENGTH + changes.len() * 64);
+        bytes.extend_from_slice(&level.to_le_bytes());
+        bytes.extend_from_slice(&(changes.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&[0; 8]); // Replaced by the length of the changes
+
+        // Last change of each key hash, two keys of this block might have the same hash
+        let mut last_changes: HashMap<u64, u64> = HashMap::with_capacity(changes.len());
+
+        for change in changes {
+            let key_hash = key_hash(change.key.as_bytes());
+            let previous = match last_changes.get(&key_hash) {
+                Some(offset) => *offset,
+                None => self.last_change(key_hash)?,
+            };
+            let offset = start + bytes.len() as u64;
+
+            write_change(&mut bytes, level, previous, change)?;
+            last_changes.insert(key_hash, offset);
+        }
+
+        let length = (bytes.len() - BLOCK_HEADER_LENGTH) as u64;
+        bytes[8..BLOCK_HEADER_LENGTH].copy_from_slice(&length.to_le_bytes());
+
+        if let Err(e) = self.changes.append(&bytes) {
+            // Do not leave a partial block before the next one
+            self.changes.truncate(start)?;
+            return Err(e);
+        }
+
+        for (key_hash, offset) in last_changes {
+            self.set_last_change(key_hash, offset)?;
+        }
+        self.push_recent_block(level, start);
+        self.indexed_until = self.changes.offset().as_u64();
+
+        self.write_meta()
+    }
+
+    /// Record that the changes of the block applied at `level` are unknown,
+    /// `Self::get` reports it.
+    pub fn append_missing(&mut self, level: u32) -> Result<(), io::Error> {
+        let change = KeyHistoryChange {
+            key: MISSING_LEVEL_KEY.to_string(),
+            value: KeyHistoryValue::Removed,
+        };
+        self.append(level, &[change])
+    }
+
+    /// Returns the changes of `key` made by the blocks applied between `from_level` and
+    /// `to_level` (inclusive), ordered by level, and the levels of that range whose
+    /// changes could not be recorded.
+    ///
+    /// The last change before `from_level` is included too: it is the value
+    /// of the key at `from_level`.
+    pub fn get(
+        &self,
+        key: &str,
+        from_level: u32,
+        to_level: u32,
+    ) -> Result<KeyHistoryChanges, io::Error> {
+        let changes = self.key_changes(key, from_level, to_level)?;
+        let missing_levels = self
+            .key_changes(MISSING_LEVEL_KEY, from_level, to_level)?
+            .into_iter()
+            .map(|(level, _)| level)
+            .filter(|level| *level >= from_level)
+            .collect();
+
+        Ok(KeyHistoryChanges {
+            changes,
+            missing_levels,
+        })
+    }
+
+    /// See `Self::get`, follows the changes of `key` from the last one
+    fn key_changes(
+        &self,
+        key: &str,
+        from_level: u32,
+        to_level: u32,
+    ) -> Result<Vec<(u32, KeyHistoryValue)>, io::Error> {
+        let mut changes = Vec::new();
+        let mut offset = self.last_change(key_hash(key.as_bytes()))?;
+
+        while offset != 0 {
+            let mut header = [0; CHANGE_HEADER_LENGTH];
+            self.changes.read_exact_at(&mut header, offset.into())?;
+
+            let (level, previous, value, key_length) = read_change_header(&header)?;
+
+            let mut change_key = vec![0; key_length];
+            self.changes.read_exact_at(
+                &mut change_key,
+                (offset + CHANGE_HEADER_LENGTH as u64).into(),
+            )?;
+
+            if change_key == key.as_bytes() && level <= to_level {
+                changes.push((level, value));
+                if level < from_level {
+                    break;
+                }
+            }
+            offset = previous;
+        }
+
+        changes.reverse();
+        Ok(changes)
+    }
+
+    /// Add to the index the blocks written after `Self::indexed_until`
+    fn index_new_blocks(&mut self) -> Result<(), io::Error> {
+        let end = self.changes.offset().as_u64();
+        let mut offset = self.indexed_until;
+
+        while offset < end {
+            let (level, changes, next_offset) = match self.read_block(offset, end)? {
+                Some(block) => block,
+                None => {
+                    elog!(
+                        "Key history: truncating incomplete block at offset {:?}",
+                        offset
+                    );
+                    self.changes.truncate(offset)?;
+                    break;
+                }
+            };
+
+            self.discard_from(level)?;
+            for change in changes {
+                self.set_last_change(change.key_hash, change.offset)?;
+            }
+            self.push_recent_block(level, offset);
+
+            offset = next_offset;
+        }
+
+        self.indexed_until = offset;
+        self.write_meta()
+    }
+
+    /// Discard the changes of the recent blocks applied at `level` or higher: the last
+    /// change of their keys becomes the change before them.
+    fn discard_from(&mut self, level: u32) -> Result<(), io::Error> {
+        let end = self.changes.offset().as_u64();
+        let mut discarded = false;
+
+        while let Some(&(block_level, block_offset)) = self.recent_blocks.back() {
+            if block_level < level {
+                break;
+            }
+
+            let (_, changes, _) = self.read_block(block_offset, end)?.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "Recent block not found")
+            })?;
+
+            for change in changes.iter().rev() {
+                if self.last_change(change.key_hash)? == change.offset {
+                    self.set_last_change(change.key_hash, change.previous)?;
+                }
+            }
+
+            self.recent_blocks.pop_back();
+            discarded = true;
+        }
+
+        if discarded {
+            self.write_meta()?;
+        }
+        Ok(())
+    }
+
+    fn push_recent_block(&mut self, level: u32, offset: u64) {
+        if self.recent_blocks.len() == RECENT_BLOCKS_LENGTH {
+            self.recent_blocks.pop_front();
+        }
+        self.recent_blocks.push_back((level, offset));
+    }
+
+    /// Read the block starting at `offset`.
+    ///
+    /// Returns its level, its changes and the offset of the next block, or `None` when
+    /// the block ends after `end`.
+    fn read_block(
+        &self,
+        offset: u64,
+        end: u64,
+    ) -> Result<Option<(u32, Vec<IndexedChange>, u64)>, io::Error> {
+        if offset + BLOCK_HEADER_LENGTH as u64 > end {
+            return Ok(None);
+        }
+
+        let mut header = [0; BLOCK_HEADER_LENGTH];
+        self.changes.read_exact_at(&mut header, offset.into())?;
+
+        let level = u32::from_le_bytes(header[0..4].try_into().unwrap()); // never fail
+        let nchanges = u32::from_le_bytes(header[4..8].try_into().unwrap()); // never fail
+        let length = u64::from_le_bytes(header[8..16].try_into().unwrap()); // never fail
+
+        let changes_offset = offset + BLOCK_HEADER_LENGTH as u64;
+        if changes_offset + length > end {
+            return Ok(None);
+        }
+
+        let mut bytes = vec![0; length as usize];
+        self.changes
+            .read_exact_at(&mut bytes, changes_offset.into())?;
+
+        let mut changes = Vec::with_capacity(nchanges as usize);
+        let mut pos = 0;
+
+        for _ in 0..nchanges {
+            let header = bytes
+                .get(pos..pos + CHANGE_HEADER_LENGTH)
+                .ok_or_else(invalid_block)?;
+            let (_, previous, _, key_length) = read_change_header(header)?;
+
+            let key_start = pos + CHANGE_HEADER_LENGTH;
+            let key = bytes
+                .get(key_start..key_start + key_length)
+                .ok_or_else(invalid_block)?;
+
+            changes.push(IndexedChange {
+                key_hash: key_hash(key),
+                offset: changes_offset + pos as u64,
+                previous,
+            });
+            pos = key_start + key_length;
+        }
+
+        Ok(Some((level, changes, changes_offset + length)))
+    }
+
+    /// Returns the offset of the last change of the keys with this hash, 0 when none
+    fn last_change(&self, key_hash: u64) -> Result<u64, io::Error> {
+        let (_, _, offset) = self.find_bucket(key_hash)?;
+        Ok(offset)
+    }
+
+    fn set_last_change(&mut self, key_hash: u64, offset: u64) -> Result<(), io::Error> {
+        let (slot, is_used, _) = self.find_bucket(key_hash)?;
+
+        write_bucket(&mut self.index, self.table_offset, slot, key_hash, offset)?;
+
+        if !is_used {
+            self.length += 1;
+            if self.length * 2 > self.capacity {
+                self.grow()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the slot of the bucket of `key_hash` (or of the empty bucket where it would
+    /// be inserted), if it is used, and its offset
+    fn find_bucket(&self, key_hash: u64) -> Result<(u64, bool, u64), io::Error> {
+        let mask = self.capacity - 1;
+        let mut slot = key_hash & mask;
+
+        loop {
+            let (hash, offset) = read_bucket(&self.index, self.table_offset, slot)?;
+
+            if hash == key_hash {
+                return Ok((slot, true, offset));
+            } else if hash == 0 {
+                return Ok((slot, false, 0));
+            }
+            slot = (slot + 1) & mask;
+        }
+    }
+
+    /// Replace the table by a table twice larger, written after it
+    fn grow(&mut self) -> Result<(), io::Error> {
+        let capacity = self.capacity * 2;
+        let table_offset = self.table_offset + self.capacity * BUCKET_LENGTH;
+        let mask = capacity - 1;
+
+        write_bucket(&mut self.index, table_offset, capacity - 1, 0, 0)?;
+
+        let mut buffer = vec![0; (GROW_CHUNK_LENGTH * BUCKET_LENGTH) as usize];
+        let mut first_slot = 0;
+
+        while first_slot < self.capacity {
+            let nbuckets = GROW_CHUNK_LENGTH.min(self.capacity - first_slot);
+            let chunk = &mut buffer[..(nbuckets * BUCKET_LENGTH) as usize];
+            self.index.read_exact_at(
+                chunk,
+                (self.table_offset + first_slot * BUCKET_LENGTH).into(),
+            )?;
+
+            for bucket in chunk.chunks_exact(BUCKET_LENGTH as usize) {
+                let hash = u64::from_le_bytes(bucket[0..8].try_into().unwrap()); // never fail
+                let offset = u64::from_le_bytes(bucket[8..16].try_into().unwrap()); // never fail
+                if hash == 0 {
+                    continue;
+                }
+
+                let mut slot = hash & mask;
+                while read_bucket(&self.index, table_offset, slot)?.0 != 0 {
+                    slot = (slot + 1) & mask;
+                }
+                write_bucket(&mut self.index, table_offset, slot, hash, offset)?;
+            }
+
+            first_slot += nbuckets;
+        }
+
+        self.table_offset = table_offset;
+        self.capacity = capacity;
+
+        self.write_meta()
+    }
+
+    /// Write the metadata of the index, after the header of `Self::index`:
+    /// [indexed until (u64), table offset (u64), capacity (u64), length (u64),
+    ///  number of recent blocks (u32), [level (u32), offset (u64)], ..]
+    fn write_meta(&mut self) -> Result<(), io::Error> {
+        let mut bytes = Vec::with_capacity(META_LENGTH);
+
+        bytes.extend_from_slice(&self.indexed_until.to_le_bytes());
+        bytes.extend_from_slice(&self.table_offset.to_le_bytes());
+        bytes.extend_from_slice(&self.capacity.to_le_bytes());
+        bytes.extend_from_slice(&self.length.to_le_bytes());
+        bytes.extend_from_slice(&(self.recent_blocks.len() as u32).to_le_bytes());
+
+        for (level, offset) in &self.recent_blocks {
+            bytes.extend_from_slice(&level.to_le_bytes());
+            bytes.extend_from_slice(&offset.to_le_bytes());
+        }
+
+        let start = self.index.start();
+        self.index.write_all_at(&bytes, start.into())
+    }
+
+    fn read_meta(&mut self) -> Result<(), io::Error> {
+        let mut bytes = vec![0; META_LENGTH];
+        self.index
+            .read_exact_at(&mut bytes, self.index.start().into())?;
+
+        // never fail
+        let read_u64 = |pos: usize| u64::from_le_bytes(bytes[pos..pos + 8].try_into().unwrap());
+
+        self.indexed_until = read_u64(0);
+        self.table_offset = read_u64(8);
+        self.capacity = read_u64(16);
+        self.length = read_u64(24);
+
+        let nrecent = u32::from_le_bytes(bytes[32..36].try_into().unwrap()); // never fail
+        let nrecent = nrecent as usize;
+        if !self.capacity.is_power_of_two() || nrecent > RECENT_BLOCKS_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid key history index",
+            ));
+        }
+
+        self.recent_blocks.clear();
+        for index in 0..nrecent {
+            let pos = 36 + index * 12;
+            let level = u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap()); // never fail
+            self.recent_blocks.push_back((level, read_u64(pos + 4)));
+        }
+
+        Ok(())
+    }
+}
+
+/// Hash of `key` in the index, never 0 (an empty bucket)
+fn key_hash(key: &[u8]) -> u64 {
+    let mut hasher = VarBlake2b::new(8).unwrap();
+    hasher.update(key);
+    let hash = hasher.finalize_boxed();
+
+    u64::from_le_bytes(hash[..8].try_into().unwrap()).max(1) // never fail
+}
+
+fn read_bucket(
+    index: &File<{ TAG_KEY_HISTORY_INDEX }>,
+    table_offset: u64,
+    slot: u64,
+) -> Result<(u64, u64), io::Error> {
+    let mut bytes = [0; BUCKET_LENGTH as usize];
+    index.read_exact_at(&mut bytes, (table_offset + slot * BUCKET_LENGTH).into())?;
+
+    let hash = u64::from_le_bytes(bytes[0..8].try_into().unwrap()); // never fail
+    let offset = u64::from_le_bytes(bytes[8..16].try_into().unwrap()); // never fail
+
+    Ok((hash, offset))
+}
+
+fn write_bucket(
+    index: &mut File<{ TAG_KEY_HISTORY_INDEX }>,
+    table_offset: u64,
+    slot: u64,
+    hash: u64,
+    offset: u64,
+) -> Result<(), io::Error> {
+    let mut bytes = [0; BUCKET_LENGTH as usize];
+    bytes[0..8].copy_from_slice(&hash.to_le_bytes());
+    bytes[8..16].copy_from_slice(&offset.to_le_bytes());
+
+    index.write_all_at(bytes, (table_offset + slot * BUCKET_LENGTH).into())
+}
+
+fn write_change(
+    output: &mut Vec<u8>,
+    level: u32,
+    previous: u64,
+    change: &KeyHistoryChange,
+) -> Result<(), io::Error> {
+    let mut value = [0; 8];
+    let kind = match &change.value {
+        KeyHistoryValue::Removed => VALUE_REMOVED,
+        KeyHistoryValue::Object(offset) => {
+            value.copy_from_slice(&offset.as_u64().to_le_bytes());
+            VALUE_OBJECT
+        }
+        KeyHistoryValue::Inlined(inlined) => {
+            if inlined.len() > INLINED_VALUE_MAX_LENGTH {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Inlined value too long",
+                ));
+            }
+            value[0] = inlined.len() as u8;
+            value[1..1 + inlined.len()].copy_from_slice(inlined);
+            VALUE_INLINED
+        }
+    };
+
+    output.extend_from_slice(&level.to_le_bytes());
+    output.extend_from_slice(&previous.to_le_bytes());
+    output.push(kind);
+    output.extend_from_slice(&value);
+    output.extend_from_slice(&(change.key.len() as u32).to_le_bytes());
+    output.extend_from_slice(change.key.as_bytes());
+
+    Ok(())
+}
+
+/// Returns the level, the offset of the previous change, the value and the key length
+fn read_change_header(header: &[u8]) -> Result<(u32, u64, KeyHistoryValue, usize), io::Error> {
+    let level = u32::from_le_bytes(header[0..4].try_into().unwrap()); // never fail
+    let previous = u64::from_le_bytes(header[4..12].try_into().unwrap()); // never fail
+    let value = &header[13..21];
+    let key_length = u32::from_le_bytes(header[21..25].try_into().unwrap()); // never fail
+
+    let value = match header[12] {
+        VALUE_REMOVED => KeyHistoryValue::Removed,
+        VALUE_OBJECT => {
+            let offset = u64::from_le_bytes(value.try_into().unwrap()); // never fail
+            KeyHistoryValue::Object(offset.into())
+        }
+        VALUE_INLINED if value[0] as usize <= INLINED_VALUE_MAX_LENGTH => {
+            KeyHistoryValue::Inlined(value[1..1 + value[0] as usize].to_vec())
+        }
+        _ => return Err(invalid_block()),
+    };
+
+    Ok((level, previous, value, key_length as usize))
+}
+
+fn invalid_block() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Invalid key history block")
+}
@@ -11,11 +11,13 @@ use std::{
 
 use crypto::hash::ContextHash;
 use parking_lot::RwLock;
+use tezos_context_api::{ContextKey, ContextKeyHistory};
 use thiserror::Error;
 
 use tezos_timing::{RepositoryMemoryUsage, SerializeStats};
 
 #[cfg(test)]
+use crate::persistent::key_history::KeyHistoryChange;
 use crate::serialize::persistent::AbsoluteOffset;
 
 use crate::{
@@ -37,6 +39,7 @@ use crate::{
 };
 
 pub mod file;
+pub mod key_history;
 pub mod lock;
 
 pub trait Flushable {
@@ -161,6 +164,26 @@ pub trait KeyValueStoreBackend {
     fn get_read_statistics(&self) -> Result<Option<ReadStatistics>, DBError>;
     /// Simulate a `commit`, by writing data to disk/memory, without computing hash
     fn latest_context_hashes(&self, count: i64) -> Result<Vec<ContextHash>, DBError>;
+    /// Check if the changes of each applied block are recorded, see `key_history`
+    fn is_key_history_enabled(&self) -> bool;
+    /// Record the values changed by the block applied at `block_level`
+    fn put_key_history(
+        &mut self,
+        block_level: u32,
+        changes: &[KeyHistoryChange],
+    ) -> Result<(), DBError>;
+    /// Record that the values changed by the block applied at `block_level` are unknown
+    fn put_key_history_missing(&mut self, block_level: u32) -> Result<(), DBError>;
+    /// Returns the values of `key` changed by the blocks applied between `from_level`
+    /// and `to_level`, ordered by level, see `key_history::KeyHistory::get`
+    ///
+    /// Returns `None` when the changes are not recorded.
+    fn get_key_history(
+        &self,
+        key: &ContextKey,
+        from_level: u32,
+        to_level: u32,
+    ) -> Result<Option<ContextKeyHistory>, DBError>;
     #[cfg(test)]
     fn synchronize_data(
         &mut self,
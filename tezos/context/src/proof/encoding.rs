@@ -0,0 +1,440 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Binary encoding of tree proofs, following the Octez `Context.Proof` encoding
+//! (`Merkle_proof_encoding.V1.Tree32`).
+//!
+//! ```text
+//! tree_proof     = version: int16, before: kinded_hash, after: kinded_hash, state: tree
+//! kinded_hash    = tag 0: Value (hash) | tag 1: Node (hash)
+//! tree           = tag 0: Value (bytes) | tag 1: Blinded_value (hash)
+//!                | tag 2: Node (list of step, tree) | tag 3: Blinded_node (hash)
+//!                | tag 4: Inode (inode) | tag 5: Extender (extender)
+//! inode_tree     = tag 0: Blinded_inode (hash) | tag 1: Inode_values (list of step, tree)
+//!                | tag 2: Inode_tree (inode) | tag 3: Inode_extender (extender)
+//! inode          = length: int64, proofs
+//! proofs         = tag 0: sparse (list of uint8 index, inode_tree)
+//!                | tag 1: dense (32 times option of inode_tree)
+//! extender       = length: int64, segment, proof: inode_tree
+//! ```
+//!
+//! Lists and bytes are prefixed by their size in bytes (`uint30`), steps by their length
+//! (`uint8`). A segment is a bit sequence of 5 bits per index, followed by a `1` bit and
+//! padded with `0` bits to a full byte, prefixed by its length (`uint8`).
+//!
+//! The compact `Merkle_proof_encoding.V2.Tree32`, used since Jakarta and by the `merkle_tree_v2`
+//! rpc of Octez, is not implemented: requesting it fails with `ProofError::UnsupportedEncoding`.
+
+use std::convert::TryInto;
+
+use crate::hash::{ObjectHash, OBJECT_HASH_LEN};
+
+use super::{
+    Inode, InodeExtender, InodeTree, KindedHash, ProofError, Tree, TreeProof, INODE_ENTRIES,
+};
+
+const SEGMENT_INDEX_BITS: usize = 5;
+
+/// Maximum nesting accepted when decoding, a real tree is never close to this
+const MAX_DECODE_DEPTH: usize = 1024;
+
+/// Versions of the Octez `Merkle_proof_encoding`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofEncoding {
+    V1,
+    V2,
+}
+
+impl TreeProof {
+    pub fn to_bytes_with(&self, encoding: ProofEncoding) -> Result<Vec<u8>, ProofError> {
+        match encoding {
+            ProofEncoding::V1 => self.to_bytes(),
+            ProofEncoding::V2 => Err(ProofError::UnsupportedEncoding { encoding }),
+        }
+    }
+
+    pub fn from_bytes_with(bytes: &[u8], encoding: ProofEncoding) -> Result<Self, ProofError> {
+        match encoding {
+            ProofEncoding::V1 => Self::from_bytes(bytes),
+            ProofEncoding::V2 => Err(ProofError::UnsupportedEncoding { encoding }),
+        }
+    }
+
+    /// Encodes the proof with `ProofEncoding::V1`
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ProofError> {
+        let mut output = Vec::new();
+        output.extend_from_slice(&self.version.to_be_bytes());
+        write_kinded_hash(&mut output, &self.before);
+        write_kinded_hash(&mut output, &self.after);
+        write_tree(&mut output, &self.state)?;
+        Ok(output)
+    }
+
+    /// Decodes a proof encoded with `ProofEncoding::V1`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofError> {
+        let mut input = Reader { bytes, depth: 0 };
+
+        let version = u16::from_be_bytes(input.array()?);
+        let before = input.kinded_hash()?;
+        let after = input.kinded_hash()?;
+        let state = input.tree()?;
+
+        if !input.bytes.is_empty() {
+            return Err(decode_error("trailing bytes"));
+        }
+
+        Ok(Self {
+            version,
+            before,
+            after,
+            state,
+        })
+    }
+}
+
+fn decode_error(reason: &str) -> ProofError {
+    ProofError::DecodeError {
+        reason: reason.to_string(),
+    }
+}
+
+fn encode_error(reason: &str) -> ProofError {
+    ProofError::EncodeError {
+        reason: reason.to_string(),
+    }
+}
+
+fn write_kinded_hash(output: &mut Vec<u8>, hash: &KindedHash) {
+    match hash {
+        KindedHash::Value(_) => output.push(0),
+        KindedHash::Node(_) => output.push(1),
+    }
+    output.extend_from_slice(hash.hash());
+}
+
+fn write_dynamic<F>(output: &mut Vec<u8>, f: F) -> Result<(), ProofError>
+where
+    F: FnOnce(&mut Vec<u8>) -> Result<(), ProofError>,
+{
+    let start = output.len();
+    output.extend_from_slice(&[0; 4]);
+    f(output)?;
+
+    let size: u32 = (output.len() - start - 4)
+        .try_into()
+        .map_err(|_| encode_error("proof too large"))?;
+    output[start..start + 4].copy_from_slice(&size.to_be_bytes());
+    Ok(())
+}
+
+fn write_entries(output: &mut Vec<u8>, entries: &[(String, Tree)]) -> Result<(), ProofError> {
+    write_dynamic(output, |output| {
+        for (step, tree) in entries {
+            let length: u8 = step
+                .len()
+                .try_into()
+                .map_err(|_| encode_error("step longer than 255 bytes"))?;
+            output.push(length);
+            output.extend_from_slice(step.as_bytes());
+            write_tree(output, tree)?;
+        }
+        Ok(())
+    })
+}
+
+fn write_tree(output: &mut Vec<u8>, tree: &Tree) -> Result<(), ProofError> {
+    match tree {
+        Tree::Value(value) => {
+            output.push(0);
+            write_dynamic(output, |output| {
+                output.extend_from_slice(value);
+                Ok(())
+            })
+        }
+        Tree::BlindedValue(hash) => {
+            output.push(1);
+            output.extend_from_slice(hash);
+            Ok(())
+        }
+        Tree::Node(entries) => {
+            output.push(2);
+            write_entries(output, entries)
+        }
+        Tree::BlindedNode(hash) => {
+            output.push(3);
+            output.extend_from_slice(hash);
+            Ok(())
+        }
+        Tree::Inode(inode) => {
+            output.push(4);
+            write_inode(output, inode)
+        }
+        Tree::Extender(extender) => {
+            output.push(5);
+            write_extender(output, extender)
+        }
+    }
+}
+
+fn write_inode_tree(output: &mut Vec<u8>, tree: &InodeTree) -> Result<(), ProofError> {
+    match tree {
+        InodeTree::BlindedInode(hash) => {
+            output.push(0);
+            output.extend_from_slice(hash);
+            Ok(())
+        }
+        InodeTree::InodeValues(entries) => {
+            output.push(1);
+            write_entries(output, entries)
+        }
+        InodeTree::InodeTree(inode) => {
+            output.push(2);
+            write_inode(output, inode)
+        }
+        InodeTree::InodeExtender(extender) => {
+            output.push(3);
+            write_extender(output, extender)
+        }
+    }
+}
+
+fn write_inode(output: &mut Vec<u8>, inode: &Inode<InodeTree>) -> Result<(), ProofError> {
+    output.extend_from_slice(&(inode.length as i64).to_be_bytes());
+
+    if inode
+        .proofs
+        .iter()
+        .any(|(i, _)| *i as usize >= INODE_ENTRIES)
+    {
+        return Err(encode_error("inode index out of bounds"));
+    }
+
+    // large inodes are encoded as an array, without the indexes
+    if inode.proofs.len() < INODE_ENTRIES / 2 {
+        output.push(0);
+        write_dynamic(output, |output| {
+            for (ptr_index, tree) in &inode.proofs {
+                output.push(*ptr_index);
+                write_inode_tree(output, tree)?;
+            }
+            Ok(())
+        })
+    } else {
+        output.push(1);
+        for ptr_index in 0..INODE_ENTRIES as u8 {
+            match inode.proofs.iter().find(|(i, _)| *i == ptr_index) {
+                Some((_, tree)) => {
+                    output.push(1);
+                    write_inode_tree(output, tree)?;
+                }
+                None => output.push(0),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_extender(
+    output: &mut Vec<u8>,
+    extender: &InodeExtender<InodeTree>,
+) -> Result<(), ProofError> {
+    output.extend_from_slice(&(extender.length as i64).to_be_bytes());
+
+    let segment = encode_segment(&extender.segment)?;
+    let length: u8 = segment
+        .len()
+        .try_into()
+        .map_err(|_| encode_error("extender segment too long"))?;
+    output.push(length);
+    output.extend_from_slice(&segment);
+
+    write_inode_tree(output, &extender.proof)
+}
+
+fn encode_segment(segment: &[u8]) -> Result<Vec<u8>, ProofError> {
+    let mut bits = Vec::with_capacity(segment.len() * SEGMENT_INDEX_BITS + 8);
+    for ptr_index in segment {
+        if *ptr_index as usize >= INODE_ENTRIES {
+            return Err(encode_error("extender index out of bounds"));
+        }
+        for shift in (0..SEGMENT_INDEX_BITS).rev() {
+            bits.push((ptr_index >> shift) & 1);
+        }
+    }
+    // terminator, then padding
+    bits.push(1);
+    while bits.len() % 8 != 0 {
+        bits.push(0);
+    }
+
+    Ok(bits
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0u8, |acc, bit| acc << 1 | bit))
+        .collect())
+}
+
+fn decode_segment(bytes: &[u8]) -> Result<Vec<u8>, ProofError> {
+    let mut bits: Vec<u8> = bytes
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |shift| (byte >> shift) & 1))
+        .collect();
+
+    // remove the padding and the terminator
+    while bits.last() == Some(&0) {
+        bits.pop();
+    }
+    if bits.pop() != Some(1) || bits.len() % SEGMENT_INDEX_BITS != 0 {
+        return Err(decode_error("invalid extender segment"));
+    }
+
+    Ok(bits
+        .chunks(SEGMENT_INDEX_BITS)
+        .map(|index| index.iter().fold(0u8, |acc, bit| acc << 1 | bit))
+        .collect())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], ProofError> {
+        if self.bytes.len() < length {
+            return Err(decode_error("not enough bytes"));
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ProofError> {
+        self.take(N)?
+            .try_into()
+            .map_err(|_| decode_error("not enough bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8, ProofError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn hash(&mut self) -> Result<ObjectHash, ProofError> {
+        self.array::<OBJECT_HASH_LEN>()
+    }
+
+    fn length(&mut self) -> Result<u64, ProofError> {
+        let length = i64::from_be_bytes(self.array()?);
+        length
+            .try_into()
+            .map_err(|_| decode_error("negative inode length"))
+    }
+
+    fn dynamic(&mut self) -> Result<Reader<'a>, ProofError> {
+        let size = u32::from_be_bytes(self.array()?) as usize;
+        Ok(Reader {
+            bytes: self.take(size)?,
+            depth: self.depth,
+        })
+    }
+
+    /// Runs `f` one level deeper in the tree
+    fn nested<T, F>(&mut self, f: F) -> Result<T, ProofError>
+    where
+        F: FnOnce(&mut Self) -> Result<T, ProofError>,
+    {
+        if self.depth >= MAX_DECODE_DEPTH {
+            return Err(decode_error("proof nested too deeply"));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn kinded_hash(&mut self) -> Result<KindedHash, ProofError> {
+        match self.u8()? {
+            0 => Ok(KindedHash::Value(self.hash()?)),
+            1 => Ok(KindedHash::Node(self.hash()?)),
+            _ => Err(decode_error("unknown kinded hash tag")),
+        }
+    }
+
+    fn entries(&mut self) -> Result<Vec<(String, Tree)>, ProofError> {
+        let mut list = self.dynamic()?;
+        let mut entries = Vec::new();
+
+        while !list.bytes.is_empty() {
+            let length = list.u8()? as usize;
+            let step = String::from_utf8(list.take(length)?.to_vec())
+                .map_err(|_| decode_error("step is not valid utf-8"))?;
+            let tree = list.tree()?;
+            entries.push((step, tree));
+        }
+        Ok(entries)
+    }
+
+    fn tree(&mut self) -> Result<Tree, ProofError> {
+        self.nested(|this| match this.u8()? {
+            0 => Ok(Tree::Value(this.dynamic()?.bytes.to_vec())),
+            1 => Ok(Tree::BlindedValue(this.hash()?)),
+            2 => Ok(Tree::Node(this.entries()?)),
+            3 => Ok(Tree::BlindedNode(this.hash()?)),
+            4 => Ok(Tree::Inode(this.inode()?)),
+            5 => Ok(Tree::Extender(this.extender()?)),
+            _ => Err(decode_error("unknown tree tag")),
+        })
+    }
+
+    fn inode_tree(&mut self) -> Result<InodeTree, ProofError> {
+        self.nested(|this| match this.u8()? {
+            0 => Ok(InodeTree::BlindedInode(this.hash()?)),
+            1 => Ok(InodeTree::InodeValues(this.entries()?)),
+            2 => Ok(InodeTree::InodeTree(this.inode()?)),
+            3 => Ok(InodeTree::InodeExtender(this.extender()?)),
+            _ => Err(decode_error("unknown inode tree tag")),
+        })
+    }
+
+    fn inode(&mut self) -> Result<Inode<InodeTree>, ProofError> {
+        let length = self.length()?;
+        let mut proofs = Vec::new();
+
+        match self.u8()? {
+            0 => {
+                let mut list = self.dynamic()?;
+                while !list.bytes.is_empty() {
+                    let ptr_index = list.u8()?;
+                    if ptr_index as usize >= INODE_ENTRIES {
+                        return Err(decode_error("inode index out of bounds"));
+                    }
+                    proofs.push((ptr_index, list.inode_tree()?));
+                }
+            }
+            1 => {
+                for ptr_index in 0..INODE_ENTRIES as u8 {
+                    match self.u8()? {
+                        0 => (),
+                        1 => proofs.push((ptr_index, self.inode_tree()?)),
+                        _ => return Err(decode_error("unknown option tag")),
+                    }
+                }
+            }
+            _ => return Err(decode_error("unknown inode proofs tag")),
+        }
+
+        Ok(Inode { length, proofs })
+    }
+
+    fn extender(&mut self) -> Result<InodeExtender<InodeTree>, ProofError> {
+        let length = self.length()?;
+        let segment_length = self.u8()? as usize;
+        let segment = decode_segment(self.take(segment_length)?)?;
+        let proof = Box::new(self.inode_tree()?);
+
+        Ok(InodeExtender {
+            length,
+            segment,
+            proof,
+        })
+    }
+}
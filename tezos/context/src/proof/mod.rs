@@ -0,0 +1,555 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Merkle proofs of context trees, compatible with Irmin/Octez tree proofs (`Context.Proof`).
+//!
+//! A proof is a partial copy of a tree: the parts required to read some keys are kept, all
+//! the other subtrees are replaced by their hash ("blinded"). Hashing the proof the same way the
+//! full tree is hashed must give back the root hash of the tree.
+//!
+//! Proofs are produced with `WorkingTree::produce_tree_proof` (or `IndexApi::produce_tree_proof`
+//! for a committed context), and verified with `TreeProof::verify`, which doesn't require any
+//! context storage.
+
+use blake2::digest::{Update, VariableOutput};
+use blake2::VarBlake2b;
+use thiserror::Error;
+
+use crate::hash::{index, HashingError, ObjectHash, OBJECT_HASH_LEN};
+use crate::{ContextKey, ContextValue};
+
+pub mod encoding;
+
+/// Version of the proofs with a 32-ary inode tree, as used by Tezos (not binary, not a stream)
+pub const TREE_PROOF_VERSION: u16 = 0;
+
+/// Number of pointers of an inode
+pub const INODE_ENTRIES: usize = 32;
+
+/// A directory longer than this is hashed as an inode tree, see `crate::hash`
+const STABLE_HASH_THRESHOLD: usize = 256;
+
+#[derive(Debug, Error)]
+pub enum ProofError {
+    #[error("Unsupported proof version {version}")]
+    UnsupportedVersion { version: u16 },
+    #[error("Proof hash mismatch, expected {expected} but computed {computed}")]
+    HashMismatch { expected: String, computed: String },
+    #[error("Invalid proof: {reason}")]
+    InvalidProof { reason: String },
+    #[error("Key {key:?} is blinded in the proof")]
+    BlindedKey { key: String },
+    #[error("Failed to decode proof: {reason}")]
+    DecodeError { reason: String },
+    #[error("Failed to encode proof: {reason}")]
+    EncodeError { reason: String },
+    #[error("Unsupported proof encoding {encoding:?}")]
+    UnsupportedEncoding { encoding: encoding::ProofEncoding },
+    #[error("Hashing error: {error}")]
+    HashingError {
+        #[from]
+        error: HashingError,
+    },
+}
+
+impl From<std::io::Error> for ProofError {
+    fn from(error: std::io::Error) -> Self {
+        Self::HashingError {
+            error: error.into(),
+        }
+    }
+}
+
+/// Hash of the root of a proof, with the kind of the root object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KindedHash {
+    Value(ObjectHash),
+    Node(ObjectHash),
+}
+
+impl KindedHash {
+    pub fn hash(&self) -> &ObjectHash {
+        match self {
+            Self::Value(hash) | Self::Node(hash) => hash,
+        }
+    }
+}
+
+/// Inode with only the pointers that are part of the proof, sorted by index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inode<T> {
+    /// Number of entries in the whole directory under this inode
+    pub length: u64,
+    pub proofs: Vec<(u8, T)>,
+}
+
+/// Chain of inodes with a single pointer each, `segment` are the indexes of those pointers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InodeExtender<T> {
+    pub length: u64,
+    pub segment: Vec<u8>,
+    pub proof: Box<T>,
+}
+
+/// Tree of a proof, `Blinded*` variants only contain the hash of the object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tree {
+    Value(ContextValue),
+    BlindedValue(ObjectHash),
+    /// Directory with all its entries, sorted by name
+    Node(Vec<(String, Tree)>),
+    BlindedNode(ObjectHash),
+    /// Directory large enough to be hashed as an inode tree
+    Inode(Inode<InodeTree>),
+    Extender(InodeExtender<InodeTree>),
+}
+
+/// Subtree of an inode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InodeTree {
+    BlindedInode(ObjectHash),
+    /// Leaf of the inode tree, with all its entries sorted by name
+    InodeValues(Vec<(String, Tree)>),
+    InodeTree(Inode<InodeTree>),
+    InodeExtender(InodeExtender<InodeTree>),
+}
+
+/// Proof that a tree with the `before` root hash contains the values in `state`.
+///
+/// Proofs produced by this crate only read the tree, so `after` is always equal to `before`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeProof {
+    pub version: u16,
+    pub before: KindedHash,
+    pub after: KindedHash,
+    pub state: Tree,
+}
+
+impl TreeProof {
+    /// Creates a read-only proof of `state`, `root` is the hash of the whole tree.
+    pub fn new(state: Tree, root: ObjectHash) -> Self {
+        let hash = match state {
+            Tree::Value(_) | Tree::BlindedValue(_) => KindedHash::Value(root),
+            _ => KindedHash::Node(root),
+        };
+        Self {
+            version: TREE_PROOF_VERSION,
+            before: hash,
+            after: hash,
+            state,
+        }
+    }
+
+    /// Checks that hashing `state` gives the `before` hash, and that the proof doesn't
+    /// modify the tree.
+    pub fn verify(&self) -> Result<(), ProofError> {
+        if self.version != TREE_PROOF_VERSION {
+            return Err(ProofError::UnsupportedVersion {
+                version: self.version,
+            });
+        }
+        if self.before != self.after {
+            return Err(ProofError::InvalidProof {
+                reason: "the `after` hash differs from the `before` hash".to_string(),
+            });
+        }
+
+        let computed = self.state.kinded_hash()?;
+        if computed != self.before {
+            return Err(ProofError::HashMismatch {
+                expected: hex::encode(self.before.hash()),
+                computed: hex::encode(computed.hash()),
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that the proof is valid for the tree of `root` hash.
+    pub fn verify_root(&self, root: &ObjectHash) -> Result<(), ProofError> {
+        if self.before.hash() != root {
+            return Err(ProofError::HashMismatch {
+                expected: hex::encode(root),
+                computed: hex::encode(self.before.hash()),
+            });
+        }
+        self.verify()
+    }
+
+    /// Returns the value of `key` in the proof.
+    ///
+    /// Returns `Ok(None)` when the proof shows that there is no value at `key`, and an
+    /// error when that part of the tree was blinded.
+    /// The proof must be verified first, see `Self::verify`.
+    pub fn find(&self, key: &ContextKey) -> Result<Option<ContextValue>, ProofError> {
+        self.state.find(key, key)
+    }
+}
+
+impl Tree {
+    /// Computes the hash of the tree this proof represents.
+    pub fn kinded_hash(&self) -> Result<KindedHash, ProofError> {
+        match self {
+            Tree::Value(value) => Ok(KindedHash::Value(hash_value(value)?)),
+            Tree::BlindedValue(hash) => Ok(KindedHash::Value(*hash)),
+            Tree::Node(entries) => Ok(KindedHash::Node(hash_node(entries)?)),
+            Tree::BlindedNode(hash) => Ok(KindedHash::Node(*hash)),
+            Tree::Inode(inode) => Ok(KindedHash::Node(hash_inode(inode, 0)?)),
+            Tree::Extender(extender) => Ok(KindedHash::Node(hash_extender(extender, 0)?)),
+        }
+    }
+
+    fn find(
+        &self,
+        full_key: &ContextKey,
+        key: &ContextKey,
+    ) -> Result<Option<ContextValue>, ProofError> {
+        let (name, rest) = match key.split_first() {
+            Some(split) => split,
+            None => {
+                return match self {
+                    Tree::Value(value) => Ok(Some(value.clone())),
+                    Tree::BlindedValue(_) => Err(blinded_key(full_key)),
+                    _ => Ok(None),
+                }
+            }
+        };
+
+        let entry = match self {
+            Tree::Value(_) => return Ok(None),
+            Tree::BlindedValue(_) | Tree::BlindedNode(_) => return Err(blinded_key(full_key)),
+            Tree::Node(entries) => find_entry(entries, name),
+            Tree::Inode(inode) => find_in_inode(inode, 0, name, full_key)?,
+            Tree::Extender(extender) => find_in_extender(extender, 0, name, full_key)?,
+        };
+
+        match entry {
+            Some(tree) => tree.find(full_key, rest),
+            None => Ok(None),
+        }
+    }
+}
+
+impl InodeTree {
+    fn hash(&self, depth: u32) -> Result<ObjectHash, ProofError> {
+        match self {
+            InodeTree::BlindedInode(hash) => Ok(*hash),
+            InodeTree::InodeValues(entries) => hash_inode_values(entries),
+            InodeTree::InodeTree(inode) => hash_inode(inode, depth),
+            InodeTree::InodeExtender(extender) => hash_extender(extender, depth),
+        }
+    }
+
+    fn find<'a>(
+        &'a self,
+        depth: u32,
+        name: &str,
+        full_key: &ContextKey,
+    ) -> Result<Option<&'a Tree>, ProofError> {
+        match self {
+            InodeTree::BlindedInode(_) => Err(blinded_key(full_key)),
+            InodeTree::InodeValues(entries) => Ok(find_entry(entries, name)),
+            InodeTree::InodeTree(inode) => find_in_inode(inode, depth, name, full_key),
+            InodeTree::InodeExtender(extender) => find_in_extender(extender, depth, name, full_key),
+        }
+    }
+}
+
+fn blinded_key(key: &ContextKey) -> ProofError {
+    ProofError::BlindedKey { key: key.join("/") }
+}
+
+fn invalid_proof(reason: &str) -> ProofError {
+    ProofError::InvalidProof {
+        reason: reason.to_string(),
+    }
+}
+
+fn find_entry<'a>(entries: &'a [(String, Tree)], name: &str) -> Option<&'a Tree> {
+    entries
+        .binary_search_by(|(entry_name, _)| entry_name.as_str().cmp(name))
+        .ok()
+        .map(|position| &entries[position].1)
+}
+
+fn find_in_inode<'a>(
+    inode: &'a Inode<InodeTree>,
+    depth: u32,
+    name: &str,
+    full_key: &ContextKey,
+) -> Result<Option<&'a Tree>, ProofError> {
+    let ptr_index = index(depth, name) as u8;
+    match inode.proofs.iter().find(|(i, _)| *i == ptr_index) {
+        Some((_, tree)) => tree.find(depth + 1, name, full_key),
+        // all the pointers of an inode are part of the proof, blinded or not
+        None => Ok(None),
+    }
+}
+
+fn find_in_extender<'a>(
+    extender: &'a InodeExtender<InodeTree>,
+    depth: u32,
+    name: &str,
+    full_key: &ContextKey,
+) -> Result<Option<&'a Tree>, ProofError> {
+    for (offset, ptr_index) in extender.segment.iter().enumerate() {
+        if index(depth + offset as u32, name) as u8 != *ptr_index {
+            return Ok(None);
+        }
+    }
+    let depth = depth + extender.segment.len() as u32;
+    extender.proof.find(depth, name, full_key)
+}
+
+fn finalize(hasher: VarBlake2b) -> ObjectHash {
+    let mut hash: ObjectHash = Default::default();
+    hasher.finalize_variable(|r| hash.copy_from_slice(r));
+    hash
+}
+
+fn check_sorted(entries: &[(String, Tree)]) -> Result<(), ProofError> {
+    if entries.windows(2).all(|pair| pair[0].0 < pair[1].0) {
+        Ok(())
+    } else {
+        Err(invalid_proof("directory entries are not sorted"))
+    }
+}
+
+/// See `crate::hash::hash_blob`
+fn hash_value(value: &[u8]) -> Result<ObjectHash, ProofError> {
+    let mut hasher = VarBlake2b::new(OBJECT_HASH_LEN).map_err(HashingError::from)?;
+    hasher.update(&(value.len() as u64).to_be_bytes());
+    hasher.update(value);
+    Ok(finalize(hasher))
+}
+
+/// See `crate::hash::hash_short_inode`
+fn hash_node(entries: &[(String, Tree)]) -> Result<ObjectHash, ProofError> {
+    if entries.len() > STABLE_HASH_THRESHOLD {
+        return Err(invalid_proof("too many entries in a node"));
+    }
+    check_sorted(entries)?;
+
+    let mut hasher = VarBlake2b::new(OBJECT_HASH_LEN).map_err(HashingError::from)?;
+    hasher.update(&(entries.len() as u64).to_be_bytes());
+
+    for (name, tree) in entries {
+        let (kind, hash) = match tree.kinded_hash()? {
+            KindedHash::Value(hash) => ([255, 0, 0, 0, 0, 0, 0, 0], hash),
+            KindedHash::Node(hash) => ([0; 8], hash),
+        };
+        hasher.update(kind);
+        leb128::write::unsigned(&mut hasher, name.len() as u64)?;
+        hasher.update(name.as_bytes());
+        hasher.update(&(OBJECT_HASH_LEN as u64).to_be_bytes());
+        hasher.update(hash);
+    }
+
+    Ok(finalize(hasher))
+}
+
+/// See `crate::hash::hash_long_inode`
+fn hash_inode_values(entries: &[(String, Tree)]) -> Result<ObjectHash, ProofError> {
+    if entries.len() > INODE_ENTRIES {
+        return Err(invalid_proof("too many entries in an inode leaf"));
+    }
+    check_sorted(entries)?;
+
+    let mut hasher = VarBlake2b::new(OBJECT_HASH_LEN).map_err(HashingError::from)?;
+    hasher.update(&[0u8]);
+    hasher.update(&[entries.len() as u8]);
+
+    for (name, tree) in entries {
+        let (kind, hash) = match tree.kinded_hash()? {
+            KindedHash::Value(hash) => (1u8, hash),
+            KindedHash::Node(hash) => (0u8, hash),
+        };
+        leb128::write::unsigned(&mut hasher, name.len() as u64)?;
+        hasher.update(name.as_bytes());
+        hasher.update(&[kind]);
+        hasher.update(hash);
+    }
+
+    Ok(finalize(hasher))
+}
+
+/// See `crate::hash::hash_long_inode`
+fn hash_inode_pointers(
+    depth: u32,
+    length: u64,
+    pointers: &[(u8, ObjectHash)],
+) -> Result<ObjectHash, ProofError> {
+    if pointers.len() > INODE_ENTRIES
+        || pointers.iter().any(|(i, _)| *i as usize >= INODE_ENTRIES)
+        || !pointers.windows(2).all(|pair| pair[0].0 < pair[1].0)
+    {
+        return Err(invalid_proof("invalid inode pointers"));
+    }
+
+    let mut hasher = VarBlake2b::new(OBJECT_HASH_LEN).map_err(HashingError::from)?;
+    hasher.update(&[1u8]);
+    leb128::write::unsigned(&mut hasher, depth as u64)?;
+    leb128::write::unsigned(&mut hasher, length)?;
+    hasher.update(&[pointers.len() as u8]);
+
+    for (ptr_index, hash) in pointers {
+        hasher.update(&[*ptr_index]);
+        hasher.update(hash);
+    }
+
+    Ok(finalize(hasher))
+}
+
+fn hash_inode(inode: &Inode<InodeTree>, depth: u32) -> Result<ObjectHash, ProofError> {
+    let pointers = inode
+        .proofs
+        .iter()
+        .map(|(ptr_index, tree)| Ok((*ptr_index, tree.hash(depth + 1)?)))
+        .collect::<Result<Vec<_>, ProofError>>()?;
+
+    hash_inode_pointers(depth, inode.length, &pointers)
+}
+
+fn hash_extender(
+    extender: &InodeExtender<InodeTree>,
+    depth: u32,
+) -> Result<ObjectHash, ProofError> {
+    if extender.segment.is_empty() {
+        return Err(invalid_proof("empty extender segment"));
+    }
+
+    // the extender is a shortcut for a chain of inodes with a single pointer
+    let end_depth = depth + extender.segment.len() as u32;
+    let mut hash = extender.proof.hash(end_depth)?;
+
+    for (offset, ptr_index) in extender.segment.iter().enumerate().rev() {
+        hash = hash_inode_pointers(
+            depth + offset as u32,
+            extender.length,
+            &[(*ptr_index, hash)],
+        )?;
+    }
+
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs::File, io::Read, path::Path};
+
+    use crypto::hash::{ContextHash, HashTrait};
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    // Tests from Tarides json dataset, see `crate::hash`
+
+    #[derive(serde::Deserialize)]
+    struct DirEntryHashTest {
+        hash: String,
+        bindings: Vec<DirEntryHashBinding>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct DirEntryHashBinding {
+        name: String,
+        kind: String,
+        hash: String,
+    }
+
+    fn object_hash(base58: &str) -> ObjectHash {
+        let hash = ContextHash::from_base58_check(base58).unwrap();
+        hash.0.as_slice().try_into().unwrap()
+    }
+
+    #[test]
+    fn test_blinded_node_hashes() {
+        let path = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("tests")
+            .join("resources")
+            .join("nodes.json.gz");
+        let mut bytes = Vec::new();
+        GzDecoder::new(File::open(path).unwrap())
+            .read_to_end(&mut bytes)
+            .unwrap();
+        let test_cases: Vec<DirEntryHashTest> = serde_json::from_slice(&bytes).unwrap();
+
+        for test_case in test_cases {
+            let mut entries: Vec<(String, Tree)> = test_case
+                .bindings
+                .into_iter()
+                .map(|binding| {
+                    let hash = object_hash(&binding.hash);
+                    let tree = match binding.kind.as_str() {
+                        "Tree" => Tree::BlindedNode(hash),
+                        "Contents" => Tree::BlindedValue(hash),
+                        other => panic!("Got unexpected binding kind: {}", other),
+                    };
+                    (binding.name, tree)
+                })
+                .collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            let first_name = entries.first().map(|(name, _)| name.clone());
+
+            let proof = TreeProof::new(Tree::Node(entries), object_hash(&test_case.hash));
+            proof.verify().unwrap();
+
+            let decoded = TreeProof::from_bytes(&proof.to_bytes().unwrap()).unwrap();
+            assert_eq!(decoded, proof);
+
+            if let Some(name) = first_name {
+                assert!(matches!(
+                    proof.find(&[name.as_str()]),
+                    Err(ProofError::BlindedKey { .. })
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_proof() {
+        let value = Tree::Value(vec![1, 2, 3]);
+        let hash = value.kinded_hash().unwrap();
+        let node = Tree::Node(vec![("a".to_string(), value)]);
+        let root = *node.kinded_hash().unwrap().hash();
+
+        let mut proof = TreeProof::new(node, root);
+        proof.verify().unwrap();
+        assert_eq!(proof.find(&["a"]).unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(proof.find(&["b"]).unwrap(), None);
+        assert_eq!(proof.find(&["a", "b"]).unwrap(), None);
+
+        proof.state = Tree::Node(vec![("a".to_string(), Tree::Value(vec![1, 2, 4]))]);
+        assert!(matches!(
+            proof.verify(),
+            Err(ProofError::HashMismatch { .. })
+        ));
+
+        proof.state = Tree::Node(vec![("a".to_string(), Tree::BlindedValue(*hash.hash()))]);
+        proof.verify().unwrap();
+        assert!(matches!(
+            proof.find(&["a"]),
+            Err(ProofError::BlindedKey { .. })
+        ));
+    }
+
+    #[test]
+    fn test_extender_encoding() {
+        let extender = Tree::Extender(InodeExtender {
+            length: 300,
+            segment: vec![0, 31, 7, 16, 1],
+            proof: Box::new(InodeTree::InodeTree(Inode {
+                length: 300,
+                proofs: (0..INODE_ENTRIES as u8)
+                    .map(|i| (i, InodeTree::BlindedInode([i; OBJECT_HASH_LEN])))
+                    .collect(),
+            })),
+        });
+        let root = *extender.kinded_hash().unwrap().hash();
+        let proof = TreeProof::new(extender, root);
+        proof.verify().unwrap();
+
+        let decoded = TreeProof::from_bytes(&proof.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded, proof);
+        decoded.verify().unwrap();
+    }
+}
@@ -0,0 +1,441 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Context integrity scrub.
+//!
+//! Walks all the objects reachable from the most recent commits, recomputes
+//! their hashes from their content and compares them with the hashes stored
+//! in the repository.
+//!
+//! Unlike `sizes.db` checks, this detects corrupted bytes in the data file.
+//! Objects shared between commits are verified only once.
+
+use std::{collections::HashSet, fmt};
+
+use crypto::hash::ContextHash;
+use thiserror::Error;
+
+use crate::{
+    hash::{commit_hash, directory_hash, hash_inlined_blob, index, long_inode_hash},
+    persistent::DBError,
+    serialize::persistent::AbsoluteOffset,
+    working_tree::{
+        storage::{DirectoryId, DirectoryOrInodeId, InodePointer, Storage},
+        string_interner::StringInterner,
+        DirEntryKind, Object, ObjectReference,
+    },
+    ContextKeyValueStore, ObjectHash,
+};
+
+#[derive(Debug, Error)]
+pub enum ScrubError {
+    #[error("Failed to read the list of commits: {error}")]
+    DBError {
+        #[from]
+        error: DBError,
+    },
+}
+
+#[derive(Debug)]
+pub enum CorruptionKind {
+    /// The hash recomputed from the object content differs from the stored one
+    HashMismatch {
+        stored: ObjectHash,
+        computed: ObjectHash,
+    },
+    /// The object could not be read or deserialized
+    Unreadable(String),
+    /// An entry of an inode is not under the pointer given by its name
+    MisplacedEntry { depth: u32, ptr_index: u8 },
+    /// The context hash is not in the repository
+    MissingCommit,
+}
+
+/// First corruption found by the scrub
+#[derive(Debug)]
+pub struct Corruption {
+    pub commit: ContextHash,
+    /// Offset of the corrupted object, `None` for in-memory repositories
+    pub offset: Option<AbsoluteOffset>,
+    /// Path of the directory or blob containing the corrupted object
+    pub path: String,
+    pub kind: CorruptionKind,
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let offset = match self.offset {
+            Some(offset) => offset.as_u64().to_string(),
+            None => "-".to_string(),
+        };
+        write!(
+            f,
+            "commit={} offset={} path={:?}: ",
+            self.commit.to_base58_check(),
+            offset,
+            self.path
+        )?;
+
+        match &self.kind {
+            CorruptionKind::HashMismatch { stored, computed } => write!(
+                f,
+                "hash mismatch, stored={} computed={}",
+                hex::encode(stored),
+                hex::encode(computed)
+            ),
+            CorruptionKind::Unreadable(reason) => write!(f, "unreadable object: {}", reason),
+            CorruptionKind::MisplacedEntry { depth, ptr_index } => write!(
+                f,
+                "entry is misplaced in inode, depth={} pointer={}",
+                depth, ptr_index
+            ),
+            CorruptionKind::MissingCommit => write!(f, "commit not found"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ScrubReport {
+    pub commits_checked: usize,
+    pub objects_checked: usize,
+    pub corruption: Option<Corruption>,
+}
+
+/// Object to verify, referenced by a directory
+struct PendingObject {
+    object_ref: ObjectReference,
+    kind: DirEntryKind,
+    path: String,
+}
+
+/// Corruption of an object, the commit is added by the caller
+struct ObjectCorruption {
+    offset: Option<AbsoluteOffset>,
+    path: String,
+    kind: CorruptionKind,
+}
+
+fn unreadable(
+    object_ref: ObjectReference,
+    path: &str,
+    error: impl fmt::Display,
+) -> ObjectCorruption {
+    ObjectCorruption {
+        offset: object_ref.offset_opt(),
+        path: path.to_string(),
+        kind: CorruptionKind::Unreadable(error.to_string()),
+    }
+}
+
+fn compare_hashes(
+    repository: &ContextKeyValueStore,
+    object_ref: ObjectReference,
+    path: &str,
+    computed: ObjectHash,
+) -> Result<(), ObjectCorruption> {
+    let stored = repository
+        .get_hash(object_ref)
+        .map_err(|e| unreadable(object_ref, path, e))?;
+
+    if *stored != computed {
+        return Err(ObjectCorruption {
+            offset: object_ref.offset_opt(),
+            path: path.to_string(),
+            kind: CorruptionKind::HashMismatch {
+                stored: stored.into_owned(),
+                computed,
+            },
+        });
+    }
+
+    Ok(())
+}
+
+struct Scrubber<'a> {
+    repository: &'a mut ContextKeyValueStore,
+    storage: Storage,
+    strings: StringInterner,
+    /// Offsets (or `HashId` in the in-memory repository) of the objects already verified
+    visited: HashSet<u64>,
+    objects_checked: usize,
+}
+
+impl<'a> Scrubber<'a> {
+    /// Returns `true` if the object was not verified yet
+    fn visit(&mut self, object_ref: ObjectReference) -> bool {
+        match (object_ref.offset_opt(), object_ref.hash_id_opt()) {
+            (Some(offset), _) => self.visited.insert(offset.as_u64()),
+            (None, Some(hash_id)) => self.visited.insert(hash_id.as_u64()),
+            (None, None) => true,
+        }
+    }
+
+    fn scrub_commit(&mut self, context_hash: &ContextHash) -> Result<(), ObjectCorruption> {
+        let commit_ref = match self.repository.get_context_hash(context_hash) {
+            Ok(Some(commit_ref)) => commit_ref,
+            Ok(None) => {
+                return Err(ObjectCorruption {
+                    offset: None,
+                    path: String::new(),
+                    kind: CorruptionKind::MissingCommit,
+                })
+            }
+            Err(e) => return Err(unreadable(ObjectReference::new(None, None), "", e)),
+        };
+
+        let commit =
+            match self
+                .repository
+                .get_object(commit_ref, &mut self.storage, &mut self.strings)
+            {
+                Ok(Object::Commit(commit)) => commit,
+                Ok(_) => return Err(unreadable(commit_ref, "", "object is not a commit")),
+                Err(e) => return Err(unreadable(commit_ref, "", e)),
+            };
+
+        let computed =
+            commit_hash(&commit, &*self.repository).map_err(|e| unreadable(commit_ref, "", e))?;
+        self.objects_checked += 1;
+
+        if context_hash.as_ref().as_slice() != computed.as_slice() {
+            let mut stored = ObjectHash::default();
+            stored.copy_from_slice(context_hash.as_ref());
+
+            return Err(ObjectCorruption {
+                offset: commit_ref.offset_opt(),
+                path: String::new(),
+                kind: CorruptionKind::HashMismatch { stored, computed },
+            });
+        }
+
+        let mut pending = vec![PendingObject {
+            object_ref: commit.root_ref,
+            kind: DirEntryKind::Directory,
+            path: String::new(),
+        }];
+
+        while let Some(object) = pending.pop() {
+            if !self.visit(object.object_ref) {
+                continue;
+            }
+
+            self.scrub_object(object, &mut pending)?;
+
+            // Objects are verified one by one, there is no need to keep
+            // them in memory
+            self.storage.clear();
+        }
+
+        Ok(())
+    }
+
+    fn scrub_object(
+        &mut self,
+        object: PendingObject,
+        pending: &mut Vec<PendingObject>,
+    ) -> Result<(), ObjectCorruption> {
+        let PendingObject {
+            object_ref,
+            kind,
+            path,
+        } = object;
+
+        let loaded = self
+            .repository
+            .get_object(object_ref, &mut self.storage, &mut self.strings)
+            .map_err(|e| unreadable(object_ref, &path, e))?;
+        self.objects_checked += 1;
+
+        match (kind, loaded) {
+            (DirEntryKind::Blob, Object::Blob(blob_id)) => {
+                let blob = self
+                    .storage
+                    .get_blob(blob_id)
+                    .map_err(|e| unreadable(object_ref, &path, e))?;
+                let computed =
+                    hash_inlined_blob(blob).map_err(|e| unreadable(object_ref, &path, e))?;
+
+                compare_hashes(&*self.repository, object_ref, &path, computed)
+            }
+            (DirEntryKind::Directory, Object::Directory(dir_id)) => {
+                let computed =
+                    directory_hash(dir_id, self.repository, &self.storage, &self.strings)
+                        .map_err(|e| unreadable(object_ref, &path, e))?;
+
+                compare_hashes(&*self.repository, object_ref, &path, computed)?;
+
+                match dir_id.get_inode_id() {
+                    Some(inode_id) => self.scrub_inode(
+                        DirectoryOrInodeId::Inode(inode_id),
+                        object_ref,
+                        &path,
+                        &mut Vec::new(),
+                        pending,
+                    ),
+                    None => self.push_entries(dir_id, object_ref, &path, &[], pending),
+                }
+            }
+            (kind, _) => Err(unreadable(
+                object_ref,
+                &path,
+                format!("object is not a {:?}", kind),
+            )),
+        }
+    }
+
+    /// Verify the pointers of an inode, and the placement of its entries.
+    ///
+    /// `ptr_indexes` contains the `(depth, index)` of the pointers followed to
+    /// reach this inode: an entry must be under the pointer given by
+    /// `hash::index(depth, name)` at every depth.
+    fn scrub_inode(
+        &mut self,
+        ptr_id: DirectoryOrInodeId,
+        object_ref: ObjectReference,
+        path: &str,
+        ptr_indexes: &mut Vec<(u32, u8)>,
+        pending: &mut Vec<PendingObject>,
+    ) -> Result<(), ObjectCorruption> {
+        let inode_id = match ptr_id {
+            DirectoryOrInodeId::Directory(dir_id) => {
+                return self.push_entries(dir_id, object_ref, path, ptr_indexes, pending);
+            }
+            DirectoryOrInodeId::Inode(inode_id) => inode_id,
+        };
+
+        let depth = self
+            .storage
+            .get_inode(inode_id)
+            .map_err(|e| unreadable(object_ref, path, e))?
+            .depth as u32;
+
+        let pointers = self
+            .storage
+            .inode_pointers(inode_id, &mut self.strings, &*self.repository)
+            .map_err(|e| unreadable(object_ref, path, e))?;
+
+        for InodePointer {
+            index: ptr_index,
+            ptr_id,
+            hash_id,
+            offset,
+        } in pointers
+        {
+            let pointer_ref = ObjectReference::new(hash_id, offset);
+
+            if !self.visit(pointer_ref) {
+                continue;
+            }
+
+            let computed = long_inode_hash(ptr_id, self.repository, &self.storage, &self.strings)
+                .map_err(|e| unreadable(pointer_ref, path, e))?;
+            self.objects_checked += 1;
+
+            compare_hashes(&*self.repository, pointer_ref, path, computed)?;
+
+            ptr_indexes.push((depth, ptr_index));
+            self.scrub_inode(ptr_id, pointer_ref, path, ptr_indexes, pending)?;
+            ptr_indexes.pop();
+        }
+
+        Ok(())
+    }
+
+    /// Push the entries of `dir_id` to `pending`, inlined blobs are skipped:
+    /// they are part of their directory, which was already verified.
+    fn push_entries(
+        &mut self,
+        dir_id: DirectoryId,
+        object_ref: ObjectReference,
+        path: &str,
+        ptr_indexes: &[(u32, u8)],
+        pending: &mut Vec<PendingObject>,
+    ) -> Result<(), ObjectCorruption> {
+        let dir = self
+            .storage
+            .get_small_dir(dir_id)
+            .map_err(|e| unreadable(object_ref, path, e))?;
+
+        for (name_id, dir_entry_id) in dir.as_ref() {
+            let name = self
+                .strings
+                .get_str(*name_id)
+                .map_err(|e| unreadable(object_ref, path, e))?;
+
+            for &(depth, ptr_index) in ptr_indexes {
+                if index(depth, &name) as u8 != ptr_index {
+                    return Err(ObjectCorruption {
+                        offset: object_ref.offset_opt(),
+                        path: format!("{}/{}", path, name),
+                        kind: CorruptionKind::MisplacedEntry { depth, ptr_index },
+                    });
+                }
+            }
+
+            let dir_entry = self
+                .storage
+                .get_dir_entry(*dir_entry_id)
+                .map_err(|e| unreadable(object_ref, path, e))?;
+
+            if dir_entry.is_inlined_blob() {
+                continue;
+            }
+
+            pending.push(PendingObject {
+                object_ref: dir_entry.get_reference(),
+                kind: dir_entry.dir_entry_kind(),
+                path: format!("{}/{}", path, name),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Verify the `count` most recent commits of the repository, starting with the
+/// most recent one.
+///
+/// The scrub stops at the first corrupted object found.
+pub fn scrub_context(
+    repository: &mut ContextKeyValueStore,
+    count: usize,
+    log: fn(&str),
+) -> Result<ScrubReport, ScrubError> {
+    let mut context_hashes = repository.latest_context_hashes(count as i64)?;
+    context_hashes.reverse();
+
+    let strings = repository.take_strings_on_reload().unwrap_or_default();
+
+    let mut scrubber = Scrubber {
+        repository,
+        storage: Storage::new(),
+        strings,
+        visited: HashSet::default(),
+        objects_checked: 0,
+    };
+    let mut report = ScrubReport::default();
+
+    for context_hash in context_hashes {
+        log(&format!(
+            " Scrubbing commit {}",
+            context_hash.to_base58_check()
+        ));
+
+        let result = scrubber.scrub_commit(&context_hash);
+
+        report.commits_checked += 1;
+        report.objects_checked = scrubber.objects_checked;
+
+        if let Err(ObjectCorruption { offset, path, kind }) = result {
+            report.corruption = Some(Corruption {
+                commit: context_hash,
+                offset,
+                path,
+                kind,
+            });
+            break;
+        }
+    }
+
+    Ok(report)
+}
@@ -21,7 +21,9 @@ use crate::{
 pub fn reload_context_readonly(context_path: String) -> Result<Persistent, Error> {
     let sizes_file = File::<{ TAG_SIZES }>::try_new(&context_path, true)?;
     let sizes = FileSizes::make_list_from_file(&sizes_file).unwrap_or_default();
-    assert!(!sizes.is_empty(), "sizes.db is invalid: {:?}", sizes);
+    if sizes.is_empty() {
+        anyhow::bail!("sizes.db is invalid: {:?}", sizes);
+    }
 
     let mut repo = Persistent::try_new(PersistentConfiguration {
         db_path: Some(context_path),
@@ -9,14 +9,21 @@ use std::{cell::RefCell, convert::TryInto, sync::Arc};
 use crypto::hash::ContextHash;
 use ocaml_interop::BoxRoot;
 use parking_lot::RwLock;
-use tezos_context_api::StringDirectoryMap;
+use tezos_context_api::{
+    ContextDiff, ContextDiffEntry, ContextDiffRequest, ContextKeyHistory, StringDirectoryMap,
+};
 use tezos_timing::{BlockMemoryUsage, ContextMemoryUsage};
 
 use crate::working_tree::working_tree::FoldOrder;
 use crate::{
     hash::ObjectHash,
     kv_store::HashId,
-    persistent::{get_commit_hash, DBError},
+    persistent::{
+        get_commit_hash,
+        key_history::{KeyHistoryChange, KeyHistoryValue},
+        DBError,
+    },
+    proof::TreeProof,
     timings::send_statistics,
     working_tree::{
         storage::{BlobId, DirEntryId, DirectoryId, Storage},
@@ -769,6 +776,124 @@ impl TezedgeIndex {
             .map_err(Into::into)
     }
 
+    fn get_context_diff_impl(
+        &self,
+        from_context_hash: &ContextHash,
+        to_context_hash: &ContextHash,
+        request: &ContextDiffRequest,
+    ) -> Result<ContextDiff, ContextError> {
+        let checkout = |context_hash: &ContextHash| {
+            self.checkout(context_hash)?
+                .ok_or_else(|| ContextError::UnknownContextHashError {
+                    context_hash: context_hash.to_base58_check(),
+                })
+        };
+        let from = checkout(from_context_hash)?;
+        let to = checkout(to_context_hash)?;
+
+        from.tree.diff(&to.tree, request).map_err(Into::into)
+    }
+
+    /// Changes made by the commit `context_hash` to its parent commit, with the location
+    /// of the new values in the repository, see `persistent::key_history`.
+    ///
+    /// A commit without parent is compared to an empty tree.
+    fn get_block_changes_impl(
+        &self,
+        context_hash: &ContextHash,
+    ) -> Result<Vec<KeyHistoryChange>, ContextError> {
+        let request = ContextDiffRequest {
+            after: None,
+            limit: usize::MAX,
+            with_values: false,
+        };
+        let context =
+            self.checkout(context_hash)?
+                .ok_or_else(|| ContextError::UnknownContextHashError {
+                    context_hash: context_hash.to_base58_check(),
+                })?;
+        let parent_commit_ref = self
+            .fetch_commit_from_context_hash(context_hash)?
+            .and_then(|commit| commit.parent_commit_ref);
+
+        let diff = match parent_commit_ref {
+            Some(parent_commit_ref) => {
+                let parent_hash = get_commit_hash(parent_commit_ref, &*self.repository.read())?;
+                let parent = self.checkout(&parent_hash)?.ok_or_else(|| {
+                    ContextError::UnknownContextHashError {
+                        context_hash: parent_hash.to_base58_check(),
+                    }
+                })?;
+                parent.tree.diff(&context.tree, &request)?
+            }
+            None => context.tree.empty().diff(&context.tree, &request)?,
+        };
+
+        diff.entries
+            .into_iter()
+            .map(|entry| -> Result<KeyHistoryChange, ContextError> {
+                let key = entry.key().join("/");
+                let value = match entry {
+                    ContextDiffEntry::Removed { .. } => KeyHistoryValue::Removed,
+                    ContextDiffEntry::Added { key: parts, .. }
+                    | ContextDiffEntry::Modified { key: parts, .. } => {
+                        let parts: Vec<&str> = parts.iter().map(String::as_str).collect();
+                        context
+                            .tree
+                            .find_value_location(&parts)?
+                            .ok_or_else(|| MerkleError::ValueNotFound { key: key.clone() })?
+                    }
+                };
+                Ok(KeyHistoryChange { key, value })
+            })
+            .collect()
+    }
+
+    /// Record the values changed by the commit `context_hash` in the key history.
+    ///
+    /// When they cannot be found, the level is recorded as missing.
+    fn put_key_history_impl(
+        &self,
+        block_level: u32,
+        context_hash: &ContextHash,
+    ) -> Result<(), ContextError> {
+        let result = self
+            .get_block_changes_impl(context_hash)
+            .and_then(|changes| {
+                Ok(self
+                    .repository
+                    .write()
+                    .put_key_history(block_level, &changes)?)
+            });
+
+        if let Err(e) = result {
+            elog!(
+                "Failed to record the key history of block level={}, recording it as missing, error={:?}",
+                block_level,
+                e
+            );
+            self.repository
+                .write()
+                .put_key_history_missing(block_level)?;
+        }
+
+        Ok(())
+    }
+
+    fn produce_tree_proof_impl(
+        &self,
+        context_hash: &ContextHash,
+        keys: &[ContextKeyOwned],
+    ) -> Result<TreeProof, ContextError> {
+        let context =
+            self.checkout(context_hash)?
+                .ok_or_else(|| ContextError::UnknownContextHashError {
+                    context_hash: context_hash.to_base58_check(),
+                })?;
+
+        context.tree.produce_tree_proof(keys).map_err(Into::into)
+    }
+
     fn get_context_tree_by_prefix_impl(
         &self,
         context_hash: &ContextHash,
@@ -875,10 +1000,16 @@ impl IndexApi<TezedgeContext> for TezedgeIndex {
         block_level: u32,
         context_hash: &ContextHash,
     ) -> Result<(), ContextError> {
-        Ok(self
-            .repository
+        self.repository
             .write()
-            .block_applied(block_level, context_hash)?)
+            .block_applied(block_level, context_hash)?;
+
+        if self.repository.read().is_key_history_enabled() {
+            let index = self.with_deallocation();
+            index.put_key_history_impl(block_level, context_hash)?;
+        }
+
+        Ok(())
     }
 
     fn cycle_started(&mut self) -> Result<(), ContextError> {
@@ -912,6 +1043,37 @@ impl IndexApi<TezedgeContext> for TezedgeIndex {
         let index = self.with_deallocation();
         index.get_context_tree_by_prefix_impl(context_hash, prefix, depth)
     }
+
+    fn get_context_diff(
+        &self,
+        from_context_hash: &ContextHash,
+        to_context_hash: &ContextHash,
+        request: &ContextDiffRequest,
+    ) -> Result<ContextDiff, ContextError> {
+        let index = self.with_deallocation();
+        index.get_context_diff_impl(from_context_hash, to_context_hash, request)
+    }
+
+    fn get_key_history(
+        &self,
+        key: &ContextKey,
+        from_level: u32,
+        to_level: u32,
+    ) -> Result<Option<ContextKeyHistory>, ContextError> {
+        Ok(self
+            .repository
+            .read()
+            .get_key_history(key, from_level, to_level)?)
+    }
+
+    fn produce_tree_proof(
+        &self,
+        context_hash: &ContextHash,
+        keys: &[ContextKeyOwned],
+    ) -> Result<TreeProof, ContextError> {
+        let index = self.with_deallocation();
+        index.produce_tree_proof_impl(context_hash, keys)
+    }
 }
 
 /// Handle that represents a specific context (obtained from a checkout).
@@ -1158,6 +1320,7 @@ mod tests {
             backend: ContextKvStoreConfiguration::InMem(TezosContextTezedgeOnDiskBackendOptions {
                 base_path: "".to_string(),
                 startup_check: false,
+                key_history: false,
             }),
             ipc_socket_path: None,
         })
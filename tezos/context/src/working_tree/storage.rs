@@ -1009,6 +1009,18 @@ pub struct Inode {
 
 assert_eq_size!([u8; 16], Inode);
 
+/// A pointer of an inode, returned by `Storage::inode_pointers`
+#[derive(Debug, Clone, Copy)]
+pub struct InodePointer {
+    /// Index of the pointer in the inode (0 to 31)
+    pub index: u8,
+    pub ptr_id: DirectoryOrInodeId,
+    /// `None` when the pointer has not been hashed yet
+    pub hash_id: Option<HashId>,
+    /// `None` when the pointer has not been serialized yet
+    pub offset: Option<AbsoluteOffset>,
+}
+
 /// A `DirectoryId` or `InodeId`
 ///
 /// When accessing `FatPointer`, its value (`FatPointer::ptr_id()`) is either
@@ -2039,6 +2051,39 @@ impl Storage {
         }
     }
 
+    /// Returns the pointers of `inode_id`
+    ///
+    /// The pointers are fetched from the repository when necessary.
+    pub fn inode_pointers(
+        &mut self,
+        inode_id: InodeId,
+        strings: &mut StringInterner,
+        repository: &ContextKeyValueStore,
+    ) -> Result<Vec<InodePointer>, MerkleError> {
+        let inode = self.get_inode(inode_id)?;
+        let mut pointers = Vec::with_capacity(inode.pointers.npointers());
+
+        for (ptr_index, thin_pointer_id) in inode.pointers.iter() {
+            let ptr_id = match self.pointer_get_id(thin_pointer_id)? {
+                Some(ptr_id) => ptr_id,
+                None => self.pointer_fetch(thin_pointer_id, repository, strings)?,
+            };
+            let pointer = self.pointer_copy(thin_pointer_id)?;
+            let hash_id = self.pointer_retrieve_hashid(&pointer, repository)?;
+            // Pointers created in this working tree don't have any data yet
+            let offset = self.pointer_retrieve_offset(&pointer).ok().flatten();
+
+            pointers.push(InodePointer {
+                index: ptr_index as u8,
+                ptr_id,
+                hash_id,
+                offset,
+            });
+        }
+
+        Ok(pointers)
+    }
+
     /// [test only] Remove hash ids in the inode and it's children
     ///
     /// This is used to force recomputing hashes
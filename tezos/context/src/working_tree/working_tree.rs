@@ -60,6 +60,7 @@ use thiserror::Error;
 use crypto::hash::FromBytesError;
 use tezos_timing::SerializeStats;
 
+use crate::proof::{self, TreeProof};
 use crate::{
     chunks::ChunkedVec,
     gc::GarbageCollectionError,
@@ -72,18 +73,22 @@ use crate::{
 };
 use crate::{hash::ObjectHash, ContextKeyOwned};
 use crate::{
-    hash::{hash_blob, hash_inlined_blob},
+    hash::{hash_blob, hash_inlined_blob, hash_long_inode, index},
     working_tree::{Commit, DirEntry, DirEntryKind, Object},
 };
 use crate::{
     hash::{hash_commit, hash_directory, HashingError},
     kv_store::HashId,
 };
-use crate::{persistent, ContextKeyValueStore};
-use crate::{ContextKey, ContextValue};
+use crate::{persistent, persistent::key_history::KeyHistoryValue, ContextKeyValueStore};
+use crate::{ContextDiffEntry, ContextKey, ContextValue};
+use tezos_context_api::{ContextDiff, ContextDiffRequest};
 
 use super::{
-    storage::{BlobId, DirEntryId, DirectoryId, Storage, StorageError},
+    storage::{
+        BlobId, DirEntryId, DirectoryId, DirectoryOrInodeId, InodeId, InodePointer, Storage,
+        StorageError,
+    },
     string_interner::{StringId, StringInterner},
     ObjectReference,
 };
@@ -176,6 +181,41 @@ pub struct WorkingTree {
     pub index: TezedgeIndex,
 }
 
+/// Changes found by `WorkingTree::diff`, one more than `limit` to know if more follow
+struct DiffCollector {
+    entries: Vec<ContextDiffEntry>,
+    limit: usize,
+    with_values: bool,
+}
+
+impl DiffCollector {
+    fn is_full(&self) -> bool {
+        self.entries.len() > self.limit
+    }
+
+    fn push(&mut self, entry: ContextDiffEntry) {
+        if !self.is_full() {
+            self.entries.push(entry);
+        }
+    }
+}
+
+/// Tree of the values collected by `WorkingTree::diff_collect`
+#[derive(Clone, Copy)]
+enum DiffSide {
+    Added,
+    Removed,
+}
+
+impl DiffSide {
+    fn entry(self, key: ContextKeyOwned, value: Option<ContextValue>) -> ContextDiffEntry {
+        match self {
+            Self::Added => ContextDiffEntry::Added { key, value },
+            Self::Removed => ContextDiffEntry::Removed { key, value },
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub enum FoldDepth {
     Eq(i64), // folds over nodes and contents of depth exactly [d].
@@ -745,6 +785,53 @@ impl WorkingTree {
         Ok(self.hash()? == other.hash()?)
     }
 
+    /// Returns the values added, removed or modified in `other` compared to this working tree,
+    /// sorted by key, the page starting after `request.after` with at most `request.limit`
+    /// changes (at least one).
+    ///
+    /// Both trees are walked in parallel, subtrees with the same hash are skipped without
+    /// being fetched from the repository, so are subtrees before `request.after`.
+    pub fn diff(
+        &self,
+        other: &Self,
+        request: &ContextDiffRequest,
+    ) -> Result<ContextDiff, MerkleError> {
+        let mut collector = DiffCollector {
+            entries: Vec::new(),
+            limit: request.limit.max(1),
+            with_values: request.with_values,
+        };
+        if self.hash()? != other.hash()? {
+            self.diff_objects(
+                other,
+                &mut Vec::new(),
+                request.after.as_deref(),
+                self.root_object(),
+                other.root_object(),
+                &mut collector,
+            )?;
+        }
+
+        let mut entries = collector.entries;
+        let next = if entries.len() > collector.limit {
+            entries.truncate(collector.limit);
+            entries.last().map(|entry| entry.key().clone())
+        } else {
+            None
+        };
+        Ok(ContextDiff { entries, next })
+    }
+
+    /// Returns a proof of the values of `keys` in this working tree, see `crate::proof`.
+    ///
+    /// The objects on the path of the keys are part of the proof, all other subtrees are
+    /// blinded (replaced by their hash).
+    pub fn produce_tree_proof(&self, keys: &[ContextKeyOwned]) -> Result<TreeProof, MerkleError> {
+        let keys: Vec<&[String]> = keys.iter().map(Vec::as_slice).collect();
+        let state = self.proof_tree(self.root_object(), &keys)?;
+        Ok(TreeProof::new(state, self.hash()?))
+    }
+
     /// Returns the root hash of this working tree.
     pub fn hash(&self) -> Result<ObjectHash, MerkleError> {
         let storage = self.index.storage.borrow();
@@ -924,6 +1011,42 @@ impl WorkingTree {
         }
     }
 
+    /// Returns where the value at `key` is stored in the repository: the offset of its
+    /// object, or the value itself when it is inlined in its directory.
+    ///
+    /// If object at `key` is missing or is a directory, this returns `None`.
+    pub fn find_value_location(
+        &self,
+        key: &ContextKey,
+    ) -> Result<Option<KeyHistoryValue>, MerkleError> {
+        let mut storage = self.index.storage.borrow_mut();
+        let mut strings = self.index.get_string_interner()?;
+
+        let root = self.get_root_directory();
+
+        let dir_entry_id = match self
+            .index
+            .find_dir_entry(root, key, &mut storage, &mut strings)?
+        {
+            Some(dir_entry_id) => dir_entry_id,
+            None => return Ok(None),
+        };
+        let dir_entry = storage.get_dir_entry(dir_entry_id)?;
+
+        if dir_entry.dir_entry_kind() != DirEntryKind::Blob {
+            return Ok(None);
+        }
+
+        if let Some(blob) = dir_entry.get_inlined_blob(&storage) {
+            return Ok(Some(KeyHistoryValue::Inlined(blob.to_vec())));
+        }
+
+        dir_entry
+            .get_offset()
+            .map(|offset| Some(KeyHistoryValue::Object(offset)))
+            .ok_or(MerkleError::InvalidState("Missing object offset"))
+    }
+
     /// Checks if a value (blob) at `key` exists in this working tree.
     ///
     /// Returns false if object at `key` is not a blob.
@@ -1331,6 +1454,374 @@ impl WorkingTree {
         }
     }
 
+    fn root_object(&self) -> Object {
+        match self.root {
+            WorkingTreeRoot::Directory(dir_id) => Object::Directory(dir_id),
+            WorkingTreeRoot::Value(blob_id) => Object::Blob(blob_id),
+        }
+    }
+
+    /// Entries of the directory `dir_id` with their names, sorted by name.
+    fn named_dir_entries(
+        &self,
+        dir_id: DirectoryId,
+    ) -> Result<Vec<(String, DirEntryId)>, MerkleError> {
+        let mut storage = self.index.storage.borrow_mut();
+        let mut strings = self.index.get_string_interner()?;
+        let repository = self.index.repository.read();
+
+        storage
+            .dir_to_vec_sorted(dir_id, &mut strings, &*repository)?
+            .into_iter()
+            .map(|(string_id, dir_entry_id)| {
+                Ok((strings.get_str(string_id)?.into_owned(), dir_entry_id))
+            })
+            .collect()
+    }
+
+    /// Returns the object of `dir_entry_id` and its hash, the hash is `None` for inlined blobs.
+    fn dir_entry_with_hash(
+        &self,
+        dir_entry_id: DirEntryId,
+    ) -> Result<(Object, Option<ObjectHash>), MerkleError> {
+        let mut storage = self.index.storage.borrow_mut();
+        let mut strings = self.index.get_string_interner()?;
+
+        let object = self
+            .index
+            .dir_entry_object(dir_entry_id, &mut storage, &mut strings)?;
+        let dir_entry = storage.get_dir_entry(dir_entry_id)?;
+
+        let mut repository = self.index.repository.write();
+        let hash = match dir_entry.object_hash_id(&mut *repository, &storage, &strings)? {
+            Some(hash_id) => Some(repository.get_hash(hash_id.into())?.into_owned()),
+            None => None,
+        };
+        Ok((object, hash))
+    }
+
+    fn blob_value(&self, blob_id: BlobId) -> Result<ContextValue, MerkleError> {
+        let storage = self.index.storage.borrow();
+        Ok(storage.get_blob(blob_id)?.to_vec())
+    }
+
+    /// See `Self::diff`, `self` is the tree of the `left` object, `other` of the `right` object.
+    ///
+    /// `after`, when set, starts with `key`: the objects are on the path of the first key to
+    /// return, changes of `key` itself and of the keys sorted before `after` are skipped.
+    fn diff_objects(
+        &self,
+        other: &Self,
+        key: &mut ContextKeyOwned,
+        after: Option<&[String]>,
+        left: Object,
+        right: Object,
+        diff: &mut DiffCollector,
+    ) -> Result<(), MerkleError> {
+        match (left, right) {
+            (Object::Blob(left), Object::Blob(right)) => {
+                if after.is_some() {
+                    return Ok(());
+                }
+                let old_value = self.blob_value(left)?;
+                let new_value = other.blob_value(right)?;
+                if old_value != new_value {
+                    let (old_value, new_value) = if diff.with_values {
+                        (Some(old_value), Some(new_value))
+                    } else {
+                        (None, None)
+                    };
+                    diff.push(ContextDiffEntry::Modified {
+                        key: key.clone(),
+                        old_value,
+                        new_value,
+                    });
+                }
+                Ok(())
+            }
+            (Object::Directory(left), Object::Directory(right)) => {
+                let mut left = self.named_dir_entries(left)?.into_iter().peekable();
+                let mut right = other.named_dir_entries(right)?.into_iter().peekable();
+
+                while !diff.is_full() {
+                    let name = match (left.peek(), right.peek()) {
+                        (None, None) => break,
+                        (Some((left_name, _)), Some((right_name, _))) => {
+                            left_name.min(right_name).clone()
+                        }
+                        (Some((name, _)), None) | (None, Some((name, _))) => name.clone(),
+                    };
+                    let left_entry = left.next_if(|(n, _)| *n == name).map(|(_, id)| id);
+                    let right_entry = right.next_if(|(n, _)| *n == name).map(|(_, id)| id);
+
+                    key.push(name);
+                    let after = match after {
+                        Some(after) if after.starts_with(key.as_slice()) => Some(after),
+                        // the whole subtree is sorted before `after`
+                        Some(after) if key.as_slice() < after => {
+                            key.pop();
+                            continue;
+                        }
+                        _ => None,
+                    };
+                    match (left_entry, right_entry) {
+                        (Some(left_entry), Some(right_entry)) => {
+                            let (left_object, left_hash) = self.dir_entry_with_hash(left_entry)?;
+                            let (right_object, right_hash) =
+                                other.dir_entry_with_hash(right_entry)?;
+                            // identical subtrees (or non-inlined blobs) are skipped
+                            if left_hash.is_none() || left_hash != right_hash {
+                                self.diff_objects(
+                                    other,
+                                    key,
+                                    after,
+                                    left_object,
+                                    right_object,
+                                    diff,
+                                )?;
+                            }
+                        }
+                        (Some(left_entry), None) => {
+                            let (object, _) = self.dir_entry_with_hash(left_entry)?;
+                            self.diff_collect(key, after, object, diff, DiffSide::Removed)?;
+                        }
+                        (None, Some(right_entry)) => {
+                            let (object, _) = other.dir_entry_with_hash(right_entry)?;
+                            other.diff_collect(key, after, object, diff, DiffSide::Added)?;
+                        }
+                        (None, None) => (),
+                    }
+                    key.pop();
+                }
+                Ok(())
+            }
+            (Object::Commit(_), _) | (_, Object::Commit(_)) => {
+                Err(MerkleError::FoundUnexpectedStructure {
+                    sought: "directory or blob".to_string(),
+                    found: "commit".to_string(),
+                })
+            }
+            // a value replaced by a directory, the value is sorted before the directory
+            (left @ Object::Blob(_), right) => {
+                self.diff_collect(key, after, left, diff, DiffSide::Removed)?;
+                other.diff_collect(key, after, right, diff, DiffSide::Added)
+            }
+            // a directory replaced by a value
+            (left, right) => {
+                other.diff_collect(key, after, right, diff, DiffSide::Added)?;
+                self.diff_collect(key, after, left, diff, DiffSide::Removed)
+            }
+        }
+    }
+
+    /// Pushes all values under `object` to `diff` as `side` changes, see `Self::diff_objects`
+    /// for `after`.
+    fn diff_collect(
+        &self,
+        key: &mut ContextKeyOwned,
+        after: Option<&[String]>,
+        object: Object,
+        diff: &mut DiffCollector,
+        side: DiffSide,
+    ) -> Result<(), MerkleError> {
+        match object {
+            Object::Blob(blob_id) => {
+                if after.is_none() {
+                    let value = if diff.with_values {
+                        Some(self.blob_value(blob_id)?)
+                    } else {
+                        None
+                    };
+                    diff.push(side.entry(key.clone(), value));
+                }
+                Ok(())
+            }
+            Object::Directory(dir_id) => {
+                for (name, dir_entry_id) in self.named_dir_entries(dir_id)? {
+                    if diff.is_full() {
+                        break;
+                    }
+                    key.push(name);
+                    let after = match after {
+                        Some(after) if after.starts_with(key.as_slice()) => Some(after),
+                        Some(after) if key.as_slice() < after => {
+                            key.pop();
+                            continue;
+                        }
+                        _ => None,
+                    };
+                    let (object, _) = self.dir_entry_with_hash(dir_entry_id)?;
+                    self.diff_collect(key, after, object, diff, side)?;
+                    key.pop();
+                }
+                Ok(())
+            }
+            Object::Commit(_) => Err(MerkleError::FoundUnexpectedStructure {
+                sought: "directory or blob".to_string(),
+                found: "commit".to_string(),
+            }),
+        }
+    }
+
+    /// See `Self::produce_tree_proof`, `keys` are the remaining parts of the keys under `object`.
+    fn proof_tree(&self, object: Object, keys: &[&[String]]) -> Result<proof::Tree, MerkleError> {
+        match object {
+            Object::Blob(blob_id) => Ok(proof::Tree::Value(self.blob_value(blob_id)?)),
+            Object::Directory(dir_id) => match dir_id.get_inode_id() {
+                None => Ok(proof::Tree::Node(self.proof_entries(dir_id, keys)?)),
+                Some(inode_id) => match self.proof_inode(inode_id, keys)? {
+                    proof::InodeTree::InodeTree(inode) => Ok(proof::Tree::Inode(inode)),
+                    proof::InodeTree::InodeExtender(extender) => {
+                        Ok(proof::Tree::Extender(extender))
+                    }
+                    _ => Err(MerkleError::InvalidState("inode without pointers")),
+                },
+            },
+            Object::Commit(_) => Err(MerkleError::FoundUnexpectedStructure {
+                sought: "directory or blob".to_string(),
+                found: "commit".to_string(),
+            }),
+        }
+    }
+
+    /// All the entries of the small directory `dir_id`, blinded when not on the path of `keys`.
+    fn proof_entries(
+        &self,
+        dir_id: DirectoryId,
+        keys: &[&[String]],
+    ) -> Result<Vec<(String, proof::Tree)>, MerkleError> {
+        self.named_dir_entries(dir_id)?
+            .into_iter()
+            .map(|(name, dir_entry_id)| {
+                let entry_keys: Vec<&[String]> = keys
+                    .iter()
+                    .filter_map(|key| match key.split_first() {
+                        Some((first, rest)) if *first == name => Some(rest),
+                        _ => None,
+                    })
+                    .collect();
+
+                let tree = if entry_keys.is_empty() {
+                    self.proof_blinded_entry(dir_entry_id)?
+                } else {
+                    let (object, _) = self.dir_entry_with_hash(dir_entry_id)?;
+                    self.proof_tree(object, &entry_keys)?
+                };
+                Ok((name, tree))
+            })
+            .collect()
+    }
+
+    fn proof_blinded_entry(&self, dir_entry_id: DirEntryId) -> Result<proof::Tree, MerkleError> {
+        let storage = self.index.storage.borrow();
+        let strings = self.index.get_string_interner()?;
+        let mut repository = self.index.repository.write();
+
+        let dir_entry = storage.get_dir_entry(dir_entry_id)?;
+        let hash = match dir_entry.object_hash_id(&mut *repository, &storage, &strings)? {
+            Some(hash_id) => repository.get_hash(hash_id.into())?.into_owned(),
+            None => {
+                let blob = dir_entry
+                    .get_inlined_blob(&storage)
+                    .ok_or(MerkleError::InvalidState("missing hash of a dir entry"))?;
+                hash_inlined_blob(blob)?
+            }
+        };
+
+        match dir_entry.dir_entry_kind() {
+            DirEntryKind::Blob => Ok(proof::Tree::BlindedValue(hash)),
+            DirEntryKind::Directory => Ok(proof::Tree::BlindedNode(hash)),
+        }
+    }
+
+    /// The pointers of `inode_id`, blinded when no key in `keys` goes through them.
+    ///
+    /// A chain of inodes with a single pointer is returned as an extender.
+    fn proof_inode(
+        &self,
+        inode_id: InodeId,
+        keys: &[&[String]],
+    ) -> Result<proof::InodeTree, MerkleError> {
+        let (depth, length, pointers) = {
+            let mut storage = self.index.storage.borrow_mut();
+            let mut strings = self.index.get_string_interner()?;
+            let repository = self.index.repository.read();
+
+            let inode = storage.get_inode(inode_id)?;
+            let (depth, length) = (inode.depth as u32, inode.nchildren as u64);
+            let pointers = storage.inode_pointers(inode_id, &mut strings, &*repository)?;
+            (depth, length, pointers)
+        };
+
+        let mut proofs = Vec::with_capacity(pointers.len());
+        for InodePointer {
+            index: ptr_index,
+            ptr_id,
+            hash_id,
+            ..
+        } in pointers
+        {
+            let pointer_keys: Vec<&[String]> = keys
+                .iter()
+                .filter(|key| match key.first() {
+                    Some(name) => index(depth, name) as u8 == ptr_index,
+                    None => false,
+                })
+                .copied()
+                .collect();
+
+            let tree = if pointer_keys.is_empty() {
+                proof::InodeTree::BlindedInode(self.proof_pointer_hash(ptr_id, hash_id)?)
+            } else {
+                match ptr_id {
+                    DirectoryOrInodeId::Directory(dir_id) => {
+                        proof::InodeTree::InodeValues(self.proof_entries(dir_id, &pointer_keys)?)
+                    }
+                    DirectoryOrInodeId::Inode(inode_id) => {
+                        self.proof_inode(inode_id, &pointer_keys)?
+                    }
+                }
+            };
+            proofs.push((ptr_index, tree));
+        }
+
+        match proofs.as_slice() {
+            [(_, proof::InodeTree::InodeTree(_))] | [(_, proof::InodeTree::InodeExtender(_))] => {
+                let (ptr_index, tree) = proofs.remove(0);
+                let (segment, proof) = match tree {
+                    proof::InodeTree::InodeExtender(extender) => {
+                        let mut segment = vec![ptr_index];
+                        segment.extend(extender.segment);
+                        (segment, extender.proof)
+                    }
+                    tree => (vec![ptr_index], Box::new(tree)),
+                };
+                Ok(proof::InodeTree::InodeExtender(proof::InodeExtender {
+                    length,
+                    segment,
+                    proof,
+                }))
+            }
+            _ => Ok(proof::InodeTree::InodeTree(proof::Inode { length, proofs })),
+        }
+    }
+
+    fn proof_pointer_hash(
+        &self,
+        ptr_id: DirectoryOrInodeId,
+        hash_id: Option<HashId>,
+    ) -> Result<ObjectHash, MerkleError> {
+        let storage = self.index.storage.borrow();
+        let strings = self.index.get_string_interner()?;
+        let mut repository = self.index.repository.write();
+
+        let hash_id = match hash_id {
+            Some(hash_id) => hash_id,
+            None => hash_long_inode(ptr_id, &mut *repository, &storage, &strings)?,
+        };
+        Ok(repository.get_hash(hash_id.into())?.into_owned())
+    }
+
     /// See `Self::traverse_working_tree` below
     fn traverse_working_tree_recursive(
         &self,
@@ -1468,10 +1959,7 @@ impl WorkingTree {
         &self,
         enable_stats: bool,
     ) -> Result<Option<WorkingTreeStatistics>, MerkleError> {
-        let object = match self.root {
-            WorkingTreeRoot::Directory(dir_id) => Object::Directory(dir_id),
-            WorkingTreeRoot::Value(blob_id) => Object::Blob(blob_id),
-        };
+        let object = self.root_object();
 
         let mut storage = self.index.storage.borrow_mut();
         let mut strings = self.index.get_string_interner()?;
@@ -2,16 +2,31 @@
 // SPDX-License-Identifier: MIT
 
 use std::convert::{TryFrom, TryInto};
+use std::env;
+use std::fs;
+use std::path::Path;
 use std::sync::Arc;
 
 use crypto::hash::ContextHash;
+use parking_lot::RwLock;
 use storage::tests_common::TmpStorage;
 use storage::{BlockHeaderWithHash, BlockStorage};
+use tezos_context::export::{export_commit, import_commit};
 use tezos_context::initializer::{initialize_tezedge_context, ContextKvStoreConfiguration};
-use tezos_context::{context_key, ContextError, TezedgeContext};
+use tezos_context::kv_store::persistent::PersistentConfiguration;
+use tezos_context::persistent::file::get_persistent_base_path;
+use tezos_context::persistent::KeyValueStoreBackend;
+use tezos_context::proof::encoding::ProofEncoding;
+use tezos_context::proof::{ProofError, TreeProof};
+use tezos_context::scrub::scrub_context;
+use tezos_context::{
+    context_key, ContextError, ContextKeyValueStore, Persistent, TezedgeContext, TezedgeIndex,
+};
 use tezos_context::{IndexApi, ProtocolContextApi, ShellContextApi};
 use tezos_context_api::{
-    ContextKey, TezosContextTezEdgeStorageConfiguration, TezosContextTezedgeOnDiskBackendOptions,
+    context_key_owned, ContextDiffEntry, ContextDiffRequest, ContextKey, ContextKeyHistory,
+    ContextKeyHistoryEntry, TezosContextTezEdgeStorageConfiguration,
+    TezosContextTezedgeOnDiskBackendOptions,
 };
 use tezos_messages::p2p::encoding::fitness::Fitness;
 use tezos_messages::p2p::encoding::prelude::BlockHeaderBuilder;
@@ -22,6 +37,7 @@ pub fn test_context_set_get_commit_persistent() -> Result<(), anyhow::Error> {
         ContextKvStoreConfiguration::OnDisk(TezosContextTezedgeOnDiskBackendOptions {
             base_path: "".to_string(),
             startup_check: false,
+            key_history: false,
         }),
         "__context:test_context_set_get_commit_persistent",
     )
@@ -33,6 +49,7 @@ pub fn test_context_set_get_commit() -> Result<(), anyhow::Error> {
         ContextKvStoreConfiguration::InMem(TezosContextTezedgeOnDiskBackendOptions {
             base_path: "".to_string(),
             startup_check: false,
+            key_history: false,
         }),
         "__context:test_context_set_get_commit",
     )
@@ -96,6 +113,7 @@ pub fn test_context_hash_from_working_tree_persistent() -> Result<(), anyhow::Er
         ContextKvStoreConfiguration::OnDisk(TezosContextTezedgeOnDiskBackendOptions {
             base_path: "".to_string(),
             startup_check: false,
+            key_history: false,
         }),
         "__context:test_context_hash_from_working_tree_persistent",
     )
@@ -107,6 +125,7 @@ pub fn test_context_hash_from_working_tree_memory() -> Result<(), anyhow::Error>
         ContextKvStoreConfiguration::InMem(TezosContextTezedgeOnDiskBackendOptions {
             base_path: "".to_string(),
             startup_check: false,
+            key_history: false,
         }),
         "__context:test_context_hash_from_working_tree_memory",
     )
@@ -171,6 +190,7 @@ pub fn test_context_delete_and_remove_persistent() -> Result<(), anyhow::Error>
         ContextKvStoreConfiguration::OnDisk(TezosContextTezedgeOnDiskBackendOptions {
             base_path: "".to_string(),
             startup_check: false,
+            key_history: false,
         }),
         "__context:test_context_delete_and_remove_persistent",
     )
@@ -182,6 +202,7 @@ pub fn test_context_delete_and_remove() -> Result<(), anyhow::Error> {
         ContextKvStoreConfiguration::InMem(TezosContextTezedgeOnDiskBackendOptions {
             base_path: "".to_string(),
             startup_check: false,
+            key_history: false,
         }),
         "__context:test_context_delete_and_remove",
     )
@@ -357,6 +378,7 @@ pub fn test_context_copy_persistent() -> Result<(), anyhow::Error> {
         ContextKvStoreConfiguration::OnDisk(TezosContextTezedgeOnDiskBackendOptions {
             base_path: "".to_string(),
             startup_check: false,
+            key_history: false,
         }),
         "__context:test_context_copy_persistent",
     )
@@ -368,6 +390,7 @@ pub fn test_context_copy() -> Result<(), anyhow::Error> {
         ContextKvStoreConfiguration::InMem(TezosContextTezedgeOnDiskBackendOptions {
             base_path: "".to_string(),
             startup_check: false,
+            key_history: false,
         }),
         "__context:test_context_copy",
     )
@@ -544,6 +567,586 @@ fn context_copy(backend: ContextKvStoreConfiguration, tmp_dir: &str) -> Result<(
     Ok(())
 }
 
+#[test]
+pub fn test_context_diff() -> Result<(), anyhow::Error> {
+    context_diff(
+        ContextKvStoreConfiguration::InMem(TezosContextTezedgeOnDiskBackendOptions {
+            base_path: "".to_string(),
+            startup_check: false,
+            key_history: false,
+        }),
+        "__context:test_context_diff",
+    )
+}
+
+fn context_diff(backend: ContextKvStoreConfiguration, tmp_dir: &str) -> Result<(), anyhow::Error> {
+    // prepare temp storage
+    let tmp_storage = TmpStorage::create_to_out_dir(tmp_dir).expect("Storage error");
+    let persistent_storage = tmp_storage.storage();
+
+    // init block with level 0 (because of commit)
+    let block = dummy_block("BLockGenesisGenesisGenesisGenesisGenesisb83baZgbyZe", 0)?;
+    let block_storage = BlockStorage::new(persistent_storage);
+    block_storage.put_block_header(&block)?;
+
+    // context
+    let mut context = initialize_tezedge_context(&TezosContextTezEdgeStorageConfiguration {
+        backend,
+        ipc_socket_path: None,
+    })
+    .unwrap();
+
+    context = context.add(&context_key!("data/a/unchanged"), &[1])?;
+    context = context.add(&context_key!("data/a/modified"), &[2])?;
+    context = context.add(&context_key!("data/b/removed/0"), &[3])?;
+    context = context.add(&context_key!("data/b/removed/1"), &[4])?;
+    context = context.add(&context_key!("data/c"), &[5])?;
+    context = context.add(&context_key!("data/e/f"), &[9])?;
+    let context_hash_1 = context.commit("Tezos".to_string(), "Genesis".to_string(), 0)?;
+
+    context = context.add(&context_key!("data/a/modified"), &[6])?;
+    context = context.delete(&context_key!("data/b"))?;
+    context = context.add(&context_key!("data/b/added"), &[7])?;
+    // a blob replaced by a directory is removed and added again
+    context = context.delete(&context_key!("data/c"))?;
+    context = context.add(&context_key!("data/c/d"), &[8])?;
+    // a directory replaced by a blob, the blob is sorted first
+    context = context.delete(&context_key!("data/e"))?;
+    context = context.add(&context_key!("data/e"), &[10])?;
+    let context_hash_2 = context.commit("Tezos".to_string(), "Genesis".to_string(), 0)?;
+
+    let diff = context.index.get_context_diff(
+        &context_hash_1,
+        &context_hash_2,
+        &ContextDiffRequest {
+            after: None,
+            limit: 100,
+            with_values: true,
+        },
+    )?;
+    assert_eq!(diff.next, None);
+    assert_eq!(
+        diff.entries,
+        vec![
+            ContextDiffEntry::Modified {
+                key: context_key_owned!("data/a/modified"),
+                old_value: Some(vec![2]),
+                new_value: Some(vec![6]),
+            },
+            ContextDiffEntry::Added {
+                key: context_key_owned!("data/b/added"),
+                value: Some(vec![7]),
+            },
+            ContextDiffEntry::Removed {
+                key: context_key_owned!("data/b/removed/0"),
+                value: Some(vec![3]),
+            },
+            ContextDiffEntry::Removed {
+                key: context_key_owned!("data/b/removed/1"),
+                value: Some(vec![4]),
+            },
+            ContextDiffEntry::Removed {
+                key: context_key_owned!("data/c"),
+                value: Some(vec![5]),
+            },
+            ContextDiffEntry::Added {
+                key: context_key_owned!("data/c/d"),
+                value: Some(vec![8]),
+            },
+            ContextDiffEntry::Added {
+                key: context_key_owned!("data/e"),
+                value: Some(vec![10]),
+            },
+            ContextDiffEntry::Removed {
+                key: context_key_owned!("data/e/f"),
+                value: Some(vec![9]),
+            },
+        ]
+    );
+
+    // the same changes, page by page and without values
+    let mut pages: Vec<Vec<String>> = Vec::new();
+    let mut request = ContextDiffRequest {
+        after: None,
+        limit: 3,
+        with_values: false,
+    };
+    loop {
+        let page = context
+            .index
+            .get_context_diff(&context_hash_1, &context_hash_2, &request)?;
+        assert!(page.entries.len() <= 3);
+        pages.push(page.entries.iter().map(|e| e.key().join("/")).collect());
+        match page.next {
+            Some(next) => request.after = Some(next),
+            None => break,
+        }
+    }
+    assert_eq!(
+        pages,
+        vec![
+            vec!["data/a/modified", "data/b/added", "data/b/removed/0"],
+            vec!["data/b/removed/1", "data/c", "data/c/d"],
+            vec!["data/e", "data/e/f"],
+        ]
+    );
+    let page = context.index.get_context_diff(
+        &context_hash_1,
+        &context_hash_2,
+        &ContextDiffRequest {
+            after: Some(context_key_owned!("data/b/removed")),
+            limit: 1,
+            with_values: false,
+        },
+    )?;
+    assert_eq!(
+        page.entries,
+        vec![ContextDiffEntry::Removed {
+            key: context_key_owned!("data/b/removed/0"),
+            value: None,
+        }]
+    );
+    assert_eq!(page.next, Some(context_key_owned!("data/b/removed/0")));
+
+    // no changes between a commit and itself
+    assert!(context
+        .index
+        .get_context_diff(
+            &context_hash_2,
+            &context_hash_2,
+            &ContextDiffRequest::default()
+        )?
+        .entries
+        .is_empty());
+
+    Ok(())
+}
+
+#[test]
+pub fn test_context_tree_proof_persistent() -> Result<(), anyhow::Error> {
+    context_tree_proof(
+        ContextKvStoreConfiguration::OnDisk(TezosContextTezedgeOnDiskBackendOptions {
+            base_path: "".to_string(),
+            startup_check: false,
+            key_history: false,
+        }),
+        "__context:test_context_tree_proof_persistent",
+    )
+}
+
+#[test]
+pub fn test_context_tree_proof() -> Result<(), anyhow::Error> {
+    context_tree_proof(
+        ContextKvStoreConfiguration::InMem(TezosContextTezedgeOnDiskBackendOptions {
+            base_path: "".to_string(),
+            startup_check: false,
+            key_history: false,
+        }),
+        "__context:test_context_tree_proof",
+    )
+}
+
+fn context_tree_proof(
+    backend: ContextKvStoreConfiguration,
+    tmp_dir: &str,
+) -> Result<(), anyhow::Error> {
+    // prepare temp storage
+    let tmp_storage = TmpStorage::create_to_out_dir(tmp_dir).expect("Storage error");
+    let persistent_storage = tmp_storage.storage();
+
+    // init block with level 0 (because of commit)
+    let block = dummy_block("BLockGenesisGenesisGenesisGenesisGenesisb83baZgbyZe", 0)?;
+    let block_storage = BlockStorage::new(persistent_storage);
+    block_storage.put_block_header(&block)?;
+
+    // context
+    let mut context = initialize_tezedge_context(&TezosContextTezEdgeStorageConfiguration {
+        backend,
+        ipc_socket_path: None,
+    })
+    .unwrap();
+
+    // a large directory is hashed as an inode tree
+    for index in 0..1000 {
+        context = context.add(
+            &context_key!("data/contracts/index/{}", index),
+            &[(index % 256) as u8; 40],
+        )?;
+    }
+    context = context.add(&context_key!("data/votes/current_period"), &[1, 2])?;
+    context = context.add(&context_key!("data/votes/listings_size"), &[3])?;
+    let context_hash = context.commit("Tezos".to_string(), "Genesis".to_string(), 0)?;
+    let root = context.get_merkle_root()?;
+
+    let keys = vec![
+        context_key_owned!("data/contracts/index/42"),
+        context_key_owned!("data/contracts/index/999"),
+        context_key_owned!("data/contracts/index/missing"),
+        context_key_owned!("data/votes/current_period"),
+    ];
+    let proof = context.index.produce_tree_proof(&context_hash, &keys)?;
+    proof.verify_root(&root)?;
+
+    assert_eq!(
+        proof.find(&context_key!("data/contracts/index/42"))?,
+        Some(vec![42; 40])
+    );
+    assert_eq!(
+        proof.find(&context_key!("data/contracts/index/999"))?,
+        Some(vec![(999 % 256) as u8; 40])
+    );
+    assert_eq!(
+        proof.find(&context_key!("data/contracts/index/missing"))?,
+        None
+    );
+    assert_eq!(
+        proof.find(&context_key!("data/votes/current_period"))?,
+        Some(vec![1, 2])
+    );
+    // not part of the proof
+    assert!(proof
+        .find(&context_key!("data/votes/listings_size"))
+        .is_err());
+
+    // the proof survives the encoding
+    let decoded = TreeProof::from_bytes(&proof.to_bytes()?)?;
+    assert_eq!(decoded, proof);
+    decoded.verify_root(&root)?;
+
+    // a proof with a modified value doesn't verify
+    let other_proof = {
+        let context = context.add(&context_key!("data/contracts/index/42"), &[0])?;
+        let context_hash = context.commit("Tezos".to_string(), "Genesis".to_string(), 0)?;
+        context.index.produce_tree_proof(&context_hash, &keys)?
+    };
+    other_proof.verify()?;
+    assert!(other_proof.verify_root(&root).is_err());
+
+    Ok(())
+}
+
+/// `Context.Proof` fixtures in the Octez `Merkle_proof_encoding.V1.Tree32` encoding,
+/// generated by `tests/resources/context_proofs/generate.py`
+#[test]
+pub fn test_octez_tree_proofs() -> Result<(), anyhow::Error> {
+    #[derive(serde::Deserialize)]
+    struct Fixture {
+        key: String,
+        value: Option<String>,
+    }
+
+    let dir = Path::new(&env::var("CARGO_MANIFEST_DIR")?)
+        .join("tests")
+        .join("resources")
+        .join("context_proofs");
+    let mut fixtures = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("bin") {
+            continue;
+        }
+        let bytes = fs::read(&path)?;
+        let fixture: Fixture = serde_json::from_slice(&fs::read(path.with_extension("json"))?)?;
+
+        let proof = TreeProof::from_bytes(&bytes)?;
+        proof.verify()?;
+        assert_eq!(proof.to_bytes()?, bytes, "{}", path.display());
+
+        let key = fixture.key.split('/').collect::<Vec<_>>();
+        assert_eq!(
+            proof.find(&key)?.map(hex::encode),
+            fixture.value,
+            "{}",
+            path.display()
+        );
+
+        assert!(matches!(
+            TreeProof::from_bytes_with(&bytes, ProofEncoding::V2),
+            Err(ProofError::UnsupportedEncoding { .. })
+        ));
+        assert!(matches!(
+            proof.to_bytes_with(ProofEncoding::V2),
+            Err(ProofError::UnsupportedEncoding { .. })
+        ));
+
+        fixtures += 1;
+    }
+    assert_eq!(fixtures, 3);
+
+    Ok(())
+}
+
+#[test]
+pub fn test_context_export_import_persistent() -> Result<(), anyhow::Error> {
+    context_export_import(
+        ContextKvStoreConfiguration::OnDisk(TezosContextTezedgeOnDiskBackendOptions {
+            base_path: "".to_string(),
+            startup_check: false,
+            key_history: false,
+        }),
+        "__context:test_context_export_import_persistent",
+    )
+}
+
+#[test]
+pub fn test_context_export_import() -> Result<(), anyhow::Error> {
+    context_export_import(
+        ContextKvStoreConfiguration::InMem(TezosContextTezedgeOnDiskBackendOptions {
+            base_path: "".to_string(),
+            startup_check: false,
+            key_history: false,
+        }),
+        "__context:test_context_export_import",
+    )
+}
+
+fn context_export_import(
+    backend: ContextKvStoreConfiguration,
+    tmp_dir: &str,
+) -> Result<(), anyhow::Error> {
+    // prepare temp storage
+    let tmp_storage = TmpStorage::create_to_out_dir(tmp_dir).expect("Storage error");
+    let persistent_storage = tmp_storage.storage();
+
+    // init block with level 0 (because of commit)
+    let block = dummy_block("BLockGenesisGenesisGenesisGenesisGenesisb83baZgbyZe", 0)?;
+    let block_storage = BlockStorage::new(persistent_storage);
+    block_storage.put_block_header(&block)?;
+
+    // context
+    let mut context = initialize_tezedge_context(&TezosContextTezEdgeStorageConfiguration {
+        backend,
+        ipc_socket_path: None,
+    })
+    .unwrap();
+
+    // a large directory is hashed as an inode tree
+    for index in 0..1000 {
+        context = context.add(
+            &context_key!("data/contracts/index/{}", index),
+            &[(index % 256) as u8; 40],
+        )?;
+    }
+    context = context.add(&context_key!("data/votes/current_period"), &[1, 2])?;
+    context = context.add(&context_key!("data/empty"), &[])?;
+    let parent_hash = context.commit("Tezos".to_string(), "Genesis".to_string(), 0)?;
+
+    let context = context.index.checkout(&parent_hash)?.unwrap();
+    let context = context.add(&context_key!("data/votes/listings_size"), &[3; 200])?;
+    let context_hash = context.commit("Tezos".to_string(), "Block 1".to_string(), 1)?;
+
+    let mut stream = Vec::new();
+    export_commit(&context.index, &context_hash, &mut stream)?;
+
+    for target in [
+        ContextKvStoreConfiguration::InMem(TezosContextTezedgeOnDiskBackendOptions {
+            base_path: "".to_string(),
+            startup_check: false,
+            key_history: false,
+        }),
+        ContextKvStoreConfiguration::OnDisk(TezosContextTezedgeOnDiskBackendOptions {
+            base_path: "".to_string(),
+            startup_check: false,
+            key_history: false,
+        }),
+    ] {
+        let imported = initialize_tezedge_context(&TezosContextTezEdgeStorageConfiguration {
+            backend: target,
+            ipc_socket_path: None,
+        })
+        .unwrap();
+
+        let imported_hash = import_commit(&imported.index, stream.as_slice())?;
+        assert_eq!(imported_hash, context_hash);
+
+        let imported = imported.index.checkout(&context_hash)?.unwrap();
+        assert_eq!(
+            imported.find(&context_key!("data/contracts/index/999"))?,
+            Some(vec![(999 % 256) as u8; 40])
+        );
+        assert_eq!(
+            imported.find(&context_key!("data/votes/listings_size"))?,
+            Some(vec![3; 200])
+        );
+        assert_eq!(imported.find(&context_key!("data/empty"))?, Some(vec![]));
+    }
+
+    // a modified stream is rejected: change the first byte of the author,
+    // after the magic, version, context hash, parent hash, time and author length
+    let mut modified = stream.clone();
+    modified[8 + 1 + 32 + 1 + 32 + 1 + 1] ^= 1;
+    let imported = initialize_tezedge_context(&TezosContextTezEdgeStorageConfiguration {
+        backend: ContextKvStoreConfiguration::InMem(TezosContextTezedgeOnDiskBackendOptions {
+            base_path: "".to_string(),
+            startup_check: false,
+            key_history: false,
+        }),
+        ipc_socket_path: None,
+    })
+    .unwrap();
+    assert!(import_commit(&imported.index, modified.as_slice()).is_err());
+
+    Ok(())
+}
+
+#[test]
+pub fn test_context_scrub_persistent() -> Result<(), anyhow::Error> {
+    // prepare temp storage
+    let tmp_storage = TmpStorage::create_to_out_dir("__context:test_context_scrub_persistent")
+        .expect("Storage error");
+    let persistent_storage = tmp_storage.storage();
+
+    // init block with level 0 (because of commit)
+    let block = dummy_block("BLockGenesisGenesisGenesisGenesisGenesisb83baZgbyZe", 0)?;
+    let block_storage = BlockStorage::new(persistent_storage);
+    block_storage.put_block_header(&block)?;
+
+    // context
+    let mut context = initialize_tezedge_context(&TezosContextTezEdgeStorageConfiguration {
+        backend: ContextKvStoreConfiguration::OnDisk(TezosContextTezedgeOnDiskBackendOptions {
+            base_path: "".to_string(),
+            startup_check: false,
+            key_history: false,
+        }),
+        ipc_socket_path: None,
+    })
+    .unwrap();
+
+    // a large directory is hashed as an inode tree
+    for index in 0..1000 {
+        context = context.add(
+            &context_key!("data/contracts/index/{}", index),
+            &[(index % 256) as u8; 40],
+        )?;
+    }
+    context = context.add(&context_key!("data/votes/current_period"), &[1; 200])?;
+    let context_hash = context.commit("Tezos".to_string(), "Genesis".to_string(), 0)?;
+
+    let context = context.index.checkout(&context_hash)?.unwrap();
+    let context = context.add(&context_key!("data/contracts/index/42"), &[0])?;
+    context.commit("Tezos".to_string(), "Block 1".to_string(), 1)?;
+
+    let mut repository = context.index.repository.write();
+    let report = scrub_context(&mut *repository, 10, |_| {})?;
+
+    assert!(report.corruption.is_none(), "{:?}", report.corruption);
+    assert_eq!(report.commits_checked, 2);
+    assert!(report.objects_checked > 0);
+
+    Ok(())
+}
+
+#[test]
+pub fn test_context_key_history_persistent() -> Result<(), anyhow::Error> {
+    let base_path = get_persistent_base_path(None);
+    let open_repository = || -> Result<Persistent, anyhow::Error> {
+        let mut repository = Persistent::try_new(PersistentConfiguration {
+            db_path: Some(base_path.clone()),
+            startup_check: false,
+            read_mode: false,
+        })?;
+        repository.enable_key_history()?;
+        Ok(repository)
+    };
+    let entry = |level: u32, value: Option<&[u8]>| ContextKeyHistoryEntry {
+        level,
+        value: value.map(<[u8]>::to_vec),
+    };
+    let history = |entries: Vec<ContextKeyHistoryEntry>, missing_levels: Vec<u32>| {
+        Some(ContextKeyHistory {
+            entries,
+            missing_levels,
+        })
+    };
+
+    // values of `data/a` are stored as objects, the others are inlined in their directory
+    let expected_a = vec![entry(0, Some(&[0; 40])), entry(1, Some(&[1; 40]))];
+
+    {
+        let repository: Arc<RwLock<ContextKeyValueStore>> =
+            Arc::new(RwLock::new(open_repository()?));
+        let index = TezedgeIndex::new(repository, None);
+
+        // level 0
+        let context = TezedgeContext::new(index.clone(), None, None);
+        let context = context.add(&context_key!("data/a"), &[0; 40])?;
+        let context = context.add(&context_key!("data/b"), &[0])?;
+        let genesis = context.commit("Tezos".to_string(), "Genesis".to_string(), 0)?;
+        index.block_applied(0, &genesis)?;
+
+        // level 1
+        let context = index.checkout(&genesis)?.unwrap();
+        let context = context.add(&context_key!("data/a"), &[1; 40])?;
+        let context = context.delete(&context_key!("data/b"))?;
+        let block1 = context.commit("Tezos".to_string(), "Block 1".to_string(), 1)?;
+        index.block_applied(1, &block1)?;
+
+        // level 2
+        let context = index.checkout(&block1)?.unwrap();
+        let context = context.add(&context_key!("data/a"), &[2; 40])?;
+        let block2 = context.commit("Tezos".to_string(), "Block 2".to_string(), 2)?;
+        index.block_applied(2, &block2)?;
+
+        // level 2 on another branch, replacing the changes of the previous block 2
+        let context = index.checkout(&block1)?.unwrap();
+        let context = context.add(&context_key!("data/c"), &[2])?;
+        let block2 = context.commit("Tezos".to_string(), "Block 2'".to_string(), 2)?;
+        index.block_applied(2, &block2)?;
+
+        assert_eq!(
+            index.get_key_history(&context_key!("data/a"), 0, 10)?,
+            history(expected_a.clone(), vec![])
+        );
+        assert_eq!(
+            index.get_key_history(&context_key!("data/b"), 0, 10)?,
+            history(vec![entry(0, Some(&[0])), entry(1, None)], vec![])
+        );
+        assert_eq!(
+            index.get_key_history(&context_key!("data/c"), 0, 10)?,
+            history(vec![entry(2, Some(&[2]))], vec![])
+        );
+        assert_eq!(
+            index.get_key_history(&context_key!("data/d"), 0, 10)?,
+            history(vec![], vec![])
+        );
+
+        // the last change before `from_level` is the value at `from_level`
+        assert_eq!(
+            index.get_key_history(&context_key!("data/a"), 2, 10)?,
+            history(vec![entry(1, Some(&[1; 40]))], vec![])
+        );
+        assert_eq!(
+            index.get_key_history(&context_key!("data/b"), 0, 0)?,
+            history(vec![entry(0, Some(&[0]))], vec![])
+        );
+
+        // level 3, its changes could not be recorded
+        index.repository.write().put_key_history_missing(3)?;
+
+        assert_eq!(
+            index.get_key_history(&context_key!("data/a"), 0, 10)?,
+            history(expected_a.clone(), vec![3])
+        );
+        assert_eq!(
+            index.get_key_history(&context_key!("data/a"), 0, 2)?,
+            history(expected_a.clone(), vec![])
+        );
+    }
+
+    // the history and its index are read again from disk
+    let repository = open_repository()?;
+    assert_eq!(
+        repository.get_key_history(&context_key!("data/a"), 0, 10)?,
+        history(expected_a, vec![3])
+    );
+    assert_eq!(
+        repository.get_key_history(&context_key!("data/c"), 0, 10)?,
+        history(vec![entry(2, Some(&[2]))], vec![3])
+    );
+
+    Ok(())
+}
+
 fn dummy_block(block_hash: &str, level: i32) -> Result<BlockHeaderWithHash, anyhow::Error> {
     Ok(BlockHeaderWithHash {
         hash: block_hash.try_into()?,
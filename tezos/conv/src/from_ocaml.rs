@@ -112,6 +112,7 @@ impl_from_ocaml_record! {
     OCamlTezosContextTezedgeOnDiskBackendOptions => TezosContextTezedgeOnDiskBackendOptions {
         base_path: String,
         startup_check: bool,
+        key_history: bool,
     }
 }
 
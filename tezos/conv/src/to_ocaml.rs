@@ -119,6 +119,7 @@ impl_to_ocaml_record! {
     TezosContextTezedgeOnDiskBackendOptions => OCamlTezosContextTezedgeOnDiskBackendOptions {
         base_path: String,
         startup_check: bool,
+        key_history: bool,
     }
 }
 
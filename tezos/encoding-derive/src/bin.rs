@@ -8,13 +8,14 @@ use syn::spanned::Spanned;
 
 pub fn generate_bin_write_for_data(data: &DataWithEncoding) -> TokenStream {
     let name = data.name;
+    let (impl_generics, ty_generics, where_clause) = data.generics.split_for_impl();
     let bin_write = generate_bin_write(&data.encoding);
     quote_spanned! {
         data.name.span()=>
         #[allow(unused_parens)]
         #[allow(clippy::unnecessary_cast)]
         #[allow(clippy::redundant_closure_call)]
-        impl tezos_encoding::enc::BinWriter for #name {
+        impl #impl_generics tezos_encoding::enc::BinWriter for #name #ty_generics #where_clause {
             fn bin_write(&self, out: &mut Vec<u8>) -> tezos_encoding::enc::BinResult {
                 #bin_write(self, out)
             }
@@ -26,7 +27,7 @@ fn generate_bin_write(encoding: &Encoding) -> TokenStream {
     match encoding {
         Encoding::Unit => unreachable!(),
         Encoding::Primitive(primitive, span) => generage_primitive_bin_write(*primitive, *span),
-        Encoding::Bytes(span) => generate_bytes_bin_write(*span),
+        Encoding::Bytes(span) | Encoding::BytesRef(span) => generate_bytes_bin_write(*span),
         Encoding::Path(path) => {
             quote_spanned!(path.span()=> <#path as tezos_encoding::enc::BinWriter>::bin_write)
         }
@@ -8,9 +8,10 @@ use syn::spanned::Spanned;
 
 pub fn generate_encoding_for_data(data: &DataWithEncoding) -> TokenStream {
     let name = data.name;
+    let (impl_generics, ty_generics, where_clause) = data.generics.split_for_impl();
     let encoding = generate_encoding(&data.encoding);
     quote_spanned! {data.name.span()=>
-        impl tezos_encoding::encoding::HasEncoding for #name {
+        impl #impl_generics tezos_encoding::encoding::HasEncoding for #name #ty_generics #where_clause {
             fn encoding() -> tezos_encoding::encoding::Encoding {
                 #encoding
             }
@@ -22,7 +23,9 @@ pub(crate) fn generate_encoding(encoding: &Encoding) -> TokenStream {
     match encoding {
         Encoding::Unit => quote!(tezos_encoding::encoding::Encoding::Unit),
         Encoding::Primitive(primitive, span) => generage_primitive_encoding(*primitive, *span),
-        Encoding::Bytes(span) => quote_spanned!(*span=> tezos_encoding::encoding::Encoding::Bytes),
+        Encoding::Bytes(span) | Encoding::BytesRef(span) => {
+            quote_spanned!(*span=> tezos_encoding::encoding::Encoding::Bytes)
+        }
         Encoding::Path(path) => {
             quote_spanned!(path.span()=> #[allow(clippy::redundant_clone)]<#path as tezos_encoding::encoding::HasEncoding>::encoding().clone())
         }
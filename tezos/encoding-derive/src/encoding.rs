@@ -9,9 +9,17 @@ use proc_macro2::Span;
 #[derive(Debug)]
 pub struct DataWithEncoding<'a> {
     pub name: &'a syn::Ident,
+    pub generics: &'a syn::Generics,
     pub encoding: Encoding<'a>,
 }
 
+impl<'a> DataWithEncoding<'a> {
+    /// Lifetime of the input buffer the data references, if any.
+    pub fn borrowed_lifetime(&self) -> Option<&syn::Lifetime> {
+        self.generics.lifetimes().next().map(|def| &def.lifetime)
+    }
+}
+
 #[derive(Debug)]
 pub struct StructEncoding<'a> {
     pub name: &'a syn::Ident,
@@ -66,6 +74,8 @@ pub enum Encoding<'a> {
     Unit,
     Primitive(PrimitiveEncoding, Span),
     Bytes(Span),
+    /// Bytes referenced by `&'a [u8]` field.
+    BytesRef(Span),
     Path(&'a syn::Path),
     Zarith(Span),
     MuTez(Span),
@@ -11,15 +11,23 @@ type Result<T> = std::result::Result<T, syn::Error>;
 
 pub fn make_encoding(input: &syn::DeriveInput) -> Result<DataWithEncoding> {
     let meta = &mut get_encoding_meta(&input.attrs)?;
-    let data_with_encoding = make_data_with_encoding(&input.data, &input.ident, meta)?;
+    let data_with_encoding =
+        make_data_with_encoding(&input.data, &input.ident, &input.generics, meta)?;
     Ok(data_with_encoding)
 }
 
 fn make_data_with_encoding<'a>(
     data: &'a syn::Data,
     name: &'a syn::Ident,
+    generics: &'a syn::Generics,
     meta: &mut Vec<syn::Meta>,
 ) -> Result<DataWithEncoding<'a>> {
+    if let Some(param) = generics.type_params().next() {
+        return Err(error_spanned(param, "Type parameters are not supported"));
+    }
+    if let Some(def) = generics.lifetimes().nth(1) {
+        return Err(error_spanned(def, "Only single lifetime parameter is supported"));
+    }
     let encoding = match data {
         syn::Data::Struct(data_struct) => {
             Encoding::Struct(make_struct_encoding(data_struct, name)?)
@@ -34,7 +42,11 @@ fn make_data_with_encoding<'a>(
     };
     let encoding = make_bounded_encoding(meta, encoding)?;
     assert_empty_meta(meta)?;
-    Ok(DataWithEncoding { name, encoding })
+    Ok(DataWithEncoding {
+        name,
+        generics,
+        encoding,
+    })
 }
 
 fn make_struct_encoding<'a>(
@@ -91,10 +103,24 @@ fn make_field(field: &syn::Field) -> Result<FieldEncoding> {
 fn make_type_encoding<'a>(ty: &'a syn::Type, meta: &mut Vec<syn::Meta>) -> Result<Encoding<'a>> {
     match ty {
         syn::Type::Path(type_path) => make_type_path_encoding(&type_path.path, meta),
+        syn::Type::Reference(type_ref) if is_byte_slice(&type_ref.elem) => {
+            Ok(Encoding::BytesRef(ty.span()))
+        }
         _ => Err(error_spanned(ty, "Unsupported type")),
     }
 }
 
+/// Checks that the type is `[u8]`.
+fn is_byte_slice(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Slice(slice) => matches!(
+            &*slice.elem,
+            syn::Type::Path(type_path) if type_path.path == symbol::rust::U8
+        ),
+        _ => false,
+    }
+}
+
 /// Creates encoding from the type path `ty` (e.g. `mod::ty` or `u8`) and meta attributes.
 fn make_type_path_encoding<'a>(
     path: &'a syn::Path,
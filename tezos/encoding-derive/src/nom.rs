@@ -13,6 +13,20 @@ const NOM_TUPLE_MAX: usize = 26;
 pub fn generate_nom_read_for_data(data: &DataWithEncoding) -> TokenStream {
     let name = data.name;
     let nom_read = generate_nom_read(&data.encoding);
+    if let Some(lifetime) = data.borrowed_lifetime() {
+        let (impl_generics, ty_generics, where_clause) = data.generics.split_for_impl();
+        return quote_spanned! {
+            data.name.span()=>
+            #[allow(unused_parens)]
+            #[allow(clippy::unnecessary_cast)]
+            #[allow(clippy::redundant_closure_call)]
+            impl #impl_generics tezos_encoding::nom::NomReaderBorrowed<#lifetime> for #name #ty_generics #where_clause {
+                fn nom_read_borrowed(bytes: &#lifetime [u8]) -> tezos_encoding::nom::NomResult<#lifetime, Self> {
+                    #nom_read(bytes)
+                }
+            }
+        };
+    }
     quote_spanned! {
         data.name.span()=>
         #[allow(unused_parens)]
@@ -31,6 +45,10 @@ fn generate_nom_read(encoding: &Encoding) -> TokenStream {
         Encoding::Unit => unreachable!(),
         Encoding::Primitive(primitive, span) => generage_primitive_nom_read(*primitive, *span),
         Encoding::Bytes(span) => generate_bytes_nom_read(*span),
+        Encoding::BytesRef(span) => quote_spanned!(*span=> tezos_encoding::nom::bytes_ref),
+        Encoding::Path(path) if is_borrowed_path(path) => {
+            quote_spanned!(path.span()=> <#path as tezos_encoding::nom::NomReaderBorrowed>::nom_read_borrowed)
+        }
         Encoding::Path(path) => {
             quote_spanned!(path.span()=> <#path as tezos_encoding::nom::NomReader>::nom_read)
         }
@@ -48,6 +66,17 @@ fn generate_nom_read(encoding: &Encoding) -> TokenStream {
     }
 }
 
+/// Checks if the type has a lifetime argument, i.e. references the input buffer.
+fn is_borrowed_path(path: &syn::Path) -> bool {
+    path.segments.last().map_or(false, |segment| {
+        matches!(
+            &segment.arguments,
+            syn::PathArguments::AngleBracketed(args)
+                if args.args.iter().any(|arg| matches!(arg, syn::GenericArgument::Lifetime(_)))
+        )
+    })
+}
+
 fn get_primitive_byte_mapping(kind: PrimitiveEncoding) -> Option<&'static str> {
     static PRIMITIVE_BYTES_MAPPING: SyncLazy<Vec<(PrimitiveEncoding, &'static str)>> =
         SyncLazy::new(|| {
@@ -0,0 +1,1514 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Binary schema descriptions in the format used by Octez.
+//!
+//! [BinarySchema] mirrors OCaml `Data_encoding.Binary_schema`: a toplevel layout
+//! followed by auxiliary definitions referenced as `$name`. A schema can be built
+//! from an [Encoding], rendered as the text printed by `tezos-codec describe <id> binary schema`,
+//! parsed back from that text and structurally compared with another schema.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::encoding::{Encoding, TagMap};
+
+const Z_DESCRIPTION: &str = "A variable-length sequence of bytes encoding a Zarith integer. Each byte has a running unary size bit: the most significant bit of each byte indicates whether this is the last byte in the sequence (0) or whether the sequence continues (1). The second most significant bit of the first byte is reserved for the sign (0 for positive, 1 for negative). Size and sign bits ignored, the data is the binary representation of the absolute value of the number in little-endian order.";
+
+const N_DESCRIPTION: &str = "A variable-length sequence of bytes encoding a Zarith natural number. Each byte has a running unary size bit: the most significant bit of each byte indicates whether this is the last byte in the sequence (0) or whether the sequence continues (1). Size bits ignored, the data is the binary representation of the number in little-endian order.";
+
+/// Integer kinds appearing in binary descriptions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegerKind {
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int31,
+    Int32,
+    Uint30,
+    Uint32,
+    Int64,
+}
+
+impl IntegerKind {
+    /// Size of the integer in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            IntegerKind::Int8 | IntegerKind::Uint8 => 1,
+            IntegerKind::Int16 | IntegerKind::Uint16 => 2,
+            IntegerKind::Int31 | IntegerKind::Int32 | IntegerKind::Uint30 | IntegerKind::Uint32 => 4,
+            IntegerKind::Int64 => 8,
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            IntegerKind::Int8 => "signed 8-bit integer",
+            IntegerKind::Uint8 => "unsigned 8-bit integer",
+            IntegerKind::Int16 => "signed 16-bit integer",
+            IntegerKind::Uint16 => "unsigned 16-bit integer",
+            IntegerKind::Int31 => "signed 31-bit integer",
+            IntegerKind::Int32 => "signed 32-bit integer",
+            IntegerKind::Uint30 => "unsigned 30-bit integer",
+            IntegerKind::Uint32 => "unsigned 32-bit integer",
+            IntegerKind::Int64 => "signed 64-bit integer",
+        }
+    }
+
+    fn tag(size: usize) -> Self {
+        if size == 1 {
+            IntegerKind::Uint8
+        } else {
+            IntegerKind::Uint16
+        }
+    }
+}
+
+impl fmt::Display for IntegerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+impl FromStr for IntegerKind {
+    type Err = SchemaParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            IntegerKind::Int8,
+            IntegerKind::Uint8,
+            IntegerKind::Int16,
+            IntegerKind::Uint16,
+            IntegerKind::Int31,
+            IntegerKind::Int32,
+            IntegerKind::Uint30,
+            IntegerKind::Uint32,
+            IntegerKind::Int64,
+        ]
+        .into_iter()
+        .find(|kind| kind.description() == s)
+        .ok_or_else(|| SchemaParseError::new(format!("unknown integer kind `{}`", s)))
+    }
+}
+
+/// Size of a field, `Data_encoding.Encoding.Kind.t` in OCaml.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeKind {
+    /// Field of a fixed size in bytes.
+    Fixed(usize),
+    /// Field whose size can be determined from its contents.
+    Dynamic,
+    /// Field that occupies all the remaining data.
+    Variable,
+}
+
+impl fmt::Display for SizeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SizeKind::Fixed(1) => write!(f, "1 byte"),
+            SizeKind::Fixed(size) => write!(f, "{} bytes", size),
+            SizeKind::Dynamic => write!(f, "Determined from data"),
+            SizeKind::Variable => write!(f, "Variable"),
+        }
+    }
+}
+
+impl FromStr for SizeKind {
+    type Err = SchemaParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Determined from data" => Ok(SizeKind::Dynamic),
+            "Variable" => Ok(SizeKind::Variable),
+            _ => s
+                .strip_suffix(" bytes")
+                .or_else(|| s.strip_suffix(" byte"))
+                .and_then(|size| size.parse().ok())
+                .map(SizeKind::Fixed)
+                .ok_or_else(|| SchemaParseError::new(format!("unknown size `{}`", s))),
+        }
+    }
+}
+
+/// Contents of a field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Layout {
+    ZeroWidth,
+    Int(IntegerKind),
+    Bool,
+    Float,
+    Bytes,
+    Padding,
+    /// Integer representing an enumeration described by the named definition.
+    Enum(IntegerKind, String),
+    /// Reference to a named definition.
+    Ref(String),
+    /// Sequence of elements, optionally limited in length.
+    Seq(Box<Layout>, Option<usize>),
+    /// Contents that cannot be described structurally.
+    Other(String),
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layout::ZeroWidth => write!(f, "placeholder (not a real field)"),
+            Layout::Int(kind) => write!(f, "{}", kind),
+            Layout::Bool => write!(f, "boolean (0 for false, 255 for true)"),
+            Layout::Float => write!(f, "double-precision floating-point number"),
+            Layout::Bytes => write!(f, "bytes"),
+            Layout::Padding => write!(f, "padding"),
+            Layout::Enum(kind, name) => write!(f, "{} encoding an enumeration (see {})", kind, name),
+            Layout::Ref(name) => write!(f, "${}", name),
+            Layout::Seq(layout, None) => write!(f, "sequence of {}", layout),
+            Layout::Seq(layout, Some(max)) => write!(f, "sequence of at most {} {}", max, layout),
+            Layout::Other(description) => write!(f, "{}", description),
+        }
+    }
+}
+
+impl FromStr for Layout {
+    type Err = SchemaParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let layout = match s {
+            "placeholder (not a real field)" => Layout::ZeroWidth,
+            "boolean (0 for false, 255 for true)" => Layout::Bool,
+            "double-precision floating-point number" => Layout::Float,
+            "bytes" => Layout::Bytes,
+            "padding" => Layout::Padding,
+            _ => {
+                if let Some(name) = s.strip_prefix('$') {
+                    Layout::Ref(name.to_string())
+                } else if let Some(rest) = s.strip_prefix("sequence of at most ") {
+                    let (max, element) = rest.split_once(' ').ok_or_else(|| {
+                        SchemaParseError::new(format!("invalid sequence `{}`", s))
+                    })?;
+                    let max = max.parse().map_err(|_| {
+                        SchemaParseError::new(format!("invalid sequence length in `{}`", s))
+                    })?;
+                    Layout::Seq(Box::new(element.parse()?), Some(max))
+                } else if let Some(element) = s.strip_prefix("sequence of ") {
+                    Layout::Seq(Box::new(element.parse()?), None)
+                } else if let Some((kind, name)) = s
+                    .strip_suffix(')')
+                    .and_then(|s| s.split_once(" encoding an enumeration (see "))
+                {
+                    Layout::Enum(kind.parse()?, name.to_string())
+                } else if let Ok(kind) = s.parse() {
+                    Layout::Int(kind)
+                } else {
+                    Layout::Other(s.to_string())
+                }
+            }
+        };
+        Ok(layout)
+    }
+}
+
+/// Single row of a binary description table.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldDescr {
+    Named {
+        name: String,
+        kind: SizeKind,
+        layout: Layout,
+    },
+    Anonymous {
+        kind: SizeKind,
+        layout: Layout,
+    },
+    /// Size prefix of the field(s) that follow.
+    DynamicSize {
+        name: Option<String>,
+        size: IntegerKind,
+    },
+    /// Presence flag of an optional field.
+    OptionalPresence(String),
+}
+
+impl FieldDescr {
+    fn new(name: Option<&str>, kind: SizeKind, layout: Layout) -> Self {
+        match name {
+            Some(name) => FieldDescr::Named {
+                name: name.to_string(),
+                kind,
+                layout,
+            },
+            None => FieldDescr::Anonymous { kind, layout },
+        }
+    }
+}
+
+/// A case of a tagged union.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub tag: u16,
+    pub name: String,
+    pub fields: Vec<FieldDescr>,
+}
+
+/// Description of a toplevel encoding or a named definition.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Toplevel {
+    Obj(Vec<FieldDescr>),
+    Cases {
+        kind: SizeKind,
+        tag_size: IntegerKind,
+        cases: Vec<Case>,
+    },
+    IntEnum {
+        size: IntegerKind,
+        cases: Vec<(u16, String)>,
+    },
+}
+
+/// Named auxiliary definition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    pub description: Option<String>,
+    pub toplevel: Toplevel,
+}
+
+/// Binary schema of an encoding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinarySchema {
+    pub toplevel: Toplevel,
+    pub definitions: Vec<Definition>,
+}
+
+impl BinarySchema {
+    /// Builds description of the `encoding`.
+    pub fn from_encoding(encoding: &Encoding) -> Self {
+        let mut describer = Describer::default();
+        let toplevel = describer.toplevel(encoding);
+        BinarySchema {
+            toplevel,
+            definitions: describer.definitions,
+        }
+    }
+
+    /// Returns the definition named `name`.
+    pub fn definition(&self, name: &str) -> Option<&Definition> {
+        self.definitions.iter().find(|def| def.name == name)
+    }
+
+    /// Compares this schema with the `other` one.
+    ///
+    /// Names of definitions are not compared, references are followed in parallel instead.
+    /// Objects are inlined into the fields referencing them, so an object with a single field
+    /// is considered the same as that field.
+    pub fn diff(&self, other: &BinarySchema) -> Vec<SchemaDifference> {
+        let mut differ = Differ {
+            left: self,
+            right: other,
+            visited: HashSet::new(),
+            differences: Vec::new(),
+        };
+        differ.toplevel("", &self.toplevel, &other.toplevel);
+        differ.differences
+    }
+}
+
+#[derive(Default)]
+struct Describer {
+    definitions: Vec<Definition>,
+    anonymous: usize,
+}
+
+impl Describer {
+    fn toplevel(&mut self, encoding: &Encoding) -> Toplevel {
+        match encoding {
+            Encoding::Tags(size, tags) => self.cases(*size, tags),
+            Encoding::Option(inner) => Toplevel::Cases {
+                kind: classify(encoding),
+                tag_size: IntegerKind::Uint8,
+                cases: vec![
+                    Case {
+                        tag: 0,
+                        name: "None".to_string(),
+                        fields: Vec::new(),
+                    },
+                    Case {
+                        tag: 1,
+                        name: "Some".to_string(),
+                        fields: self.fields(inner),
+                    },
+                ],
+            },
+            Encoding::Bounded(_, encoding) => self.toplevel(encoding),
+            _ => Toplevel::Obj(self.fields(encoding)),
+        }
+    }
+
+    fn cases(&mut self, size: usize, tags: &TagMap) -> Toplevel {
+        let tag_size = IntegerKind::tag(size);
+        let mut tags = tags.tags().collect::<Vec<_>>();
+        tags.sort_by_key(|tag| tag.get_id());
+        if tags
+            .iter()
+            .all(|tag| matches!(tag.get_encoding(), Encoding::Unit))
+        {
+            return Toplevel::IntEnum {
+                size: tag_size,
+                cases: tags
+                    .iter()
+                    .map(|tag| (tag.get_id(), tag.get_variant().clone()))
+                    .collect(),
+            };
+        }
+        let kind = tags
+            .iter()
+            .map(|tag| classify(tag.get_encoding()))
+            .reduce(|a, b| match (a, b) {
+                (SizeKind::Fixed(a), SizeKind::Fixed(b)) if a == b => SizeKind::Fixed(a),
+                (SizeKind::Variable, _) | (_, SizeKind::Variable) => SizeKind::Variable,
+                _ => SizeKind::Dynamic,
+            })
+            .map(|kind| match kind {
+                SizeKind::Fixed(case_size) => SizeKind::Fixed(size + case_size),
+                kind => kind,
+            })
+            .unwrap_or(SizeKind::Fixed(size));
+        let cases = tags
+            .iter()
+            .map(|tag| Case {
+                tag: tag.get_id(),
+                name: tag.get_variant().clone(),
+                fields: self.fields(tag.get_encoding()),
+            })
+            .collect();
+        Toplevel::Cases {
+            kind,
+            tag_size,
+            cases,
+        }
+    }
+
+    /// Describes fields of the `encoding` when it is used as an object or its part.
+    fn fields(&mut self, encoding: &Encoding) -> Vec<FieldDescr> {
+        match encoding {
+            Encoding::Unit => Vec::new(),
+            Encoding::Obj(_, fields) => {
+                let mut result = Vec::new();
+                for field in fields {
+                    result.extend(self.field(Some(field.get_name()), field.get_encoding()));
+                }
+                result
+            }
+            Encoding::Tup(encodings) => {
+                let mut result = Vec::new();
+                for encoding in encodings {
+                    result.extend(self.field(None, encoding));
+                }
+                result
+            }
+            Encoding::Dynamic(encoding) | Encoding::BoundedDynamic(_, encoding) => {
+                let mut result = vec![FieldDescr::DynamicSize {
+                    name: None,
+                    size: IntegerKind::Uint30,
+                }];
+                result.extend(self.fields(encoding));
+                result
+            }
+            Encoding::ShortDynamic(encoding) => {
+                let mut result = vec![FieldDescr::DynamicSize {
+                    name: None,
+                    size: IntegerKind::Uint8,
+                }];
+                result.extend(self.fields(encoding));
+                result
+            }
+            Encoding::Bounded(_, encoding) | Encoding::Greedy(encoding) => self.fields(encoding),
+            _ => self.field(None, encoding),
+        }
+    }
+
+    /// Describes the `encoding` used as a (possibly anonymous) field.
+    fn field(&mut self, name: Option<&str>, encoding: &Encoding) -> Vec<FieldDescr> {
+        match encoding {
+            Encoding::Unit => Vec::new(),
+            Encoding::OptionalField(encoding) => {
+                let mut result = vec![FieldDescr::OptionalPresence(
+                    name.unwrap_or_default().to_string(),
+                )];
+                result.extend(self.field(name, encoding));
+                result
+            }
+            Encoding::String | Encoding::BoundedString(_) => vec![
+                FieldDescr::DynamicSize {
+                    name: None,
+                    size: IntegerKind::Uint30,
+                },
+                FieldDescr::new(name, SizeKind::Variable, Layout::Bytes),
+            ],
+            Encoding::Dynamic(encoding) | Encoding::BoundedDynamic(_, encoding) => {
+                self.dynamic_field(name, IntegerKind::Uint30, encoding)
+            }
+            Encoding::ShortDynamic(encoding) => {
+                self.dynamic_field(name, IntegerKind::Uint8, encoding)
+            }
+            Encoding::Bounded(_, encoding) | Encoding::Greedy(encoding) => {
+                self.field(name, encoding)
+            }
+            _ => vec![FieldDescr::new(
+                name,
+                classify(encoding),
+                self.layout(encoding),
+            )],
+        }
+    }
+
+    fn dynamic_field(
+        &mut self,
+        name: Option<&str>,
+        size: IntegerKind,
+        encoding: &Encoding,
+    ) -> Vec<FieldDescr> {
+        let mut result = vec![FieldDescr::DynamicSize {
+            name: name.map(str::to_string),
+            size,
+        }];
+        result.extend(self.field(name, encoding));
+        result
+    }
+
+    /// Describes contents of a field with the `encoding`.
+    fn layout(&mut self, encoding: &Encoding) -> Layout {
+        match encoding {
+            Encoding::Unit => Layout::ZeroWidth,
+            Encoding::Int8 => Layout::Int(IntegerKind::Int8),
+            Encoding::Uint8 | Encoding::Enum => Layout::Int(IntegerKind::Uint8),
+            Encoding::Int16 => Layout::Int(IntegerKind::Int16),
+            Encoding::Uint16 => Layout::Int(IntegerKind::Uint16),
+            Encoding::Int31 | Encoding::RangedInt => Layout::Int(IntegerKind::Int31),
+            Encoding::Int32 => Layout::Int(IntegerKind::Int32),
+            Encoding::Uint32 => Layout::Int(IntegerKind::Uint32),
+            Encoding::Int64 | Encoding::Timestamp => Layout::Int(IntegerKind::Int64),
+            Encoding::Float | Encoding::RangedFloat => Layout::Float,
+            Encoding::Bool => Layout::Bool,
+            Encoding::Z => Layout::Ref(self.zarith("Z.t", Z_DESCRIPTION)),
+            Encoding::Mutez => Layout::Ref(self.zarith("N.t", N_DESCRIPTION)),
+            Encoding::Bytes | Encoding::Hash(_) => Layout::Bytes,
+            Encoding::List(encoding) if matches!(**encoding, Encoding::Uint8) => Layout::Bytes,
+            Encoding::BoundedList(_, encoding) if matches!(**encoding, Encoding::Uint8) => {
+                Layout::Bytes
+            }
+            Encoding::List(encoding) => Layout::Seq(Box::new(self.layout(encoding)), None),
+            Encoding::BoundedList(max, encoding) => {
+                Layout::Seq(Box::new(self.layout(encoding)), Some(*max))
+            }
+            Encoding::Sized(_, encoding)
+            | Encoding::Bounded(_, encoding)
+            | Encoding::Greedy(encoding) => self.layout(encoding),
+            Encoding::Tags(size, tags) => {
+                let toplevel = self.cases(*size, tags);
+                match toplevel {
+                    Toplevel::IntEnum { size, .. } => {
+                        Layout::Enum(size, self.define(None, None, toplevel))
+                    }
+                    _ => Layout::Ref(self.define(None, None, toplevel)),
+                }
+            }
+            Encoding::Obj(name, _) => {
+                let toplevel = self.toplevel(encoding);
+                Layout::Ref(self.define(Some(*name), None, toplevel))
+            }
+            Encoding::Custom => Layout::Other("custom encoding".to_string()),
+            Encoding::Tup(_)
+            | Encoding::Option(_)
+            | Encoding::OptionalField(_)
+            | Encoding::String
+            | Encoding::BoundedString(_)
+            | Encoding::Dynamic(_)
+            | Encoding::BoundedDynamic(_, _)
+            | Encoding::ShortDynamic(_) => {
+                let toplevel = self.toplevel(encoding);
+                Layout::Ref(self.define(None, None, toplevel))
+            }
+        }
+    }
+
+    fn zarith(&mut self, name: &str, description: &str) -> String {
+        let toplevel = Toplevel::Obj(vec![FieldDescr::new(
+            Some(name),
+            SizeKind::Dynamic,
+            Layout::Bytes,
+        )]);
+        self.define(Some(name), Some(description), toplevel)
+    }
+
+    /// Adds the definition, reusing an existing one with the same name and contents.
+    fn define(
+        &mut self,
+        name: Option<&str>,
+        description: Option<&str>,
+        toplevel: Toplevel,
+    ) -> String {
+        let name = match name {
+            Some(name) => {
+                let mut unique = name.to_string();
+                let mut index = 0;
+                while let Some(def) = self.definitions.iter().find(|def| def.name == unique) {
+                    if def.toplevel == toplevel {
+                        return unique;
+                    }
+                    index += 1;
+                    unique = format!("{}_{}", name, index);
+                }
+                unique
+            }
+            None => {
+                if let Some(def) = self
+                    .definitions
+                    .iter()
+                    .find(|def| def.name.starts_with("X_") && def.toplevel == toplevel)
+                {
+                    return def.name.clone();
+                }
+                let name = format!("X_{}", self.anonymous);
+                self.anonymous += 1;
+                name
+            }
+        };
+        self.definitions.push(Definition {
+            name: name.clone(),
+            description: description.map(str::to_string),
+            toplevel,
+        });
+        name
+    }
+}
+
+/// Classifies the size of the `encoding`.
+fn classify(encoding: &Encoding) -> SizeKind {
+    match encoding {
+        Encoding::Unit => SizeKind::Fixed(0),
+        Encoding::Int8 | Encoding::Uint8 | Encoding::Bool | Encoding::Enum => SizeKind::Fixed(1),
+        Encoding::Int16 | Encoding::Uint16 => SizeKind::Fixed(2),
+        Encoding::Int31
+        | Encoding::Int32
+        | Encoding::Uint32
+        | Encoding::RangedInt => SizeKind::Fixed(4),
+        Encoding::Int64 | Encoding::Timestamp | Encoding::Float | Encoding::RangedFloat => {
+            SizeKind::Fixed(8)
+        }
+        Encoding::Hash(hash_type) => SizeKind::Fixed(hash_type.size()),
+        Encoding::Sized(size, _) => SizeKind::Fixed(*size),
+        Encoding::Z
+        | Encoding::Mutez
+        | Encoding::String
+        | Encoding::BoundedString(_)
+        | Encoding::Dynamic(_)
+        | Encoding::BoundedDynamic(_, _)
+        | Encoding::ShortDynamic(_)
+        | Encoding::OptionalField(_)
+        | Encoding::Custom => SizeKind::Dynamic,
+        Encoding::Bytes | Encoding::List(_) | Encoding::BoundedList(_, _) | Encoding::Greedy(_) => {
+            SizeKind::Variable
+        }
+        Encoding::Option(encoding) => match classify(encoding) {
+            SizeKind::Fixed(0) => SizeKind::Fixed(1),
+            _ => SizeKind::Dynamic,
+        },
+        Encoding::Bounded(_, encoding) => classify(encoding),
+        Encoding::Obj(_, fields) => {
+            classify_all(fields.iter().map(|field| classify(field.get_encoding())))
+        }
+        Encoding::Tup(encodings) => classify_all(encodings.iter().map(classify)),
+        Encoding::Tags(size, tags) => {
+            let mut kinds = tags.tags().map(|tag| classify(tag.get_encoding()));
+            let first = kinds.next().unwrap_or(SizeKind::Fixed(0));
+            let kind = kinds.fold(first, |a, b| match (a, b) {
+                (SizeKind::Fixed(a), SizeKind::Fixed(b)) if a == b => SizeKind::Fixed(a),
+                (SizeKind::Variable, _) | (_, SizeKind::Variable) => SizeKind::Variable,
+                _ => SizeKind::Dynamic,
+            });
+            match kind {
+                SizeKind::Fixed(case_size) => SizeKind::Fixed(size + case_size),
+                kind => kind,
+            }
+        }
+    }
+}
+
+fn classify_all(kinds: impl Iterator<Item = SizeKind>) -> SizeKind {
+    kinds.fold(SizeKind::Fixed(0), |a, b| match (a, b) {
+        (SizeKind::Fixed(a), SizeKind::Fixed(b)) => SizeKind::Fixed(a + b),
+        (SizeKind::Variable, _) | (_, SizeKind::Variable) => SizeKind::Variable,
+        _ => SizeKind::Dynamic,
+    })
+}
+
+impl fmt::Display for BinarySchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_toplevel(f, &self.toplevel)?;
+        for def in &self.definitions {
+            let title = match &def.toplevel {
+                Toplevel::Obj(_) => def.name.clone(),
+                Toplevel::Cases { kind, tag_size, .. } => {
+                    format!("{} ({}, {}-bit tag)", def.name, kind, tag_size.size() * 8)
+                }
+                Toplevel::IntEnum { size, .. } => {
+                    format!("{} (Enumeration: {}):", def.name, size)
+                }
+            };
+            write_title(f, &title, '*')?;
+            if let Some(description) = &def.description {
+                writeln!(f, "{}", description)?;
+                writeln!(f)?;
+            }
+            write_toplevel(f, &def.toplevel)?;
+        }
+        Ok(())
+    }
+}
+
+const FIELDS_HEADER: [&str; 3] = ["Name", "Size", "Contents"];
+const ENUM_HEADER: [&str; 2] = ["Case number", "Encoded string"];
+
+fn write_toplevel(f: &mut fmt::Formatter<'_>, toplevel: &Toplevel) -> fmt::Result {
+    match toplevel {
+        Toplevel::Obj(fields) => write_table(f, &FIELDS_HEADER, &field_rows(None, fields)),
+        Toplevel::Cases {
+            tag_size, cases, ..
+        } => cases.iter().try_for_each(|case| {
+            write_title(f, &format!("{} (tag {})", case.name, case.tag), '=')?;
+            write_table(f, &FIELDS_HEADER, &field_rows(Some(*tag_size), &case.fields))
+        }),
+        Toplevel::IntEnum { cases, .. } => {
+            let rows = cases
+                .iter()
+                .map(|(tag, name)| vec![tag.to_string(), name.clone()])
+                .collect::<Vec<_>>();
+            write_table(f, &ENUM_HEADER, &rows)
+        }
+    }
+}
+
+fn field_rows(tag: Option<IntegerKind>, fields: &[FieldDescr]) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    if let Some(tag) = tag {
+        rows.push(vec![
+            "Tag".to_string(),
+            SizeKind::Fixed(tag.size()).to_string(),
+            tag.to_string(),
+        ]);
+    }
+    let mut anonymous = 0;
+    for field in fields {
+        let row = match field {
+            FieldDescr::Named { name, kind, layout } => {
+                vec![name.clone(), kind.to_string(), layout.to_string()]
+            }
+            FieldDescr::Anonymous { kind, layout } => {
+                anonymous += 1;
+                vec![
+                    format!("Unnamed field {}", anonymous - 1),
+                    kind.to_string(),
+                    layout.to_string(),
+                ]
+            }
+            FieldDescr::DynamicSize { name, size } => vec![
+                match name {
+                    Some(name) => format!("# bytes in field \"{}\"", name),
+                    None => "# bytes in next field".to_string(),
+                },
+                SizeKind::Fixed(size.size()).to_string(),
+                size.to_string(),
+            ],
+            FieldDescr::OptionalPresence(name) => vec![
+                format!("? presence of field \"{}\"", name),
+                SizeKind::Fixed(1).to_string(),
+                Layout::Bool.to_string(),
+            ],
+        };
+        rows.push(row);
+    }
+    rows
+}
+
+fn write_title(f: &mut fmt::Formatter<'_>, title: &str, underline: char) -> fmt::Result {
+    writeln!(f, "{}", title)?;
+    writeln!(f, "{}", underline.to_string().repeat(title.chars().count()))?;
+    writeln!(f)
+}
+
+fn write_table(f: &mut fmt::Formatter<'_>, header: &[&str], rows: &[Vec<String>]) -> fmt::Result {
+    let widths = header
+        .iter()
+        .enumerate()
+        .map(|(i, header)| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .fold(header.chars().count(), usize::max)
+        })
+        .collect::<Vec<_>>();
+    let border = |c: char| {
+        widths.iter().fold(String::from("+"), |mut line, width| {
+            line.push_str(&c.to_string().repeat(width + 2));
+            line.push('+');
+            line
+        })
+    };
+
+    writeln!(f, "{}", border('-'))?;
+    write_row(f, &widths, header.iter().copied())?;
+    writeln!(f, "{}", border('='))?;
+    for row in rows {
+        write_row(f, &widths, row.iter().map(String::as_str))?;
+        writeln!(f, "{}", border('-'))?;
+    }
+    writeln!(f)?;
+    writeln!(f)
+}
+
+fn write_row<'a>(
+    f: &mut fmt::Formatter<'_>,
+    widths: &[usize],
+    cells: impl Iterator<Item = &'a str>,
+) -> fmt::Result {
+    write!(f, "|")?;
+    for (cell, width) in cells.zip(widths) {
+        write!(f, " {:width$} |", cell, width = *width)?;
+    }
+    writeln!(f)
+}
+
+/// Error parsing textual binary schema description.
+#[derive(Debug, Error)]
+#[error("Error parsing binary schema: {0}")]
+pub struct SchemaParseError(String);
+
+impl SchemaParseError {
+    fn new(message: String) -> Self {
+        Self(message)
+    }
+}
+
+/// Building blocks of the textual description.
+enum Block<'a> {
+    Title(&'a str, char),
+    Table(Vec<Vec<String>>),
+    Text(&'a str),
+}
+
+fn blocks(s: &str) -> Vec<Block> {
+    let lines = s.lines().map(str::trim_end).collect::<Vec<_>>();
+    let is_underline = |line: &str| match line.chars().next() {
+        Some(c @ ('*' | '=' | '-')) => line.chars().all(|d| d == c),
+        _ => false,
+    };
+    let mut blocks = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if line.is_empty() {
+            i += 1;
+        } else if line.starts_with('+') {
+            let mut rows = Vec::new();
+            while i < lines.len() && (lines[i].starts_with('+') || lines[i].starts_with('|')) {
+                if lines[i].starts_with('|') {
+                    rows.push(
+                        lines[i]
+                            .trim_matches('|')
+                            .split('|')
+                            .map(|cell| cell.trim().to_string())
+                            .collect(),
+                    );
+                }
+                i += 1;
+            }
+            // skip the header
+            blocks.push(Block::Table(rows.into_iter().skip(1).collect()));
+        } else if i + 1 < lines.len() && is_underline(lines[i + 1]) {
+            blocks.push(Block::Title(line, lines[i + 1].chars().next().unwrap_or('*')));
+            i += 2;
+        } else {
+            blocks.push(Block::Text(line));
+            i += 1;
+        }
+    }
+    blocks
+}
+
+fn parse_fields(rows: Vec<Vec<String>>) -> Result<Vec<FieldDescr>, SchemaParseError> {
+    rows.into_iter()
+        .map(|row| {
+            let (name, size, contents) = match row.as_slice() {
+                [name, size, contents] => (name.as_str(), size.as_str(), contents.as_str()),
+                _ => return Err(SchemaParseError::new(format!("invalid row {:?}", row))),
+            };
+            let field = if let Some(rest) = name.strip_prefix("# bytes in ") {
+                let name = rest
+                    .strip_prefix("field \"")
+                    .and_then(|name| name.strip_suffix('"'))
+                    .map(str::to_string);
+                FieldDescr::DynamicSize {
+                    name,
+                    size: contents.parse()?,
+                }
+            } else if let Some(name) = name
+                .strip_prefix("? presence of field \"")
+                .and_then(|name| name.strip_suffix('"'))
+            {
+                FieldDescr::OptionalPresence(name.to_string())
+            } else if name.starts_with("Unnamed field ") {
+                FieldDescr::Anonymous {
+                    kind: size.parse()?,
+                    layout: contents.parse()?,
+                }
+            } else {
+                FieldDescr::Named {
+                    name: name.to_string(),
+                    kind: size.parse()?,
+                    layout: contents.parse()?,
+                }
+            };
+            Ok(field)
+        })
+        .collect()
+}
+
+fn parse_enum_cases(rows: Vec<Vec<String>>) -> Result<Vec<(u16, String)>, SchemaParseError> {
+    rows.into_iter()
+        .map(|row| match row.as_slice() {
+            [tag, name] => tag
+                .parse()
+                .map(|tag| (tag, name.clone()))
+                .map_err(|_| SchemaParseError::new(format!("invalid case number `{}`", tag))),
+            _ => Err(SchemaParseError::new(format!("invalid row {:?}", row))),
+        })
+        .collect()
+}
+
+/// Parses cases introduced by `Name (tag N)` titles.
+fn parse_cases<'a>(
+    blocks: &mut std::iter::Peekable<impl Iterator<Item = Block<'a>>>,
+) -> Result<(IntegerKind, Vec<Case>), SchemaParseError> {
+    let mut tag_size = IntegerKind::Uint8;
+    let mut cases = Vec::new();
+    while let Some(&Block::Title(title, '=')) = blocks.peek() {
+        let (name, tag) = title
+            .strip_suffix(')')
+            .and_then(|title| title.rsplit_once(" (tag "))
+            .ok_or_else(|| SchemaParseError::new(format!("invalid case title `{}`", title)))?;
+        let tag = tag
+            .parse()
+            .map_err(|_| SchemaParseError::new(format!("invalid tag in `{}`", title)))?;
+        let name = name.to_string();
+        blocks.next();
+        let mut rows = match blocks.next() {
+            Some(Block::Table(rows)) => rows,
+            _ => return Err(SchemaParseError::new(format!("no table for case `{}`", name))),
+        };
+        if rows.first().map_or(false, |row| row[0] == "Tag") {
+            let row = rows.remove(0);
+            tag_size = match row.get(1).map(|size| size.parse()) {
+                Some(Ok(SizeKind::Fixed(2))) => IntegerKind::Uint16,
+                _ => IntegerKind::Uint8,
+            };
+        }
+        cases.push(Case {
+            tag,
+            name,
+            fields: parse_fields(rows)?,
+        });
+    }
+    Ok((tag_size, cases))
+}
+
+impl FromStr for BinarySchema {
+    type Err = SchemaParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut blocks = blocks(s).into_iter().peekable();
+
+        // skip document headings and text preceding the toplevel description
+        while matches!(
+            blocks.peek(),
+            Some(Block::Text(_)) | Some(Block::Title(_, '-'))
+        ) {
+            blocks.next();
+        }
+        let toplevel = if matches!(blocks.peek(), Some(Block::Title(_, '='))) {
+            let (tag_size, cases) = parse_cases(&mut blocks)?;
+            Toplevel::Cases {
+                kind: SizeKind::Dynamic,
+                tag_size,
+                cases,
+            }
+        } else {
+            match blocks.next() {
+                Some(Block::Table(rows)) => Toplevel::Obj(parse_fields(rows)?),
+                _ => return Err(SchemaParseError::new("no toplevel description".to_string())),
+            }
+        };
+
+        let mut definitions = Vec::new();
+        while let Some(block) = blocks.next() {
+            let title = match block {
+                Block::Title(title, '*') => title,
+                Block::Title(title, _) | Block::Text(title) => {
+                    return Err(SchemaParseError::new(format!("unexpected `{}`", title)))
+                }
+                Block::Table(_) => {
+                    return Err(SchemaParseError::new("unexpected table".to_string()))
+                }
+            };
+            let mut description = Vec::new();
+            while let Some(&Block::Text(text)) = blocks.peek() {
+                description.push(text);
+                blocks.next();
+            }
+            let description = if description.is_empty() {
+                None
+            } else {
+                Some(description.join("\n"))
+            };
+
+            let definition = if let Some((name, size)) = title
+                .strip_suffix("):")
+                .and_then(|title| title.split_once(" (Enumeration: "))
+            {
+                let cases = match blocks.next() {
+                    Some(Block::Table(rows)) => parse_enum_cases(rows)?,
+                    _ => return Err(SchemaParseError::new(format!("no table for `{}`", name))),
+                };
+                Definition {
+                    name: name.to_string(),
+                    description,
+                    toplevel: Toplevel::IntEnum {
+                        size: size.parse()?,
+                        cases,
+                    },
+                }
+            } else if let Some((name, kind)) = title
+                .strip_suffix("-bit tag)")
+                .and_then(|title| title.rsplit_once(" ("))
+            {
+                let (kind, _) = kind.rsplit_once(", ").ok_or_else(|| {
+                    SchemaParseError::new(format!("invalid union title `{}`", title))
+                })?;
+                let (tag_size, cases) = parse_cases(&mut blocks)?;
+                Definition {
+                    name: name.to_string(),
+                    description,
+                    toplevel: Toplevel::Cases {
+                        kind: kind.parse()?,
+                        tag_size,
+                        cases,
+                    },
+                }
+            } else {
+                let fields = match blocks.next() {
+                    Some(Block::Table(rows)) => parse_fields(rows)?,
+                    _ => return Err(SchemaParseError::new(format!("no table for `{}`", title))),
+                };
+                Definition {
+                    name: title.to_string(),
+                    description,
+                    toplevel: Toplevel::Obj(fields),
+                }
+            };
+            definitions.push(definition);
+        }
+
+        Ok(BinarySchema {
+            toplevel,
+            definitions,
+        })
+    }
+}
+
+/// Kind of a difference between two schemas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DifferenceKind {
+    /// Fields or cases are named differently, binary representation is the same.
+    Name,
+    /// Binary representation is different.
+    Structure,
+}
+
+/// Difference between two schemas found by [BinarySchema::diff].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaDifference {
+    pub kind: DifferenceKind,
+    /// Dot-separated path to the differing element.
+    pub path: String,
+    pub left: String,
+    pub right: String,
+}
+
+impl fmt::Display for SchemaDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            DifferenceKind::Name => "name",
+            DifferenceKind::Structure => "structure",
+        };
+        write!(
+            f,
+            "{}: {} differs: `{}` vs `{}`",
+            if self.path.is_empty() {
+                "<toplevel>"
+            } else {
+                self.path.as_str()
+            },
+            kind,
+            self.left,
+            self.right
+        )
+    }
+}
+
+/// Field row with objects inlined, used for comparison.
+#[derive(Debug, Clone, PartialEq)]
+enum Row {
+    Size(IntegerKind),
+    Presence(String),
+    Field(Option<String>, SizeKind, Layout),
+}
+
+impl fmt::Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Row::Size(size) => write!(f, "# bytes ({})", size),
+            Row::Presence(name) => write!(f, "? presence of field \"{}\"", name),
+            Row::Field(Some(name), kind, layout) => write!(f, "{} | {} | {}", name, kind, layout),
+            Row::Field(None, kind, layout) => write!(f, "{} | {}", kind, layout),
+        }
+    }
+}
+
+struct Differ<'a> {
+    left: &'a BinarySchema,
+    right: &'a BinarySchema,
+    visited: HashSet<(String, String)>,
+    differences: Vec<SchemaDifference>,
+}
+
+impl<'a> Differ<'a> {
+    fn report(&mut self, kind: DifferenceKind, path: &str, left: String, right: String) {
+        self.differences.push(SchemaDifference {
+            kind,
+            path: path.to_string(),
+            left,
+            right,
+        });
+    }
+
+    fn toplevel(&mut self, path: &str, left: &Toplevel, right: &Toplevel) {
+        let (left_schema, right_schema) = (self.left, self.right);
+        match (left, right) {
+            (Toplevel::Obj(left_fields), Toplevel::Obj(right_fields)) => {
+                let left_rows = flatten(left_schema, left_fields, &mut Vec::new());
+                let right_rows = flatten(right_schema, right_fields, &mut Vec::new());
+                self.rows(path, &left_rows, &right_rows);
+            }
+            (
+                Toplevel::Cases {
+                    tag_size: left_tag,
+                    cases: left_cases,
+                    ..
+                },
+                Toplevel::Cases {
+                    tag_size: right_tag,
+                    cases: right_cases,
+                    ..
+                },
+            ) => {
+                if left_tag.size() != right_tag.size() {
+                    self.report(
+                        DifferenceKind::Structure,
+                        path,
+                        format!("{}-bit tag", left_tag.size() * 8),
+                        format!("{}-bit tag", right_tag.size() * 8),
+                    );
+                }
+                for left_case in left_cases {
+                    let case_path = join(path, &left_case.name);
+                    match right_cases.iter().find(|case| case.tag == left_case.tag) {
+                        Some(right_case) => {
+                            if left_case.name != right_case.name {
+                                self.report(
+                                    DifferenceKind::Name,
+                                    &case_path,
+                                    left_case.name.clone(),
+                                    right_case.name.clone(),
+                                );
+                            }
+                            let left_rows =
+                                flatten(left_schema, &left_case.fields, &mut Vec::new());
+                            let right_rows =
+                                flatten(right_schema, &right_case.fields, &mut Vec::new());
+                            self.rows(&case_path, &left_rows, &right_rows);
+                        }
+                        None => self.report(
+                            DifferenceKind::Structure,
+                            &case_path,
+                            format!("tag {}", left_case.tag),
+                            "no such tag".to_string(),
+                        ),
+                    }
+                }
+                for right_case in right_cases {
+                    if !left_cases.iter().any(|case| case.tag == right_case.tag) {
+                        self.report(
+                            DifferenceKind::Structure,
+                            &join(path, &right_case.name),
+                            "no such tag".to_string(),
+                            format!("tag {}", right_case.tag),
+                        );
+                    }
+                }
+            }
+            (
+                Toplevel::IntEnum {
+                    size: left_size,
+                    cases: left_cases,
+                },
+                Toplevel::IntEnum {
+                    size: right_size,
+                    cases: right_cases,
+                },
+            ) => {
+                if left_size.size() != right_size.size() {
+                    self.report(
+                        DifferenceKind::Structure,
+                        path,
+                        left_size.to_string(),
+                        right_size.to_string(),
+                    );
+                }
+                let left_tags = left_cases.iter().map(|(tag, _)| *tag).collect::<Vec<_>>();
+                let right_tags = right_cases.iter().map(|(tag, _)| *tag).collect::<Vec<_>>();
+                if left_tags != right_tags {
+                    self.report(
+                        DifferenceKind::Structure,
+                        path,
+                        format!("cases {:?}", left_tags),
+                        format!("cases {:?}", right_tags),
+                    );
+                }
+            }
+            _ => self.report(
+                DifferenceKind::Structure,
+                path,
+                toplevel_kind(left).to_string(),
+                toplevel_kind(right).to_string(),
+            ),
+        }
+    }
+
+    fn rows(&mut self, path: &str, left: &[Row], right: &[Row]) {
+        for (left_row, right_row) in left.iter().zip(right) {
+            match (left_row, right_row) {
+                (Row::Size(left_size), Row::Size(right_size)) if left_size == right_size => (),
+                (Row::Presence(left_name), Row::Presence(right_name)) => {
+                    if left_name != right_name {
+                        self.report(
+                            DifferenceKind::Name,
+                            &join(path, left_name),
+                            left_name.clone(),
+                            right_name.clone(),
+                        );
+                    }
+                }
+                (
+                    Row::Field(left_name, left_kind, left_layout),
+                    Row::Field(right_name, right_kind, right_layout),
+                ) => {
+                    let field_path = join(
+                        path,
+                        left_name
+                            .as_deref()
+                            .or(right_name.as_deref())
+                            .unwrap_or("<unnamed>"),
+                    );
+                    if let (Some(left_name), Some(right_name)) = (left_name, right_name) {
+                        if left_name != right_name {
+                            self.report(
+                                DifferenceKind::Name,
+                                &field_path,
+                                left_name.clone(),
+                                right_name.clone(),
+                            );
+                        }
+                    }
+                    if left_kind != right_kind {
+                        self.report(
+                            DifferenceKind::Structure,
+                            &field_path,
+                            left_kind.to_string(),
+                            right_kind.to_string(),
+                        );
+                    }
+                    self.layout(&field_path, left_layout, right_layout);
+                }
+                _ => self.report(
+                    DifferenceKind::Structure,
+                    path,
+                    left_row.to_string(),
+                    right_row.to_string(),
+                ),
+            }
+        }
+        for row in left.iter().skip(right.len()) {
+            self.report(
+                DifferenceKind::Structure,
+                path,
+                row.to_string(),
+                "no such field".to_string(),
+            );
+        }
+        for row in right.iter().skip(left.len()) {
+            self.report(
+                DifferenceKind::Structure,
+                path,
+                "no such field".to_string(),
+                row.to_string(),
+            );
+        }
+    }
+
+    fn layout(&mut self, path: &str, left: &Layout, right: &Layout) {
+        let (left_schema, right_schema) = (self.left, self.right);
+        let left = resolve(left_schema, normalize(left));
+        let right = resolve(right_schema, normalize(right));
+        match (&left, &right) {
+            (Layout::Ref(left_name), Layout::Ref(right_name)) => {
+                if !self
+                    .visited
+                    .insert((left_name.to_string(), right_name.to_string()))
+                {
+                    return;
+                }
+                match (
+                    left_schema.definition(left_name),
+                    right_schema.definition(right_name),
+                ) {
+                    (Some(left_def), Some(right_def)) => {
+                        self.toplevel(path, &left_def.toplevel, &right_def.toplevel)
+                    }
+                    (None, _) => self.report(
+                        DifferenceKind::Structure,
+                        path,
+                        format!("undefined ${}", left_name),
+                        right.to_string(),
+                    ),
+                    (_, None) => self.report(
+                        DifferenceKind::Structure,
+                        path,
+                        left.to_string(),
+                        format!("undefined ${}", right_name),
+                    ),
+                }
+            }
+            (Layout::Seq(left_element, _), Layout::Seq(right_element, _)) => {
+                self.layout(&format!("{}[]", path), left_element, right_element)
+            }
+            (Layout::Enum(left_size, _), Layout::Int(right_size))
+            | (Layout::Int(left_size), Layout::Enum(right_size, _))
+            | (Layout::Enum(left_size, _), Layout::Enum(right_size, _))
+                if left_size.size() == right_size.size() => {}
+            _ if left == right => (),
+            _ => self.report(
+                DifferenceKind::Structure,
+                path,
+                left.to_string(),
+                right.to_string(),
+            ),
+        }
+    }
+}
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+fn toplevel_kind(toplevel: &Toplevel) -> &'static str {
+    match toplevel {
+        Toplevel::Obj(_) => "object",
+        Toplevel::Cases { .. } => "tagged union",
+        Toplevel::IntEnum { .. } => "enumeration",
+    }
+}
+
+/// Sequence of bytes is the same as bytes.
+fn normalize(layout: &Layout) -> Layout {
+    match layout {
+        Layout::Seq(element, _)
+            if matches!(
+                **element,
+                Layout::Int(IntegerKind::Uint8) | Layout::Int(IntegerKind::Int8)
+            ) =>
+        {
+            Layout::Bytes
+        }
+        layout => layout.clone(),
+    }
+}
+
+/// Replaces reference to an object consisting of a single field with that field's layout.
+fn resolve(schema: &BinarySchema, layout: Layout) -> Layout {
+    let mut layout = layout;
+    let mut seen = Vec::new();
+    while let Layout::Ref(name) = &layout {
+        if seen.contains(name) {
+            break;
+        }
+        seen.push(name.clone());
+        let fields = match schema.definition(name).map(|def| &def.toplevel) {
+            Some(Toplevel::Obj(fields)) => fields,
+            _ => break,
+        };
+        match flatten(schema, fields, &mut Vec::new()).as_slice() {
+            [Row::Field(_, _, inner)] => layout = normalize(inner),
+            _ => break,
+        }
+    }
+    layout
+}
+
+/// Inlines fields of referenced objects and drops zero-width fields.
+fn flatten(schema: &BinarySchema, fields: &[FieldDescr], stack: &mut Vec<String>) -> Vec<Row> {
+    let mut rows = Vec::new();
+    for field in fields {
+        let (name, kind, layout) = match field {
+            FieldDescr::DynamicSize { size, .. } => {
+                rows.push(Row::Size(*size));
+                continue;
+            }
+            FieldDescr::OptionalPresence(name) => {
+                rows.push(Row::Presence(name.clone()));
+                continue;
+            }
+            FieldDescr::Named { name, kind, layout } => (Some(name), kind, layout),
+            FieldDescr::Anonymous { kind, layout } => (None, kind, layout),
+        };
+        match layout {
+            Layout::ZeroWidth => (),
+            Layout::Ref(reference) if !stack.contains(reference) => {
+                match schema.definition(reference).map(|def| &def.toplevel) {
+                    Some(Toplevel::Obj(inner)) => {
+                        stack.push(reference.clone());
+                        rows.extend(flatten(schema, inner, stack));
+                        stack.pop();
+                    }
+                    _ => rows.push(Row::Field(name.cloned(), *kind, layout.clone())),
+                }
+            }
+            _ => rows.push(Row::Field(name.cloned(), *kind, layout.clone())),
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{Field, Tag};
+
+    fn encoding() -> Encoding {
+        Encoding::Obj(
+            "Message",
+            vec![
+                Field::new("port", Encoding::Uint16),
+                Field::new("flag", Encoding::option_field(Encoding::Bool)),
+                Field::new(
+                    "kind",
+                    Encoding::Tags(
+                        1,
+                        TagMap::new(vec![
+                            Tag::new(0, "First", Encoding::Unit),
+                            Tag::new(1, "Second", Encoding::Unit),
+                        ]),
+                    ),
+                ),
+                Field::new(
+                    "names",
+                    Encoding::dynamic(Encoding::list(Encoding::String)),
+                ),
+                Field::new("amount", Encoding::Mutez),
+                Field::new("data", Encoding::list(Encoding::Uint8)),
+            ],
+        )
+    }
+
+    #[test]
+    fn describe_roundtrip() {
+        let schema = BinarySchema::from_encoding(&encoding());
+        let text = schema.to_string();
+        let parsed: BinarySchema = text.parse().expect("cannot parse description");
+        assert_eq!(parsed.to_string(), text);
+        assert!(schema.diff(&parsed).is_empty());
+    }
+
+    #[test]
+    fn describe_obj() {
+        let schema = BinarySchema::from_encoding(&encoding());
+        let text = schema.to_string();
+        assert!(text.lines().any(|line| line.starts_with("| port ")
+            && line.contains("| 2 bytes ")
+            && line.ends_with("| unsigned 16-bit integer |")));
+        assert!(text.contains("? presence of field \"flag\""));
+        assert!(text.contains("# bytes in field \"names\""));
+        assert!(text.contains("sequence of $X_1"));
+        assert!(text.contains("$N.t"));
+        assert!(text.contains("X_0 (Enumeration: unsigned 8-bit integer):"));
+    }
+
+    #[test]
+    fn diff_structure() {
+        let left = BinarySchema::from_encoding(&encoding());
+        let right = BinarySchema::from_encoding(&Encoding::Obj(
+            "Message",
+            vec![
+                Field::new("port", Encoding::Uint32),
+                Field::new("flag", Encoding::option_field(Encoding::Bool)),
+            ],
+        ));
+        let differences = left.diff(&right);
+        assert!(differences
+            .iter()
+            .any(|d| d.kind == DifferenceKind::Structure && d.path == "port"));
+        assert!(differences
+            .iter()
+            .any(|d| d.kind == DifferenceKind::Structure && d.right == "no such field"));
+    }
+
+    #[test]
+    fn diff_inlines_objects() {
+        let inner = Encoding::Obj(
+            "Inner",
+            vec![
+                Field::new("a", Encoding::Int32),
+                Field::new("b", Encoding::Bool),
+            ],
+        );
+        let nested = BinarySchema::from_encoding(&Encoding::Obj(
+            "Outer",
+            vec![Field::new("inner", inner)],
+        ));
+        let flat = BinarySchema::from_encoding(&Encoding::Obj(
+            "Flat",
+            vec![
+                Field::new("a", Encoding::Int32),
+                Field::new("c", Encoding::Bool),
+            ],
+        ));
+        let differences = nested.diff(&flat);
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].kind, DifferenceKind::Name);
+        assert_eq!(differences[0].path, "b");
+    }
+}
@@ -6,6 +6,8 @@
 use crypto::hash::{HashTrait, HashType};
 use std::collections::HashMap;
 
+use crate::describe::BinarySchema;
+
 pub use tezos_encoding_derive::HasEncoding;
 
 #[derive(Debug, Clone)]
@@ -295,6 +297,12 @@ impl Encoding {
     pub fn option_field(encoding: Encoding) -> Encoding {
         Encoding::OptionalField(Box::new(encoding))
     }
+
+    /// Describes binary layout of this encoding in the format of Octez
+    /// `tezos-codec describe <id> binary schema`.
+    pub fn describe_binary(&self) -> BinarySchema {
+        BinarySchema::from_encoding(self)
+    }
 }
 
 /// Indicates that type has its own ser/de schema.
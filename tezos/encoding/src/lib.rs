@@ -11,6 +11,7 @@ pub mod types;
 pub mod binary_reader;
 pub mod binary_writer;
 
+pub mod describe;
 pub mod enc;
 pub mod encoding;
 pub mod nom;
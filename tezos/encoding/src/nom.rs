@@ -228,6 +228,14 @@ pub trait NomReader: Sized {
     fn nom_read(input: &[u8]) -> NomResult<Self>;
 }
 
+/// Traits defining message decoding into a value that references the input buffer.
+///
+/// Derived for types with a lifetime parameter, e.g. `struct OperationRef<'a>`,
+/// whose fields like `&'a [u8]` or [crate::types::HashRef] are not copied from the input.
+pub trait NomReaderBorrowed<'a>: Sized {
+    fn nom_read_borrowed(input: NomInput<'a>) -> NomResult<'a, Self>;
+}
+
 macro_rules! hash_nom_reader {
     ($hash_name:ident) => {
         impl NomReader for crypto::hash::$hash_name {
@@ -292,6 +300,12 @@ pub fn bytes(input: NomInput) -> NomResult<Vec<u8>> {
     map(rest, Vec::from)(input)
 }
 
+/// Reads all available bytes without copying them. Used in conjunction with [sized].
+#[inline(always)]
+pub fn bytes_ref(input: NomInput) -> NomResult<&[u8]> {
+    rest(input)
+}
+
 /// Reads size encoded as 4-bytes big-endian unsigned.
 #[inline(always)]
 pub fn size(input: NomInput) -> NomResult<u32> {
@@ -614,6 +628,16 @@ mod test {
         assert_eq!(res, Ok((&[][..], vec![0, 1, 2, 3])))
     }
 
+    #[test]
+    fn test_bytes_ref() {
+        let input = &[0, 1, 2, 3];
+        let res: NomResult<&[u8]> = bytes_ref(input);
+        assert_eq!(res, Ok((&[][..], &input[..])));
+
+        let res: NomResult<&[u8]> = sized(2, bytes_ref)(input);
+        assert_eq!(res, Ok((&[2, 3][..], &input[..2])));
+    }
+
     #[test]
     fn test_optional_field() {
         let res: NomResult<Option<u8>> = optional_field(u8)(&[0x00, 0x01][..]);
@@ -3,13 +3,16 @@
 
 //! Defines types of the intermediate data format.
 
+use std::marker::PhantomData;
 use std::str::FromStr;
 
 use crate::enc::BinWriter;
 use crate::encoding::Encoding;
 use crate::encoding::HasEncoding;
 use crate::has_encoding;
-use crate::nom::NomReader;
+use crate::nom::{NomReader, NomReaderBorrowed};
+
+use crypto::hash::HashTrait;
 
 use hex::FromHexError;
 use num_bigint::Sign;
@@ -434,6 +437,76 @@ impl<'de> serde::Deserialize<'de> for Bytes {
     }
 }
 
+/// Hash referencing the decoded input, see [crate::nom::NomReaderBorrowed].
+pub struct HashRef<'a, H> {
+    bytes: &'a [u8],
+    _hash: PhantomData<H>,
+}
+
+impl<'a, H: HashTrait> HashRef<'a, H> {
+    /// Returns bytes of the hash.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Copies the hash out of the input buffer.
+    pub fn to_hash(&self) -> H {
+        H::try_from_bytes(self.bytes)
+            .unwrap_or_else(|_| unreachable!("Hash size is checked by the decoder"))
+    }
+}
+
+impl<'a, H> Clone for HashRef<'a, H> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, H> Copy for HashRef<'a, H> {}
+
+impl<'a, H> PartialEq for HashRef<'a, H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes == other.bytes
+    }
+}
+
+impl<'a, H> Eq for HashRef<'a, H> {}
+
+impl<'a, H: HashTrait> std::fmt::Debug for HashRef<'a, H> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("HashRef")
+            .field(&self.to_hash().to_b58check())
+            .finish()
+    }
+}
+
+impl<'a, H: HashTrait> HasEncoding for HashRef<'a, H> {
+    fn encoding() -> Encoding {
+        Encoding::Hash(H::hash_type())
+    }
+}
+
+impl<'a, H: HashTrait> NomReaderBorrowed<'a> for HashRef<'a, H> {
+    fn nom_read_borrowed(input: &'a [u8]) -> crate::nom::NomResult<'a, Self> {
+        use crate::nom::sized;
+        let (input, bytes) = sized(H::hash_size(), crate::nom::bytes_ref)(input)?;
+        Ok((
+            input,
+            Self {
+                bytes,
+                _hash: PhantomData,
+            },
+        ))
+    }
+}
+
+impl<'a, H> BinWriter for HashRef<'a, H> {
+    fn bin_write(&self, output: &mut Vec<u8>) -> crate::enc::BinResult {
+        crate::enc::put_bytes(self.bytes, output);
+        Ok(())
+    }
+}
+
 /// Represents `true` value in binary format.
 pub const BYTE_VAL_TRUE: u8 = 0xFF;
 /// Represents `false` value in binary format.
@@ -534,6 +607,19 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn hash_ref_decode() {
+        use crypto::hash::BlockHash;
+
+        let input = [0xab; 33];
+        let (rest, hash) = HashRef::<BlockHash>::nom_read_borrowed(&input).unwrap();
+        assert_eq!(rest, &input[32..]);
+        assert!(std::ptr::eq(hash.as_bytes(), &input[..32]));
+        assert_eq!(hash.to_hash(), BlockHash::try_from_bytes(&input[..32]).unwrap());
+
+        HashRef::<BlockHash>::nom_read_borrowed(&input[..31]).expect_err("Error is expected");
+    }
+
     #[test]
     fn bytes_to_string() {
         let bytes = Bytes(vec![0xde, 0xad, 0xbe, 0xef]);
@@ -179,6 +179,7 @@ fn init_test_protocol_context(dir_name: &str) -> (ChainId, BlockHeader, InitProt
                 TezosContextTezedgeOnDiskBackendOptions {
                     base_path: data_dir,
                     startup_check: false,
+                    key_history: false,
                 },
             ),
             ipc_socket_path: None,
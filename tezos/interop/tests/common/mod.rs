@@ -95,6 +95,7 @@ pub fn init_test_protocol_context(
                 TezosContextTezedgeOnDiskBackendOptions {
                     base_path: dir_name.to_string(),
                     startup_check: false,
+                    key_history: false,
                 },
             ),
             ipc_socket_path: None,
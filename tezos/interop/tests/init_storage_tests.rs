@@ -86,6 +86,7 @@ fn test_init_empty_context_for_all_enviroment_expect_custom_nets() {
                         TezosContextTezedgeOnDiskBackendOptions {
                             base_path: storage_data_dir.to_string(),
                             startup_check: false,
+                            key_history: false,
                         },
                     ),
                     ipc_socket_path: None,
@@ -168,6 +169,7 @@ fn test_init_empty_context_for_custom_network() {
                 TezosContextTezedgeOnDiskBackendOptions {
                     base_path: storage_data_dir.to_string(),
                     startup_check: false,
+                    key_history: false,
                 },
             ),
             ipc_socket_path: None,
@@ -234,6 +236,7 @@ fn test_init_empty_context_for_sandbox_with_patch_json() -> Result<(), anyhow::E
                 TezosContextTezedgeOnDiskBackendOptions {
                     base_path: storage_data_dir.to_string(),
                     startup_check: false,
+                    key_history: false,
                 },
             ),
             ipc_socket_path: None,
@@ -294,6 +297,7 @@ fn test_init_empty_context_for_sandbox_without_patch_json() -> Result<(), anyhow
                 TezosContextTezedgeOnDiskBackendOptions {
                     base_path: storage_data_dir.to_string(),
                     startup_check: false,
+                    key_history: false,
                 },
             ),
             ipc_socket_path: None,
@@ -0,0 +1,116 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Compares plain owned decoding of peer messages with the decoding used by
+//! the node's read path, which decodes messages received in large numbers
+//! during bootstrap without copying first, so they can be dropped cheaply.
+//! Reports number of allocations per decoding.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+mod codecs_bench_common;
+use codecs_bench_common::*;
+use tezos_messages::p2p::{
+    binary_message::{BinaryRead, BinaryWrite},
+    encoding::{
+        block_header::BlockHeaderMessage, current_head::CurrentHeadMessage,
+        operations_for_blocks::OperationsForBlocksMessage, peer::PeerMessageResponse,
+    },
+};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn report_allocations<T>(name: &str, decode: impl Fn() -> T) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    black_box(decode());
+    println!(
+        "{}: {} allocations, {} bytes",
+        name,
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes
+    );
+}
+
+/// Benchmarks decoding of the peer message as the node's read path does it,
+/// with the message kept or dropped after its borrowed decoding.
+fn bench_read_path(c: &mut Criterion, message: &str, data: &[u8]) {
+    let owned = || PeerMessageResponse::from_bytes(data).expect("Failed to decode");
+    let kept = || {
+        PeerMessageResponse::from_bytes_filtered(data, |_| true)
+            .expect("Failed to decode")
+            .expect("Message is kept")
+    };
+    let dropped =
+        || PeerMessageResponse::from_bytes_filtered(data, |_| false).expect("Failed to decode");
+
+    report_allocations(&format!("{}-decode-owned", message), owned);
+    report_allocations(&format!("{}-decode-kept", message), kept);
+    report_allocations(&format!("{}-decode-dropped", message), dropped);
+
+    c.bench_function(&format!("{}-decode-owned", message), |b| {
+        b.iter(|| black_box(owned()))
+    });
+    c.bench_function(&format!("{}-decode-kept", message), |b| {
+        b.iter(|| black_box(kept()))
+    });
+    c.bench_function(&format!("{}-decode-dropped", message), |b| {
+        b.iter(|| black_box(dropped()))
+    });
+}
+
+fn peer_message_data<T>(file: &str) -> Vec<u8>
+where
+    T: BinaryRead + Into<PeerMessageResponse>,
+{
+    let message: PeerMessageResponse = T::from_bytes(read_data_unwrap(file))
+        .expect("Failed to decode")
+        .into();
+    message.as_bytes().expect("Failed to encode")
+}
+
+fn block_header_benchmark(c: &mut Criterion) {
+    let data = peer_message_data::<BlockHeaderMessage>("block-header.msg");
+    bench_read_path(c, "block-header", &data);
+}
+
+fn operations_for_blocks_benchmark(c: &mut Criterion) {
+    let data = peer_message_data::<OperationsForBlocksMessage>("operations-for-blocks.huge.msg");
+    bench_read_path(c, "operations-for-blocks", &data);
+}
+
+fn current_head_benchmark(c: &mut Criterion) {
+    let data = peer_message_data::<CurrentHeadMessage>("current-head.big.msg");
+    bench_read_path(c, "current-head", &data);
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default();
+    targets = block_header_benchmark, operations_for_blocks_benchmark, current_head_benchmark,
+}
+
+criterion_main!(benches);
@@ -29,6 +29,12 @@ pub trait BinaryRead: Sized {
     fn from_bytes<B: AsRef<[u8]>>(buf: B) -> Result<Self, BinaryReaderError>;
 }
 
+/// Trait for reading a binary message that references the input buffer.
+pub trait BinaryReadBorrowed<'a>: Sized {
+    /// Create new struct referencing the bytes.
+    fn from_bytes_borrowed(buf: &'a [u8]) -> Result<Self, BinaryReaderError>;
+}
+
 /// Trait for writing a binary message.
 pub trait BinaryWrite {
     /// Produce bytes from the struct.
@@ -62,6 +68,19 @@ where
     }
 }
 
+impl<'a, T> BinaryReadBorrowed<'a> for T
+where
+    T: tezos_encoding::nom::NomReaderBorrowed<'a>,
+{
+    #[inline]
+    fn from_bytes_borrowed(buf: &'a [u8]) -> Result<Self, BinaryReaderError> {
+        all_consuming(complete(T::nom_read_borrowed))(buf)
+            .finish()
+            .map(|(_, output)| output)
+            .map_err(|error| map_nom_error(buf, error))
+    }
+}
+
 /// This trait is able to predict the exact size of the message from the first bytes of the message.
 pub trait SizeFromChunk {
     /// Returns the size of the message.
@@ -11,7 +11,7 @@ use super::fitness::Fitness;
 use crypto::hash::{BlockHash, BlockPayloadHash, ContextHash, OperationListListHash, Signature};
 use tezos_encoding::encoding::HasEncoding;
 use tezos_encoding::nom::NomReader;
-use tezos_encoding::types::SizedBytes;
+use tezos_encoding::types::{HashRef, SizedBytes};
 use tezos_encoding::{enc::BinWriter, types::Bytes};
 
 use super::limits::{
@@ -45,6 +45,21 @@ impl From<BlockHeaderMessage> for BlockHeader {
     }
 }
 
+/// [BlockHeaderMessage] referencing the decoded input.
+#[derive(Debug, Eq, PartialEq, Getters, Clone, HasEncoding, NomReader, BinWriter)]
+pub struct BlockHeaderMessageRef<'a> {
+    #[get = "pub"]
+    block_header: BlockHeaderRef<'a>,
+}
+
+impl From<BlockHeaderMessageRef<'_>> for BlockHeaderMessage {
+    fn from(msg: BlockHeaderMessageRef<'_>) -> Self {
+        BlockHeaderMessage {
+            block_header: msg.block_header.into(),
+        }
+    }
+}
+
 // -----------------------------------------------------------------------------------------------
 #[cfg_attr(feature = "fuzzing", derive(fuzzcheck::DefaultMutator))]
 #[derive(
@@ -160,6 +175,54 @@ impl std::fmt::Debug for BlockHeader {
     }
 }
 
+/// [BlockHeader] referencing the decoded input.
+#[derive(Eq, PartialEq, Clone, Debug, Getters, CopyGetters, HasEncoding, NomReader, BinWriter)]
+#[encoding(bounded = "BLOCK_HEADER_MAX_SIZE")]
+pub struct BlockHeaderRef<'a> {
+    #[get_copy = "pub"]
+    #[encoding(builtin = "Int32")]
+    level: Level,
+    #[get_copy = "pub"]
+    proto: u8,
+    #[get_copy = "pub"]
+    predecessor: HashRef<'a, BlockHash>,
+    #[get_copy = "pub"]
+    timestamp: Timestamp,
+    #[get_copy = "pub"]
+    validation_pass: u8,
+    #[get_copy = "pub"]
+    operations_hash: HashRef<'a, OperationListListHash>,
+    #[get = "pub"]
+    fitness: Fitness,
+    #[get_copy = "pub"]
+    context: HashRef<'a, ContextHash>,
+
+    #[get_copy = "pub"]
+    #[encoding(bounded = "BLOCK_HEADER_PROTOCOL_DATA_MAX_SIZE")]
+    protocol_data: &'a [u8],
+
+    #[get = "pub"]
+    #[encoding(hash)]
+    hash: EncodingHash,
+}
+
+impl From<BlockHeaderRef<'_>> for BlockHeader {
+    fn from(header: BlockHeaderRef<'_>) -> Self {
+        BlockHeader {
+            level: header.level,
+            proto: header.proto,
+            predecessor: header.predecessor.to_hash(),
+            timestamp: header.timestamp,
+            validation_pass: header.validation_pass,
+            operations_hash: header.operations_hash.to_hash(),
+            fitness: header.fitness,
+            context: header.context.to_hash(),
+            protocol_data: header.protocol_data.to_vec().into(),
+            hash: header.hash,
+        }
+    }
+}
+
 /// Optional 256-bit digest of encoded data
 /// TODO https://viablesystems.atlassian.net/browse/TE-675
 #[cfg_attr(
@@ -3,7 +3,7 @@
 
 use std::convert::TryFrom;
 
-use getset::Getters;
+use getset::{CopyGetters, Getters};
 use hex::FromHexError;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -14,7 +14,10 @@ use crypto::{
 };
 use tezos_encoding::encoding::HasEncoding;
 use tezos_encoding::nom::NomReader;
-use tezos_encoding::{enc::BinWriter, types::Bytes};
+use tezos_encoding::{
+    enc::BinWriter,
+    types::{Bytes, HashRef},
+};
 
 use super::limits::{GET_OPERATIONS_MAX_LENGTH, OPERATION_MAX_SIZE};
 
@@ -58,6 +61,25 @@ impl Operation {
     }
 }
 
+/// [Operation] referencing the decoded input.
+#[derive(Clone, Eq, PartialEq, Debug, HasEncoding, NomReader, BinWriter, CopyGetters)]
+pub struct OperationRef<'a> {
+    #[get_copy = "pub"]
+    branch: HashRef<'a, BlockHash>,
+    #[encoding(bounded = "OPERATION_MAX_SIZE")]
+    #[get_copy = "pub"]
+    data: &'a [u8],
+}
+
+impl From<OperationRef<'_>> for Operation {
+    fn from(operation: OperationRef<'_>) -> Self {
+        Self {
+            branch: operation.branch.to_hash(),
+            data: operation.data.to_vec().into(),
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum FromDecodedOperationError {
     #[error("Failed to decode from base58 string: {0}")]
@@ -18,9 +18,10 @@ use tezos_encoding::{
     encoding::{Encoding, HasEncoding},
     has_encoding,
     nom::NomReader,
+    types::HashRef,
 };
 
-use crate::p2p::encoding::operation::Operation;
+use crate::p2p::encoding::operation::{Operation, OperationRef};
 
 use super::limits::{GET_OPERATIONS_FOR_BLOCKS_MAX_LENGTH, OPERATION_LIST_MAX_SIZE};
 
@@ -119,6 +120,44 @@ impl From<OperationsForBlocksMessage> for Vec<Operation> {
     }
 }
 
+/// [OperationsForBlock] referencing the decoded input.
+#[derive(Clone, Debug, Eq, PartialEq, CopyGetters, HasEncoding, NomReader, BinWriter)]
+pub struct OperationsForBlockRef<'a> {
+    #[get_copy = "pub"]
+    hash: HashRef<'a, BlockHash>,
+    #[get_copy = "pub"]
+    validation_pass: i8,
+}
+
+impl From<OperationsForBlockRef<'_>> for OperationsForBlock {
+    fn from(ofb: OperationsForBlockRef<'_>) -> Self {
+        OperationsForBlock::new(ofb.hash.to_hash(), ofb.validation_pass)
+    }
+}
+
+/// [OperationsForBlocksMessage] referencing the decoded input, so operations
+/// are not copied until the message is known to be needed.
+#[derive(Clone, Debug, Eq, PartialEq, Getters, HasEncoding, NomReader, BinWriter)]
+pub struct OperationsForBlocksMessageRef<'a> {
+    #[get = "pub"]
+    operations_for_block: OperationsForBlockRef<'a>,
+    #[get = "pub"]
+    operation_hashes_path: Path,
+    #[get = "pub"]
+    #[encoding(bounded = "OPERATION_LIST_MAX_SIZE", list, dynamic)]
+    operations: Vec<OperationRef<'a>>,
+}
+
+impl From<OperationsForBlocksMessageRef<'_>> for OperationsForBlocksMessage {
+    fn from(msg: OperationsForBlocksMessageRef<'_>) -> Self {
+        OperationsForBlocksMessage {
+            operations_for_block: msg.operations_for_block.into(),
+            operation_hashes_path: msg.operation_hashes_path,
+            operations: msg.operations.into_iter().map(Operation::from).collect(),
+        }
+    }
+}
+
 // -----------------------------------------------------------------------------------------------
 #[cfg_attr(feature = "fuzzing", derive(fuzzcheck::DefaultMutator))]
 #[derive(Clone, Serialize, Deserialize, Eq, PartialEq, Debug, Getters)]
@@ -5,15 +5,18 @@ use getset::Getters;
 use serde::{Deserialize, Serialize};
 
 use crypto::hash::OperationHash;
+use tezos_encoding::binary_reader::BinaryReaderError;
 use tezos_encoding::enc::BinWriter;
 use tezos_encoding::encoding::HasEncoding;
 use tezos_encoding::nom::NomReader;
 
-use crate::p2p::binary_message::{MessageHash, SizeFromChunk};
+use crate::p2p::binary_message::{BinaryRead, BinaryReadBorrowed, MessageHash, SizeFromChunk};
 use crate::p2p::encoding::prelude::*;
 use crate::p2p::peer_message_size;
 
+use super::block_header::BlockHeaderMessageRef;
 use super::limits::MESSAGE_MAX_SIZE;
+use super::operations_for_blocks::OperationsForBlocksMessageRef;
 use super::predecessor_header::{GetPredecessorHeaderMessage, PredecessorHeaderMessage};
 use super::protocol_branch::{GetProtocolBranchMessage, ProtocolBranchMessage};
 
@@ -254,6 +257,61 @@ impl PeerMessageResponse {
     pub fn set_size_hint(&mut self, size: usize) {
         self.size_hint = Some(size);
     }
+
+    /// Decodes the message, decoding bulk messages without copying first, so
+    /// that `keep` can drop them before they are copied out of the `bytes`.
+    ///
+    /// Each message is decoded only once, `None` is returned for dropped messages.
+    pub fn from_bytes_filtered(
+        bytes: &[u8],
+        keep: impl FnOnce(&PeerMessageRef) -> bool,
+    ) -> Result<Option<Self>, BinaryReaderError> {
+        match PeerMessageResponseRef::from_bytes_borrowed(bytes) {
+            Ok(message) if keep(message.message()) => Ok(Some(message.into())),
+            Ok(_) => Ok(None),
+            // Not a bulk message, only the owned decoding supports it.
+            Err(BinaryReaderError::UnknownTag(_)) => Self::from_bytes(bytes).map(Some),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// [PeerMessage] carrying bulk data, referencing the decoded input.
+///
+/// Only messages received in large numbers during bootstrap are supported,
+/// decoding other messages fails with unknown tag error.
+#[derive(Debug, Clone, HasEncoding, NomReader)]
+#[encoding(tags = "u16", ignore_unknown)]
+pub enum PeerMessageRef<'a> {
+    #[encoding(tag = 0x21)]
+    BlockHeader(BlockHeaderMessageRef<'a>),
+    #[encoding(tag = 0x61)]
+    OperationsForBlocks(OperationsForBlocksMessageRef<'a>),
+}
+
+/// [PeerMessageResponse] referencing the decoded input.
+#[derive(Debug, Clone, Getters, HasEncoding, NomReader)]
+#[encoding(dynamic = "MESSAGE_MAX_SIZE")]
+pub struct PeerMessageResponseRef<'a> {
+    #[get = "pub"]
+    message: PeerMessageRef<'a>,
+}
+
+impl From<PeerMessageRef<'_>> for PeerMessage {
+    fn from(message: PeerMessageRef<'_>) -> Self {
+        match message {
+            PeerMessageRef::BlockHeader(msg) => PeerMessage::BlockHeader(msg.into()),
+            PeerMessageRef::OperationsForBlocks(msg) => {
+                PeerMessage::OperationsForBlocks(msg.into())
+            }
+        }
+    }
+}
+
+impl From<PeerMessageResponseRef<'_>> for PeerMessageResponse {
+    fn from(message: PeerMessageResponseRef<'_>) -> Self {
+        PeerMessage::from(message.message).into()
+    }
 }
 
 impl From<PeerMessage> for PeerMessageResponse {
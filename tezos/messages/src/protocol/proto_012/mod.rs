@@ -7,6 +7,7 @@ pub mod block_header;
 pub mod constants;
 pub mod contract;
 pub mod operation;
+pub mod operation_selection;
 pub mod rights;
 pub mod votes;
 
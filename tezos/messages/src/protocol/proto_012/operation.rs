@@ -328,8 +328,10 @@ Double_endorsement_evidence (tag 2)
 #[cfg_attr(feature = "fuzzing", derive(fuzzcheck::DefaultMutator))]
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, HasEncoding, NomReader, BinWriter)]
 pub struct DoubleEndorsementEvidenceOperation {
-    op1: InlinedEndorsement,
-    op2: InlinedEndorsement,
+    #[encoding(dynamic)]
+    pub op1: InlinedEndorsement,
+    #[encoding(dynamic)]
+    pub op2: InlinedEndorsement,
 }
 
 /**
@@ -467,9 +469,9 @@ Double_preendorsement_evidence (tag 7)
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, HasEncoding, NomReader, BinWriter)]
 pub struct DoublePreendorsementEvidenceOperation {
     #[encoding(dynamic)]
-    op1: InlinedPreendorsement,
+    pub op1: InlinedPreendorsement,
     #[encoding(dynamic)]
-    op2: InlinedPreendorsement,
+    pub op2: InlinedPreendorsement,
 }
 
 /**
@@ -653,12 +655,12 @@ Set_deposits_limit (tag 112)
 #[cfg_attr(feature = "fuzzing", derive(fuzzcheck::DefaultMutator))]
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, HasEncoding, NomReader, BinWriter)]
 pub struct SetDepositsLimitOperation {
-    source: SignaturePublicKeyHash,
-    fee: Mutez,
-    counter: Mutez,
-    gas_limit: Mutez,
-    storage_limit: Mutez,
-    limit: Option<Mutez>,
+    pub source: SignaturePublicKeyHash,
+    pub fee: Mutez,
+    pub counter: Mutez,
+    pub gas_limit: Mutez,
+    pub storage_limit: Mutez,
+    pub limit: Option<Mutez>,
 }
 
 #[cfg(test)]
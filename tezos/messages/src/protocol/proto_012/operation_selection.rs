@@ -0,0 +1,461 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Selection of the operations included in a block.
+//!
+//! Each validation pass has its own quota on the total size and on the number
+//! of operations, the manager operations also share the gas limit of the block.
+//! The operations paying the highest fee per the consumed share of the quota
+//! are taken first. The manager operations of the same source are taken in
+//! the order of their counters, an operation is never included without its
+//! predecessor.
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, BinaryHeap, VecDeque},
+};
+
+use num_traits::ToPrimitive;
+use tezos_encoding::types::Mutez;
+
+use crate::base::signature_public_key::SignaturePublicKeyHash;
+
+use super::operation::Contents;
+
+/// Limits of one validation pass of the block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PassQuota {
+    /// Total size in bytes of the operations of the pass
+    pub max_size: usize,
+    /// Number of operations of the pass, unlimited if `None`
+    pub max_op: Option<usize>,
+}
+
+/// Quotas of the consensus, votes, anonymous and managers passes.
+pub const QUOTAS: [PassQuota; 4] = [
+    PassQuota {
+        max_size: 2048 * 2048,
+        max_op: Some(2048),
+    },
+    PassQuota {
+        max_size: 32 * 1024,
+        max_op: None,
+    },
+    PassQuota {
+        max_size: 132 * 1024,
+        max_op: Some(132),
+    },
+    PassQuota {
+        max_size: 512 * 1024,
+        max_op: None,
+    },
+];
+
+/// The `hard_gas_limit_per_block` constant of the protocol.
+pub const HARD_GAS_LIMIT_PER_BLOCK: u64 = 5_200_000;
+
+/// Summary of a batch of manager operations signed by one source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManagerInfo {
+    pub source: SignaturePublicKeyHash,
+    pub first_counter: u64,
+    pub last_counter: u64,
+    /// Sum of the fees of the batch, in mutez
+    pub fee: u64,
+    /// Sum of the gas limits of the batch
+    pub gas_limit: u64,
+}
+
+impl ManagerInfo {
+    /// Returns `None` if some of the contents is not a manager operation,
+    /// or the batch has several sources, or its counters are not consecutive.
+    pub fn from_contents(contents: &[Contents]) -> Option<Self> {
+        let batch = contents
+            .iter()
+            .map(manager_fields)
+            .collect::<Option<Vec<_>>>()?;
+        Self::from_batch(batch)
+    }
+
+    /// Returns `None` if the batch is empty, or has several sources,
+    /// or its counters are not consecutive.
+    pub fn from_batch(batch: impl IntoIterator<Item = ManagerFields>) -> Option<Self> {
+        let mut batch = batch.into_iter();
+        let first = batch.next()?;
+        let mut info = ManagerInfo {
+            source: first.source,
+            first_counter: first.counter,
+            last_counter: first.counter,
+            fee: first.fee,
+            gas_limit: first.gas_limit,
+        };
+        for fields in batch {
+            if fields.source != info.source || fields.counter != info.last_counter.checked_add(1)? {
+                return None;
+            }
+            info.last_counter += 1;
+            info.fee = info.fee.checked_add(fields.fee)?;
+            info.gas_limit = info.gas_limit.checked_add(fields.gas_limit)?;
+        }
+        Some(info)
+    }
+}
+
+/// Fields of one manager operation of a batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManagerFields {
+    pub source: SignaturePublicKeyHash,
+    pub fee: u64,
+    pub counter: u64,
+    pub gas_limit: u64,
+}
+
+fn manager_fields(contents: &Contents) -> Option<ManagerFields> {
+    fn fields(
+        source: &SignaturePublicKeyHash,
+        fee: &Mutez,
+        counter: &Mutez,
+        gas_limit: &Mutez,
+    ) -> Option<ManagerFields> {
+        Some(ManagerFields {
+            source: source.clone(),
+            fee: to_u64(fee)?,
+            counter: to_u64(counter)?,
+            gas_limit: to_u64(gas_limit)?,
+        })
+    }
+
+    match contents {
+        Contents::Reveal(op) => fields(&op.source, &op.fee, &op.counter, &op.gas_limit),
+        Contents::Transaction(op) => fields(&op.source, &op.fee, &op.counter, &op.gas_limit),
+        Contents::Origination(op) => fields(&op.source, &op.fee, &op.counter, &op.gas_limit),
+        Contents::Delegation(op) => fields(&op.source, &op.fee, &op.counter, &op.gas_limit),
+        Contents::RegisterGlobalConstant(op) => {
+            fields(&op.source, &op.fee, &op.counter, &op.gas_limit)
+        }
+        Contents::SetDepositsLimit(op) => fields(&op.source, &op.fee, &op.counter, &op.gas_limit),
+        _ => None,
+    }
+}
+
+fn to_u64(value: &Mutez) -> Option<u64> {
+    value.0.to_u64()
+}
+
+/// An operation which may be included in the block.
+#[derive(Debug, Clone)]
+pub struct Candidate<T> {
+    pub operation: T,
+    /// Size in bytes of the operation, including its branch and signature
+    pub size: usize,
+    /// `None` for the operations that are not manager operations, they pay no fee
+    pub manager: Option<ManagerInfo>,
+}
+
+impl<T> Candidate<T> {
+    pub fn new(operation: T, size: usize, manager: Option<ManagerInfo>) -> Self {
+        Candidate {
+            operation,
+            size,
+            manager,
+        }
+    }
+}
+
+/// The operation at the front of a chain, ordered by its fee per weight.
+/// The weight is the largest share of the pass size or of the block gas
+/// the operation consumes.
+#[derive(PartialEq, Eq)]
+struct Head {
+    fee: u64,
+    weight: u128,
+    index: usize,
+    chain: usize,
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let this = u128::from(self.fee) * other.weight;
+        let that = u128::from(other.fee) * self.weight;
+        // on equal ratio, the earlier candidate goes first
+        this.cmp(&that).then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+/// Selects the operations of one validation pass, in the order they should
+/// appear in the block.
+///
+/// The result depends only on the candidates and their order, the order
+/// breaks the ties between operations paying the same fee per weight.
+/// `gas_limit` is the gas still available in the block.
+pub fn select<T>(candidates: Vec<Candidate<T>>, quota: &PassQuota, gas_limit: u64) -> Vec<T> {
+    // an operation of a chain may be included only after its predecessor
+    let mut chains = vec![];
+    let mut sources = BTreeMap::<_, Vec<usize>>::new();
+    for (index, candidate) in candidates.iter().enumerate() {
+        match &candidate.manager {
+            Some(manager) => sources.entry(&manager.source).or_default().push(index),
+            None => chains.push(VecDeque::from(vec![index])),
+        }
+    }
+    let manager = |index: usize| {
+        candidates[index]
+            .manager
+            .as_ref()
+            .expect("manager operation")
+    };
+    for (_, mut indexes) in sources {
+        // of the operations with the same counter, the one with the highest fee is kept
+        indexes.sort_by_key(|&i| (manager(i).first_counter, Reverse(manager(i).fee), i));
+        let mut chain = VecDeque::new();
+        let mut next_counter = None;
+        for index in indexes {
+            let info = manager(index);
+            match next_counter {
+                Some(counter) if info.first_counter < counter => continue,
+                Some(counter) if info.first_counter > counter => break,
+                _ => (),
+            }
+            next_counter = info.last_counter.checked_add(1);
+            chain.push_back(index);
+            if next_counter.is_none() {
+                break;
+            }
+        }
+        chains.push(chain);
+    }
+
+    let max_size = quota.max_size as u64;
+    let gas = |index: usize| {
+        candidates[index]
+            .manager
+            .as_ref()
+            .map_or(0, |manager| manager.gas_limit)
+    };
+    let head = |chain: usize, index: usize| {
+        let candidate = &candidates[index];
+        // clamp the values exceeding the quota, such operation does not fit anyway
+        let size = u128::from((candidate.size as u64).min(max_size.saturating_add(1)));
+        let gas = u128::from(gas(index).min(gas_limit.saturating_add(1)));
+        let weight = (size * u128::from(gas_limit.max(1))).max(gas * u128::from(max_size.max(1)));
+        Head {
+            fee: candidate.manager.as_ref().map_or(0, |manager| manager.fee),
+            weight,
+            index,
+            chain,
+        }
+    };
+    let mut heap = chains
+        .iter()
+        .enumerate()
+        .filter_map(|(chain, indexes)| Some(head(chain, *indexes.front()?)))
+        .collect::<BinaryHeap<_>>();
+
+    let mut selected = vec![];
+    let (mut size, mut used_gas) = (0, 0);
+    while let Some(Head { index, chain, .. }) = heap.pop() {
+        if matches!(quota.max_op, Some(max_op) if selected.len() >= max_op) {
+            break;
+        }
+        let candidate_size = candidates[index].size;
+        let candidate_gas = gas(index);
+        if size + candidate_size > quota.max_size || used_gas + candidate_gas > gas_limit {
+            // the rest of the chain is dropped as well
+            continue;
+        }
+        size += candidate_size;
+        used_gas += candidate_gas;
+        selected.push(index);
+
+        chains[chain].pop_front();
+        if let Some(&next) = chains[chain].front() {
+            heap.push(head(chain, next));
+        }
+    }
+
+    let mut candidates = candidates
+        .into_iter()
+        .map(|candidate| Some(candidate.operation))
+        .collect::<Vec<_>>();
+    selected
+        .into_iter()
+        .filter_map(|index| candidates[index].take())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::base::signature_public_key::SignaturePublicKeyHash;
+
+    use super::{select, Candidate, Contents, ManagerInfo, PassQuota};
+
+    const ALICE: &str = "tz1VSUr8wwNhLAzempoch5d6hLRiTh8Cjcjb";
+    const BOB: &str = "tz1aSkwEot3L2kmUvcoxzjMomb9mvBNuzFK6";
+    const CAROL: &str = "tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx";
+
+    const QUOTA: PassQuota = PassQuota {
+        max_size: 1000,
+        max_op: None,
+    };
+
+    fn manager(
+        name: &'static str,
+        source: &str,
+        counter: u64,
+        fee: u64,
+        gas_limit: u64,
+        size: usize,
+    ) -> Candidate<&'static str> {
+        let info = ManagerInfo {
+            source: SignaturePublicKeyHash::from_b58_hash(source).unwrap(),
+            first_counter: counter,
+            last_counter: counter,
+            fee,
+            gas_limit,
+        };
+        Candidate::new(name, size, Some(info))
+    }
+
+    fn other(name: &'static str, size: usize) -> Candidate<&'static str> {
+        Candidate::new(name, size, None)
+    }
+
+    #[test]
+    fn pass_quota() {
+        let candidates = vec![
+            other("a", 400),
+            other("b", 700),
+            other("c", 300),
+            other("d", 300),
+        ];
+        // `b` does not fit, `d` exceeds the count
+        let quota = PassQuota {
+            max_size: 1000,
+            max_op: Some(2),
+        };
+        assert_eq!(select(candidates.clone(), &quota, 0), ["a", "c"]);
+        assert_eq!(select(candidates, &QUOTA, 0), ["a", "c", "d"]);
+    }
+
+    #[test]
+    fn highest_fee_per_weight_first() {
+        let candidates = vec![
+            manager("cheap", ALICE, 1, 100, 1000, 100),
+            manager("rich", BOB, 1, 1000, 1000, 100),
+            // pays more, but takes the whole gas of the block
+            manager("heavy", CAROL, 1, 1500, 10_000, 100),
+        ];
+        assert_eq!(
+            select(candidates.clone(), &QUOTA, 10_000),
+            ["rich", "cheap"]
+        );
+        assert_eq!(
+            select(candidates, &QUOTA, 100_000),
+            ["heavy", "rich", "cheap"]
+        );
+    }
+
+    #[test]
+    fn gas_limit() {
+        let candidates = vec![
+            manager("a", ALICE, 1, 300, 600, 100),
+            manager("b", BOB, 1, 200, 500, 100),
+            manager("c", CAROL, 1, 100, 400, 100),
+        ];
+        // `b` does not fit into the rest of the gas, but `c` does
+        assert_eq!(select(candidates, &QUOTA, 1000), ["a", "c"]);
+    }
+
+    #[test]
+    fn counter_order() {
+        let candidates = vec![
+            manager("third", ALICE, 3, 900, 100, 100),
+            manager("first", ALICE, 1, 100, 100, 100),
+            manager("bob", BOB, 7, 500, 100, 100),
+            manager("second", ALICE, 2, 100, 100, 100),
+        ];
+        assert_eq!(
+            select(candidates, &QUOTA, 1000),
+            ["bob", "first", "second", "third"]
+        );
+    }
+
+    #[test]
+    fn counter_gap() {
+        let candidates = vec![
+            manager("first", ALICE, 1, 100, 100, 100),
+            manager("third", ALICE, 3, 900, 100, 100),
+        ];
+        assert_eq!(select(candidates, &QUOTA, 1000), ["first"]);
+    }
+
+    #[test]
+    fn same_counter() {
+        let candidates = vec![
+            manager("low", ALICE, 1, 100, 100, 100),
+            manager("high", ALICE, 1, 200, 100, 100),
+            manager("next", ALICE, 2, 100, 100, 100),
+        ];
+        assert_eq!(select(candidates, &QUOTA, 1000), ["high", "next"]);
+    }
+
+    #[test]
+    fn chain_dropped() {
+        let candidates = vec![
+            manager("big", ALICE, 1, 100, 100, 900),
+            manager("after_big", ALICE, 2, 900, 100, 100),
+            manager("bob", BOB, 1, 500, 100, 200),
+        ];
+        // `big` does not fit after `bob`, then `after_big` cannot be included
+        assert_eq!(select(candidates, &QUOTA, 1000), ["bob"]);
+    }
+
+    #[test]
+    fn ties_keep_order() {
+        let candidates = vec![
+            manager("a", ALICE, 1, 100, 100, 100),
+            other("unknown", 100),
+            manager("b", BOB, 1, 100, 100, 100),
+            manager("c", CAROL, 1, 100, 100, 100),
+        ];
+        assert_eq!(select(candidates, &QUOTA, 1000), ["a", "b", "c", "unknown"]);
+    }
+
+    #[test]
+    fn manager_info() {
+        let batch = serde_json::from_str::<Vec<Contents>>(&format!(
+            r#"[
+                {{ "kind": "reveal", "source": "{ALICE}", "fee": "100", "counter": "7", "gas_limit": "1000", "storage_limit": "0", "public_key": "edpkuBknW28nW72KG6RoHtYW7p12T6GKc7nAbwYX5m8Wd9sDVC9yav" }},
+                {{ "kind": "transaction", "source": "{ALICE}", "fee": "300", "counter": "8", "gas_limit": "1500", "storage_limit": "0", "amount": "1", "destination": "{BOB}" }}
+            ]"#
+        ))
+        .unwrap();
+        assert_eq!(
+            ManagerInfo::from_contents(&batch),
+            Some(ManagerInfo {
+                source: SignaturePublicKeyHash::from_b58_hash(ALICE).unwrap(),
+                first_counter: 7,
+                last_counter: 8,
+                fee: 400,
+                gas_limit: 2500,
+            })
+        );
+        assert_eq!(
+            ManagerInfo::from_contents(&batch[1..])
+                .unwrap()
+                .first_counter,
+            8
+        );
+
+        let mut reversed = batch;
+        reversed.reverse();
+        assert_eq!(ManagerInfo::from_contents(&reversed), None);
+        assert_eq!(ManagerInfo::from_contents(&[]), None);
+    }
+}
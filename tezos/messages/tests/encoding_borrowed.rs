@@ -0,0 +1,111 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::{fs, ops::Range, path::PathBuf};
+
+use crypto::hash::ChainId;
+use tezos_messages::p2p::{
+    binary_message::{BinaryRead, BinaryReadBorrowed, BinaryWrite},
+    encoding::{
+        block_header::{BlockHeaderMessage, BlockHeaderMessageRef},
+        operations_for_blocks::{OperationsForBlocksMessage, OperationsForBlocksMessageRef},
+        peer::{PeerMessageRef, PeerMessageResponseRef},
+        prelude::*,
+    },
+};
+
+fn read_data(file: &str) -> Vec<u8> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("resources")
+        .join(file);
+    fs::read(&path).unwrap_or_else(|e| panic!("Cannot read {}: {}", path.display(), e))
+}
+
+fn contains(buffer: &[u8], slice: &[u8]) -> bool {
+    let Range { start, end } = buffer.as_ptr_range();
+    let slice = slice.as_ptr_range();
+    start <= slice.start && slice.end <= end
+}
+
+#[test]
+fn borrowed_operations_for_blocks() {
+    let data = read_data("operations-for-blocks.huge.msg");
+    let owned = OperationsForBlocksMessage::from_bytes(&data).unwrap();
+    let borrowed = OperationsForBlocksMessageRef::from_bytes_borrowed(&data).unwrap();
+
+    assert!(!borrowed.operations().is_empty());
+    for operation in borrowed.operations() {
+        assert!(contains(&data, operation.data()));
+        assert!(contains(&data, operation.branch().as_bytes()));
+    }
+    assert_eq!(borrowed.as_bytes().unwrap(), data);
+    assert_eq!(OperationsForBlocksMessage::from(borrowed), owned);
+}
+
+#[test]
+fn borrowed_block_header() {
+    let data = read_data("block-header.msg");
+    let owned = BlockHeaderMessage::from_bytes(&data).unwrap();
+    let borrowed = BlockHeaderMessageRef::from_bytes_borrowed(&data).unwrap();
+
+    let header = borrowed.block_header();
+    assert!(contains(&data, header.protocol_data()));
+    assert_eq!(header.hash(), owned.block_header().hash());
+    assert_eq!(borrowed.as_bytes().unwrap(), data);
+    assert_eq!(BlockHeaderMessage::from(borrowed), owned);
+}
+
+#[test]
+fn borrowed_peer_message() {
+    let data = PeerMessageResponse::from(
+        OperationsForBlocksMessage::from_bytes(read_data("operations-for-blocks.huge.msg"))
+            .unwrap(),
+    )
+    .as_bytes()
+    .unwrap();
+    let message = PeerMessageResponseRef::from_bytes_borrowed(&data).unwrap();
+    assert!(matches!(
+        message.message(),
+        PeerMessageRef::OperationsForBlocks(_)
+    ));
+
+    let data = PeerMessageResponse::from(GetCurrentHeadMessage::new(
+        ChainId::try_from(vec![0; 4]).unwrap(),
+    ))
+    .as_bytes()
+    .unwrap();
+    PeerMessageResponseRef::from_bytes_borrowed(&data).expect_err("Error is expected");
+}
+
+#[test]
+fn peer_message_from_bytes_filtered() {
+    let owned = PeerMessageResponse::from(
+        OperationsForBlocksMessage::from_bytes(read_data("operations-for-blocks.huge.msg"))
+            .unwrap(),
+    );
+    let data = owned.as_bytes().unwrap();
+
+    let kept = PeerMessageResponse::from_bytes_filtered(&data, |message| {
+        matches!(message, PeerMessageRef::OperationsForBlocks(_))
+    })
+    .unwrap()
+    .expect("Message is kept");
+    assert_eq!(kept.as_bytes().unwrap(), data);
+
+    let dropped = PeerMessageResponse::from_bytes_filtered(&data, |_| false).unwrap();
+    assert!(dropped.is_none());
+
+    // Messages other than bulk ones are always kept.
+    let data = PeerMessageResponse::from(GetCurrentHeadMessage::new(
+        ChainId::try_from(vec![0; 4]).unwrap(),
+    ))
+    .as_bytes()
+    .unwrap();
+    let kept = PeerMessageResponse::from_bytes_filtered(&data, |_| false)
+        .unwrap()
+        .expect("Message is kept");
+    assert!(matches!(kept.message(), PeerMessage::GetCurrentHead(_)));
+
+    PeerMessageResponse::from_bytes_filtered(&data[..data.len() - 1], |_| true)
+        .expect_err("Error is expected");
+}
@@ -0,0 +1,87 @@
+// Copyright (c) SimpleStaking, Viable Systems and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Compares binary layout of p2p messages with descriptions produced by Octez
+//! `tezos-codec describe <id> binary schema`, stored in `resources/describe`.
+
+use std::{fs, path::PathBuf};
+
+use tezos_encoding::{
+    describe::{BinarySchema, DifferenceKind},
+    encoding::{Encoding, HasEncoding},
+};
+use tezos_messages::p2p::encoding::{
+    ack::AckMessage, connection::ConnectionMessage, metadata::MetadataMessage,
+    peer::PeerMessageResponse,
+};
+
+const MESSAGES: [&str; 4] = [
+    "connection_message",
+    "connection_metadata",
+    "ack",
+    "p2p_message",
+];
+
+/// Structural differences that are known and accepted.
+const KNOWN_DIFFERENCES: &[(&str, &str)] = &[
+    // recursive Merkle path is implemented with a custom encoding
+    (
+        "p2p_message",
+        "message.OperationsForBlocks.operation_hashes_path",
+    ),
+];
+
+fn octez_schema(name: &str) -> BinarySchema {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("resources")
+        .join("describe")
+        .join(format!("{}.txt", name));
+    let text = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Cannot read {}: {}", path.display(), e));
+    text.parse()
+        .unwrap_or_else(|e| panic!("Cannot parse {}: {}", path.display(), e))
+}
+
+fn encoding(name: &str) -> Encoding {
+    match name {
+        "connection_message" => ConnectionMessage::encoding(),
+        "connection_metadata" => MetadataMessage::encoding(),
+        "ack" => AckMessage::encoding(),
+        "p2p_message" => PeerMessageResponse::encoding(),
+        _ => unreachable!("unknown message {}", name),
+    }
+}
+
+#[test]
+fn octez_descriptions_roundtrip() {
+    for name in MESSAGES {
+        let schema = octez_schema(name);
+        let reparsed: BinarySchema = schema.to_string().parse().unwrap();
+        assert_eq!(schema, reparsed, "Roundtrip failed for {}", name);
+    }
+}
+
+#[test]
+fn p2p_messages_match_octez() {
+    let mut unexpected = Vec::new();
+    for name in MESSAGES {
+        let differences = encoding(name).describe_binary().diff(&octez_schema(name));
+        for difference in differences {
+            let known = KNOWN_DIFFERENCES
+                .iter()
+                .any(|(message, path)| *message == name && *path == difference.path);
+            match difference.kind {
+                DifferenceKind::Name => println!("{}: {}", name, difference),
+                DifferenceKind::Structure if known => {
+                    println!("{}: known {}", name, difference)
+                }
+                DifferenceKind::Structure => unexpected.push(format!("{}: {}", name, difference)),
+            }
+        }
+    }
+    assert!(
+        unexpected.is_empty(),
+        "Binary layout differs from Octez:\n{}",
+        unexpected.join("\n")
+    );
+}
@@ -282,6 +282,10 @@ impl ProtocolRunnerApi {
         tokio::task::block_in_place(|| self.tokio_runtime.block_on(self.wait_for_context_init()))
     }
 
+    pub fn configuration(&self) -> &ProtocolRunnerConfiguration {
+        &self.configuration
+    }
+
     /// Connect to protocol runner without waiting for context initialization.
     pub async fn connect(&self) -> Result<ProtocolRunnerConnection, IpcError> {
         let ipc_client = async_ipc::IpcClient::new(&self.socket_path);